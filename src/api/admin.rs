@@ -1,25 +1,60 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
 
+use super::handlers::{default_page_limit, parse_timestamp_param, MAX_PAGE_LIMIT};
+use crate::auth::{self, AdminUser, AuthenticatedUser};
 use crate::rate_limit::RateLimit;
-use crate::storage::StorageBackend;
+use crate::storage::{
+    models::{AuditEntry, MailboxSummary, SenderRule, SenderRuleAction, StorageStats, User},
+    StorageBackend,
+};
+use crate::webhooks::DeletionNotifier;
+
+/// Record an administrative change in the audit log, logging (not propagating) storage
+/// failures so the change itself still succeeds
+pub(super) async fn record_audit(
+    storage: &Arc<dyn StorageBackend>,
+    actor: Option<String>,
+    action: &str,
+    target: &str,
+    detail: Value,
+) {
+    let entry = AuditEntry::new(actor, action, target.to_string(), detail);
+    if let Err(e) = storage.audit(entry).await {
+        warn!(
+            "⚠️ Failed to record audit entry {} for {}: {}",
+            action, target, e
+        );
+    }
+}
+
+/// Audit log actor for a request: the logged-in user, or `None` when auth is disabled
+/// or the request has no user (e.g. it was authorized with a mailbox password)
+pub(super) fn audit_actor(user: Option<AuthenticatedUser>) -> Option<String> {
+    user.filter(|user| !user.is_anonymous())
+        .map(|user| user.user_id)
+}
 
 /// Request to create or update a rate limit
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SetRateLimitRequest {
     pub requests_per_hour: u32,
     pub requests_per_day: u32,
 }
 
 /// Response containing rate limit information
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct RateLimitResponse {
     pub mailbox_address: String,
     pub requests_per_hour: u32,
@@ -41,9 +76,20 @@ impl From<RateLimit> for RateLimitResponse {
 }
 
 /// Get rate limit for a specific mailbox
+#[utoipa::path(
+    get,
+    path = "/api/admin/rate-limit/{address}",
+    params(("address" = String, Path, description = "Mailbox address")),
+    responses(
+        (status = 200, description = "The mailbox's rate limit, or the default", body = RateLimitResponse),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
 pub async fn get_rate_limit(
     Path(address): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     match storage.get_rate_limit(&address).await {
         Ok(Some(limit)) => Ok(Json(json!(RateLimitResponse::from(limit)))),
@@ -60,9 +106,22 @@ pub async fn get_rate_limit(
 }
 
 /// Set or update rate limit for a specific mailbox
+#[utoipa::path(
+    post,
+    path = "/api/admin/rate-limit/{address}",
+    params(("address" = String, Path, description = "Mailbox address")),
+    request_body = SetRateLimitRequest,
+    responses(
+        (status = 200, description = "Rate limit saved", body = Value),
+        (status = 400, description = "Limits must be positive"),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
 pub async fn set_rate_limit(
     Path(address): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
+    admin: AdminUser,
     Json(request): Json<SetRateLimitRequest>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     // Validate inputs
@@ -105,9 +164,20 @@ pub async fn set_rate_limit(
             })?;
 
         info!(
-            "Updated rate limit for {}: {}/hr, {}/day",
-            address, request.requests_per_hour, request.requests_per_day
+            "Updated rate limit for {}: {}/hr, {}/day (by admin {})",
+            address, request.requests_per_hour, request.requests_per_day, admin.user_id
         );
+        record_audit(
+            &storage,
+            Some(admin.user_id),
+            "rate_limit.set",
+            &address,
+            json!({
+                "requests_per_hour": request.requests_per_hour,
+                "requests_per_day": request.requests_per_day,
+            }),
+        )
+        .await;
 
         Ok(Json(json!({
             "message": "Rate limit updated successfully",
@@ -132,9 +202,20 @@ pub async fn set_rate_limit(
             })?;
 
         info!(
-            "Created rate limit for {}: {}/hr, {}/day",
-            address, request.requests_per_hour, request.requests_per_day
+            "Created rate limit for {}: {}/hr, {}/day (by admin {})",
+            address, request.requests_per_hour, request.requests_per_day, admin.user_id
         );
+        record_audit(
+            &storage,
+            Some(admin.user_id),
+            "rate_limit.set",
+            &address,
+            json!({
+                "requests_per_hour": request.requests_per_hour,
+                "requests_per_day": request.requests_per_day,
+            }),
+        )
+        .await;
 
         Ok(Json(json!({
             "message": "Rate limit created successfully",
@@ -144,9 +225,20 @@ pub async fn set_rate_limit(
 }
 
 /// Delete rate limit for a specific mailbox (revert to defaults)
+#[utoipa::path(
+    delete,
+    path = "/api/admin/rate-limit/{address}",
+    params(("address" = String, Path, description = "Mailbox address")),
+    responses(
+        (status = 200, description = "Rate limit removed", body = Value),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
 pub async fn delete_rate_limit(
     Path(address): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
+    admin: AdminUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     storage.delete_rate_limit(&address).await.map_err(|e| {
         (
@@ -155,7 +247,18 @@ pub async fn delete_rate_limit(
         )
     })?;
 
-    info!("Deleted rate limit for {} (reverted to defaults)", address);
+    info!(
+        "Deleted rate limit for {} (reverted to defaults, by admin {})",
+        address, admin.user_id
+    );
+    record_audit(
+        &storage,
+        Some(admin.user_id),
+        "rate_limit.delete",
+        &address,
+        json!({}),
+    )
+    .await;
 
     Ok(Json(json!({
         "message": "Rate limit deleted successfully (reverted to defaults)"
@@ -163,9 +266,20 @@ pub async fn delete_rate_limit(
 }
 
 /// Get rate limit stats for a mailbox (current usage)
+#[utoipa::path(
+    get,
+    path = "/api/admin/rate-limit/{address}/stats",
+    params(("address" = String, Path, description = "Mailbox address")),
+    responses(
+        (status = 200, description = "Requests made against the rate limit", body = Value),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
 pub async fn get_rate_limit_stats(
     Path(address): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     // Get rate limit
     let rate_limit = match storage.get_rate_limit(&address).await {
@@ -227,21 +341,492 @@ pub async fn get_rate_limit_stats(
     })))
 }
 
+/// List all registered users, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    responses(
+        (status = 200, description = "Registered users, without password hashes", body = Value),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn list_users(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<User>>, (StatusCode, String)> {
+    let users = storage.list_users().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list users: {}", e),
+        )
+    })?;
+
+    Ok(Json(users))
+}
+
+/// Attachments with the given content hash across every mailbox, oldest email first
+///
+/// Shows how often the same file was received and how much space its copies take.
+#[utoipa::path(
+    get,
+    path = "/api/admin/attachments/{hash}",
+    params(("hash" = String, Path, description = "Hex SHA-256 of the attachment content")),
+    responses(
+        (status = 200, description = "Emails holding the attachment, without its content", body = Value),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn find_attachments_by_hash(
+    Path(hash): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let hash = hash.trim().to_lowercase();
+    let found = storage.get_attachments_by_hash(&hash).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to find attachments: {}", e),
+        )
+    })?;
+
+    let total_bytes: usize = found.iter().map(|(_, attachment)| attachment.size).sum();
+    let attachments: Vec<Value> = found
+        .into_iter()
+        .map(|(email_id, attachment)| {
+            json!({
+                "email_id": email_id,
+                "filename": attachment.filename,
+                "content_type": attachment.content_type,
+                "size": attachment.size,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "content_hash": hash,
+        "count": attachments.len(),
+        "total_bytes": total_bytes,
+        "attachments": attachments,
+    })))
+}
+
+/// Totals of stored email across the instance
+///
+/// Open to everyone when auth is disabled, and to admins only when it is enabled.
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    responses(
+        (status = 200, description = "Email, mailbox, attachment and size totals", body = StorageStats),
+        (status = 403, description = "Auth is enabled and the user is not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn get_storage_stats(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
+) -> Result<Json<StorageStats>, (StatusCode, String)> {
+    if !user.is_anonymous() {
+        auth::verify_admin(&storage, &user).await?;
+    }
+
+    let stats = storage.storage_stats().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to compute storage stats: {}", e),
+        )
+    })?;
+
+    Ok(Json(stats))
+}
+
+/// Query parameters for listing mailboxes
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MailboxListQuery {
+    /// Page size (default 50, max 500)
+    #[serde(default = "default_page_limit")]
+    limit: i64,
+    /// Number of mailboxes to skip (default 0)
+    #[serde(default)]
+    offset: i64,
+}
+
+/// Page of mailboxes that have received email, with their counts and newest email
+///
+/// Open to everyone when auth is disabled, and to admins only when it is enabled.
+#[utoipa::path(
+    get,
+    path = "/api/mailboxes",
+    params(MailboxListQuery),
+    responses(
+        (status = 200, description = "Page of mailboxes: `{mailboxes, limit, offset}`", body = Value),
+        (status = 403, description = "Auth is enabled and the user is not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn list_mailboxes(
+    Query(params): Query<MailboxListQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    if !user.is_anonymous() {
+        auth::verify_admin(&storage, &user).await?;
+    }
+
+    let limit = params.limit.clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.max(0);
+    let mailboxes: Vec<MailboxSummary> =
+        storage.list_mailboxes(limit, offset).await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list mailboxes: {}", e),
+            )
+        })?;
+
+    Ok(Json(json!({
+        "mailboxes": mailboxes,
+        "limit": limit,
+        "offset": offset
+    })))
+}
+
+/// State of the on-demand retention cleanup endpoint
+#[derive(Clone)]
+pub struct CleanupState {
+    pub storage: Arc<dyn StorageBackend>,
+    pub deletion_notifier: DeletionNotifier,
+    /// Configured `EMAIL_RETENTION_HOURS`
+    pub retention_hours: Option<i64>,
+}
+
+/// Request to run retention cleanup
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CleanupRequest {
+    /// Age in hours past which email is deleted, instead of `EMAIL_RETENTION_HOURS`
+    pub hours: Option<i64>,
+}
+
+/// Run retention cleanup now instead of waiting for the hourly task
+///
+/// Deleted emails are announced to WebSocket/SSE clients and deletion webhooks, as the
+/// scheduled cleanup does. Mailboxes with their own retention keep using it.
+#[utoipa::path(
+    post,
+    path = "/api/admin/cleanup",
+    request_body(content = CleanupRequest, description = "Optional override of the retention in hours"),
+    responses(
+        (status = 200, description = "Deleted emails: `{count, deleted}` with the deleted IDs", body = Value),
+        (status = 400, description = "Hours is not positive"),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn run_cleanup(
+    State(state): State<CleanupState>,
+    user: AuthenticatedUser,
+    request: Option<Json<CleanupRequest>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let admin = AdminUser::from_user(&state.storage, user).await?;
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    if request.hours.is_some_and(|hours| hours <= 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "hours must be positive".to_string(),
+        ));
+    }
+
+    let hours = request.hours.or(state.retention_hours);
+    let deleted = state
+        .deletion_notifier
+        .delete_expired_emails(hours)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to run retention cleanup: {}", e),
+            )
+        })?;
+    info!(
+        "🗑️  Admin {} ran retention cleanup: deleted {} email(s)",
+        admin.user_id,
+        deleted.len()
+    );
+
+    let ids: Vec<String> = deleted.into_iter().map(|(id, _)| id).collect();
+    record_audit(
+        &state.storage,
+        Some(admin.user_id),
+        "cleanup.run",
+        "emails",
+        json!({ "hours": hours, "deleted": ids.len() }),
+    )
+    .await;
+    Ok(Json(json!({
+        "count": ids.len(),
+        "deleted": ids
+    })))
+}
+
+/// Request to block or allow mail from matching senders
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateSenderRuleRequest {
+    /// Glob (`*@spam.example`), domain (`spam.example` or `@spam.example`) or exact address
+    pub pattern: String,
+    /// `block` or `allow`; allow rules win over block rules
+    pub action: String,
+}
+
+/// Add a rule blocking or allowing inbound mail by envelope sender
+///
+/// The SMTP server reloads sender rules every minute, so a new rule can take that long to
+/// apply.
+#[utoipa::path(
+    post,
+    path = "/api/admin/sender-rules",
+    request_body = CreateSenderRuleRequest,
+    responses(
+        (status = 200, description = "The created rule", body = SenderRule),
+        (status = 400, description = "Empty pattern or unknown action"),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn create_sender_rule(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    admin: AdminUser,
+    Json(request): Json<CreateSenderRuleRequest>,
+) -> Result<Json<SenderRule>, (StatusCode, String)> {
+    let pattern = request.pattern.trim();
+    if pattern.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "pattern must not be empty".to_string(),
+        ));
+    }
+    let action = SenderRuleAction::from_str(&request.action.to_lowercase()).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid action '{}', expected 'block' or 'allow'",
+                request.action
+            ),
+        )
+    })?;
+
+    let rule = SenderRule::new(pattern.to_string(), action);
+    storage
+        .create_sender_rule(rule.clone())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create sender rule: {}", e),
+            )
+        })?;
+
+    info!(
+        "🔒 Admin {} added sender rule {}: {} {}",
+        admin.user_id,
+        rule.id,
+        rule.action.as_str(),
+        rule.pattern
+    );
+    record_audit(
+        &storage,
+        Some(admin.user_id),
+        "sender_rule.create",
+        &rule.id,
+        json!({ "pattern": rule.pattern, "action": rule.action }),
+    )
+    .await;
+    Ok(Json(rule))
+}
+
+/// List sender rules, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/sender-rules",
+    responses(
+        (status = 200, description = "All sender rules", body = [SenderRule]),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn list_sender_rules(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<SenderRule>>, (StatusCode, String)> {
+    let rules = storage.list_sender_rules().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to list sender rules: {}", e),
+        )
+    })?;
+
+    Ok(Json(rules))
+}
+
+/// Delete a sender rule
+#[utoipa::path(
+    delete,
+    path = "/api/admin/sender-rules/{id}",
+    params(("id" = String, Path, description = "Sender rule ID")),
+    responses(
+        (status = 200, description = "Rule deleted", body = Value),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Rule not found")
+    ),
+    tag = "admin"
+)]
+pub async fn delete_sender_rule(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    admin: AdminUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let deleted = storage.delete_sender_rule(&id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to delete sender rule: {}", e),
+        )
+    })?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Sender rule not found".to_string()));
+    }
+
+    info!("Admin {} deleted sender rule {}", admin.user_id, id);
+    record_audit(
+        &storage,
+        Some(admin.user_id),
+        "sender_rule.delete",
+        &id,
+        json!({}),
+    )
+    .await;
+    Ok(Json(json!({
+        "message": "Sender rule deleted successfully"
+    })))
+}
+
+/// Query parameters for reading the audit log
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    /// Number of entries (default 50, max 500)
+    #[serde(default = "default_page_limit")]
+    limit: i64,
+}
+
+/// Most recent administrative changes, newest first
+///
+/// Covers rate limits, sender rules, retention cleanups and webhook changes.
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "Audit log entries", body = [AuditEntry]),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn get_audit_log(
+    Query(params): Query<AuditLogQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    let limit = params.limit.clamp(1, MAX_PAGE_LIMIT);
+    let entries = storage.get_audit_log(limit).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read audit log: {}", e),
+        )
+    })?;
+
+    Ok(Json(entries))
+}
+
+/// Query parameters for the JSON-lines export
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Only export emails received at or after this RFC3339 time
+    since: Option<String>,
+}
+
+/// Stream every email outside the trash as newline-delimited JSON, oldest first, for
+/// backing up the whole instance
+///
+/// Emails are read from the database as the response is sent, so large instances are not
+/// loaded into memory. A storage failure part way through ends the response early.
+#[utoipa::path(
+    get,
+    path = "/api/admin/export.jsonl",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "One JSON email per line", content_type = "application/x-ndjson", body = String),
+        (status = 400, description = "`since` is not an RFC3339 time"),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "admin"
+)]
+pub async fn export_emails_jsonl(
+    Query(params): Query<ExportQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    _admin: AdminUser,
+) -> Result<Response, (StatusCode, String)> {
+    let since = parse_timestamp_param("since", params.since.as_deref())?;
+    info!("📦 Exporting emails as JSON lines (since {:?})", since);
+
+    let lines = storage.stream_all_emails(since).map(|email| {
+        let mut line = serde_json::to_vec(&email?)?;
+        line.push(b'\n');
+        Ok::<_, anyhow::Error>(line)
+    });
+    let lines = lines.inspect(|line| {
+        if let Err(e) = line {
+            error!("Email export failed part way through: {}", e);
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::AuthConfig;
     use crate::storage::sqlite::SqliteBackend;
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::{delete, get, post},
+        Router,
+    };
+    use tower::util::ServiceExt;
 
     async fn create_test_storage() -> Arc<dyn StorageBackend> {
         Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap())
     }
 
+    fn admin() -> AdminUser {
+        AdminUser {
+            user_id: "admin".to_string(),
+        }
+    }
+
     #[tokio::test]
     async fn test_get_rate_limit_default() {
         let storage = create_test_storage().await;
         let address = "test@example.com".to_string();
 
-        let result = get_rate_limit(Path(address), State(storage)).await;
+        let result = get_rate_limit(Path(address), State(storage), admin()).await;
         assert!(result.is_ok());
 
         let json = result.unwrap().0;
@@ -259,11 +844,16 @@ mod tests {
             requests_per_day: 500,
         };
 
-        let set_result =
-            set_rate_limit(Path(address.clone()), State(storage.clone()), Json(request)).await;
+        let set_result = set_rate_limit(
+            Path(address.clone()),
+            State(storage.clone()),
+            admin(),
+            Json(request),
+        )
+        .await;
         assert!(set_result.is_ok());
 
-        let get_result = get_rate_limit(Path(address), State(storage)).await;
+        let get_result = get_rate_limit(Path(address), State(storage), admin()).await;
         assert!(get_result.is_ok());
 
         let json = get_result.unwrap().0;
@@ -282,8 +872,13 @@ mod tests {
             requests_per_day: 500,
         };
 
-        let result =
-            set_rate_limit(Path(address.clone()), State(storage.clone()), Json(request)).await;
+        let result = set_rate_limit(
+            Path(address.clone()),
+            State(storage.clone()),
+            admin(),
+            Json(request),
+        )
+        .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
 
@@ -293,7 +888,7 @@ mod tests {
             requests_per_day: 500,
         };
 
-        let result = set_rate_limit(Path(address), State(storage), Json(request)).await;
+        let result = set_rate_limit(Path(address), State(storage), admin(), Json(request)).await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
     }
@@ -309,17 +904,641 @@ mod tests {
             requests_per_day: 500,
         };
 
-        set_rate_limit(Path(address.clone()), State(storage.clone()), Json(request))
-            .await
-            .unwrap();
+        let set_result = set_rate_limit(
+            Path(address.clone()),
+            State(storage.clone()),
+            admin(),
+            Json(request),
+        )
+        .await;
+        assert_eq!(set_result.unwrap().0["rate_limit"]["requests_per_hour"], 50);
 
         // Delete it
-        let delete_result = delete_rate_limit(Path(address.clone()), State(storage.clone())).await;
+        let delete_result =
+            delete_rate_limit(Path(address.clone()), State(storage.clone()), admin()).await;
         assert!(delete_result.is_ok());
 
         // Verify it's gone (returns default)
-        let get_result = get_rate_limit(Path(address), State(storage)).await;
+        let get_result = get_rate_limit(Path(address), State(storage), admin()).await;
         let json = get_result.unwrap().0;
         assert_eq!(json["requests_per_hour"], 100); // Default
     }
+
+    fn admin_app(storage: Arc<dyn StorageBackend>, auth_config: AuthConfig) -> Router {
+        Router::new()
+            .route("/api/admin/users", get(list_users))
+            .route("/api/admin/rate-limit/:address", post(set_rate_limit))
+            .route("/api/stats", get(get_storage_stats))
+            .route("/api/mailboxes", get(list_mailboxes))
+            .route(
+                "/api/admin/sender-rules",
+                get(list_sender_rules).post(create_sender_rule),
+            )
+            .route("/api/admin/sender-rules/:id", delete(delete_sender_rule))
+            .route("/api/admin/audit", get(get_audit_log))
+            .route("/api/admin/export.jsonl", get(export_emails_jsonl))
+            .route("/api/webhooks", post(crate::api::handlers::create_webhook))
+            .with_state(storage.clone())
+            .layer(axum::middleware::from_fn_with_state(
+                (storage, auth_config),
+                auth::auth_config_middleware,
+            ))
+    }
+
+    fn enabled_auth_config() -> AuthConfig {
+        AuthConfig {
+            enabled: true,
+            jwt_secret: "admin-test-secret".to_string(),
+            jwt_expiry_hours: 1,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
+            auth_domains: None,
+            outbound_enabled: false,
+            password_hasher: crate::auth::PasswordHasher::Bcrypt,
+        }
+    }
+
+    /// Send a request, returning its status and JSON body (null when not JSON)
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn create_user_with_token(
+        storage: &Arc<dyn StorageBackend>,
+        email: &str,
+        is_admin: bool,
+        config: &AuthConfig,
+    ) -> String {
+        let mut user = User::new(email.to_string(), "hash".to_string());
+        user.is_admin = is_admin;
+        storage.create_user(user.clone()).await.unwrap();
+        auth::generate_token(&user, config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_allow_admin() {
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let token = create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let app = admin_app(storage.clone(), config);
+
+        let (status, users) = send(&app, "GET", "/api/admin/users", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let users = users.as_array().unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0]["email"], "admin@example.com");
+        assert_eq!(users[0]["is_admin"], true);
+        assert_eq!(users[1]["is_admin"], false);
+        assert!(users[0].get("password_hash").is_none());
+
+        let body = json!({ "requests_per_hour": 5, "requests_per_day": 50 });
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/admin/rate-limit/box@example.com",
+            Some(&token),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let limit = storage
+            .get_rate_limit("box@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(limit.requests_per_hour, 5);
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_deny_non_admin() {
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let app = admin_app(storage.clone(), config);
+
+        let (status, _) = send(&app, "GET", "/api/admin/users", Some(&token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let body = json!({ "requests_per_hour": 5, "requests_per_day": 50 });
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/admin/rate-limit/box@example.com",
+            Some(&token),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(storage
+            .get_rate_limit("box@example.com")
+            .await
+            .unwrap()
+            .is_none());
+
+        let (status, _) = send(&app, "GET", "/api/admin/users", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // Without auth there is no admin at all
+        let mut disabled = enabled_auth_config();
+        disabled.enabled = false;
+        let app = admin_app(storage, disabled);
+        let (status, _) = send(&app, "GET", "/api/admin/users", None, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_sender_rule_endpoints() {
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let app = admin_app(storage.clone(), config);
+
+        let body = json!({ "pattern": " *@spam.example ", "action": "Block" });
+        let (status, rule) = send(
+            &app,
+            "POST",
+            "/api/admin/sender-rules",
+            Some(&admin_token),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rule["pattern"], "*@spam.example");
+        assert_eq!(rule["action"], "block");
+
+        for body in [
+            json!({ "pattern": "", "action": "block" }),
+            json!({ "pattern": "spam.example", "action": "reject" }),
+        ] {
+            let (status, _) = send(
+                &app,
+                "POST",
+                "/api/admin/sender-rules",
+                Some(&admin_token),
+                Some(body),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let body = json!({ "pattern": "spam.example", "action": "allow" });
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/admin/sender-rules",
+            Some(&user_token),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, rules) = send(
+            &app,
+            "GET",
+            "/api/admin/sender-rules",
+            Some(&admin_token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rules.as_array().unwrap().len(), 1);
+
+        let uri = format!("/api/admin/sender-rules/{}", rule["id"].as_str().unwrap());
+        let (status, _) = send(&app, "DELETE", &uri, Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(&app, "DELETE", &uri, Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(storage.list_sender_rules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_changes() {
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let admin_id = storage
+            .get_user_by_email("admin@example.com")
+            .await
+            .unwrap()
+            .unwrap()
+            .id;
+        let app = admin_app(storage.clone(), config);
+
+        let body = json!({ "pattern": "*@spam.example", "action": "block" });
+        let (_, rule) = send(
+            &app,
+            "POST",
+            "/api/admin/sender-rules",
+            Some(&admin_token),
+            Some(body),
+        )
+        .await;
+        let body = json!({
+            "mailbox_address": "inbox@example.com",
+            "webhook_url": "https://example.com/hook",
+            "events": ["arrival"],
+            "secret": "not-in-the-log"
+        });
+        let (status, webhook) = send(
+            &app,
+            "POST",
+            "/api/webhooks",
+            Some(&admin_token),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, entries) =
+            send(&app, "GET", "/api/admin/audit", Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let entries = entries.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // Newest first: the webhook, then the sender rule
+        assert_eq!(entries[0]["action"], "webhook.create");
+        assert_eq!(entries[0]["target"], webhook["id"]);
+        assert_eq!(entries[0]["actor"], admin_id.as_str());
+        assert_eq!(entries[0]["detail"]["mailbox_address"], "inbox");
+        assert_eq!(
+            entries[0]["detail"]["webhook_url"],
+            "https://example.com/hook"
+        );
+        assert!(!entries[0].to_string().contains("not-in-the-log"));
+        assert_eq!(entries[1]["action"], "sender_rule.create");
+        assert_eq!(entries[1]["target"], rule["id"]);
+        assert_eq!(entries[1]["detail"]["pattern"], "*@spam.example");
+
+        let (_, limited) = send(
+            &app,
+            "GET",
+            "/api/admin/audit?limit=1",
+            Some(&admin_token),
+            None,
+        )
+        .await;
+        assert_eq!(limited.as_array().unwrap().len(), 1);
+        let (status, _) = send(&app, "GET", "/api/admin/audit", Some(&user_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_find_attachments_by_hash() {
+        use crate::smtp::parser::parse_email;
+
+        let storage = create_test_storage().await;
+        let raw = |to: &str, content: &str| {
+            format!(
+                "From: sender@example.com\r\nTo: {}\r\nSubject: Report\r\n\
+                 MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+                 --b\r\nContent-Type: text/plain\r\n\r\nAttached\r\n\
+                 --b\r\nContent-Type: text/plain\r\n\
+                 Content-Disposition: attachment; filename=\"report.txt\"\r\n\r\n{}\r\n--b--\r\n",
+                to, content
+            )
+        };
+        let mut ids = Vec::new();
+        for (to, content) in [
+            ("one@example.com", "quarterly numbers"),
+            ("two@example.com", "quarterly numbers"),
+            ("one@example.com", "something else"),
+        ] {
+            let email = parse_email(raw(to, content).as_bytes(), to).unwrap();
+            ids.push(email.id.clone());
+            storage.store_email(email).await.unwrap();
+        }
+
+        let hash = crate::storage::models::Attachment::hash_content(b"quarterly numbers");
+        let json = find_attachments_by_hash(Path(hash.to_uppercase()), State(storage), admin())
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(json["content_hash"], hash);
+        assert_eq!(json["count"], 2);
+        assert_eq!(json["total_bytes"], 34);
+        let email_ids: Vec<&str> = json["attachments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["email_id"].as_str().unwrap())
+            .collect();
+        assert!(email_ids.contains(&ids[0].as_str()));
+        assert!(email_ids.contains(&ids[1].as_str()));
+        assert_eq!(json["attachments"][0]["filename"], "report.txt");
+        assert!(json["attachments"][0].get("content").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_storage_stats_endpoint() {
+        use crate::storage::models::Email;
+
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        for to in ["one@example.com", "one@example.com", "two@example.com"] {
+            let email = Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+
+        let app = admin_app(storage.clone(), config.clone());
+        let (status, stats) = send(&app, "GET", "/api/stats", Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["total_emails"], 3);
+        assert_eq!(stats["total_mailboxes"], 2);
+        assert_eq!(stats["total_attachments"], 0);
+        assert_eq!(stats["total_bytes"], 12);
+        assert!(stats["oldest_email"].is_string());
+
+        let (status, _) = send(&app, "GET", "/api/stats", Some(&user_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Without auth anyone may read the stats
+        let mut disabled = config;
+        disabled.enabled = false;
+        let app = admin_app(storage, disabled);
+        let (status, stats) = send(&app, "GET", "/api/stats", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["total_emails"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_list_mailboxes_endpoint() {
+        use crate::storage::models::Email;
+
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let now = chrono::Utc::now();
+        for (to, minutes_ago) in [
+            ("one@example.com", 30),
+            ("one@example.com", 20),
+            ("two@example.com", 10),
+        ] {
+            let mut email = Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = now - chrono::Duration::minutes(minutes_ago);
+            storage.store_email(email).await.unwrap();
+        }
+
+        let app = admin_app(storage.clone(), config.clone());
+        let (status, page) = send(&app, "GET", "/api/mailboxes", Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let mailboxes = page["mailboxes"].as_array().unwrap();
+        assert_eq!(mailboxes.len(), 2);
+        assert_eq!(mailboxes[0]["address"], "two@example.com");
+        assert_eq!(mailboxes[0]["count"], 1);
+        assert_eq!(mailboxes[1]["address"], "one@example.com");
+        assert_eq!(mailboxes[1]["count"], 2);
+        assert!(mailboxes[1]["latest"].is_string());
+
+        let (_, page) = send(
+            &app,
+            "GET",
+            "/api/mailboxes?limit=1&offset=1",
+            Some(&admin_token),
+            None,
+        )
+        .await;
+        assert_eq!(page["limit"], 1);
+        assert_eq!(page["offset"], 1);
+        assert_eq!(page["mailboxes"].as_array().unwrap().len(), 1);
+        assert_eq!(page["mailboxes"][0]["address"], "one@example.com");
+
+        let (status, _) = send(&app, "GET", "/api/mailboxes", Some(&user_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Without auth anyone may list the mailboxes
+        let mut disabled = config;
+        disabled.enabled = false;
+        let app = admin_app(storage, disabled);
+        let (status, page) = send(&app, "GET", "/api/mailboxes", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["mailboxes"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_endpoint_deletes_and_broadcasts() {
+        use crate::storage::models::Email;
+        use crate::webhooks::WebhookTrigger;
+
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+
+        let new_email = |hours_ago: i64| {
+            let mut email = Email::new(
+                "old@example.com".to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = chrono::Utc::now() - chrono::Duration::hours(hours_ago);
+            email
+        };
+        let old = new_email(30);
+        let recent = new_email(2);
+        storage.store_email(old.clone()).await.unwrap();
+        storage.store_email(recent.clone()).await.unwrap();
+
+        let (deletion_tx, mut deletion_rx) = tokio::sync::broadcast::channel(10);
+        let app = Router::new()
+            .route("/api/admin/cleanup", post(run_cleanup))
+            .with_state(CleanupState {
+                storage: storage.clone(),
+                deletion_notifier: DeletionNotifier::new(
+                    WebhookTrigger::new(storage.clone()),
+                    deletion_tx,
+                ),
+                retention_hours: Some(48),
+            })
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), config),
+                auth::auth_config_middleware,
+            ));
+
+        let (status, _) = send(&app, "POST", "/api/admin/cleanup", Some(&user_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Nothing is older than the configured retention
+        let (status, result) =
+            send(&app, "POST", "/api/admin/cleanup", Some(&admin_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["count"], 0);
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/admin/cleanup",
+            Some(&admin_token),
+            Some(json!({ "hours": 0 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, result) = send(
+            &app,
+            "POST",
+            "/api/admin/cleanup",
+            Some(&admin_token),
+            Some(json!({ "hours": 24 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["count"], 1);
+        assert_eq!(result["deleted"], json!([old.id]));
+
+        assert!(storage.get_email_by_id(&old.id).await.unwrap().is_none());
+        assert!(storage.get_email_by_id(&recent.id).await.unwrap().is_some());
+        assert_eq!(
+            deletion_rx.try_recv().unwrap(),
+            (old.id.clone(), "old@example.com".to_string())
+        );
+        assert!(deletion_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_emails_jsonl() {
+        use crate::storage::models::Email;
+
+        let storage = create_test_storage().await;
+        let config = enabled_auth_config();
+        let admin_token =
+            create_user_with_token(&storage, "admin@example.com", true, &config).await;
+        let user_token = create_user_with_token(&storage, "user@example.com", false, &config).await;
+        let now = chrono::Utc::now();
+        let mut ids = Vec::new();
+        for (to, minutes_ago) in [
+            ("one@example.com", 30),
+            ("two@example.com", 20),
+            ("one@example.com", 10),
+            ("three@example.com", 5),
+        ] {
+            let mut email = Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                format!("{} minutes ago", minutes_ago),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = now - chrono::Duration::minutes(minutes_ago);
+            ids.push(email.id.clone());
+            storage.store_email(email).await.unwrap();
+        }
+        // Trashed emails are left out
+        storage.delete_email(&ids[3]).await.unwrap();
+
+        let app = admin_app(storage.clone(), config);
+        let export = |uri: String, token: String| {
+            let app = app.clone();
+            async move {
+                let request = Request::builder()
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    content_type,
+                    String::from_utf8(bytes.to_vec()).unwrap(),
+                )
+            }
+        };
+
+        let (status, content_type, body) =
+            export("/api/admin/export.jsonl".to_string(), admin_token.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type.unwrap(), "application/x-ndjson");
+        assert!(body.ends_with('\n'));
+        let emails: Vec<Email> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let exported: Vec<&str> = emails.iter().map(|email| email.id.as_str()).collect();
+        assert_eq!(exported, vec![&ids[0], &ids[1], &ids[2]]);
+        assert_eq!(emails[1].to, "two@example.com");
+        assert_eq!(emails[2].subject, "10 minutes ago");
+
+        let since = (now - chrono::Duration::minutes(15))
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let (status, _, body) = export(
+            format!("/api/admin/export.jsonl?since={}", since),
+            admin_token.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let emails: Vec<Email> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].id, ids[2]);
+
+        let (status, _, _) = export(
+            "/api/admin/export.jsonl?since=yesterday".to_string(),
+            admin_token,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _, _) = export("/api/admin/export.jsonl".to_string(), user_token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
 }
@@ -1,38 +1,48 @@
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use serde_json::{json, Value};
 
+use super::admin::{audit_actor, record_audit};
+use crate::auth::AuthenticatedUser;
+use crate::build_info::build_info;
+use crate::mbox;
 use crate::outbound::{OutboundMailer, SendEmailRequest};
 use crate::storage::{
     fts::SearchQuery,
-    models::{SentEmail, Webhook, WebhookEvent},
+    models::{
+        Email, EmailFilter, ForwardingRule, RoutingRule, SenderSummary, SentEmail, Webhook,
+        WebhookEvent, WebhookFormat,
+    },
     StorageBackend,
 };
-use crate::webhooks::WebhookTrigger;
+use crate::webhooks::{
+    sample_email, validate_payload_template, validate_webhook_headers, DeletionNotifier,
+    WebhookTrigger,
+};
+use std::collections::HashMap;
 use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
 
 /// Shared application configuration
 #[derive(Clone)]
 pub struct AppConfig {
     pub domain_name: String,
+    pub additional_domains: Vec<String>,
 }
 
 impl AppConfig {
-    /// Normalize an email address by appending domain if not present
+    /// Normalize an email address by appending the primary domain if not present
+    ///
+    /// Addresses on any configured domain use that domain's configured spelling.
     pub fn normalize_address(&self, input: &str) -> String {
-        let input = input.trim();
-
-        // If it already contains @, use as-is
-        if input.contains('@') {
-            input.to_string()
-        } else {
-            // Append the server domain
-            format!("{}@{}", input, self.domain_name)
-        }
+        crate::config::normalize_address(input, &self.domain_name, &self.additional_domains)
     }
 
     /// Extract just the local part (username) from an email address
@@ -49,13 +59,15 @@ impl AppConfig {
 }
 
 /// Query parameters for password-protected endpoints
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PasswordQuery {
-    password: Option<String>,
+    /// Password of the mailbox, if locked
+    pub(super) password: Option<String>,
 }
 
 /// Verify password for a mailbox
-async fn verify_mailbox_password(
+pub(super) async fn verify_mailbox_password(
     storage: &Arc<dyn StorageBackend>,
     address: &str,
     provided_password: Option<&str>,
@@ -112,22 +124,127 @@ async fn verify_mailbox_password(
     Ok(())
 }
 
-/// Get all emails for a specific address
+/// Check that the authenticated user may access a mailbox (by local part)
+///
+/// Unowned mailboxes are open to any user; owned ones only to their owner.
+/// Always passes when auth is disabled.
+async fn verify_mailbox_owner(
+    storage: &Arc<dyn StorageBackend>,
+    local_part: &str,
+    user: &AuthenticatedUser,
+) -> Result<(), (StatusCode, String)> {
+    if user.is_anonymous() {
+        return Ok(());
+    }
+
+    let owner = storage
+        .get_mailbox_owner(local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match owner {
+        Some(owner) if owner != user.user_id => Err((
+            StatusCode::FORBIDDEN,
+            "Mailbox belongs to another user".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Maximum page size for email listings
+pub(crate) const MAX_PAGE_LIMIT: i64 = 500;
+
+pub(crate) fn default_page_limit() -> i64 {
+    50
+}
+
+/// Query parameters for listing a mailbox's emails
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EmailListQuery {
+    /// Password of the mailbox, if locked
+    pub(super) password: Option<String>,
+    /// Page size (default 50, max 500)
+    #[serde(default = "default_page_limit")]
+    limit: i64,
+    /// Number of emails to skip (default 0)
+    #[serde(default)]
+    offset: i64,
+    /// Only emails whose sender contains this text
+    from: Option<String>,
+    /// Only emails whose subject contains this text
+    subject_contains: Option<String>,
+    /// Only emails received at or after this RFC3339 time
+    since: Option<String>,
+    /// Only emails received at or before this RFC3339 time
+    until: Option<String>,
+}
+
+/// Parse an optional RFC3339 query parameter, rejecting malformed values with 400
+pub(super) fn parse_timestamp_param(
+    name: &str,
+    value: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, (StatusCode, String)> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid '{}' timestamp (expected RFC3339): {}", name, e),
+                    )
+                })
+        })
+        .transpose()
+}
+
+/// Get a page of emails for a specific address, optionally filtered by sender, subject
+/// and received time
+#[utoipa::path(
+    get,
+    path = "/api/emails/{address}",
+    params(("address" = String, Path, description = "Mailbox address or local part"), EmailListQuery),
+    responses(
+        (status = 200, description = "Page of matching emails: `{emails, total, limit, offset}`", body = Value),
+        (status = 400, description = "`since` or `until` is not an RFC3339 time"),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
 pub async fn get_emails_for_address(
     Path(address): Path<String>,
-    Query(params): Query<PasswordQuery>,
+    Query(params): Query<EmailListQuery>,
     State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     // Get local part for mailbox password verification, full address for email lookup
     let local_part = config.extract_local_part(&address);
     let normalized_address = config.normalize_address(&address);
 
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+
     // Verify password if mailbox is locked (mailboxes keyed by username only)
     verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
 
+    let filter = EmailFilter {
+        from: params.from,
+        subject_contains: params.subject_contains,
+        since: parse_timestamp_param("since", params.since.as_deref())?,
+        until: parse_timestamp_param("until", params.until.as_deref())?,
+        limit: params.limit.clamp(1, MAX_PAGE_LIMIT),
+        offset: params.offset.max(0),
+    };
+
     // Fetch emails by full address (emails stored with full "to" address)
-    match storage.get_emails_for_address(&normalized_address).await {
-        Ok(emails) => Ok(Json(json!({ "emails": emails }))),
+    match storage.query_emails(&normalized_address, &filter).await {
+        Ok((emails, total)) => Ok(Json(json!({
+            "emails": emails,
+            "total": total,
+            "limit": filter.limit,
+            "offset": filter.offset
+        }))),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to fetch emails: {}", e),
@@ -135,13 +252,133 @@ pub async fn get_emails_for_address(
     }
 }
 
+/// Query parameters for searching a mailbox
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MailboxSearchQuery {
+    /// Plain-text search terms matched against subject, body and addresses
+    #[serde(default)]
+    q: String,
+    /// Password of the mailbox, if locked
+    pub(super) password: Option<String>,
+}
+
+/// Search a specific mailbox's emails
+#[utoipa::path(
+    get,
+    path = "/api/emails/{address}/search",
+    params(("address" = String, Path, description = "Mailbox address or local part"), MailboxSearchQuery),
+    responses(
+        (status = 200, description = "Emails matching the search terms, newest first", body = Value),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
+pub async fn search_mailbox_emails(
+    Path(address): Path<String>,
+    Query(params): Query<MailboxSearchQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
+
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
+
+    if params.q.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Search query 'q' is required".to_string(),
+        ));
+    }
+
+    match storage
+        .search_emails_for_address(&normalized_address, &params.q)
+        .await
+    {
+        Ok(emails) => Ok(Json(json!({
+            "count": emails.len(),
+            "emails": emails
+        }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Search failed: {}", e),
+        )),
+    }
+}
+
+/// Query parameters for the sender summary
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SenderSummaryQuery {
+    /// Password of the mailbox, if locked
+    pub(super) password: Option<String>,
+    /// Maximum number of senders to return (max 500), all when unset
+    limit: Option<i64>,
+}
+
+/// Count a mailbox's emails per sender, most frequent first
+#[utoipa::path(
+    get,
+    path = "/api/emails/{address}/senders",
+    params(("address" = String, Path, description = "Mailbox address or local part"), SenderSummaryQuery),
+    responses(
+        (status = 200, description = "Senders with their email count and newest email time", body = [SenderSummary]),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
+pub async fn get_sender_summary(
+    Path(address): Path<String>,
+    Query(params): Query<SenderSummaryQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Vec<SenderSummary>>, (StatusCode, String)> {
+    let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
+
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
+
+    let limit = params.limit.map(|limit| limit.clamp(1, MAX_PAGE_LIMIT));
+    let senders = storage
+        .sender_summary(&normalized_address, limit)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to summarize senders: {}", e),
+            )
+        })?;
+
+    Ok(Json(senders))
+}
+
 /// Get a specific email by ID
+#[utoipa::path(
+    get,
+    path = "/api/email/{id}",
+    params(("id" = String, Path, description = "Email ID")),
+    responses(
+        (status = 200, description = "The email", body = Email),
+        (status = 404, description = "Email not found")
+    ),
+    tag = "emails"
+)]
 pub async fn get_email_by_id(
     Path(id): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     match storage.get_email_by_id(&id).await {
-        Ok(Some(email)) => Ok(Json(json!(email))),
+        Ok(Some(email)) => {
+            let local_part = email.to.split('@').next().unwrap_or(&email.to);
+            verify_mailbox_owner(&storage, local_part, &user).await?;
+            Ok(Json(json!(email)))
+        }
         Ok(None) => Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -150,8 +387,219 @@ pub async fn get_email_by_id(
     }
 }
 
+/// Get the emails in a conversation, oldest first
+///
+/// Emails in mailboxes the user may not access are left out; a thread with none left is
+/// not found.
+#[utoipa::path(
+    get,
+    path = "/api/thread/{thread_id}",
+    params(("thread_id" = String, Path, description = "Message-ID of the conversation's first message")),
+    responses(
+        (status = 200, description = "The conversation, oldest first: `{thread_id, emails}`", body = Value),
+        (status = 404, description = "No visible emails in the thread")
+    ),
+    tag = "emails"
+)]
+pub async fn get_thread(
+    Path(thread_id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let emails = storage.get_thread(&thread_id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch thread: {}", e),
+        )
+    })?;
+
+    let mut visible = Vec::with_capacity(emails.len());
+    for email in emails {
+        let local_part = email.to.split('@').next().unwrap_or(&email.to);
+        match verify_mailbox_owner(&storage, local_part, &user).await {
+            Ok(()) => visible.push(email),
+            Err((StatusCode::FORBIDDEN, _)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    if visible.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Thread not found".to_string()));
+    }
+    Ok(Json(json!({
+        "thread_id": thread_id,
+        "emails": visible,
+    })))
+}
+
+/// Download a single attachment, decoded from inline base64 or read from its file
+///
+/// Attachments with a content hash are sent with it as their `ETag`, and a matching
+/// `If-None-Match` gets `304 Not Modified` without the content.
+#[utoipa::path(
+    get,
+    path = "/api/email/{id}/attachment/{index}",
+    params(("id" = String, Path, description = "Email ID"), ("index" = usize, Path, description = "Zero-based attachment index"), PasswordQuery),
+    responses(
+        (status = 200, description = "Attachment content", content_type = "application/octet-stream"),
+        (status = 304, description = "The client's copy, named by If-None-Match, is current"),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user"),
+        (status = 404, description = "Email or attachment not found"),
+        (status = 410, description = "Attachment content was dropped for exceeding the attachment limits")
+    ),
+    tag = "emails"
+)]
+pub async fn download_attachment(
+    Path((id, index)): Path<(String, usize)>,
+    Query(params): Query<PasswordQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let email = match storage.get_email_by_id(&id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch email: {}", e),
+            ))
+        }
+    };
+    let local_part = email.to.split('@').next().unwrap_or(&email.to);
+    verify_mailbox_owner(&storage, local_part, &user).await?;
+    verify_mailbox_password(&storage, local_part, params.password.as_deref()).await?;
+
+    let attachment = email
+        .attachments
+        .get(index)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Attachment not found".to_string()))?;
+    if attachment.dropped {
+        return Err((
+            StatusCode::GONE,
+            "Attachment was dropped for exceeding the attachment limits".to_string(),
+        ));
+    }
+
+    let etag = attachment
+        .content_hash
+        .as_ref()
+        .map(|hash| format!("\"{}\"", hash));
+    if let Some(etag) = &etag {
+        let cached = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag.as_str())
+            });
+        if cached {
+            return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag.clone())]).into_response());
+        }
+    }
+
+    let content = match &attachment.path {
+        Some(path) => storage.read_attachment_file(path).await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read attachment: {}", e),
+            )
+        })?,
+        None => base64::Engine::decode(
+            &base64::engine::general_purpose::STANDARD,
+            &attachment.content,
+        )
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Attachment content is not valid base64: {}", e),
+            )
+        })?,
+    };
+
+    // Keep the filename from breaking out of the quoted header value
+    let filename: String = attachment
+        .filename
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if c == '"' || c == '\\' { '_' } else { c })
+        .collect();
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, attachment.content_type.clone()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        content,
+    )
+        .into_response();
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    Ok(response)
+}
+
+/// Request body for marking an email read or unread
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MarkReadRequest {
+    pub read: bool,
+}
+
+/// Mark an email as read or unread
+#[utoipa::path(
+    put,
+    path = "/api/email/{id}/read",
+    params(("id" = String, Path, description = "Email ID")),
+    request_body = MarkReadRequest,
+    responses(
+        (status = 200, description = "The updated email", body = Email),
+        (status = 403, description = "Mailbox belongs to another user"),
+        (status = 404, description = "Email not found")
+    ),
+    tag = "emails"
+)]
+pub async fn mark_email_read(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: AuthenticatedUser,
+    Json(request): Json<MarkReadRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mut email = match storage.get_email_by_id(&id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch email: {}", e),
+            ))
+        }
+    };
+    let local_part = email.to.split('@').next().unwrap_or(&email.to);
+    verify_mailbox_owner(&storage, local_part, &user).await?;
+
+    storage
+        .mark_email_read(&id, request.read)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to update email: {}", e),
+            )
+        })?;
+
+    email.read = request.read;
+    Ok(Json(json!(email)))
+}
+
 /// Search parameters
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SearchParams {
     /// Search query string
     q: String,
@@ -164,6 +612,17 @@ pub struct SearchParams {
 }
 
 /// Search emails using FTS5 full-text search
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Full-text search results with snippets: `{results}`", body = Value),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
 pub async fn search_emails(
     Query(params): Query<SearchParams>,
     State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
@@ -197,44 +656,44 @@ pub async fn search_emails(
 }
 
 /// Delete email by ID
+///
+/// The email is moved to the trash and can be restored until it is purged.
+#[utoipa::path(
+    delete,
+    path = "/api/email/{id}",
+    params(("id" = String, Path, description = "Email ID")),
+    responses(
+        (status = 200, description = "Email moved to the trash", body = Value),
+        (status = 404, description = "Email not found")
+    ),
+    tag = "emails"
+)]
 pub async fn delete_email(
     Path(id): Path<String>,
-    State((storage, webhook_trigger)): State<(Arc<dyn StorageBackend>, WebhookTrigger)>,
+    State((storage, deletion_notifier)): State<(Arc<dyn StorageBackend>, DeletionNotifier)>,
+    user: AuthenticatedUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // First get the email to extract mailbox info for webhook
-    let email = match storage.get_email_by_id(&id).await {
-        Ok(Some(email)) => email,
-        Ok(None) => return Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to fetch email: {}", e),
-            ))
-        }
-    };
-
-    // Extract mailbox name (without domain) for webhook
-    let mailbox_name = if let Some(at_pos) = email.to.find('@') {
-        &email.to[..at_pos]
-    } else {
-        &email.to
-    };
-
-    // Delete the email
-    match storage.delete_email(&id).await {
-        Ok(_) => {
-            // Trigger webhook for deletion event
-            if let Err(e) = webhook_trigger
-                .trigger_webhooks(
-                    mailbox_name,
-                    crate::storage::models::WebhookEvent::Deletion,
-                    Some(&email),
+    if !user.is_anonymous() {
+        let email = storage
+            .get_email_by_id(&id)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to fetch email: {}", e),
                 )
-                .await
-            {
-                tracing::warn!("Failed to trigger deletion webhook: {}", e);
-            }
+            })?
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "Email not found".to_string()))?;
+        let local_part = email.to.split('@').next().unwrap_or(&email.to);
+        verify_mailbox_owner(&storage, local_part, &user).await?;
+    }
 
+    match storage.delete_email_by_id(&id).await {
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
+        Ok(Some(email)) => {
+            deletion_notifier
+                .handle_email_deletion(&email.id, &email.to)
+                .await;
             Ok(Json(json!({ "message": "Email deleted successfully" })))
         }
         Err(e) => Err((
@@ -244,617 +703,4051 @@ pub async fn delete_email(
     }
 }
 
-/// Claim mailbox request
-#[derive(Debug, Deserialize)]
-pub struct ClaimMailboxRequest {
-    pub password: String,
+/// Query parameters for clearing a mailbox
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BulkDeleteQuery {
+    /// Password of the mailbox, if locked
+    pub(super) password: Option<String>,
+    /// Only delete emails received more than this many hours ago
+    older_than_hours: Option<i64>,
 }
 
-/// Check mailbox status (locked or not)
-pub async fn check_mailbox_status(
+/// State for [`delete_emails_for_address`]: storage, domain config and deletion
+/// announcements
+pub type BulkDeleteState = (Arc<dyn StorageBackend>, AppConfig, DeletionNotifier);
+
+/// Delete all emails for an address, or only those older than `older_than_hours`
+///
+/// The emails are moved to the trash. Each one is announced to WebSocket/SSE clients and
+/// deletion webhooks, as when deleting emails one at a time.
+#[utoipa::path(
+    delete,
+    path = "/api/emails/{address}",
+    params(("address" = String, Path, description = "Mailbox address or local part"), BulkDeleteQuery),
+    responses(
+        (status = 200, description = "Emails moved to the trash: `{message, deleted}`", body = Value),
+        (status = 400, description = "`older_than_hours` is negative"),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
+pub async fn delete_emails_for_address(
     Path(address): Path<String>,
-    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    Query(params): Query<BulkDeleteQuery>,
+    State((storage, config, deletion_notifier)): State<BulkDeleteState>,
+    user: AuthenticatedUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Mailboxes are keyed by username only (local part)
     let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
 
-    let is_locked = storage
-        .is_mailbox_locked(&local_part)
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
+
+    if params.older_than_hours.is_some_and(|hours| hours < 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "older_than_hours must not be negative".to_string(),
+        ));
+    }
+
+    let deleted = storage
+        .delete_emails_for_address(&normalized_address, params.older_than_hours)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to delete emails: {}", e),
+            )
+        })?;
+
+    for (id, address) in &deleted {
+        deletion_notifier.handle_email_deletion(id, address).await;
+    }
 
     Ok(Json(json!({
-        "address": local_part,
-        "is_locked": is_locked
+        "message": "Emails deleted successfully",
+        "deleted": deleted.len()
     })))
 }
 
-/// Claim a mailbox with a password (first-claim model)
-pub async fn claim_mailbox(
+/// Upper bound on the size of an mbox accepted by [`import_mailbox_mbox`]
+pub const MAX_MBOX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Export all emails for an address as an mbox file, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/emails/{address}/export.mbox",
+    params(("address" = String, Path, description = "Mailbox address or local part"), PasswordQuery),
+    responses(
+        (status = 200, description = "The mailbox's emails in mboxrd format", content_type = "application/mbox", body = String),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
+pub async fn export_mailbox_mbox(
     Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
     State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
-    Json(request): Json<ClaimMailboxRequest>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    // Mailboxes are keyed by username only (local part)
+    user: AuthenticatedUser,
+) -> Result<Response, (StatusCode, String)> {
     let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
 
-    // Check if mailbox is already locked
-    let is_locked = storage
-        .is_mailbox_locked(&local_part)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    if is_locked {
-        return Err((
-            StatusCode::CONFLICT,
-            "Mailbox is already claimed and locked".to_string(),
-        ));
-    }
-
-    // Hash the password
-    let password_hash = bcrypt::hash(&request.password, bcrypt::DEFAULT_COST).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to hash password: {}", e),
-        )
-    })?;
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
 
-    // Set mailbox password (keyed by username only)
-    storage
-        .set_mailbox_password(&local_part, password_hash)
+    let mut emails = storage
+        .get_emails_for_address(&normalized_address)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch emails: {}", e),
+            )
+        })?;
+    emails.reverse();
 
-    Ok(Json(json!({
-        "message": "Mailbox claimed successfully",
-        "address": local_part
-    })))
+    let entries = futures::stream::iter(
+        emails
+            .into_iter()
+            .map(|email| Ok::<_, std::convert::Infallible>(mbox::to_mbox_entry(&email))),
+    );
+    // Keep the filename from breaking out of the quoted header value
+    let filename: String = local_part
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '+'))
+        .collect();
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/mbox".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.mbox\"", filename),
+            ),
+        ],
+        Body::from_stream(entries),
+    )
+        .into_response())
 }
 
-/// Release (unclaim) a mailbox by removing its password
-pub async fn release_mailbox(
+/// Import the messages of an mbox file into a mailbox
+///
+/// Messages whose `Message-ID` the mailbox already has are skipped, so importing the same
+/// file twice is harmless. Imported emails keep their mbox dates and do not trigger
+/// webhooks or realtime notifications.
+#[utoipa::path(
+    post,
+    path = "/api/emails/{address}/import",
+    params(("address" = String, Path, description = "Mailbox address or local part"), PasswordQuery),
+    request_body(content = String, content_type = "application/mbox", description = "Messages in mbox format"),
+    responses(
+        (status = 200, description = "Messages stored: `{message, imported, skipped}`", body = Value),
+        (status = 400, description = "The body is not a valid mbox"),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong"),
+        (status = 403, description = "Mailbox belongs to another user")
+    ),
+    tag = "emails"
+)]
+pub async fn import_mailbox_mbox(
     Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
     State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
-    Json(request): Json<ClaimMailboxRequest>,
+    user: AuthenticatedUser,
+    body: Bytes,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Mailboxes are keyed by username only (local part)
     let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
 
-    // Verify the current password first
-    verify_mailbox_password(&storage, &local_part, Some(&request.password)).await?;
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
 
-    // Clear the password
-    storage
-        .clear_mailbox_password(&local_part)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mbox = String::from_utf8_lossy(&body);
+    let emails = mbox::parse_mbox(&mbox, &normalized_address).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to parse mbox message: {}", e),
+        )
+    })?;
+    if emails.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No messages found in mbox".to_string(),
+        ));
+    }
+
+    // Messages whose Message-ID the mailbox already has are skipped by the store
+    let total = emails.len();
+    let mut imported = 0;
+    for email in emails {
+        let inserted = storage.store_email(email).await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store email: {}", e),
+            )
+        })?;
+        if inserted {
+            imported += 1;
+        }
+    }
 
     Ok(Json(json!({
-        "message": "Mailbox released successfully",
-        "address": local_part
+        "message": "Mailbox imported successfully",
+        "imported": imported,
+        "skipped": total - imported
     })))
 }
 
-/// Create webhook request
-#[derive(Debug, Deserialize)]
-pub struct CreateWebhookRequest {
-    pub mailbox_address: String,
-    pub webhook_url: String,
-    pub events: Vec<String>,
-    pub password: Option<String>,
-}
-
-/// Update webhook request
-#[derive(Debug, Deserialize)]
-pub struct UpdateWebhookRequest {
-    pub mailbox_address: Option<String>,
-    pub webhook_url: Option<String>,
-    pub events: Option<Vec<String>>,
-    pub enabled: Option<bool>,
-}
-
-/// Create a new webhook
-pub async fn create_webhook(
+/// Restore a deleted email from the trash
+#[utoipa::path(
+    post,
+    path = "/api/email/{id}/restore",
+    params(("id" = String, Path, description = "Email ID")),
+    responses(
+        (status = 200, description = "Email restored from the trash", body = Value),
+        (status = 404, description = "Email not in the trash")
+    ),
+    tag = "emails"
+)]
+pub async fn restore_email(
+    Path(id): Path<String>,
     State(storage): State<Arc<dyn StorageBackend>>,
-    Json(request): Json<CreateWebhookRequest>,
+    user: AuthenticatedUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Verify password if mailbox is locked
-    verify_mailbox_password(
-        &storage,
-        &request.mailbox_address,
-        request.password.as_deref(),
-    )
-    .await?;
-
-    // Parse events
-    let events: Result<Vec<WebhookEvent>, _> = request
-        .events
-        .into_iter()
-        .map(|s| WebhookEvent::from_str(&s).ok_or_else(|| format!("Invalid event: {}", s)))
-        .collect();
-
-    let events = match events {
-        Ok(events) => events,
-        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
-    };
-
-    // Validate and normalize webhook URL
-    let webhook_url = if request.webhook_url.starts_with("http://")
-        || request.webhook_url.starts_with("https://")
-    {
-        request.webhook_url
-    } else {
-        format!("http://{}", request.webhook_url)
-    };
-
-    // Extract mailbox name without domain for webhook storage
-    let mailbox_name = request
-        .mailbox_address
-        .split('@')
-        .next()
-        .unwrap_or(&request.mailbox_address);
-
-    let webhook = Webhook::new(mailbox_name.to_string(), webhook_url, events);
-
-    match storage.create_webhook(webhook.clone()).await {
-        Ok(_) => Ok(Json(json!(webhook))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to create webhook: {}", e),
-        )),
+    let email = storage
+        .get_deleted_email_by_id(&id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch email: {}", e),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "Email not found in trash".to_string(),
+            )
+        })?;
+    if !user.is_anonymous() {
+        let local_part = email.to.split('@').next().unwrap_or(&email.to);
+        verify_mailbox_owner(&storage, local_part, &user).await?;
     }
-}
-
-/// Get webhooks for a mailbox
-pub async fn get_webhooks_for_mailbox(
-    Path(address): Path<String>,
-    Query(params): Query<PasswordQuery>,
-    State(storage): State<Arc<dyn StorageBackend>>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    // Verify password if mailbox is locked
-    verify_mailbox_password(&storage, &address, params.password.as_deref()).await?;
 
-    // Extract mailbox name without domain for webhook lookup
-    let mailbox_name = address.split('@').next().unwrap_or(&address);
-    match storage.get_webhooks_for_mailbox(mailbox_name).await {
-        Ok(webhooks) => Ok(Json(json!({ "webhooks": webhooks }))),
+    match storage.restore_email(&id).await {
+        Ok(true) => Ok(Json(json!({ "message": "Email restored successfully" }))),
+        // Purged between the lookup and the restore
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            "Email not found in trash".to_string(),
+        )),
         Err(e) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to fetch webhooks: {}", e),
+            format!("Failed to restore email: {}", e),
         )),
     }
 }
 
-/// Get a specific webhook by ID
-pub async fn get_webhook_by_id(
-    Path(id): Path<String>,
-    State(storage): State<Arc<dyn StorageBackend>>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    match storage.get_webhook_by_id(&id).await {
-        Ok(Some(webhook)) => Ok(Json(json!(webhook))),
-        Ok(None) => Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to fetch webhook: {}", e),
-        )),
-    }
+/// Claim mailbox request
+#[derive(Debug, Deserialize)]
+pub struct ClaimMailboxRequest {
+    pub password: String,
 }
 
-/// Update a webhook
-pub async fn update_webhook(
-    Path(id): Path<String>,
-    State(storage): State<Arc<dyn StorageBackend>>,
-    Json(request): Json<UpdateWebhookRequest>,
+/// Check mailbox status (locked or not)
+pub async fn check_mailbox_status(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Get existing webhook
-    let mut webhook = match storage.get_webhook_by_id(&id).await {
-        Ok(Some(webhook)) => webhook,
-        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to fetch webhook: {}", e),
-            ))
-        }
-    };
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
 
-    // Update fields if provided
-    if let Some(mailbox_address) = request.mailbox_address {
-        webhook.mailbox_address = mailbox_address;
-    }
-    if let Some(webhook_url) = request.webhook_url {
-        // Normalize URL
-        webhook.webhook_url =
-            if webhook_url.starts_with("http://") || webhook_url.starts_with("https://") {
-                webhook_url
-            } else {
-                format!("http://{}", webhook_url)
-            };
-    }
-    if let Some(events) = request.events {
-        let parsed_events: Result<Vec<WebhookEvent>, _> = events
-            .into_iter()
-            .map(|s| WebhookEvent::from_str(&s).ok_or_else(|| format!("Invalid event: {}", s)))
-            .collect();
+    let is_locked = storage
+        .is_mailbox_locked(&local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        match parsed_events {
-            Ok(events) => webhook.events = events,
-            Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
-        }
-    }
-    if let Some(enabled) = request.enabled {
-        webhook.enabled = enabled;
-    }
+    Ok(Json(json!({
+        "address": local_part,
+        "is_locked": is_locked
+    })))
+}
 
-    match storage.update_webhook(webhook.clone()).await {
-        Ok(_) => Ok(Json(json!(webhook))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to update webhook: {}", e),
-        )),
-    }
+/// How a random mailbox name is generated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailboxNameStyle {
+    /// Alternating consonants and vowels followed by two digits, e.g. `bakodure42`
+    #[default]
+    Pronounceable,
+    /// The first 12 hex digits of a random UUID
+    Uuid,
 }
 
-/// Delete a webhook
-pub async fn delete_webhook(
-    Path(id): Path<String>,
-    State(storage): State<Arc<dyn StorageBackend>>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    match storage.delete_webhook(&id).await {
-        Ok(_) => Ok(Json(json!({ "message": "Webhook deleted successfully" }))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to delete webhook: {}", e),
-        )),
-    }
+/// Random mailbox query parameters
+#[derive(Debug, Deserialize)]
+pub struct RandomMailboxQuery {
+    /// Text the generated local part starts with
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub style: MailboxNameStyle,
 }
 
-/// Test a webhook
-pub async fn test_webhook(
-    Path(id): Path<String>,
-    State(storage): State<Arc<dyn StorageBackend>>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    let webhook = match storage.get_webhook_by_id(&id).await {
-        Ok(Some(webhook)) => webhook,
-        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
-        Err(e) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to fetch webhook: {}", e),
-            ))
+/// Names tried before giving up on finding an unused mailbox
+const RANDOM_MAILBOX_ATTEMPTS: usize = 10;
+
+/// Generate a random local part in the given style
+fn random_local_part(style: MailboxNameStyle) -> String {
+    const CONSONANTS: &[u8] = b"bcdfghjklmnprstvwz";
+    const VOWELS: &[u8] = b"aeiou";
+
+    let random = uuid::Uuid::new_v4();
+    match style {
+        MailboxNameStyle::Uuid => random.simple().to_string()[..12].to_string(),
+        MailboxNameStyle::Pronounceable => {
+            let bytes = random.as_bytes();
+            let mut name: String = bytes[..8]
+                .chunks(2)
+                .flat_map(|pair| {
+                    [
+                        CONSONANTS[pair[0] as usize % CONSONANTS.len()] as char,
+                        VOWELS[pair[1] as usize % VOWELS.len()] as char,
+                    ]
+                })
+                .collect();
+            name.push_str(&format!("{:02}", bytes[9] % 100));
+            name
         }
-    };
-
-    let webhook_trigger = WebhookTrigger::new(storage);
-    match webhook_trigger.test_webhook(&webhook).await {
-        Ok(success) => Ok(Json(json!({ "success": success }))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to test webhook: {}", e),
-        )),
     }
 }
 
-/// Send an email via the outbound mailer
-pub async fn send_email(
-    State((storage, mailer, config)): State<(
-        Arc<dyn StorageBackend>,
-        Arc<OutboundMailer>,
-        AppConfig,
-    )>,
-    Json(request): Json<SendEmailRequest>,
+/// Generate a random mailbox address on the primary domain that has no email yet
+pub async fn random_mailbox(
+    Query(params): Query<RandomMailboxQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Validate to address
-    if !request.to.contains('@') {
+    let prefix = params.prefix.as_deref().unwrap_or("").trim();
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
         return Err((
             StatusCode::BAD_REQUEST,
-            "Invalid recipient address".to_string(),
+            "prefix may only contain letters, digits, '.', '_' and '-'".to_string(),
         ));
     }
 
-    // Build from address
-    let from_local = request.from_address.as_deref().unwrap_or("noreply");
-    let from_address = format!("{}@{}", from_local, config.domain_name);
+    for _ in 0..RANDOM_MAILBOX_ATTEMPTS {
+        let address = format!(
+            "{}{}@{}",
+            prefix,
+            random_local_part(params.style),
+            config.domain_name
+        );
+        let emails = storage
+            .get_emails_for_address(&address)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if emails.is_empty() {
+            return Ok(Json(json!({ "address": address })));
+        }
+    }
 
-    // Send the email
-    let message_id = mailer.send_email(&request).await.map_err(|e| {
-        tracing::error!(error = %e, to = %request.to, "Failed to send email");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to send email: {}", e),
-        )
-    })?;
+    Err((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Could not find an unused mailbox name, try again".to_string(),
+    ))
+}
 
-    // Store sent email in DB
-    let sent = SentEmail::new(
-        from_address.clone(),
-        request.to.clone(),
-        request.subject.clone(),
-        request.body_text.clone(),
-        request.body_html.clone(),
-        message_id.clone(),
-    );
+/// Claim a mailbox with a password (first-claim model)
+pub async fn claim_mailbox(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    Json(request): Json<ClaimMailboxRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
 
-    if let Err(e) = storage.store_sent_email(sent).await {
-        tracing::warn!("Failed to store sent email: {}", e);
+    // Check if mailbox is already locked
+    let is_locked = storage
+        .is_mailbox_locked(&local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if is_locked {
+        return Err((
+            StatusCode::CONFLICT,
+            "Mailbox is already claimed and locked".to_string(),
+        ));
+    }
+
+    // Hash the password
+    let password_hash = bcrypt::hash(&request.password, bcrypt::DEFAULT_COST).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to hash password: {}", e),
+        )
+    })?;
+
+    // Set mailbox password (keyed by username only)
+    storage
+        .set_mailbox_password(&local_part, password_hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({
+        "message": "Mailbox claimed successfully",
+        "address": local_part
+    })))
+}
+
+/// Claim ownership of a mailbox for the authenticated user
+///
+/// Once owned, only that user can list, read or delete the mailbox's emails.
+pub async fn claim_mailbox_owner(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    if user.is_anonymous() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Authentication is not enabled".to_string(),
+        ));
+    }
+
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
+
+    let claimed = storage
+        .claim_mailbox_owner(&local_part, &user.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !claimed {
+        // Claiming a mailbox you already own is a no-op
+        verify_mailbox_owner(&storage, &local_part, &user)
+            .await
+            .map_err(|(status, message)| match status {
+                StatusCode::FORBIDDEN => (
+                    StatusCode::CONFLICT,
+                    "Mailbox is already owned by another user".to_string(),
+                ),
+                _ => (status, message),
+            })?;
     }
 
     Ok(Json(json!({
-        "message_id": message_id,
-        "from": from_address,
-        "to": request.to,
-        "subject": request.subject,
+        "message": "Mailbox ownership claimed",
+        "address": local_part,
+        "owner": user.user_id
+    })))
+}
+
+/// Release (unclaim) a mailbox by removing its password
+pub async fn release_mailbox(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    Json(request): Json<ClaimMailboxRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
+
+    // Verify the current password first
+    verify_mailbox_password(&storage, &local_part, Some(&request.password)).await?;
+
+    // Clear the password
+    storage
+        .clear_mailbox_password(&local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({
+        "message": "Mailbox released successfully",
+        "address": local_part
+    })))
+}
+
+/// Set mailbox password request
+#[derive(Debug, Deserialize)]
+pub struct SetMailboxPasswordRequest {
+    /// New password, used for the web UI and IMAP
+    pub password: String,
+    /// Current password, needed when auth is disabled and one is already set
+    #[serde(default)]
+    pub current_password: Option<String>,
+}
+
+/// Check that a user may change a mailbox's password
+///
+/// When auth is enabled only the mailbox's owner or an admin may. Otherwise anyone who
+/// knows the current password may, as for releasing a mailbox.
+async fn verify_mailbox_password_manager(
+    storage: &Arc<dyn StorageBackend>,
+    local_part: &str,
+    user: &AuthenticatedUser,
+    current_password: Option<&str>,
+) -> Result<(), (StatusCode, String)> {
+    if user.is_anonymous() {
+        return verify_mailbox_password(storage, local_part, current_password).await;
+    }
+
+    let owner = storage
+        .get_mailbox_owner(local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if owner.as_deref() == Some(user.user_id.as_str()) {
+        return Ok(());
+    }
+    crate::auth::verify_admin(storage, user)
+        .await
+        .map_err(|(status, message)| match status {
+            StatusCode::FORBIDDEN => (
+                StatusCode::FORBIDDEN,
+                "Only the mailbox's owner or an admin can change its password".to_string(),
+            ),
+            _ => (status, message),
+        })
+}
+
+/// Set or replace a mailbox's password
+pub async fn set_mailbox_password(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+    Json(request): Json<SetMailboxPasswordRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
+
+    if request.password.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Password must not be empty".to_string(),
+        ));
+    }
+
+    verify_mailbox_password_manager(
+        &storage,
+        &local_part,
+        &user,
+        request.current_password.as_deref(),
+    )
+    .await?;
+
+    let password_hash = bcrypt::hash(&request.password, bcrypt::DEFAULT_COST).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to hash password: {}", e),
+        )
+    })?;
+    storage
+        .set_mailbox_password(&local_part, password_hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tracing::info!("🔑 Mailbox password set for {}", local_part);
+
+    Ok(Json(json!({
+        "message": "Mailbox password set",
+        "address": local_part
+    })))
+}
+
+/// Remove a mailbox's password, unlocking it
+pub async fn clear_mailbox_password(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Mailboxes are keyed by username only (local part)
+    let local_part = config.extract_local_part(&address);
+
+    verify_mailbox_password_manager(&storage, &local_part, &user, params.password.as_deref())
+        .await?;
+
+    storage
+        .clear_mailbox_password(&local_part)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tracing::info!("🔑 Mailbox password cleared for {}", local_part);
+
+    Ok(Json(json!({
+        "message": "Mailbox password cleared",
+        "address": local_part
+    })))
+}
+
+/// Mailbox retention request
+#[derive(Debug, Deserialize)]
+pub struct MailboxRetentionRequest {
+    /// Hours to keep the mailbox's email; `null` returns it to the global retention
+    pub retention_hours: Option<i64>,
+    pub password: Option<String>,
+}
+
+/// Get the retention a mailbox overrides the global one with; `null` when it has none
+pub async fn get_mailbox_retention(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
+
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, params.password.as_deref()).await?;
+
+    let retention_hours = storage
+        .get_mailbox_retention(&normalized_address)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch mailbox retention: {}", e),
+            )
+        })?;
+
+    Ok(Json(json!({
+        "address": normalized_address,
+        "retention_hours": retention_hours
+    })))
+}
+
+/// Keep a mailbox's email for longer or shorter than the global `EMAIL_RETENTION_HOURS`
+pub async fn set_mailbox_retention(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+    Json(request): Json<MailboxRetentionRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let local_part = config.extract_local_part(&address);
+    let normalized_address = config.normalize_address(&address);
+
+    verify_mailbox_owner(&storage, &local_part, &user).await?;
+    verify_mailbox_password(&storage, &local_part, request.password.as_deref()).await?;
+
+    if request.retention_hours.is_some_and(|hours| hours <= 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "retention_hours must be positive".to_string(),
+        ));
+    }
+
+    storage
+        .set_mailbox_retention(&normalized_address, request.retention_hours)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to set mailbox retention: {}", e),
+            )
+        })?;
+
+    Ok(Json(json!({
+        "address": normalized_address,
+        "retention_hours": request.retention_hours
+    })))
+}
+
+/// Create alias request
+#[derive(Debug, Deserialize)]
+pub struct CreateAliasRequest {
+    /// Mailbox whose email is redirected
+    pub source: String,
+    /// Mailboxes that receive the email instead
+    pub destinations: Vec<String>,
+    /// Password of the source mailbox, if locked
+    pub password: Option<String>,
+}
+
+/// Create an alias delivering a mailbox's email into one or more other mailboxes
+pub async fn create_alias(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+    Json(request): Json<CreateAliasRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let source = config.extract_local_part(&request.source);
+    let destinations: Vec<String> = request
+        .destinations
+        .iter()
+        .map(|destination| config.extract_local_part(destination))
+        .collect();
+
+    if source.is_empty() || destinations.is_empty() || destinations.iter().any(|d| d.is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "An alias needs a source and at least one destination".to_string(),
+        ));
+    }
+    if destinations.contains(&source) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A mailbox cannot be aliased to itself".to_string(),
+        ));
+    }
+
+    verify_mailbox_password(&storage, &source, request.password.as_deref()).await?;
+    verify_mailbox_owner(&storage, &source, &user).await?;
+    for destination in &destinations {
+        verify_mailbox_owner(&storage, destination, &user).await?;
+    }
+
+    storage
+        .create_alias(&source, &destinations)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create alias: {}", e),
+            )
+        })?;
+
+    let destinations = storage
+        .get_aliases_for(&source)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({
+        "source": source,
+        "destinations": destinations
     })))
 }
 
-/// Get sent emails for a given from address
-pub async fn get_sent_emails(
-    Path(address): Path<String>,
-    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    let normalized = config.normalize_address(&address);
+/// Get the mailboxes an address is aliased to
+pub async fn get_aliases(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let source = config.extract_local_part(&address);
+    verify_mailbox_password(&storage, &source, params.password.as_deref()).await?;
+    verify_mailbox_owner(&storage, &source, &user).await?;
+
+    match storage.get_aliases_for(&source).await {
+        Ok(destinations) => Ok(Json(json!({
+            "source": source,
+            "destinations": destinations
+        }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch aliases: {}", e),
+        )),
+    }
+}
+
+/// Create forwarding rule request
+#[derive(Debug, Deserialize)]
+pub struct CreateForwardingRequest {
+    /// Mailbox whose received email is forwarded
+    pub address: String,
+    /// Address the email is relayed to
+    pub destination: String,
+    /// Upstream SMTP server to relay through
+    pub relay_host: String,
+    /// Relay port (default 587)
+    pub relay_port: Option<u16>,
+    pub relay_username: Option<String>,
+    pub relay_password: Option<String>,
+    /// Require STARTTLS on the relay connection (default true)
+    pub relay_starttls: Option<bool>,
+    /// Password of the mailbox, if locked
+    pub password: Option<String>,
+}
+
+/// Forward a mailbox's received email to an outside address
+pub async fn create_forwarding_rule(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+    Json(request): Json<CreateForwardingRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mailbox = config.extract_local_part(&request.address);
+    if mailbox.is_empty() || request.relay_host.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A forwarding rule needs a mailbox and a relay host".to_string(),
+        ));
+    }
+    if request.destination.parse::<lettre::Address>().is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid destination address: {}", request.destination),
+        ));
+    }
+
+    verify_mailbox_password(&storage, &mailbox, request.password.as_deref()).await?;
+    verify_mailbox_owner(&storage, &mailbox, &user).await?;
+
+    let mut rule = ForwardingRule::new(
+        mailbox,
+        request.destination,
+        request.relay_host.trim().to_string(),
+        request.relay_port.unwrap_or(587),
+    );
+    rule.relay_username = request.relay_username.filter(|u| !u.is_empty());
+    rule.relay_password = request.relay_password.filter(|p| !p.is_empty());
+    rule.relay_starttls = request.relay_starttls.unwrap_or(true);
+
+    match storage.create_forwarding_rule(rule.clone()).await {
+        Ok(_) => Ok(Json(json!(rule))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create forwarding rule: {}", e),
+        )),
+    }
+}
+
+/// Get the forwarding rules for a mailbox
+pub async fn get_forwarding_rules(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mailbox = config.extract_local_part(&address);
+    verify_mailbox_password(&storage, &mailbox, params.password.as_deref()).await?;
+    verify_mailbox_owner(&storage, &mailbox, &user).await?;
+
+    match storage.get_forwarding_rules(&mailbox).await {
+        Ok(rules) => Ok(Json(json!({
+            "address": mailbox,
+            "rules": rules
+        }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch forwarding rules: {}", e),
+        )),
+    }
+}
+
+/// Create routing rule request
+#[derive(Debug, Deserialize)]
+pub struct CreateRoutingRuleRequest {
+    /// Mailbox whose received email is checked
+    pub mailbox: String,
+    /// Glob (`*`, `?`) or text the subject must match, ignoring case
+    pub subject_pattern: String,
+    /// Mailbox that also receives matching email
+    pub target_mailbox: String,
+    /// Password of the mailbox, if locked
+    pub password: Option<String>,
+}
+
+/// Copy a mailbox's email whose subject matches a pattern into another mailbox
+pub async fn create_routing_rule(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+    user: AuthenticatedUser,
+    Json(request): Json<CreateRoutingRuleRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mailbox = config.extract_local_part(&request.mailbox);
+    let target_mailbox = config.extract_local_part(&request.target_mailbox);
+    let subject_pattern = request.subject_pattern.trim();
+    if mailbox.is_empty() || target_mailbox.is_empty() || subject_pattern.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A routing rule needs a mailbox, a subject pattern and a target mailbox".to_string(),
+        ));
+    }
+    if target_mailbox == mailbox {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A mailbox cannot be routed to itself".to_string(),
+        ));
+    }
+
+    verify_mailbox_password(&storage, &mailbox, request.password.as_deref()).await?;
+    verify_mailbox_owner(&storage, &mailbox, &user).await?;
+    verify_mailbox_owner(&storage, &target_mailbox, &user).await?;
+
+    let rule = RoutingRule::new(mailbox, subject_pattern.to_string(), target_mailbox);
+    match storage.create_routing_rule(rule.clone()).await {
+        Ok(_) => Ok(Json(json!(rule))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create routing rule: {}", e),
+        )),
+    }
+}
+
+/// Create webhook request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub mailbox_address: String,
+    pub webhook_url: String,
+    pub events: Vec<String>,
+    pub password: Option<String>,
+    /// Optional secret used to sign deliveries with HMAC-SHA256
+    pub secret: Option<String>,
+    /// Retries after a failed delivery (default 2)
+    pub max_retries: Option<u32>,
+    /// Delay before the first retry, doubled for each later one (default 1)
+    pub initial_backoff_secs: Option<u64>,
+    /// Ceiling on the delay between retries (default 60)
+    pub max_backoff_secs: Option<u64>,
+    /// Only fire for senders containing this text (case-insensitive)
+    pub from_filter: Option<String>,
+    /// Only fire for subjects containing this text (case-insensitive)
+    pub subject_filter: Option<String>,
+
+    /// Include base64 attachment content in payloads (default false)
+    pub include_attachment_content: Option<bool>,
+    /// Template rendered into the payload instead of the default one, e.g.
+    /// `{"text": "New mail from {{ email.from }}: {{ email.subject }}"}`
+    pub payload_template: Option<String>,
+    /// Payload shape: `json` (default), `slack` or `discord`
+    pub format: Option<String>,
+    /// Extra headers sent with every delivery, e.g. `{"Authorization": "Bearer ..."}`; a
+    /// `User-Agent` here replaces the default one
+    pub headers: Option<HashMap<String, String>>,
+}
+
+/// Update webhook request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateWebhookRequest {
+    pub mailbox_address: Option<String>,
+    pub webhook_url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    /// New signing secret; an empty string removes it
+    pub secret: Option<String>,
+    pub max_retries: Option<u32>,
+    pub initial_backoff_secs: Option<u64>,
+    pub max_backoff_secs: Option<u64>,
+    /// New sender filter; an empty string removes it
+    pub from_filter: Option<String>,
+    /// New subject filter; an empty string removes it
+    pub subject_filter: Option<String>,
+
+    pub include_attachment_content: Option<bool>,
+    /// New payload template; an empty string restores the default payload
+    pub payload_template: Option<String>,
+    /// New payload shape: `json`, `slack` or `discord`
+    pub format: Option<String>,
+    /// New extra headers, replacing the current ones; an empty object removes them
+    pub headers: Option<HashMap<String, String>>,
+}
+
+/// Reject a payload template that does not render valid JSON
+fn check_payload_template(template: &str) -> Result<(), (StatusCode, String)> {
+    validate_payload_template(template).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid payload template: {:#}", e),
+        )
+    })
+}
+
+/// Reject custom webhook headers that are malformed or reserved
+fn check_webhook_headers(headers: &HashMap<String, String>) -> Result<(), (StatusCode, String)> {
+    validate_webhook_headers(headers).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid webhook headers: {:#}", e),
+        )
+    })
+}
+
+/// Parse a webhook format name from a request
+fn parse_webhook_format(format: &str) -> Result<WebhookFormat, (StatusCode, String)> {
+    WebhookFormat::from_str(format).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid format: {}", format),
+        )
+    })
+}
+
+/// Build a webhook from a create request, checking its events, payload template and
+/// headers
+fn webhook_from_request(request: CreateWebhookRequest) -> Result<Webhook, (StatusCode, String)> {
+    // Parse events
+    let events: Result<Vec<WebhookEvent>, _> = request
+        .events
+        .into_iter()
+        .map(|s| WebhookEvent::from_str(&s).ok_or_else(|| format!("Invalid event: {}", s)))
+        .collect();
+
+    let events = match events {
+        Ok(events) => events,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
+    };
+
+    let payload_template = request.payload_template.filter(|t| !t.is_empty());
+    if let Some(template) = &payload_template {
+        check_payload_template(template)?;
+    }
+
+    // Validate and normalize webhook URL
+    let webhook_url = if request.webhook_url.starts_with("http://")
+        || request.webhook_url.starts_with("https://")
+    {
+        request.webhook_url
+    } else {
+        format!("http://{}", request.webhook_url)
+    };
+
+    // Extract mailbox name without domain for webhook storage
+    let mailbox_name = request
+        .mailbox_address
+        .split('@')
+        .next()
+        .unwrap_or(&request.mailbox_address);
+
+    let mut webhook = Webhook::new(mailbox_name.to_string(), webhook_url, events);
+    webhook.secret = request.secret.filter(|s| !s.is_empty());
+    webhook.from_filter = request.from_filter.filter(|f| !f.is_empty());
+    webhook.subject_filter = request.subject_filter.filter(|f| !f.is_empty());
+    webhook.include_attachment_content = request.include_attachment_content.unwrap_or(false);
+    webhook.payload_template = payload_template;
+    if let Some(format) = &request.format {
+        webhook.format = parse_webhook_format(format)?;
+    }
+    if let Some(headers) = request.headers {
+        check_webhook_headers(&headers)?;
+        webhook.headers = headers;
+    }
+    if let Some(max_retries) = request.max_retries {
+        webhook.max_retries = max_retries;
+    }
+    if let Some(initial_backoff_secs) = request.initial_backoff_secs {
+        webhook.initial_backoff_secs = initial_backoff_secs;
+    }
+    if let Some(max_backoff_secs) = request.max_backoff_secs {
+        webhook.max_backoff_secs = max_backoff_secs;
+    }
+
+    Ok(webhook)
+}
+
+/// Create a new webhook
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 200, description = "The created webhook", body = Webhook),
+        (status = 400, description = "Unknown event name, invalid payload template or invalid headers"),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong")
+    ),
+    tag = "webhooks"
+)]
+pub async fn create_webhook(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Verify password if mailbox is locked
+    verify_mailbox_password(
+        &storage,
+        &request.mailbox_address,
+        request.password.as_deref(),
+    )
+    .await?;
+
+    let webhook = webhook_from_request(request)?;
+
+    match storage.create_webhook(webhook.clone()).await {
+        Ok(_) => {
+            record_audit(
+                &storage,
+                audit_actor(user),
+                "webhook.create",
+                &webhook.id,
+                webhook_audit_detail(&webhook),
+            )
+            .await;
+            Ok(Json(json!(webhook)))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create webhook: {}", e),
+        )),
+    }
+}
+
+/// Audit log details of a webhook, leaving out its secret and headers
+fn webhook_audit_detail(webhook: &Webhook) -> Value {
+    json!({
+        "mailbox_address": webhook.mailbox_address,
+        "webhook_url": webhook.webhook_url,
+        "events": webhook.events,
+        "enabled": webhook.enabled,
+    })
+}
+
+/// Create several webhooks at once, e.g. the same webhook for many mailboxes
+///
+/// Every entry is checked before any is created, and the webhooks are stored in one
+/// transaction, so a batch with an invalid entry creates nothing.
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/batch",
+    request_body = Vec<CreateWebhookRequest>,
+    responses(
+        (status = 200, description = "The created webhooks: `{webhooks}`", body = Value),
+        (status = 400, description = "Empty batch, or an entry with an unknown event name or invalid payload template; the message names the entry's index"),
+        (status = 401, description = "An entry's mailbox is locked and the password is missing or wrong")
+    ),
+    tag = "webhooks"
+)]
+pub async fn create_webhooks_batch(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+    Json(requests): Json<Vec<CreateWebhookRequest>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    if requests.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Batch contains no webhooks".to_string(),
+        ));
+    }
+
+    let mut webhooks = Vec::with_capacity(requests.len());
+    for (index, request) in requests.into_iter().enumerate() {
+        let in_entry = |(status, message): (StatusCode, String)| {
+            (status, format!("Webhook {}: {}", index, message))
+        };
+        verify_mailbox_password(
+            &storage,
+            &request.mailbox_address,
+            request.password.as_deref(),
+        )
+        .await
+        .map_err(in_entry)?;
+        webhooks.push(webhook_from_request(request).map_err(in_entry)?);
+    }
+
+    match storage.create_webhooks(webhooks.clone()).await {
+        Ok(_) => {
+            let actor = audit_actor(user);
+            for webhook in &webhooks {
+                record_audit(
+                    &storage,
+                    actor.clone(),
+                    "webhook.create",
+                    &webhook.id,
+                    webhook_audit_detail(webhook),
+                )
+                .await;
+            }
+            Ok(Json(json!({ "webhooks": webhooks })))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create webhooks: {}", e),
+        )),
+    }
+}
+
+/// Sample email for a webhook preview
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PreviewEmail {
+    pub from: String,
+    pub subject: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// Webhook preview request: a webhook definition plus what to preview it with
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PreviewWebhookRequest {
+    #[serde(flatten)]
+    pub webhook: CreateWebhookRequest,
+    /// Event to preview (default: the webhook's first event)
+    pub event: Option<String>,
+    /// Email to build the payload from; an example email is used when omitted
+    pub email: Option<PreviewEmail>,
+}
+
+/// Preview the payload a webhook would send, without saving or sending anything
+#[utoipa::path(
+    post,
+    path = "/api/webhooks/preview",
+    request_body = PreviewWebhookRequest,
+    responses(
+        (status = 200, description = "The payload the webhook would send", body = Value),
+        (status = 400, description = "Unknown event name, invalid payload template or invalid headers")
+    ),
+    tag = "webhooks"
+)]
+pub async fn preview_webhook(
+    State(storage): State<Arc<dyn StorageBackend>>,
+    Json(request): Json<PreviewWebhookRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let webhook = webhook_from_request(request.webhook)?;
+    let event = match &request.event {
+        Some(event) => WebhookEvent::from_str(event)
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Invalid event: {}", event)))?,
+        None => webhook
+            .events
+            .first()
+            .cloned()
+            .unwrap_or(WebhookEvent::Arrival),
+    };
+
+    let to = webhook.mailbox_address.clone();
+    let email = match request.email {
+        Some(sample) => Email::new(to, sample.from, sample.subject, sample.body, None, vec![]),
+        None => sample_email(&to),
+    };
+
+    let webhook_trigger = WebhookTrigger::new(storage);
+    Ok(Json(webhook_trigger.create_webhook_payload(
+        &event,
+        Some(&email),
+        &webhook,
+    )))
+}
+
+/// Get webhooks for a mailbox
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{address}",
+    params(("address" = String, Path, description = "Mailbox address or local part"), PasswordQuery),
+    responses(
+        (status = 200, description = "The mailbox's webhooks: `{webhooks}`", body = Value),
+        (status = 401, description = "Mailbox is locked and the password is missing or wrong")
+    ),
+    tag = "webhooks"
+)]
+pub async fn get_webhooks_for_mailbox(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Verify password if mailbox is locked
+    verify_mailbox_password(&storage, &address, params.password.as_deref()).await?;
+
+    // Extract mailbox name without domain for webhook lookup
+    let mailbox_name = address.split('@').next().unwrap_or(&address);
+    match storage.get_webhooks_for_mailbox(mailbox_name).await {
+        Ok(webhooks) => Ok(Json(json!({ "webhooks": webhooks }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch webhooks: {}", e),
+        )),
+    }
+}
+
+/// Get a specific webhook by ID
+#[utoipa::path(
+    get,
+    path = "/api/webhook/{id}",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The webhook", body = Webhook),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn get_webhook_by_id(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    match storage.get_webhook_by_id(&id).await {
+        Ok(Some(webhook)) => Ok(Json(json!(webhook))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch webhook: {}", e),
+        )),
+    }
+}
+
+/// Update a webhook
+#[utoipa::path(
+    put,
+    path = "/api/webhook/{id}",
+    params(("id" = String, Path, description = "Webhook ID")),
+    request_body = UpdateWebhookRequest,
+    responses(
+        (status = 200, description = "The updated webhook", body = Webhook),
+        (status = 400, description = "Unknown event name, invalid payload template or invalid headers"),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn update_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+    Json(request): Json<UpdateWebhookRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Get existing webhook
+    let mut webhook = match storage.get_webhook_by_id(&id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch webhook: {}", e),
+            ))
+        }
+    };
+
+    // Update fields if provided
+    if let Some(mailbox_address) = request.mailbox_address {
+        webhook.mailbox_address = mailbox_address;
+    }
+    if let Some(webhook_url) = request.webhook_url {
+        // Normalize URL
+        webhook.webhook_url =
+            if webhook_url.starts_with("http://") || webhook_url.starts_with("https://") {
+                webhook_url
+            } else {
+                format!("http://{}", webhook_url)
+            };
+    }
+    if let Some(events) = request.events {
+        let parsed_events: Result<Vec<WebhookEvent>, _> = events
+            .into_iter()
+            .map(|s| WebhookEvent::from_str(&s).ok_or_else(|| format!("Invalid event: {}", s)))
+            .collect();
+
+        match parsed_events {
+            Ok(events) => webhook.events = events,
+            Err(e) => return Err((StatusCode::BAD_REQUEST, e)),
+        }
+    }
+    if let Some(enabled) = request.enabled {
+        webhook.enabled = enabled;
+    }
+    if let Some(secret) = request.secret {
+        webhook.secret = Some(secret).filter(|s| !s.is_empty());
+    }
+    if let Some(from_filter) = request.from_filter {
+        webhook.from_filter = Some(from_filter).filter(|f| !f.is_empty());
+    }
+    if let Some(subject_filter) = request.subject_filter {
+        webhook.subject_filter = Some(subject_filter).filter(|f| !f.is_empty());
+    }
+    if let Some(include_attachment_content) = request.include_attachment_content {
+        webhook.include_attachment_content = include_attachment_content;
+    }
+    if let Some(payload_template) = request.payload_template {
+        if !payload_template.is_empty() {
+            check_payload_template(&payload_template)?;
+        }
+        webhook.payload_template = Some(payload_template).filter(|t| !t.is_empty());
+    }
+    if let Some(format) = &request.format {
+        webhook.format = parse_webhook_format(format)?;
+    }
+    if let Some(headers) = request.headers {
+        check_webhook_headers(&headers)?;
+        webhook.headers = headers;
+    }
+    if let Some(max_retries) = request.max_retries {
+        webhook.max_retries = max_retries;
+    }
+    if let Some(initial_backoff_secs) = request.initial_backoff_secs {
+        webhook.initial_backoff_secs = initial_backoff_secs;
+    }
+    if let Some(max_backoff_secs) = request.max_backoff_secs {
+        webhook.max_backoff_secs = max_backoff_secs;
+    }
+
+    match storage.update_webhook(webhook.clone()).await {
+        Ok(_) => {
+            record_audit(
+                &storage,
+                audit_actor(user),
+                "webhook.update",
+                &webhook.id,
+                webhook_audit_detail(&webhook),
+            )
+            .await;
+            Ok(Json(json!(webhook)))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update webhook: {}", e),
+        )),
+    }
+}
+
+/// Query parameters for listing webhook deliveries
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryListQuery {
+    /// Maximum number of deliveries to return (default 50, max 500)
+    #[serde(default = "default_page_limit")]
+    limit: i64,
+}
+
+/// Get recent delivery attempts for a webhook
+#[utoipa::path(
+    get,
+    path = "/api/webhook/{id}/deliveries",
+    params(("id" = String, Path, description = "Webhook ID"), DeliveryListQuery),
+    responses(
+        (status = 200, description = "Recent delivery attempts, newest first: `{deliveries}`", body = Value),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn get_webhook_deliveries(
+    Path(id): Path<String>,
+    Query(params): Query<DeliveryListQuery>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    match storage.get_webhook_by_id(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch webhook: {}", e),
+            ))
+        }
+    }
+
+    let limit = params.limit.clamp(1, MAX_PAGE_LIMIT);
+    match storage.get_webhook_deliveries(&id, limit).await {
+        Ok(deliveries) => Ok(Json(json!({ "deliveries": deliveries }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch deliveries: {}", e),
+        )),
+    }
+}
+
+/// Enable a webhook
+#[utoipa::path(
+    post,
+    path = "/api/webhook/{id}/enable",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The updated webhook", body = Webhook),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn enable_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    set_webhook_enabled(&storage, &id, true, audit_actor(user)).await
+}
+
+/// Disable a webhook, pausing its deliveries until it is enabled again
+#[utoipa::path(
+    post,
+    path = "/api/webhook/{id}/disable",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The updated webhook", body = Webhook),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn disable_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    set_webhook_enabled(&storage, &id, false, audit_actor(user)).await
+}
+
+async fn set_webhook_enabled(
+    storage: &Arc<dyn StorageBackend>,
+    id: &str,
+    enabled: bool,
+    actor: Option<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let mut webhook = match storage.get_webhook_by_id(id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch webhook: {}", e),
+            ))
+        }
+    };
+
+    webhook.enabled = enabled;
+    match storage.update_webhook(webhook.clone()).await {
+        Ok(_) => {
+            let action = if enabled {
+                "webhook.enable"
+            } else {
+                "webhook.disable"
+            };
+            record_audit(storage, actor, action, id, json!({})).await;
+            Ok(Json(json!(webhook)))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update webhook: {}", e),
+        )),
+    }
+}
+
+/// Delete a webhook
+#[utoipa::path(
+    delete,
+    path = "/api/webhook/{id}",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "Webhook deleted", body = Value)
+    ),
+    tag = "webhooks"
+)]
+pub async fn delete_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    user: Option<AuthenticatedUser>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    match storage.delete_webhook(&id).await {
+        Ok(_) => {
+            record_audit(
+                &storage,
+                audit_actor(user),
+                "webhook.delete",
+                &id,
+                json!({}),
+            )
+            .await;
+            Ok(Json(json!({ "message": "Webhook deleted successfully" })))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to delete webhook: {}", e),
+        )),
+    }
+}
+
+/// Test a webhook
+#[utoipa::path(
+    post,
+    path = "/api/webhook/{id}/test",
+    params(("id" = String, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "Whether the test delivery succeeded: `{success}`", body = Value),
+        (status = 404, description = "Webhook not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn test_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let webhook = match storage.get_webhook_by_id(&id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch webhook: {}", e),
+            ))
+        }
+    };
+
+    let webhook_trigger = WebhookTrigger::new(storage);
+    match webhook_trigger.test_webhook(&webhook).await {
+        Ok(success) => Ok(Json(json!({ "success": success }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to test webhook: {}", e),
+        )),
+    }
+}
+
+/// Request body for replaying a webhook
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReplayWebhookRequest {
+    /// Email whose arrival is delivered again
+    pub email_id: String,
+}
+
+/// Deliver the arrival payload of an email to a webhook again
+#[utoipa::path(
+    post,
+    path = "/api/webhook/{id}/replay",
+    params(("id" = String, Path, description = "Webhook ID")),
+    request_body = ReplayWebhookRequest,
+    responses(
+        (status = 200, description = "Whether the delivery succeeded: `{success}`", body = Value),
+        (status = 400, description = "Email does not belong to the webhook's mailbox"),
+        (status = 404, description = "Webhook or email not found")
+    ),
+    tag = "webhooks"
+)]
+pub async fn replay_webhook(
+    Path(id): Path<String>,
+    State(storage): State<Arc<dyn StorageBackend>>,
+    Json(request): Json<ReplayWebhookRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let webhook = match storage.get_webhook_by_id(&id).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch webhook: {}", e),
+            ))
+        }
+    };
+    let email = match storage.get_email_by_id(&request.email_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch email: {}", e),
+            ))
+        }
+    };
+
+    // Webhooks are registered by mailbox name, without the domain
+    let mailbox_name = email.to.split('@').next().unwrap_or(&email.to);
+    if mailbox_name != webhook.mailbox_address {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Email does not belong to the webhook's mailbox".to_string(),
+        ));
+    }
+
+    let webhook_trigger = WebhookTrigger::new(storage);
+    match webhook_trigger.replay_arrival(&webhook, &email).await {
+        Ok(success) => Ok(Json(json!({ "success": success }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to replay webhook: {}", e),
+        )),
+    }
+}
+
+/// Send an email via the outbound mailer
+pub async fn send_email(
+    State((storage, mailer, config)): State<(
+        Arc<dyn StorageBackend>,
+        Arc<OutboundMailer>,
+        AppConfig,
+    )>,
+    Json(request): Json<SendEmailRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    // Validate to address
+    if !request.to.contains('@') {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Invalid recipient address".to_string(),
+        ));
+    }
+
+    // Build from address
+    let from_local = request.from_address.as_deref().unwrap_or("noreply");
+    let from_address = format!("{}@{}", from_local, config.domain_name);
+
+    // Send the email
+    let message_id = mailer.send_email(&request).await.map_err(|e| {
+        tracing::error!(error = %e, to = %request.to, "Failed to send email");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to send email: {}", e),
+        )
+    })?;
+
+    // Store sent email in DB
+    let sent = SentEmail::new(
+        from_address.clone(),
+        request.to.clone(),
+        request.subject.clone(),
+        request.body_text.clone(),
+        request.body_html.clone(),
+        message_id.clone(),
+    );
+
+    if let Err(e) = storage.store_sent_email(sent).await {
+        tracing::warn!("Failed to store sent email: {}", e);
+    }
+
+    Ok(Json(json!({
+        "message_id": message_id,
+        "from": from_address,
+        "to": request.to,
+        "subject": request.subject,
+    })))
+}
+
+/// Get sent emails for a given from address
+pub async fn get_sent_emails(
+    Path(address): Path<String>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AppConfig)>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let normalized = config.normalize_address(&address);
+
+    match storage.get_sent_emails(&normalized).await {
+        Ok(emails) => Ok(Json(json!({ "sent_emails": emails }))),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch sent emails: {}", e),
+        )),
+    }
+}
+
+/// Get version and build information for the running server
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    responses(
+        (status = 200, description = "Server version and build details", body = Value)
+    ),
+    security(()),
+    tag = "system"
+)]
+pub async fn get_version() -> Json<Value> {
+    Json(json!(build_info()))
+}
+
+/// Liveness probe: answers as soon as the server is up, without touching the database
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    responses(
+        (status = 200, description = "The server is running", body = Value)
+    ),
+    security(()),
+    tag = "system"
+)]
+pub async fn health() -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+/// Readiness probe: 503 when the database can't be reached
+#[utoipa::path(
+    get,
+    path = "/api/ready",
+    responses(
+        (status = 200, description = "The database is reachable", body = Value),
+        (status = 503, description = "The database is unreachable")
+    ),
+    security(()),
+    tag = "system"
+)]
+pub async fn ready(
+    State(storage): State<Arc<dyn StorageBackend>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    storage.ping().await.map_err(|e| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Database unavailable: {}", e),
+        )
+    })?;
+
+    Ok(Json(json!({ "status": "ready" })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, AuthConfig};
+    use axum::Router;
+    use tokio::sync::broadcast;
+
+    fn disabled_auth_config() -> AuthConfig {
+        AuthConfig {
+            enabled: false,
+            jwt_secret: "test-secret".to_string(),
+            jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
+            auth_domains: None,
+            outbound_enabled: false,
+            password_hasher: crate::auth::PasswordHasher::Bcrypt,
+        }
+    }
+
+    #[test]
+    fn test_app_config_normalize_address() {
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+
+        // Test normalization of address without @
+        assert_eq!(config.normalize_address("user"), "user@example.com");
+
+        // Test address with @ should remain unchanged
+        assert_eq!(config.normalize_address("user@test.com"), "user@test.com");
+
+        // Test address with @ and domain should remain unchanged
+        assert_eq!(
+            config.normalize_address("user@example.com"),
+            "user@example.com"
+        );
+
+        // Test trimming whitespace
+        assert_eq!(config.normalize_address("  user  "), "user@example.com");
+
+        // Test empty string
+        assert_eq!(config.normalize_address(""), "@example.com");
+    }
+
+    #[test]
+    fn test_app_config_with_different_domain() {
+        let config = AppConfig {
+            domain_name: "test.local".to_string(),
+            additional_domains: vec![],
+        };
+
+        // Test normalization with different domain
+        assert_eq!(config.normalize_address("user"), "user@test.local");
+        assert_eq!(
+            config.normalize_address("user@example.com"),
+            "user@example.com"
+        );
+        assert_eq!(
+            config.normalize_address("user@test.local"),
+            "user@test.local"
+        );
+    }
+
+    #[test]
+    fn test_app_config_additional_domains() {
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec!["second.org".to_string()],
+        };
+
+        // Bare local parts still use the primary domain
+        assert_eq!(config.normalize_address("user"), "user@example.com");
+        assert_eq!(
+            config.normalize_address("user@SECOND.org"),
+            "user@second.org"
+        );
+        assert_eq!(
+            config.normalize_address("user@unlisted.net"),
+            "user@unlisted.net"
+        );
+    }
+
+    #[test]
+    fn test_app_config_edge_cases() {
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+
+        // Test with @ in the middle
+        assert_eq!(config.normalize_address("user@domain"), "user@domain");
+
+        // Test with multiple @ symbols
+        assert_eq!(config.normalize_address("user@@domain"), "user@@domain");
+
+        // Test with just @
+        assert_eq!(config.normalize_address("@"), "@");
+
+        // Test with domain only
+        assert_eq!(config.normalize_address("@example.com"), "@example.com");
+    }
+
+    #[test]
+    fn test_extract_local_part() {
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+
+        // Test extracting local part from full address
+        assert_eq!(config.extract_local_part("user@example.com"), "user");
+        assert_eq!(config.extract_local_part("user@other.com"), "user");
+
+        // Test username without domain returns as-is
+        assert_eq!(config.extract_local_part("user"), "user");
+
+        // Test trimming whitespace
+        assert_eq!(config.extract_local_part("  user  "), "user");
+        assert_eq!(config.extract_local_part("  user@domain.com  "), "user");
+
+        // Test empty string
+        assert_eq!(config.extract_local_part(""), "");
+
+        // Test edge cases
+        assert_eq!(config.extract_local_part("@"), "");
+        assert_eq!(config.extract_local_part("@example.com"), "");
+    }
+
+    #[tokio::test]
+    async fn test_get_emails_for_address_paginated() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for i in 0..3 {
+            let email = Email::new(
+                "page@example.com".to_string(),
+                "sender@example.com".to_string(),
+                format!("Email {}", i),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/emails/:address", get(get_emails_for_address))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                crate::auth::auth_config_middleware,
+            ));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/emails/page?limit=2&offset=1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(result["emails"].as_array().unwrap().len(), 2);
+        assert_eq!(result["total"], 3);
+        assert_eq!(result["limit"], 2);
+        assert_eq!(result["offset"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_emails_with_filters() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let now = chrono::Utc::now();
+        for (from, subject, hours_old) in [
+            ("Billing <billing@shop.com>", "Invoice March", 72),
+            ("billing@shop.com", "Invoice April", 24),
+            ("friend@example.com", "Lunch invoice?", 2),
+            ("friend@example.com", "Weekend", 1),
+        ] {
+            let mut email = Email::new(
+                "filter@example.com".to_string(),
+                from.to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = now - chrono::Duration::hours(hours_old);
+            storage.store_email(email).await.unwrap();
+        }
+
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/emails/:address", get(get_emails_for_address))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                crate::auth::auth_config_middleware,
+            ));
+
+        let list = |query: String| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri(format!("/api/emails/filter?{}", query))
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let result: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+                let subjects: Vec<String> = result["emails"]
+                    .as_array()
+                    .map(|emails| {
+                        emails
+                            .iter()
+                            .map(|e| e["subject"].as_str().unwrap().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (status, subjects, result["total"].clone())
+            }
+        };
+        // RFC3339 with an offset, percent-encoded for the query string
+        let time = |hours_ago: i64| {
+            (now - chrono::Duration::hours(hours_ago))
+                .to_rfc3339()
+                .replace('+', "%2B")
+        };
+
+        let (status, subjects, total) = list("from=BILLING".to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(subjects, vec!["Invoice April", "Invoice March"]);
+        assert_eq!(total, 2);
+
+        let (_, subjects, _) = list("subject_contains=invoice".to_string()).await;
+        assert_eq!(
+            subjects,
+            vec!["Lunch invoice?", "Invoice April", "Invoice March"]
+        );
+
+        let (_, subjects, _) = list(format!("since={}", time(3))).await;
+        assert_eq!(subjects, vec!["Weekend", "Lunch invoice?"]);
+
+        let (_, subjects, _) = list(format!("until={}", time(3))).await;
+        assert_eq!(subjects, vec!["Invoice April", "Invoice March"]);
+
+        // Filters combine with each other and with pagination
+        let (_, subjects, total) = list(format!(
+            "subject_contains=invoice&since={}&until={}&limit=1",
+            time(48),
+            time(1)
+        ))
+        .await;
+        assert_eq!(subjects, vec!["Lunch invoice?"]);
+        assert_eq!(total, 2);
+        let (_, subjects, _) = list(format!(
+            "subject_contains=invoice&since={}&until={}&limit=1&offset=1",
+            time(48),
+            time(1)
+        ))
+        .await;
+        assert_eq!(subjects, vec!["Invoice April"]);
+
+        let (status, _, _) = list("from=friend&until=yesterday".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_sender_summary() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for from in [
+            "news@shop.com",
+            "friend@example.com",
+            "news@shop.com",
+            "news@shop.com",
+            "friend@example.com",
+        ] {
+            let email = Email::new(
+                "senders@example.com".to_string(),
+                from.to_string(),
+                "Hello".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/emails/:address/senders", get(get_sender_summary))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                crate::auth::auth_config_middleware,
+            ));
+
+        let summary = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<Vec<Value>>(&body).unwrap()
+            }
+        };
+
+        let senders = summary("/api/emails/senders/senders").await;
+        assert_eq!(senders.len(), 2);
+        assert_eq!(senders[0]["from"], "news@shop.com");
+        assert_eq!(senders[0]["count"], 3);
+        assert_eq!(senders[1]["from"], "friend@example.com");
+        assert_eq!(senders[1]["count"], 2);
+        assert!(senders[0]["last_seen"].is_string());
+
+        let senders = summary("/api/emails/senders/senders?limit=1").await;
+        assert_eq!(senders.len(), 1);
+        assert_eq!(senders[0]["from"], "news@shop.com");
+    }
+
+    #[tokio::test]
+    async fn test_search_mailbox_emails() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for subject in ["Invoice March", "Invoice April", "Lunch"] {
+            let email = Email::new(
+                "find@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/emails/:address/search", get(search_mailbox_emails))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage, disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/emails/find/search?q=invoice")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result["count"], 2);
+        assert_eq!(result["emails"].as_array().unwrap().len(), 2);
+
+        // Missing query is rejected
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/emails/find/search")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_download_attachment() {
+        use crate::storage::{
+            models::{Attachment, Email},
+            sqlite::SqliteBackend,
+        };
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let email = Email::new(
+            "reader@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Subject".to_string(),
+            "Body".to_string(),
+            None,
+            vec![
+                Attachment {
+                    filename: "hello.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: 5,
+                    content: "aGVsbG8=".to_string(),
+                    scan_result: None,
+                    path: None,
+                    dropped: false,
+                    content_hash: Some(Attachment::hash_content(b"hello")),
+                },
+                Attachment {
+                    filename: "broken.bin".to_string(),
+                    content_type: "application/octet-stream".to_string(),
+                    size: 1,
+                    content: "not base64!".to_string(),
+                    scan_result: None,
+                    path: None,
+                    dropped: false,
+                    content_hash: None,
+                },
+                Attachment {
+                    filename: "big.bin".to_string(),
+                    content_type: "application/octet-stream".to_string(),
+                    size: 1024,
+                    content: String::new(),
+                    scan_result: None,
+                    path: None,
+                    dropped: true,
+                    content_hash: None,
+                },
+            ],
+        );
+        storage.store_email(email.clone()).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/email/:id/attachment/:index", get(download_attachment))
+            .with_state(storage.clone())
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+
+        let get_attachment = |uri: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let response = get_attachment(format!("/api/email/{}/attachment/0", email.id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = format!("\"{}\"", Attachment::hash_content(b"hello"));
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"hello.txt\""
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        // A client holding the current copy is told it is unchanged
+        for if_none_match in [etag.clone(), format!("\"other\", W/{}", etag)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/email/{}/attachment/0", email.id))
+                        .header(header::IF_NONE_MATCH, if_none_match)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        }
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/email/{}/attachment/0", email.id))
+                    .header(header::IF_NONE_MATCH, "\"stale\"")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get_attachment(format!("/api/email/{}/attachment/2", email.id)).await;
+        assert_eq!(response.status(), StatusCode::GONE);
+
+        let response = get_attachment(format!("/api/email/{}/attachment/3", email.id)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = get_attachment(format!("/api/email/{}/attachment/1", email.id)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = get_attachment("/api/email/missing/attachment/0".to_string()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // A locked mailbox's attachments need its password
+        storage
+            .set_mailbox_password("reader", bcrypt::hash("secret", 4).unwrap())
+            .await
+            .unwrap();
+        let response = get_attachment(format!("/api/email/{}/attachment/0", email.id)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = get_attachment(format!(
+            "/api/email/{}/attachment/0?password=secret",
+            email.id
+        ))
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_download_attachment_from_each_store() {
+        use crate::storage::{
+            attachments::AttachmentStore,
+            models::{Attachment, Email},
+            sqlite::SqliteBackend,
+        };
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for store in [
+            AttachmentStore::Inline,
+            AttachmentStore::Filesystem(dir.path().to_path_buf()),
+        ] {
+            let storage: Arc<dyn StorageBackend> = Arc::new(
+                SqliteBackend::new("sqlite::memory:")
+                    .await
+                    .unwrap()
+                    .with_attachment_store(store.clone()),
+            );
+            let email = Email::new(
+                "reader@example.com".to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![Attachment {
+                    filename: "hello.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: 5,
+                    content: "aGVsbG8=".to_string(),
+                    scan_result: None,
+                    path: None,
+                    dropped: false,
+                    content_hash: None,
+                }],
+            );
+            storage.store_email(email.clone()).await.unwrap();
+
+            let stored = storage.get_email_by_id(&email.id).await.unwrap().unwrap();
+            let file = dir.path().join(&email.id).join("0");
+            if store == AttachmentStore::Inline {
+                assert_eq!(stored.attachments[0].content, "aGVsbG8=");
+                assert_eq!(stored.attachments[0].path, None);
+                assert!(!file.exists());
+            } else {
+                assert_eq!(stored.attachments[0].content, "");
+                assert_eq!(std::fs::read(&file).unwrap(), b"hello");
+            }
+
+            let app = Router::new()
+                .route("/api/email/:id/attachment/:index", get(download_attachment))
+                .with_state(storage.clone())
+                .layer(axum::middleware::from_fn_with_state(
+                    (storage, disabled_auth_config()),
+                    auth::auth_config_middleware,
+                ));
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/email/{}/attachment/0", email.id))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"hello");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_read() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::put, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let email = Email::new(
+            "reader@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Subject".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        storage.store_email(email.clone()).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/email/:id/read", put(mark_email_read))
+            .with_state(storage.clone())
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/api/email/{}/read", email.id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"read": true}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result["read"], true);
+        assert!(
+            storage
+                .get_email_by_id(&email.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .read
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri("/api/email/missing/read")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"read": true}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let Json(body) = health().await;
+
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        use crate::storage::sqlite::SqliteBackend;
+
+        let backend = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let storage: Arc<dyn StorageBackend> = backend.clone();
+
+        let Json(body) = ready(State(storage.clone())).await.unwrap();
+        assert_eq!(body["status"], "ready");
+
+        backend.close().await;
+        let (status, _) = ready(State(storage)).await.unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_probes_and_version_bypass_auth() {
+        use crate::storage::sqlite::SqliteBackend;
+        use crate::webhooks::WebhookTrigger;
+        use axum::{body::Body, http::Request};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, _) = tokio::sync::broadcast::channel(1);
+        let (deletion_sender, _) = tokio::sync::broadcast::channel(1);
+        let app = crate::api::create_router(
+            storage.clone(),
+            email_sender,
+            deletion_sender,
+            "example.com".to_string(),
+            vec![],
+            WebhookTrigger::new(storage),
+            AuthConfig {
+                enabled: true,
+                ..disabled_auth_config()
+            },
+            None,
+            None,
+            std::time::Duration::from_secs(30),
+            None,
+        );
+
+        for uri in ["/api/health", "/api/ready", "/api/version"] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_version() {
+        let Json(info) = get_version().await;
+
+        assert_eq!(info["name"], "dynip-email");
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(info["git_commit"].is_string());
+        assert!(chrono::DateTime::parse_from_rfc3339(info["built_at"].as_str().unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_success() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        let app = Router::new()
+            .route("/api/webhooks", post(create_webhook))
+            .with_state(storage.clone());
+
+        let request_body = json!({
+            "mailbox_address": "test@example.com",
+            "webhook_url": "http://localhost:3009",
+            "events": ["arrival", "deletion"],
+            "secret": "signing-secret",
+            "max_retries": 0,
+            "max_backoff_secs": 30
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/webhooks")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let webhook: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(webhook["mailbox_address"], "test");
+        assert_eq!(webhook["webhook_url"], "http://localhost:3009");
+        assert!(webhook["events"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("Arrival")));
+        assert!(webhook["events"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("Deletion")));
+        assert_eq!(webhook["max_retries"], 0);
+        assert_eq!(webhook["initial_backoff_secs"], 1);
+        assert_eq!(webhook["max_backoff_secs"], 30);
+        assert_eq!(webhook["include_attachment_content"], false);
+
+        // The secret is stored but never echoed back
+        assert!(webhook.get("secret").is_none());
+        let stored = storage
+            .get_webhook_by_id(webhook["id"].as_str().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.secret, Some("signing-secret".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_invalid_events() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        let app = Router::new()
+            .route("/api/webhooks", post(create_webhook))
+            .with_state(storage);
+
+        let request_body = json!({
+            "mailbox_address": "test@example.com",
+            "webhook_url": "http://localhost:3009",
+            "events": ["invalid_event"]
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/webhooks")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_payload_template() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let app = Router::new()
+            .route("/api/webhooks", post(create_webhook))
+            .with_state(storage.clone());
+        let create = |template: &str| {
+            let request_body = json!({
+                "mailbox_address": "test@example.com",
+                "webhook_url": "http://localhost:3009",
+                "events": ["arrival"],
+                "payload_template": template
+            });
+            Request::builder()
+                .method("POST")
+                .uri("/api/webhooks")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                .unwrap()
+        };
+
+        // Broken template syntax and output that is not JSON are both refused
+        for template in [
+            "{\"text\": \"{{ email.subject\"}",
+            "New mail: {{ email.subject }}",
+        ] {
+            let response = app.clone().oneshot(create(template)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", template);
+        }
+
+        let template = "{\"text\": \"New mail from {{ email.from }}: {{ email.subject }}\"}";
+        let response = app.oneshot(create(template)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let webhook: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(webhook["payload_template"], template);
+        let stored = storage
+            .get_webhook_by_id(webhook["id"].as_str().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.payload_template.as_deref(), Some(template));
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_headers() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let app = Router::new()
+            .route("/api/webhooks", post(create_webhook))
+            .with_state(storage.clone());
+        let create = |headers: Value| {
+            let request_body = json!({
+                "mailbox_address": "test@example.com",
+                "webhook_url": "http://localhost:3009",
+                "events": ["arrival"],
+                "headers": headers
+            });
+            Request::builder()
+                .method("POST")
+                .uri("/api/webhooks")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                .unwrap()
+        };
+
+        // Reserved headers, bad names and bad values are refused
+        for headers in [
+            json!({"Content-Length": "10"}),
+            json!({"x-webhook-signature": "sha256=forged"}),
+            json!({"Bad Header": "x"}),
+            json!({"X-Routing": "line\nbreak"}),
+        ] {
+            let response = app.clone().oneshot(create(headers.clone())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", headers);
+        }
+
+        let response = app
+            .oneshot(create(json!({
+                "Authorization": "Bearer abc",
+                "User-Agent": "receiver-router/1.0"
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let webhook: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(webhook["headers"]["Authorization"], "Bearer abc");
+        let stored = storage
+            .get_webhook_by_id(webhook["id"].as_str().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.headers["User-Agent"], "receiver-router/1.0");
+    }
+
+    #[tokio::test]
+    async fn test_create_webhooks_batch() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let app = Router::new()
+            .route("/api/webhooks/batch", post(create_webhooks_batch))
+            .with_state(storage.clone());
+        let batch = |entries: Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/webhooks/batch")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&entries).unwrap()))
+                .unwrap()
+        };
+        let entry = |mailbox: &str, event: &str| {
+            json!({
+                "mailbox_address": mailbox,
+                "webhook_url": "hooks.example.com/mail",
+                "events": [event]
+            })
+        };
+
+        // One bad entry fails the whole batch, naming the entry
+        let response = app
+            .clone()
+            .oneshot(batch(json!([
+                entry("one@example.com", "arrival"),
+                entry("two@example.com", "bogus"),
+                entry("three@example.com", "arrival")
+            ])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            "Webhook 1: Invalid event: bogus"
+        );
+        assert!(storage
+            .get_webhooks_for_mailbox("one")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let response = app.clone().oneshot(batch(json!([]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .oneshot(batch(json!([
+                entry("one@example.com", "arrival"),
+                entry("two@example.com", "deletion")
+            ])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: Value = serde_json::from_slice(&body).unwrap();
+        let created = created["webhooks"].as_array().unwrap();
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[1]["mailbox_address"], "two");
+        assert_eq!(created[1]["webhook_url"], "http://hooks.example.com/mail");
+        for (mailbox, webhook) in ["one", "two"].iter().zip(created) {
+            let stored = storage.get_webhooks_for_mailbox(mailbox).await.unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].id, webhook["id"].as_str().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preview_webhook_matches_trigger_payload() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let app = Router::new()
+            .route("/api/webhooks/preview", post(preview_webhook))
+            .with_state(storage.clone());
+        let preview = |request_body: Value| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/api/webhooks/preview")
+                            .header("content-type", "application/json")
+                            .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, serde_json::from_slice::<Value>(&body).ok())
+            }
+        };
+        let definition = json!({
+            "mailbox_address": "test@example.com",
+            "webhook_url": "http://localhost:3009",
+            "events": ["arrival", "deletion"],
+            "email": {"from": "alice@example.com", "subject": "Hello", "body": "Hi there"}
+        });
+
+        // The payload a real trigger builds for the same webhook and email
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival, WebhookEvent::Deletion],
+        );
+        let email = Email::new(
+            "test".to_string(),
+            "alice@example.com".to_string(),
+            "Hello".to_string(),
+            "Hi there".to_string(),
+            None,
+            vec![],
+        );
+        let trigger = WebhookTrigger::new(storage.clone());
+        let mut expected =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+
+        let (status, payload) = preview(definition.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut payload = payload.unwrap();
+        // Ids and times differ between the two, everything else must match
+        for payload in [&mut payload, &mut expected] {
+            payload["webhook_id"] = json!("id");
+            payload["timestamp"] = json!("now");
+            payload["email"]["id"] = json!("id");
+            payload["email"]["timestamp"] = json!("now");
+        }
+        assert_eq!(payload, expected);
+
+        // A template is rendered as it would be when sending
+        let mut templated = definition.clone();
+        templated["event"] = json!("deletion");
+        templated["payload_template"] =
+            json!("{\"text\": \"{{ event }} in {{ mailbox }}: {{ email.subject }}\"}");
+        let (status, payload) = preview(templated).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload.unwrap(), json!({"text": "deletion in test: Hello"}));
+
+        // Bad definitions are refused, and nothing is ever saved
+        let mut invalid = definition.clone();
+        invalid["event"] = json!("bogus");
+        assert_eq!(preview(invalid).await.0, StatusCode::BAD_REQUEST);
+        let mut invalid = definition;
+        invalid["payload_template"] = json!("not json {{ mailbox }}");
+        assert_eq!(preview(invalid).await.0, StatusCode::BAD_REQUEST);
+        assert!(storage
+            .get_webhooks_for_mailbox("test")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_webhooks_for_mailbox() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::get,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        // Create a test webhook first
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhooks/:address", get(get_webhooks_for_mailbox))
+            .with_state(storage);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/api/webhooks/test")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(result["webhooks"].as_array().unwrap().len() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_webhook_by_id() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::get,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        // Create a test webhook first
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        let webhook_id = webhook.id.clone();
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhook/:id", get(get_webhook_by_id))
+            .with_state(storage);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri(&format!("/api/webhook/{}", webhook_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(result["id"], webhook_id);
+    }
+
+    #[tokio::test]
+    async fn test_get_webhook_by_id_not_found() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::get,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        let app = Router::new()
+            .route("/api/webhook/:id", get(get_webhook_by_id))
+            .with_state(storage);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/api/webhook/nonexistent-id")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_webhook() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::put,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        // Create a test webhook first
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        let webhook_id = webhook.id.clone();
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhook/:id", put(update_webhook))
+            .with_state(storage);
+
+        let request_body = json!({
+            "webhook_url": "http://localhost:3010",
+            "events": ["deletion"],
+            "format": "slack"
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(&format!("/api/webhook/{}", webhook_id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(result["webhook_url"], "http://localhost:3010");
+        assert!(result["events"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("Deletion")));
+        assert_eq!(result["format"], "Slack");
+    }
+
+    #[tokio::test]
+    async fn test_disable_and_enable_webhook() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        let webhook_id = webhook.id.clone();
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhook/:id/enable", post(enable_webhook))
+            .route("/api/webhook/:id/disable", post(disable_webhook))
+            .with_state(storage.clone());
+        let toggle = |action: &str, id: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/webhook/{}/{}", id, action))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let active_ids = || async {
+            storage
+                .get_active_webhooks_for_event("test", WebhookEvent::Arrival)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|webhook| webhook.id)
+                .collect::<Vec<_>>()
+        };
+
+        let response = toggle("disable", &webhook_id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let updated: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(updated["id"], webhook_id.as_str());
+        assert_eq!(updated["enabled"], false);
+        assert!(active_ids().await.is_empty());
+
+        let response = toggle("enable", &webhook_id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(active_ids().await, vec![webhook_id]);
+
+        let response = toggle("disable", "missing").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_replay_webhook_delivers_arrival() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::post,
+            Router,
+        };
+        use mockito::{Matcher, Server};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Missed".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        let other = Email::new(
+            "other@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Elsewhere".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        storage.store_email(email.clone()).await.unwrap();
+        storage.store_email(other.clone()).await.unwrap();
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/hook")
+            .match_body(Matcher::PartialJson(json!({
+                "event": "arrival",
+                "mailbox": "test",
+                "email": { "id": email.id, "subject": "Missed" },
+            })))
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+        let webhook = Webhook::new(
+            "test".to_string(),
+            format!("{}/hook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        let webhook_id = webhook.id.clone();
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhook/:id/replay", post(replay_webhook))
+            .with_state(storage.clone());
+        let replay = |id: &str, email_id: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/webhook/{}/replay", id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(json!({ "email_id": email_id }).to_string()))
+                    .unwrap(),
+            )
+        };
+
+        let response = replay(&webhook_id, &email.id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result["success"], true);
+        mock.assert_async().await;
+
+        let deliveries = storage
+            .get_webhook_deliveries(&webhook_id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0].success);
+
+        // Another mailbox's email is refused without a delivery
+        let response = replay(&webhook_id, &other.id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = replay(&webhook_id, "missing").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = replay("missing", &email.id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_webhook() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body,
+            http::{Request, StatusCode},
+            routing::delete,
+            Router,
+        };
+        use tempfile::tempdir;
+        use tower::util::ServiceExt;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = Arc::new(
+            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+                .await
+                .unwrap(),
+        );
+
+        // Create a test webhook first
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        let webhook_id = webhook.id.clone();
+        storage.create_webhook(webhook).await.unwrap();
+
+        let app = Router::new()
+            .route("/api/webhook/:id", delete(delete_webhook))
+            .with_state(storage.clone());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(&format!("/api/webhook/{}", webhook_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Verify webhook was deleted
+        let result = storage.get_webhook_by_id(&webhook_id).await.unwrap();
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_webhook_deliveries() {
+        use crate::storage::{models::WebhookDelivery, sqlite::SqliteBackend};
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        storage.create_webhook(webhook.clone()).await.unwrap();
+        for attempt in 1..=3 {
+            storage
+                .record_webhook_delivery(WebhookDelivery::new(
+                    webhook.id.clone(),
+                    attempt,
+                    Some(500),
+                    false,
+                    Some("Internal Server Error".to_string()),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let app = Router::new()
+            .route("/api/webhook/:id/deliveries", get(get_webhook_deliveries))
+            .with_state(storage);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/webhook/{}/deliveries?limit=2", webhook.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let deliveries = result["deliveries"].as_array().unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0]["status_code"], 500);
+        assert_eq!(deliveries[0]["success"], false);
 
-    match storage.get_sent_emails(&normalized).await {
-        Ok(emails) => Ok(Json(json!({ "sent_emails": emails }))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to fetch sent emails: {}", e),
-        )),
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/webhook/missing/deliveries")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn ownership_app(storage: Arc<dyn StorageBackend>, auth_config: AuthConfig) -> Router {
+        use axum::routing::{delete, get, post, put};
 
-    #[test]
-    fn test_app_config_normalize_address() {
         let config = AppConfig {
             domain_name: "example.com".to_string(),
+            additional_domains: vec![],
         };
+        Router::new()
+            .route("/api/mailbox/:address/owner", post(claim_mailbox_owner))
+            .route(
+                "/api/mailbox/:address/password",
+                post(set_mailbox_password).delete(clear_mailbox_password),
+            )
+            .route("/api/emails/:address", get(get_emails_for_address))
+            .route("/api/emails/:address/search", get(search_mailbox_emails))
+            .with_state((storage.clone(), config))
+            .route("/api/email/:id", get(get_email_by_id))
+            .route("/api/email/:id/attachment/:index", get(download_attachment))
+            .route("/api/email/:id/read", put(mark_email_read))
+            .route("/api/email/:id/restore", post(restore_email))
+            .route("/api/thread/:thread_id", get(get_thread))
+            .with_state(storage.clone())
+            .route("/api/email/:id", delete(delete_email))
+            .with_state((
+                storage.clone(),
+                DeletionNotifier::new(
+                    WebhookTrigger::new(storage.clone()),
+                    broadcast::channel(16).0,
+                ),
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), auth_config),
+                auth::auth_config_middleware,
+            ))
+    }
 
-        // Test normalization of address without @
-        assert_eq!(config.normalize_address("user"), "user@example.com");
-
-        // Test address with @ should remain unchanged
-        assert_eq!(config.normalize_address("user@test.com"), "user@test.com");
+    async fn send(app: &Router, method: &str, uri: &str, token: Option<&str>) -> StatusCode {
+        use axum::{body::Body, http::Request};
+        use tower::util::ServiceExt;
 
-        // Test address with @ and domain should remain unchanged
-        assert_eq!(
-            config.normalize_address("user@example.com"),
-            "user@example.com"
-        );
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
 
-        // Test trimming whitespace
-        assert_eq!(config.normalize_address("  user  "), "user@example.com");
+    async fn send_json(
+        app: &Router,
+        uri: &str,
+        token: Option<&str>,
+        body: serde_json::Value,
+    ) -> StatusCode {
+        use axum::{body::Body, http::Request};
+        use tower::util::ServiceExt;
 
-        // Test empty string
-        assert_eq!(config.normalize_address(""), "@example.com");
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        app.clone()
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap()
+            .status()
     }
 
-    #[test]
-    fn test_app_config_with_different_domain() {
-        let config = AppConfig {
-            domain_name: "test.local".to_string(),
-        };
+    async fn store_owned_email(storage: &Arc<dyn StorageBackend>) -> String {
+        use crate::storage::models::Email;
 
-        // Test normalization with different domain
-        assert_eq!(config.normalize_address("user"), "user@test.local");
-        assert_eq!(
-            config.normalize_address("user@example.com"),
-            "user@example.com"
-        );
-        assert_eq!(
-            config.normalize_address("user@test.local"),
-            "user@test.local"
+        let email = Email::new(
+            "owned@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Private".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
         );
+        let id = email.id.clone();
+        storage.store_email(email).await.unwrap();
+        id
     }
 
-    #[test]
-    fn test_app_config_edge_cases() {
-        let config = AppConfig {
-            domain_name: "example.com".to_string(),
+    #[tokio::test]
+    async fn test_mailbox_owner_access() {
+        use crate::storage::{models::User, sqlite::SqliteBackend};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let auth_config = AuthConfig {
+            enabled: true,
+            ..disabled_auth_config()
         };
+        let owner = User::new("owner@example.com".to_string(), "hash".to_string());
+        let token = auth::generate_token(&owner, &auth_config).unwrap();
+        let id = store_owned_email(&storage).await;
+        let app = ownership_app(storage.clone(), auth_config);
 
-        // Test with @ in the middle
-        assert_eq!(config.normalize_address("user@domain"), "user@domain");
-
-        // Test with multiple @ symbols
-        assert_eq!(config.normalize_address("user@@domain"), "user@@domain");
+        let status = send(&app, "POST", "/api/mailbox/owned/owner", Some(&token)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            storage.get_mailbox_owner("owned").await.unwrap(),
+            Some(owner.id.clone())
+        );
 
-        // Test with just @
-        assert_eq!(config.normalize_address("@"), "@");
+        // Claiming again is idempotent for the owner
+        let status = send(&app, "POST", "/api/mailbox/owned/owner", Some(&token)).await;
+        assert_eq!(status, StatusCode::OK);
 
-        // Test with domain only
-        assert_eq!(config.normalize_address("@example.com"), "@example.com");
+        let status = send(&app, "GET", "/api/emails/owned", Some(&token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "GET", &format!("/api/email/{}", id), Some(&token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "DELETE", &format!("/api/email/{}", id), Some(&token)).await;
+        assert_eq!(status, StatusCode::OK);
     }
 
-    #[test]
-    fn test_extract_local_part() {
-        let config = AppConfig {
-            domain_name: "example.com".to_string(),
+    #[tokio::test]
+    async fn test_mailbox_non_owner_denied() {
+        use crate::storage::{models::User, sqlite::SqliteBackend};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let auth_config = AuthConfig {
+            enabled: true,
+            ..disabled_auth_config()
         };
+        let owner = User::new("owner@example.com".to_string(), "hash".to_string());
+        let other = User::new("other@example.com".to_string(), "hash".to_string());
+        let other_token = auth::generate_token(&other, &auth_config).unwrap();
+        let id = store_owned_email(&storage).await;
+        storage
+            .claim_mailbox_owner("owned", &owner.id)
+            .await
+            .unwrap();
+        let app = ownership_app(storage.clone(), auth_config);
+
+        let status = send(&app, "POST", "/api/mailbox/owned/owner", Some(&other_token)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let status = send(&app, "GET", "/api/emails/owned", Some(&other_token)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let status = send(
+            &app,
+            "GET",
+            &format!("/api/email/{}", id),
+            Some(&other_token),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let status = send(
+            &app,
+            "DELETE",
+            &format!("/api/email/{}", id),
+            Some(&other_token),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(storage.get_email_by_id(&id).await.unwrap().is_some());
+    }
 
-        // Test extracting local part from full address
-        assert_eq!(config.extract_local_part("user@example.com"), "user");
-        assert_eq!(config.extract_local_part("user@other.com"), "user");
-
-        // Test username without domain returns as-is
-        assert_eq!(config.extract_local_part("user"), "user");
+    /// An app with an email in `owned@`, which belongs to another user than the returned
+    /// token's
+    async fn non_owner_app() -> (Arc<dyn StorageBackend>, Router, String, String) {
+        use crate::storage::{models::User, sqlite::SqliteBackend};
 
-        // Test trimming whitespace
-        assert_eq!(config.extract_local_part("  user  "), "user");
-        assert_eq!(config.extract_local_part("  user@domain.com  "), "user");
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let auth_config = AuthConfig {
+            enabled: true,
+            ..disabled_auth_config()
+        };
+        let other = User::new("other@example.com".to_string(), "hash".to_string());
+        let other_token = auth::generate_token(&other, &auth_config).unwrap();
+        let id = store_owned_email(&storage).await;
+        storage
+            .claim_mailbox_owner("owned", "owner-id")
+            .await
+            .unwrap();
+        let app = ownership_app(storage.clone(), auth_config);
+        (storage, app, other_token, id)
+    }
 
-        // Test empty string
-        assert_eq!(config.extract_local_part(""), "");
+    #[tokio::test]
+    async fn test_search_mailbox_non_owner_denied() {
+        let (_, app, other_token, _) = non_owner_app().await;
+
+        let status = send(
+            &app,
+            "GET",
+            "/api/emails/owned/search?q=private",
+            Some(&other_token),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
 
-        // Test edge cases
-        assert_eq!(config.extract_local_part("@"), "");
-        assert_eq!(config.extract_local_part("@example.com"), "");
+    #[tokio::test]
+    async fn test_download_attachment_non_owner_denied() {
+        let (_, app, other_token, id) = non_owner_app().await;
+
+        let status = send(
+            &app,
+            "GET",
+            &format!("/api/email/{}/attachment/0", id),
+            Some(&other_token),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
     }
 
     #[tokio::test]
-    async fn test_create_webhook_success() {
-        use crate::storage::sqlite::SqliteBackend;
-        use axum::{
-            body::Body,
-            http::{Request, StatusCode},
-            routing::post,
-            Router,
-        };
-        use tempfile::tempdir;
+    async fn test_mark_email_read_non_owner_denied() {
+        use axum::{body::Body, http::Request};
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
-                .await
-                .unwrap(),
-        );
-
-        let app = Router::new()
-            .route("/api/webhooks", post(create_webhook))
-            .with_state(storage);
-
-        let request_body = json!({
-            "mailbox_address": "test@example.com",
-            "webhook_url": "http://localhost:3009",
-            "events": ["arrival", "deletion"]
-        });
+        let (storage, app, other_token, id) = non_owner_app().await;
 
         let response = app
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/api/webhooks")
-                    .header("content-type", "application/json")
-                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .method("PUT")
+                    .uri(format!("/api/email/{}/read", id))
+                    .header(header::AUTHORIZATION, format!("Bearer {}", other_token))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"read": true}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!storage.get_email_by_id(&id).await.unwrap().unwrap().read);
+    }
 
-        assert_eq!(response.status(), StatusCode::OK);
+    #[tokio::test]
+    async fn test_mailbox_ownership_ignored_when_auth_disabled() {
+        use crate::storage::sqlite::SqliteBackend;
 
-        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let id = store_owned_email(&storage).await;
+        storage
+            .claim_mailbox_owner("owned", "someone-else")
             .await
             .unwrap();
-        let webhook: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let app = ownership_app(storage, disabled_auth_config());
+
+        let status = send(&app, "GET", "/api/emails/owned", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "GET", &format!("/api/email/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "POST", "/api/mailbox/owned/owner", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let status = send(&app, "DELETE", &format!("/api/email/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
 
-        assert_eq!(webhook["mailbox_address"], "test");
-        assert_eq!(webhook["webhook_url"], "http://localhost:3009");
-        assert!(webhook["events"]
-            .as_array()
-            .unwrap()
-            .contains(&json!("Arrival")));
-        assert!(webhook["events"]
-            .as_array()
-            .unwrap()
-            .contains(&json!("Deletion")));
+    #[tokio::test]
+    async fn test_mailbox_password_set_by_owner_or_admin() {
+        use crate::storage::{models::User, sqlite::SqliteBackend};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let auth_config = AuthConfig {
+            enabled: true,
+            ..disabled_auth_config()
+        };
+        let owner = User::new("owner@example.com".to_string(), "hash".to_string());
+        let other = User::new("other@example.com".to_string(), "hash".to_string());
+        let mut admin = User::new("admin@example.com".to_string(), "hash".to_string());
+        admin.is_admin = true;
+        for user in [&owner, &other, &admin] {
+            storage.create_user(user.clone()).await.unwrap();
+        }
+        let token = |user: &User| auth::generate_token(user, &auth_config).unwrap();
+        let (owner_token, other_token, admin_token) = (token(&owner), token(&other), token(&admin));
+        storage
+            .claim_mailbox_owner("owned", &owner.id)
+            .await
+            .unwrap();
+        let app = ownership_app(storage.clone(), auth_config.clone());
+        let uri = "/api/mailbox/owned@example.com/password";
+
+        let body = json!({ "password": "imap-secret" });
+        let status = send_json(&app, uri, Some(&other_token), body.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(!storage.is_mailbox_locked("owned").await.unwrap());
+
+        let status = send_json(&app, uri, Some(&owner_token), body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(storage
+            .verify_mailbox_password("owned", "imap-secret")
+            .await
+            .unwrap());
+        assert!(!storage
+            .verify_mailbox_password("owned", "wrong")
+            .await
+            .unwrap());
+
+        let body = json!({ "password": "admin-reset" });
+        let status = send_json(&app, uri, Some(&admin_token), body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(storage
+            .verify_mailbox_password("owned", "admin-reset")
+            .await
+            .unwrap());
+
+        let status = send(&app, "DELETE", uri, Some(&other_token)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let status = send(&app, "DELETE", uri, Some(&owner_token)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!storage.is_mailbox_locked("owned").await.unwrap());
     }
 
     #[tokio::test]
-    async fn test_create_webhook_invalid_events() {
+    async fn test_mailbox_password_needs_current_password_when_auth_disabled() {
         use crate::storage::sqlite::SqliteBackend;
-        use axum::{
-            body::Body,
-            http::{Request, StatusCode},
-            routing::post,
-            Router,
-        };
-        use tempfile::tempdir;
-        use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .set_mailbox_password("locked", bcrypt::hash("old", 4).unwrap())
+            .await
+            .unwrap();
+        let app = ownership_app(storage.clone(), disabled_auth_config());
+        let uri = "/api/mailbox/locked/password";
+
+        let status = send_json(&app, uri, None, json!({ "password": "new" })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let status = send_json(
+            &app,
+            uri,
+            None,
+            json!({ "password": "new", "current_password": "old" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(storage
+            .verify_mailbox_password("locked", "new")
+            .await
+            .unwrap());
+
+        let status = send(&app, "DELETE", &format!("{}?password=old", uri), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let status = send(&app, "DELETE", &format!("{}?password=new", uri), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!storage.is_mailbox_locked("locked").await.unwrap());
+
+        let status = send_json(&app, uri, None, json!({ "password": "" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_restore_email() {
+        use crate::storage::sqlite::SqliteBackend;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let id = store_owned_email(&storage).await;
+        let app = ownership_app(storage.clone(), disabled_auth_config());
+
+        let status = send(&app, "DELETE", &format!("/api/email/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "GET", &format!("/api/email/{}", id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(storage
+            .get_emails_for_address("owned@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let restore = format!("/api/email/{}/restore", id);
+        let status = send(&app, "POST", &restore, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "GET", &format!("/api/email/{}", id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            storage
+                .get_emails_for_address("owned@example.com")
                 .await
-                .unwrap(),
+                .unwrap()
+                .len(),
+            1
         );
 
+        // Only emails in the trash can be restored
+        let status = send(&app, "POST", &restore, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    /// App serving only the bulk delete route, with a receiver for its deletion broadcasts
+    fn bulk_delete_app(
+        storage: Arc<dyn StorageBackend>,
+    ) -> (Router, broadcast::Receiver<(String, String)>) {
+        use axum::routing::delete;
+
+        let (deletion_tx, deletion_rx) = broadcast::channel(16);
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
         let app = Router::new()
-            .route("/api/webhooks", post(create_webhook))
-            .with_state(storage);
+            .route("/api/emails/:address", delete(delete_emails_for_address))
+            .with_state((
+                storage.clone(),
+                config,
+                DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_tx),
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+        (app, deletion_rx)
+    }
 
-        let request_body = json!({
-            "mailbox_address": "test@example.com",
-            "webhook_url": "http://localhost:3009",
-            "events": ["invalid_event"]
-        });
+    async fn store_aged_email(storage: &Arc<dyn StorageBackend>, to: &str, hours_old: i64) {
+        use crate::storage::models::Email;
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/api/webhooks")
-                    .header("content-type", "application/json")
-                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
-                    .unwrap(),
-            )
+        let mut email = Email::new(
+            to.to_string(),
+            "sender@example.com".to_string(),
+            "Hello".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email.timestamp = chrono::Utc::now() - chrono::Duration::hours(hours_old);
+        storage.store_email(email).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_all_emails_for_address() {
+        use crate::storage::sqlite::SqliteBackend;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for hours_old in [0, 5, 50] {
+            store_aged_email(&storage, "clear@example.com", hours_old).await;
+        }
+        store_aged_email(&storage, "keep@example.com", 0).await;
+        let (app, mut deletion_rx) = bulk_delete_app(storage.clone());
+
+        let status = send(&app, "DELETE", "/api/emails/clear", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(storage
+            .get_emails_for_address("clear@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            storage
+                .get_emails_for_address("keep@example.com")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Every deletion is broadcast
+        for _ in 0..3 {
+            let (_, address) = deletion_rx.try_recv().unwrap();
+            assert_eq!(address, "clear@example.com");
+        }
+        assert!(deletion_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_emails_older_than() {
+        use crate::storage::sqlite::SqliteBackend;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for hours_old in [0, 5, 50] {
+            store_aged_email(&storage, "aged@example.com", hours_old).await;
+        }
+        let (app, mut deletion_rx) = bulk_delete_app(storage.clone());
+
+        let status = send(
+            &app,
+            "DELETE",
+            "/api/emails/aged@example.com?older_than_hours=24",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let remaining = storage
+            .get_emails_for_address("aged@example.com")
             .await
             .unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining
+            .iter()
+            .all(|e| e.timestamp > chrono::Utc::now() - chrono::Duration::hours(24)));
+        assert!(deletion_rx.try_recv().is_ok());
+        assert!(deletion_rx.try_recv().is_err());
+
+        let status = send(
+            &app,
+            "DELETE",
+            "/api/emails/aged@example.com?older_than_hours=-1",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    #[tokio::test]
+    async fn test_get_thread() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let mut first = Email::new(
+            "owned@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Hello".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        first.message_id = Some("first@example.com".to_string());
+        let mut reply = first.for_recipient("owned@example.com".to_string());
+        reply.subject = "Re: Hello".to_string();
+        reply.message_id = Some("reply@example.com".to_string());
+        reply.in_reply_to = Some("first@example.com".to_string());
+        storage.store_email(first).await.unwrap();
+        storage.store_email(reply).await.unwrap();
+        let app = ownership_app(storage.clone(), disabled_auth_config());
+
+        let status = send(&app, "GET", "/api/thread/first@example.com", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = send(&app, "GET", "/api/thread/unknown@example.com", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_get_webhooks_for_mailbox() {
+    async fn test_create_and_get_aliases() {
         use crate::storage::sqlite::SqliteBackend;
         use axum::{
             body::Body,
-            http::{Request, StatusCode},
-            routing::get,
-            Router,
+            http::Request,
+            routing::{get, post},
         };
-        use tempfile::tempdir;
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
-                .await
-                .unwrap(),
-        );
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/aliases", post(create_alias))
+            .route("/api/aliases/:address", get(get_aliases))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+        let post_alias = |body: Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/aliases")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
 
-        // Create a test webhook first
-        let webhook = Webhook::new(
-            "test".to_string(),
-            "http://localhost:3009".to_string(),
-            vec![WebhookEvent::Arrival],
-        );
-        storage.create_webhook(webhook).await.unwrap();
+        let response = app
+            .clone()
+            .oneshot(post_alias(json!({
+                "source": "sales@example.com",
+                "destinations": ["alice", "bob@example.com"]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
 
-        let app = Router::new()
-            .route("/api/webhooks/:address", get(get_webhooks_for_mailbox))
-            .with_state(storage);
+        let response = app
+            .clone()
+            .oneshot(post_alias(json!({
+                "source": "sales",
+                "destinations": ["sales"]
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
         let response = app
             .oneshot(
                 Request::builder()
-                    .method("GET")
-                    .uri("/api/webhooks/test")
+                    .uri("/api/aliases/sales@example.com")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(response.status(), StatusCode::OK);
-
         let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
             .unwrap();
-        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
-
-        assert!(result["webhooks"].as_array().unwrap().len() > 0);
+        let aliases: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(aliases["source"], "sales");
+        let mut destinations: Vec<&str> = aliases["destinations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d.as_str().unwrap())
+            .collect();
+        destinations.sort();
+        assert_eq!(destinations, vec!["alice", "bob"]);
     }
 
     #[tokio::test]
-    async fn test_get_webhook_by_id() {
+    async fn test_random_mailbox() {
         use crate::storage::sqlite::SqliteBackend;
         use axum::{
             body::Body,
@@ -862,53 +4755,60 @@ mod tests {
             routing::get,
             Router,
         };
-        use tempfile::tempdir;
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
-                .await
-                .unwrap(),
-        );
-
-        // Create a test webhook first
-        let webhook = Webhook::new(
-            "test".to_string(),
-            "http://localhost:3009".to_string(),
-            vec![WebhookEvent::Arrival],
-        );
-        let webhook_id = webhook.id.clone();
-        storage.create_webhook(webhook).await.unwrap();
-
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec!["other.com".to_string()],
+        };
         let app = Router::new()
-            .route("/api/webhook/:id", get(get_webhook_by_id))
-            .with_state(storage);
-
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri(&format!("/api/webhook/{}", webhook_id))
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        assert_eq!(response.status(), StatusCode::OK);
+            .route("/api/mailbox/random", get(random_mailbox))
+            .with_state((storage, config));
+        let random = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, serde_json::from_slice::<Value>(&body).ok())
+            }
+        };
 
-        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
-            .await
+        let (status, body) = random("/api/mailbox/random").await;
+        assert_eq!(status, StatusCode::OK);
+        let address = body.unwrap()["address"].as_str().unwrap().to_string();
+        let (local_part, domain) = address.split_once('@').unwrap();
+        assert_eq!(domain, "example.com");
+        assert_eq!(local_part.len(), 10);
+        assert!(local_part[..8].chars().all(|c| c.is_ascii_lowercase()));
+        assert!(local_part[8..].chars().all(|c| c.is_ascii_digit()));
+
+        let (status, body) = random("/api/mailbox/random?prefix=test-&style=uuid").await;
+        assert_eq!(status, StatusCode::OK);
+        let address = body.unwrap()["address"].as_str().unwrap().to_string();
+        let local_part = address
+            .strip_prefix("test-")
+            .and_then(|rest| rest.strip_suffix("@example.com"))
             .unwrap();
-        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(local_part.len(), 12);
+        assert!(local_part.chars().all(|c| c.is_ascii_hexdigit()));
 
-        assert_eq!(result["id"], webhook_id);
+        let (status, _) = random("/api/mailbox/random?prefix=a@b").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = random("/api/mailbox/random?style=emoji").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
-    async fn test_get_webhook_by_id_not_found() {
+    async fn test_set_and_get_mailbox_retention() {
+        use crate::auth;
         use crate::storage::sqlite::SqliteBackend;
         use axum::{
             body::Body,
@@ -916,147 +4816,342 @@ mod tests {
             routing::get,
             Router,
         };
-        use tempfile::tempdir;
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route(
+                "/api/mailbox/:address/retention",
+                get(get_mailbox_retention).put(set_mailbox_retention),
+            )
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+        let put_retention = |body: Value| {
+            Request::builder()
+                .method("PUT")
+                .uri("/api/mailbox/archive/retention")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
+        let get_retention = |app: Router| async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/mailbox/archive@example.com/retention")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
                 .await
-                .unwrap(),
-        );
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        };
 
-        let app = Router::new()
-            .route("/api/webhook/:id", get(get_webhook_by_id))
-            .with_state(storage);
+        let response = app
+            .clone()
+            .oneshot(put_retention(json!({ "retention_hours": 0 })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
         let response = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri("/api/webhook/nonexistent-id")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
+            .clone()
+            .oneshot(put_retention(json!({ "retention_hours": 720 })))
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let retention = get_retention(app.clone()).await;
+        assert_eq!(retention["address"], "archive@example.com");
+        assert_eq!(retention["retention_hours"], 720);
+        assert_eq!(
+            storage
+                .get_mailbox_retention("archive@example.com")
+                .await
+                .unwrap(),
+            Some(720)
+        );
 
-        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = app
+            .clone()
+            .oneshot(put_retention(json!({ "retention_hours": null })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(get_retention(app).await["retention_hours"], Value::Null);
     }
 
     #[tokio::test]
-    async fn test_update_webhook() {
+    async fn test_create_and_get_forwarding_rules() {
         use crate::storage::sqlite::SqliteBackend;
         use axum::{
             body::Body,
-            http::{Request, StatusCode},
-            routing::put,
-            Router,
+            http::Request,
+            routing::{get, post},
         };
-        use tempfile::tempdir;
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
-                .await
-                .unwrap(),
-        );
-
-        // Create a test webhook first
-        let webhook = Webhook::new(
-            "test".to_string(),
-            "http://localhost:3009".to_string(),
-            vec![WebhookEvent::Arrival],
-        );
-        let webhook_id = webhook.id.clone();
-        storage.create_webhook(webhook).await.unwrap();
-
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
         let app = Router::new()
-            .route("/api/webhook/:id", put(update_webhook))
-            .with_state(storage);
+            .route("/api/forwarding", post(create_forwarding_rule))
+            .route("/api/forwarding/:address", get(get_forwarding_rules))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+        let post_rule = |body: Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/forwarding")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
 
-        let request_body = json!({
-            "webhook_url": "http://localhost:3010",
-            "events": ["deletion"]
-        });
+        let response = app
+            .clone()
+            .oneshot(post_rule(json!({
+                "address": "temp@example.com",
+                "destination": "me@real.example",
+                "relay_host": "smtp.real.example",
+                "relay_username": "relay-user",
+                "relay_password": "relay-pass"
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(post_rule(json!({
+                "address": "temp",
+                "destination": "not an address",
+                "relay_host": "smtp.real.example"
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
         let response = app
             .oneshot(
                 Request::builder()
-                    .method("PUT")
-                    .uri(&format!("/api/webhook/{}", webhook_id))
-                    .header("content-type", "application/json")
-                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .uri("/api/forwarding/temp@example.com")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(response.status(), StatusCode::OK);
-
         let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
             .unwrap();
-        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
-
-        assert_eq!(result["webhook_url"], "http://localhost:3010");
-        assert!(result["events"]
-            .as_array()
-            .unwrap()
-            .contains(&json!("Deletion")));
+        let result: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(result["address"], "temp");
+        let rules = result["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0]["destination"], "me@real.example");
+        assert_eq!(rules[0]["relay_port"], 587);
+        assert_eq!(rules[0]["relay_starttls"], true);
+        // The relay password is never returned
+        assert!(rules[0].get("relay_password").is_none());
     }
 
     #[tokio::test]
-    async fn test_delete_webhook() {
+    async fn test_create_routing_rule() {
         use crate::storage::sqlite::SqliteBackend;
+        use axum::{body::Body, http::Request, routing::post};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
+        let app = Router::new()
+            .route("/api/routing-rules", post(create_routing_rule))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
+        let post_rule = |body: Value| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/routing-rules")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(post_rule(json!({
+                "mailbox": "alice@example.com",
+                "subject_pattern": "[alert]*",
+                "target_mailbox": "triage"
+            })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let rules = storage.get_routing_rules("alice").await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].subject_pattern, "[alert]*");
+        assert_eq!(rules[0].target_mailbox, "triage");
+
+        for body in [
+            json!({ "mailbox": "alice", "subject_pattern": " ", "target_mailbox": "triage" }),
+            json!({ "mailbox": "alice", "subject_pattern": "x", "target_mailbox": "alice@example.com" }),
+        ] {
+            let response = app.clone().oneshot(post_rule(body)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mbox_export_import_round_trip() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
         use axum::{
             body::Body,
-            http::{Request, StatusCode},
-            routing::delete,
-            Router,
+            http::Request,
+            routing::{get, post},
         };
-        use tempfile::tempdir;
         use tower::util::ServiceExt;
 
-        let temp_dir = tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let storage = Arc::new(
-            SqliteBackend::new(&format!("sqlite:{}", db_path.display()))
-                .await
-                .unwrap(),
-        );
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
 
-        // Create a test webhook first
-        let webhook = Webhook::new(
-            "test".to_string(),
-            "http://localhost:3009".to_string(),
-            vec![WebhookEvent::Arrival],
+        let mut received = Email::new(
+            "source@example.com".to_string(),
+            "alice@example.com".to_string(),
+            "Received".to_string(),
+            "From the top\nsecond line".to_string(),
+            None,
+            vec![],
         );
-        let webhook_id = webhook.id.clone();
-        storage.create_webhook(webhook).await.unwrap();
+        received.raw = Some(
+            "From: alice@example.com\r\nTo: source@example.com\r\nSubject: Received\r\n\
+             Message-ID: <received@example.com>\r\n\r\nFrom the top\r\nsecond line\r\n"
+                .to_string(),
+        );
+        received.timestamp = chrono::Utc::now() - chrono::Duration::hours(2);
+        let mut rebuilt = Email::new(
+            "source@example.com".to_string(),
+            "bob@example.com".to_string(),
+            "No raw".to_string(),
+            "Stored fields only".to_string(),
+            None,
+            vec![],
+        );
+        rebuilt.timestamp = chrono::Utc::now() - chrono::Duration::hours(1);
+        storage.store_email(received).await.unwrap();
+        storage.store_email(rebuilt).await.unwrap();
 
+        let config = AppConfig {
+            domain_name: "example.com".to_string(),
+            additional_domains: vec![],
+        };
         let app = Router::new()
-            .route("/api/webhook/:id", delete(delete_webhook))
-            .with_state(storage.clone());
+            .route("/api/emails/:address/export.mbox", get(export_mailbox_mbox))
+            .route("/api/emails/:address/import", post(import_mailbox_mbox))
+            .with_state((storage.clone(), config))
+            .layer(axum::middleware::from_fn_with_state(
+                (storage.clone(), disabled_auth_config()),
+                auth::auth_config_middleware,
+            ));
 
         let response = app
+            .clone()
             .oneshot(
-                Request::builder()
-                    .method("DELETE")
-                    .uri(&format!("/api/webhook/{}", webhook_id))
+                Request::get("/api/emails/source/export.mbox")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/mbox");
+        let mbox = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mbox_text = String::from_utf8(mbox.to_vec()).unwrap();
+        // Oldest first, with the body's "From " line escaped
+        assert!(mbox_text.starts_with("From alice@example.com "));
+        assert!(mbox_text.contains("\n>From the top\n"));
+
+        let import = |body: Bytes| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::post("/api/emails/copy/import")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, serde_json::from_slice::<Value>(&body).ok())
+            }
+        };
 
-        // Verify webhook was deleted
-        let result = storage.get_webhook_by_id(&webhook_id).await.unwrap();
-        assert!(result.is_none());
+        let (status, result) = import(mbox.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result.unwrap()["imported"], 2);
+
+        let mut copies = storage
+            .get_emails_for_address("copy@example.com")
+            .await
+            .unwrap();
+        copies.reverse();
+        assert_eq!(copies.len(), 2);
+        assert_eq!(copies[0].subject, "Received");
+        assert_eq!(copies[0].from, "alice@example.com");
+        assert!(copies[0]
+            .body_text
+            .as_deref()
+            .unwrap()
+            .contains("From the top\nsecond line"));
+        assert_eq!(
+            copies[0].message_id.as_deref(),
+            Some("received@example.com")
+        );
+        assert_eq!(copies[1].subject, "No raw");
+        assert_eq!(copies[1].from, "bob@example.com");
+        assert_eq!(
+            copies[1].body_text.as_deref().map(str::trim),
+            Some("Stored fields only")
+        );
+        assert!(copies[0].timestamp < copies[1].timestamp);
+
+        // Importing again skips the message that has a Message-ID
+        let (status, result) = import(mbox).await;
+        assert_eq!(status, StatusCode::OK);
+        let result = result.unwrap();
+        assert_eq!(result["imported"], 1);
+        assert_eq!(result["skipped"], 1);
+
+        let (status, _) = import(Bytes::from_static(b"not an mbox")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 }
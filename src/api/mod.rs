@@ -1,54 +1,193 @@
 pub mod admin;
 pub mod handlers;
+pub mod request_id;
+pub mod sse;
 pub mod websocket;
 
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
     routing::{delete, get, post, put},
     Router,
 };
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast;
 use tower_http::{
+    compression::CompressionLayer,
     cors::{Any, CorsLayer},
     services::ServeDir,
 };
 use tracing::info;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
 use crate::auth::{self, AuthConfig};
 use crate::outbound::OutboundMailer;
-use crate::rate_limit;
+use crate::rate_limit::{self, ip::IpRateLimiter, RateLimitBuckets};
 use crate::storage::{models::Email, StorageBackend};
-use crate::webhooks::WebhookTrigger;
-use admin::{delete_rate_limit, get_rate_limit, get_rate_limit_stats, set_rate_limit};
+use crate::webhooks::{DeletionNotifier, WebhookTrigger};
+use admin::{
+    create_sender_rule, delete_rate_limit, delete_sender_rule, export_emails_jsonl,
+    find_attachments_by_hash, get_audit_log, get_rate_limit, get_rate_limit_stats,
+    get_storage_stats, list_mailboxes, list_sender_rules, list_users, run_cleanup, set_rate_limit,
+    CleanupState,
+};
 use handlers::{
-    check_mailbox_status, claim_mailbox, create_webhook, delete_email, delete_webhook,
-    get_email_by_id, get_emails_for_address, get_sent_emails, get_webhook_by_id,
-    get_webhooks_for_mailbox, release_mailbox, search_emails, send_email, test_webhook,
-    update_webhook, AppConfig,
+    check_mailbox_status, claim_mailbox, claim_mailbox_owner, clear_mailbox_password, create_alias,
+    create_forwarding_rule, create_routing_rule, create_webhook, create_webhooks_batch,
+    delete_email, delete_emails_for_address, delete_webhook, disable_webhook, download_attachment,
+    enable_webhook, export_mailbox_mbox, get_aliases, get_email_by_id, get_emails_for_address,
+    get_forwarding_rules, get_mailbox_retention, get_sender_summary, get_sent_emails, get_thread,
+    get_version, get_webhook_by_id, get_webhook_deliveries, get_webhooks_for_mailbox, health,
+    import_mailbox_mbox, mark_email_read, preview_webhook, random_mailbox, ready, release_mailbox,
+    replay_webhook, restore_email, search_emails, search_mailbox_emails, send_email,
+    set_mailbox_password, set_mailbox_retention, test_webhook, update_webhook, AppConfig,
+    MAX_MBOX_IMPORT_BYTES,
 };
+use sse::sse_handler;
 use websocket::{websocket_handler, WsState};
 
+/// OpenAPI document for the email, webhook, auth and admin routes
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "dynip-email API"),
+    paths(
+        handlers::get_emails_for_address,
+        handlers::search_mailbox_emails,
+        handlers::get_sender_summary,
+        handlers::delete_emails_for_address,
+        handlers::export_mailbox_mbox,
+        handlers::import_mailbox_mbox,
+        handlers::get_email_by_id,
+        handlers::mark_email_read,
+        handlers::delete_email,
+        handlers::restore_email,
+        handlers::download_attachment,
+        handlers::get_thread,
+        handlers::search_emails,
+        handlers::create_webhook,
+        handlers::create_webhooks_batch,
+        handlers::preview_webhook,
+        handlers::get_webhooks_for_mailbox,
+        handlers::get_webhook_by_id,
+        handlers::update_webhook,
+        handlers::enable_webhook,
+        handlers::disable_webhook,
+        handlers::delete_webhook,
+        handlers::test_webhook,
+        handlers::replay_webhook,
+        handlers::get_webhook_deliveries,
+        handlers::get_version,
+        handlers::health,
+        handlers::ready,
+        auth::status,
+        auth::register,
+        auth::login,
+        auth::refresh,
+        auth::logout,
+        auth::me,
+        auth::change_password,
+        auth::mailbox_auth,
+        admin::get_rate_limit,
+        admin::set_rate_limit,
+        admin::delete_rate_limit,
+        admin::get_rate_limit_stats,
+        admin::list_users,
+        admin::get_storage_stats,
+        admin::list_mailboxes,
+        admin::run_cleanup,
+        admin::find_attachments_by_hash,
+        admin::create_sender_rule,
+        admin::list_sender_rules,
+        admin::delete_sender_rule,
+        admin::get_audit_log,
+        admin::export_emails_jsonl,
+    ),
+    modifiers(&BearerAuth),
+    security(("bearer_auth" = [])),
+    tags(
+        (name = "emails", description = "Reading, searching and deleting received email"),
+        (name = "webhooks", description = "Per-mailbox webhook configuration"),
+        (name = "auth", description = "User registration and JWT tokens"),
+        (name = "admin", description = "Per-mailbox rate limits and users, for admins only"),
+        (name = "system", description = "Version and health probes"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the JWT bearer scheme used when `AUTH_ENABLED` is set
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Serve the OpenAPI document
+pub async fn openapi_json() -> axum::Json<utoipa::openapi::OpenApi> {
+    axum::Json(ApiDoc::openapi())
+}
+
 /// Build the API router
+#[allow(clippy::too_many_arguments)]
 pub fn create_router(
     storage: Arc<dyn StorageBackend>,
     email_sender: broadcast::Sender<Email>,
     deletion_sender: broadcast::Sender<(String, String)>,
     domain_name: String,
+    additional_domains: Vec<String>,
     webhook_trigger: WebhookTrigger,
     auth_config: AuthConfig,
     outbound_mailer: Option<Arc<OutboundMailer>>,
+    max_requests_per_minute_per_ip: Option<u32>,
+    ws_ping_interval: Duration,
+    email_retention_hours: Option<i64>,
 ) -> Router {
     let ws_state = WsState {
+        storage: storage.clone(),
         email_receiver: email_sender.clone(),
-        deletion_sender,
+        deletion_sender: deletion_sender.clone(),
         domain_name: domain_name.clone(),
+        additional_domains: additional_domains.clone(),
+        ping_interval: ws_ping_interval,
+    };
+
+    let app_config = AppConfig {
+        domain_name,
+        additional_domains,
     };
 
-    let app_config = AppConfig { domain_name };
+    // Deleting emails notifies WebSocket/SSE clients and deletion webhooks
+    let deletion_notifier = DeletionNotifier::new(webhook_trigger, deletion_sender);
+    let delete_email_state = (storage.clone(), deletion_notifier.clone());
+    let bulk_delete_state = (
+        storage.clone(),
+        app_config.clone(),
+        deletion_notifier.clone(),
+    );
+    let cleanup_state = CleanupState {
+        storage: storage.clone(),
+        deletion_notifier,
+        retention_hours: email_retention_hours,
+    };
 
-    // Create state for delete email route (storage + webhook_trigger)
-    let delete_email_state = (storage.clone(), webhook_trigger);
+    // Token buckets shared by every mailbox rate-limited route. Client IPs are limited
+    // once for the whole router below, so the mailbox middleware doesn't count them again.
+    let rate_limit_state = (storage.clone(), Arc::new(RateLimitBuckets::new()), None);
+    let ip_limiter =
+        max_requests_per_minute_per_ip.map(|max| Arc::new(IpRateLimiter::per_minute(max)));
 
     // Create auth state
     let auth_state = (storage.clone(), auth_config.clone());
@@ -56,27 +195,74 @@ pub fn create_router(
     // Build protected routes (require auth when enabled)
     let protected_routes = Router::new()
         // Mailbox routes
+        .route("/api/mailbox/random", get(random_mailbox))
+        .with_state((storage.clone(), app_config.clone()))
         .route("/api/mailbox/:address/status", get(check_mailbox_status))
         .with_state((storage.clone(), app_config.clone()))
         .route("/api/mailbox/:address/claim", post(claim_mailbox))
         .with_state((storage.clone(), app_config.clone()))
         .route("/api/mailbox/:address/release", post(release_mailbox))
         .with_state((storage.clone(), app_config.clone()))
+        .route("/api/mailbox/:address/owner", post(claim_mailbox_owner))
+        .with_state((storage.clone(), app_config.clone()))
+        .route(
+            "/api/mailbox/:address/password",
+            post(set_mailbox_password).delete(clear_mailbox_password),
+        )
+        .with_state((storage.clone(), app_config.clone()))
+        .route(
+            "/api/mailbox/:address/retention",
+            get(get_mailbox_retention).put(set_mailbox_retention),
+        )
+        .with_state((storage.clone(), app_config.clone()))
+        // Alias routes
+        .route("/api/aliases", post(create_alias))
+        .with_state((storage.clone(), app_config.clone()))
+        .route("/api/aliases/:address", get(get_aliases))
+        .with_state((storage.clone(), app_config.clone()))
+        .route("/api/routing-rules", post(create_routing_rule))
+        .with_state((storage.clone(), app_config.clone()))
         // API routes with combined state (storage + config)
         .route("/api/emails/:address", get(get_emails_for_address))
         .with_state((storage.clone(), app_config.clone()))
+        .route("/api/emails/:address", delete(delete_emails_for_address))
+        .with_state(bulk_delete_state)
+        .route("/api/emails/:address/search", get(search_mailbox_emails))
+        .with_state((storage.clone(), app_config.clone()))
+        .route("/api/emails/:address/senders", get(get_sender_summary))
+        .with_state((storage.clone(), app_config.clone()))
+        .route("/api/emails/:address/export.mbox", get(export_mailbox_mbox))
+        .with_state((storage.clone(), app_config.clone()))
+        .route(
+            "/api/emails/:address/import",
+            post(import_mailbox_mbox).layer(DefaultBodyLimit::max(MAX_MBOX_IMPORT_BYTES)),
+        )
+        .with_state((storage.clone(), app_config.clone()))
         // Search emails (needs storage + config for mailbox normalization)
         .route("/api/search", get(search_emails))
         .with_state((storage.clone(), app_config.clone()))
         // Email by ID doesn't need domain normalization
         .route("/api/email/:id", get(get_email_by_id))
         .with_state(storage.clone())
+        .route("/api/email/:id/read", put(mark_email_read))
+        .with_state(storage.clone())
+        .route("/api/email/:id/attachment/:index", get(download_attachment))
+        .with_state(storage.clone())
         // Delete email route needs storage + webhook_trigger
         .route("/api/email/:id", delete(delete_email))
         .with_state(delete_email_state)
+        .route("/api/email/:id/restore", post(restore_email))
+        .with_state(storage.clone())
+        // Conversation by root Message-ID
+        .route("/api/thread/:thread_id", get(get_thread))
+        .with_state(storage.clone())
         // Webhook routes
         .route("/api/webhooks", post(create_webhook))
         .with_state(storage.clone())
+        .route("/api/webhooks/preview", post(preview_webhook))
+        .with_state(storage.clone())
+        .route("/api/webhooks/batch", post(create_webhooks_batch))
+        .with_state(storage.clone())
         .route("/api/webhooks/:address", get(get_webhooks_for_mailbox))
         .with_state(storage.clone())
         .route("/api/webhook/:id", get(get_webhook_by_id))
@@ -85,9 +271,17 @@ pub fn create_router(
         .with_state(storage.clone())
         .route("/api/webhook/:id", delete(delete_webhook))
         .with_state(storage.clone())
+        .route("/api/webhook/:id/enable", post(enable_webhook))
+        .with_state(storage.clone())
+        .route("/api/webhook/:id/disable", post(disable_webhook))
+        .with_state(storage.clone())
         .route("/api/webhook/:id/test", post(test_webhook))
         .with_state(storage.clone())
-        // Admin routes for rate limiting
+        .route("/api/webhook/:id/replay", post(replay_webhook))
+        .with_state(storage.clone())
+        .route("/api/webhook/:id/deliveries", get(get_webhook_deliveries))
+        .with_state(storage.clone())
+        // Admin routes, only for users with the admin role
         .route("/api/admin/rate-limit/:address", get(get_rate_limit))
         .with_state(storage.clone())
         .route("/api/admin/rate-limit/:address", post(set_rate_limit))
@@ -99,15 +293,43 @@ pub fn create_router(
             get(get_rate_limit_stats),
         )
         .with_state(storage.clone())
+        .route("/api/admin/users", get(list_users))
+        .with_state(storage.clone())
+        .route("/api/admin/cleanup", post(run_cleanup))
+        .with_state(cleanup_state)
+        .route(
+            "/api/admin/attachments/:hash",
+            get(find_attachments_by_hash),
+        )
+        .with_state(storage.clone())
+        .route("/api/admin/sender-rules", post(create_sender_rule))
+        .with_state(storage.clone())
+        .route("/api/admin/sender-rules", get(list_sender_rules))
+        .with_state(storage.clone())
+        .route("/api/admin/sender-rules/:id", delete(delete_sender_rule))
+        .with_state(storage.clone())
+        .route("/api/admin/audit", get(get_audit_log))
+        .with_state(storage.clone())
+        .route("/api/admin/export.jsonl", get(export_emails_jsonl))
+        .with_state(storage.clone())
+        .route("/api/stats", get(get_storage_stats))
+        .with_state(storage.clone())
+        .route("/api/mailboxes", get(list_mailboxes))
+        .with_state(storage.clone())
         // Apply rate limiting middleware first
         .layer(middleware::from_fn_with_state(
-            storage.clone(),
+            rate_limit_state.clone(),
             rate_limit::rate_limit_middleware,
         ))
         // Apply auth middleware to protected routes
         .layer(middleware::from_fn_with_state(
-            auth_config.clone(),
+            auth_state.clone(),
             auth::require_auth,
+        ))
+        // Expose auth config to the AuthenticatedUser extractor for ownership checks
+        .layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            auth::auth_config_middleware,
         ));
 
     // Add outbound email routes if mailer is configured
@@ -127,11 +349,11 @@ pub fn create_router(
             send_route
                 .merge(sent_route)
                 .layer(middleware::from_fn_with_state(
-                    storage.clone(),
+                    rate_limit_state.clone(),
                     rate_limit::rate_limit_middleware,
                 ))
                 .layer(middleware::from_fn_with_state(
-                    auth_config.clone(),
+                    auth_state.clone(),
                     auth::require_auth_always,
                 )),
         )
@@ -139,53 +361,109 @@ pub fn create_router(
         None
     };
 
+    // SECURITY: Forwarding rules relay every incoming email through any SMTP server the
+    // caller names, so like outbound routes they ALWAYS require authentication
+    let forwarding_routes = Router::new()
+        .route("/api/forwarding", post(create_forwarding_rule))
+        .route("/api/forwarding/:address", get(get_forwarding_rules))
+        .with_state((storage.clone(), app_config.clone()))
+        .layer(middleware::from_fn_with_state(
+            rate_limit_state.clone(),
+            rate_limit::rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            auth::require_auth_always,
+        ))
+        .layer(middleware::from_fn_with_state(
+            auth_state.clone(),
+            auth::auth_config_middleware,
+        ));
+
     // Build auth routes (public, no auth required)
     let auth_routes = Router::new()
         .route("/api/auth/status", get(auth::status))
         .route("/api/auth/register", post(auth::register))
         .route("/api/auth/login", post(auth::login))
+        .route("/api/auth/refresh", post(auth::refresh))
+        .route("/api/auth/logout", post(auth::logout))
         .route("/api/auth/me", get(auth::me))
-        .with_state(auth_state)
+        .route("/api/auth/change-password", post(auth::change_password))
+        // Mailbox password check for external IMAP auth proxies
+        .route("/api/mailbox/auth", post(auth::mailbox_auth))
+        .with_state(auth_state.clone())
         // Apply auth config middleware so AuthenticatedUser extractor can access config
         .layer(middleware::from_fn_with_state(
-            auth_config.clone(),
+            auth_state,
             auth::auth_config_middleware,
         ));
 
+    // Liveness and readiness probes (public, never rate limited)
+    let probe_routes = Router::new()
+        .route("/api/health", get(health))
+        .route("/api/ready", get(ready))
+        .with_state(storage.clone());
+
     let mut router = Router::new()
-        // WebSocket route (needs domain for normalization)
-        .route("/api/ws/:address", get(websocket_handler))
-        .with_state(ws_state)
+        // Build info (public, useful for support)
+        .route("/api/version", get(get_version))
+        // Machine-readable API description (public)
+        .route("/api/openapi.json", get(openapi_json))
         // Merge auth routes (public)
         .merge(auth_routes)
         // Merge protected routes
-        .merge(protected_routes);
+        .merge(protected_routes)
+        .merge(forwarding_routes);
 
     // Merge outbound routes if available
     if let Some(outbound) = outbound_routes {
         router = router.merge(outbound);
     }
 
+    // WebSocket and SSE routes (need domain for normalization), kept out of the
+    // compression layer so upgrades and event streams pass through untouched
+    let realtime_routes = Router::new()
+        .route("/api/ws/:address", get(websocket_handler))
+        // Server-Sent Events alternative to the WebSocket
+        .route("/api/sse/:address", get(sse_handler))
+        .with_state(ws_state);
+
     router
         // Serve static files
         .nest_service("/", ServeDir::new("static"))
+        // Compress responses for clients that send Accept-Encoding
+        .layer(CompressionLayer::new())
+        .merge(realtime_routes)
+        // Limit each client IP across every route, including login and static files
+        .layer(middleware::from_fn_with_state(
+            ip_limiter,
+            rate_limit::ip_rate_limit_middleware,
+        ))
+        .merge(probe_routes)
         // CORS for development
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
+                .allow_headers(Any)
+                .expose_headers([request_id::REQUEST_ID_HEADER.clone()]),
         )
+        // Outermost, so every log line of a request carries its ID
+        .layer(middleware::from_fn(request_id::request_id_middleware))
 }
 
 /// Start the API server
 #[allow(dead_code)]
-pub async fn start_server(router: Router, port: u16) -> anyhow::Result<()> {
-    let addr = format!("0.0.0.0:{}", port);
+pub async fn start_server(router: Router, bind_address: IpAddr, port: u16) -> anyhow::Result<()> {
+    let addr = SocketAddr::new(bind_address, port);
     info!("Starting API server on {}", addr);
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, router).await?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -193,13 +471,14 @@ pub async fn start_server(router: Router, port: u16) -> anyhow::Result<()> {
 /// Start the API server with graceful shutdown support
 pub async fn start_server_with_shutdown(
     router: Router,
+    bind_address: IpAddr,
     port: u16,
     shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
 ) -> anyhow::Result<()> {
-    let addr = format!("0.0.0.0:{}", port);
+    let addr = SocketAddr::new(bind_address, port);
     info!("Starting API server on {}", addr);
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
 
     // Create a shutdown signal that can be used to gracefully stop the server
     let shutdown_signal = async {
@@ -208,10 +487,216 @@ pub async fn start_server_with_shutdown(
     };
 
     // Start the server with graceful shutdown
-    axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown_signal)
-        .await?;
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal)
+    .await?;
 
     info!("✅ API server stopped gracefully");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::util::ServiceExt;
+
+    fn test_router(
+        storage: Arc<dyn StorageBackend>,
+        max_requests_per_minute_per_ip: Option<u32>,
+    ) -> Router {
+        let (email_sender, _) = broadcast::channel(1);
+        let (deletion_sender, _) = broadcast::channel(1);
+        create_router(
+            storage.clone(),
+            email_sender,
+            deletion_sender,
+            "example.com".to_string(),
+            vec![],
+            WebhookTrigger::new(storage),
+            AuthConfig {
+                enabled: false,
+                jwt_secret: "test-secret".to_string(),
+                jwt_expiry_hours: 24,
+                refresh_token_expiry_days: 30,
+                login_max_attempts: 5,
+                login_window_minutes: 15,
+                auth_domains: None,
+                outbound_enabled: false,
+                password_hasher: crate::auth::PasswordHasher::Bcrypt,
+            },
+            None,
+            max_requests_per_minute_per_ip,
+            Duration::from_secs(30),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_large_responses_are_compressed() {
+        use crate::storage::{models::Email, sqlite::SqliteBackend};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for i in 0..20 {
+            let email = Email::new(
+                "big@example.com".to_string(),
+                "sender@example.com".to_string(),
+                format!("Report {}", i),
+                "A fairly repetitive body line. ".repeat(50),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+        let app = test_router(storage, None);
+
+        let request = |encoding: Option<&str>| {
+            let mut request = Request::builder().uri("/api/emails/big");
+            if let Some(encoding) = encoding {
+                request = request.header(axum::http::header::ACCEPT_ENCODING, encoding);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request(Some("gzip"))).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        let compressed = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let response = app.oneshot(request(None)).await.unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+        let plain = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(compressed.len() < plain.len());
+    }
+
+    #[tokio::test]
+    async fn test_ip_limit_covers_public_routes() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::extract::connect_info::MockConnectInfo;
+        use std::net::SocketAddr;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let app = test_router(storage, Some(3))
+            .layer(MockConnectInfo(SocketAddr::from(([192, 0, 2, 1], 4000))));
+
+        let login = || {
+            Request::builder()
+                .method("POST")
+                .uri("/api/auth/login")
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    r#"{"email":"a@example.com","password":"guess"}"#,
+                ))
+                .unwrap()
+        };
+        for _ in 0..3 {
+            let response = app.clone().oneshot(login()).await.unwrap();
+            assert_ne!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+        }
+        let response = app.clone().oneshot(login()).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+
+        // Probes stay reachable for the same client
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/health")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_routes_always_require_auth() {
+        use crate::storage::sqlite::SqliteBackend;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        // Auth is disabled, yet anonymous callers can't set up relaying
+        let app = test_router(storage, None);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/forwarding")
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"address":"temp","destination":"me@real.example","relay_host":"relay.example"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/forwarding/temp")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_lists_routes() {
+        let app = Router::new().route("/api/openapi.json", get(openapi_json));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/api/emails/{address}",
+            "/api/email/{id}",
+            "/api/webhooks",
+            "/api/webhook/{id}",
+            "/api/auth/login",
+            "/api/admin/rate-limit/{address}",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(paths["/api/emails/{address}"]["get"].is_object());
+        assert!(paths["/api/emails/{address}"]["delete"].is_object());
+
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        for schema in [
+            "Email",
+            "Webhook",
+            "CreateWebhookRequest",
+            "RegisterRequest",
+        ] {
+            assert!(schemas.contains_key(schema), "missing schema {}", schema);
+        }
+        assert!(doc["components"]["securitySchemes"]["bearer_auth"].is_object());
+    }
+}
@@ -0,0 +1,117 @@
+//! `X-Request-Id` handling, so log lines for one API request can be correlated
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// Header carrying the request ID, both ways
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request ID that is echoed back
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Run the request inside a span tagged with its request ID and return the ID in the
+/// response
+///
+/// A client-supplied `X-Request-Id` is kept when it is at most [`MAX_REQUEST_ID_LEN`]
+/// printable ASCII characters without spaces; otherwise a new UUID is generated.
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .filter(|value| is_valid_request_id(value.as_bytes()))
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&Uuid::new_v4().to_string())
+                .expect("a UUID is a valid header value")
+        });
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER.clone(), request_id.clone());
+
+    let span = info_span!(
+        "http_request",
+        request_id = request_id.to_str().unwrap_or_default(),
+        method = %request.method(),
+        path = request.uri().path(),
+    );
+    let mut response = next.run(request).instrument(span).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER.clone(), request_id);
+    response
+}
+
+fn is_valid_request_id(value: &[u8]) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value.iter().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|request: Request| async move {
+                    // Handlers see the same ID the client gets back
+                    request.headers()[&REQUEST_ID_HEADER]
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                }),
+            )
+            .layer(middleware::from_fn(request_id_middleware))
+    }
+
+    async fn request_id(header: Option<&str>) -> (String, String) {
+        let mut request = axum::http::Request::builder().uri("/");
+        if let Some(header) = header {
+            request = request.header("X-Request-Id", header);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let echoed = response.headers()[&REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (echoed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_echoed_or_generated() {
+        let (echoed, seen) = request_id(Some("client-trace-42")).await;
+        assert_eq!(echoed, "client-trace-42");
+        assert_eq!(seen, "client-trace-42");
+
+        for header in [
+            None,
+            Some(""),
+            Some("has spaces"),
+            Some(&"x".repeat(129)[..]),
+        ] {
+            let (generated, seen) = request_id(header).await;
+            assert!(Uuid::parse_str(&generated).is_ok(), "{:?}", header);
+            assert_eq!(seen, generated);
+        }
+
+        let (first, _) = request_id(None).await;
+        let (second, _) = request_id(None).await;
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,295 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info};
+
+use super::handlers::{verify_mailbox_password, PasswordQuery};
+use super::websocket::{resync_message, WsMessage, WsState};
+
+/// Stream real-time email updates for an address as Server-Sent Events
+///
+/// Each event's data is a JSON-encoded [`WsMessage`], the same messages the WebSocket sends.
+/// Locked mailboxes need their password in the `password` query parameter.
+pub async fn sse_handler(
+    Path(address): Path<String>,
+    Query(params): Query<PasswordQuery>,
+    State(state): State<WsState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let normalized_address = state.normalize_address(&address);
+    info!(
+        "SSE connection requested for address: {} (normalized: {})",
+        address, normalized_address
+    );
+    let local_part = normalized_address
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    verify_mailbox_password(&state.storage, &local_part, params.password.as_deref()).await?;
+
+    let events = message_stream(normalized_address, &state).filter_map(|msg| async move {
+        match serde_json::to_string(&msg) {
+            Ok(json) => Some(Ok(Event::default().data(json))),
+            Err(e) => {
+                error!("Failed to serialize SSE message: {}", e);
+                None
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Messages for one address: `Connected` first, then its new and deleted emails
+///
+/// Updates dropped because the client fell behind are replaced by a `Resync` message with
+/// the mailbox's current emails.
+fn message_stream(address: String, state: &WsState) -> impl Stream<Item = WsMessage> {
+    // Subscribe now so nothing sent after the request arrives is missed
+    let email_rx = state.email_receiver.subscribe();
+    let deletion_rx = state.deletion_sender.subscribe();
+    let storage = state.storage.clone();
+    let connected = WsMessage::Connected {
+        address: address.clone(),
+    };
+
+    let updates = stream::unfold(
+        (email_rx, deletion_rx, storage, address),
+        |(mut email_rx, mut deletion_rx, storage, address)| async move {
+            loop {
+                let missed = tokio::select! {
+                    result = email_rx.recv() => match result {
+                        Ok(email) if email.to == address => {
+                            let msg = WsMessage::from(email);
+                            return Some((msg, (email_rx, deletion_rx, storage, address)));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(missed)) => missed,
+                        Err(RecvError::Closed) => return None,
+                    },
+                    result = deletion_rx.recv() => match result {
+                        Ok((id, deleted_address)) if deleted_address == address => {
+                            let msg = WsMessage::EmailDeleted {
+                                id,
+                                address: deleted_address,
+                            };
+                            return Some((msg, (email_rx, deletion_rx, storage, address)));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(missed)) => missed,
+                        Err(RecvError::Closed) => return None,
+                    },
+                };
+                if let Some(msg) = resync_message(&storage, &address, missed).await {
+                    return Some((msg, (email_rx, deletion_rx, storage, address)));
+                }
+            }
+        },
+    );
+
+    stream::once(async move { connected }).chain(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{models::Email, StorageBackend};
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+    use tower::util::ServiceExt;
+
+    /// Read the next event from an SSE response body
+    async fn next_event<S>(body: &mut S) -> String
+    where
+        S: Stream<Item = Result<axum::body::Bytes, axum::Error>> + Unpin,
+    {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("timed out waiting for an SSE event")
+            .unwrap()
+            .unwrap();
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_streams_emails_for_address() {
+        let (email_tx, _) = broadcast::channel::<Email>(16);
+        let (deletion_tx, _) = broadcast::channel::<(String, String)>(16);
+        let storage = crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let state = WsState {
+            storage: std::sync::Arc::new(storage),
+            email_receiver: email_tx.clone(),
+            deletion_sender: deletion_tx.clone(),
+            domain_name: "test.local".to_string(),
+            additional_domains: vec![],
+            ping_interval: Duration::from_secs(30),
+        };
+        let app = Router::new()
+            .route("/api/sse/:address", get(sse_handler))
+            .with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/sse/user")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+        let connected = next_event(&mut body).await;
+        assert!(connected.starts_with("data: "));
+        assert!(connected.contains(r#""type":"Connected""#));
+        assert!(connected.contains("user@test.local"));
+
+        // Emails for other addresses are skipped
+        let other = Email::new(
+            "other@test.local".to_string(),
+            "sender@example.com".to_string(),
+            "Not for you".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email_tx.send(other).unwrap();
+        let email = Email::new(
+            "user@test.local".to_string(),
+            "sender@example.com".to_string(),
+            "Hello over SSE".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email_tx.send(email.clone()).unwrap();
+
+        let event = next_event(&mut body).await;
+        let data: serde_json::Value =
+            serde_json::from_str(event.trim().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(data["type"], "Email");
+        assert_eq!(data["id"], email.id);
+        assert_eq!(data["subject"], "Hello over SSE");
+
+        deletion_tx
+            .send((email.id.clone(), "user@test.local".to_string()))
+            .unwrap();
+        let event = next_event(&mut body).await;
+        assert!(event.contains(r#""type":"EmailDeleted""#));
+        assert!(event.contains(&email.id));
+    }
+
+    fn sse_app(
+        storage: crate::storage::sqlite::SqliteBackend,
+        email_tx: broadcast::Sender<Email>,
+        deletion_tx: broadcast::Sender<(String, String)>,
+    ) -> Router {
+        let state = WsState {
+            storage: std::sync::Arc::new(storage),
+            email_receiver: email_tx,
+            deletion_sender: deletion_tx,
+            domain_name: "test.local".to_string(),
+            additional_domains: vec![],
+            ping_interval: Duration::from_secs(30),
+        };
+        Router::new()
+            .route("/api/sse/:address", get(sse_handler))
+            .with_state(state)
+    }
+
+    fn sse_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_resyncs_lagging_client() {
+        let storage = crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let (email_tx, _) = broadcast::channel::<Email>(2);
+        let mut emails = Vec::new();
+        for i in 0..5 {
+            let email = Email::new(
+                "user@test.local".to_string(),
+                "sender@example.com".to_string(),
+                format!("Burst {}", i),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email.clone()).await.unwrap();
+            emails.push(email);
+        }
+        let (deletion_tx, _) = broadcast::channel::<(String, String)>(16);
+        let app = sse_app(storage, email_tx.clone(), deletion_tx.clone());
+
+        let response = app.oneshot(sse_request("/api/sse/user")).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+        assert!(next_event(&mut body)
+            .await
+            .contains(r#""type":"Connected""#));
+
+        // Outrun the two-slot channel before the client reads anything
+        for email in emails {
+            email_tx.send(email).unwrap();
+        }
+
+        let event = next_event(&mut body).await;
+        let data: serde_json::Value =
+            serde_json::from_str(event.trim().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(data["type"], "Resync");
+        assert_eq!(data["address"], "user@test.local");
+        assert_eq!(data["missed"], 3);
+        assert_eq!(data["emails"].as_array().unwrap().len(), 5);
+
+        // The updates still buffered follow the resync
+        assert!(next_event(&mut body).await.contains("Burst 3"));
+        assert!(next_event(&mut body).await.contains("Burst 4"));
+    }
+
+    #[tokio::test]
+    async fn test_sse_locked_mailbox_requires_password() {
+        let storage = crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+            .await
+            .unwrap();
+        storage
+            .set_mailbox_password("locked", bcrypt::hash("secret", 4).unwrap())
+            .await
+            .unwrap();
+        let (email_tx, _) = broadcast::channel::<Email>(16);
+        let (deletion_tx, _) = broadcast::channel::<(String, String)>(16);
+        let app = sse_app(storage, email_tx, deletion_tx.clone());
+
+        let response = app
+            .clone()
+            .oneshot(sse_request("/api/sse/locked"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(sse_request("/api/sse/locked?password=wrong"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(sse_request("/api/sse/locked?password=secret"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        assert!(next_event(&mut body)
+            .await
+            .contains(r#""type":"Connected""#));
+    }
+}
@@ -5,11 +5,13 @@ use axum::{
     },
     response::Response,
 };
-use futures::{SinkExt, StreamExt};
-use tokio::sync::broadcast;
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tracing::{error, info, warn};
 
-use crate::storage::models::Email;
+use crate::storage::{models::Email, StorageBackend};
 use serde::{Deserialize, Serialize};
 
 /// WebSocket message types
@@ -26,11 +28,22 @@ pub enum WsMessage {
         timestamp: String,
         raw: Option<String>,
         attachments: Vec<crate::storage::models::Attachment>,
+        #[serde(default)]
+        cc: Vec<String>,
+        #[serde(default)]
+        reply_to: Option<String>,
     },
     /// Email deleted
     EmailDeleted { id: String, address: String },
     /// Connection established
     Connected { address: String },
+    /// The client fell behind and `missed` updates were dropped; `emails` is everything
+    /// now in the mailbox, to replace the client's list with
+    Resync {
+        address: String,
+        missed: u64,
+        emails: Vec<Email>,
+    },
 }
 
 impl From<Email> for WsMessage {
@@ -44,6 +57,8 @@ impl From<Email> for WsMessage {
             timestamp: email.timestamp.to_rfc3339(),
             raw: email.raw,
             attachments: email.attachments,
+            cc: email.cc,
+            reply_to: email.reply_to,
         }
     }
 }
@@ -51,23 +66,20 @@ impl From<Email> for WsMessage {
 /// WebSocket connection state
 #[derive(Clone)]
 pub struct WsState {
+    /// Where lagging clients are resynced from
+    pub storage: Arc<dyn StorageBackend>,
     pub email_receiver: broadcast::Sender<Email>,
     pub deletion_sender: broadcast::Sender<(String, String)>, // (email_id, address)
     pub domain_name: String,
+    pub additional_domains: Vec<String>,
+    /// Time between pings sent to the client
+    pub ping_interval: Duration,
 }
 
 impl WsState {
-    /// Normalize an email address by appending domain if not present
-    fn normalize_address(&self, input: &str) -> String {
-        let input = input.trim();
-
-        // If it already contains @, use as-is
-        if input.contains('@') {
-            input.to_string()
-        } else {
-            // Append the server domain
-            format!("{}@{}", input, self.domain_name)
-        }
+    /// Normalize an email address by appending the primary domain if not present
+    pub(crate) fn normalize_address(&self, input: &str) -> String {
+        crate::config::normalize_address(input, &self.domain_name, &self.additional_domains)
     }
 }
 
@@ -86,7 +98,68 @@ pub async fn websocket_handler(
     ws.on_upgrade(move |socket| handle_socket(socket, normalized_address, state))
 }
 
+/// Serialize and send one message, returning whether the client is still there
+async fn send_message(sender: &mut SplitSink<WebSocket, Message>, msg: &WsMessage) -> bool {
+    let json = match serde_json::to_string(msg) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to serialize WebSocket message: {}", e);
+            return true;
+        }
+    };
+    sender.send(Message::Text(json)).await.is_ok()
+}
+
+/// The `Resync` message for a client that fell behind by `missed` updates, holding what
+/// its mailbox now holds
+///
+/// A failed storage read is only logged and gives `None`, and the client carries on with
+/// the updates it still gets.
+pub(super) async fn resync_message(
+    storage: &Arc<dyn StorageBackend>,
+    address: &str,
+    missed: u64,
+) -> Option<WsMessage> {
+    warn!(
+        "⚠️ Client for {} fell behind and missed {} update(s), resyncing",
+        address, missed
+    );
+    match storage.get_emails_for_address(address).await {
+        Ok(emails) => Some(WsMessage::Resync {
+            address: address.to_string(),
+            missed,
+            emails,
+        }),
+        Err(e) => {
+            error!("❌ Failed to load emails to resync {}: {}", address, e);
+            None
+        }
+    }
+}
+
+/// Tell a WebSocket client that fell behind what its mailbox now holds
+///
+/// Returns whether the client is still there.
+async fn resync(
+    sender: &mut SplitSink<WebSocket, Message>,
+    storage: &Arc<dyn StorageBackend>,
+    address: &str,
+    missed: u64,
+) -> bool {
+    match resync_message(storage, address, missed).await {
+        Some(msg) => send_message(sender, &msg).await,
+        None => true,
+    }
+}
+
 /// Handle individual WebSocket connections
+///
+/// The server pings the client every `ping_interval`. A client that sends nothing, not even
+/// a pong, for two intervals is considered gone and the connection is closed, which also
+/// drops its broadcast subscriptions.
+///
+/// When the broadcast channels outrun the client, the updates it missed are replaced by a
+/// `Resync` message with the mailbox's current emails.
 async fn handle_socket(socket: WebSocket, address: String, state: WsState) {
     let (mut sender, mut receiver) = socket.split();
     let mut email_rx = state.email_receiver.subscribe();
@@ -111,32 +184,44 @@ async fn handle_socket(socket: WebSocket, address: String, state: WsState) {
 
     // Spawn a task to handle incoming messages from the client (mostly just pings)
     let address_for_send = address.clone();
+    let ping_interval = state.ping_interval;
+    let storage = state.storage.clone();
     let mut send_task = tokio::spawn(async move {
+        let mut ping =
+            tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
         loop {
             tokio::select! {
+                // Keep the connection alive and give the client something to answer
+                _ = ping.tick() => {
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        break;
+                    }
+                }
                 // Handle new emails
                 email_result = email_rx.recv() => {
-                    if let Ok(email) = email_result {
+                    match email_result {
                         // Only send emails that match this address
-                        if email.to == address_for_send {
-                            let msg = WsMessage::from(email);
-                            let json = match serde_json::to_string(&msg) {
-                                Ok(json) => json,
-                                Err(e) => {
-                                    error!("Failed to serialize email: {}", e);
-                                    continue;
-                                }
-                            };
-
-                            if sender.send(Message::Text(json)).await.is_err() {
+                        Ok(email) if email.to == address_for_send => {
+                            if !send_message(&mut sender, &WsMessage::from(email)).await {
                                 break;
                             }
                         }
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(missed)) => {
+                            if !resync(&mut sender, &storage, &address_for_send, missed).await {
+                                break;
+                            }
+                        }
+                        Err(RecvError::Closed) => break,
                     }
                 }
                 // Handle email deletions
                 deletion_result = deletion_rx.recv() => {
-                    if let Ok((email_id, deleted_address)) = deletion_result {
+                    if let Err(RecvError::Lagged(missed)) = deletion_result {
+                        if !resync(&mut sender, &storage, &address_for_send, missed).await {
+                            break;
+                        }
+                    } else if let Ok((email_id, deleted_address)) = deletion_result {
                         info!("📨 Received deletion event for email {} to address {}", email_id, deleted_address);
                         // Only send deletions for this address
                         if deleted_address == address_for_send {
@@ -172,8 +257,20 @@ async fn handle_socket(socket: WebSocket, address: String, state: WsState) {
 
     // Handle incoming messages (ping/pong, close, etc.)
     let address_for_recv = address_clone.clone();
+    let idle_timeout = ping_interval * 2;
     let mut recv_task = tokio::spawn(async move {
-        while let Some(msg) = receiver.next().await {
+        loop {
+            let msg = match tokio::time::timeout(idle_timeout, receiver.next()).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(_) => {
+                    warn!(
+                        "⏳ WebSocket client for {} did not answer pings for {:?}, closing",
+                        address_for_recv, idle_timeout
+                    );
+                    break;
+                }
+            };
             match msg {
                 Ok(Message::Close(_)) => {
                     info!(
@@ -187,7 +284,7 @@ async fn handle_socket(socket: WebSocket, address: String, state: WsState) {
                     info!("Received ping for address: {}", address_for_recv);
                 }
                 Ok(Message::Pong(_)) => {
-                    // Pong received
+                    // Pong received; like any message it resets the idle timeout
                 }
                 Ok(Message::Text(text)) => {
                     info!("Received message for {}: {}", address_for_recv, text);
@@ -217,20 +314,30 @@ mod tests {
     use serde_json::json;
     use tokio::sync::broadcast;
 
-    fn create_test_ws_state() -> WsState {
-        let (email_tx, _) = broadcast::channel::<Email>(100);
-        let (deletion_tx, _) = broadcast::channel::<(String, String)>(100);
+    async fn create_test_ws_state() -> WsState {
+        create_test_ws_state_with_capacity(100).await
+    }
+
+    async fn create_test_ws_state_with_capacity(capacity: usize) -> WsState {
+        let (email_tx, _) = broadcast::channel::<Email>(capacity);
+        let (deletion_tx, _) = broadcast::channel::<(String, String)>(capacity);
+        let storage = crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+            .await
+            .unwrap();
 
         WsState {
+            storage: Arc::new(storage),
             email_receiver: email_tx,
             deletion_sender: deletion_tx,
             domain_name: "test.local".to_string(),
+            additional_domains: vec![],
+            ping_interval: Duration::from_secs(30),
         }
     }
 
     #[test]
     fn test_ws_message_from_email() {
-        let email = Email::new(
+        let mut email = Email::new(
             "test@test.local".to_string(),
             "sender@example.com".to_string(),
             "Test Subject".to_string(),
@@ -238,6 +345,8 @@ mod tests {
             None,
             vec![],
         );
+        email.cc = vec!["copy@example.com".to_string()];
+        email.reply_to = Some("replies@example.com".to_string());
 
         let ws_message = WsMessage::from(email.clone());
 
@@ -251,6 +360,8 @@ mod tests {
                 timestamp,
                 raw,
                 attachments,
+                cc,
+                reply_to,
             } => {
                 assert_eq!(id, email.id);
                 assert_eq!(to, email.to);
@@ -259,6 +370,8 @@ mod tests {
                 assert_eq!(body, email.body);
                 assert_eq!(raw, email.raw);
                 assert_eq!(attachments.len(), email.attachments.len());
+                assert_eq!(cc, email.cc);
+                assert_eq!(reply_to, email.reply_to);
                 assert!(!timestamp.is_empty());
             }
             _ => panic!("Expected Email message type"),
@@ -359,9 +472,145 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_ws_state_normalize_address() {
-        let state = create_test_ws_state();
+    /// Read server frames until a text frame arrives and return its payload
+    async fn read_text_frame(stream: &mut tokio::net::TcpStream) -> String {
+        use tokio::io::AsyncReadExt;
+
+        loop {
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).await.unwrap();
+            let len = match header[1] & 0x7f {
+                126 => stream.read_u16().await.unwrap() as usize,
+                127 => stream.read_u64().await.unwrap() as usize,
+                len => len as usize,
+            };
+            let mut payload = vec![0u8; len];
+            stream.read_exact(&mut payload).await.unwrap();
+            if header[0] & 0x0f == 0x1 {
+                return String::from_utf8(payload).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_client_is_resynced() {
+        use axum::{routing::get, Router};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        let state = create_test_ws_state_with_capacity(2).await;
+        let email_tx = state.email_receiver.clone();
+        let storage = state.storage.clone();
+        let app = Router::new()
+            .route("/api/ws/:address", get(websocket_handler))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET /api/ws/test HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            addr
+        );
+        stream.write_all(handshake.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(stream.read_u8().await.unwrap());
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101"));
+        let connected: serde_json::Value =
+            serde_json::from_str(&read_text_frame(&mut stream).await).unwrap();
+        assert_eq!(connected["type"], "Connected");
+
+        let mut emails = Vec::new();
+        for i in 0..5 {
+            let email = Email::new(
+                "test@test.local".to_string(),
+                "sender@example.com".to_string(),
+                format!("Email {}", i),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email.clone()).await.unwrap();
+            emails.push(email);
+        }
+        // Announce more emails than the channel holds without yielding to the connection,
+        // so its subscription falls behind
+        for email in &emails {
+            email_tx.send(email.clone()).unwrap();
+        }
+
+        let resync: serde_json::Value =
+            serde_json::from_str(&read_text_frame(&mut stream).await).unwrap();
+        assert_eq!(resync["type"], "Resync");
+        assert_eq!(resync["address"], "test@test.local");
+        assert_eq!(resync["missed"], 3);
+        assert_eq!(resync["emails"].as_array().unwrap().len(), 5);
+
+        // The updates still buffered follow as usual
+        for email in &emails[3..] {
+            let update: serde_json::Value =
+                serde_json::from_str(&read_text_frame(&mut stream).await).unwrap();
+            assert_eq!(update["type"], "Email");
+            assert_eq!(update["id"], email.id.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_client_is_dropped() {
+        use axum::{routing::get, Router};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        let mut state = create_test_ws_state().await;
+        state.ping_interval = Duration::from_millis(100);
+        let email_tx = state.email_receiver.clone();
+        let app = Router::new()
+            .route("/api/ws/:address", get(websocket_handler))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Complete the handshake, then read everything without ever answering a ping
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET /api/ws/test HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            addr
+        );
+        stream.write_all(handshake.as_bytes()).await.unwrap();
+
+        let started = tokio::time::Instant::now();
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut received))
+            .await
+            .expect("connection was not closed")
+            .unwrap();
+
+        assert!(received.starts_with(b"HTTP/1.1 101"));
+        // An empty ping frame was sent before the connection was given up on
+        assert!(received.windows(2).any(|frame| frame == [0x89, 0x00]));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // The closed connection no longer holds a broadcast subscription
+        for _ in 0..50 {
+            if email_tx.receiver_count() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(email_tx.receiver_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ws_state_normalize_address() {
+        let state = create_test_ws_state().await;
 
         // Test normalization of address without @
         assert_eq!(state.normalize_address("user"), "user@test.local");
@@ -401,6 +650,10 @@ mod tests {
             content_type: "text/plain".to_string(),
             size: 100,
             content: "dGVzdCBjb250ZW50".to_string(),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
         });
 
         let ws_message = WsMessage::from(email);
@@ -6,19 +6,35 @@
 use axum::{
     async_trait,
     body::Body,
-    extract::{FromRequestParts, State},
-    http::{header::AUTHORIZATION, request::Parts, Request, StatusCode},
+    extract::{ConnectInfo, FromRef, FromRequestParts, State},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        request::Parts,
+        HeaderMap, Request, StatusCode,
+    },
     middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
 
-use crate::storage::{models::User, StorageBackend};
+use crate::storage::{
+    models::{RefreshToken, User},
+    StorageBackend,
+};
+
+mod password;
+
+pub use password::PasswordHasher;
 
 /// JWT claims
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +47,10 @@ pub struct Claims {
     pub exp: i64,
     /// Issued at (Unix timestamp)
     pub iat: i64,
+    /// Unique token ID, recorded on logout to revoke the token. Empty in tokens issued
+    /// before IDs were added, which cannot be revoked and simply expire.
+    #[serde(default)]
+    pub jti: String,
 }
 
 /// Auth configuration passed to handlers
@@ -39,25 +59,57 @@ pub struct AuthConfig {
     pub enabled: bool,
     pub jwt_secret: String,
     pub jwt_expiry_hours: u64,
+    pub refresh_token_expiry_days: u64,
+    /// Failed logins allowed per email, or mailbox password checks per mailbox, within the
+    /// window before locking out (0 disables)
+    pub login_max_attempts: u32,
+    /// Window over which failed logins are counted
+    pub login_window_minutes: u64,
     /// Optional domain restrictions for registration (e.g., vec!["example.com", "company.com"])
     pub auth_domains: Option<Vec<String>>,
     pub outbound_enabled: bool,
+    /// Algorithm new user passwords are hashed with
+    pub password_hasher: PasswordHasher,
 }
 
 /// Request body for registration
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterRequest {
     pub email: String,
     pub password: String,
 }
 
 /// Request body for login
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
+/// Request body for checking a mailbox password
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MailboxAuthRequest {
+    /// Mailbox name, or a full address whose local part is the mailbox
+    pub mailbox: String,
+    pub password: String,
+}
+
+/// Request body for changing the authenticated user's password
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Request body for refreshing an access token or logging out
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Shortest password accepted at registration or on a password change
+const MIN_PASSWORD_LENGTH: usize = 8;
+
 /// Generate a JWT token for a user
 pub fn generate_token(
     user: &User,
@@ -71,6 +123,7 @@ pub fn generate_token(
         email: user.email.clone(),
         exp: exp.timestamp(),
         iat: now.timestamp(),
+        jti: Uuid::new_v4().to_string(),
     };
 
     encode(
@@ -80,6 +133,36 @@ pub fn generate_token(
     )
 }
 
+/// Hash a refresh token for storage; only the hash is ever persisted
+fn hash_refresh_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Issue a new refresh token for a user and store its hash
+async fn issue_refresh_token(
+    storage: &Arc<dyn StorageBackend>,
+    user: &User,
+    config: &AuthConfig,
+) -> Result<String, (StatusCode, String)> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = Utc::now();
+
+    storage
+        .store_refresh_token(RefreshToken {
+            token_hash: hash_refresh_token(&token),
+            user_id: user.id.clone(),
+            expires_at: now + Duration::days(config.refresh_token_expiry_days as i64),
+            created_at: now,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(token)
+}
+
 /// Validate email format
 fn is_valid_email(email: &str) -> bool {
     // Basic email validation
@@ -134,7 +217,43 @@ pub fn verify_token(
     Ok(token_data.claims)
 }
 
+/// Verify a JWT token and check that it has not been revoked by logging out
+pub async fn authenticate_token(
+    token: &str,
+    config: &AuthConfig,
+    storage: &Arc<dyn StorageBackend>,
+) -> Result<Claims, (StatusCode, String)> {
+    let claims = verify_token(token, config)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e)))?;
+
+    if !claims.jti.is_empty()
+        && storage
+            .is_token_revoked(&claims.jti)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Token has been revoked".to_string(),
+        ));
+    }
+
+    Ok(claims)
+}
+
 /// Register a new user
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "The new user", body = serde_json::Value),
+        (status = 400, description = "Invalid email, weak password or disallowed domain"),
+        (status = 409, description = "Email already registered")
+    ),
+    security(()),
+    tag = "auth"
+)]
 pub async fn register(
     State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     Json(request): Json<RegisterRequest>,
@@ -165,10 +284,13 @@ pub async fn register(
     }
 
     // Validate password
-    if request.password.len() < 8 {
+    if request.password.len() < MIN_PASSWORD_LENGTH {
         return Err((
             StatusCode::BAD_REQUEST,
-            "Password must be at least 8 characters".to_string(),
+            format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ),
         ));
     }
 
@@ -182,16 +304,26 @@ pub async fn register(
         return Err((StatusCode::CONFLICT, "Email already registered".to_string()));
     }
 
+    // The first user to register administers the instance
+    let is_first_user = !storage
+        .has_users()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     // Hash password
-    let password_hash = bcrypt::hash(&request.password, bcrypt::DEFAULT_COST).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to hash password: {}", e),
-        )
-    })?;
+    let password_hash = config
+        .password_hasher
+        .hash(&request.password)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to hash password: {}", e),
+            )
+        })?;
 
     // Create user
-    let user = User::new(request.email.clone(), password_hash);
+    let mut user = User::new(request.email.clone(), password_hash);
+    user.is_admin = is_first_user;
     storage
         .create_user(user.clone())
         .await
@@ -204,9 +336,11 @@ pub async fn register(
             format!("Failed to generate token: {}", e),
         )
     })?;
+    let refresh_token = issue_refresh_token(&storage, &user, &config).await?;
 
     Ok(Json(json!({
         "token": token,
+        "refresh_token": refresh_token,
         "user": {
             "id": user.id,
             "email": user.email
@@ -214,11 +348,57 @@ pub async fn register(
     })))
 }
 
+/// Seconds until another login attempt is allowed, or `None` if not locked out
+async fn login_lockout_remaining(
+    storage: &Arc<dyn StorageBackend>,
+    email: &str,
+    config: &AuthConfig,
+) -> Result<Option<u64>, (StatusCode, String)> {
+    if config.login_max_attempts == 0 {
+        return Ok(None);
+    }
+
+    let window = Duration::minutes(config.login_window_minutes as i64);
+    let now = Utc::now();
+    let attempts = storage
+        .get_failed_logins_since(email, now - window)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if attempts.len() < config.login_max_attempts as usize {
+        return Ok(None);
+    }
+
+    // The lockout lifts once enough of the oldest attempts fall out of the window
+    let unlocks_at = attempts[attempts.len() - config.login_max_attempts as usize] + window;
+    Ok(Some((unlocks_at - now).num_seconds().max(1) as u64))
+}
+
+/// Record a failed login and return the generic credentials error
+async fn failed_login(storage: &Arc<dyn StorageBackend>, email: &str) -> (StatusCode, String) {
+    if let Err(e) = storage.record_failed_login(email, Utc::now()).await {
+        warn!("Failed to record failed login for {}: {}", email, e);
+    }
+    (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string())
+}
+
 /// Login an existing user
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Access and refresh tokens", body = serde_json::Value),
+        (status = 401, description = "Invalid credentials"),
+        (status = 429, description = "Too many failed logins")
+    ),
+    security(()),
+    tag = "auth"
+)]
 pub async fn login(
     State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     Json(request): Json<LoginRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     if !config.enabled {
         return Err((
             StatusCode::NOT_FOUND,
@@ -226,26 +406,198 @@ pub async fn login(
         ));
     }
 
+    // Throttle before doing any password verification
+    let attempt_key = request.email.to_lowercase();
+    if let Some(retry_after) = login_lockout_remaining(&storage, &attempt_key, &config).await? {
+        warn!("🔒 Too many failed logins for {}", attempt_key);
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            "Too many failed login attempts, try again later",
+        )
+            .into_response());
+    }
+
     // Find user by email
-    let user = storage
+    let user = match storage
         .get_user_by_email(&request.email)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()))?;
+    {
+        Some(user) => user,
+        None => return Err(failed_login(&storage, &attempt_key).await),
+    };
 
     // Verify password
-    let password_valid = bcrypt::verify(&request.password, &user.password_hash).map_err(|e| {
+    let password_valid =
+        PasswordHasher::verify(&request.password, &user.password_hash).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Password verification error: {}", e),
+            )
+        })?;
+
+    if !password_valid {
+        return Err(failed_login(&storage, &attempt_key).await);
+    }
+
+    storage
+        .clear_failed_logins(&attempt_key)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Generate token
+    let token = generate_token(&user, &config).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Password verification error: {}", e),
+            format!("Failed to generate token: {}", e),
         )
     })?;
+    let refresh_token = issue_refresh_token(&storage, &user, &config).await?;
 
-    if !password_valid {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
+    Ok(Json(json!({
+        "token": token,
+        "refresh_token": refresh_token,
+        "user": {
+            "id": user.id,
+            "email": user.email
+        }
+    }))
+    .into_response())
+}
+
+/// Check a mailbox password the way IMAP LOGIN does, for external auth proxies
+///
+/// No token is issued. Failures count towards the login lockout keyed by client IP and
+/// mailbox, so one client can't lock other clients out of a mailbox; the per-IP API rate
+/// limit caps how fast a client can guess across mailboxes.
+#[utoipa::path(
+    post,
+    path = "/api/mailbox/auth",
+    request_body = MailboxAuthRequest,
+    responses(
+        (status = 200, description = "Credentials accepted: `{success, mailbox}`", body = serde_json::Value),
+        (status = 400, description = "No mailbox given"),
+        (status = 401, description = "Invalid credentials"),
+        (status = 429, description = "Too many failed attempts")
+    ),
+    security(()),
+    tag = "auth"
+)]
+pub async fn mailbox_auth(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<MailboxAuthRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    // Mailboxes are keyed by local part only, as in IMAP
+    let mailbox = request
+        .mailbox
+        .trim()
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if mailbox.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Mailbox is required".to_string()));
     }
 
-    // Generate token
+    // Requests without a known peer address (e.g. in-process tests) share one key
+    let client = connect_info
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let attempt_key = format!("mailbox:{}:{}", client, mailbox.to_lowercase());
+    if let Some(retry_after) = login_lockout_remaining(&storage, &attempt_key, &config).await? {
+        warn!(
+            "🔒 Too many failed mailbox logins for {} from {}",
+            mailbox, client
+        );
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            "Too many failed login attempts, try again later",
+        )
+            .into_response());
+    }
+
+    let valid = storage
+        .verify_mailbox_password(&mailbox, &request.password)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !valid {
+        warn!("🔑 Mailbox password rejected for {}", mailbox);
+        return Err(failed_login(&storage, &attempt_key).await);
+    }
+
+    storage
+        .clear_failed_logins(&attempt_key)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "mailbox": mailbox
+    }))
+    .into_response())
+}
+
+/// Exchange a refresh token for a new access token
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, description = "A new access token", body = serde_json::Value),
+        (status = 401, description = "Refresh token is invalid or expired")
+    ),
+    security(()),
+    tag = "auth"
+)]
+pub async fn refresh(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !config.enabled {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Authentication is not enabled".to_string(),
+        ));
+    }
+
+    let token_hash = hash_refresh_token(&request.refresh_token);
+    let stored = storage
+        .get_refresh_token(&token_hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                "Invalid refresh token".to_string(),
+            )
+        })?;
+
+    if stored.expires_at <= Utc::now() {
+        // Expired tokens are useless, so clean them up on sight
+        storage
+            .delete_refresh_token(&token_hash)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Refresh token has expired".to_string(),
+        ));
+    }
+
+    let user = storage
+        .get_user_by_id(&stored.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                "Invalid refresh token".to_string(),
+            )
+        })?;
+
     let token = generate_token(&user, &config).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -262,7 +614,181 @@ pub async fn login(
     })))
 }
 
+/// Log out by revoking the bearer access token and/or a refresh token
+///
+/// The access token in the Authorization header is rejected from then on, until it
+/// would have expired anyway. A refresh token in the body is deleted.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    request_body(content = RefreshRequest, description = "Optional refresh token to revoke"),
+    responses(
+        (status = 200, description = "Tokens revoked: `{success, revoked, access_token_revoked}`", body = serde_json::Value),
+        (status = 400, description = "Neither an access token nor a refresh token was given")
+    ),
+    tag = "auth"
+)]
+pub async fn logout(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
+    headers: HeaderMap,
+    request: Option<Json<RefreshRequest>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !config.enabled {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Authentication is not enabled".to_string(),
+        ));
+    }
+
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if token.is_none() && request.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Expected a bearer token or a refresh token".to_string(),
+        ));
+    }
+
+    // An invalid or expired access token is already unusable, so there is nothing to revoke
+    let mut access_token_revoked = false;
+    if let Some(claims) = token.and_then(|token| verify_token(token, &config).ok()) {
+        if !claims.jti.is_empty() {
+            let expires_at = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+            storage
+                .revoke_token(&claims.jti, expires_at)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            access_token_revoked = true;
+            info!(
+                "🔒 Revoked access token {} for {}",
+                claims.jti, claims.email
+            );
+        }
+    }
+
+    let revoked = match request {
+        Some(Json(request)) => storage
+            .delete_refresh_token(&hash_refresh_token(&request.refresh_token))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        None => false,
+    };
+
+    Ok(Json(json!({
+        "success": true,
+        "revoked": revoked,
+        "access_token_revoked": access_token_revoked
+    })))
+}
+
+/// Change the authenticated user's password
+///
+/// All of the user's refresh tokens are revoked, logging out other sessions; a new
+/// refresh token is returned for the current one.
+#[utoipa::path(
+    post,
+    path = "/api/auth/change-password",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed; new access and refresh tokens", body = serde_json::Value),
+        (status = 400, description = "New password is too short"),
+        (status = 401, description = "Not authenticated, or the current password is wrong"),
+        (status = 404, description = "User not found")
+    ),
+    tag = "auth"
+)]
+pub async fn change_password(
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
+    claims: AuthenticatedUser,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !config.enabled {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Authentication is not enabled".to_string(),
+        ));
+    }
+
+    if request.new_password.len() < MIN_PASSWORD_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ),
+        ));
+    }
+
+    let user = storage
+        .get_user_by_id(&claims.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    let password_valid = PasswordHasher::verify(&request.current_password, &user.password_hash)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Password verification error: {}", e),
+            )
+        })?;
+    if !password_valid {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Current password is incorrect".to_string(),
+        ));
+    }
+
+    let password_hash = config
+        .password_hasher
+        .hash(&request.new_password)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to hash password: {}", e),
+            )
+        })?;
+    storage
+        .update_user_password(&user.id, &password_hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let revoked = storage
+        .delete_refresh_tokens_for_user(&user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!(
+        "🔑 Password changed for {}, revoked {} refresh token(s)",
+        user.email, revoked
+    );
+
+    let token = generate_token(&user, &config).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to generate token: {}", e),
+        )
+    })?;
+    let refresh_token = issue_refresh_token(&storage, &user, &config).await?;
+
+    Ok(Json(json!({
+        "success": true,
+        "token": token,
+        "refresh_token": refresh_token
+    })))
+}
+
 /// Get current user info
+#[utoipa::path(
+    get,
+    path = "/api/auth/me",
+    responses(
+        (status = 200, description = "The authenticated user", body = serde_json::Value),
+        (status = 401, description = "Not authenticated")
+    ),
+    tag = "auth"
+)]
 pub async fn me(
     State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     claims: AuthenticatedUser,
@@ -283,11 +809,21 @@ pub async fn me(
     Ok(Json(json!({
         "id": user.id,
         "email": user.email,
-        "created_at": user.created_at
+        "created_at": user.created_at,
+        "is_admin": user.is_admin
     })))
 }
 
 /// Get auth status (whether auth is enabled and if users exist)
+#[utoipa::path(
+    get,
+    path = "/api/auth/status",
+    responses(
+        (status = 200, description = "Whether authentication is enabled and registration is open", body = serde_json::Value)
+    ),
+    security(()),
+    tag = "auth"
+)]
 pub async fn status(
     State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -309,6 +845,9 @@ pub async fn status(
     })))
 }
 
+/// User ID given to every request when auth is disabled
+pub const ANONYMOUS_USER_ID: &str = "anonymous";
+
 /// Authenticated user extracted from JWT
 #[derive(Clone, Debug)]
 pub struct AuthenticatedUser {
@@ -317,6 +856,13 @@ pub struct AuthenticatedUser {
     pub email: String,
 }
 
+impl AuthenticatedUser {
+    /// Whether this is the placeholder user used when auth is disabled
+    pub fn is_anonymous(&self) -> bool {
+        self.user_id == ANONYMOUS_USER_ID
+    }
+}
+
 /// Extractor for authenticated requests
 /// When auth is enabled, this extracts the user from the JWT token.
 /// When auth is disabled, this creates a dummy user.
@@ -343,11 +889,22 @@ where
         // If auth is disabled, return a dummy user
         if !auth_config.enabled {
             return Ok(AuthenticatedUser {
-                user_id: "anonymous".to_string(),
-                email: "anonymous".to_string(),
+                user_id: ANONYMOUS_USER_ID.to_string(),
+                email: ANONYMOUS_USER_ID.to_string(),
             });
         }
 
+        let storage = parts
+            .extensions
+            .get::<Arc<dyn StorageBackend>>()
+            .ok_or_else(|| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Auth storage not found".to_string(),
+                )
+            })?
+            .clone();
+
         // Extract Bearer token
         let auth_header = parts
             .headers
@@ -368,8 +925,7 @@ where
         })?;
 
         // Verify token
-        let claims = verify_token(token, &auth_config)
-            .map_err(|e| (StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e)))?;
+        let claims = authenticate_token(token, &auth_config, &storage).await?;
 
         Ok(AuthenticatedUser {
             user_id: claims.sub,
@@ -378,20 +934,84 @@ where
     }
 }
 
-/// Middleware to inject auth config into request extensions
+/// Check that an authenticated user has the admin role, with 403 if not
+pub async fn verify_admin(
+    storage: &Arc<dyn StorageBackend>,
+    user: &AuthenticatedUser,
+) -> Result<(), (StatusCode, String)> {
+    let is_admin = storage
+        .get_user_by_id(&user.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .is_some_and(|user| user.is_admin);
+    if !is_admin {
+        warn!("🔒 Non-admin user {} denied admin access", user.email);
+        return Err((StatusCode::FORBIDDEN, "Admin access required".to_string()));
+    }
+    Ok(())
+}
+
+/// Authenticated user with the admin role
+///
+/// Rejects everyone else with 403, including all requests when auth is disabled, since
+/// there is then no way to tell an admin apart.
+#[derive(Clone, Debug)]
+pub struct AdminUser {
+    pub user_id: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    Arc<dyn StorageBackend>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        AdminUser::from_user(&Arc::<dyn StorageBackend>::from_ref(state), user).await
+    }
+}
+
+impl AdminUser {
+    /// Check that an already extracted user is an admin, for handlers whose state the
+    /// extractor cannot take the storage from
+    pub async fn from_user(
+        storage: &Arc<dyn StorageBackend>,
+        user: AuthenticatedUser,
+    ) -> Result<Self, (StatusCode, String)> {
+        if user.is_anonymous() {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Admin routes require authentication to be enabled".to_string(),
+            ));
+        }
+
+        verify_admin(storage, &user).await?;
+
+        Ok(AdminUser {
+            user_id: user.user_id,
+        })
+    }
+}
+
+/// Middleware to inject auth config into request extensions, along with the storage
+/// revoked tokens are looked up in
 pub async fn auth_config_middleware(
-    State(config): State<AuthConfig>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     mut request: Request<Body>,
     next: Next,
 ) -> Response {
     request.extensions_mut().insert(config);
+    request.extensions_mut().insert(storage);
     next.run(request).await
 }
 
 /// Middleware that ALWAYS requires authentication regardless of auth_enabled.
 /// Used for security-critical routes like outbound email to prevent open relay.
 pub async fn require_auth_always(
-    State(config): State<AuthConfig>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
@@ -403,11 +1023,9 @@ pub async fn require_auth_always(
     match auth_header {
         Some(header) if header.starts_with("Bearer ") => {
             let token = &header[7..];
-            match verify_token(token, &config) {
+            match authenticate_token(token, &config, &storage).await {
                 Ok(_) => next.run(request).await,
-                Err(e) => {
-                    (StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e)).into_response()
-                }
+                Err(rejection) => rejection.into_response(),
             }
         }
         _ => (
@@ -420,7 +1038,7 @@ pub async fn require_auth_always(
 
 /// Middleware to require authentication when auth is enabled
 pub async fn require_auth(
-    State(config): State<AuthConfig>,
+    State((storage, config)): State<(Arc<dyn StorageBackend>, AuthConfig)>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
@@ -438,11 +1056,9 @@ pub async fn require_auth(
     match auth_header {
         Some(header) if header.starts_with("Bearer ") => {
             let token = &header[7..];
-            match verify_token(token, &config) {
+            match authenticate_token(token, &config, &storage).await {
                 Ok(_) => next.run(request).await,
-                Err(e) => {
-                    (StatusCode::UNAUTHORIZED, format!("Invalid token: {}", e)).into_response()
-                }
+                Err(rejection) => rejection.into_response(),
             }
         }
         _ => (
@@ -463,8 +1079,12 @@ mod tests {
             enabled: true,
             jwt_secret: "test-secret-key".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
             outbound_enabled: false,
+            password_hasher: PasswordHasher::Bcrypt,
         };
 
         let user = User::new("test@example.com".to_string(), "hash".to_string());
@@ -481,8 +1101,12 @@ mod tests {
             enabled: true,
             jwt_secret: "test-secret-key".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
             outbound_enabled: false,
+            password_hasher: PasswordHasher::Bcrypt,
         };
 
         let result = verify_token("invalid-token", &config);
@@ -495,16 +1119,24 @@ mod tests {
             enabled: true,
             jwt_secret: "secret1".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
             outbound_enabled: false,
+            password_hasher: PasswordHasher::Bcrypt,
         };
 
         let config2 = AuthConfig {
             enabled: true,
             jwt_secret: "secret2".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
             outbound_enabled: false,
+            password_hasher: PasswordHasher::Bcrypt,
         };
 
         let user = User::new("test@example.com".to_string(), "hash".to_string());
@@ -557,8 +1189,12 @@ mod tests {
             enabled: true,
             jwt_secret: "test-secret-key-for-testing".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
             outbound_enabled: false,
+            password_hasher: PasswordHasher::Bcrypt,
         }
     }
 
@@ -574,11 +1210,15 @@ mod tests {
         Router::new()
             .route("/api/auth/register", post(register))
             .route("/api/auth/login", post(login))
+            .route("/api/auth/refresh", post(refresh))
+            .route("/api/auth/logout", post(logout))
             .route("/api/auth/me", get(me))
+            .route("/api/auth/change-password", post(change_password))
             .route("/api/auth/status", get(status))
-            .with_state((storage, config.clone()))
+            .route("/api/mailbox/auth", post(mailbox_auth))
+            .with_state((storage.clone(), config.clone()))
             .layer(middleware::from_fn_with_state(
-                config,
+                (storage, config),
                 auth_config_middleware,
             ))
     }
@@ -623,6 +1263,36 @@ mod tests {
             .unwrap()
     }
 
+    async fn check_mailbox_password(
+        app: &Router,
+        mailbox: &str,
+        password: &str,
+    ) -> axum::http::Response<Body> {
+        check_mailbox_password_from(app, None, mailbox, password).await
+    }
+
+    async fn check_mailbox_password_from(
+        app: &Router,
+        peer: Option<SocketAddr>,
+        mailbox: &str,
+        password: &str,
+    ) -> axum::http::Response<Body> {
+        let body = serde_json::json!({
+            "mailbox": mailbox,
+            "password": password,
+        });
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("/api/mailbox/auth")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap();
+        if let Some(peer) = peer {
+            request.extensions_mut().insert(ConnectInfo(peer));
+        }
+        app.clone().oneshot(request).await.unwrap()
+    }
+
     async fn body_json(response: axum::http::Response<Body>) -> serde_json::Value {
         let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
@@ -643,6 +1313,7 @@ mod tests {
 
         let json = body_json(response).await;
         assert!(json["token"].is_string());
+        assert!(json["refresh_token"].is_string());
         assert_eq!(json["user"]["email"], "user@example.com");
         assert!(json["user"]["id"].is_string());
     }
@@ -999,9 +1670,9 @@ mod tests {
             enabled: false,
             ..test_auth_config()
         };
-        let app = Router::new()
-            .route("/protected", get(dummy_handler))
-            .layer(middleware::from_fn_with_state(config, require_auth));
+        let app = Router::new().route("/protected", get(dummy_handler)).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth),
+        );
 
         let response = app
             .oneshot(
@@ -1019,9 +1690,9 @@ mod tests {
     #[tokio::test]
     async fn test_require_auth_blocks_without_token() {
         let config = test_auth_config();
-        let app = Router::new()
-            .route("/protected", get(dummy_handler))
-            .layer(middleware::from_fn_with_state(config, require_auth));
+        let app = Router::new().route("/protected", get(dummy_handler)).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth),
+        );
 
         let response = app
             .oneshot(
@@ -1042,9 +1713,9 @@ mod tests {
         let user = User::new("test@example.com".to_string(), "hash".to_string());
         let token = generate_token(&user, &config).unwrap();
 
-        let app = Router::new()
-            .route("/protected", get(dummy_handler))
-            .layer(middleware::from_fn_with_state(config, require_auth));
+        let app = Router::new().route("/protected", get(dummy_handler)).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth),
+        );
 
         let response = app
             .oneshot(
@@ -1063,9 +1734,9 @@ mod tests {
     #[tokio::test]
     async fn test_require_auth_rejects_invalid_token() {
         let config = test_auth_config();
-        let app = Router::new()
-            .route("/protected", get(dummy_handler))
-            .layer(middleware::from_fn_with_state(config, require_auth));
+        let app = Router::new().route("/protected", get(dummy_handler)).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth),
+        );
 
         let response = app
             .oneshot(
@@ -1168,9 +1839,9 @@ mod tests {
             ..test_auth_config()
         };
 
-        let app = Router::new()
-            .route("/test", get(|| async { "ok" }))
-            .layer(middleware::from_fn_with_state(config, require_auth_always));
+        let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth_always),
+        );
 
         let response = app
             .oneshot(Request::builder().uri("/test").body(Body::empty()).unwrap())
@@ -1187,9 +1858,9 @@ mod tests {
         let user = User::new("test@example.com".to_string(), "hash".to_string());
         let token = generate_token(&user, &config).unwrap();
 
-        let app = Router::new()
-            .route("/test", get(|| async { "ok" }))
-            .layer(middleware::from_fn_with_state(config, require_auth_always));
+        let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth_always),
+        );
 
         let response = app
             .oneshot(
@@ -1212,9 +1883,9 @@ mod tests {
             ..test_auth_config()
         };
 
-        let app = Router::new()
-            .route("/test", get(|| async { "ok" }))
-            .layer(middleware::from_fn_with_state(config, require_auth_always));
+        let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+            middleware::from_fn_with_state((test_storage().await, config), require_auth_always),
+        );
 
         let response = app
             .oneshot(
@@ -1229,4 +1900,498 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
     }
+
+    // Refresh token tests
+
+    async fn post_refresh_token(
+        app: &Router,
+        uri: &str,
+        refresh_token: &str,
+    ) -> axum::http::Response<Body> {
+        let body = serde_json::json!({ "refresh_token": refresh_token });
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_refresh_success() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage.clone(), config.clone());
+
+        register_user(&app, "user@example.com", "password123").await;
+        let response = login_user(&app, "user@example.com", "password123").await;
+        let json = body_json(response).await;
+        let refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+        // Only the hash is stored
+        assert!(storage
+            .get_refresh_token(&refresh_token)
+            .await
+            .unwrap()
+            .is_none());
+
+        let response = post_refresh_token(&app, "/api/auth/refresh", &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = body_json(response).await;
+        let claims = verify_token(json["token"].as_str().unwrap(), &config).unwrap();
+        assert_eq!(claims.email, "user@example.com");
+        assert_eq!(json["user"]["email"], "user@example.com");
+
+        // The refresh token stays valid until it expires or is revoked
+        let response = post_refresh_token(&app, "/api/auth/refresh", &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = post_refresh_token(&app, "/api/auth/refresh", "unknown-token").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_after_logout() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage, config);
+
+        let response = register_user(&app, "user@example.com", "password123").await;
+        let json = body_json(response).await;
+        let refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+        let response = post_refresh_token(&app, "/api/auth/logout", &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["revoked"], true);
+
+        let response = post_refresh_token(&app, "/api/auth/refresh", &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// Send a request with a bearer token, returning the status
+    async fn send_with_token(app: &Router, method: &str, uri: &str, token: &str) -> StatusCode {
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_access_token_rejected_after_logout() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage.clone(), config.clone());
+        let protected = Router::new().route("/protected", get(dummy_handler)).layer(
+            middleware::from_fn_with_state((storage.clone(), config.clone()), require_auth),
+        );
+
+        let response = register_user(&app, "user@example.com", "password123").await;
+        let token = body_json(response).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let response = login_user(&app, "user@example.com", "password123").await;
+        let other_session = body_json(response).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            send_with_token(&app, "GET", "/api/auth/me", &token).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send_with_token(&protected, "GET", "/protected", &token).await,
+            StatusCode::OK
+        );
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/auth/logout")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = body_json(response).await;
+        assert_eq!(json["access_token_revoked"], true);
+        assert_eq!(json["revoked"], false);
+
+        assert_eq!(
+            send_with_token(&app, "GET", "/api/auth/me", &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send_with_token(&protected, "GET", "/protected", &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // Other sessions of the same user are unaffected
+        assert_eq!(
+            send_with_token(&app, "GET", "/api/auth/me", &other_session).await,
+            StatusCode::OK
+        );
+
+        let claims = verify_token(&token, &config).unwrap();
+        assert!(storage.is_token_revoked(&claims.jti).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_logout_without_tokens() {
+        let app = auth_app(test_storage().await, test_auth_config());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/auth/logout")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_expired_token() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage.clone(), config);
+
+        register_user(&app, "user@example.com", "password123").await;
+        let user = storage
+            .get_user_by_email("user@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let refresh_token = "expired-refresh-token";
+        storage
+            .store_refresh_token(RefreshToken {
+                token_hash: hash_refresh_token(refresh_token),
+                user_id: user.id,
+                expires_at: Utc::now() - Duration::minutes(1),
+                created_at: Utc::now() - Duration::days(31),
+            })
+            .await
+            .unwrap();
+
+        let response = post_refresh_token(&app, "/api/auth/refresh", refresh_token).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // The expired token is removed
+        assert!(storage
+            .get_refresh_token(&hash_refresh_token(refresh_token))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    // Mailbox password check tests
+
+    #[tokio::test]
+    async fn test_mailbox_auth_checks_password() {
+        let storage = test_storage().await;
+        storage
+            .set_mailbox_password("locked", bcrypt::hash("secret", 4).unwrap())
+            .await
+            .unwrap();
+        // Works whether or not user accounts are enabled
+        let config = AuthConfig {
+            enabled: false,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage, config);
+
+        let response = check_mailbox_password(&app, "locked", "secret").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_json(response).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["mailbox"], "locked");
+        assert!(body.get("token").is_none());
+
+        // A full address is checked against its local part
+        let response = check_mailbox_password(&app, "locked@example.com", "secret").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = check_mailbox_password(&app, "locked", "wrong").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = check_mailbox_password(&app, " ", "secret").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_auth_lockout() {
+        let storage = test_storage().await;
+        storage
+            .set_mailbox_password("locked", bcrypt::hash("secret", 4).unwrap())
+            .await
+            .unwrap();
+        let config = AuthConfig {
+            login_max_attempts: 3,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage, config);
+
+        for _ in 0..3 {
+            let response = check_mailbox_password(&app, "locked", "guess").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // Even the correct password is refused while locked out
+        let response = check_mailbox_password(&app, "locked", "secret").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_auth_lockout_is_per_client() {
+        let storage = test_storage().await;
+        storage
+            .set_mailbox_password("locked", bcrypt::hash("secret", 4).unwrap())
+            .await
+            .unwrap();
+        let config = AuthConfig {
+            login_max_attempts: 3,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage, config);
+        let attacker = SocketAddr::from(([192, 0, 2, 1], 4000));
+        let owner = SocketAddr::from(([192, 0, 2, 2], 4000));
+
+        for _ in 0..3 {
+            let response =
+                check_mailbox_password_from(&app, Some(attacker), "locked", "guess").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = check_mailbox_password_from(&app, Some(attacker), "locked", "secret").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Another client can still log in to the mailbox
+        let response = check_mailbox_password_from(&app, Some(owner), "locked", "secret").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Login throttling tests
+
+    #[tokio::test]
+    async fn test_login_lockout_after_failed_attempts() {
+        let storage = test_storage().await;
+        let config = AuthConfig {
+            login_max_attempts: 3,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage, config);
+
+        register_user(&app, "user@example.com", "password123").await;
+
+        for _ in 0..3 {
+            let response = login_user(&app, "user@example.com", "wrongpassword").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // Even the correct password is refused while locked out
+        let response = login_user(&app, "USER@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: i64 = response
+            .headers()
+            .get(RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 15 * 60);
+
+        // Other accounts are unaffected
+        register_user(&app, "other@example.com", "password123").await;
+        let response = login_user(&app, "other@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_login_lockout_expires_after_window() {
+        let storage = test_storage().await;
+        let config = AuthConfig {
+            login_max_attempts: 3,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage.clone(), config);
+
+        register_user(&app, "user@example.com", "password123").await;
+
+        // Failures older than the window no longer count
+        for _ in 0..3 {
+            storage
+                .record_failed_login("user@example.com", Utc::now() - Duration::minutes(16))
+                .await
+                .unwrap();
+        }
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_successful_login_resets_failed_attempts() {
+        let storage = test_storage().await;
+        let config = AuthConfig {
+            login_max_attempts: 3,
+            ..test_auth_config()
+        };
+        let app = auth_app(storage.clone(), config);
+
+        register_user(&app, "user@example.com", "password123").await;
+
+        for _ in 0..2 {
+            login_user(&app, "user@example.com", "wrongpassword").await;
+        }
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(storage
+            .get_failed_logins_since("user@example.com", Utc::now() - Duration::hours(1))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The counter starts over, so two more failures do not lock the account
+        for _ in 0..2 {
+            login_user(&app, "user@example.com", "wrongpassword").await;
+        }
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_first_registered_user_is_admin() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage.clone(), config);
+
+        register_user(&app, "first@example.com", "password123").await;
+        register_user(&app, "second@example.com", "password123").await;
+
+        let first = storage
+            .get_user_by_email("first@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        let second = storage
+            .get_user_by_email("second@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(first.is_admin);
+        assert!(!second.is_admin);
+    }
+
+    // Password change tests
+
+    async fn post_change_password(
+        app: &Router,
+        token: &str,
+        current_password: &str,
+        new_password: &str,
+    ) -> axum::http::Response<Body> {
+        let body = serde_json::json!({
+            "current_password": current_password,
+            "new_password": new_password,
+        });
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/auth/change-password")
+                    .header("content-type", "application/json")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_change_password_success() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage, config);
+
+        let json = body_json(register_user(&app, "user@example.com", "password123").await).await;
+        let token = json["token"].as_str().unwrap().to_string();
+        let old_refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+        let response = post_change_password(&app, &token, "password123", "new-password").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = body_json(response).await;
+        let new_refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+        // Only the new password logs in
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = login_user(&app, "user@example.com", "new-password").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Refresh tokens issued before the change are revoked
+        let response = post_refresh_token(&app, "/api/auth/refresh", &old_refresh_token).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = post_refresh_token(&app, "/api/auth/refresh", &new_refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_wrong_current_password() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage, config);
+
+        let json = body_json(register_user(&app, "user@example.com", "password123").await).await;
+        let token = json["token"].as_str().unwrap().to_string();
+        let refresh_token = json["refresh_token"].as_str().unwrap().to_string();
+
+        let response = post_change_password(&app, &token, "wrong-password", "new-password").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Nothing changed
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = post_refresh_token(&app, "/api/auth/refresh", &refresh_token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_too_short() {
+        let storage = test_storage().await;
+        let config = test_auth_config();
+        let app = auth_app(storage, config);
+
+        let json = body_json(register_user(&app, "user@example.com", "password123").await).await;
+        let token = json["token"].as_str().unwrap().to_string();
+
+        let response = post_change_password(&app, &token, "password123", "short").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = login_user(&app, "user@example.com", "password123").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A token is required
+        let response = post_change_password(&app, "invalid", "password123", "new-password").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,99 @@
+//! Password hashing for user accounts (`PASSWORD_HASH`)
+
+use anyhow::{anyhow, bail, Result};
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+
+/// Algorithm new password hashes are made with
+///
+/// Verification works out the algorithm from the stored hash, so switching algorithms
+/// keeps existing passwords working and hashes them anew only when they are changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PasswordHasher {
+    #[default]
+    Bcrypt,
+    /// Argon2id with the crate's default parameters
+    Argon2,
+}
+
+impl std::str::FromStr for PasswordHasher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bcrypt" => Ok(PasswordHasher::Bcrypt),
+            "argon2" => Ok(PasswordHasher::Argon2),
+            other => bail!(
+                "Invalid PASSWORD_HASH '{}', expected 'bcrypt' or 'argon2'",
+                other
+            ),
+        }
+    }
+}
+
+impl PasswordHasher {
+    /// Hash a password with this algorithm
+    pub fn hash(&self, password: &str) -> Result<String> {
+        match self {
+            PasswordHasher::Bcrypt => Ok(bcrypt::hash(password, bcrypt::DEFAULT_COST)?),
+            PasswordHasher::Argon2 => {
+                let salt = SaltString::generate(&mut OsRng);
+                let hash = Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(|e| anyhow!("Argon2 hashing failed: {}", e))?;
+                Ok(hash.to_string())
+            }
+        }
+    }
+
+    /// Check a password against a stored hash of either algorithm
+    pub fn verify(password: &str, hash: &str) -> Result<bool> {
+        if hash.starts_with("$argon2") {
+            let parsed =
+                PasswordHash::new(hash).map_err(|e| anyhow!("Invalid Argon2 hash: {}", e))?;
+            Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok())
+        } else {
+            Ok(bcrypt::verify(password, hash)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_argon2_hash_verifies() {
+        let hash = PasswordHasher::Argon2.hash("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(PasswordHasher::verify("correct horse", &hash).unwrap());
+        assert!(!PasswordHasher::verify("wrong horse", &hash).unwrap());
+    }
+
+    #[test]
+    fn test_bcrypt_hash_still_verifies_in_argon2_mode() {
+        // A hash stored before switching to Argon2
+        let hash = bcrypt::hash("correct horse", 4).unwrap();
+        let hasher: PasswordHasher = "argon2".parse().unwrap();
+        assert_eq!(hasher, PasswordHasher::Argon2);
+        assert!(PasswordHasher::verify("correct horse", &hash).unwrap());
+        assert!(!PasswordHasher::verify("wrong horse", &hash).unwrap());
+    }
+
+    #[test]
+    fn test_parse_password_hasher() {
+        assert_eq!(
+            " Bcrypt ".parse::<PasswordHasher>().unwrap(),
+            PasswordHasher::Bcrypt
+        );
+        assert!("scrypt"
+            .parse::<PasswordHasher>()
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid PASSWORD_HASH"));
+    }
+}
@@ -0,0 +1,67 @@
+//! Build metadata embedded at compile time
+//!
+//! The package version comes from Cargo, and the git commit and build time are injected by
+//! `build.rs`.
+
+use serde::Serialize;
+
+/// Package version from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit hash the binary was built from ("unknown" outside a git checkout)
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT_HASH");
+
+/// Unix time in seconds the build script last ran, or `SOURCE_DATE_EPOCH` when set
+pub const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Version string shown by `--version`
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT_HASH"),
+    ")"
+);
+
+/// Information about the running build
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    /// RFC 3339 time of the build
+    pub built_at: String,
+}
+
+/// Get information about the running build
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: VERSION,
+        git_commit: GIT_COMMIT,
+        built_at: BUILD_TIMESTAMP
+            .parse()
+            .ok()
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+            .unwrap_or_default()
+            .to_rfc3339(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.name, "dynip-email");
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_commit.is_empty());
+        let built_at = chrono::DateTime::parse_from_rfc3339(&info.built_at).unwrap();
+        assert_eq!(built_at.timestamp().to_string(), BUILD_TIMESTAMP);
+        assert_eq!(
+            LONG_VERSION,
+            format!("{} ({})", info.version, info.git_commit)
+        );
+    }
+}
@@ -1,5 +1,9 @@
-use anyhow::{bail, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::auth::PasswordHasher;
 
 /// Type alias for SSL certificate data (certificates, private_key)
 pub type SslCertificates = (Vec<Vec<u8>>, Vec<u8>);
@@ -7,23 +11,85 @@ pub type SslCertificates = (Vec<Vec<u8>>, Vec<u8>);
 /// Application configuration
 #[derive(Debug, Clone)]
 pub struct Config {
+    /// Address the SMTP, IMAP, API and MCP servers listen on (`BIND_ADDRESS`)
+    pub bind_address: IpAddr,
     pub smtp_port: u16,
     pub smtp_starttls_port: u16, // Port 587 for STARTTLS (explicit TLS)
     pub smtp_ssl_port: u16,      // Port 465 for SMTPS (implicit TLS)
+    pub smtp_max_message_bytes: usize,
+    /// Messages accepted per source IP per hour, unlimited when unset
+    pub smtp_max_messages_per_ip_per_hour: Option<u32>,
+    /// Check the sender's SPF record for inbound mail (`SMTP_SPF_ENABLED`)
+    pub smtp_spf_enabled: bool,
+    /// Refuse mail whose SPF check is a hard `fail` (`SMTP_REJECT_SPF_FAIL`)
+    pub smtp_reject_spf_fail: bool,
+    /// Verify DKIM signatures on inbound mail (`SMTP_DKIM_ENABLED`)
+    pub smtp_dkim_enabled: bool,
+    /// Refuse mail whose DKIM verification is a `fail` (`SMTP_REJECT_DKIM_FAIL`)
+    pub smtp_reject_dkim_fail: bool,
+    /// clamd `host:port` that attachments are scanned with (`CLAMAV_ADDRESS`)
+    pub clamav_address: Option<String>,
+    /// Refuse mail with an attachment clamd flags as infected (`SMTP_REJECT_INFECTED`)
+    pub smtp_reject_infected: bool,
+    /// Strip scripts and remote resources from HTML bodies before storage (`SANITIZE_HTML`)
+    pub sanitize_html: bool,
+    /// Read a PROXY protocol header from every SMTP connection to learn the real client
+    /// address (`SMTP_PROXY_PROTOCOL`)
+    pub smtp_proxy_protocol: bool,
+    /// Recipient domains accepted besides the configured ones (`SMTP_ACCEPT_POLICY`)
+    pub smtp_accept_policy: AcceptPolicy,
     pub api_port: u16,
+    /// API requests accepted per client IP per minute, unlimited when unset
+    pub api_max_requests_per_minute_per_ip: Option<u32>,
+    /// Seconds between pings sent to WebSocket clients (`WS_PING_INTERVAL_SECS`)
+    pub ws_ping_interval_secs: u64,
+    /// Webhook requests allowed in flight at once (`WEBHOOK_MAX_CONCURRENT`)
+    pub webhook_max_concurrent: usize,
+    /// Updates buffered for each subscriber of the email and deletion broadcast channels
+    /// (`BROADCAST_CHANNEL_CAPACITY`)
+    pub broadcast_channel_capacity: usize,
     pub database_url: String,
+    /// Size of the database connection pool (`DATABASE_MAX_CONNECTIONS`)
+    pub database_max_connections: u32,
+    /// Cache hot mailbox and email reads in memory (`STORAGE_CACHE_ENABLED`)
+    pub storage_cache_enabled: bool,
+    /// Where attachment content is kept (`ATTACHMENT_STORE`)
+    pub attachment_store: AttachmentStoreMode,
+    /// Directory of filesystem attachments (`ATTACHMENT_DIR`)
+    pub attachment_dir: PathBuf,
     pub smtp_ssl: SmtpSslConfig,
     pub domain_name: String,
+    /// Hostname announced in the SMTP greeting (`SMTP_HOSTNAME`), `domain_name` by default
+    pub smtp_hostname: String,
+    /// Further domains accepted alongside `domain_name` (`ADDITIONAL_DOMAINS`)
+    pub additional_domains: Vec<String>,
     pub email_retention_hours: Option<i64>,
+    /// Hours a deleted email stays in the trash before it is purged
+    pub trash_retention_hours: i64,
     pub reject_non_domain_emails: bool,
+    /// Address that receives mail for unknown mailboxes (`CATCH_ALL_MAILBOX`)
+    pub catch_all_mailbox: Option<String>,
+    pub mailbox_quota: MailboxQuotaConfig,
+    /// Per-email attachment limits
+    pub attachment_limits: AttachmentLimitsConfig,
     pub mcp_enabled: bool,
     pub mcp_port: u16,
+    pub mcp_transport: McpTransport,
     pub imap_enabled: bool,
     pub imap_port: u16,
+    pub imap_ssl_port: u16, // Port 993 for IMAPS (implicit TLS)
+    pub imap_ssl: SmtpSslConfig,
+    /// Refuse IMAP logins until the connection uses TLS (`IMAP_REQUIRE_TLS`)
+    pub imap_require_tls: bool,
     pub auth_enabled: bool,
     pub jwt_secret: String,
     pub jwt_expiry_hours: u64,
+    pub refresh_token_expiry_days: u64,
+    pub login_max_attempts: u32,
+    pub login_window_minutes: u64,
     pub auth_domains: Option<Vec<String>>,
+    /// Algorithm new user passwords are hashed with (`PASSWORD_HASH`)
+    pub password_hasher: PasswordHasher,
     // Outbound email configuration
     pub outbound_enabled: bool,
     pub dkim_private_key_path: Option<PathBuf>,
@@ -35,6 +101,289 @@ pub struct Config {
     pub smtp_relay_password: Option<String>,
 }
 
+/// How the MCP server talks to clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McpTransport {
+    /// REST-style endpoints on `MCP_PORT`
+    Http,
+    /// Newline-delimited JSON-RPC 2.0 over stdin/stdout
+    Stdio,
+}
+
+impl std::str::FromStr for McpTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "http" => Ok(McpTransport::Http),
+            "stdio" => Ok(McpTransport::Stdio),
+            other => bail!(
+                "Invalid MCP_TRANSPORT '{}', expected 'http' or 'stdio'",
+                other
+            ),
+        }
+    }
+}
+
+/// Where attachment content is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentStoreMode {
+    /// Base64 content in the database row
+    Inline,
+    /// Decoded files under `ATTACHMENT_DIR`
+    Filesystem,
+}
+
+impl std::str::FromStr for AttachmentStoreMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "inline" => Ok(AttachmentStoreMode::Inline),
+            "filesystem" => Ok(AttachmentStoreMode::Filesystem),
+            other => bail!(
+                "Invalid ATTACHMENT_STORE '{}', expected 'inline' or 'filesystem'",
+                other
+            ),
+        }
+    }
+}
+
+/// Read `CATCH_ALL_MAILBOX`, qualifying a bare local part with the configured domain
+fn catch_all_mailbox(settings: &Settings, domain_name: &str) -> Option<String> {
+    let value = settings.var("CATCH_ALL_MAILBOX").ok()?;
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else if value.contains('@') {
+        Some(value.to_string())
+    } else {
+        Some(format!("{}@{}", value, domain_name))
+    }
+}
+
+/// Parse a comma-separated list of domains, skipping blank entries
+fn domain_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|d| d.trim().to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// The configured spelling of `domain` if it is the primary or an additional domain
+///
+/// Domains compare case-insensitively.
+pub fn configured_domain<'a>(
+    domain: &str,
+    domain_name: &'a str,
+    additional_domains: &'a [String],
+) -> Option<&'a str> {
+    std::iter::once(domain_name)
+        .chain(additional_domains.iter().map(String::as_str))
+        .find(|configured| configured.eq_ignore_ascii_case(domain))
+}
+
+/// Normalize an address for the configured domains
+///
+/// A bare local part gets the primary domain; an address on any configured domain
+/// gets that domain's configured spelling. Other addresses are left as-is.
+pub fn normalize_address(input: &str, domain_name: &str, additional_domains: &[String]) -> String {
+    let input = input.trim();
+    match input.rsplit_once('@') {
+        Some((local_part, domain)) => {
+            match configured_domain(domain, domain_name, additional_domains) {
+                Some(configured) => format!("{}@{}", local_part, configured),
+                None => input.to_string(),
+            }
+        }
+        None => format!("{}@{}", input, domain_name),
+    }
+}
+
+/// Default size of the database connection pool
+pub const DEFAULT_DATABASE_MAX_CONNECTIONS: u32 = 5;
+
+/// Default interval between WebSocket pings
+pub const DEFAULT_WS_PING_INTERVAL_SECS: u64 = 30;
+
+/// Default limit on webhook requests in flight at once
+pub const DEFAULT_WEBHOOK_MAX_CONCURRENT: usize = 10;
+
+/// Default number of updates the broadcast channels buffer for a slow subscriber
+pub const DEFAULT_BROADCAST_CHANNEL_CAPACITY: usize = 100;
+
+/// Default time deleted emails stay in the trash (3 days)
+pub const DEFAULT_TRASH_RETENTION_HOURS: i64 = 72;
+
+/// Default maximum accepted message size (25 MB)
+pub const DEFAULT_SMTP_MAX_MESSAGE_BYTES: usize = 25 * 1024 * 1024;
+
+/// Which recipient domains are accepted when `REJECT_NON_DOMAIN_EMAILS` is set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AcceptPolicy {
+    /// Only `DOMAIN_NAME` and `ADDITIONAL_DOMAINS`
+    #[default]
+    Exact,
+    /// Also any domain whose MX records point at `SMTP_HOSTNAME`
+    Mx,
+}
+
+impl std::str::FromStr for AcceptPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "exact" => Ok(AcceptPolicy::Exact),
+            "mx" => Ok(AcceptPolicy::Mx),
+            other => bail!(
+                "Invalid SMTP_ACCEPT_POLICY '{}', expected 'exact' or 'mx'",
+                other
+            ),
+        }
+    }
+}
+
+/// What to do when a new email would exceed a mailbox quota
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Refuse the message
+    #[default]
+    Reject,
+    /// Delete the mailbox's oldest emails until the new one fits
+    Evict,
+}
+
+impl std::str::FromStr for QuotaPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "reject" => Ok(QuotaPolicy::Reject),
+            "evict" => Ok(QuotaPolicy::Evict),
+            other => bail!(
+                "Invalid QUOTA_POLICY '{}', expected 'reject' or 'evict'",
+                other
+            ),
+        }
+    }
+}
+
+/// Per-mailbox storage limits
+#[derive(Debug, Clone, Default)]
+pub struct MailboxQuotaConfig {
+    pub max_emails: Option<i64>,
+    pub max_bytes: Option<i64>,
+    pub policy: QuotaPolicy,
+}
+
+impl MailboxQuotaConfig {
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.max_emails.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Load quota settings from `MAILBOX_MAX_EMAILS`, `MAILBOX_MAX_BYTES` and `QUOTA_POLICY`
+    fn load(settings: &Settings) -> Result<Self> {
+        let max_emails = settings
+            .var("MAILBOX_MAX_EMAILS")
+            .ok()
+            .map(|s| s.parse::<i64>())
+            .transpose()?
+            .filter(|n| *n > 0);
+        let max_bytes = settings
+            .var("MAILBOX_MAX_BYTES")
+            .ok()
+            .map(|s| s.parse::<i64>())
+            .transpose()?
+            .filter(|n| *n > 0);
+        let policy = settings
+            .var("QUOTA_POLICY")
+            .unwrap_or_else(|_| "reject".to_string())
+            .parse()?;
+
+        Ok(MailboxQuotaConfig {
+            max_emails,
+            max_bytes,
+            policy,
+        })
+    }
+}
+
+/// What to do with an email whose attachments exceed the limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentOverflowPolicy {
+    /// Keep the attachments that fit and drop the content of the rest
+    Truncate,
+    /// Refuse the message
+    Reject,
+}
+
+impl std::str::FromStr for AttachmentOverflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "truncate" => Ok(AttachmentOverflowPolicy::Truncate),
+            "reject" => Ok(AttachmentOverflowPolicy::Reject),
+            other => bail!(
+                "Invalid ATTACHMENT_OVERFLOW_POLICY '{}', expected 'truncate' or 'reject'",
+                other
+            ),
+        }
+    }
+}
+
+/// Limits on the attachments of a single email
+#[derive(Debug, Clone)]
+pub struct AttachmentLimitsConfig {
+    pub max_count: Option<usize>,
+    pub max_total_bytes: Option<usize>,
+    pub policy: AttachmentOverflowPolicy,
+}
+
+impl Default for AttachmentLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_count: None,
+            max_total_bytes: None,
+            policy: AttachmentOverflowPolicy::Truncate,
+        }
+    }
+}
+
+impl AttachmentLimitsConfig {
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.max_count.is_some() || self.max_total_bytes.is_some()
+    }
+
+    /// Load limits from `MAX_ATTACHMENTS_PER_EMAIL`, `MAX_ATTACHMENT_TOTAL_BYTES` and
+    /// `ATTACHMENT_OVERFLOW_POLICY`
+    fn load(settings: &Settings) -> Result<Self> {
+        let max_count = settings
+            .var("MAX_ATTACHMENTS_PER_EMAIL")
+            .ok()
+            .map(|s| s.parse::<usize>())
+            .transpose()?;
+        let max_total_bytes = settings
+            .var("MAX_ATTACHMENT_TOTAL_BYTES")
+            .ok()
+            .map(|s| s.parse::<usize>())
+            .transpose()?;
+        let policy = settings
+            .var("ATTACHMENT_OVERFLOW_POLICY")
+            .unwrap_or_else(|_| "truncate".to_string())
+            .parse()?;
+
+        Ok(AttachmentLimitsConfig {
+            max_count,
+            max_total_bytes,
+            policy,
+        })
+    }
+}
+
 /// SMTP SSL/TLS configuration for Let's Encrypt certificates
 #[derive(Debug, Clone)]
 pub struct SmtpSslConfig {
@@ -43,82 +392,310 @@ pub struct SmtpSslConfig {
     pub key_path: Option<PathBuf>,
 }
 
+impl SmtpSslConfig {
+    /// Load IMAP TLS settings, falling back to the SMTP certificate and key paths
+    fn imap(settings: &Settings) -> Self {
+        let enabled = settings
+            .var("IMAP_SSL_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        if !enabled {
+            return Self {
+                enabled: false,
+                cert_path: None,
+                key_path: None,
+            };
+        }
+
+        let cert_path = settings
+            .var("IMAP_SSL_CERT_PATH")
+            .or_else(|_| settings.var("SMTP_SSL_CERT_PATH"))
+            .map(PathBuf::from)
+            .ok();
+        let key_path = settings
+            .var("IMAP_SSL_KEY_PATH")
+            .or_else(|_| settings.var("SMTP_SSL_KEY_PATH"))
+            .map(PathBuf::from)
+            .ok();
+
+        Self {
+            enabled: true,
+            cert_path,
+            key_path,
+        }
+    }
+}
+
 impl Config {
     /// Load configuration from environment variables
     pub fn from_env() -> Result<Self> {
         // Load .env file if it exists (don't fail if it doesn't)
         let _ = dotenvy::dotenv();
 
+        Self::load(&Settings::env_only())
+    }
+
+    /// Load configuration from a TOML file, with environment variables taking precedence
+    ///
+    /// Keys are the environment variable names in lowercase, optionally grouped into
+    /// tables whose names are joined with `_` (`[smtp_ssl] enabled = true` is
+    /// `SMTP_SSL_ENABLED`). Arrays are joined with commas.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let _ = dotenvy::dotenv();
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let file = parse_config_file(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        Self::load(&Settings {
+            file,
+            env: |name| std::env::var(name).ok(),
+        })
+    }
+
+    /// Build the configuration from one source of settings
+    fn load(settings: &Settings) -> Result<Self> {
+        let bind_address = settings
+            .var("BIND_ADDRESS")
+            .unwrap_or_else(|_| "0.0.0.0".to_string())
+            .trim()
+            .parse::<IpAddr>()
+            .context("Invalid BIND_ADDRESS, expected an IP address such as 0.0.0.0 or 127.0.0.1")?;
+
         // Non-TLS SMTP port (always listening)
-        let smtp_port = std::env::var("SMTP_PORT")
+        let smtp_port = settings
+            .var("SMTP_PORT")
             .unwrap_or_else(|_| "2525".to_string())
             .parse()?;
 
         // STARTTLS port (explicit TLS upgrade on port 587) - only used if SSL enabled
-        let smtp_starttls_port = std::env::var("SMTP_STARTTLS_PORT")
+        let smtp_starttls_port = settings
+            .var("SMTP_STARTTLS_PORT")
             .unwrap_or_else(|_| "587".to_string())
             .parse()?;
 
         // SMTPS port (implicit TLS on port 465) - only used if SSL enabled
-        let smtp_ssl_port = std::env::var("SMTP_SSL_PORT")
+        let smtp_ssl_port = settings
+            .var("SMTP_SSL_PORT")
             .unwrap_or_else(|_| "465".to_string())
             .parse()?;
 
-        let api_port = std::env::var("API_PORT")
+        let smtp_max_message_bytes = settings
+            .var("SMTP_MAX_MESSAGE_BYTES")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_SMTP_MAX_MESSAGE_BYTES);
+
+        let smtp_max_messages_per_ip_per_hour = settings
+            .var("SMTP_MAX_MESSAGES_PER_IP_PER_HOUR")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?;
+
+        let smtp_spf_enabled = settings
+            .var("SMTP_SPF_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let smtp_reject_spf_fail = settings
+            .var("SMTP_REJECT_SPF_FAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let smtp_dkim_enabled = settings
+            .var("SMTP_DKIM_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let smtp_reject_dkim_fail = settings
+            .var("SMTP_REJECT_DKIM_FAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let clamav_address = settings
+            .var("CLAMAV_ADDRESS")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let smtp_reject_infected = settings
+            .var("SMTP_REJECT_INFECTED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let sanitize_html = settings
+            .var("SANITIZE_HTML")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let smtp_proxy_protocol = settings
+            .var("SMTP_PROXY_PROTOCOL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let smtp_accept_policy = settings
+            .var("SMTP_ACCEPT_POLICY")
+            .unwrap_or_else(|_| "exact".to_string())
+            .parse()?;
+
+        let api_port = settings
+            .var("API_PORT")
             .unwrap_or_else(|_| "3000".to_string())
             .parse()?;
 
-        let database_url =
-            std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:emails.db".to_string());
+        let api_max_requests_per_minute_per_ip = settings
+            .var("API_MAX_REQUESTS_PER_MINUTE_PER_IP")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?;
 
-        let domain_name =
-            std::env::var("DOMAIN_NAME").unwrap_or_else(|_| "tempmail.local".to_string());
+        let ws_ping_interval_secs = settings
+            .var("WS_PING_INTERVAL_SECS")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_WS_PING_INTERVAL_SECS);
 
-        let email_retention_hours = std::env::var("EMAIL_RETENTION_HOURS")
+        let webhook_max_concurrent = settings
+            .var("WEBHOOK_MAX_CONCURRENT")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_WEBHOOK_MAX_CONCURRENT);
+
+        let broadcast_channel_capacity = settings
+            .var("BROADCAST_CHANNEL_CAPACITY")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_BROADCAST_CHANNEL_CAPACITY);
+
+        let database_url = settings
+            .var("DATABASE_URL")
+            .unwrap_or_else(|_| "sqlite:emails.db".to_string());
+
+        let database_max_connections = settings
+            .var("DATABASE_MAX_CONNECTIONS")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_DATABASE_MAX_CONNECTIONS);
+
+        let storage_cache_enabled = settings
+            .var("STORAGE_CACHE_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let attachment_store = settings
+            .var("ATTACHMENT_STORE")
+            .unwrap_or_else(|_| "inline".to_string())
+            .parse()?;
+
+        let attachment_dir = PathBuf::from(
+            settings
+                .var("ATTACHMENT_DIR")
+                .unwrap_or_else(|_| "attachments".to_string()),
+        );
+
+        let domain_name = settings
+            .var("DOMAIN_NAME")
+            .unwrap_or_else(|_| "tempmail.local".to_string());
+
+        let smtp_hostname = settings
+            .var("SMTP_HOSTNAME")
+            .unwrap_or_else(|_| domain_name.clone());
+
+        let additional_domains = settings
+            .var("ADDITIONAL_DOMAINS")
+            .map(|s| domain_list(&s))
+            .unwrap_or_default();
+
+        let email_retention_hours = settings
+            .var("EMAIL_RETENTION_HOURS")
             .ok()
             .and_then(|s| s.parse().ok());
 
-        let reject_non_domain_emails = std::env::var("REJECT_NON_DOMAIN_EMAILS")
+        let trash_retention_hours = settings
+            .var("TRASH_RETENTION_HOURS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_HOURS);
+
+        let reject_non_domain_emails = settings
+            .var("REJECT_NON_DOMAIN_EMAILS")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
-        let mcp_enabled = std::env::var("MCP_ENABLED")
+        let catch_all_mailbox = catch_all_mailbox(settings, &domain_name);
+
+        let mailbox_quota = MailboxQuotaConfig::load(settings)?;
+        let attachment_limits = AttachmentLimitsConfig::load(settings)?;
+
+        let mcp_enabled = settings
+            .var("MCP_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
-        let mcp_port = std::env::var("MCP_PORT")
+        let mcp_port = settings
+            .var("MCP_PORT")
             .unwrap_or_else(|_| "3001".to_string())
             .parse()?;
 
-        let imap_enabled = std::env::var("IMAP_ENABLED")
+        let mcp_transport = settings
+            .var("MCP_TRANSPORT")
+            .unwrap_or_else(|_| "http".to_string())
+            .parse()?;
+
+        let imap_enabled = settings
+            .var("IMAP_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
-        let imap_port = std::env::var("IMAP_PORT")
+        let imap_port = settings
+            .var("IMAP_PORT")
             .unwrap_or_else(|_| "143".to_string())
             .parse()?;
 
+        // IMAPS port (implicit TLS) - only used if IMAP SSL enabled
+        let imap_ssl_port = settings
+            .var("IMAP_SSL_PORT")
+            .unwrap_or_else(|_| "993".to_string())
+            .parse()?;
+
+        let imap_ssl = SmtpSslConfig::imap(settings);
+
+        let imap_require_tls = settings
+            .var("IMAP_REQUIRE_TLS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
         // SMTP SSL configuration for Let's Encrypt
-        let smtp_ssl_enabled = std::env::var("SMTP_SSL_ENABLED")
+        let smtp_ssl_enabled = settings
+            .var("SMTP_SSL_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
         let smtp_ssl = if smtp_ssl_enabled {
-            let cert_path = std::env::var("SMTP_SSL_CERT_PATH").map(PathBuf::from).ok();
-            let key_path = std::env::var("SMTP_SSL_KEY_PATH").map(PathBuf::from).ok();
-
-            if cert_path.is_none() || key_path.is_none() {
-                anyhow::bail!("SMTP_SSL_ENABLED is true but SMTP_SSL_CERT_PATH and SMTP_SSL_KEY_PATH must be set");
-            }
-
             SmtpSslConfig {
                 enabled: true,
-                cert_path,
-                key_path,
+                cert_path: settings.var("SMTP_SSL_CERT_PATH").map(PathBuf::from).ok(),
+                key_path: settings.var("SMTP_SSL_KEY_PATH").map(PathBuf::from).ok(),
             }
         } else {
             SmtpSslConfig {
@@ -129,13 +706,14 @@ impl Config {
         };
 
         // User authentication configuration
-        let auth_enabled = std::env::var("AUTH_ENABLED")
+        let auth_enabled = settings
+            .var("AUTH_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
         // JWT secret - generate a random one if not provided (for dev), but warn
-        let jwt_secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| {
+        let jwt_secret = settings.var("JWT_SECRET").unwrap_or_else(|_| {
             if auth_enabled {
                 tracing::warn!(
                     "JWT_SECRET not set but AUTH_ENABLED is true. Using random secret (sessions won't persist across restarts)"
@@ -144,13 +722,33 @@ impl Config {
             uuid::Uuid::new_v4().to_string()
         });
 
-        let jwt_expiry_hours = std::env::var("JWT_EXPIRY_HOURS")
+        let jwt_expiry_hours = settings
+            .var("JWT_EXPIRY_HOURS")
             .unwrap_or_else(|_| "24".to_string())
             .parse::<u64>()
             .unwrap_or(24);
 
+        let refresh_token_expiry_days = settings
+            .var("REFRESH_TOKEN_EXPIRY_DAYS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30);
+
+        let login_max_attempts = settings
+            .var("LOGIN_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .unwrap_or(5);
+
+        let login_window_minutes = settings
+            .var("LOGIN_WINDOW_MINUTES")
+            .unwrap_or_else(|_| "15".to_string())
+            .parse::<u64>()
+            .unwrap_or(15);
+
         // Optional domain restriction for user registration (e.g., "example.com,company.com")
-        let auth_domains = std::env::var("AUTH_DOMAIN")
+        let auth_domains = settings
+            .var("AUTH_DOMAIN")
             .ok()
             .filter(|s| !s.is_empty())
             .map(|domains_str| {
@@ -161,55 +759,89 @@ impl Config {
                     .collect()
             });
 
+        let password_hasher = settings
+            .var("PASSWORD_HASH")
+            .unwrap_or_else(|_| "bcrypt".to_string())
+            .parse()?;
+
         // Outbound email configuration
-        let outbound_enabled = std::env::var("OUTBOUND_ENABLED")
+        let outbound_enabled = settings
+            .var("OUTBOUND_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
             .unwrap_or(false);
 
-        let dkim_private_key_path = std::env::var("DKIM_PRIVATE_KEY_PATH")
+        let dkim_private_key_path = settings
+            .var("DKIM_PRIVATE_KEY_PATH")
             .ok()
             .map(PathBuf::from);
 
-        let dkim_selector =
-            std::env::var("DKIM_SELECTOR").unwrap_or_else(|_| "default".to_string());
+        let dkim_selector = settings
+            .var("DKIM_SELECTOR")
+            .unwrap_or_else(|_| "default".to_string());
 
-        let dkim_domain = std::env::var("DKIM_DOMAIN").ok();
+        let dkim_domain = settings.var("DKIM_DOMAIN").ok();
 
-        let smtp_relay_host = std::env::var("SMTP_RELAY_HOST").ok();
-        let smtp_relay_port = std::env::var("SMTP_RELAY_PORT")
+        let smtp_relay_host = settings.var("SMTP_RELAY_HOST").ok();
+        let smtp_relay_port = settings
+            .var("SMTP_RELAY_PORT")
             .ok()
             .and_then(|s| s.parse().ok());
-        let smtp_relay_username = std::env::var("SMTP_RELAY_USERNAME").ok();
-        let smtp_relay_password = std::env::var("SMTP_RELAY_PASSWORD").ok();
+        let smtp_relay_username = settings.var("SMTP_RELAY_USERNAME").ok();
+        let smtp_relay_password = settings.var("SMTP_RELAY_PASSWORD").ok();
 
-        if outbound_enabled {
-            if dkim_private_key_path.is_none() {
-                bail!("OUTBOUND_ENABLED is true but DKIM_PRIVATE_KEY_PATH must be set");
-            }
-            if !auth_enabled {
-                bail!("OUTBOUND_ENABLED requires AUTH_ENABLED=true to prevent open relay");
-            }
-        }
-
-        Ok(Config {
+        let config = Config {
+            bind_address,
             smtp_port,
             smtp_starttls_port,
             smtp_ssl_port,
+            smtp_max_message_bytes,
+            smtp_max_messages_per_ip_per_hour,
+            smtp_spf_enabled,
+            smtp_reject_spf_fail,
+            smtp_dkim_enabled,
+            smtp_reject_dkim_fail,
+            clamav_address,
+            smtp_reject_infected,
+            sanitize_html,
+            smtp_proxy_protocol,
+            smtp_accept_policy,
             api_port,
+            api_max_requests_per_minute_per_ip,
+            ws_ping_interval_secs,
+            webhook_max_concurrent,
+            broadcast_channel_capacity,
             database_url,
+            database_max_connections,
+            storage_cache_enabled,
+            attachment_store,
+            attachment_dir,
             smtp_ssl,
             domain_name,
+            smtp_hostname,
+            additional_domains,
             email_retention_hours,
+            trash_retention_hours,
             reject_non_domain_emails,
+            catch_all_mailbox,
+            mailbox_quota,
+            attachment_limits,
             mcp_enabled,
             mcp_port,
+            mcp_transport,
             imap_enabled,
             imap_port,
+            imap_ssl_port,
+            imap_ssl,
+            imap_require_tls,
             auth_enabled,
             jwt_secret,
             jwt_expiry_hours,
+            refresh_token_expiry_days,
+            login_max_attempts,
+            login_window_minutes,
             auth_domains,
+            password_hasher,
             outbound_enabled,
             dkim_private_key_path,
             dkim_selector,
@@ -218,7 +850,213 @@ impl Config {
             smtp_relay_port,
             smtp_relay_username,
             smtp_relay_password,
-        })
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check combinations of settings that are required together or conflict
+    pub fn validate(&self) -> Result<()> {
+        if self.smtp_ssl.enabled
+            && (self.smtp_ssl.cert_path.is_none() || self.smtp_ssl.key_path.is_none())
+        {
+            bail!(
+                "SMTP_SSL_ENABLED is true but SMTP_SSL_CERT_PATH and SMTP_SSL_KEY_PATH must be set"
+            );
+        }
+
+        if self.imap_ssl.enabled
+            && (self.imap_ssl.cert_path.is_none() || self.imap_ssl.key_path.is_none())
+        {
+            bail!("IMAP_SSL_ENABLED is true but IMAP_SSL_CERT_PATH and IMAP_SSL_KEY_PATH (or the SMTP_SSL_* equivalents) must be set");
+        }
+
+        if self.imap_require_tls && !self.imap_ssl.enabled {
+            bail!("IMAP_REQUIRE_TLS is true but IMAP_SSL_ENABLED is not, so no IMAP client could log in");
+        }
+
+        if self.database_max_connections == 0 {
+            bail!("DATABASE_MAX_CONNECTIONS must be at least 1");
+        }
+
+        if self.ws_ping_interval_secs == 0 {
+            bail!("WS_PING_INTERVAL_SECS must be at least 1");
+        }
+
+        if self.webhook_max_concurrent == 0 {
+            bail!("WEBHOOK_MAX_CONCURRENT must be at least 1");
+        }
+
+        if self.broadcast_channel_capacity == 0 {
+            bail!("BROADCAST_CHANNEL_CAPACITY must be at least 1");
+        }
+
+        if self.outbound_enabled {
+            if self.dkim_private_key_path.is_none() {
+                bail!("OUTBOUND_ENABLED is true but DKIM_PRIVATE_KEY_PATH must be set");
+            }
+            if !self.auth_enabled {
+                bail!("OUTBOUND_ENABLED requires AUTH_ENABLED=true to prevent open relay");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where configuration values come from: the environment first, then an optional file
+struct Settings {
+    /// Values from a config file, keyed by environment variable name
+    file: HashMap<String, String>,
+    env: fn(&str) -> Option<String>,
+}
+
+impl Settings {
+    fn env_only() -> Self {
+        Self {
+            file: HashMap::new(),
+            env: |name| std::env::var(name).ok(),
+        }
+    }
+
+    /// Look up a setting, with the same error as `std::env::var` when it is unset
+    fn var(&self, name: &str) -> Result<String, std::env::VarError> {
+        (self.env)(name)
+            .or_else(|| self.file.get(name).cloned())
+            .ok_or(std::env::VarError::NotPresent)
+    }
+}
+
+/// Every setting `Config::load` reads, so typos in a config file are caught
+/// instead of silently falling back to defaults
+const KNOWN_SETTINGS: &[&str] = &[
+    "ADDITIONAL_DOMAINS",
+    "API_MAX_REQUESTS_PER_MINUTE_PER_IP",
+    "API_PORT",
+    "ATTACHMENT_DIR",
+    "ATTACHMENT_OVERFLOW_POLICY",
+    "ATTACHMENT_STORE",
+    "AUTH_DOMAIN",
+    "AUTH_ENABLED",
+    "BIND_ADDRESS",
+    "BROADCAST_CHANNEL_CAPACITY",
+    "CATCH_ALL_MAILBOX",
+    "CLAMAV_ADDRESS",
+    "DATABASE_MAX_CONNECTIONS",
+    "DATABASE_URL",
+    "DKIM_DOMAIN",
+    "DKIM_PRIVATE_KEY_PATH",
+    "DKIM_SELECTOR",
+    "DOMAIN_NAME",
+    "EMAIL_RETENTION_HOURS",
+    "IMAP_ENABLED",
+    "IMAP_PORT",
+    "IMAP_REQUIRE_TLS",
+    "IMAP_SSL_CERT_PATH",
+    "IMAP_SSL_ENABLED",
+    "IMAP_SSL_KEY_PATH",
+    "IMAP_SSL_PORT",
+    "JWT_EXPIRY_HOURS",
+    "JWT_SECRET",
+    "LOGIN_MAX_ATTEMPTS",
+    "LOGIN_WINDOW_MINUTES",
+    "MAILBOX_MAX_BYTES",
+    "MAILBOX_MAX_EMAILS",
+    "MAX_ATTACHMENTS_PER_EMAIL",
+    "MAX_ATTACHMENT_TOTAL_BYTES",
+    "MCP_ENABLED",
+    "MCP_PORT",
+    "MCP_TRANSPORT",
+    "OUTBOUND_ENABLED",
+    "PASSWORD_HASH",
+    "QUOTA_POLICY",
+    "REFRESH_TOKEN_EXPIRY_DAYS",
+    "REJECT_NON_DOMAIN_EMAILS",
+    "SANITIZE_HTML",
+    "SMTP_ACCEPT_POLICY",
+    "SMTP_DKIM_ENABLED",
+    "SMTP_HOSTNAME",
+    "SMTP_MAX_MESSAGES_PER_IP_PER_HOUR",
+    "SMTP_MAX_MESSAGE_BYTES",
+    "SMTP_PORT",
+    "SMTP_PROXY_PROTOCOL",
+    "SMTP_REJECT_DKIM_FAIL",
+    "SMTP_REJECT_INFECTED",
+    "SMTP_REJECT_SPF_FAIL",
+    "SMTP_RELAY_HOST",
+    "SMTP_RELAY_PASSWORD",
+    "SMTP_RELAY_PORT",
+    "SMTP_RELAY_USERNAME",
+    "SMTP_SPF_ENABLED",
+    "SMTP_SSL_CERT_PATH",
+    "SMTP_SSL_ENABLED",
+    "SMTP_SSL_KEY_PATH",
+    "SMTP_SSL_PORT",
+    "SMTP_STARTTLS_PORT",
+    "STORAGE_CACHE_ENABLED",
+    "TRASH_RETENTION_HOURS",
+    "WEBHOOK_MAX_CONCURRENT",
+    "WS_PING_INTERVAL_SECS",
+];
+
+/// Flatten a TOML config file into values keyed by environment variable name
+fn parse_config_file(contents: &str) -> Result<HashMap<String, String>> {
+    let table: toml::Table = toml::from_str(contents)?;
+    let mut values = HashMap::new();
+    flatten_toml(&table, "", &mut values)?;
+
+    let mut unknown: Vec<_> = values
+        .keys()
+        .filter(|name| !KNOWN_SETTINGS.contains(&name.as_str()))
+        .map(|name| name.to_lowercase())
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        bail!("Unknown config file key(s): {}", unknown.join(", "));
+    }
+
+    Ok(values)
+}
+
+fn flatten_toml(
+    table: &toml::Table,
+    prefix: &str,
+    values: &mut HashMap<String, String>,
+) -> Result<()> {
+    for (key, value) in table {
+        let name = format!("{}{}", prefix, key.to_uppercase());
+        match value {
+            toml::Value::Table(nested) => flatten_toml(nested, &format!("{}_", name), values)?,
+            toml::Value::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(toml_scalar)
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Invalid value for {}", name))?;
+                values.insert(name, items.join(","));
+            }
+            scalar => {
+                let value =
+                    toml_scalar(scalar).with_context(|| format!("Invalid value for {}", name))?;
+                values.insert(name, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The string an environment variable would hold for a TOML value
+fn toml_scalar(value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => {
+            bail!("nested arrays and tables are not supported here")
+        }
     }
 }
 
@@ -264,6 +1102,12 @@ mod tests {
     /// Load configuration from environment variables without loading .env file
     /// This is used for tests to avoid interference from .env files
     fn from_env_test() -> Result<Config> {
+        let bind_address = std::env::var("BIND_ADDRESS")
+            .unwrap_or_else(|_| "0.0.0.0".to_string())
+            .trim()
+            .parse::<IpAddr>()
+            .context("Invalid BIND_ADDRESS, expected an IP address such as 0.0.0.0 or 127.0.0.1")?;
+
         // Non-TLS SMTP port (always listening)
         let smtp_port = std::env::var("SMTP_PORT")
             .unwrap_or_else(|_| "2525".to_string())
@@ -280,25 +1124,135 @@ mod tests {
             .parse()?;
 
         // API port
+        let smtp_max_message_bytes = std::env::var("SMTP_MAX_MESSAGE_BYTES")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_SMTP_MAX_MESSAGE_BYTES);
+
+        let smtp_max_messages_per_ip_per_hour = std::env::var("SMTP_MAX_MESSAGES_PER_IP_PER_HOUR")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?;
+
+        let smtp_spf_enabled = std::env::var("SMTP_SPF_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let smtp_reject_spf_fail = std::env::var("SMTP_REJECT_SPF_FAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let smtp_dkim_enabled = std::env::var("SMTP_DKIM_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let smtp_reject_dkim_fail = std::env::var("SMTP_REJECT_DKIM_FAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let clamav_address = std::env::var("CLAMAV_ADDRESS")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let smtp_reject_infected = std::env::var("SMTP_REJECT_INFECTED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let sanitize_html = std::env::var("SANITIZE_HTML")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let smtp_proxy_protocol = std::env::var("SMTP_PROXY_PROTOCOL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let smtp_accept_policy = std::env::var("SMTP_ACCEPT_POLICY")
+            .unwrap_or_else(|_| "exact".to_string())
+            .parse()?;
+
         let api_port = std::env::var("API_PORT")
             .unwrap_or_else(|_| "3000".to_string())
             .parse()?;
 
+        let api_max_requests_per_minute_per_ip =
+            std::env::var("API_MAX_REQUESTS_PER_MINUTE_PER_IP")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?;
+
+        let ws_ping_interval_secs = std::env::var("WS_PING_INTERVAL_SECS")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_WS_PING_INTERVAL_SECS);
+
+        let webhook_max_concurrent = std::env::var("WEBHOOK_MAX_CONCURRENT")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_WEBHOOK_MAX_CONCURRENT);
+
+        let broadcast_channel_capacity = std::env::var("BROADCAST_CHANNEL_CAPACITY")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_BROADCAST_CHANNEL_CAPACITY);
+
         let database_url =
             std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:emails.db".to_string());
 
+        let database_max_connections = std::env::var("DATABASE_MAX_CONNECTIONS")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or(DEFAULT_DATABASE_MAX_CONNECTIONS);
+
+        let storage_cache_enabled = std::env::var("STORAGE_CACHE_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false);
+
+        let attachment_store = std::env::var("ATTACHMENT_STORE")
+            .unwrap_or_else(|_| "inline".to_string())
+            .parse()?;
+
+        let attachment_dir = PathBuf::from(
+            std::env::var("ATTACHMENT_DIR").unwrap_or_else(|_| "attachments".to_string()),
+        );
+
         let domain_name =
             std::env::var("DOMAIN_NAME").unwrap_or_else(|_| "tempmail.local".to_string());
 
+        let smtp_hostname = std::env::var("SMTP_HOSTNAME").unwrap_or_else(|_| domain_name.clone());
+
+        let additional_domains = std::env::var("ADDITIONAL_DOMAINS")
+            .map(|s| domain_list(&s))
+            .unwrap_or_default();
+
         let email_retention_hours = std::env::var("EMAIL_RETENTION_HOURS")
             .ok()
             .and_then(|s| s.parse().ok());
 
+        let trash_retention_hours = std::env::var("TRASH_RETENTION_HOURS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_HOURS);
+
         let reject_non_domain_emails = std::env::var("REJECT_NON_DOMAIN_EMAILS")
             .unwrap_or_else(|_| "false".to_string())
             .parse()
             .unwrap_or(false);
 
+        let catch_all_mailbox = catch_all_mailbox(&Settings::env_only(), &domain_name);
+
         let smtp_ssl_enabled = std::env::var("SMTP_SSL_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
@@ -325,6 +1279,9 @@ mod tests {
             }
         };
 
+        let mailbox_quota = MailboxQuotaConfig::load(&Settings::env_only())?;
+        let attachment_limits = AttachmentLimitsConfig::load(&Settings::env_only())?;
+
         let mcp_enabled = std::env::var("MCP_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
@@ -335,6 +1292,10 @@ mod tests {
             .parse()
             .unwrap_or(3001);
 
+        let mcp_transport = std::env::var("MCP_TRANSPORT")
+            .unwrap_or_else(|_| "http".to_string())
+            .parse()?;
+
         let imap_enabled = std::env::var("IMAP_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
@@ -345,6 +1306,18 @@ mod tests {
             .parse()
             .unwrap_or(143);
 
+        let imap_ssl_port = std::env::var("IMAP_SSL_PORT")
+            .unwrap_or_else(|_| "993".to_string())
+            .parse()
+            .unwrap_or(993);
+
+        let imap_ssl = SmtpSslConfig::imap(&Settings::env_only());
+
+        let imap_require_tls = std::env::var("IMAP_REQUIRE_TLS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
         let auth_enabled = std::env::var("AUTH_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
@@ -357,6 +1330,21 @@ mod tests {
             .parse::<u64>()
             .unwrap_or(24);
 
+        let refresh_token_expiry_days = std::env::var("REFRESH_TOKEN_EXPIRY_DAYS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30);
+
+        let login_max_attempts = std::env::var("LOGIN_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .unwrap_or(5);
+
+        let login_window_minutes = std::env::var("LOGIN_WINDOW_MINUTES")
+            .unwrap_or_else(|_| "15".to_string())
+            .parse::<u64>()
+            .unwrap_or(15);
+
         let auth_domains = std::env::var("AUTH_DOMAIN")
             .ok()
             .filter(|s| !s.is_empty())
@@ -368,24 +1356,62 @@ mod tests {
                     .collect()
             });
 
-        Ok(Config {
+        let password_hasher = std::env::var("PASSWORD_HASH")
+            .unwrap_or_else(|_| "bcrypt".to_string())
+            .parse()?;
+
+        let config = Config {
+            bind_address,
             smtp_port,
             smtp_starttls_port,
             smtp_ssl_port,
+            smtp_max_message_bytes,
+            smtp_max_messages_per_ip_per_hour,
+            smtp_spf_enabled,
+            smtp_reject_spf_fail,
+            smtp_dkim_enabled,
+            smtp_reject_dkim_fail,
+            clamav_address,
+            smtp_reject_infected,
+            sanitize_html,
+            smtp_proxy_protocol,
+            smtp_accept_policy,
             api_port,
+            api_max_requests_per_minute_per_ip,
+            ws_ping_interval_secs,
+            webhook_max_concurrent,
+            broadcast_channel_capacity,
             database_url,
+            database_max_connections,
+            storage_cache_enabled,
+            attachment_store,
+            attachment_dir,
             domain_name,
+            smtp_hostname,
+            additional_domains,
             email_retention_hours,
+            trash_retention_hours,
             reject_non_domain_emails,
+            catch_all_mailbox,
             smtp_ssl,
+            mailbox_quota,
+            attachment_limits,
             mcp_enabled,
             mcp_port,
+            mcp_transport,
             imap_enabled,
             imap_port,
+            imap_ssl_port,
+            imap_ssl,
+            imap_require_tls,
             auth_enabled,
             jwt_secret,
             jwt_expiry_hours,
+            refresh_token_expiry_days,
+            login_max_attempts,
+            login_window_minutes,
             auth_domains,
+            password_hasher,
             outbound_enabled: false,
             dkim_private_key_path: None,
             dkim_selector: "default".to_string(),
@@ -394,30 +1420,73 @@ mod tests {
             smtp_relay_port: None,
             smtp_relay_username: None,
             smtp_relay_password: None,
-        })
+        };
+        config.validate()?;
+
+        Ok(config)
     }
 
     fn clear_all_env_vars() {
         // Clear all environment variables that might interfere with tests
+        env::remove_var("BIND_ADDRESS");
         env::remove_var("SMTP_PORT");
         env::remove_var("SMTP_STARTTLS_PORT");
         env::remove_var("SMTP_SSL_PORT");
+        env::remove_var("SMTP_MAX_MESSAGE_BYTES");
+        env::remove_var("SMTP_MAX_MESSAGES_PER_IP_PER_HOUR");
+        env::remove_var("SMTP_SPF_ENABLED");
+        env::remove_var("SMTP_REJECT_SPF_FAIL");
+        env::remove_var("SMTP_DKIM_ENABLED");
+        env::remove_var("SMTP_REJECT_DKIM_FAIL");
+        env::remove_var("CLAMAV_ADDRESS");
+        env::remove_var("SMTP_REJECT_INFECTED");
+        env::remove_var("SANITIZE_HTML");
+        env::remove_var("SMTP_PROXY_PROTOCOL");
+        env::remove_var("SMTP_ACCEPT_POLICY");
         env::remove_var("API_PORT");
+        env::remove_var("API_MAX_REQUESTS_PER_MINUTE_PER_IP");
+        env::remove_var("WS_PING_INTERVAL_SECS");
+        env::remove_var("WEBHOOK_MAX_CONCURRENT");
+        env::remove_var("BROADCAST_CHANNEL_CAPACITY");
         env::remove_var("DATABASE_URL");
+        env::remove_var("DATABASE_MAX_CONNECTIONS");
+        env::remove_var("STORAGE_CACHE_ENABLED");
+        env::remove_var("ATTACHMENT_STORE");
+        env::remove_var("ATTACHMENT_DIR");
         env::remove_var("DOMAIN_NAME");
+        env::remove_var("SMTP_HOSTNAME");
+        env::remove_var("ADDITIONAL_DOMAINS");
         env::remove_var("EMAIL_RETENTION_HOURS");
+        env::remove_var("TRASH_RETENTION_HOURS");
         env::remove_var("REJECT_NON_DOMAIN_EMAILS");
+        env::remove_var("CATCH_ALL_MAILBOX");
         env::remove_var("SMTP_SSL_ENABLED");
         env::remove_var("SMTP_SSL_CERT_PATH");
         env::remove_var("SMTP_SSL_KEY_PATH");
+        env::remove_var("MAILBOX_MAX_EMAILS");
+        env::remove_var("MAILBOX_MAX_BYTES");
+        env::remove_var("QUOTA_POLICY");
+        env::remove_var("MAX_ATTACHMENTS_PER_EMAIL");
+        env::remove_var("MAX_ATTACHMENT_TOTAL_BYTES");
+        env::remove_var("ATTACHMENT_OVERFLOW_POLICY");
         env::remove_var("MCP_ENABLED");
         env::remove_var("MCP_PORT");
+        env::remove_var("MCP_TRANSPORT");
         env::remove_var("IMAP_ENABLED");
         env::remove_var("IMAP_PORT");
+        env::remove_var("IMAP_SSL_PORT");
+        env::remove_var("IMAP_SSL_ENABLED");
+        env::remove_var("IMAP_SSL_CERT_PATH");
+        env::remove_var("IMAP_SSL_KEY_PATH");
+        env::remove_var("IMAP_REQUIRE_TLS");
         env::remove_var("AUTH_ENABLED");
         env::remove_var("JWT_SECRET");
         env::remove_var("JWT_EXPIRY_HOURS");
+        env::remove_var("REFRESH_TOKEN_EXPIRY_DAYS");
+        env::remove_var("LOGIN_MAX_ATTEMPTS");
+        env::remove_var("LOGIN_WINDOW_MINUTES");
         env::remove_var("AUTH_DOMAIN");
+        env::remove_var("PASSWORD_HASH");
     }
 
     #[test]
@@ -425,21 +1494,69 @@ mod tests {
         clear_all_env_vars();
         let config = from_env_test().unwrap();
 
+        assert_eq!(config.bind_address, IpAddr::from([0, 0, 0, 0]));
         assert_eq!(config.smtp_port, 2525);
         assert_eq!(config.smtp_starttls_port, 587);
         assert_eq!(config.smtp_ssl_port, 465);
+        assert_eq!(config.smtp_max_message_bytes, 25 * 1024 * 1024);
+        assert_eq!(config.smtp_max_messages_per_ip_per_hour, None);
+        assert!(!config.smtp_spf_enabled);
+        assert!(!config.smtp_reject_spf_fail);
+        assert!(!config.smtp_dkim_enabled);
+        assert!(!config.smtp_reject_dkim_fail);
+        assert_eq!(config.clamav_address, None);
+        assert!(!config.smtp_reject_infected);
+        assert!(!config.sanitize_html);
+        assert!(!config.smtp_proxy_protocol);
+        assert_eq!(config.smtp_accept_policy, AcceptPolicy::Exact);
         assert_eq!(config.api_port, 3000);
+        assert_eq!(config.api_max_requests_per_minute_per_ip, None);
+        assert_eq!(config.ws_ping_interval_secs, DEFAULT_WS_PING_INTERVAL_SECS);
+        assert_eq!(
+            config.webhook_max_concurrent,
+            DEFAULT_WEBHOOK_MAX_CONCURRENT
+        );
+        assert_eq!(
+            config.broadcast_channel_capacity,
+            DEFAULT_BROADCAST_CHANNEL_CAPACITY
+        );
         assert_eq!(config.database_url, "sqlite:emails.db");
+        assert_eq!(
+            config.database_max_connections,
+            DEFAULT_DATABASE_MAX_CONNECTIONS
+        );
+        assert!(!config.storage_cache_enabled);
+        assert_eq!(config.attachment_store, AttachmentStoreMode::Inline);
+        assert_eq!(config.attachment_dir, PathBuf::from("attachments"));
         assert_eq!(config.domain_name, "tempmail.local");
+        assert_eq!(config.smtp_hostname, "tempmail.local");
+        assert!(config.additional_domains.is_empty());
         assert_eq!(config.email_retention_hours, None);
+        assert_eq!(config.trash_retention_hours, 72);
         assert_eq!(config.reject_non_domain_emails, false);
+        assert_eq!(config.catch_all_mailbox, None);
         assert_eq!(config.smtp_ssl.enabled, false);
         assert_eq!(config.mcp_enabled, false);
         assert_eq!(config.mcp_port, 3001);
+        assert_eq!(config.mcp_transport, McpTransport::Http);
+        assert!(!config.mailbox_quota.is_enabled());
+        assert_eq!(config.mailbox_quota.policy, QuotaPolicy::Reject);
+        assert!(!config.attachment_limits.is_enabled());
+        assert_eq!(
+            config.attachment_limits.policy,
+            AttachmentOverflowPolicy::Truncate
+        );
         assert_eq!(config.imap_enabled, false);
         assert_eq!(config.imap_port, 143);
+        assert_eq!(config.imap_ssl_port, 993);
+        assert_eq!(config.imap_ssl.enabled, false);
+        assert!(!config.imap_require_tls);
         assert_eq!(config.auth_enabled, false);
         assert_eq!(config.jwt_expiry_hours, 24);
+        assert_eq!(config.refresh_token_expiry_days, 30);
+        assert_eq!(config.login_max_attempts, 5);
+        assert_eq!(config.login_window_minutes, 15);
+        assert_eq!(config.password_hasher, PasswordHasher::Bcrypt);
 
         // Clean up after test
         clear_all_env_vars();
@@ -449,32 +1566,104 @@ mod tests {
     fn test_config_from_env_custom() {
         // Clear all environment variables first to avoid interference
         clear_all_env_vars();
+        env::set_var("BIND_ADDRESS", "127.0.0.1");
         env::set_var("SMTP_PORT", "2526");
         env::set_var("SMTP_STARTTLS_PORT", "588");
         env::set_var("SMTP_SSL_PORT", "466");
+        env::set_var("SMTP_MAX_MESSAGE_BYTES", "1048576");
+        env::set_var("SMTP_MAX_MESSAGES_PER_IP_PER_HOUR", "50");
+        env::set_var("SMTP_SPF_ENABLED", "true");
+        env::set_var("SMTP_REJECT_SPF_FAIL", "true");
+        env::set_var("SMTP_DKIM_ENABLED", "true");
+        env::set_var("SMTP_REJECT_DKIM_FAIL", "true");
+        env::set_var("CLAMAV_ADDRESS", "127.0.0.1:3310");
+        env::set_var("SMTP_REJECT_INFECTED", "true");
+        env::set_var("SANITIZE_HTML", "true");
+        env::set_var("SMTP_PROXY_PROTOCOL", "true");
+        env::set_var("SMTP_ACCEPT_POLICY", " MX ");
         env::set_var("API_PORT", "3001");
+        env::set_var("API_MAX_REQUESTS_PER_MINUTE_PER_IP", "120");
+        env::set_var("WS_PING_INTERVAL_SECS", "15");
+        env::set_var("WEBHOOK_MAX_CONCURRENT", "4");
+        env::set_var("BROADCAST_CHANNEL_CAPACITY", "500");
         env::set_var("DATABASE_URL", "sqlite:test.db");
+        env::set_var("DATABASE_MAX_CONNECTIONS", "20");
+        env::set_var("STORAGE_CACHE_ENABLED", "true");
+        env::set_var("ATTACHMENT_STORE", "filesystem");
+        env::set_var("ATTACHMENT_DIR", "/var/lib/dynip-email/attachments");
         env::set_var("DOMAIN_NAME", "test.local");
+        env::set_var("SMTP_HOSTNAME", "mx1.test.local");
+        env::set_var("ADDITIONAL_DOMAINS", "Other.local, ,third.local");
         env::set_var("EMAIL_RETENTION_HOURS", "24");
+        env::set_var("TRASH_RETENTION_HOURS", "12");
         env::set_var("REJECT_NON_DOMAIN_EMAILS", "true");
+        env::set_var("CATCH_ALL_MAILBOX", "catchall");
         env::set_var("SMTP_SSL_ENABLED", "true");
         env::set_var("SMTP_SSL_CERT_PATH", "/path/to/cert.pem");
         env::set_var("SMTP_SSL_KEY_PATH", "/path/to/key.pem");
         env::set_var("MCP_ENABLED", "true");
         env::set_var("MCP_PORT", "3002");
+        env::set_var("MCP_TRANSPORT", "stdio");
+        env::set_var("MAILBOX_MAX_EMAILS", "100");
+        env::set_var("MAILBOX_MAX_BYTES", "1048576");
+        env::set_var("QUOTA_POLICY", "evict");
+        env::set_var("MAX_ATTACHMENTS_PER_EMAIL", "5");
+        env::set_var("MAX_ATTACHMENT_TOTAL_BYTES", "10485760");
+        env::set_var("ATTACHMENT_OVERFLOW_POLICY", "reject");
         env::set_var("IMAP_ENABLED", "true");
         env::set_var("IMAP_PORT", "1143");
+        env::set_var("IMAP_SSL_PORT", "1993");
+        env::set_var("IMAP_SSL_ENABLED", "true");
+        env::set_var("IMAP_SSL_KEY_PATH", "/path/to/imap-key.pem");
+        env::set_var("IMAP_REQUIRE_TLS", "true");
+        env::set_var("REFRESH_TOKEN_EXPIRY_DAYS", "7");
+        env::set_var("LOGIN_MAX_ATTEMPTS", "3");
+        env::set_var("LOGIN_WINDOW_MINUTES", "30");
+        env::set_var("PASSWORD_HASH", "argon2");
 
         let config = from_env_test().unwrap();
 
+        assert_eq!(config.bind_address, IpAddr::from([127, 0, 0, 1]));
         assert_eq!(config.smtp_port, 2526);
         assert_eq!(config.smtp_starttls_port, 588);
         assert_eq!(config.smtp_ssl_port, 466);
+        assert_eq!(config.smtp_max_message_bytes, 1048576);
+        assert_eq!(config.smtp_max_messages_per_ip_per_hour, Some(50));
+        assert!(config.smtp_spf_enabled);
+        assert!(config.smtp_reject_spf_fail);
+        assert!(config.smtp_dkim_enabled);
+        assert!(config.smtp_reject_dkim_fail);
+        assert_eq!(config.clamav_address.as_deref(), Some("127.0.0.1:3310"));
+        assert!(config.smtp_reject_infected);
+        assert!(config.sanitize_html);
+        assert!(config.smtp_proxy_protocol);
+        assert_eq!(config.smtp_accept_policy, AcceptPolicy::Mx);
         assert_eq!(config.api_port, 3001);
+        assert_eq!(config.api_max_requests_per_minute_per_ip, Some(120));
+        assert_eq!(config.ws_ping_interval_secs, 15);
+        assert_eq!(config.webhook_max_concurrent, 4);
+        assert_eq!(config.broadcast_channel_capacity, 500);
         assert_eq!(config.database_url, "sqlite:test.db");
+        assert_eq!(config.database_max_connections, 20);
+        assert!(config.storage_cache_enabled);
+        assert_eq!(config.attachment_store, AttachmentStoreMode::Filesystem);
+        assert_eq!(
+            config.attachment_dir,
+            PathBuf::from("/var/lib/dynip-email/attachments")
+        );
         assert_eq!(config.domain_name, "test.local");
+        assert_eq!(config.smtp_hostname, "mx1.test.local");
+        assert_eq!(
+            config.additional_domains,
+            vec!["other.local".to_string(), "third.local".to_string()]
+        );
         assert_eq!(config.email_retention_hours, Some(24));
+        assert_eq!(config.trash_retention_hours, 12);
         assert_eq!(config.reject_non_domain_emails, true);
+        assert_eq!(
+            config.catch_all_mailbox.as_deref(),
+            Some("catchall@test.local")
+        );
         assert_eq!(config.smtp_ssl.enabled, true);
         assert_eq!(
             config.smtp_ssl.cert_path,
@@ -486,8 +1675,34 @@ mod tests {
         );
         assert_eq!(config.mcp_enabled, true);
         assert_eq!(config.mcp_port, 3002);
+        assert_eq!(config.mcp_transport, McpTransport::Stdio);
+        assert_eq!(config.mailbox_quota.max_emails, Some(100));
+        assert_eq!(config.mailbox_quota.max_bytes, Some(1048576));
+        assert_eq!(config.mailbox_quota.policy, QuotaPolicy::Evict);
+        assert_eq!(config.attachment_limits.max_count, Some(5));
+        assert_eq!(config.attachment_limits.max_total_bytes, Some(10485760));
+        assert_eq!(
+            config.attachment_limits.policy,
+            AttachmentOverflowPolicy::Reject
+        );
         assert_eq!(config.imap_enabled, true);
         assert_eq!(config.imap_port, 1143);
+        assert_eq!(config.imap_ssl_port, 1993);
+        assert_eq!(config.imap_ssl.enabled, true);
+        assert!(config.imap_require_tls);
+        // The certificate falls back to the SMTP one when not set for IMAP
+        assert_eq!(
+            config.imap_ssl.cert_path,
+            Some(std::path::PathBuf::from("/path/to/cert.pem"))
+        );
+        assert_eq!(
+            config.imap_ssl.key_path,
+            Some(std::path::PathBuf::from("/path/to/imap-key.pem"))
+        );
+        assert_eq!(config.refresh_token_expiry_days, 7);
+        assert_eq!(config.login_max_attempts, 3);
+        assert_eq!(config.login_window_minutes, 30);
+        assert_eq!(config.password_hasher, PasswordHasher::Argon2);
 
         // Clean up after test
         clear_all_env_vars();
@@ -511,6 +1726,50 @@ mod tests {
         clear_all_env_vars();
     }
 
+    #[test]
+    fn test_config_imap_ssl_enabled_without_cert_paths() {
+        clear_all_env_vars();
+        env::set_var("IMAP_SSL_ENABLED", "true");
+
+        let result = from_env_test();
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("IMAP_SSL_ENABLED is true"));
+
+        // Clean up after test
+        clear_all_env_vars();
+    }
+
+    #[test]
+    fn test_config_imap_require_tls_without_ssl() {
+        let result = Config::load(&Settings {
+            file: HashMap::new(),
+            env: |name| (name == "IMAP_REQUIRE_TLS").then(|| "true".to_string()),
+        });
+        let Err(err) = result else {
+            panic!("Expected IMAP_REQUIRE_TLS without TLS to be rejected");
+        };
+        assert!(err.to_string().contains("IMAP_REQUIRE_TLS is true"));
+    }
+
+    #[test]
+    fn test_config_invalid_mcp_transport() {
+        clear_all_env_vars();
+        env::set_var("MCP_TRANSPORT", "websocket");
+
+        let result = from_env_test();
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid MCP_TRANSPORT"));
+
+        // Clean up after test
+        clear_all_env_vars();
+    }
+
     #[test]
     fn test_config_invalid_port() {
         clear_all_env_vars();
@@ -523,6 +1782,35 @@ mod tests {
         clear_all_env_vars();
     }
 
+    #[test]
+    fn test_config_invalid_bind_address() {
+        clear_all_env_vars();
+        env::set_var("BIND_ADDRESS", "localhost");
+
+        let err = from_env_test().unwrap_err();
+        assert!(err.to_string().contains("Invalid BIND_ADDRESS"));
+
+        // IPv6 addresses are accepted too
+        env::set_var("BIND_ADDRESS", "::1");
+        let config = from_env_test().unwrap();
+        assert_eq!(config.bind_address, "::1".parse::<IpAddr>().unwrap());
+
+        // Clean up after test
+        clear_all_env_vars();
+    }
+
+    #[test]
+    fn test_config_invalid_accept_policy() {
+        clear_all_env_vars();
+        env::set_var("SMTP_ACCEPT_POLICY", "dns");
+
+        let err = from_env_test().unwrap_err();
+        assert!(err.to_string().contains("Invalid SMTP_ACCEPT_POLICY"));
+
+        // Clean up after test
+        clear_all_env_vars();
+    }
+
     #[test]
     fn test_config_invalid_retention_hours() {
         clear_all_env_vars();
@@ -615,4 +1903,156 @@ mod tests {
         let result = ssl_config.load_certificates();
         assert!(result.is_err()); // Expected to fail due to invalid PEM content
     }
+
+    const SAMPLE_TOML: &str = r#"
+domain_name = "mail.example.com"
+smtp_port = 2600
+reject_non_domain_emails = true
+auth_domain = ["example.com", "example.org"]
+
+[smtp_ssl]
+enabled = true
+cert_path = "/etc/ssl/cert.pem"
+key_path = "/etc/ssl/key.pem"
+
+[mailbox]
+max_emails = 100
+"#;
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = Config::load(&Settings {
+            file: parse_config_file(SAMPLE_TOML).unwrap(),
+            env: |_| None,
+        })
+        .unwrap();
+
+        assert_eq!(config.domain_name, "mail.example.com");
+        assert_eq!(config.smtp_port, 2600);
+        assert!(config.reject_non_domain_emails);
+        assert_eq!(
+            config.auth_domains,
+            Some(vec!["example.com".to_string(), "example.org".to_string()])
+        );
+        assert!(config.smtp_ssl.enabled);
+        assert_eq!(
+            config.smtp_ssl.key_path,
+            Some(PathBuf::from("/etc/ssl/key.pem"))
+        );
+        assert_eq!(config.mailbox_quota.max_emails, Some(100));
+        // Unset keys keep their defaults
+        assert_eq!(config.api_port, 3000);
+    }
+
+    #[test]
+    fn test_config_env_overrides_file() {
+        let config = Config::load(&Settings {
+            file: parse_config_file(SAMPLE_TOML).unwrap(),
+            env: |name| match name {
+                "SMTP_PORT" => Some("2700".to_string()),
+                "API_PORT" => Some("3100".to_string()),
+                _ => None,
+            },
+        })
+        .unwrap();
+
+        assert_eq!(config.smtp_port, 2700);
+        assert_eq!(config.api_port, 3100);
+        assert_eq!(config.domain_name, "mail.example.com");
+    }
+
+    #[test]
+    fn test_smtp_hostname_falls_back_to_domain_name() {
+        let config = Config::load(&Settings {
+            file: HashMap::new(),
+            env: |name| (name == "DOMAIN_NAME").then(|| "example.com".to_string()),
+        })
+        .unwrap();
+        assert_eq!(config.domain_name, "example.com");
+        assert_eq!(config.smtp_hostname, "example.com");
+
+        let config = Config::load(&Settings {
+            file: HashMap::new(),
+            env: |name| match name {
+                "DOMAIN_NAME" => Some("example.com".to_string()),
+                "SMTP_HOSTNAME" => Some("mx1.example.com".to_string()),
+                _ => None,
+            },
+        })
+        .unwrap();
+        assert_eq!(config.domain_name, "example.com");
+        assert_eq!(config.smtp_hostname, "mx1.example.com");
+    }
+
+    #[test]
+    fn test_config_file_validation() {
+        // The same checks apply whichever source a setting comes from
+        let result = Config::load(&Settings {
+            file: parse_config_file("[smtp_ssl]\nenabled = true\n").unwrap(),
+            env: |_| None,
+        });
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("SMTP_SSL_CERT_PATH and SMTP_SSL_KEY_PATH must be set"));
+
+        assert!(parse_config_file("smtp_port = [[1]]").is_err());
+        assert!(parse_config_file("not toml").is_err());
+    }
+
+    #[test]
+    fn test_config_file_rejects_unknown_keys() {
+        let err = parse_config_file("smtp_prot = 2600\n[smtp_ssl]\nenable = true\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unknown config file key(s): smtp_prot, smtp_ssl_enable"
+        );
+    }
+
+    #[test]
+    fn test_known_settings_cover_every_lookup() {
+        // Keep KNOWN_SETTINGS in step with the names Config::load reads
+        let source = include_str!("config.rs");
+        for lookup in source.split(".var(\"").skip(1) {
+            let name = &lookup[..lookup.find('"').unwrap()];
+            assert!(
+                KNOWN_SETTINGS.contains(&name),
+                "{} is missing from KNOWN_SETTINGS",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_configured_domains() {
+        let additional = vec!["second.local".to_string()];
+
+        assert_eq!(
+            configured_domain("TEST.local", "test.local", &additional),
+            Some("test.local")
+        );
+        assert_eq!(
+            configured_domain("Second.Local", "test.local", &additional),
+            Some("second.local")
+        );
+        assert_eq!(
+            configured_domain("other.com", "test.local", &additional),
+            None
+        );
+
+        assert_eq!(
+            normalize_address(" user ", "test.local", &additional),
+            "user@test.local"
+        );
+        assert_eq!(
+            normalize_address("user@SECOND.local", "test.local", &additional),
+            "user@second.local"
+        );
+        assert_eq!(
+            normalize_address("user@Other.com", "test.local", &additional),
+            "user@Other.com"
+        );
+    }
 }
@@ -1,3 +1,5 @@
+pub mod verify;
+
 use anyhow::{Context, Result};
 use mail_auth::common::crypto::{RsaKey, Sha256};
 use mail_auth::common::headers::HeaderWriter;
@@ -0,0 +1,172 @@
+//! DKIM signature verification for inbound mail
+
+use anyhow::{Context, Result};
+use mail_auth::{AuthenticatedMessage, DkimResult, MessageAuthenticator, Parameters};
+use mail_auth::{ResolverCache, Txt};
+
+/// Verifies the `DKIM-Signature` headers of received messages, fetching public keys
+/// from DNS
+pub struct DkimVerifier {
+    authenticator: MessageAuthenticator,
+    reject_fail: bool,
+}
+
+impl DkimVerifier {
+    /// Create a verifier using the system's DNS configuration
+    pub fn new(reject_fail: bool) -> Result<Self> {
+        let authenticator =
+            MessageAuthenticator::new_system_conf().context("Failed to create DNS resolver")?;
+        Ok(Self {
+            authenticator,
+            reject_fail,
+        })
+    }
+
+    /// Whether mail with a `fail` result should be refused
+    pub fn reject_fail(&self) -> bool {
+        self.reject_fail
+    }
+
+    /// Verify every signature on a raw message
+    ///
+    /// The message passes when any one signature verifies; otherwise the first signature's
+    /// result is returned, or `None` when the message is unsigned.
+    pub async fn verify(&self, raw: &[u8]) -> DkimResult {
+        self.verify_with_cache::<mail_auth::common::cache::NoCache<String, Txt>>(raw, None)
+            .await
+    }
+
+    async fn verify_with_cache<TXT: ResolverCache<String, Txt>>(
+        &self,
+        raw: &[u8],
+        txt_cache: Option<&TXT>,
+    ) -> DkimResult {
+        let Some(message) = AuthenticatedMessage::parse(raw) else {
+            return DkimResult::None;
+        };
+
+        let outputs = match txt_cache {
+            Some(cache) => {
+                self.authenticator
+                    .verify_dkim(Parameters::new(&message).with_txt_cache(cache))
+                    .await
+            }
+            None => self.authenticator.verify_dkim(&message).await,
+        };
+
+        if outputs
+            .iter()
+            .any(|output| *output.result() == DkimResult::Pass)
+        {
+            return DkimResult::Pass;
+        }
+        outputs
+            .first()
+            .map(|output| output.result().clone())
+            .unwrap_or(DkimResult::None)
+    }
+}
+
+/// Name of a DKIM result as used in `Authentication-Results` headers, and stored on the
+/// email
+pub fn dkim_result_name(result: &DkimResult) -> &'static str {
+    match result {
+        DkimResult::Pass => "pass",
+        DkimResult::Neutral(_) => "neutral",
+        DkimResult::Fail(_) => "fail",
+        DkimResult::PermError(_) => "permerror",
+        DkimResult::TempError(_) => "temperror",
+        DkimResult::None => "none",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mail_auth::common::parse::TxtRecordParser;
+    use mail_auth::common::verify::DomainKey;
+    use std::borrow::Borrow;
+    use std::collections::HashMap;
+    use std::hash::Hash;
+    use std::time::Instant;
+
+    /// Message signed by the `test._domainkey.example.com` key below
+    const SIGNED_MESSAGE: &[u8] = include_bytes!("testdata/signed.eml");
+
+    const SELECTOR_RECORD: &str = "v=DKIM1; k=rsa; p=MIIBCgKCAQEArd5YteH5r6d+GiU8JHcc41gaC1ZHSOrvTZ5mykHGeQiz9iUGCLaTiSbercQHZER+6FnUW0Q2RCd009tFs/iF9doEb6s9zMiN5j5JW3fBMPzldhRbkcd2ZFhidfCCb7YaoB71/KPQ+3Q/D0qsl+ZhoEqIqO+7QNyl8Pi5DYg1X1ZsUWYbWTF0UHNGfWqXlHYPmYv+QXSy5Kcrj/iHD0q04MfQmQs4D/1E5dSIC47bkpSmfEY4JidBv6pChECZelDbCisHOA7PbW3/jctkzi8XkmPdRF+8ji9PvcAJuOaLuo9vFHOPT+p1rogdRKSlS+HLe4my+J+4Zvipbx+WQHw/OwIDAQAB";
+
+    /// Fixed TXT records standing in for DNS
+    struct StaticTxt(HashMap<String, Txt>);
+
+    impl ResolverCache<String, Txt> for StaticTxt {
+        fn get<Q>(&self, name: &Q) -> Option<Txt>
+        where
+            String: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.0.get(name).cloned()
+        }
+
+        fn remove<Q>(&self, _name: &Q) -> Option<Txt>
+        where
+            String: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            None
+        }
+
+        fn insert(&self, _key: String, _value: Txt, _valid_until: Instant) {}
+    }
+
+    fn test_verifier() -> (DkimVerifier, StaticTxt) {
+        let key = DomainKey::parse(SELECTOR_RECORD.as_bytes()).unwrap();
+        let records = StaticTxt(HashMap::from([(
+            "test._domainkey.example.com.".to_string(),
+            Txt::from(key),
+        )]));
+        let verifier = DkimVerifier {
+            authenticator: MessageAuthenticator::new_cloudflare().unwrap(),
+            reject_fail: false,
+        };
+        (verifier, records)
+    }
+
+    #[tokio::test]
+    async fn test_valid_signature_passes() {
+        let (verifier, records) = test_verifier();
+        let result = verifier
+            .verify_with_cache(SIGNED_MESSAGE, Some(&records))
+            .await;
+        assert_eq!(result, DkimResult::Pass);
+        assert_eq!(dkim_result_name(&result), "pass");
+    }
+
+    #[tokio::test]
+    async fn test_tampered_message_does_not_pass() {
+        let (verifier, records) = test_verifier();
+        let original = String::from_utf8_lossy(SIGNED_MESSAGE);
+
+        // A changed signed header breaks the signature itself
+        let tampered = original.replace("Subject: Signed hello", "Subject: Forged hello");
+        let result = verifier
+            .verify_with_cache(tampered.as_bytes(), Some(&records))
+            .await;
+        assert_eq!(dkim_result_name(&result), "fail");
+
+        // A changed body no longer matches the signed body hash
+        let tampered = original.replace("carries a DKIM signature", "carries a forged one");
+        let result = verifier
+            .verify_with_cache(tampered.as_bytes(), Some(&records))
+            .await;
+        assert_ne!(result, DkimResult::Pass);
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_message_is_none() {
+        let (verifier, records) = test_verifier();
+        let unsigned =
+            b"From: alice@example.com\r\nTo: bob@test.local\r\nSubject: Hi\r\n\r\nHello\r\n";
+        let result = verifier.verify_with_cache(unsigned, Some(&records)).await;
+        assert_eq!(result, DkimResult::None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Forwarding of received email to outside addresses through an upstream SMTP relay
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::storage::{
+    models::{Email, ForwardingRule},
+    StorageBackend,
+};
+
+/// Attempts made to relay an email before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each later one
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How far before the newest broadcast email a catch-up reads storage from, since
+/// concurrent deliveries can be broadcast slightly out of timestamp order
+const CATCH_UP_OVERLAP: chrono::Duration = chrono::Duration::minutes(1);
+
+/// IDs of the most recently forwarded emails remembered, so a catch-up doesn't forward
+/// them twice
+const RECENT_IDS: usize = 1024;
+
+/// Relays each received email to the destinations of its mailbox's forwarding rules
+///
+/// The stored copy is never affected: forwarding happens alongside storage, and a relay
+/// that keeps failing is only logged.
+#[derive(Clone)]
+pub struct EmailForwarder {
+    storage: Arc<dyn StorageBackend>,
+    retry_delay: Duration,
+}
+
+impl EmailForwarder {
+    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            storage,
+            retry_delay: INITIAL_RETRY_DELAY,
+        }
+    }
+
+    /// Forward every email received on the broadcast channel until it closes
+    ///
+    /// When the channel outruns the forwarder, the emails it dropped are read back from
+    /// storage and forwarded before carrying on.
+    pub fn spawn(self, mut email_rx: broadcast::Receiver<Email>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut since = Utc::now();
+            let mut recent = RecentIds::default();
+            loop {
+                match email_rx.recv().await {
+                    Ok(email) => {
+                        since = since.max(email.timestamp - CATCH_UP_OVERLAP);
+                        if recent.insert(&email.id) {
+                            self.forward(&email).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Email forwarding fell behind by {} email(s), catching up from storage",
+                            skipped
+                        );
+                        self.catch_up(since, &mut recent).await;
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Forward the stored emails received at or after `since` that weren't forwarded yet
+    async fn catch_up(&self, since: DateTime<Utc>, recent: &mut RecentIds) {
+        let mut emails = self.storage.stream_all_emails(Some(since));
+        while let Some(email) = emails.next().await {
+            match email {
+                Ok(email) => {
+                    if recent.insert(&email.id) {
+                        self.forward(&email).await;
+                    }
+                }
+                Err(e) => {
+                    error!("❌ Failed to read emails to catch up forwarding: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Relay one email through each matching forwarding rule, in the background
+    pub async fn forward(&self, email: &Email) {
+        let mailbox = email.to.split('@').next().unwrap_or(&email.to);
+        let rules = match self.storage.get_forwarding_rules(mailbox).await {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Failed to load forwarding rules for {}: {}", mailbox, e);
+                return;
+            }
+        };
+        if rules.is_empty() {
+            return;
+        }
+
+        let Some(raw) = email.raw.clone() else {
+            warn!(
+                "Email {} has no raw message, not forwarding it for {}",
+                email.id, mailbox
+            );
+            return;
+        };
+
+        for rule in rules {
+            let forwarder = self.clone();
+            let email_id = email.id.clone();
+            let sender = email.to.clone();
+            let raw = raw.clone();
+            tokio::spawn(async move {
+                forwarder
+                    .relay_with_retries(&rule, &email_id, &sender, raw.as_bytes())
+                    .await;
+            });
+        }
+    }
+
+    async fn relay_with_retries(
+        &self,
+        rule: &ForwardingRule,
+        email_id: &str,
+        sender: &str,
+        raw: &[u8],
+    ) {
+        let mut delay = self.retry_delay;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match relay(rule, sender, raw).await {
+                Ok(()) => {
+                    info!(
+                        "📨 Forwarded email {} to {} via {}",
+                        email_id, rule.destination, rule.relay_host
+                    );
+                    return;
+                }
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    warn!(
+                        "Forwarding email {} to {} failed (attempt {}/{}), retrying in {:?}: {:#}",
+                        email_id, rule.destination, attempt, MAX_ATTEMPTS, delay, e
+                    );
+                    sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => {
+                    error!(
+                        "❌ Giving up forwarding email {} to {} after {} attempts: {:#}",
+                        email_id, rule.destination, MAX_ATTEMPTS, e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The IDs of the last [`RECENT_IDS`] emails handed to the forwarder
+#[derive(Default)]
+struct RecentIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl RecentIds {
+    /// Remember an email ID, returning false if it was already seen
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > RECENT_IDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Send a raw message to a rule's destination through its relay
+///
+/// The envelope sender is the receiving mailbox, so bounces come back to it rather than to
+/// the original sender.
+async fn relay(rule: &ForwardingRule, sender: &str, raw: &[u8]) -> Result<()> {
+    let mut builder = if rule.relay_starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&rule.relay_host)
+            .context("Invalid relay host")?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&rule.relay_host)
+    }
+    .port(rule.relay_port)
+    .timeout(Some(Duration::from_secs(30)));
+    if let (Some(user), Some(pass)) = (&rule.relay_username, &rule.relay_password) {
+        builder = builder.credentials(Credentials::new(user.clone(), pass.clone()));
+    }
+
+    let destination: Address = rule
+        .destination
+        .parse()
+        .context("Invalid forwarding destination")?;
+    let envelope = Envelope::new(sender.parse().ok(), vec![destination])
+        .context("Failed to create envelope")?;
+
+    debug!(
+        "Relaying message to {} via {}:{}",
+        rule.destination, rule.relay_host, rule.relay_port
+    );
+    builder
+        .build()
+        .send_raw(&envelope, raw)
+        .await
+        .context("Relay rejected the message")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteBackend;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Minimal SMTP server that turns away the first `reject_connections` connections and
+    /// then accepts messages, sending each one's envelope recipient and data
+    async fn mock_smtp_sink(reject_connections: usize) -> (u16, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel(8);
+
+        tokio::spawn(async move {
+            let mut connections = 0;
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                connections += 1;
+                let (read, mut write) = stream.into_split();
+                if connections <= reject_connections {
+                    let _ = write.write_all(b"421 sink busy\r\n").await;
+                    continue;
+                }
+
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut lines = BufReader::new(read).lines();
+                    let mut recipient = String::new();
+                    write.write_all(b"220 sink ready\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let command = line.to_ascii_uppercase();
+                        let reply: &[u8] = if command.starts_with("EHLO") {
+                            b"250 sink\r\n"
+                        } else if command.starts_with("RCPT TO:") {
+                            recipient =
+                                line[8..].trim_matches(|c| c == '<' || c == '>').to_string();
+                            b"250 ok\r\n"
+                        } else if command == "DATA" {
+                            write.write_all(b"354 go ahead\r\n").await.unwrap();
+                            let mut data = String::new();
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                if line == "." {
+                                    break;
+                                }
+                                data.push_str(&line);
+                                data.push('\n');
+                            }
+                            tx.send((recipient.clone(), data)).await.unwrap();
+                            b"250 queued\r\n"
+                        } else if command == "QUIT" {
+                            let _ = write.write_all(b"221 bye\r\n").await;
+                            break;
+                        } else {
+                            b"250 ok\r\n"
+                        };
+                        write.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (port, rx)
+    }
+
+    async fn forwarder_with_rule(port: u16) -> EmailForwarder {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let mut rule = ForwardingRule::new(
+            "temp".to_string(),
+            "me@real.example".to_string(),
+            "127.0.0.1".to_string(),
+            port,
+        );
+        rule.relay_starttls = false;
+        storage.create_forwarding_rule(rule).await.unwrap();
+
+        EmailForwarder {
+            storage,
+            retry_delay: Duration::from_millis(10),
+        }
+    }
+
+    fn received_email(to: &str) -> Email {
+        let mut email = Email::new(
+            to.to_string(),
+            "sender@example.com".to_string(),
+            "Hello".to_string(),
+            "Forward me".to_string(),
+            None,
+            vec![],
+        );
+        email.raw = Some(format!(
+            "From: sender@example.com\r\nTo: {}\r\nSubject: Hello\r\n\r\nForward me\r\n",
+            to
+        ));
+        email
+    }
+
+    async fn next_message(rx: &mut mpsc::Receiver<(String, String)>) -> (String, String) {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("timed out waiting for the relayed message")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_forwards_raw_message_to_destination() {
+        let (port, mut rx) = mock_smtp_sink(0).await;
+        let forwarder = forwarder_with_rule(port).await;
+
+        // Mail for other mailboxes is not forwarded
+        forwarder.forward(&received_email("other@test.local")).await;
+        forwarder.forward(&received_email("temp@test.local")).await;
+
+        let (recipient, data) = next_message(&mut rx).await;
+        assert_eq!(recipient, "me@real.example");
+        assert!(data.contains("To: temp@test.local"));
+        assert!(data.contains("Forward me"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retries_failed_relay() {
+        let (port, mut rx) = mock_smtp_sink(2).await;
+        let forwarder = forwarder_with_rule(port).await;
+
+        let (email_tx, email_rx) = broadcast::channel(4);
+        forwarder.spawn(email_rx);
+        email_tx.send(received_email("temp@test.local")).unwrap();
+
+        // Two rejected attempts, then the third is relayed
+        let (recipient, data) = next_message(&mut rx).await;
+        assert_eq!(recipient, "me@real.example");
+        assert!(data.contains("Subject: Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_catches_up_after_falling_behind() {
+        let (port, mut rx) = mock_smtp_sink(0).await;
+        let forwarder = forwarder_with_rule(port).await;
+        let storage = forwarder.storage.clone();
+
+        let (email_tx, email_rx) = broadcast::channel(2);
+        forwarder.spawn(email_rx);
+
+        // Five arrivals outrun the two-slot channel before the forwarder runs
+        for i in 0..5 {
+            let mut email = received_email("temp@test.local");
+            email.raw = Some(email.raw.unwrap().replace("Hello", &format!("Burst {}", i)));
+            storage.store_email(email.clone()).await.unwrap();
+            email_tx.send(email).unwrap();
+        }
+
+        // Every email is forwarded exactly once
+        let mut subjects = HashSet::new();
+        for _ in 0..5 {
+            let (_, data) = next_message(&mut rx).await;
+            let subject = data.lines().find(|l| l.starts_with("Subject:")).unwrap();
+            assert!(subjects.insert(subject.to_string()), "{} twice", subject);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,578 @@
+//! FETCH data items: parsing the request, body sections and BODYSTRUCTURE
+//!
+//! Sections and structures are served from the message as it would be sent over IMAP,
+//! so literal lengths and part offsets are byte counts into that exact text.
+
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+
+use super::escape_imap_string;
+
+/// One data item of a FETCH request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchItem {
+    Flags,
+    Uid,
+    InternalDate,
+    Envelope,
+    Rfc822Size,
+    /// `BODY` without a section: the non-extensible BODYSTRUCTURE
+    Body,
+    BodyStructure,
+    /// `MODSEQ` from the CONDSTORE extension
+    ModSeq,
+    Section(BodySection),
+}
+
+/// A `BODY[<section>]<<partial>>` item, or one of the RFC822 forms that map onto it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodySection {
+    /// Section spec between the brackets, e.g. `""`, `"HEADER"`, `"1.2"` or
+    /// `"HEADER.FIELDS (FROM TO)"`
+    pub section: String,
+    /// Requested `<offset.length>` byte range
+    pub partial: Option<(usize, usize)>,
+    /// Response name for the RFC822 forms, which are not echoed as `BODY[...]`
+    pub label: Option<&'static str>,
+}
+
+impl BodySection {
+    fn new(section: &str) -> Self {
+        Self {
+            section: section.to_string(),
+            partial: None,
+            label: None,
+        }
+    }
+
+    fn labelled(section: &str, label: &'static str) -> Self {
+        Self {
+            label: Some(label),
+            ..Self::new(section)
+        }
+    }
+}
+
+/// Parse the data items of a FETCH command, expanding the ALL, FAST and FULL macros
+///
+/// Items that are not understood are skipped.
+pub fn parse_fetch_items(data_items: &str) -> Vec<FetchItem> {
+    let data_items = data_items.trim();
+    let data_items = data_items
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(data_items);
+
+    let mut items = Vec::new();
+    for token in split_items(data_items) {
+        let upper = token.to_uppercase();
+        match upper.as_str() {
+            "FLAGS" => items.push(FetchItem::Flags),
+            "UID" => items.push(FetchItem::Uid),
+            "INTERNALDATE" => items.push(FetchItem::InternalDate),
+            "ENVELOPE" => items.push(FetchItem::Envelope),
+            "RFC822.SIZE" => items.push(FetchItem::Rfc822Size),
+            "BODY" => items.push(FetchItem::Body),
+            "BODYSTRUCTURE" => items.push(FetchItem::BodyStructure),
+            "MODSEQ" => items.push(FetchItem::ModSeq),
+            "RFC822" => items.push(FetchItem::Section(BodySection::labelled("", "RFC822"))),
+            "RFC822.HEADER" => items.push(FetchItem::Section(BodySection::labelled(
+                "HEADER",
+                "RFC822.HEADER",
+            ))),
+            "RFC822.TEXT" => items.push(FetchItem::Section(BodySection::labelled(
+                "TEXT",
+                "RFC822.TEXT",
+            ))),
+            "FAST" | "ALL" | "FULL" => {
+                items.extend([
+                    FetchItem::Flags,
+                    FetchItem::InternalDate,
+                    FetchItem::Rfc822Size,
+                ]);
+                if upper != "FAST" {
+                    items.push(FetchItem::Envelope);
+                }
+                if upper == "FULL" {
+                    items.push(FetchItem::Body);
+                }
+            }
+            _ => items.extend(parse_body_section(&upper).map(FetchItem::Section)),
+        }
+    }
+    items
+}
+
+/// Split a trailing `(CHANGEDSINCE <modseq>)` modifier off the data items of a FETCH command
+///
+/// Returns `None` when the modifier is present but its mod-sequence is not a number.
+pub fn split_changedsince(data_items: &str) -> Option<(&str, Option<u64>)> {
+    const MODIFIER: &str = "(CHANGEDSINCE ";
+
+    let trimmed = data_items.trim_end();
+    let Some(start) = trimmed.to_ascii_uppercase().rfind(MODIFIER) else {
+        return Some((data_items, None));
+    };
+    let modseq = trimmed[start + MODIFIER.len()..]
+        .strip_suffix(')')?
+        .trim()
+        .parse()
+        .ok()?;
+    Some((trimmed[..start].trim_end(), Some(modseq)))
+}
+
+/// Parse a `BODY[...]` or `BODY.PEEK[...]` item with an optional `<offset.length>`
+pub fn parse_body_section(item: &str) -> Option<BodySection> {
+    let rest = item
+        .strip_prefix("BODY.PEEK[")
+        .or_else(|| item.strip_prefix("BODY["))?;
+    let close = rest.rfind(']')?;
+    let mut section = BodySection::new(&rest[..close]);
+
+    let partial = &rest[close + 1..];
+    if !partial.is_empty() {
+        let (offset, length) = partial
+            .strip_prefix('<')?
+            .strip_suffix('>')?
+            .split_once('.')?;
+        section.partial = Some((offset.parse().ok()?, length.parse().ok()?));
+    }
+    Some(section)
+}
+
+/// Split data items on spaces that are not inside brackets or parentheses
+fn split_items(data_items: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in data_items.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 => {
+                if i > start {
+                    tokens.push(&data_items[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < data_items.len() {
+        tokens.push(&data_items[start..]);
+    }
+    tokens
+}
+
+/// Format a body section response, `NIL` when the section does not exist
+///
+/// `parsed` is only needed for numbered MIME parts.
+pub fn section_response(section: &BodySection, raw: &[u8], parsed: Option<&Message>) -> Vec<u8> {
+    let mut name = match section.label {
+        Some(label) => label.to_string(),
+        None => format!("BODY[{}]", section.section),
+    };
+
+    let Some(mut data) = section_data(&section.section, raw, parsed) else {
+        return format!("{} NIL", name).into_bytes();
+    };
+    if let Some((offset, length)) = section.partial {
+        let start = offset.min(data.len());
+        let end = start.saturating_add(length).min(data.len());
+        data = data[start..end].to_vec();
+        name.push_str(&format!("<{}>", offset));
+    }
+
+    let mut response = format!("{} {{{}}}\r\n", name, data.len()).into_bytes();
+    response.extend_from_slice(&data);
+    response
+}
+
+/// Bytes of a section spec: an optional part number path followed by an optional
+/// HEADER, HEADER.FIELDS, HEADER.FIELDS.NOT, TEXT or MIME specifier
+fn section_data(section: &str, raw: &[u8], parsed: Option<&Message>) -> Option<Vec<u8>> {
+    let mut path = Vec::new();
+    let mut spec = section;
+    while let Some(first) = spec.split('.').next() {
+        let Ok(number) = first.parse::<usize>() else {
+            break;
+        };
+        path.push(number);
+        spec = spec[first.len()..].strip_prefix('.').unwrap_or("");
+    }
+
+    if path.is_empty() {
+        message_section(raw, spec)
+    } else {
+        part_section(parsed?, &path, spec)
+    }
+}
+
+fn part_section(message: &Message, path: &[usize], spec: &str) -> Option<Vec<u8>> {
+    let (&first, rest) = path.split_first()?;
+    let root = message.parts.first()?;
+    let mut part = match &root.body {
+        PartType::Multipart(_) => child_part(message, root, first)?,
+        // A single-part message has just part 1, the message body
+        _ if first == 1 => root,
+        _ => return None,
+    };
+    for (i, &number) in rest.iter().enumerate() {
+        part = match &part.body {
+            PartType::Message(nested) => return part_section(nested, &rest[i..], spec),
+            _ => child_part(message, part, number)?,
+        };
+    }
+
+    let raw = message.raw_message();
+    match (spec, &part.body) {
+        ("", _) => raw
+            .get(part.offset_body..part.offset_end)
+            .map(<[u8]>::to_vec),
+        ("MIME", _) => raw
+            .get(part.offset_header..part.offset_body)
+            .map(<[u8]>::to_vec),
+        (_, PartType::Message(nested)) => message_section(nested.raw_message(), spec),
+        _ => None,
+    }
+}
+
+fn child_part<'a>(
+    message: &'a Message,
+    part: &MessagePart,
+    number: usize,
+) -> Option<&'a MessagePart<'a>> {
+    let PartType::Multipart(children) = &part.body else {
+        return None;
+    };
+    message.parts.get(*children.get(number.checked_sub(1)?)?)
+}
+
+/// A section of a whole message (or a nested message/rfc822 part)
+fn message_section(raw: &[u8], spec: &str) -> Option<Vec<u8>> {
+    let (header, text) = split_header(raw);
+    match spec {
+        "" => Some(raw.to_vec()),
+        "HEADER" => Some(header.to_vec()),
+        "TEXT" => Some(text.to_vec()),
+        _ => {
+            let (exclude, fields) = match spec.strip_prefix("HEADER.FIELDS.NOT") {
+                Some(fields) => (true, fields),
+                None => (false, spec.strip_prefix("HEADER.FIELDS")?),
+            };
+            let names: Vec<&str> = fields
+                .trim()
+                .strip_prefix('(')?
+                .strip_suffix(')')?
+                .split_whitespace()
+                .collect();
+            Some(header_fields(header, &names, exclude))
+        }
+    }
+}
+
+/// Split a message after the blank line that ends its header
+fn split_header(raw: &[u8]) -> (&[u8], &[u8]) {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+        .or_else(|| raw.windows(2).position(|w| w == b"\n\n").map(|pos| pos + 2))
+        .unwrap_or(raw.len());
+    raw.split_at(end)
+}
+
+/// Header fields named in `names` (or all others when `exclude`), with the closing
+/// blank line
+fn header_fields(header: &[u8], names: &[&str], exclude: bool) -> Vec<u8> {
+    let mut fields = Vec::new();
+    let mut keep = false;
+    for line in header.split_inclusive(|&b| b == b'\n') {
+        if line == b"\r\n" || line == b"\n" {
+            break;
+        }
+        // Folded continuation lines belong to the previous field
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            let name = line.split(|&b| b == b':').next().unwrap_or_default();
+            let named = names
+                .iter()
+                .any(|n| n.as_bytes().eq_ignore_ascii_case(name.trim_ascii()));
+            keep = named != exclude;
+        }
+        if keep {
+            fields.extend_from_slice(line);
+        }
+    }
+    fields.extend_from_slice(b"\r\n");
+    fields
+}
+
+/// BODYSTRUCTURE of a parsed message, without extension data
+///
+/// Without a parsed message the whole text is described as a single plain-text part.
+pub fn body_structure(raw: &[u8], parsed: Option<&Message>) -> String {
+    match parsed {
+        Some(message) if !message.parts.is_empty() => part_structure(message, 0),
+        _ => {
+            let (_, text) = split_header(raw);
+            format!(
+                "(\"TEXT\" \"PLAIN\" (\"CHARSET\" \"US-ASCII\") NIL NIL \"7BIT\" {} {})",
+                text.len(),
+                line_count(text)
+            )
+        }
+    }
+}
+
+fn part_structure(message: &Message, part_id: usize) -> String {
+    let Some(part) = message.parts.get(part_id) else {
+        return "NIL".to_string();
+    };
+    let content_type = part.content_type();
+    let subtype = content_type.and_then(|ct| ct.c_subtype.as_deref());
+
+    if let PartType::Multipart(children) = &part.body {
+        let children: String = children
+            .iter()
+            .map(|&child| part_structure(message, child))
+            .collect();
+        return format!(
+            "({} {})",
+            children,
+            quoted(&subtype.unwrap_or("mixed").to_uppercase())
+        );
+    }
+
+    let (media_type, subtype) = match content_type {
+        Some(ct) => (
+            ct.c_type.to_uppercase(),
+            subtype.unwrap_or("").to_uppercase(),
+        ),
+        None => ("TEXT".to_string(), "PLAIN".to_string()),
+    };
+    let params = match content_type.and_then(|ct| ct.attributes.as_ref()) {
+        Some(attributes) if !attributes.is_empty() => {
+            let params: Vec<String> = attributes
+                .iter()
+                .map(|(name, value)| format!("{} {}", quoted(&name.to_uppercase()), quoted(value)))
+                .collect();
+            format!("({})", params.join(" "))
+        }
+        _ if media_type == "TEXT" => "(\"CHARSET\" \"US-ASCII\")".to_string(),
+        _ => "NIL".to_string(),
+    };
+    let encoding = part
+        .content_transfer_encoding()
+        .map(str::to_uppercase)
+        .unwrap_or_else(|| "7BIT".to_string());
+    let body = message
+        .raw_message()
+        .get(part.offset_body..part.offset_end)
+        .unwrap_or_default();
+
+    let mut structure = format!(
+        "({} {} {} {} {} {} {}",
+        quoted(&media_type),
+        quoted(&subtype),
+        params,
+        nstring(part.content_id().map(|id| format!("<{}>", id)).as_deref()),
+        nstring(part.content_description()),
+        quoted(&encoding),
+        body.len()
+    );
+    match &part.body {
+        PartType::Message(nested) if media_type == "MESSAGE" => {
+            // Only the date, subject and message ID are filled in on nested envelopes
+            structure.push_str(&format!(
+                " ({} {} NIL NIL NIL NIL NIL NIL NIL {}) {} {}",
+                nstring(nested.header_raw("Date").map(str::trim)),
+                nstring(nested.subject()),
+                nstring(nested.message_id().map(|id| format!("<{}>", id)).as_deref()),
+                body_structure(nested.raw_message(), Some(nested)),
+                line_count(body)
+            ));
+        }
+        _ if media_type == "TEXT" => structure.push_str(&format!(" {}", line_count(body))),
+        _ => {}
+    }
+    structure.push(')');
+    structure
+}
+
+fn line_count(data: &[u8]) -> usize {
+    data.iter().filter(|&&b| b == b'\n').count()
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", escape_imap_string(s))
+}
+
+fn nstring(s: Option<&str>) -> String {
+    s.map(quoted).unwrap_or_else(|| "NIL".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mail_parser::MessageParser;
+
+    const MULTIPART: &str = "From: a@example.com\r\n\
+        Subject: Parts\r\n\
+        Content-Type: multipart/mixed; boundary=\"XX\"\r\n\
+        \r\n\
+        --XX\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        Hello there\r\n\
+        --XX\r\n\
+        Content-Type: application/pdf; name=\"a.pdf\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        JVBERi0=\r\n\
+        --XX--\r\n";
+
+    #[test]
+    fn test_split_changedsince() {
+        assert_eq!(split_changedsince("(FLAGS)"), Some(("(FLAGS)", None)));
+        assert_eq!(
+            split_changedsince("(FLAGS UID) (CHANGEDSINCE 12)"),
+            Some(("(FLAGS UID)", Some(12)))
+        );
+        assert_eq!(
+            split_changedsince("FLAGS (changedsince 7)"),
+            Some(("FLAGS", Some(7)))
+        );
+        assert_eq!(split_changedsince("(FLAGS) (CHANGEDSINCE x)"), None);
+        assert_eq!(
+            parse_fetch_items("(FLAGS MODSEQ)"),
+            vec![FetchItem::Flags, FetchItem::ModSeq]
+        );
+    }
+
+    #[test]
+    fn test_parse_body_section() {
+        assert_eq!(parse_body_section("BODY[]"), Some(BodySection::new("")));
+        assert_eq!(
+            parse_body_section("BODY.PEEK[HEADER]"),
+            Some(BodySection::new("HEADER"))
+        );
+        assert_eq!(
+            parse_body_section("BODY[1.2.TEXT]<0.1000>"),
+            Some(BodySection {
+                partial: Some((0, 1000)),
+                ..BodySection::new("1.2.TEXT")
+            })
+        );
+        assert_eq!(
+            parse_body_section("BODY.PEEK[HEADER.FIELDS (FROM TO)]"),
+            Some(BodySection::new("HEADER.FIELDS (FROM TO)"))
+        );
+        assert_eq!(parse_body_section("BODY[]<10>"), None);
+        assert_eq!(parse_body_section("BODY[TEXT"), None);
+        assert_eq!(parse_body_section("BODYSTRUCTURE"), None);
+    }
+
+    #[test]
+    fn test_parse_fetch_items() {
+        assert_eq!(
+            parse_fetch_items("(UID FLAGS BODY.PEEK[HEADER.FIELDS (DATE FROM)] BODYSTRUCTURE)"),
+            vec![
+                FetchItem::Uid,
+                FetchItem::Flags,
+                FetchItem::Section(BodySection::new("HEADER.FIELDS (DATE FROM)")),
+                FetchItem::BodyStructure,
+            ]
+        );
+        assert_eq!(
+            parse_fetch_items("rfc822.header"),
+            vec![FetchItem::Section(BodySection::labelled(
+                "HEADER",
+                "RFC822.HEADER"
+            ))]
+        );
+        assert_eq!(
+            parse_fetch_items("FAST"),
+            vec![
+                FetchItem::Flags,
+                FetchItem::InternalDate,
+                FetchItem::Rfc822Size
+            ]
+        );
+        assert_eq!(parse_fetch_items("(BOGUS BODY)"), vec![FetchItem::Body]);
+    }
+
+    #[test]
+    fn test_message_sections() {
+        let raw =
+            b"From: a@example.com\r\nSubject: Hi\r\n there\r\nTo: b@example.com\r\n\r\nBody\r\n";
+        assert_eq!(
+            section_data("HEADER", raw, None).unwrap(),
+            b"From: a@example.com\r\nSubject: Hi\r\n there\r\nTo: b@example.com\r\n\r\n"
+        );
+        assert_eq!(section_data("TEXT", raw, None).unwrap(), b"Body\r\n");
+        assert_eq!(
+            section_data("HEADER.FIELDS (SUBJECT)", raw, None).unwrap(),
+            b"Subject: Hi\r\n there\r\n\r\n"
+        );
+        assert_eq!(
+            section_data("HEADER.FIELDS.NOT (SUBJECT)", raw, None).unwrap(),
+            b"From: a@example.com\r\nTo: b@example.com\r\n\r\n"
+        );
+        assert_eq!(section_data("1", raw, None), None);
+    }
+
+    #[test]
+    fn test_partial_response() {
+        let raw = b"Subject: Hi\r\n\r\nBody\r\n";
+        let section = BodySection {
+            partial: Some((4, 100)),
+            ..BodySection::new("TEXT")
+        };
+        assert_eq!(
+            section_response(&section, raw, None),
+            b"BODY[TEXT]<4> {2}\r\n\r\n"
+        );
+        let section = BodySection {
+            partial: Some((50, 10)),
+            ..BodySection::new("")
+        };
+        assert_eq!(section_response(&section, raw, None), b"BODY[]<50> {0}\r\n");
+        assert_eq!(
+            section_response(&BodySection::new("3"), raw, None),
+            b"BODY[3] NIL"
+        );
+    }
+
+    #[test]
+    fn test_mime_part_sections() {
+        let message = MessageParser::default().parse(MULTIPART).unwrap();
+        let raw = MULTIPART.as_bytes();
+        assert_eq!(
+            section_data("1", raw, Some(&message)).unwrap(),
+            b"Hello there"
+        );
+        assert_eq!(section_data("2", raw, Some(&message)).unwrap(), b"JVBERi0=");
+        assert!(
+            String::from_utf8(section_data("2.MIME", raw, Some(&message)).unwrap())
+                .unwrap()
+                .starts_with("Content-Type: application/pdf")
+        );
+        assert_eq!(section_data("3", raw, Some(&message)), None);
+    }
+
+    #[test]
+    fn test_body_structure() {
+        let message = MessageParser::default().parse(MULTIPART).unwrap();
+        assert_eq!(
+            body_structure(MULTIPART.as_bytes(), Some(&message)),
+            "((\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 11 0)\
+             (\"APPLICATION\" \"PDF\" (\"NAME\" \"a.pdf\") NIL NIL \"BASE64\" 8) \"MIXED\")"
+        );
+
+        let raw = b"Subject: Plain\r\n\r\nOne\r\nTwo\r\n";
+        let message = MessageParser::default().parse(&raw[..]).unwrap();
+        assert_eq!(
+            body_structure(raw, Some(&message)),
+            "(\"TEXT\" \"PLAIN\" (\"CHARSET\" \"US-ASCII\") NIL NIL \"7BIT\" 10 2)"
+        );
+    }
+}
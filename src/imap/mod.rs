@@ -1,53 +1,165 @@
 //! IMAP server implementation for retrieving emails
 //!
 //! This module provides a minimal IMAP server that supports:
+//! - STARTTLS on the plain port and implicit TLS (IMAPS) when TLS is configured
 //! - LOGIN authentication using mailbox address and password
 //! - LIST/LSUB for listing mailboxes
 //! - SELECT for selecting a mailbox
-//! - FETCH for retrieving emails
-//! - SEARCH for searching emails
+//! - STATUS for mailbox counts without selecting it
+//! - GETQUOTA/GETQUOTAROOT for the mailbox quota (QUOTA extension)
+//! - FETCH for retrieving emails, including body sections, partial ranges and BODYSTRUCTURE
+//! - STORE for updating `\Seen`, `\Flagged` and other system flags
+//! - CONDSTORE mod-sequences: HIGHESTMODSEQ on SELECT, and FETCH with MODSEQ and
+//!   `(CHANGEDSINCE n)` so clients only download flag changes since their last sync
+//! - SEARCH for searching emails (ALL, TEXT and SUBJECT criteria)
+//! - IDLE for push notification of new emails
 //! - LOGOUT for disconnecting
 
-use anyhow::Result;
+mod fetch;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use mail_parser::MessageParser;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_rustls::TlsAcceptor;
 use tracing::{debug, error, info, warn};
 
-use crate::storage::StorageBackend;
+use crate::config::{MailboxQuotaConfig, SmtpSslConfig};
+use crate::storage::{models::Email, StorageBackend};
+use crate::webhooks::{DeletionNotifier, WebhookTrigger};
+use fetch::{parse_fetch_items, split_changedsince, BodySection, FetchItem};
+
+/// Byte stream an IMAP session runs over: plain TCP, or TLS after STARTTLS or on IMAPS
+trait ImapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ImapStream for T {}
 
 /// IMAP server that handles client connections
 pub struct ImapServer {
     storage: Arc<dyn StorageBackend>,
     domain_name: String,
+    ssl_config: SmtpSslConfig,
+    /// New emails, used to notify IDLE clients
+    email_sender: broadcast::Sender<Email>,
+    /// Expunged emails as (email_id, address), for WebSocket clients
+    deletion_sender: broadcast::Sender<(String, String)>,
+    /// Refuse logins on connections that are not encrypted
+    require_tls: bool,
+    /// Limits reported through the QUOTA extension
+    mailbox_quota: MailboxQuotaConfig,
 }
 
 impl ImapServer {
     /// Create a new IMAP server instance
-    pub fn new(storage: Arc<dyn StorageBackend>, domain_name: String) -> Self {
+    pub fn new(
+        storage: Arc<dyn StorageBackend>,
+        domain_name: String,
+        ssl_config: SmtpSslConfig,
+        email_sender: broadcast::Sender<Email>,
+        deletion_sender: broadcast::Sender<(String, String)>,
+    ) -> Self {
         Self {
             storage,
             domain_name,
+            ssl_config,
+            email_sender,
+            deletion_sender,
+            require_tls: false,
+            mailbox_quota: MailboxQuotaConfig::default(),
         }
     }
 
-    /// Start the IMAP server on the specified port
-    pub async fn start(&self, port: u16) -> Result<()> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-        info!("📬 IMAP server listening on port {}", port);
+    /// Refuse LOGIN and AUTHENTICATE until the connection is encrypted
+    pub fn with_require_tls(mut self, require_tls: bool) -> Self {
+        self.require_tls = require_tls;
+        self
+    }
+
+    /// Report these mailbox limits to clients through GETQUOTA and GETQUOTAROOT
+    pub fn with_mailbox_quota(mut self, mailbox_quota: MailboxQuotaConfig) -> Self {
+        self.mailbox_quota = mailbox_quota;
+        self
+    }
+
+    /// Start the plaintext IMAP server on the specified port, offering STARTTLS
+    /// when TLS is enabled
+    pub async fn start(&self, bind_address: IpAddr, port: u16) -> Result<()> {
+        let acceptor = if self.ssl_config.enabled {
+            Some(load_tls_acceptor(&self.ssl_config)?)
+        } else {
+            None
+        };
+        let addr = SocketAddr::new(bind_address, port);
+        let listener = TcpListener::bind(addr).await?;
+        info!("📬 IMAP server listening on {}", addr);
+        self.serve(listener, acceptor, false).await
+    }
+
+    /// Start an implicit-TLS (IMAPS) server on the specified port
+    pub async fn start_tls(&self, bind_address: IpAddr, port: u16) -> Result<()> {
+        let acceptor = load_tls_acceptor(&self.ssl_config)?;
+        let addr = SocketAddr::new(bind_address, port);
+        let listener = TcpListener::bind(addr).await?;
+        info!("📬 IMAPS server listening on {}", addr);
+        self.serve(listener, Some(acceptor), true).await
+    }
 
+    /// Accept connections, performing the TLS handshake up front when `implicit_tls` is set
+    async fn serve(
+        &self,
+        listener: TcpListener,
+        acceptor: Option<TlsAcceptor>,
+        implicit_tls: bool,
+    ) -> Result<()> {
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
                     debug!("IMAP connection from {}", addr);
                     let storage = self.storage.clone();
                     let domain_name = self.domain_name.clone();
+                    let email_sender = self.email_sender.clone();
+                    let deletion_sender = self.deletion_sender.clone();
+                    let acceptor = acceptor.clone();
+                    let require_tls = self.require_tls;
+                    let mailbox_quota = self.mailbox_quota.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = ImapConnection::new(stream, storage, domain_name)
-                            .handle()
-                            .await
-                        {
+                        let mut connection = match acceptor {
+                            Some(acceptor) if implicit_tls => match acceptor.accept(stream).await {
+                                Ok(tls) => ImapConnection::new(
+                                    Box::new(tls),
+                                    storage,
+                                    domain_name,
+                                    email_sender,
+                                    deletion_sender,
+                                    None,
+                                )
+                                .with_tls_active()
+                                .with_require_tls(require_tls)
+                                .with_mailbox_quota(mailbox_quota),
+                                Err(e) => {
+                                    warn!("IMAPS handshake with {} failed: {}", addr, e);
+                                    return;
+                                }
+                            },
+                            acceptor => ImapConnection::new(
+                                Box::new(stream),
+                                storage,
+                                domain_name,
+                                email_sender,
+                                deletion_sender,
+                                acceptor,
+                            )
+                            .with_require_tls(require_tls)
+                            .with_mailbox_quota(mailbox_quota),
+                        };
+
+                        if let Err(e) = connection.handle().await {
                             error!("IMAP connection error: {}", e);
                         }
                     });
@@ -60,6 +172,44 @@ impl ImapServer {
     }
 }
 
+/// Build a TLS acceptor from the configured PEM certificate chain and private key
+fn load_tls_acceptor(ssl_config: &SmtpSslConfig) -> Result<TlsAcceptor> {
+    let cert_path = ssl_config
+        .cert_path
+        .as_ref()
+        .context("TLS certificate path is not set")?;
+    let key_path = ssl_config
+        .key_path
+        .as_ref()
+        .context("TLS private key path is not set")?;
+
+    let cert_file = std::fs::File::open(cert_path)
+        .with_context(|| format!("Failed to open certificate {}", cert_path.display()))?;
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificate {}", cert_path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", cert_path.display());
+    }
+
+    let key_file = std::fs::File::open(key_path)
+        .with_context(|| format!("Failed to open private key {}", key_path.display()))?;
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(key_file))
+        .with_context(|| format!("Failed to parse private key {}", key_path.display()))?
+        .with_context(|| format!("No private key found in {}", key_path.display()))?;
+
+    // Both rustls crypto backends are compiled in, so pick one explicitly
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::aws_lc_rs::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .context("Invalid TLS certificate or key")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
 /// Represents the state of an IMAP connection
 #[derive(Debug, Clone, PartialEq)]
 enum ImapState {
@@ -70,24 +220,86 @@ enum ImapState {
 
 /// Handles a single IMAP client connection
 struct ImapConnection {
-    stream: BufReader<TcpStream>,
+    stream: BufReader<Box<dyn ImapStream>>,
     storage: Arc<dyn StorageBackend>,
     domain_name: String,
     state: ImapState,
     authenticated_user: Option<String>,
+    /// Whether the selected mailbox was opened with EXAMINE
+    read_only: bool,
+    /// New emails, used to notify the client while it IDLEs
+    email_sender: broadcast::Sender<Email>,
+    /// Expunged emails as (email_id, address), for WebSocket clients
+    deletion_sender: broadcast::Sender<(String, String)>,
+    /// Acceptor used to upgrade the connection on STARTTLS, if TLS is configured
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Whether the connection is already encrypted
+    tls_active: bool,
+    /// Refuse logins while the connection is not encrypted
+    require_tls: bool,
+    /// Limits reported through the QUOTA extension
+    mailbox_quota: MailboxQuotaConfig,
+    /// Whether the client has used CONDSTORE, after which FETCH responses carry MODSEQ
+    condstore_enabled: bool,
 }
 
 impl ImapConnection {
-    fn new(stream: TcpStream, storage: Arc<dyn StorageBackend>, domain_name: String) -> Self {
+    fn new(
+        stream: Box<dyn ImapStream>,
+        storage: Arc<dyn StorageBackend>,
+        domain_name: String,
+        email_sender: broadcast::Sender<Email>,
+        deletion_sender: broadcast::Sender<(String, String)>,
+        tls_acceptor: Option<TlsAcceptor>,
+    ) -> Self {
         Self {
             stream: BufReader::new(stream),
             storage,
             domain_name,
             state: ImapState::NotAuthenticated,
             authenticated_user: None,
+            read_only: false,
+            email_sender,
+            deletion_sender,
+            tls_acceptor,
+            tls_active: false,
+            require_tls: false,
+            mailbox_quota: MailboxQuotaConfig::default(),
+            condstore_enabled: false,
         }
     }
 
+    /// Mark the connection as already encrypted (implicit TLS)
+    fn with_tls_active(mut self) -> Self {
+        self.tls_active = true;
+        self
+    }
+
+    fn with_require_tls(mut self, require_tls: bool) -> Self {
+        self.require_tls = require_tls;
+        self
+    }
+
+    fn with_mailbox_quota(mut self, mailbox_quota: MailboxQuotaConfig) -> Self {
+        self.mailbox_quota = mailbox_quota;
+        self
+    }
+
+    /// Whether logging in must wait for STARTTLS
+    fn login_disabled(&self) -> bool {
+        self.require_tls && !self.tls_active
+    }
+
+    /// Refuse a login attempt on an unencrypted connection
+    async fn send_privacy_required(&mut self, tag: &str) -> Result<()> {
+        warn!("🔒 IMAP login refused before TLS");
+        self.send_line(&format!(
+            "{} NO [PRIVACYREQUIRED] Use STARTTLS before logging in",
+            tag
+        ))
+        .await
+    }
+
     async fn handle(&mut self) -> Result<()> {
         // Send greeting
         self.send_line("* OK IMAP4rev1 Service Ready").await?;
@@ -128,6 +340,13 @@ impl ImapConnection {
         Ok(())
     }
 
+    /// Write a response that may carry literal data which is not valid UTF-8
+    async fn send_bytes(&mut self, data: &[u8]) -> Result<()> {
+        debug!("IMAP sending {} bytes", data.len());
+        self.stream.get_mut().write_all(data).await?;
+        Ok(())
+    }
+
     async fn process_command(&mut self, line: &str) -> Result<()> {
         // Parse tag and command
         let parts: Vec<&str> = line.splitn(3, ' ').collect();
@@ -141,6 +360,7 @@ impl ImapConnection {
 
         match command.as_str() {
             "CAPABILITY" => self.cmd_capability(tag).await,
+            "STARTTLS" => self.cmd_starttls(tag).await,
             "NOOP" => self.cmd_noop(tag).await,
             "LOGOUT" => self.cmd_logout(tag).await,
             "LOGIN" => self.cmd_login(tag, args).await,
@@ -149,9 +369,15 @@ impl ImapConnection {
             "LSUB" => self.cmd_lsub(tag, args).await,
             "SELECT" => self.cmd_select(tag, args).await,
             "EXAMINE" => self.cmd_examine(tag, args).await,
+            "STATUS" => self.cmd_status(tag, args).await,
+            "GETQUOTA" => self.cmd_getquota(tag, args).await,
+            "GETQUOTAROOT" => self.cmd_getquotaroot(tag, args).await,
             "FETCH" => self.cmd_fetch(tag, args).await,
+            "STORE" => self.cmd_store(tag, args).await,
             "SEARCH" => self.cmd_search(tag, args).await,
             "CLOSE" => self.cmd_close(tag).await,
+            "EXPUNGE" => self.cmd_expunge(tag).await,
+            "IDLE" => self.cmd_idle(tag).await,
             "UID" => self.cmd_uid(tag, args).await,
             _ => {
                 self.send_line(&format!("{} BAD Unknown command", tag))
@@ -161,12 +387,63 @@ impl ImapConnection {
     }
 
     async fn cmd_capability(&mut self, tag: &str) -> Result<()> {
-        self.send_line("* CAPABILITY IMAP4rev1 AUTH=PLAIN LOGIN")
+        let mut capabilities = vec!["IMAP4rev1"];
+        if self.tls_acceptor.is_some() && !self.tls_active {
+            capabilities.push("STARTTLS");
+        }
+        if self.login_disabled() {
+            capabilities.push("LOGINDISABLED");
+        } else {
+            capabilities.extend(["AUTH=PLAIN", "LOGIN"]);
+        }
+        capabilities.extend(["CONDSTORE", "IDLE", "QUOTA"]);
+        if self.mailbox_quota.max_bytes.is_some() {
+            capabilities.push("QUOTA=RES-STORAGE");
+        }
+        if self.mailbox_quota.max_emails.is_some() {
+            capabilities.push("QUOTA=RES-MESSAGE");
+        }
+
+        self.send_line(&format!("* CAPABILITY {}", capabilities.join(" ")))
             .await?;
         self.send_line(&format!("{} OK CAPABILITY completed", tag))
             .await
     }
 
+    async fn cmd_starttls(&mut self, tag: &str) -> Result<()> {
+        let acceptor = match &self.tls_acceptor {
+            Some(acceptor) if !self.tls_active => acceptor.clone(),
+            _ => {
+                return self
+                    .send_line(&format!("{} BAD STARTTLS not available", tag))
+                    .await;
+            }
+        };
+
+        if self.state != ImapState::NotAuthenticated {
+            return self
+                .send_line(&format!("{} BAD Already authenticated", tag))
+                .await;
+        }
+
+        self.send_line(&format!("{} OK Begin TLS negotiation now", tag))
+            .await?;
+
+        // Swap the plain stream out; into_inner() drops anything the client pipelined
+        // before the handshake, as required by RFC 3501
+        let placeholder: Box<dyn ImapStream> = Box::new(tokio::io::duplex(1).0);
+        let plain = std::mem::replace(&mut self.stream, BufReader::new(placeholder)).into_inner();
+        let tls = acceptor
+            .accept(plain)
+            .await
+            .context("STARTTLS handshake failed")?;
+
+        self.stream = BufReader::new(Box::new(tls));
+        self.tls_active = true;
+        debug!("IMAP connection upgraded to TLS");
+        Ok(())
+    }
+
     async fn cmd_noop(&mut self, tag: &str) -> Result<()> {
         self.send_line(&format!("{} OK NOOP completed", tag)).await
     }
@@ -180,6 +457,10 @@ impl ImapConnection {
     }
 
     async fn cmd_authenticate(&mut self, tag: &str, args: &str) -> Result<()> {
+        if self.login_disabled() {
+            return self.send_privacy_required(tag).await;
+        }
+
         let mechanism = args.trim().to_uppercase();
 
         if mechanism != "PLAIN" {
@@ -274,6 +555,10 @@ impl ImapConnection {
     }
 
     async fn cmd_login(&mut self, tag: &str, args: &str) -> Result<()> {
+        if self.login_disabled() {
+            return self.send_privacy_required(tag).await;
+        }
+
         // Parse username and password from args
         // Format: LOGIN username password
         // Username/password may be quoted
@@ -357,12 +642,28 @@ impl ImapConnection {
     }
 
     async fn cmd_select(&mut self, tag: &str, args: &str) -> Result<()> {
+        self.open_mailbox(tag, args, false).await
+    }
+
+    async fn cmd_examine(&mut self, tag: &str, args: &str) -> Result<()> {
+        self.open_mailbox(tag, args, true).await
+    }
+
+    /// Shared implementation of SELECT and EXAMINE
+    async fn open_mailbox(&mut self, tag: &str, args: &str, read_only: bool) -> Result<()> {
         if self.state == ImapState::NotAuthenticated {
             return self
                 .send_line(&format!("{} NO Not authenticated", tag))
                 .await;
         }
 
+        // `SELECT INBOX (CONDSTORE)` turns on MODSEQ in FETCH responses
+        let args = args.trim();
+        let (args, condstore) = if args.to_ascii_uppercase().ends_with(" (CONDSTORE)") {
+            (&args[..args.len() - " (CONDSTORE)".len()], true)
+        } else {
+            (args, false)
+        };
         let mailbox = unquote(args.trim());
 
         // Only support INBOX for now
@@ -384,34 +685,223 @@ impl ImapConnection {
 
         // Build the full email address
         let full_address = format!("{}@{}", user, self.domain_name);
-        let emails = self
-            .storage
-            .get_emails_for_address(&full_address)
-            .await
-            .unwrap_or_default();
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
+        let uid_state = match self.storage.get_mailbox_uid_state(&full_address).await {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load UID state for {}: {}", full_address, e);
+                return self
+                    .send_line(&format!("{} NO Failed to open mailbox", tag))
+                    .await;
+            }
+        };
 
         let count = emails.len();
+        let unseen = emails.iter().filter(|e| !e.read).count();
+        let first_unseen = emails.iter().position(|e| !e.read).map(|i| i + 1);
 
         self.state = ImapState::Selected(mailbox.to_string());
+        self.read_only = read_only;
+        self.condstore_enabled |= condstore;
 
         // Send mailbox information
         self.send_line(&format!("* {} EXISTS", count)).await?;
-        self.send_line("* 0 RECENT").await?;
-        self.send_line("* OK [UIDVALIDITY 1] UIDs valid").await?;
-        self.send_line(&format!("* OK [UIDNEXT {}] Predicted next UID", count + 1))
+        // Unread messages are reported as recent since no per-session state is kept
+        self.send_line(&format!("* {} RECENT", unseen)).await?;
+        if let Some(first_unseen) = first_unseen {
+            self.send_line(&format!(
+                "* OK [UNSEEN {}] Message {} is first unseen",
+                first_unseen, first_unseen
+            ))
             .await?;
+        }
+        self.send_line(&format!(
+            "* OK [UIDVALIDITY {}] UIDs valid",
+            uid_state.uid_validity
+        ))
+        .await?;
+        self.send_line(&format!(
+            "* OK [UIDNEXT {}] Predicted next UID",
+            uid_state.uid_next
+        ))
+        .await?;
+        self.send_line(&format!(
+            "* OK [HIGHESTMODSEQ {}] Highest",
+            uid_state.highest_modseq
+        ))
+        .await?;
         self.send_line("* FLAGS (\\Seen \\Answered \\Flagged \\Deleted \\Draft)")
             .await?;
-        self.send_line("* OK [PERMANENTFLAGS ()] No permanent flags permitted")
+        if read_only {
+            self.send_line("* OK [PERMANENTFLAGS ()] No permanent flags permitted")
+                .await?;
+            self.send_line(&format!("{} OK [READ-ONLY] EXAMINE completed", tag))
+                .await
+        } else {
+            self.send_line(&format!(
+                "* OK [PERMANENTFLAGS ({})] Flags permitted",
+                PERMANENT_FLAGS.join(" ")
+            ))
             .await?;
+            self.send_line(&format!("{} OK [READ-WRITE] SELECT completed", tag))
+                .await
+        }
+    }
+
+    async fn cmd_status(&mut self, tag: &str, args: &str) -> Result<()> {
+        if self.state == ImapState::NotAuthenticated {
+            return self
+                .send_line(&format!("{} NO Not authenticated", tag))
+                .await;
+        }
+
+        let (mailbox, items) = match parse_status_args(args) {
+            Some(parsed) => parsed,
+            None => {
+                return self
+                    .send_line(&format!("{} BAD Invalid STATUS arguments", tag))
+                    .await;
+            }
+        };
+
+        // Only support INBOX for now
+        if mailbox.to_uppercase() != "INBOX" {
+            return self
+                .send_line(&format!("{} NO Mailbox does not exist", tag))
+                .await;
+        }
 
-        self.send_line(&format!("{} OK [READ-ONLY] SELECT completed", tag))
+        let user = match &self.authenticated_user {
+            Some(u) => u.clone(),
+            None => {
+                return self
+                    .send_line(&format!("{} NO Not authenticated", tag))
+                    .await;
+            }
+        };
+
+        let full_address = format!("{}@{}", user, self.domain_name);
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
+        let uid_state = match self.storage.get_mailbox_uid_state(&full_address).await {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load UID state for {}: {}", full_address, e);
+                return self.send_line(&format!("{} NO STATUS failed", tag)).await;
+            }
+        };
+        let count = emails.len();
+        let unseen = emails.iter().filter(|e| !e.read).count();
+
+        let mut values = Vec::new();
+        for item in &items {
+            let value = match item.as_str() {
+                "MESSAGES" => count as u64,
+                // Unread messages are reported as recent, matching SELECT
+                "RECENT" | "UNSEEN" => unseen as u64,
+                "UIDNEXT" => uid_state.uid_next as u64,
+                "UIDVALIDITY" => uid_state.uid_validity as u64,
+                "HIGHESTMODSEQ" => {
+                    self.condstore_enabled = true;
+                    uid_state.highest_modseq
+                }
+                _ => {
+                    return self
+                        .send_line(&format!("{} BAD Unknown STATUS item {}", tag, item))
+                        .await;
+                }
+            };
+            values.push(format!("{} {}", item, value));
+        }
+
+        self.send_line(&format!("* STATUS INBOX ({})", values.join(" ")))
+            .await?;
+        self.send_line(&format!("{} OK STATUS completed", tag))
             .await
     }
 
-    async fn cmd_examine(&mut self, tag: &str, args: &str) -> Result<()> {
-        // EXAMINE is like SELECT but read-only (which our SELECT already is)
-        self.cmd_select(tag, args).await
+    async fn cmd_getquotaroot(&mut self, tag: &str, args: &str) -> Result<()> {
+        if self.state == ImapState::NotAuthenticated {
+            return self
+                .send_line(&format!("{} NO Not authenticated", tag))
+                .await;
+        }
+
+        // Only support INBOX for now
+        if unquote(args.trim()).to_uppercase() != "INBOX" {
+            return self
+                .send_line(&format!("{} NO Mailbox does not exist", tag))
+                .await;
+        }
+
+        // Without limits INBOX has no quota root
+        if !self.mailbox_quota.is_enabled() {
+            self.send_line("* QUOTAROOT INBOX").await?;
+            return self
+                .send_line(&format!("{} OK GETQUOTAROOT completed", tag))
+                .await;
+        }
+
+        let user = match &self.authenticated_user {
+            Some(u) => u.clone(),
+            None => {
+                return self
+                    .send_line(&format!("{} NO Not authenticated", tag))
+                    .await;
+            }
+        };
+
+        let full_address = format!("{}@{}", user, self.domain_name);
+        let quota =
+            match quota_response(self.storage.as_ref(), &self.mailbox_quota, &full_address).await {
+                Ok(quota) => quota,
+                Err(e) => {
+                    error!("Failed to compute quota usage for {}: {}", full_address, e);
+                    return self
+                        .send_line(&format!("{} NO GETQUOTAROOT failed", tag))
+                        .await;
+                }
+            };
+        self.send_line("* QUOTAROOT INBOX \"\"").await?;
+        self.send_line(&quota).await?;
+        self.send_line(&format!("{} OK GETQUOTAROOT completed", tag))
+            .await
+    }
+
+    async fn cmd_getquota(&mut self, tag: &str, args: &str) -> Result<()> {
+        if self.state == ImapState::NotAuthenticated {
+            return self
+                .send_line(&format!("{} NO Not authenticated", tag))
+                .await;
+        }
+
+        // The mailbox's only quota root is ""
+        if !unquote(args.trim()).is_empty() || !self.mailbox_quota.is_enabled() {
+            return self
+                .send_line(&format!("{} NO No such quota root", tag))
+                .await;
+        }
+
+        let user = match &self.authenticated_user {
+            Some(u) => u.clone(),
+            None => {
+                return self
+                    .send_line(&format!("{} NO Not authenticated", tag))
+                    .await;
+            }
+        };
+
+        let full_address = format!("{}@{}", user, self.domain_name);
+        let quota =
+            match quota_response(self.storage.as_ref(), &self.mailbox_quota, &full_address).await {
+                Ok(quota) => quota,
+                Err(e) => {
+                    error!("Failed to compute quota usage for {}: {}", full_address, e);
+                    return self.send_line(&format!("{} NO GETQUOTA failed", tag)).await;
+                }
+            };
+        self.send_line(&quota).await?;
+        self.send_line(&format!("{} OK GETQUOTA completed", tag))
+            .await
     }
 
     async fn cmd_fetch(&mut self, tag: &str, args: &str) -> Result<()> {
@@ -464,6 +954,7 @@ impl ImapConnection {
                 self.do_fetch(tag, subparts[0], subparts[1], true).await
             }
             "SEARCH" => self.do_search(tag, subargs, true).await,
+            "STORE" => self.do_store(tag, subargs, true).await,
             _ => {
                 self.send_line(&format!("{} BAD Unknown UID subcommand", tag))
                     .await
@@ -487,23 +978,44 @@ impl ImapConnection {
             }
         };
 
+        let Some((data_items, changed_since)) = split_changedsince(data_items) else {
+            return self
+                .send_line(&format!("{} BAD Invalid CHANGEDSINCE modifier", tag))
+                .await;
+        };
+
         let full_address = format!("{}@{}", user, self.domain_name);
-        let emails = self
-            .storage
-            .get_emails_for_address(&full_address)
-            .await
-            .unwrap_or_default();
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
 
         // Parse sequence set
-        let indices = parse_sequence_set(sequence_set, emails.len(), use_uid);
+        let indices = resolve_sequence_set(sequence_set, &emails, use_uid);
 
         // Parse what data items to fetch
-        let items = data_items.to_uppercase();
-        let want_envelope = items.contains("ENVELOPE");
-        let want_body = items.contains("BODY") || items.contains("RFC822");
-        let want_flags = items.contains("FLAGS");
-        let want_uid = items.contains("UID") || use_uid;
-        let want_internaldate = items.contains("INTERNALDATE");
+        let items = parse_fetch_items(data_items);
+        if changed_since.is_some() || items.contains(&FetchItem::ModSeq) {
+            self.condstore_enabled = true;
+        }
+        let want_modseq = self.condstore_enabled;
+        let want_envelope = items.contains(&FetchItem::Envelope);
+        let want_flags = items.contains(&FetchItem::Flags);
+        let want_uid = items.contains(&FetchItem::Uid) || use_uid;
+        let want_internaldate = items.contains(&FetchItem::InternalDate);
+        let want_size = items.contains(&FetchItem::Rfc822Size);
+        let structures: Vec<&str> = items
+            .iter()
+            .filter_map(|item| match item {
+                FetchItem::Body => Some("BODY"),
+                FetchItem::BodyStructure => Some("BODYSTRUCTURE"),
+                _ => None,
+            })
+            .collect();
+        let sections: Vec<&BodySection> = items
+            .iter()
+            .filter_map(|item| match item {
+                FetchItem::Section(section) => Some(section),
+                _ => None,
+            })
+            .collect();
 
         for idx in indices {
             if idx == 0 || idx > emails.len() {
@@ -511,61 +1023,76 @@ impl ImapConnection {
             }
 
             let email = &emails[idx - 1];
-            let mut response_parts = Vec::new();
+            let modseq = email.modseq.unwrap_or(1);
+            if changed_since.is_some_and(|since| modseq <= since) {
+                continue;
+            }
+            let mut response_parts: Vec<Vec<u8>> = Vec::new();
 
             if want_flags {
-                response_parts.push("FLAGS ()".to_string());
+                response_parts
+                    .push(format!("FLAGS ({})", imap_flags(email).join(" ")).into_bytes());
             }
 
             if want_uid {
-                response_parts.push(format!("UID {}", idx));
+                response_parts.push(format!("UID {}", email.uid.unwrap_or_default()).into_bytes());
+            }
+
+            if want_modseq {
+                response_parts.push(format!("MODSEQ ({})", modseq).into_bytes());
             }
 
             if want_internaldate {
-                let date = email.timestamp.format("%d-%b-%Y %H:%M:%S %z");
-                response_parts.push(format!("INTERNALDATE \"{}\"", date));
+                response_parts.push(
+                    format!("INTERNALDATE \"{}\"", internal_date(&email.timestamp)).into_bytes(),
+                );
             }
 
             if want_envelope {
+                let reply_to = email.reply_to.as_deref().unwrap_or(&email.from);
+                let cc: Vec<&str> = email.cc.iter().map(String::as_str).collect();
                 let envelope = format!(
-                    "ENVELOPE (\"{}\" \"{}\" ((NIL NIL \"{}\" \"{}\")) ((NIL NIL \"{}\" \"{}\")) ((NIL NIL \"{}\" \"{}\")) ((NIL NIL \"{}\" \"{}\")) NIL NIL NIL NIL)",
-                    email.timestamp.format("%a, %d %b %Y %H:%M:%S %z"),
+                    "ENVELOPE (\"{}\" \"{}\" {} {} {} {} {} NIL NIL NIL)",
+                    rfc5322_date(&email.timestamp),
                     escape_imap_string(&email.subject),
-                    extract_local_part(&email.from),
-                    extract_domain(&email.from),
-                    extract_local_part(&email.from),
-                    extract_domain(&email.from),
-                    extract_local_part(&email.from),
-                    extract_domain(&email.from),
-                    extract_local_part(&email.to),
-                    extract_domain(&email.to),
+                    envelope_address_list(&[&email.from]),
+                    envelope_address_list(&[&email.from]),
+                    envelope_address_list(&[reply_to]),
+                    envelope_address_list(&[&email.to]),
+                    envelope_address_list(&cc),
                 );
-                response_parts.push(envelope);
+                response_parts.push(envelope.into_bytes());
             }
 
-            if want_body {
-                // Build RFC822-style message
-                let rfc822 = if let Some(raw) = &email.raw {
-                    raw.clone()
+            let needs_message = !structures.is_empty() || !sections.is_empty();
+            if want_size && !needs_message && email.raw.is_some() {
+                // FETCH serves the raw message, whose length was recorded when it was stored
+                response_parts.push(format!("RFC822.SIZE {}", email.size_bytes).into_bytes());
+            } else if want_size || needs_message {
+                let rfc822 = rfc822_message(email, &self.domain_name);
+                let raw = rfc822.as_bytes();
+                let parsed = if structures.is_empty() && sections.is_empty() {
+                    None
                 } else {
-                    format!(
-                        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\r\n{}",
-                        email.from,
-                        email.to,
-                        email.subject,
-                        email.timestamp.format("%a, %d %b %Y %H:%M:%S %z"),
-                        email.id,
-                        self.domain_name,
-                        email.body
-                    )
+                    MessageParser::default().parse(raw)
                 };
 
-                let body_len = rfc822.len();
-                response_parts.push(format!("BODY[] {{{}}}\r\n{}", body_len, rfc822));
+                if want_size {
+                    response_parts.push(format!("RFC822.SIZE {}", raw.len()).into_bytes());
+                }
+                for name in &structures {
+                    let structure = fetch::body_structure(raw, parsed.as_ref());
+                    response_parts.push(format!("{} {}", name, structure).into_bytes());
+                }
+                for section in &sections {
+                    response_parts.push(fetch::section_response(section, raw, parsed.as_ref()));
+                }
             }
 
-            let response = format!("* {} FETCH ({})", idx, response_parts.join(" "));
-            self.send_line(&response).await?;
+            let mut response = format!("* {} FETCH (", idx).into_bytes();
+            response.extend(response_parts.join(&b' '));
+            response.extend_from_slice(b")\r\n");
+            self.send_bytes(&response).await?;
         }
 
         let cmd_name = if use_uid { "UID FETCH" } else { "FETCH" };
@@ -573,17 +1100,31 @@ impl ImapConnection {
             .await
     }
 
-    async fn cmd_search(&mut self, tag: &str, args: &str) -> Result<()> {
+    async fn cmd_store(&mut self, tag: &str, args: &str) -> Result<()> {
         if !matches!(self.state, ImapState::Selected(_)) {
             return self
                 .send_line(&format!("{} NO No mailbox selected", tag))
                 .await;
         }
 
-        self.do_search(tag, args, false).await
+        self.do_store(tag, args, false).await
     }
 
-    async fn do_search(&mut self, tag: &str, args: &str, use_uid: bool) -> Result<()> {
+    async fn do_store(&mut self, tag: &str, args: &str, use_uid: bool) -> Result<()> {
+        let cmd_name = if use_uid { "UID STORE" } else { "STORE" };
+
+        if self.read_only {
+            return self
+                .send_line(&format!("{} NO Mailbox is read-only", tag))
+                .await;
+        }
+
+        let Some(store) = parse_store_args(args) else {
+            return self
+                .send_line(&format!("{} BAD Invalid {} arguments", tag, cmd_name))
+                .await;
+        };
+
         let user = match &self.authenticated_user {
             Some(u) => u.clone(),
             None => {
@@ -594,57 +1135,341 @@ impl ImapConnection {
         };
 
         let full_address = format!("{}@{}", user, self.domain_name);
-        let emails = self
-            .storage
-            .get_emails_for_address(&full_address)
-            .await
-            .unwrap_or_default();
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
 
-        // Simple search implementation - just return all message numbers for now
-        // A real implementation would parse the search criteria
-        let args_upper = args.to_uppercase();
+        for idx in resolve_sequence_set(&store.sequence_set, &emails, use_uid) {
+            if idx == 0 || idx > emails.len() {
+                continue;
+            }
 
-        let results: Vec<usize> = if args_upper.contains("ALL") || args_upper.is_empty() {
-            (1..=emails.len()).collect()
-        } else {
-            // For any other search, return all for now
-            // TODO: Implement proper search criteria parsing
-            (1..=emails.len()).collect()
-        };
+            let email = &emails[idx - 1];
+            let current = imap_flags(email);
+            let updated: Vec<String> = match store.mode {
+                StoreMode::Replace => store.flags.clone(),
+                StoreMode::Add => {
+                    let mut flags = current.clone();
+                    for flag in &store.flags {
+                        if !flags.contains(flag) {
+                            flags.push(flag.clone());
+                        }
+                    }
+                    flags
+                }
+                StoreMode::Remove => current
+                    .iter()
+                    .filter(|f| !store.flags.contains(f))
+                    .cloned()
+                    .collect(),
+            };
+
+            let read = updated.iter().any(|f| f == "\\Seen");
+            let other_flags: Vec<String> = updated.into_iter().filter(|f| f != "\\Seen").collect();
+
+            if read != email.read {
+                if let Err(e) = self.storage.mark_email_read(&email.id, read).await {
+                    error!("Failed to update read state for {}: {}", email.id, e);
+                    return self
+                        .send_line(&format!("{} NO {} failed", tag, cmd_name))
+                        .await;
+                }
+            }
+            if other_flags != email.flags {
+                if let Err(e) = self.storage.set_email_flags(&email.id, &other_flags).await {
+                    error!("Failed to update flags for {}: {}", email.id, e);
+                    return self
+                        .send_line(&format!("{} NO {} failed", tag, cmd_name))
+                        .await;
+                }
+            }
 
-        if results.is_empty() {
-            self.send_line("* SEARCH").await?;
-        } else {
-            let result_str = results
-                .iter()
-                .map(|n| n.to_string())
-                .collect::<Vec<_>>()
-                .join(" ");
-            self.send_line(&format!("* SEARCH {}", result_str)).await?;
+            if !store.silent {
+                let mut flags = other_flags;
+                if read {
+                    flags.insert(0, "\\Seen".to_string());
+                }
+                let uid = if use_uid {
+                    format!(" UID {}", email.uid.unwrap_or_default())
+                } else {
+                    String::new()
+                };
+                let modseq = if self.condstore_enabled {
+                    // Reload the email for the mod-sequence its update was given
+                    let modseq = match self.storage.get_email_by_id(&email.id).await {
+                        Ok(Some(updated)) => updated.modseq,
+                        _ => email.modseq,
+                    };
+                    format!(" MODSEQ ({})", modseq.unwrap_or(1))
+                } else {
+                    String::new()
+                };
+                self.send_line(&format!(
+                    "* {} FETCH (FLAGS ({}){}{})",
+                    idx,
+                    flags.join(" "),
+                    uid,
+                    modseq
+                ))
+                .await?;
+            }
         }
 
-        let cmd_name = if use_uid { "UID SEARCH" } else { "SEARCH" };
         self.send_line(&format!("{} OK {} completed", tag, cmd_name))
             .await
     }
 
-    async fn cmd_close(&mut self, tag: &str) -> Result<()> {
+    async fn cmd_search(&mut self, tag: &str, args: &str) -> Result<()> {
         if !matches!(self.state, ImapState::Selected(_)) {
             return self
                 .send_line(&format!("{} NO No mailbox selected", tag))
                 .await;
         }
 
-        self.state = ImapState::Authenticated;
-        self.send_line(&format!("{} OK CLOSE completed", tag)).await
+        self.do_search(tag, args, false).await
     }
-}
 
-// Helper functions
+    async fn do_search(&mut self, tag: &str, args: &str, use_uid: bool) -> Result<()> {
+        let user = match &self.authenticated_user {
+            Some(u) => u.clone(),
+            None => {
+                return self
+                    .send_line(&format!("{} NO Not authenticated", tag))
+                    .await;
+            }
+        };
 
-/// Parse LOGIN arguments (username and password, possibly quoted)
-fn parse_login_args(args: &str) -> Option<(String, String)> {
-    let mut parts = Vec::new();
+        let full_address = format!("{}@{}", user, self.domain_name);
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
+
+        let mut matching: Option<HashSet<String>> = None;
+        for criterion in parse_search_criteria(args) {
+            let ids: HashSet<String> = match criterion {
+                SearchCriterion::All => continue,
+                SearchCriterion::Text(term) => self
+                    .storage
+                    .search_emails_for_address(&full_address, &term)
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|e| e.id)
+                    .collect(),
+                SearchCriterion::Subject(term) => {
+                    let needle = term.to_lowercase();
+                    self.storage
+                        .search_emails_for_address(&full_address, &term)
+                        .await
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|e| e.subject.to_lowercase().contains(&needle))
+                        .map(|e| e.id)
+                        .collect()
+                }
+            };
+            matching = Some(match matching {
+                Some(existing) => existing.intersection(&ids).cloned().collect(),
+                None => ids,
+            });
+        }
+
+        // Message numbers are 1-based positions in the mailbox listing
+        let results: Vec<usize> = emails
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| matching.as_ref().is_none_or(|ids| ids.contains(&e.id)))
+            .map(|(i, e)| {
+                if use_uid {
+                    e.uid.unwrap_or_default() as usize
+                } else {
+                    i + 1
+                }
+            })
+            .collect();
+
+        if results.is_empty() {
+            self.send_line("* SEARCH").await?;
+        } else {
+            let result_str = results
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.send_line(&format!("* SEARCH {}", result_str)).await?;
+        }
+
+        let cmd_name = if use_uid { "UID SEARCH" } else { "SEARCH" };
+        self.send_line(&format!("{} OK {} completed", tag, cmd_name))
+            .await
+    }
+
+    /// Wait for new emails, reporting each as `* n EXISTS`, until the client sends DONE
+    async fn cmd_idle(&mut self, tag: &str) -> Result<()> {
+        if !matches!(self.state, ImapState::Selected(_)) {
+            return self
+                .send_line(&format!("{} NO No mailbox selected", tag))
+                .await;
+        }
+
+        let user = match &self.authenticated_user {
+            Some(u) => u.clone(),
+            None => {
+                return self
+                    .send_line(&format!("{} NO Not authenticated", tag))
+                    .await;
+            }
+        };
+        let full_address = format!("{}@{}", user, self.domain_name);
+
+        // Subscribe before replying so nothing arriving in between is missed
+        let mut receiver = self.email_sender.subscribe();
+        let mut channel_closed = false;
+        self.send_line("+ idling").await?;
+
+        // read_until keeps partial input in `line` if the other branch wins the select
+        let mut line = Vec::new();
+        loop {
+            tokio::select! {
+                read = self.stream.read_until(b'\n', &mut line) => {
+                    if read? == 0 {
+                        debug!("IMAP client disconnected during IDLE");
+                        return Ok(());
+                    }
+                    if !line.ends_with(b"\n") {
+                        continue;
+                    }
+
+                    let done = String::from_utf8_lossy(&line).trim().eq_ignore_ascii_case("DONE");
+                    return if done {
+                        self.send_line(&format!("{} OK IDLE terminated", tag)).await
+                    } else {
+                        self.send_line(&format!("{} BAD Expected DONE", tag)).await
+                    };
+                }
+                received = receiver.recv(), if !channel_closed => {
+                    let notify = match received {
+                        Ok(email) => email.to.eq_ignore_ascii_case(&full_address),
+                        // Missed some emails, so recount to be safe
+                        Err(RecvError::Lagged(_)) => true,
+                        Err(RecvError::Closed) => {
+                            channel_closed = true;
+                            false
+                        }
+                    };
+
+                    if notify {
+                        let count = self
+                            .storage
+                            .count_emails_for_address(&full_address)
+                            .await
+                            .unwrap_or_default();
+                        self.send_line(&format!("* {} EXISTS", count)).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove messages flagged `\Deleted`, reporting each as `* n EXPUNGE`
+    async fn cmd_expunge(&mut self, tag: &str) -> Result<()> {
+        if !matches!(self.state, ImapState::Selected(_)) {
+            return self
+                .send_line(&format!("{} NO No mailbox selected", tag))
+                .await;
+        }
+        if self.read_only {
+            return self
+                .send_line(&format!("{} NO Mailbox is read-only", tag))
+                .await;
+        }
+
+        match self.expunge().await {
+            Ok(expunged) => {
+                for seq in expunged {
+                    self.send_line(&format!("* {} EXPUNGE", seq)).await?;
+                }
+                self.send_line(&format!("{} OK EXPUNGE completed", tag))
+                    .await
+            }
+            Err(e) => {
+                error!("IMAP EXPUNGE failed: {}", e);
+                self.send_line(&format!("{} NO EXPUNGE failed", tag)).await
+            }
+        }
+    }
+
+    async fn cmd_close(&mut self, tag: &str) -> Result<()> {
+        if !matches!(self.state, ImapState::Selected(_)) {
+            return self
+                .send_line(&format!("{} NO No mailbox selected", tag))
+                .await;
+        }
+
+        // CLOSE expunges silently, and never for a mailbox opened with EXAMINE
+        if !self.read_only {
+            if let Err(e) = self.expunge().await {
+                error!("IMAP CLOSE failed to expunge: {}", e);
+            }
+        }
+
+        self.state = ImapState::Authenticated;
+        self.send_line(&format!("{} OK CLOSE completed", tag)).await
+    }
+
+    /// Delete the selected mailbox's messages flagged `\Deleted`
+    ///
+    /// Returns their sequence numbers in descending order, so each stays valid as the
+    /// ones after it are removed. Deletions are announced to WebSocket clients and
+    /// webhooks like any other deletion.
+    async fn expunge(&mut self) -> Result<Vec<usize>> {
+        let Some(user) = self.authenticated_user.clone() else {
+            return Ok(Vec::new());
+        };
+        let full_address = format!("{}@{}", user, self.domain_name);
+        let emails = mailbox_emails(self.storage.as_ref(), &full_address).await;
+        let deletion_notifier = DeletionNotifier::new(
+            WebhookTrigger::new(self.storage.clone()),
+            self.deletion_sender.clone(),
+        );
+
+        let mut expunged = Vec::new();
+        for (idx, email) in emails.iter().enumerate().rev() {
+            if !email.flags.iter().any(|f| f == "\\Deleted") {
+                continue;
+            }
+            let Some(email) = self.storage.delete_email_by_id(&email.id).await? else {
+                continue;
+            };
+            expunged.push(idx + 1);
+
+            deletion_notifier
+                .handle_email_deletion(&email.id, &email.to)
+                .await;
+        }
+
+        if !expunged.is_empty() {
+            info!(
+                "IMAP expunged {} message(s) from {}",
+                expunged.len(),
+                full_address
+            );
+        }
+        Ok(expunged)
+    }
+}
+
+// Helper functions
+
+/// Load a mailbox's emails in UID order, so message sequence numbers follow UIDs
+async fn mailbox_emails(storage: &dyn StorageBackend, address: &str) -> Vec<Email> {
+    let mut emails = storage
+        .get_emails_for_address(address)
+        .await
+        .unwrap_or_default();
+    emails.sort_by_key(|e| e.uid);
+    emails
+}
+
+/// Parse LOGIN arguments (username and password, possibly quoted)
+fn parse_login_args(args: &str) -> Option<(String, String)> {
+    let mut parts = Vec::new();
     let mut current = String::new();
     let mut in_quotes = false;
     let chars = args.chars().peekable();
@@ -685,7 +1510,52 @@ fn parse_list_args(args: &str) -> (String, String) {
     (reference.to_string(), pattern.to_string())
 }
 
+/// Parse STATUS arguments into the mailbox name and upper-cased status items,
+/// e.g. `INBOX (MESSAGES UNSEEN)`
+fn parse_status_args(args: &str) -> Option<(String, Vec<String>)> {
+    let open = args.find('(')?;
+    let close = args.rfind(')')?;
+    if close < open {
+        return None;
+    }
+
+    let mailbox = unquote(&args[..open]);
+    let items: Vec<String> = args[open + 1..close]
+        .split_whitespace()
+        .map(|item| item.to_uppercase())
+        .collect();
+
+    if mailbox.is_empty() || items.is_empty() {
+        return None;
+    }
+
+    Some((mailbox.to_string(), items))
+}
+
 /// Remove surrounding quotes from a string
+/// `* QUOTA` response with a mailbox's usage of each configured limit: STORAGE in
+/// units of 1024 octets and MESSAGE as an email count
+async fn quota_response(
+    storage: &dyn StorageBackend,
+    mailbox_quota: &MailboxQuotaConfig,
+    address: &str,
+) -> Result<String> {
+    let mut resources = Vec::new();
+    if let Some(max_bytes) = mailbox_quota.max_bytes {
+        let used = storage.total_bytes_for_address(address).await?;
+        resources.push(format!(
+            "STORAGE {} {}",
+            (used.max(0) as u64).div_ceil(1024),
+            max_bytes / 1024
+        ));
+    }
+    if let Some(max_emails) = mailbox_quota.max_emails {
+        let used = storage.count_emails_for_address(address).await?;
+        resources.push(format!("MESSAGE {} {}", used, max_emails));
+    }
+    Ok(format!("* QUOTA \"\" ({})", resources.join(" ")))
+}
+
 fn unquote(s: &str) -> &str {
     let s = s.trim();
     if s.starts_with('"') && s.ends_with('"') && s.len() >= 2 {
@@ -695,8 +1565,196 @@ fn unquote(s: &str) -> &str {
     }
 }
 
+/// Flags that can be changed with STORE and are persisted in storage
+const PERMANENT_FLAGS: &[&str] = &["\\Seen", "\\Answered", "\\Flagged", "\\Deleted", "\\Draft"];
+
+/// All IMAP flags currently set on an email
+fn imap_flags(email: &Email) -> Vec<String> {
+    let mut flags = Vec::new();
+    if email.read {
+        flags.push("\\Seen".to_string());
+    }
+    flags.extend(email.flags.iter().cloned());
+    flags
+}
+
+/// How a STORE command combines the given flags with the existing ones
+#[derive(Debug, PartialEq)]
+enum StoreMode {
+    Replace,
+    Add,
+    Remove,
+}
+
+/// Parsed arguments of a STORE command
+#[derive(Debug, PartialEq)]
+struct StoreArgs {
+    sequence_set: String,
+    mode: StoreMode,
+    silent: bool,
+    flags: Vec<String>,
+}
+
+/// Parse STORE arguments (e.g. `1:3 +FLAGS.SILENT (\Seen \Flagged)`)
+///
+/// Flags are normalized to their canonical case; unsupported flags are dropped.
+fn parse_store_args(args: &str) -> Option<StoreArgs> {
+    let mut parts = args.trim().splitn(3, ' ');
+    let sequence_set = parts.next().filter(|s| !s.is_empty())?.to_string();
+    let item = parts.next()?.to_uppercase();
+    let flag_list = parts.next().unwrap_or("").trim();
+
+    let (item, silent) = match item.strip_suffix(".SILENT") {
+        Some(item) => (item.to_string(), true),
+        None => (item, false),
+    };
+    let mode = match item.as_str() {
+        "FLAGS" => StoreMode::Replace,
+        "+FLAGS" => StoreMode::Add,
+        "-FLAGS" => StoreMode::Remove,
+        _ => return None,
+    };
+
+    let flag_list = flag_list
+        .strip_prefix('(')
+        .and_then(|f| f.strip_suffix(')'))
+        .unwrap_or(flag_list);
+    let mut flags: Vec<String> = Vec::new();
+    for flag in flag_list.split_whitespace() {
+        if let Some(canonical) = PERMANENT_FLAGS
+            .iter()
+            .find(|f| f.eq_ignore_ascii_case(flag))
+        {
+            if !flags.iter().any(|f| f == canonical) {
+                flags.push(canonical.to_string());
+            }
+        }
+    }
+
+    Some(StoreArgs {
+        sequence_set,
+        mode,
+        silent,
+        flags,
+    })
+}
+
+/// A single SEARCH criterion understood by this server
+#[derive(Debug, PartialEq)]
+enum SearchCriterion {
+    All,
+    Text(String),
+    Subject(String),
+}
+
+/// Parse SEARCH arguments into criteria, ignoring unsupported keys
+fn parse_search_criteria(args: &str) -> Vec<SearchCriterion> {
+    // Split into tokens, keeping quoted strings together
+    let mut tokens = Vec::new();
+    let mut chars = args.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            token.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => token.push(c),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    let mut criteria = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token.to_uppercase().as_str() {
+            "ALL" => criteria.push(SearchCriterion::All),
+            "TEXT" | "BODY" => {
+                if let Some(term) = tokens.next() {
+                    criteria.push(SearchCriterion::Text(term));
+                }
+            }
+            "SUBJECT" => {
+                if let Some(term) = tokens.next() {
+                    criteria.push(SearchCriterion::Subject(term));
+                }
+            }
+            _ => {}
+        }
+    }
+    criteria
+}
+
+/// Resolve a sequence set to message sequence numbers, reading it as a UID set when
+/// `use_uid` is set; `emails` must be in UID order
+fn resolve_sequence_set(set: &str, emails: &[Email], use_uid: bool) -> Vec<usize> {
+    if use_uid {
+        let uids: Vec<u32> = emails.iter().map(|e| e.uid.unwrap_or_default()).collect();
+        parse_uid_set(set, &uids)
+    } else {
+        parse_sequence_set(set, emails.len())
+    }
+}
+
+/// Parse an IMAP UID set against the mailbox's ascending UIDs, returning the sequence
+/// numbers of the matching messages
+///
+/// `*` stands for the highest UID in use, so `n:*` always includes the last message.
+fn parse_uid_set(set: &str, uids: &[u32]) -> Vec<usize> {
+    let max = uids.last().copied().unwrap_or(0);
+    let bound = |s: &str| {
+        if s == "*" {
+            Some(max)
+        } else {
+            s.parse::<u32>().ok()
+        }
+    };
+
+    let mut ranges = Vec::new();
+    for part in set.split(',') {
+        let part = part.trim();
+        let range = match part.split_once(':') {
+            Some((start, end)) => match (bound(start), bound(end)) {
+                (Some(start), Some(end)) => Some((start.min(end), start.max(end))),
+                _ => None,
+            },
+            None => bound(part).map(|uid| (uid, uid)),
+        };
+        ranges.extend(range);
+    }
+
+    uids.iter()
+        .enumerate()
+        .filter(|(_, uid)| {
+            ranges
+                .iter()
+                .any(|(start, end)| (start..=end).contains(uid))
+        })
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
 /// Parse IMAP sequence set (e.g., "1", "1:5", "1,3,5", "*")
-fn parse_sequence_set(set: &str, total: usize, _use_uid: bool) -> Vec<usize> {
+fn parse_sequence_set(set: &str, total: usize) -> Vec<usize> {
     let mut result = Vec::new();
 
     for part in set.split(',') {
@@ -754,10 +1812,273 @@ fn extract_domain(email: &str) -> &str {
     email.split('@').nth(1).unwrap_or("")
 }
 
+/// INTERNALDATE in RFC 3501's `date-time` form, e.g. `05-Mar-2024 09:07:01 +0000`
+fn internal_date(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%d-%b-%Y %H:%M:%S +0000").to_string()
+}
+
+/// RFC 5322 `date-time` for the ENVELOPE and rebuilt `Date:` header, e.g.
+/// `Tue, 05 Mar 2024 09:07:01 +0000`
+fn rfc5322_date(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%a, %d %b %Y %H:%M:%S +0000").to_string()
+}
+
+/// The message as served by FETCH: the raw message, or one rebuilt from the stored fields
+///
+/// Rebuilt messages declare the body's type and UTF-8 charset, and have CRLF line endings.
+fn rfc822_message(email: &Email, domain_name: &str) -> String {
+    if let Some(raw) = &email.raw {
+        return raw.clone();
+    }
+    let content_type = if email.body_html.is_some() {
+        "text/html"
+    } else {
+        "text/plain"
+    };
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: {}; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n{}",
+        mailbox_header(email.from_name.as_deref(), &email.from),
+        email.to,
+        header_text(&email.subject),
+        rfc5322_date(&email.timestamp),
+        email.id,
+        domain_name,
+        content_type,
+        email.body.replace("\r\n", "\n").replace('\n', "\r\n")
+    )
+}
+
+/// An address with its display name for a rebuilt header, e.g. `"Ann Example" <ann@example.com>`
+fn mailbox_header(name: Option<&str>, address: &str) -> String {
+    match name.map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) if is_plain_header_text(name) => format!(
+            "\"{}\" <{}>",
+            name.replace('\\', "\\\\").replace('"', "\\\""),
+            address
+        ),
+        Some(name) => format!("{} <{}>", header_text(name), address),
+        None => address.to_string(),
+    }
+}
+
+/// Whether text can go into a header as is: printable ASCII and spaces only
+fn is_plain_header_text(text: &str) -> bool {
+    text.chars().all(|c| c == ' ' || c.is_ascii_graphic())
+}
+
+/// Text for an unstructured header value, with line breaks flattened to spaces and anything
+/// beyond printable ASCII written as RFC 2047 encoded-words, folded onto continuation lines
+fn header_text(text: &str) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    // Input bytes per encoded-word, keeping each word within RFC 2047's 75 characters
+    const WORD_BYTES: usize = 45;
+
+    let text = text.replace(['\r', '\n', '\t'], " ");
+    if is_plain_header_text(&text) {
+        return text;
+    }
+
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > WORD_BYTES {
+            words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+    words.join("\r\n ")
+}
+
+/// Format addresses as an ENVELOPE address list, or `NIL` when there are none
+fn envelope_address_list(addresses: &[&str]) -> String {
+    if addresses.is_empty() {
+        return "NIL".to_string();
+    }
+
+    let entries: Vec<String> = addresses
+        .iter()
+        .map(|addr| {
+            format!(
+                "(NIL NIL \"{}\" \"{}\")",
+                escape_imap_string(extract_local_part(addr)),
+                escape_imap_string(extract_domain(addr))
+            )
+        })
+        .collect();
+    format!("({})", entries.join(""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_date_formats() {
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2024, 3, 5, 9, 7, 1).unwrap();
+        assert_eq!(internal_date(&timestamp), "05-Mar-2024 09:07:01 +0000");
+        assert_eq!(rfc5322_date(&timestamp), "Tue, 05 Mar 2024 09:07:01 +0000");
+
+        let timestamp = Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(internal_date(&timestamp), "31-Dec-2023 23:59:59 +0000");
+        assert_eq!(rfc5322_date(&timestamp), "Sun, 31 Dec 2023 23:59:59 +0000");
+    }
+
+    #[test]
+    fn test_rebuilt_message_headers() {
+        let raw = "From: \"Ann \\\"The\\\" Example\" <ann@example.com>\r\nTo: box@example.com\r\n\
+                   Subject: Hello\r\n\r\nLine one\nLine two\n";
+        let mut named =
+            crate::smtp::parser::parse_email(raw.as_bytes(), "box@example.com").unwrap();
+        assert_eq!(named.from_name.as_deref(), Some("Ann \"The\" Example"));
+        named.raw = None;
+        named.body = "Line one\nLine two\n".to_string();
+        named.body_html = None;
+
+        let mut unnamed = named.clone();
+        unnamed.from_name = None;
+
+        let header = |email: &Email| {
+            rfc822_message(email, "example.com")
+                .lines()
+                .find(|line| line.starts_with("From: "))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            header(&named),
+            "From: \"Ann \\\"The\\\" Example\" <ann@example.com>"
+        );
+        assert_eq!(header(&unnamed), "From: ann@example.com");
+
+        // Clients parse the rebuilt message back into the same fields
+        let rebuilt = rfc822_message(&named, "example.com");
+        assert!(rebuilt.ends_with(
+            "Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 8bit\r\n\r\nLine one\r\nLine two\r\n"
+        ));
+        assert_eq!(
+            rebuilt.matches('\n').count(),
+            rebuilt.matches("\r\n").count()
+        );
+        let message = MessageParser::default().parse(rebuilt.as_bytes()).unwrap();
+        let from = message.from().and_then(|addrs| addrs.first()).unwrap();
+        assert_eq!(from.name(), Some("Ann \"The\" Example"));
+        assert_eq!(from.address(), Some("ann@example.com"));
+        assert_eq!(
+            message.message_id(),
+            Some(format!("{}@example.com", named.id).as_str())
+        );
+
+        // Names and subjects beyond ASCII are sent as encoded-words
+        let mut encoded = named.clone();
+        encoded.from_name = Some("Zoë Müller".to_string());
+        encoded.subject = format!("Grüße {}\r\nBcc: injected@example.com", "ü".repeat(40));
+        let rebuilt = rfc822_message(&encoded, "example.com");
+        assert!(!rebuilt.contains("\nBcc:"));
+        assert!(rebuilt
+            .split_whitespace()
+            .filter(|word| word.starts_with("=?"))
+            .all(|word| word.len() <= 75));
+        let message = MessageParser::default().parse(rebuilt.as_bytes()).unwrap();
+        let from = message.from().and_then(|addrs| addrs.first()).unwrap();
+        assert_eq!(from.name(), Some("Zoë Müller"));
+        assert_eq!(
+            message.subject(),
+            Some(encoded.subject.replace("\r\n", "  ").as_str())
+        );
+    }
+
+    #[test]
+    fn test_envelope_address_list() {
+        assert_eq!(envelope_address_list(&[]), "NIL");
+        assert_eq!(
+            envelope_address_list(&["a@example.com"]),
+            "((NIL NIL \"a\" \"example.com\"))"
+        );
+        assert_eq!(
+            envelope_address_list(&["a@example.com", "b@example.org"]),
+            "((NIL NIL \"a\" \"example.com\")(NIL NIL \"b\" \"example.org\"))"
+        );
+    }
+
+    #[test]
+    fn test_parse_store_args() {
+        assert_eq!(
+            parse_store_args("1:3 +FLAGS (\\Seen \\flagged)"),
+            Some(StoreArgs {
+                sequence_set: "1:3".to_string(),
+                mode: StoreMode::Add,
+                silent: false,
+                flags: vec!["\\Seen".to_string(), "\\Flagged".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_store_args("2 -flags.silent \\Seen"),
+            Some(StoreArgs {
+                sequence_set: "2".to_string(),
+                mode: StoreMode::Remove,
+                silent: true,
+                flags: vec!["\\Seen".to_string()],
+            })
+        );
+        let replace = parse_store_args("* FLAGS ()").unwrap();
+        assert_eq!(replace.mode, StoreMode::Replace);
+        assert!(replace.flags.is_empty());
+        // Unsupported keywords are ignored
+        assert!(parse_store_args("1 FLAGS (custom)")
+            .unwrap()
+            .flags
+            .is_empty());
+        assert_eq!(parse_store_args("1 X-FLAGS (\\Seen)"), None);
+        assert_eq!(parse_store_args("1"), None);
+    }
+
+    #[test]
+    fn test_parse_search_criteria() {
+        assert_eq!(parse_search_criteria("ALL"), vec![SearchCriterion::All]);
+        assert!(parse_search_criteria("").is_empty());
+        assert_eq!(
+            parse_search_criteria(r#"TEXT "foo bar" SUBJECT baz"#),
+            vec![
+                SearchCriterion::Text("foo bar".to_string()),
+                SearchCriterion::Subject("baz".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_search_criteria(r#"CHARSET UTF-8 subject "say \"hi\"""#),
+            vec![SearchCriterion::Subject(r#"say "hi""#.to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_status_args() {
+        assert_eq!(
+            parse_status_args("INBOX (MESSAGES UIDNEXT UIDVALIDITY UNSEEN)"),
+            Some((
+                "INBOX".to_string(),
+                vec![
+                    "MESSAGES".to_string(),
+                    "UIDNEXT".to_string(),
+                    "UIDVALIDITY".to_string(),
+                    "UNSEEN".to_string()
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_status_args("\"inbox\" (messages)"),
+            Some(("inbox".to_string(), vec!["MESSAGES".to_string()]))
+        );
+        assert_eq!(parse_status_args("INBOX"), None);
+        assert_eq!(parse_status_args("INBOX ()"), None);
+        assert_eq!(parse_status_args("(MESSAGES)"), None);
+    }
+
     #[test]
     fn test_parse_login_args() {
         assert_eq!(
@@ -785,11 +2106,25 @@ mod tests {
 
     #[test]
     fn test_parse_sequence_set() {
-        assert_eq!(parse_sequence_set("1", 10, false), vec![1]);
-        assert_eq!(parse_sequence_set("1:3", 10, false), vec![1, 2, 3]);
-        assert_eq!(parse_sequence_set("1,3,5", 10, false), vec![1, 3, 5]);
-        assert_eq!(parse_sequence_set("*", 10, false), vec![10]);
-        assert_eq!(parse_sequence_set("1:*", 5, false), vec![1, 2, 3, 4, 5]);
+        assert_eq!(parse_sequence_set("1", 10), vec![1]);
+        assert_eq!(parse_sequence_set("1:3", 10), vec![1, 2, 3]);
+        assert_eq!(parse_sequence_set("1,3,5", 10), vec![1, 3, 5]);
+        assert_eq!(parse_sequence_set("*", 10), vec![10]);
+        assert_eq!(parse_sequence_set("1:*", 5), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_parse_uid_set() {
+        let uids = [2, 5, 9];
+        assert_eq!(parse_uid_set("5", &uids), vec![2]);
+        assert_eq!(parse_uid_set("3", &uids), Vec::<usize>::new());
+        assert_eq!(parse_uid_set("1:5", &uids), vec![1, 2]);
+        assert_eq!(parse_uid_set("2,9", &uids), vec![1, 3]);
+        assert_eq!(parse_uid_set("*", &uids), vec![3]);
+        assert_eq!(parse_uid_set("6:*", &uids), vec![3]);
+        // n:* includes the last message even when n is past it
+        assert_eq!(parse_uid_set("20:*", &uids), vec![3]);
+        assert_eq!(parse_uid_set("1:*", &[]), Vec::<usize>::new());
     }
 
     #[test]
@@ -799,4 +2134,792 @@ mod tests {
         assert_eq!(extract_local_part("justuser"), "justuser");
         assert_eq!(extract_domain("justuser"), "");
     }
+
+    fn testdata_ssl_config() -> SmtpSslConfig {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/imap/testdata");
+        SmtpSslConfig {
+            enabled: true,
+            cert_path: Some(dir.join("localhost.crt")),
+            key_path: Some(dir.join("localhost.key")),
+        }
+    }
+
+    async fn test_connection(
+        tls_acceptor: Option<TlsAcceptor>,
+        require_tls: bool,
+    ) -> tokio::io::DuplexStream {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            tls_acceptor,
+        )
+        .with_require_tls(require_tls);
+        tokio::spawn(async move { connection.handle().await });
+        client
+    }
+
+    /// Send a command and collect response lines up to the tagged completion
+    async fn command<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut BufReader<S>,
+        tag: &str,
+        command: &str,
+    ) -> Vec<String> {
+        stream
+            .get_mut()
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await
+            .unwrap();
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            let done = line.starts_with(&format!("{} ", tag));
+            lines.push(line.trim_end().to_string());
+            if done {
+                return lines;
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_tls_acceptor() {
+        assert!(load_tls_acceptor(&testdata_ssl_config()).is_ok());
+
+        let missing = SmtpSslConfig {
+            cert_path: Some("/nonexistent/cert.pem".into()),
+            ..testdata_ssl_config()
+        };
+        let Err(err) = load_tls_acceptor(&missing) else {
+            panic!("Expected a missing certificate to fail");
+        };
+        assert!(err.to_string().contains("Failed to open certificate"));
+    }
+
+    /// Complete the client side of a TLS handshake, trusting the test CA
+    async fn tls_handshake(
+        stream: tokio::io::DuplexStream,
+    ) -> tokio_rustls::client::TlsStream<tokio::io::DuplexStream> {
+        let ca = include_bytes!("testdata/ca.crt");
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &ca[..]) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let client_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::aws_lc_rs::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        connector.connect(server_name, stream).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_starttls_upgrade() {
+        let acceptor = load_tls_acceptor(&testdata_ssl_config()).unwrap();
+        let mut client = BufReader::new(test_connection(Some(acceptor), false).await);
+
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+        assert!(greeting.starts_with("* OK"));
+
+        let lines = command(&mut client, "a1", "CAPABILITY").await;
+        assert!(lines[0].contains("STARTTLS"));
+
+        let lines = command(&mut client, "a2", "STARTTLS").await;
+        assert_eq!(lines, vec!["a2 OK Begin TLS negotiation now"]);
+
+        let mut client = BufReader::new(tls_handshake(client.into_inner()).await);
+
+        // STARTTLS is no longer offered once the connection is encrypted
+        let lines = command(&mut client, "a3", "CAPABILITY").await;
+        assert!(!lines[0].contains("STARTTLS"));
+        assert_eq!(lines[1], "a3 OK CAPABILITY completed");
+
+        let lines = command(&mut client, "a4", "STARTTLS").await;
+        assert!(lines[0].starts_with("a4 BAD"));
+    }
+
+    #[tokio::test]
+    async fn test_require_tls_refuses_login_before_starttls() {
+        let acceptor = load_tls_acceptor(&testdata_ssl_config()).unwrap();
+        let mut client = BufReader::new(test_connection(Some(acceptor), true).await);
+
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        let lines = command(&mut client, "a1", "CAPABILITY").await;
+        assert_eq!(
+            lines[0],
+            "* CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED CONDSTORE IDLE QUOTA"
+        );
+
+        let lines = command(&mut client, "a2", "LOGIN user secret").await;
+        assert!(lines[0].starts_with("a2 NO [PRIVACYREQUIRED]"));
+        let lines = command(&mut client, "a3", "AUTHENTICATE PLAIN").await;
+        assert!(lines[0].starts_with("a3 NO [PRIVACYREQUIRED]"));
+
+        // Still not logged in
+        let lines = command(&mut client, "a4", "SELECT INBOX").await;
+        assert!(lines[0].starts_with("a4 NO"));
+
+        let lines = command(&mut client, "a5", "STARTTLS").await;
+        assert_eq!(lines, vec!["a5 OK Begin TLS negotiation now"]);
+        let mut client = BufReader::new(tls_handshake(client.into_inner()).await);
+
+        let lines = command(&mut client, "a6", "CAPABILITY").await;
+        assert_eq!(
+            lines[0],
+            "* CAPABILITY IMAP4rev1 AUTH=PLAIN LOGIN CONDSTORE IDLE QUOTA"
+        );
+
+        let lines = command(&mut client, "a7", "LOGIN user secret").await;
+        assert_eq!(lines, vec!["a7 OK LOGIN completed"]);
+    }
+
+    #[tokio::test]
+    async fn test_starttls_unavailable_without_tls_config() {
+        let mut client = BufReader::new(test_connection(None, false).await);
+
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        let lines = command(&mut client, "a1", "CAPABILITY").await;
+        assert_eq!(
+            lines[0],
+            "* CAPABILITY IMAP4rev1 AUTH=PLAIN LOGIN CONDSTORE IDLE QUOTA"
+        );
+
+        let lines = command(&mut client, "a2", "STARTTLS").await;
+        assert_eq!(lines, vec!["a2 BAD STARTTLS not available"]);
+    }
+
+    fn idle_test_email(to: &str) -> Email {
+        Email::new(
+            to.to_string(),
+            "sender@example.com".to_string(),
+            "Pushed".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_idle_notifies_new_email() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let server = ImapServer::new(
+            storage.clone(),
+            "example.com".to_string(),
+            SmtpSslConfig {
+                enabled: false,
+                cert_path: None,
+                key_path: None,
+            },
+            email_sender.clone(),
+            deletion_sender,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(listener, None, false).await });
+
+        let mut client = BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        command(&mut client, "a1", "LOGIN idler secret").await;
+        command(&mut client, "a2", "SELECT INBOX").await;
+
+        client.get_mut().write_all(b"a3 IDLE\r\n").await.unwrap();
+        let mut line = String::new();
+        client.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), "+ idling");
+
+        // Mail for another mailbox is not reported
+        let other = idle_test_email("someone@example.com");
+        storage.store_email(other.clone()).await.unwrap();
+        email_sender.send(other).unwrap();
+
+        let email = idle_test_email("idler@example.com");
+        storage.store_email(email.clone()).await.unwrap();
+        email_sender.send(email).unwrap();
+
+        line.clear();
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            client.read_line(&mut line),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(line.trim_end(), "* 1 EXISTS");
+
+        client.get_mut().write_all(b"DONE\r\n").await.unwrap();
+        line.clear();
+        client.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), "a3 OK IDLE terminated");
+
+        // Normal commands work again after IDLE
+        let lines = command(&mut client, "a4", "NOOP").await;
+        assert_eq!(lines, vec!["a4 OK NOOP completed"]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_client_disconnect() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        let handle = tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+        command(&mut client, "a1", "LOGIN idler secret").await;
+        command(&mut client, "a2", "SELECT INBOX").await;
+        client.get_mut().write_all(b"a3 IDLE\r\n").await.unwrap();
+        let mut line = String::new();
+        client.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim_end(), "+ idling");
+
+        // Dropping the client ends the session without an error
+        drop(client);
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_status_command() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        for subject in ["One", "Two", "Three"] {
+            let email = Email::new(
+                "status@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email.clone()).await.unwrap();
+            if subject == "One" {
+                storage.mark_email_read(&email.id, true).await.unwrap();
+            }
+        }
+
+        let uid_validity = storage
+            .get_mailbox_uid_state("status@example.com")
+            .await
+            .unwrap()
+            .uid_validity;
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        let lines = command(&mut client, "a1", "STATUS INBOX (MESSAGES)").await;
+        assert_eq!(lines, vec!["a1 NO Not authenticated"]);
+
+        command(&mut client, "a2", "LOGIN status secret").await;
+
+        let lines = command(
+            &mut client,
+            "a3",
+            "STATUS INBOX (MESSAGES UIDNEXT UIDVALIDITY UNSEEN)",
+        )
+        .await;
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "* STATUS INBOX (MESSAGES 3 UIDNEXT 4 UIDVALIDITY {} UNSEEN 2)",
+                    uid_validity
+                ),
+                "a3 OK STATUS completed".to_string()
+            ]
+        );
+
+        let lines = command(&mut client, "a4", "STATUS Archive (MESSAGES)").await;
+        assert_eq!(lines, vec!["a4 NO Mailbox does not exist"]);
+
+        let lines = command(&mut client, "a5", "STATUS INBOX (BOGUS)").await;
+        assert!(lines[0].starts_with("a5 BAD"));
+    }
+
+    #[tokio::test]
+    async fn test_getquotaroot_reports_usage() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        for subject in ["One", "Two"] {
+            let email = Email::new(
+                "quota@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "x".repeat(3000),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+        let used_bytes = storage
+            .total_bytes_for_address("quota@example.com")
+            .await
+            .unwrap();
+        assert!(used_bytes >= 6000);
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        )
+        .with_mailbox_quota(MailboxQuotaConfig {
+            max_emails: Some(100),
+            max_bytes: Some(1024 * 1024),
+            ..Default::default()
+        });
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        let lines = command(&mut client, "a1", "GETQUOTAROOT INBOX").await;
+        assert_eq!(lines, vec!["a1 NO Not authenticated"]);
+
+        let lines = command(&mut client, "a2", "CAPABILITY").await;
+        assert!(lines[0].ends_with("QUOTA QUOTA=RES-STORAGE QUOTA=RES-MESSAGE"));
+
+        command(&mut client, "a3", "LOGIN quota secret").await;
+
+        let quota = format!(
+            "* QUOTA \"\" (STORAGE {} 1024 MESSAGE 2 100)",
+            (used_bytes as u64).div_ceil(1024)
+        );
+        let lines = command(&mut client, "a4", "GETQUOTAROOT INBOX").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* QUOTAROOT INBOX \"\"".to_string(),
+                quota.clone(),
+                "a4 OK GETQUOTAROOT completed".to_string()
+            ]
+        );
+
+        let lines = command(&mut client, "a5", "GETQUOTA \"\"").await;
+        assert_eq!(lines, vec![quota, "a5 OK GETQUOTA completed".to_string()]);
+
+        let lines = command(&mut client, "a6", "GETQUOTA \"user\"").await;
+        assert_eq!(lines, vec!["a6 NO No such quota root"]);
+
+        let lines = command(&mut client, "a7", "GETQUOTAROOT Archive").await;
+        assert_eq!(lines, vec!["a7 NO Mailbox does not exist"]);
+    }
+
+    #[tokio::test]
+    async fn test_uids_stable_after_delete() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let mut ids = Vec::new();
+        for subject in ["One", "Two", "Three"] {
+            let email = Email::new(
+                "uids@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            ids.push(email.id.clone());
+            storage.store_email(email).await.unwrap();
+        }
+        storage.delete_email(&ids[1]).await.unwrap();
+        let uid_validity = storage
+            .get_mailbox_uid_state("uids@example.com")
+            .await
+            .unwrap()
+            .uid_validity;
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        command(&mut client, "a1", "LOGIN uids secret").await;
+        let lines = command(&mut client, "a2", "SELECT INBOX").await;
+        assert!(lines.contains(&"* 2 EXISTS".to_string()));
+        assert!(lines.contains(&format!("* OK [UIDVALIDITY {}] UIDs valid", uid_validity)));
+        assert!(lines.contains(&"* OK [UIDNEXT 4] Predicted next UID".to_string()));
+
+        let lines = command(&mut client, "a3", "FETCH 1:* (UID)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (UID 1)",
+                "* 2 FETCH (UID 3)",
+                "a3 OK FETCH completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a4", "UID FETCH 3 (UID)").await;
+        assert_eq!(
+            lines,
+            vec!["* 2 FETCH (UID 3)", "a4 OK UID FETCH completed"]
+        );
+
+        let lines = command(&mut client, "a5", "UID FETCH 2 (UID)").await;
+        assert_eq!(lines, vec!["a5 OK UID FETCH completed"]);
+
+        let lines = command(&mut client, "a6", "UID SEARCH ALL").await;
+        assert_eq!(lines, vec!["* SEARCH 1 3", "a6 OK UID SEARCH completed"]);
+
+        let lines = command(&mut client, "a7", "SEARCH ALL").await;
+        assert_eq!(lines, vec!["* SEARCH 1 2", "a7 OK SEARCH completed"]);
+    }
+
+    #[tokio::test]
+    async fn test_condstore_changedsince() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        for subject in ["One", "Two", "Three"] {
+            let email = Email::new(
+                "sync@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+
+        command(&mut client, "a1", "LOGIN sync secret").await;
+        let lines = command(&mut client, "a2", "SELECT INBOX").await;
+        assert!(lines.contains(&"* OK [HIGHESTMODSEQ 4] Highest".to_string()));
+
+        let lines = command(&mut client, "a3", "FETCH 1:* (FLAGS)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (FLAGS ())",
+                "* 2 FETCH (FLAGS ())",
+                "* 3 FETCH (FLAGS ())",
+                "a3 OK FETCH completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a4", "FETCH 1:* (MODSEQ)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (MODSEQ (2))",
+                "* 2 FETCH (MODSEQ (3))",
+                "* 3 FETCH (MODSEQ (4))",
+                "a4 OK FETCH completed"
+            ]
+        );
+
+        // MODSEQ is reported by every FETCH once the client has used it
+        let lines = command(&mut client, "a5", "STORE 2 +FLAGS (\\Flagged)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 2 FETCH (FLAGS (\\Flagged) MODSEQ (5))",
+                "a5 OK STORE completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a6", "FETCH 1:* (FLAGS) (CHANGEDSINCE 4)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 2 FETCH (FLAGS (\\Flagged) MODSEQ (5))",
+                "a6 OK FETCH completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a7", "UID FETCH 1:* (FLAGS) (CHANGEDSINCE 5)").await;
+        assert_eq!(lines, vec!["a7 OK UID FETCH completed"]);
+
+        let lines = command(&mut client, "a8", "FETCH 1:* (FLAGS) (CHANGEDSINCE x)").await;
+        assert_eq!(lines, vec!["a8 BAD Invalid CHANGEDSINCE modifier"]);
+
+        let lines = command(&mut client, "a9", "STATUS INBOX (HIGHESTMODSEQ)").await;
+        assert_eq!(
+            lines,
+            vec!["* STATUS INBOX (HIGHESTMODSEQ 5)", "a9 OK STATUS completed"]
+        );
+
+        let lines = command(&mut client, "b1", "EXAMINE INBOX (CONDSTORE)").await;
+        assert!(lines.contains(&"* OK [HIGHESTMODSEQ 5] Highest".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_expunge_deleted_messages() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let mut ids = Vec::new();
+        for subject in ["One", "Two", "Three", "Four"] {
+            let email = Email::new(
+                "expunge@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            ids.push(email.id.clone());
+            storage.store_email(email).await.unwrap();
+        }
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, mut deletion_receiver) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage.clone(),
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+        command(&mut client, "a1", "LOGIN expunge secret").await;
+        command(&mut client, "a2", "SELECT INBOX").await;
+
+        let lines = command(&mut client, "a3", "STORE 2,4 +FLAGS.SILENT (\\Deleted)").await;
+        assert_eq!(lines, vec!["a3 OK STORE completed"]);
+        let lines = command(&mut client, "a4", "EXPUNGE").await;
+        assert_eq!(
+            lines,
+            vec!["* 4 EXPUNGE", "* 2 EXPUNGE", "a4 OK EXPUNGE completed"]
+        );
+
+        // Both deletions reach WebSocket clients
+        assert_eq!(
+            deletion_receiver.recv().await.unwrap(),
+            (ids[3].clone(), "expunge@example.com".to_string())
+        );
+        assert_eq!(
+            deletion_receiver.recv().await.unwrap(),
+            (ids[1].clone(), "expunge@example.com".to_string())
+        );
+        assert!(storage.get_email_by_id(&ids[1]).await.unwrap().is_none());
+        let lines = command(&mut client, "a5", "FETCH 1:* (UID)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (UID 1)",
+                "* 2 FETCH (UID 3)",
+                "a5 OK FETCH completed"
+            ]
+        );
+
+        // CLOSE expunges without untagged responses
+        command(&mut client, "a6", "STORE 1 +FLAGS (\\Deleted)").await;
+        let lines = command(&mut client, "a7", "CLOSE").await;
+        assert_eq!(lines, vec!["a7 OK CLOSE completed"]);
+        assert_eq!(
+            storage
+                .count_emails_for_address("expunge@example.com")
+                .await
+                .unwrap(),
+            1
+        );
+
+        // Nothing is removed from a mailbox opened with EXAMINE
+        command(&mut client, "a8", "SELECT INBOX").await;
+        command(&mut client, "a9", "STORE 1 +FLAGS (\\Deleted)").await;
+        command(&mut client, "a10", "EXAMINE INBOX").await;
+        let lines = command(&mut client, "a11", "EXPUNGE").await;
+        assert_eq!(lines, vec!["a11 NO Mailbox is read-only"]);
+        command(&mut client, "a12", "CLOSE").await;
+        assert_eq!(
+            storage
+                .count_emails_for_address("expunge@example.com")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_body_sections() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let raw = "From: sender@example.com\r\nTo: sections@example.com\r\nSubject: Sections\r\n\r\nFirst line\r\nSecond line\r\n";
+        let mut email = crate::smtp::parser::parse_email(raw.as_bytes(), "sections@example.com")
+            .unwrap()
+            .for_recipient("sections@example.com".to_string());
+        email.raw = Some(raw.to_string());
+        storage.store_email(email).await.unwrap();
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (email_sender, _) = broadcast::channel(16);
+        let (deletion_sender, _) = broadcast::channel(16);
+        let mut connection = ImapConnection::new(
+            Box::new(server),
+            storage,
+            "example.com".to_string(),
+            email_sender,
+            deletion_sender,
+            None,
+        );
+        tokio::spawn(async move { connection.handle().await });
+
+        let mut client = BufReader::new(client);
+        let mut greeting = String::new();
+        client.read_line(&mut greeting).await.unwrap();
+        command(&mut client, "a1", "LOGIN sections secret").await;
+        command(&mut client, "a2", "SELECT INBOX").await;
+
+        let lines = command(&mut client, "a3", "FETCH 1 (BODY.PEEK[HEADER])").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (BODY[HEADER] {73}",
+                "From: sender@example.com",
+                "To: sections@example.com",
+                "Subject: Sections",
+                "",
+                ")",
+                "a3 OK FETCH completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a4", "FETCH 1 (BODY[]<0.10> RFC822.SIZE)").await;
+        assert_eq!(
+            lines,
+            vec![
+                format!("* 1 FETCH (RFC822.SIZE {} BODY[]<0> {{10}}", raw.len()),
+                "From: send)".to_string(),
+                "a4 OK FETCH completed".to_string()
+            ]
+        );
+
+        let lines = command(&mut client, "a5", "FETCH 1 (BODY[TEXT]<6.100>)").await;
+        assert_eq!(
+            lines,
+            vec![
+                "* 1 FETCH (BODY[TEXT]<6> {19}",
+                "line",
+                "Second line",
+                ")",
+                "a5 OK FETCH completed"
+            ]
+        );
+
+        let lines = command(&mut client, "a6", "FETCH 1 (RFC822.SIZE)").await;
+        assert_eq!(lines[0], format!("* 1 FETCH (RFC822.SIZE {})", raw.len()));
+
+        let lines = command(&mut client, "a7", "FETCH 1 (BODYSTRUCTURE)").await;
+        assert_eq!(
+            lines[0],
+            "* 1 FETCH (BODYSTRUCTURE (\"TEXT\" \"PLAIN\" (\"CHARSET\" \"US-ASCII\") NIL NIL \"7BIT\" 25 2))"
+        );
+    }
 }
@@ -1,8 +1,11 @@
 mod api;
 mod auth;
+mod build_info;
 mod config;
 mod dkim;
+mod forwarding;
 mod imap;
+mod mbox;
 mod mcp;
 mod outbound;
 mod rate_limit;
@@ -15,7 +18,7 @@ mod integration_tests;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use config::Config;
+use config::{AttachmentStoreMode, Config, McpTransport};
 use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::broadcast;
@@ -24,14 +27,13 @@ use tracing_subscriber::EnvFilter;
 
 use mcp::EmailMcpServer;
 use storage::{
-    models::{Email, WebhookEvent},
-    sqlite::SqliteBackend,
-    StorageBackend,
+    attachments::AttachmentStore, cache::CachingBackend, models::Email, postgres::PostgresBackend,
+    sqlite::SqliteBackend, StorageBackend,
 };
 use webhooks::WebhookTrigger;
 
 #[derive(Parser)]
-#[command(name = "dynip-email")]
+#[command(name = "dynip-email", version = build_info::LONG_VERSION)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
@@ -81,15 +83,32 @@ async fn main() {
                 }
             }));
 
-            // Initialize tracing with env filter
-            tracing_subscriber::fmt()
-                .with_env_filter(
-                    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-                )
-                .init();
+            // Settings from CONFIG_FILE (TOML) are overridden by environment variables
+            let config_file = std::env::var("CONFIG_FILE").ok();
+            let config = match &config_file {
+                Some(path) => Config::from_file(std::path::Path::new(path)),
+                None => Config::from_env(),
+            };
+
+            // Initialize tracing with env filter. Logs go to stderr when MCP
+            // uses stdio, so they don't corrupt the JSON-RPC stream on stdout.
+            // The transport may come from CONFIG_FILE, so the config is loaded
+            // first; a config that fails to load is reported on stderr too.
+            let mcp_stdio = match &config {
+                Ok(config) => config.mcp_transport == McpTransport::Stdio,
+                Err(_) => true,
+            };
+            let subscriber = tracing_subscriber::fmt().with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+            );
+            if mcp_stdio {
+                subscriber.with_writer(std::io::stderr).init();
+            } else {
+                subscriber.init();
+            }
 
             // Run the actual main logic and handle errors explicitly
-            if let Err(e) = run().await {
+            if let Err(e) = run(config_file, config).await {
                 eprintln!("Fatal error: {}", e);
                 std::process::exit(1);
             }
@@ -97,12 +116,19 @@ async fn main() {
     }
 }
 
-async fn run() -> Result<()> {
-    info!("🚀 Starting dynip-email server...");
+async fn run(config_file: Option<String>, config: Result<Config>) -> Result<()> {
+    info!(
+        "🚀 Starting dynip-email server v{} ({})...",
+        build_info::VERSION,
+        build_info::GIT_COMMIT
+    );
 
-    let config = match Config::from_env() {
+    let config = match config {
         Ok(config) => {
-            info!("✅ Configuration loaded successfully");
+            match &config_file {
+                Some(path) => info!("✅ Configuration loaded successfully from {}", path),
+                None => info!("✅ Configuration loaded successfully"),
+            }
             config
         }
         Err(e) => {
@@ -111,15 +137,42 @@ async fn run() -> Result<()> {
         }
     };
 
-    // Initialize storage backend
-    info!(
-        "📊 Initializing database connection to: {}",
-        config.database_url
-    );
-    let storage: Arc<dyn StorageBackend> = match SqliteBackend::new(&config.database_url).await {
+    // Initialize storage backend, selected by the DATABASE_URL scheme
+    let is_postgres = config.database_url.starts_with("postgres:")
+        || config.database_url.starts_with("postgresql:");
+    let cache = config.storage_cache_enabled;
+    let attachment_store = match config.attachment_store {
+        AttachmentStoreMode::Inline => AttachmentStore::Inline,
+        AttachmentStoreMode::Filesystem => {
+            info!(
+                "📎 Storing attachments on the filesystem in {}",
+                config.attachment_dir.display()
+            );
+            AttachmentStore::Filesystem(config.attachment_dir.clone())
+        }
+    };
+    let backend: Result<Arc<dyn StorageBackend>> = if is_postgres {
+        info!("📊 Initializing PostgreSQL database connection");
+        PostgresBackend::with_max_connections(&config.database_url, config.database_max_connections)
+            .await
+            .map(|backend| {
+                with_optional_cache(backend.with_attachment_store(attachment_store), cache)
+            })
+    } else {
+        info!(
+            "📊 Initializing database connection to: {}",
+            config.database_url
+        );
+        SqliteBackend::with_max_connections(&config.database_url, config.database_max_connections)
+            .await
+            .map(|backend| {
+                with_optional_cache(backend.with_attachment_store(attachment_store), cache)
+            })
+    };
+    let storage = match backend {
         Ok(backend) => {
             info!("✅ Database connection established successfully");
-            Arc::new(backend)
+            backend
         }
         Err(e) => {
             error!("❌ Failed to initialize database: {}", e);
@@ -127,93 +180,259 @@ async fn run() -> Result<()> {
         }
     };
 
+    // Every webhook trigger shares one limit on requests in flight
+    webhooks::set_max_concurrent_deliveries(config.webhook_max_concurrent);
+
     // Create broadcast channels for email notifications and deletions
-    let (email_tx, _) = broadcast::channel::<Email>(100);
-    let (deletion_tx, _) = broadcast::channel::<(String, String)>(100);
+    let (email_tx, _) = broadcast::channel::<Email>(config.broadcast_channel_capacity);
+    let (deletion_tx, _) =
+        broadcast::channel::<(String, String)>(config.broadcast_channel_capacity);
 
-    // Start email retention cleanup task if configured
-    if let Some(retention_hours) = config.email_retention_hours {
-        info!(
+    // Relay received email according to each mailbox's forwarding rules
+    forwarding::EmailForwarder::new(storage.clone()).spawn(email_tx.subscribe());
+
+    // Start the email retention cleanup task; mailboxes can override the global retention
+    let retention_hours = config.email_retention_hours;
+    match retention_hours {
+        Some(hours) => info!(
             "📅 Email retention enabled: emails older than {} hours will be deleted",
-            retention_hours
-        );
-        let storage_clone = storage.clone();
-        let deletion_tx_clone = deletion_tx.clone();
-        let webhook_trigger = WebhookTrigger::new(storage.clone());
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600)); // Run every hour
-            loop {
-                interval.tick().await;
-                match storage_clone
-                    .delete_old_emails_with_details(retention_hours)
-                    .await
-                {
-                    Ok(deleted_emails) => {
-                        if !deleted_emails.is_empty() {
-                            info!(
-                                "🗑️  Email retention cleanup: deleted {} old email(s)",
-                                deleted_emails.len()
-                            );
-
-                            // Send deletion notifications for each deleted email
-                            for (email_id, address) in deleted_emails {
-                                info!("📤 Broadcasting deletion notification for email {} to address {}", email_id, address);
-                                let _ = deletion_tx_clone.send((email_id.clone(), address.clone()));
-
-                                // Trigger webhooks for email deletion
-                                // Extract mailbox name without domain for webhook lookup
-                                let mailbox_name = address.split('@').next().unwrap_or(&address);
-                                if let Err(e) = webhook_trigger
-                                    .trigger_webhooks(mailbox_name, WebhookEvent::Deletion, None)
-                                    .await
-                                {
-                                    error!("Failed to trigger deletion webhooks: {}", e);
-                                }
-                            }
-                        }
+            hours
+        ),
+        None => info!(
+            "📅 Email retention disabled: emails will be kept indefinitely unless their mailbox sets a retention"
+        ),
+    }
+    let storage_clone = storage.clone();
+    let deletion_notifier =
+        webhooks::DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_tx.clone());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600)); // Run every hour
+        loop {
+            interval.tick().await;
+            run_retention_cleanup(&deletion_notifier, retention_hours).await;
+
+            // Clean up old rate limit requests (keep for 7 days)
+            let seven_days_ago = chrono::Utc::now() - chrono::Duration::days(7);
+            match storage_clone
+                .cleanup_old_rate_limit_requests(seven_days_ago)
+                .await
+            {
+                Ok(deleted_count) => {
+                    if deleted_count > 0 {
+                        info!(
+                            "🗑️  Rate limit cleanup: deleted {} old request(s)",
+                            deleted_count
+                        );
                     }
-                    Err(e) => {
-                        error!("❌ Email retention cleanup failed: {}", e);
+                }
+                Err(e) => {
+                    error!("❌ Rate limit cleanup failed: {}", e);
+                }
+            }
+
+            // Revoked access tokens only need remembering until they would have expired
+            match storage_clone.prune_revoked_tokens(chrono::Utc::now()).await {
+                Ok(pruned) => {
+                    if pruned > 0 {
+                        info!("🗑️  Pruned {} expired revoked token(s)", pruned);
                     }
                 }
+                Err(e) => {
+                    error!("❌ Revoked token cleanup failed: {}", e);
+                }
+            }
+        }
+    });
 
-                // Clean up old rate limit requests (keep for 7 days)
-                let seven_days_ago = chrono::Utc::now() - chrono::Duration::days(7);
-                match storage_clone
-                    .cleanup_old_rate_limit_requests(seven_days_ago)
-                    .await
-                {
-                    Ok(deleted_count) => {
-                        if deleted_count > 0 {
-                            info!(
-                                "🗑️  Rate limit cleanup: deleted {} old request(s)",
-                                deleted_count
-                            );
-                        }
+    // Permanently delete emails that have been in the trash too long
+    let trash_retention_hours = config.trash_retention_hours;
+    info!(
+        "🗑️  Deleted emails stay in the trash for {} hours",
+        trash_retention_hours
+    );
+    let trash_storage = storage.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600)); // Run every hour
+        loop {
+            interval.tick().await;
+            match trash_storage
+                .purge_deleted_emails(trash_retention_hours)
+                .await
+            {
+                Ok(purged) => {
+                    if purged > 0 {
+                        info!("🗑️  Trash cleanup: purged {} email(s)", purged);
                     }
-                    Err(e) => {
-                        error!("❌ Rate limit cleanup failed: {}", e);
+                }
+                Err(e) => {
+                    error!("❌ Trash cleanup failed: {}", e);
+                }
+            }
+        }
+    });
+
+    // Retry webhook deliveries that failed every immediate attempt, including those queued
+    // before a restart
+    let retry_trigger = WebhookTrigger::new(storage.clone());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(webhooks::PENDING_DELIVERY_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match retry_trigger
+                .retry_pending_deliveries(chrono::Utc::now())
+                .await
+            {
+                Ok(delivered) => {
+                    if delivered > 0 {
+                        info!(
+                            "📤 Webhook retry queue: delivered {} queued webhook(s)",
+                            delivered
+                        );
                     }
                 }
+                Err(e) => {
+                    error!("❌ Webhook retry queue failed: {}", e);
+                }
             }
-        });
+        }
+    });
+
+    if !config.additional_domains.is_empty() {
+        info!(
+            "🌐 Additional domains: {}",
+            config.additional_domains.join(", ")
+        );
+    }
+    if let Some(catch_all) = &config.catch_all_mailbox {
+        info!(
+            "📥 Catch-all mailbox: unknown recipients are delivered to {}",
+            catch_all
+        );
+    }
+
+    let spf_checker = if config.smtp_spf_enabled {
+        match smtp::spf::SpfChecker::new(config.smtp_reject_spf_fail) {
+            Ok(checker) => {
+                info!(
+                    "🛡️ SPF checking enabled{}",
+                    if config.smtp_reject_spf_fail {
+                        " (hard fails are rejected)"
+                    } else {
+                        ""
+                    }
+                );
+                Some(Arc::new(checker))
+            }
+            Err(e) => {
+                error!("❌ Failed to set up SPF checking: {}", e);
+                return Err(e);
+            }
+        }
     } else {
-        info!("📅 Email retention disabled: emails will be kept indefinitely");
+        None
+    };
+
+    let dkim_verifier = if config.smtp_dkim_enabled {
+        match dkim::verify::DkimVerifier::new(config.smtp_reject_dkim_fail) {
+            Ok(verifier) => {
+                info!(
+                    "🔏 DKIM verification enabled{}",
+                    if config.smtp_reject_dkim_fail {
+                        " (failed signatures are rejected)"
+                    } else {
+                        ""
+                    }
+                );
+                Some(Arc::new(verifier))
+            }
+            Err(e) => {
+                error!("❌ Failed to set up DKIM verification: {}", e);
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+
+    let clamav_scanner = config.clamav_address.as_ref().map(|address| {
+        info!(
+            "🦠 Scanning attachments with clamd at {}{}",
+            address,
+            if config.smtp_reject_infected {
+                " (infected mail is rejected)"
+            } else {
+                ""
+            }
+        );
+        Arc::new(smtp::clamav::ClamavScanner::new(
+            address.clone(),
+            config.smtp_reject_infected,
+        ))
+    });
+
+    let mx_validator = match config.smtp_accept_policy {
+        config::AcceptPolicy::Exact => None,
+        config::AcceptPolicy::Mx => {
+            info!(
+                "📮 Also accepting recipient domains whose MX points at {}",
+                config.smtp_hostname
+            );
+            match smtp::mx::MxValidator::new(config.smtp_hostname.clone()) {
+                Ok(validator) => Some(Arc::new(validator)),
+                Err(e) => {
+                    error!("❌ Failed to set up MX checks: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+    };
+
+    // Sender block and allow rules are checked from memory and reloaded periodically, so
+    // rule changes made through the API apply without a restart
+    let sender_rules = Arc::new(smtp::sender_rules::SenderRules::default());
+    if let Err(e) = sender_rules.refresh(storage.as_ref()).await {
+        error!("❌ Failed to load sender rules: {}", e);
     }
+    let refresh_rules = sender_rules.clone();
+    let refresh_storage = storage.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(smtp::sender_rules::SENDER_RULES_REFRESH_INTERVAL).await;
+            if let Err(e) = refresh_rules.refresh(refresh_storage.as_ref()).await {
+                error!("❌ Failed to reload sender rules: {}", e);
+            }
+        }
+    });
 
     // Start SMTP servers (non-TLS always, plus SSL ports if enabled)
     info!("📧 Starting SMTP servers...");
     let smtp_server = Arc::new(smtp::SmtpServer::new(
         storage.clone(),
         email_tx.clone(),
+        webhooks::DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_tx.clone()),
         config.domain_name.clone(),
+        config.smtp_hostname.clone(),
+        config.additional_domains.clone(),
         config.smtp_ssl.clone(),
         config.reject_non_domain_emails,
+        config.catch_all_mailbox.clone(),
+        config.mailbox_quota.clone(),
+        config.attachment_limits.clone(),
+        config.smtp_max_message_bytes,
+        config.smtp_max_messages_per_ip_per_hour,
+        spf_checker,
+        dkim_verifier,
+        clamav_scanner,
+        mx_validator,
+        config.sanitize_html,
+        config.smtp_proxy_protocol,
+        sender_rules,
     ));
 
     // Start SMTP servers and wait for them to be ready
     match smtp_server
         .start_all(
+            config.bind_address,
             config.smtp_port,          // Non-TLS port (always listening)
             config.smtp_starttls_port, // STARTTLS port (if SSL enabled)
             config.smtp_ssl_port,      // SMTPS port (if SSL enabled)
@@ -247,8 +466,12 @@ async fn run() -> Result<()> {
         enabled: config.auth_enabled,
         jwt_secret: config.jwt_secret.clone(),
         jwt_expiry_hours: config.jwt_expiry_hours,
+        refresh_token_expiry_days: config.refresh_token_expiry_days,
+        login_max_attempts: config.login_max_attempts,
+        login_window_minutes: config.login_window_minutes,
         auth_domains: config.auth_domains.clone(),
         outbound_enabled: config.outbound_enabled,
+        password_hasher: config.password_hasher,
     };
 
     if config.auth_enabled {
@@ -283,7 +506,10 @@ async fn run() -> Result<()> {
         };
 
         let mailer = outbound::OutboundMailer::new(&config, dkim_signer)?;
-        info!("Outbound email enabled (domain: {})", mailer.sender_domain());
+        info!(
+            "Outbound email enabled (domain: {})",
+            mailer.sender_domain()
+        );
         Some(Arc::new(mailer))
     } else {
         info!("Outbound email disabled");
@@ -293,24 +519,46 @@ async fn run() -> Result<()> {
     // Create API router
     let router = api::create_router(
         storage.clone(),
-        email_tx,
-        deletion_tx,
+        email_tx.clone(),
+        deletion_tx.clone(),
         config.domain_name.clone(),
+        config.additional_domains.clone(),
         webhook_trigger,
         auth_config,
         outbound_mailer,
+        config.api_max_requests_per_minute_per_ip,
+        std::time::Duration::from_secs(config.ws_ping_interval_secs),
+        config.email_retention_hours,
     );
 
     // Start MCP server if enabled
     if config.mcp_enabled {
-        info!("🔌 Starting MCP server on port {}...", config.mcp_port);
-        let mcp_server = EmailMcpServer::new(storage.clone());
-        let mcp_port = config.mcp_port;
-        tokio::spawn(async move {
-            if let Err(e) = mcp_server.start(mcp_port).await {
-                error!("❌ MCP server error: {}", e);
+        let mcp_server = EmailMcpServer::new(
+            storage.clone(),
+            webhooks::DeletionNotifier::new(
+                WebhookTrigger::new(storage.clone()),
+                deletion_tx.clone(),
+            ),
+        );
+        match config.mcp_transport {
+            McpTransport::Http => {
+                info!("🔌 Starting MCP server on port {}...", config.mcp_port);
+                let (bind_address, mcp_port) = (config.bind_address, config.mcp_port);
+                tokio::spawn(async move {
+                    if let Err(e) = mcp_server.start(bind_address, mcp_port).await {
+                        error!("❌ MCP server error: {}", e);
+                    }
+                });
             }
-        });
+            McpTransport::Stdio => {
+                info!("🔌 Starting MCP server on stdio...");
+                tokio::spawn(async move {
+                    if let Err(e) = mcp_server.start_stdio().await {
+                        error!("❌ MCP server error: {}", e);
+                    }
+                });
+            }
+        }
     } else {
         info!("🔌 MCP server disabled");
     }
@@ -318,13 +566,37 @@ async fn run() -> Result<()> {
     // Start IMAP server if enabled
     if config.imap_enabled {
         info!("📬 Starting IMAP server on port {}...", config.imap_port);
-        let imap_server = imap::ImapServer::new(storage.clone(), config.domain_name.clone());
-        let imap_port = config.imap_port;
+        let imap_server = Arc::new(
+            imap::ImapServer::new(
+                storage.clone(),
+                config.domain_name.clone(),
+                config.imap_ssl.clone(),
+                email_tx.clone(),
+                deletion_tx.clone(),
+            )
+            .with_require_tls(config.imap_require_tls)
+            .with_mailbox_quota(config.mailbox_quota.clone()),
+        );
+        let (bind_address, imap_port) = (config.bind_address, config.imap_port);
+        let server = imap_server.clone();
         tokio::spawn(async move {
-            if let Err(e) = imap_server.start(imap_port).await {
+            if let Err(e) = server.start(bind_address, imap_port).await {
                 error!("❌ IMAP server error: {}", e);
             }
         });
+
+        if config.imap_ssl.enabled {
+            info!(
+                "📬 Starting IMAPS server on port {}...",
+                config.imap_ssl_port
+            );
+            let imap_ssl_port = config.imap_ssl_port;
+            tokio::spawn(async move {
+                if let Err(e) = imap_server.start_tls(bind_address, imap_ssl_port).await {
+                    error!("❌ IMAPS server error: {}", e);
+                }
+            });
+        }
     } else {
         info!("📬 IMAP server disabled");
     }
@@ -361,12 +633,9 @@ async fn run() -> Result<()> {
             },
         }
 
-        // Shutdown SMTP servers
+        // Stop accepting mail and let accepted emails finish storing
         info!("🛑 Shutting down SMTP servers...");
-        smtp_server_clone.shutdown();
-
-        // Give SMTP servers a moment to shutdown gracefully
-        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        smtp_server_clone.shutdown().await;
         info!("✅ SMTP servers shutdown complete");
     };
 
@@ -374,13 +643,17 @@ async fn run() -> Result<()> {
     info!("✅ Server is running. Press Ctrl+C to stop gracefully...");
 
     // Run the server until shutdown signal is received
-    match api::start_server_with_shutdown(router, config.api_port, shutdown_signal).await {
+    match api::start_server_with_shutdown(
+        router,
+        config.bind_address,
+        config.api_port,
+        shutdown_signal,
+    )
+    .await
+    {
         Ok(_) => {
             info!("✅ Server shutdown completed gracefully");
-            // Force exit after graceful shutdown
-            // This ensures the process exits even if background tasks are still running
-            info!("🔄 Exiting application...");
-            std::process::exit(0);
+            Ok(())
         }
         Err(e) => {
             error!("❌ Server error: {}", e);
@@ -389,6 +662,42 @@ async fn run() -> Result<()> {
     }
 }
 
+/// Wrap a storage backend in the read cache when `STORAGE_CACHE_ENABLED` is set
+fn with_optional_cache<B: StorageBackend + 'static>(
+    backend: B,
+    cache: bool,
+) -> Arc<dyn StorageBackend> {
+    if cache {
+        info!("📊 Caching hot mailbox reads in memory");
+        Arc::new(CachingBackend::new(backend))
+    } else {
+        Arc::new(backend)
+    }
+}
+
+/// Move emails past their retention to the trash and announce each deletion
+async fn run_retention_cleanup(
+    deletion_notifier: &webhooks::DeletionNotifier,
+    retention_hours: Option<i64>,
+) {
+    match deletion_notifier
+        .delete_expired_emails(retention_hours)
+        .await
+    {
+        Ok(deleted_emails) => {
+            if !deleted_emails.is_empty() {
+                info!(
+                    "🗑️  Email retention cleanup: deleted {} old email(s)",
+                    deleted_emails.len()
+                );
+            }
+        }
+        Err(e) => {
+            error!("❌ Email retention cleanup failed: {}", e);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -399,6 +708,10 @@ mod tests {
     /// Load configuration from environment variables without loading .env file
     /// This is used for tests to avoid interference from .env files
     fn from_env_test() -> Result<Config> {
+        let bind_address = std::env::var("BIND_ADDRESS")
+            .unwrap_or_else(|_| "0.0.0.0".to_string())
+            .parse()?;
+
         // Non-TLS SMTP port (always listening)
         let smtp_port = std::env::var("SMTP_PORT")
             .unwrap_or_else(|_| "2525".to_string())
@@ -448,23 +761,65 @@ mod tests {
         };
 
         Ok(Config {
+            bind_address,
             smtp_port,
             smtp_starttls_port,
             smtp_ssl_port,
+            smtp_max_message_bytes: crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            smtp_max_messages_per_ip_per_hour: None,
+            smtp_spf_enabled: false,
+            smtp_reject_spf_fail: false,
+            smtp_dkim_enabled: false,
+            smtp_reject_dkim_fail: false,
+            clamav_address: None,
+            smtp_reject_infected: false,
+            sanitize_html: false,
+            smtp_proxy_protocol: false,
+            smtp_accept_policy: config::AcceptPolicy::Exact,
             api_port,
+            api_max_requests_per_minute_per_ip: None,
+            ws_ping_interval_secs: crate::config::DEFAULT_WS_PING_INTERVAL_SECS,
+            webhook_max_concurrent: crate::config::DEFAULT_WEBHOOK_MAX_CONCURRENT,
+            broadcast_channel_capacity: crate::config::DEFAULT_BROADCAST_CHANNEL_CAPACITY,
             database_url,
+            database_max_connections: crate::config::DEFAULT_DATABASE_MAX_CONNECTIONS,
+            storage_cache_enabled: false,
+            attachment_store: crate::config::AttachmentStoreMode::Inline,
+            attachment_dir: std::path::PathBuf::from("attachments"),
+            smtp_hostname: domain_name.clone(),
             domain_name,
+            additional_domains: vec![],
             email_retention_hours,
+            trash_retention_hours: crate::config::DEFAULT_TRASH_RETENTION_HOURS,
             reject_non_domain_emails,
+            catch_all_mailbox: None,
             smtp_ssl,
+            mailbox_quota: crate::config::MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            attachment_limits: crate::config::AttachmentLimitsConfig::default(),
             mcp_enabled: false,
             mcp_port: 3001,
+            mcp_transport: crate::config::McpTransport::Http,
             imap_enabled: false,
             imap_port: 143,
+            imap_ssl_port: 993,
+            imap_ssl: crate::config::SmtpSslConfig {
+                enabled: false,
+                cert_path: None,
+                key_path: None,
+            },
+            imap_require_tls: false,
             auth_enabled: false,
             jwt_secret: "test-secret".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            login_max_attempts: 5,
+            login_window_minutes: 15,
             auth_domains: None,
+            password_hasher: auth::PasswordHasher::Bcrypt,
             outbound_enabled: false,
             dkim_private_key_path: None,
             dkim_selector: "default".to_string(),
@@ -608,6 +963,122 @@ mod tests {
         assert_eq!(emails[0].id, new_email.id);
     }
 
+    #[tokio::test]
+    async fn test_api_and_retention_deletions_notify_alike() {
+        use crate::api::websocket::WsMessage;
+        use crate::storage::models::{Webhook, WebhookEvent};
+        use axum::{body::Body, http::Request, routing::delete, Router};
+        use std::sync::Mutex;
+        use tower::util::ServiceExt;
+
+        let mut server = mockito::Server::new_async().await;
+        let payloads = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+        let captured = payloads.clone();
+        let _mock = server
+            .mock("POST", "/hook")
+            .match_request(move |request| {
+                let payload = serde_json::from_slice(request.body().unwrap()).unwrap();
+                captured.lock().unwrap().push(payload);
+                true
+            })
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .create_webhook(Webhook::new(
+                "test".to_string(),
+                format!("{}/hook", server.url()),
+                vec![WebhookEvent::Deletion],
+            ))
+            .await
+            .unwrap();
+
+        let new_email = |subject: &str| {
+            Email::new(
+                "test@test.local".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            )
+        };
+        let api_email = new_email("Deleted by hand");
+        let mut expired_email = new_email("Expired");
+        expired_email.timestamp = chrono::Utc::now() - chrono::Duration::hours(25);
+        storage.store_email(api_email.clone()).await.unwrap();
+        storage.store_email(expired_email.clone()).await.unwrap();
+
+        let (deletion_tx, mut deletion_rx) = broadcast::channel(10);
+        let notifier =
+            webhooks::DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_tx);
+
+        let app = Router::new()
+            .route("/api/email/:id", delete(api::handlers::delete_email))
+            .with_state((storage.clone(), notifier.clone()))
+            .layer(axum::middleware::from_fn_with_state(
+                (
+                    storage.clone(),
+                    auth::AuthConfig {
+                        enabled: false,
+                        jwt_secret: "test-secret".to_string(),
+                        jwt_expiry_hours: 24,
+                        refresh_token_expiry_days: 30,
+                        login_max_attempts: 5,
+                        login_window_minutes: 15,
+                        auth_domains: None,
+                        outbound_enabled: false,
+                        password_hasher: auth::PasswordHasher::Bcrypt,
+                    },
+                ),
+                auth::auth_config_middleware,
+            ));
+        let response = app
+            .oneshot(
+                Request::delete(format!("/api/email/{}", api_email.id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+
+        run_retention_cleanup(&notifier, Some(24)).await;
+
+        // Both deletions reach WebSocket clients as the same kind of message
+        for email in [&api_email, &expired_email] {
+            let (id, address) = deletion_rx.try_recv().unwrap();
+            assert_eq!(id, email.id);
+            assert_eq!(address, "test@test.local");
+            let message = serde_json::to_value(WsMessage::EmailDeleted { id, address }).unwrap();
+            assert_eq!(message["type"], "EmailDeleted");
+        }
+        assert!(deletion_rx.try_recv().is_err());
+
+        // ...and fire the same webhook event with the same payload shape
+        _mock.assert_async().await;
+        let payloads = payloads.lock().unwrap();
+        let keys = |payload: &serde_json::Value| {
+            payload
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&payloads[0]), keys(&payloads[1]));
+        for (payload, email) in payloads.iter().zip([&api_email, &expired_email]) {
+            assert_eq!(payload["event"], "deletion");
+            assert_eq!(payload["mailbox"], "test");
+            assert_eq!(payload["email"]["id"], email.id.as_str());
+            assert_eq!(payload["email"]["subject"], email.subject.as_str());
+        }
+    }
+
     #[tokio::test]
     async fn test_broadcast_channel_creation() {
         let (email_tx, mut email_rx) = broadcast::channel::<Email>(100);
@@ -666,6 +1137,10 @@ mod tests {
             content_type: "text/plain".to_string(),
             size: 100,
             content: "dGVzdCBjb250ZW50".to_string(),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
         }];
 
         let email = Email::new(
@@ -0,0 +1,220 @@
+//! Conversion between stored emails and the mbox mailbox format
+//!
+//! Exports use the mboxrd variant: body lines that start with `From ` (after any number
+//! of `>`) get one more `>`, so they can be told apart from the separator lines and
+//! restored on import.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::smtp::parser::parse_email;
+use crate::storage::models::Email;
+
+/// `asctime` layout of the date on a `From ` separator line
+const SEPARATOR_DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Y";
+
+/// One email as an mbox entry: separator line, message, and a trailing blank line
+///
+/// The stored raw message is used when there is one. Otherwise a minimal plain-text (or
+/// HTML-only) message is rebuilt from the stored fields, without attachments.
+pub fn to_mbox_entry(email: &Email) -> String {
+    let message = match &email.raw {
+        Some(raw) => raw.clone(),
+        None => reconstruct_message(email),
+    };
+    let message = message.replace("\r\n", "\n");
+
+    let mut entry = format!(
+        "From {} {}\n",
+        envelope_sender(&email.from),
+        email.timestamp.format(SEPARATOR_DATE_FORMAT)
+    );
+    for line in message.lines() {
+        if is_from_line(line.trim_start_matches('>')) {
+            entry.push('>');
+        }
+        entry.push_str(line);
+        entry.push('\n');
+    }
+    entry.push('\n');
+    entry
+}
+
+/// Split an mbox into its messages, each with the date from its separator line
+///
+/// Text before the first separator is ignored, and `>From ` escaping is undone.
+pub fn split_mbox(mbox: &str) -> Vec<(Option<DateTime<Utc>>, String)> {
+    let mut messages = Vec::new();
+    let mut current: Option<(Option<DateTime<Utc>>, String)> = None;
+
+    for line in mbox.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if is_from_line(line) {
+            messages.extend(current.take());
+            current = Some((separator_date(line), String::new()));
+            continue;
+        }
+        let Some((_, message)) = current.as_mut() else {
+            continue;
+        };
+        let unescaped = match line.strip_prefix('>') {
+            Some(rest) if is_from_line(rest.trim_start_matches('>')) => rest,
+            _ => line,
+        };
+        message.push_str(unescaped);
+        message.push('\n');
+    }
+    messages.extend(current);
+
+    // Drop the blank line that ends each entry
+    for (_, message) in &mut messages {
+        if message.ends_with("\n\n") {
+            message.pop();
+        }
+    }
+    messages
+}
+
+/// Parse the messages of an mbox into emails for `recipient`
+///
+/// Each email keeps the date of its separator line, falling back to the time of import.
+pub fn parse_mbox(mbox: &str, recipient: &str) -> Result<Vec<Email>> {
+    split_mbox(mbox)
+        .into_iter()
+        .map(|(date, message)| {
+            let mut email =
+                parse_email(message.as_bytes(), recipient)?.for_recipient(recipient.to_string());
+            if let Some(date) = date {
+                email.timestamp = date;
+            }
+            Ok(email)
+        })
+        .collect()
+}
+
+fn is_from_line(line: &str) -> bool {
+    line.starts_with("From ")
+}
+
+/// Bare address for the separator line, which must not contain spaces
+fn envelope_sender(from: &str) -> String {
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    };
+    let address: String = address.chars().filter(|c| !c.is_whitespace()).collect();
+    if address.is_empty() {
+        "MAILER-DAEMON".to_string()
+    } else {
+        address
+    }
+}
+
+fn separator_date(line: &str) -> Option<DateTime<Utc>> {
+    // "From <sender> <date>", where the date itself contains spaces
+    let date = line.strip_prefix("From ")?.split_once(' ')?.1.trim();
+    NaiveDateTime::parse_from_str(date, SEPARATOR_DATE_FORMAT)
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn reconstruct_message(email: &Email) -> String {
+    let mut headers = vec![format!("From: {}", email.from), format!("To: {}", email.to)];
+    if !email.cc.is_empty() {
+        headers.push(format!("Cc: {}", email.cc.join(", ")));
+    }
+    if let Some(reply_to) = &email.reply_to {
+        headers.push(format!("Reply-To: {}", reply_to));
+    }
+    headers.push(format!("Subject: {}", email.subject));
+    headers.push(format!("Date: {}", email.timestamp.to_rfc2822()));
+    if let Some(message_id) = &email.message_id {
+        headers.push(format!("Message-ID: <{}>", message_id));
+    }
+    if let Some(in_reply_to) = &email.in_reply_to {
+        headers.push(format!("In-Reply-To: <{}>", in_reply_to));
+    }
+    headers.push("MIME-Version: 1.0".to_string());
+
+    let (content_type, body) = match (&email.body_text, &email.body_html) {
+        (Some(text), _) => ("text/plain", text.as_str()),
+        (None, Some(html)) => ("text/html", html.as_str()),
+        (None, None) => ("text/plain", email.body.as_str()),
+    };
+    headers.push(format!("Content-Type: {}; charset=utf-8", content_type));
+    headers.push("Content-Transfer-Encoding: 8bit".to_string());
+
+    format!("{}\n\n{}\n", headers.join("\n"), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn email_without_raw(subject: &str, body: &str) -> Email {
+        let mut email = Email::new(
+            "inbox@test.local".to_string(),
+            "Alice <alice@example.com>".to_string(),
+            subject.to_string(),
+            body.to_string(),
+            None,
+            vec![],
+        );
+        email.timestamp = Utc.with_ymd_and_hms(2024, 3, 5, 9, 7, 1).unwrap();
+        email
+    }
+
+    #[test]
+    fn test_separator_line() {
+        let entry = to_mbox_entry(&email_without_raw("Hi", "Hello"));
+        assert!(entry.starts_with("From alice@example.com Tue Mar  5 09:07:01 2024\n"));
+        assert!(entry.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn test_from_lines_are_escaped_and_restored() {
+        let body = "First line\nFrom here on\n>From quoted\nlast";
+        let entry = to_mbox_entry(&email_without_raw("Escapes", body));
+        assert!(entry.contains("\n>From here on\n"));
+        assert!(entry.contains("\n>>From quoted\n"));
+
+        let messages = split_mbox(&entry);
+        assert_eq!(messages.len(), 1);
+        let (date, message) = &messages[0];
+        assert_eq!(
+            *date,
+            Some(Utc.with_ymd_and_hms(2024, 3, 5, 9, 7, 1).unwrap())
+        );
+        assert!(message.ends_with("\n\nFirst line\nFrom here on\n>From quoted\nlast\n"));
+    }
+
+    #[test]
+    fn test_split_multiple_messages() {
+        let mbox = "preamble\nFrom a@example.com Mon Jan  1 00:00:00 2024\r\nSubject: One\r\n\r\nBody one\r\n\r\n\
+                    From b@example.com Tue Jan  2 00:00:00 2024\nSubject: Two\n\nBody two\n";
+        let messages = split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].1, "Subject: One\n\nBody one\n");
+        assert_eq!(messages[1].1, "Subject: Two\n\nBody two\n");
+        assert!(split_mbox("no separators here\n").is_empty());
+    }
+
+    #[test]
+    fn test_reconstructed_message_parses() {
+        let mut email = email_without_raw("Rebuilt", "Plain body");
+        email.message_id = Some("rebuilt@example.com".to_string());
+
+        let mbox = to_mbox_entry(&email);
+        let parsed = parse_mbox(&mbox, "copy@test.local").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].to, "copy@test.local");
+        assert_eq!(parsed[0].subject, "Rebuilt");
+        assert_eq!(
+            parsed[0].body_text.as_deref().map(str::trim),
+            Some("Plain body")
+        );
+        assert_eq!(parsed[0].message_id.as_deref(), Some("rebuilt@example.com"));
+        assert_eq!(parsed[0].timestamp, email.timestamp);
+    }
+}
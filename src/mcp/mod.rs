@@ -7,39 +7,44 @@ use axum::{
     Router,
 };
 use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use tracing::info;
 
+use crate::build_info;
 use crate::storage::{
     models::{Webhook, WebhookEvent},
     StorageBackend,
 };
-use crate::webhooks::WebhookTrigger;
+use crate::webhooks::DeletionNotifier;
+
+mod stdio;
 
 /// MCP server implementation for email management
 pub struct EmailMcpServer {
     storage: Arc<dyn StorageBackend>,
-    webhook_trigger: WebhookTrigger,
+    /// Announces emails deleted through the tools, as API deletes do
+    deletion_notifier: DeletionNotifier,
 }
 
 impl EmailMcpServer {
     /// Create a new MCP server
-    pub fn new(storage: Arc<dyn StorageBackend>) -> Self {
-        let webhook_trigger = WebhookTrigger::new(storage.clone());
+    pub fn new(storage: Arc<dyn StorageBackend>, deletion_notifier: DeletionNotifier) -> Self {
         Self {
             storage,
-            webhook_trigger,
+            deletion_notifier,
         }
     }
 
     /// Start the MCP server
-    pub async fn start(&self, port: u16) -> Result<()> {
+    pub async fn start(&self, bind_address: IpAddr, port: u16) -> Result<()> {
         info!("Starting MCP server on port {}", port);
 
         let app = self.create_router();
-        let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+        let addr = SocketAddr::new(bind_address, port);
+        let listener = tokio::net::TcpListener::bind(addr).await?;
 
-        info!("🔌 MCP server listening on port {}", port);
+        info!("🔌 MCP server listening on {}", addr);
         axum::serve(listener, app).await?;
 
         Ok(())
@@ -48,7 +53,7 @@ impl EmailMcpServer {
     /// Create the MCP server router
     fn create_router(&self) -> Router {
         let storage = self.storage.clone();
-        let webhook_trigger = self.webhook_trigger.clone();
+        let deletion_notifier = self.deletion_notifier.clone();
 
         Router::new()
             .route("/", get(Self::handle_root))
@@ -56,14 +61,14 @@ impl EmailMcpServer {
             .route("/tools/:name", post(Self::handle_call_tool))
             .route("/resources", get(Self::handle_list_resources))
             .route("/resources/:id", get(Self::handle_read_resource))
-            .with_state((storage, webhook_trigger))
+            .with_state((storage, deletion_notifier))
     }
 
     /// MCP server handlers
     async fn handle_root() -> Json<Value> {
         Json(json!({
             "name": "dynip-email-mcp",
-            "version": "1.0.0",
+            "version": build_info::VERSION,
             "description": "Email management MCP server for dynip-email",
             "capabilities": {
                 "tools": true,
@@ -73,83 +78,130 @@ impl EmailMcpServer {
     }
 
     async fn handle_list_tools() -> Json<Value> {
-        Json(json!({
-            "tools": [
-                {
-                    "name": "list_emails",
-                    "description": "List emails for a specific mailbox",
-                    "inputSchema": {
-                        "type": "object",
-                        "properties": {
-                            "mailbox": {
-                                "type": "string",
-                                "description": "Mailbox name (without domain)"
-                            }
-                        },
-                        "required": ["mailbox"]
-                    }
-                },
-                {
-                    "name": "read_email",
-                    "description": "Get a specific email by ID",
-                    "inputSchema": {
-                        "type": "object",
-                        "properties": {
-                            "email_id": {
-                                "type": "string",
-                                "description": "Email ID"
-                            }
+        Json(json!({ "tools": Self::tool_definitions() }))
+    }
+
+    /// Tool descriptions shared by the HTTP and stdio transports
+    fn tool_definitions() -> Value {
+        json!([
+            {
+                "name": "list_emails",
+                "description": "List emails for a specific mailbox",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Mailbox name (without domain)"
+                        }
+                    },
+                    "required": ["mailbox"]
+                }
+            },
+            {
+                "name": "read_email",
+                "description": "Get a specific email by ID",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "email_id": {
+                            "type": "string",
+                            "description": "Email ID"
+                        }
+                    },
+                    "required": ["email_id"]
+                }
+            },
+            {
+                "name": "delete_email",
+                "description": "Delete a specific email by ID",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "email_id": {
+                            "type": "string",
+                            "description": "Email ID"
+                        }
+                    },
+                    "required": ["email_id"]
+                }
+            },
+            {
+                "name": "search_emails",
+                "description": "Search a mailbox's emails by subject, body and addresses",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Full mailbox address (e.g. user@example.com)"
                         },
-                        "required": ["email_id"]
-                    }
-                },
-                {
-                    "name": "create_webhook",
-                    "description": "Create a new webhook for a mailbox",
-                    "inputSchema": {
-                        "type": "object",
-                        "properties": {
-                            "mailbox": {
-                                "type": "string",
-                                "description": "Mailbox name (without domain)"
-                            },
-                            "webhook_url": {
-                                "type": "string",
-                                "description": "Webhook URL"
-                            },
-                            "events": {
-                                "type": "array",
-                                "items": {"type": "string"},
-                                "description": "Events to subscribe to"
-                            }
+                        "query": {
+                            "type": "string",
+                            "description": "Search terms; every term must match"
+                        }
+                    },
+                    "required": ["mailbox", "query"]
+                }
+            },
+            {
+                "name": "create_webhook",
+                "description": "Create a new webhook for a mailbox",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Mailbox name (without domain)"
                         },
-                        "required": ["mailbox", "webhook_url", "events"]
-                    }
-                },
-                {
-                    "name": "list_webhooks",
-                    "description": "List webhooks for a mailbox",
-                    "inputSchema": {
-                        "type": "object",
-                        "properties": {
-                            "mailbox": {
-                                "type": "string",
-                                "description": "Mailbox name (without domain)"
-                            }
+                        "webhook_url": {
+                            "type": "string",
+                            "description": "Webhook URL"
                         },
-                        "required": ["mailbox"]
-                    }
+                        "events": {
+                            "type": "array",
+                            "items": {"type": "string"},
+                            "description": "Events to subscribe to: arrival, deletion, attachment_received"
+                        }
+                    },
+                    "required": ["mailbox", "webhook_url", "events"]
                 }
-            ]
-        }))
+            },
+            {
+                "name": "list_webhooks",
+                "description": "List webhooks for a mailbox",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Mailbox name (without domain)"
+                        }
+                    },
+                    "required": ["mailbox"]
+                }
+            }
+        ])
     }
 
     async fn handle_call_tool(
         Path(tool_name): Path<String>,
-        State((storage, _webhook_trigger)): State<(Arc<dyn StorageBackend>, WebhookTrigger)>,
+        State((storage, deletion_notifier)): State<(Arc<dyn StorageBackend>, DeletionNotifier)>,
         Json(payload): Json<Value>,
     ) -> Result<Json<Value>, (StatusCode, String)> {
-        match tool_name.as_str() {
+        Self::call_tool(&storage, &deletion_notifier, &tool_name, &payload)
+            .await
+            .map(Json)
+    }
+
+    /// Run a tool by name, shared by the HTTP and stdio transports
+    async fn call_tool(
+        storage: &Arc<dyn StorageBackend>,
+        deletion_notifier: &DeletionNotifier,
+        tool_name: &str,
+        payload: &Value,
+    ) -> Result<Value, (StatusCode, String)> {
+        match tool_name {
             "list_emails" => {
                 let mailbox = payload
                     .get("mailbox")
@@ -162,10 +214,10 @@ impl EmailMcpServer {
                     })?;
 
                 match storage.get_emails_for_address(mailbox).await {
-                    Ok(emails) => Ok(Json(json!({
+                    Ok(emails) => Ok(json!({
                         "emails": emails,
                         "count": emails.len()
-                    }))),
+                    })),
                     Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
                 }
             }
@@ -181,11 +233,65 @@ impl EmailMcpServer {
                     })?;
 
                 match storage.get_email_by_id(email_id).await {
-                    Ok(Some(email)) => Ok(Json(json!(email))),
+                    Ok(Some(email)) => Ok(json!(email)),
                     Ok(None) => Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
                     Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
                 }
             }
+            "delete_email" => {
+                let email_id = payload
+                    .get("email_id")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            "Missing email_id parameter".to_string(),
+                        )
+                    })?;
+
+                match storage.delete_email_by_id(email_id).await {
+                    Ok(Some(email)) => {
+                        deletion_notifier
+                            .handle_email_deletion(&email.id, &email.to)
+                            .await;
+
+                        Ok(json!({
+                            "deleted": true,
+                            "email_id": email.id
+                        }))
+                    }
+                    Ok(None) => Err((StatusCode::NOT_FOUND, "Email not found".to_string())),
+                    Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+                }
+            }
+            "search_emails" => {
+                let mailbox = payload
+                    .get("mailbox")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            "Missing mailbox parameter".to_string(),
+                        )
+                    })?;
+                let query = payload
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            "Missing query parameter".to_string(),
+                        )
+                    })?;
+
+                match storage.search_emails_for_address(mailbox, query).await {
+                    Ok(emails) => Ok(json!({
+                        "emails": emails,
+                        "count": emails.len()
+                    })),
+                    Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+                }
+            }
             "create_webhook" => {
                 let mailbox = payload
                     .get("mailbox")
@@ -228,7 +334,7 @@ impl EmailMcpServer {
                     Webhook::new(mailbox.to_string(), webhook_url.to_string(), webhook_events);
 
                 match storage.create_webhook(webhook.clone()).await {
-                    Ok(_) => Ok(Json(json!(webhook))),
+                    Ok(_) => Ok(json!(webhook)),
                     Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
                 }
             }
@@ -244,10 +350,10 @@ impl EmailMcpServer {
                     })?;
 
                 match storage.get_webhooks_for_mailbox(mailbox).await {
-                    Ok(webhooks) => Ok(Json(json!({
+                    Ok(webhooks) => Ok(json!({
                         "webhooks": webhooks,
                         "count": webhooks.len()
-                    }))),
+                    })),
                     Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
                 }
             }
@@ -276,7 +382,7 @@ impl EmailMcpServer {
 
     async fn handle_read_resource(
         Path(resource_id): Path<String>,
-        State((storage, _webhook_trigger)): State<(Arc<dyn StorageBackend>, WebhookTrigger)>,
+        State((storage, _deletion_notifier)): State<(Arc<dyn StorageBackend>, DeletionNotifier)>,
     ) -> Result<Json<Value>, (StatusCode, String)> {
         if resource_id.starts_with("email://") {
             let email_id = resource_id.strip_prefix("email://").unwrap();
@@ -301,17 +407,27 @@ impl EmailMcpServer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::storage::sqlite::SqliteBackend;
+    use crate::storage::{models::Email, sqlite::SqliteBackend};
     use axum::{
         body::Body,
         http::{Request, StatusCode},
     };
+    use tokio::sync::broadcast;
     use tower::util::ServiceExt;
 
+    pub(super) fn test_server(storage: Arc<dyn StorageBackend>) -> EmailMcpServer {
+        let (deletion_tx, _) = broadcast::channel(16);
+        let notifier = DeletionNotifier::new(
+            crate::webhooks::WebhookTrigger::new(storage.clone()),
+            deletion_tx,
+        );
+        EmailMcpServer::new(storage, notifier)
+    }
+
     #[tokio::test]
     async fn test_mcp_server_creation() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let _server = EmailMcpServer::new(storage);
+        let _server = test_server(storage);
 
         // Test that server can be created
         assert!(true);
@@ -320,7 +436,7 @@ mod tests {
     #[tokio::test]
     async fn test_mcp_server_info() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let response = app
@@ -342,7 +458,7 @@ mod tests {
         let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
 
         assert_eq!(info["name"], "dynip-email-mcp");
-        assert_eq!(info["version"], "1.0.0");
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
         assert!(info["capabilities"]["tools"].as_bool().unwrap());
         assert!(info["capabilities"]["resources"].as_bool().unwrap());
     }
@@ -350,7 +466,7 @@ mod tests {
     #[tokio::test]
     async fn test_mcp_list_tools() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let response = app
@@ -383,6 +499,8 @@ mod tests {
 
         assert!(tool_names.contains(&"list_emails"));
         assert!(tool_names.contains(&"read_email"));
+        assert!(tool_names.contains(&"delete_email"));
+        assert!(tool_names.contains(&"search_emails"));
         assert!(tool_names.contains(&"create_webhook"));
         assert!(tool_names.contains(&"list_webhooks"));
     }
@@ -390,7 +508,7 @@ mod tests {
     #[tokio::test]
     async fn test_mcp_list_resources() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let response = app
@@ -428,7 +546,7 @@ mod tests {
     #[tokio::test]
     async fn test_mcp_call_tool_list_emails() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let request_body = json!({
@@ -458,10 +576,125 @@ mod tests {
         assert_eq!(result["count"], 0);
     }
 
+    #[tokio::test]
+    async fn test_mcp_call_tool_delete_email() {
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Delete me".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        storage.store_email(email.clone()).await.unwrap();
+        let (deletion_tx, mut deletion_rx) = broadcast::channel(16);
+        let server = EmailMcpServer::new(
+            storage.clone(),
+            DeletionNotifier::new(
+                crate::webhooks::WebhookTrigger::new(storage.clone()),
+                deletion_tx,
+            ),
+        );
+        let app = server.create_router();
+
+        let request_body = json!({
+            "email_id": email.id
+        });
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/tools/delete_email")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(result["deleted"], true);
+        assert!(storage.get_email_by_id(&email.id).await.unwrap().is_none());
+        // WebSocket/SSE clients hear about it like an API delete
+        assert_eq!(
+            deletion_rx.try_recv().unwrap(),
+            (email.id.clone(), "test@example.com".to_string())
+        );
+
+        // Deleting again reports not found
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/tools/delete_email")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_mcp_call_tool_search_emails() {
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        for subject in ["Quarterly report", "Lunch plans"] {
+            let email = Email::new(
+                "test@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            storage.store_email(email).await.unwrap();
+        }
+        let server = test_server(storage);
+        let app = server.create_router();
+
+        let request_body = json!({
+            "mailbox": "test@example.com",
+            "query": "report"
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/tools/search_emails")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(result["count"], 1);
+        assert_eq!(result["emails"][0]["subject"], "Quarterly report");
+    }
+
     #[tokio::test]
     async fn test_mcp_call_tool_invalid_tool() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let request_body = json!({
@@ -486,7 +719,7 @@ mod tests {
     #[tokio::test]
     async fn test_mcp_call_tool_missing_parameters() {
         let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
-        let server = EmailMcpServer::new(storage);
+        let server = test_server(storage);
         let app = server.create_router();
 
         let request_body = json!({});
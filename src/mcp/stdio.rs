@@ -0,0 +1,275 @@
+//! JSON-RPC 2.0 transport over stdin/stdout, as spoken by MCP clients that
+//! launch the server as a subprocess. Each request and response is a single
+//! line of JSON.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{info, warn};
+
+use super::EmailMcpServer;
+use crate::build_info;
+
+/// Protocol version reported when the client does not request one
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+impl EmailMcpServer {
+    /// Serve MCP over stdin/stdout until stdin is closed
+    pub async fn start_stdio(&self) -> Result<()> {
+        info!("🔌 MCP server listening on stdio");
+        self.serve_jsonrpc(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+            .await?;
+        info!("🔌 MCP stdio input closed");
+        Ok(())
+    }
+
+    /// Read newline-delimited JSON-RPC requests and write one response line per request
+    async fn serve_jsonrpc<R, W>(&self, reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle_jsonrpc_message(&line).await {
+                let mut out = serde_json::to_vec(&response)?;
+                out.push(b'\n');
+                writer.write_all(&out).await?;
+                writer.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a single JSON-RPC message, returning `None` for notifications
+    async fn handle_jsonrpc_message(&self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid JSON-RPC message: {}", e);
+                return Some(error_response(Value::Null, PARSE_ERROR, "Parse error"));
+            }
+        };
+
+        let Some(method) = request.get("method").and_then(|m| m.as_str()) else {
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            return Some(error_response(id, INVALID_REQUEST, "Invalid request"));
+        };
+
+        // Requests without an id are notifications and get no response
+        let id = request.get("id").cloned()?;
+        let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": params
+                    .get("protocolVersion")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(PROTOCOL_VERSION),
+                "capabilities": {
+                    "tools": {}
+                },
+                "serverInfo": {
+                    "name": "dynip-email-mcp",
+                    "version": build_info::VERSION
+                }
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": Self::tool_definitions() })),
+            "tools/call" => self.handle_jsonrpc_tool_call(&params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result
+            }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    /// Run a `tools/call` request, reporting tool failures as an error result
+    async fn handle_jsonrpc_tool_call(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let name = params
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| (INVALID_PARAMS, "Missing tool name".to_string()))?;
+
+        let known = Self::tool_definitions()
+            .as_array()
+            .is_some_and(|tools| tools.iter().any(|t| t["name"] == name));
+        if !known {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        }
+
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+
+        let (text, is_error) =
+            match Self::call_tool(&self.storage, &self.deletion_notifier, name, &arguments).await {
+                Ok(value) => (value.to_string(), false),
+                Err((_, message)) => (message, true),
+            };
+
+        Ok(json!({
+            "content": [
+                {
+                    "type": "text",
+                    "text": text
+                }
+            ],
+            "isError": is_error
+        }))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::tests::test_server;
+    use crate::storage::{models::Email, sqlite::SqliteBackend, StorageBackend};
+    use std::sync::Arc;
+
+    async fn run_session(server: &EmailMcpServer, input: &str) -> Vec<Value> {
+        let mut output = Vec::new();
+        server
+            .serve_jsonrpc(input.as_bytes(), &mut output)
+            .await
+            .unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stdio_initialize_and_list_tools() {
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let server = test_server(storage);
+
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05"}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            "\n",
+        );
+        let responses = run_session(&server, input).await;
+
+        // The notification gets no response
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(
+            responses[0]["result"]["serverInfo"]["name"],
+            "dynip-email-mcp"
+        );
+
+        assert_eq!(responses[1]["id"], 2);
+        let tools = responses[1]["result"]["tools"].as_array().unwrap();
+        assert!(tools.iter().any(|t| t["name"] == "list_emails"));
+        assert!(tools.iter().all(|t| t["inputSchema"].is_object()));
+    }
+
+    #[tokio::test]
+    async fn test_stdio_tools_call() {
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Hello".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        storage.store_email(email.clone()).await.unwrap();
+        let server = test_server(storage);
+
+        let input = format!(
+            "{}\n{}\n{}\n",
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": "read_email", "arguments": { "email_id": email.id } }
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "tools/call",
+                "params": { "name": "read_email", "arguments": { "email_id": "missing" } }
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "tools/call",
+                "params": { "name": "nonexistent_tool" }
+            }),
+        );
+        let responses = run_session(&server, &input).await;
+
+        assert_eq!(responses.len(), 3);
+
+        let result = &responses[0]["result"];
+        assert_eq!(result["isError"], false);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        let returned: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(returned["subject"], "Hello");
+
+        assert_eq!(responses[1]["result"]["isError"], true);
+        assert_eq!(
+            responses[1]["result"]["content"][0]["text"],
+            "Email not found"
+        );
+
+        assert_eq!(responses[2]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_stdio_protocol_errors() {
+        let storage = Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let server = test_server(storage);
+
+        let input = concat!(
+            "not json\n",
+            "\n",
+            r#"{"jsonrpc":"2.0","id":7,"method":"resources/subscribe"}"#,
+            "\n",
+        );
+        let responses = run_session(&server, input).await;
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[0]["id"], Value::Null);
+        assert_eq!(responses[1]["id"], 7);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,116 @@
+//! Per-source-IP limits, counted over a sliding window
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding-window counter of events (messages, requests) from each source IP
+#[derive(Debug)]
+pub struct IpRateLimiter {
+    max_events: u32,
+    window: Duration,
+    hits: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl IpRateLimiter {
+    /// Allow at most `max_events` per IP in any `window`
+    pub fn new(max_events: u32, window: Duration) -> Self {
+        Self {
+            max_events,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Allow at most `max_events` per IP in any hour
+    pub fn per_hour(max_events: u32) -> Self {
+        Self::new(max_events, Duration::from_secs(60 * 60))
+    }
+
+    /// Allow at most `max_events` per IP in any minute
+    pub fn per_minute(max_events: u32) -> Self {
+        Self::new(max_events, Duration::from_secs(60))
+    }
+
+    /// The most events allowed per IP in one window
+    pub fn limit(&self) -> u32 {
+        self.max_events
+    }
+
+    /// Record an event from `ip` at `now`, returning how many more the IP may send in the
+    /// window, or, if it is already at its limit, how long until it may send again (the
+    /// event is then not recorded)
+    pub fn acquire(&self, ip: IpAddr, now: Instant) -> Result<u32, Duration> {
+        let mut hits = self.hits.lock().unwrap();
+        self.prune(&mut hits, now);
+
+        let times = hits.entry(ip).or_default();
+        if times.len() >= self.max_events as usize {
+            let retry_after = times
+                .front()
+                .map(|&oldest| {
+                    self.window
+                        .saturating_sub(now.saturating_duration_since(oldest))
+                })
+                .unwrap_or(self.window);
+            return Err(retry_after);
+        }
+        times.push_back(now);
+        Ok(self.max_events - times.len() as u32)
+    }
+
+    /// Drop hits older than the window, and IPs left with none
+    fn prune(&self, hits: &mut HashMap<IpAddr, VecDeque<Instant>>, now: Instant) {
+        hits.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|&t| now.saturating_duration_since(t) >= self.window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+    }
+
+    #[cfg(test)]
+    fn tracked_ips(&self) -> usize {
+        self.hits.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windowed_counter() {
+        let limiter = IpRateLimiter::new(2, Duration::from_secs(60));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire(ip, start), Ok(1));
+        assert_eq!(limiter.acquire(ip, start + Duration::from_secs(10)), Ok(0));
+        assert_eq!(
+            limiter.acquire(ip, start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        // Other IPs are counted separately
+        assert!(limiter
+            .acquire(other, start + Duration::from_secs(20))
+            .is_ok());
+
+        // The first event leaves the window, freeing one slot
+        assert!(limiter.acquire(ip, start + Duration::from_secs(60)).is_ok());
+        assert!(limiter
+            .acquire(ip, start + Duration::from_secs(61))
+            .is_err());
+
+        // Idle IPs are pruned entirely
+        assert!(limiter
+            .acquire(ip, start + Duration::from_secs(200))
+            .is_ok());
+        assert_eq!(limiter.tracked_ips(), 1);
+    }
+}
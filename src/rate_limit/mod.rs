@@ -1,16 +1,21 @@
+pub mod ip;
+
 use anyhow::Result;
 use axum::{
-    extract::{Request, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 use tracing::{debug, warn};
 
 use crate::storage::StorageBackend;
+use ip::IpRateLimiter;
 
 /// Rate limit configuration per user/mailbox
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,11 +82,162 @@ pub struct RateLimitCheck {
     pub daily_count: u32,
     pub daily_limit: u32,
     pub retry_after: Option<u64>,
+    /// The hourly bucket after this request, as reported in the `X-RateLimit-*` headers
+    pub bucket: BucketStatus,
+}
+
+/// What is left of a mailbox's hourly bucket, the one that limits bursts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketStatus {
+    /// Requests the full bucket allows in one burst
+    pub limit: u32,
+    /// Whole requests left in the bucket, or in the daily bucket if it holds fewer
+    pub remaining: u32,
+    /// Seconds until the hourly bucket is full again, or until the next request is
+    /// allowed when this one was rejected
+    pub reset_after: u64,
+}
+
+/// Token bucket refilled continuously up to a fixed capacity
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+}
+
+impl TokenBucket {
+    fn full(capacity: f64, now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    /// Add the tokens earned since the last update, capped at `capacity`
+    fn refill(&mut self, capacity: f64, tokens_per_second: f64, now: DateTime<Utc>) {
+        let elapsed = (now - self.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * tokens_per_second).min(capacity);
+        self.updated_at = now;
+    }
+
+    /// Seconds until a whole token is available (zero if one already is)
+    fn wait_secs(&self, tokens_per_second: f64) -> u64 {
+        if self.tokens >= 1.0 {
+            0
+        } else if tokens_per_second <= 0.0 {
+            u64::MAX
+        } else {
+            ((1.0 - self.tokens) / tokens_per_second).ceil() as u64
+        }
+    }
+
+    /// Seconds until the bucket is back at `capacity`
+    fn secs_until_full(&self, capacity: f64, tokens_per_second: f64) -> u64 {
+        if self.tokens >= capacity {
+            0
+        } else if tokens_per_second <= 0.0 {
+            u64::MAX
+        } else {
+            ((capacity - self.tokens) / tokens_per_second).ceil() as u64
+        }
+    }
+}
+
+/// A mailbox's hourly and daily buckets
+#[derive(Debug, Clone)]
+struct MailboxBuckets {
+    hourly: TokenBucket,
+    daily: TokenBucket,
+    /// When both buckets are full again, after which they can be forgotten
+    full_at: DateTime<Utc>,
+}
+
+/// Most of the hourly allowance that may be used in one burst
+///
+/// Without a cap below the hourly limit a full bucket would allow the whole hour's
+/// requests at once, which is the burstiness the bucket is meant to avoid.
+fn hourly_burst(requests_per_hour: u32) -> f64 {
+    (requests_per_hour as f64 / 10.0).ceil().max(1.0)
+}
+
+/// In-memory token buckets for every rate-limited mailbox
+///
+/// The hourly bucket refills at `requests_per_hour / 3600` tokens per second and the
+/// daily one at `requests_per_day / 86400`, so limits apply smoothly instead of resetting
+/// at window boundaries. Buckets start full and are not persisted across restarts, and
+/// buckets that have refilled completely are dropped, since a new one starts out the same.
+#[derive(Debug, Default)]
+pub struct RateLimitBuckets {
+    buckets: std::sync::Mutex<std::collections::HashMap<String, MailboxBuckets>>,
+}
+
+impl RateLimitBuckets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    fn tracked_mailboxes(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// Take a token from both of the mailbox's buckets, returning what is left of them,
+    /// or the seconds until the request would be allowed (taking nothing)
+    fn try_acquire(&self, rate_limit: &RateLimit, now: DateTime<Utc>) -> Result<BucketStatus, u64> {
+        let hourly_capacity = hourly_burst(rate_limit.requests_per_hour);
+        let hourly_rate = rate_limit.requests_per_hour as f64 / 3600.0;
+        let daily_capacity = rate_limit.requests_per_day as f64;
+        let daily_rate = rate_limit.requests_per_day as f64 / 86400.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        // Forget mailboxes whose buckets are full again, so every address ever seen
+        // doesn't stay in memory
+        buckets.retain(|_, entry| entry.full_at > now);
+        let entry = buckets
+            .entry(rate_limit.mailbox_address.clone())
+            .or_insert_with(|| MailboxBuckets {
+                hourly: TokenBucket::full(hourly_capacity, now),
+                daily: TokenBucket::full(daily_capacity, now),
+                full_at: now,
+            });
+        entry.hourly.refill(hourly_capacity, hourly_rate, now);
+        entry.daily.refill(daily_capacity, daily_rate, now);
+
+        let wait = entry
+            .hourly
+            .wait_secs(hourly_rate)
+            .max(entry.daily.wait_secs(daily_rate));
+        if wait > 0 {
+            return Err(wait);
+        }
+
+        entry.hourly.tokens -= 1.0;
+        entry.daily.tokens -= 1.0;
+        let secs_until_full = entry
+            .hourly
+            .secs_until_full(hourly_capacity, hourly_rate)
+            .max(entry.daily.secs_until_full(daily_capacity, daily_rate));
+        // Buckets that never refill (a zero limit) are kept for good
+        entry.full_at = i64::try_from(secs_until_full)
+            .ok()
+            .and_then(chrono::Duration::try_seconds)
+            .and_then(|wait| now.checked_add_signed(wait))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Ok(BucketStatus {
+            limit: hourly_capacity as u32,
+            remaining: entry.hourly.tokens.min(entry.daily.tokens).floor() as u32,
+            reset_after: entry.hourly.secs_until_full(hourly_capacity, hourly_rate),
+        })
+    }
 }
 
 /// Check if a request should be allowed based on rate limits
+///
+/// A token is taken from the mailbox's buckets when the request is allowed. The hourly
+/// and daily counts are the requests recorded in the last hour and day, for reporting.
 pub async fn check_rate_limit(
     storage: &Arc<dyn StorageBackend>,
+    buckets: &RateLimitBuckets,
     mailbox_address: &str,
 ) -> Result<RateLimitCheck> {
     // Get or create rate limit for this mailbox
@@ -116,56 +272,27 @@ pub async fn check_rate_limit(
         rate_limit.requests_per_day
     );
 
-    // Check if limits are exceeded
-    let hourly_exceeded = hourly_count >= rate_limit.requests_per_hour;
-    let daily_exceeded = daily_count >= rate_limit.requests_per_day;
-
-    if hourly_exceeded || daily_exceeded {
-        // Calculate retry-after in seconds
-        let retry_after = if hourly_exceeded {
-            // If hourly limit exceeded, retry after the oldest request in the hour window expires
-            let oldest_request_time = storage
-                .get_oldest_request_since(mailbox_address, one_hour_ago)
-                .await?;
-            if let Some(oldest) = oldest_request_time {
-                let retry_time = oldest + chrono::Duration::hours(1);
-                let seconds_until_retry = (retry_time - now).num_seconds();
-                Some(seconds_until_retry.max(0) as u64)
-            } else {
-                Some(3600) // Default to 1 hour
-            }
-        } else {
-            // Daily limit exceeded
-            let oldest_request_time = storage
-                .get_oldest_request_since(mailbox_address, one_day_ago)
-                .await?;
-            if let Some(oldest) = oldest_request_time {
-                let retry_time = oldest + chrono::Duration::days(1);
-                let seconds_until_retry = (retry_time - now).num_seconds();
-                Some(seconds_until_retry.max(0) as u64)
-            } else {
-                Some(86400) // Default to 24 hours
-            }
-        };
+    let (retry_after, bucket) = match buckets.try_acquire(&rate_limit, now) {
+        Ok(bucket) => (None, bucket),
+        Err(retry_after) => (
+            Some(retry_after),
+            BucketStatus {
+                limit: hourly_burst(rate_limit.requests_per_hour) as u32,
+                remaining: 0,
+                reset_after: retry_after,
+            },
+        ),
+    };
 
-        Ok(RateLimitCheck {
-            allowed: false,
-            hourly_count,
-            hourly_limit: rate_limit.requests_per_hour,
-            daily_count,
-            daily_limit: rate_limit.requests_per_day,
-            retry_after,
-        })
-    } else {
-        Ok(RateLimitCheck {
-            allowed: true,
-            hourly_count,
-            hourly_limit: rate_limit.requests_per_hour,
-            daily_count,
-            daily_limit: rate_limit.requests_per_day,
-            retry_after: None,
-        })
-    }
+    Ok(RateLimitCheck {
+        allowed: retry_after.is_none(),
+        hourly_count,
+        hourly_limit: rate_limit.requests_per_hour,
+        daily_count,
+        daily_limit: rate_limit.requests_per_day,
+        retry_after,
+        bucket,
+    })
 }
 
 /// Record a request for rate limiting
@@ -177,12 +304,79 @@ pub async fn record_request(
     storage.record_rate_limit_request(request).await
 }
 
+/// Reject the request with a 429 when its client IP is over the per-IP limit
+///
+/// Requests without a known peer address (e.g. in-process tests) are not IP limited.
+fn check_ip_limit(
+    limiter: Option<&IpRateLimiter>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Option<Response> {
+    let limiter = limiter?;
+    let ConnectInfo(addr) = connect_info?;
+    let ip = addr.ip();
+    let wait = limiter.acquire(ip, Instant::now()).err()?;
+
+    // Round up so clients never retry before the window frees a slot
+    let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    warn!(
+        "Rate limit exceeded for IP {}: {} requests per minute",
+        ip,
+        limiter.limit()
+    );
+    let body = serde_json::json!({
+        "error": "Rate limit exceeded",
+        "limit": limiter.limit(),
+        "retry_after": retry_after
+    });
+    Some(
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [
+                (header::RETRY_AFTER, retry_after.to_string()),
+                (
+                    header::HeaderName::from_static("x-ratelimit-remaining"),
+                    "0".to_string(),
+                ),
+            ],
+            body.to_string(),
+        )
+            .into_response(),
+    )
+}
+
+/// Middleware enforcing only the per-IP limit, for routes without mailbox rate limits
+/// such as login, registration and static files
+pub async fn ip_rate_limit_middleware(
+    State(ip_limiter): State<Option<Arc<IpRateLimiter>>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match check_ip_limit(ip_limiter.as_deref(), connect_info) {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+/// State for [`rate_limit_middleware`]: storage, mailbox buckets and the optional per-IP limiter
+pub type RateLimitState = (
+    Arc<dyn StorageBackend>,
+    Arc<RateLimitBuckets>,
+    Option<Arc<IpRateLimiter>>,
+);
+
 /// Middleware to enforce rate limits on API requests
 pub async fn rate_limit_middleware(
-    State(storage): State<Arc<dyn StorageBackend>>,
+    State((storage, buckets, ip_limiter)): State<RateLimitState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     request: Request,
     next: Next,
 ) -> Result<Response, (StatusCode, String)> {
+    // Limit each client IP first, whatever the route
+    if let Some(response) = check_ip_limit(ip_limiter.as_deref(), connect_info) {
+        return Ok(response);
+    }
+
     // Extract mailbox address from request path
     // For now, we'll apply rate limiting to all API endpoints
     // You can customize this logic to extract the mailbox from specific routes
@@ -198,7 +392,7 @@ pub async fn rate_limit_middleware(
 
     if let Some(address) = mailbox_address {
         // Check rate limit
-        match check_rate_limit(&storage, &address).await {
+        match check_rate_limit(&storage, &buckets, &address).await {
             Ok(check) => {
                 if !check.allowed {
                     warn!(
@@ -220,20 +414,30 @@ pub async fn rate_limit_middleware(
                         "retry_after": retry_after
                     });
 
-                    return Err((
+                    let mut response = (
                         StatusCode::TOO_MANY_REQUESTS,
                         format!(
                             "{}\nRetry-After: {}",
                             serde_json::to_string(&response).unwrap_or_default(),
                             retry_after
                         ),
-                    ));
+                    )
+                        .into_response();
+                    set_rate_limit_headers(&mut response, &check);
+                    response
+                        .headers_mut()
+                        .insert(header::RETRY_AFTER, retry_after.into());
+                    return Ok(response);
                 }
 
                 // Record the request
                 if let Err(e) = record_request(&storage, &address).await {
                     warn!("Failed to record rate limit request: {}", e);
                 }
+
+                let mut response = next.run(request).await;
+                set_rate_limit_headers(&mut response, &check);
+                return Ok(response);
             }
             Err(e) => {
                 warn!("Failed to check rate limit: {}", e);
@@ -245,6 +449,18 @@ pub async fn rate_limit_middleware(
     Ok(next.run(request).await)
 }
 
+/// Describe the mailbox's hourly token bucket in `X-RateLimit-*` headers
+///
+/// `Limit` is the bucket's burst size and `Remaining` the requests left in it after the
+/// current one, so the request after `Remaining: 0` is rejected unless tokens refilled
+/// meanwhile. `Reset` is the number of seconds until the bucket is full again.
+fn set_rate_limit_headers(response: &mut Response, check: &RateLimitCheck) {
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit", check.bucket.limit.into());
+    headers.insert("x-ratelimit-remaining", check.bucket.remaining.into());
+    headers.insert("x-ratelimit-reset", check.bucket.reset_after.into());
+}
+
 /// Extract mailbox address from request path
 fn extract_mailbox_from_path(path: &str) -> Option<String> {
     let parts: Vec<&str> = path.split('/').collect();
@@ -302,4 +518,196 @@ mod tests {
         assert_eq!(limit.requests_per_hour, 50);
         assert_eq!(limit.requests_per_day, 500);
     }
+
+    #[test]
+    fn test_requests_spread_over_an_hour_pass() {
+        let buckets = RateLimitBuckets::new();
+        let limit = RateLimit::with_limits("test@example.com".to_string(), 100, 1000);
+        let start = Utc::now();
+
+        for i in 0..100 {
+            let now = start + chrono::Duration::seconds(i * 36);
+            assert!(
+                buckets.try_acquire(&limit, now).is_ok(),
+                "request {} was limited",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_burst_trips_limit() {
+        let buckets = RateLimitBuckets::new();
+        let limit = RateLimit::with_limits("test@example.com".to_string(), 100, 1000);
+        let start = Utc::now();
+
+        let results: Vec<_> = (0..100)
+            .map(|i| buckets.try_acquire(&limit, start + chrono::Duration::milliseconds(i * 10)))
+            .collect();
+        let allowed = results.iter().filter(|r| r.is_ok()).count();
+        assert_eq!(allowed, 10);
+
+        // One token refills every 36 seconds
+        let retry_after = results.last().unwrap().unwrap_err();
+        // Ten tokens were taken, each refilled in 36 seconds
+        assert_eq!(
+            results[9],
+            Ok(BucketStatus {
+                limit: 10,
+                remaining: 0,
+                reset_after: 360
+            })
+        );
+        assert!((1..=36).contains(&retry_after));
+        let later = start + chrono::Duration::seconds(37);
+        assert!(buckets.try_acquire(&limit, later).is_ok());
+        assert!(buckets.try_acquire(&limit, later).is_err());
+
+        // Other mailboxes have their own buckets
+        let other = RateLimit::with_limits("other@example.com".to_string(), 100, 1000);
+        assert!(buckets.try_acquire(&other, start).is_ok());
+    }
+
+    #[test]
+    fn test_full_buckets_are_forgotten() {
+        let buckets = RateLimitBuckets::new();
+        let start = Utc::now();
+        for i in 0..50 {
+            let limit = RateLimit::with_limits(format!("user{}@example.com", i), 100, 1000);
+            assert!(buckets.try_acquire(&limit, start).is_ok());
+        }
+        assert_eq!(buckets.tracked_mailboxes(), 50);
+
+        // The one daily token each mailbox used refills in 86.4 seconds, after which
+        // their buckets are full and only the mailbox just seen is tracked
+        let limit = RateLimit::with_limits("new@example.com".to_string(), 100, 1000);
+        let later = start + chrono::Duration::seconds(87);
+        assert!(buckets.try_acquire(&limit, later).is_ok());
+        assert_eq!(buckets.tracked_mailboxes(), 1);
+    }
+
+    #[test]
+    fn test_daily_limit_applies() {
+        let buckets = RateLimitBuckets::new();
+        let limit = RateLimit::with_limits("test@example.com".to_string(), 3600, 5);
+        let start = Utc::now();
+
+        for i in 0..5 {
+            assert!(buckets
+                .try_acquire(&limit, start + chrono::Duration::seconds(i))
+                .is_ok());
+        }
+        let retry_after = buckets
+            .try_acquire(&limit, start + chrono::Duration::seconds(5))
+            .unwrap_err();
+        // A daily token refills every 86400 / 5 seconds
+        assert!(retry_after > 3600);
+    }
+
+    #[tokio::test]
+    async fn test_ip_limit_rejects_request_over_limit() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{
+            body::Body, extract::connect_info::MockConnectInfo, middleware, routing::get, Router,
+        };
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let state: RateLimitState = (
+            storage,
+            Arc::new(RateLimitBuckets::new()),
+            Some(Arc::new(IpRateLimiter::per_minute(3))),
+        );
+        let app = Router::new()
+            .route("/api/version", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(state, rate_limit_middleware))
+            .layer(MockConnectInfo(SocketAddr::from(([192, 0, 2, 1], 4000))));
+
+        let request = || {
+            Request::builder()
+                .uri("/api/version")
+                .body(Body::empty())
+                .unwrap()
+        };
+        for _ in 0..3 {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_headers() {
+        use crate::storage::sqlite::SqliteBackend;
+        use axum::{body::Body, middleware, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .create_rate_limit(RateLimit::with_limits("user".to_string(), 100, 1000))
+            .await
+            .unwrap();
+        let state: RateLimitState = (storage, Arc::new(RateLimitBuckets::new()), None);
+        let app = Router::new()
+            .route("/api/emails/:address", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(state, rate_limit_middleware));
+
+        let mut response = None;
+        for _ in 0..2 {
+            response = Some(
+                app.clone()
+                    .oneshot(
+                        Request::builder()
+                            .uri("/api/emails/user")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+        let response = response.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let header_value = |response: &Response, name: &str| -> u64 {
+            response.headers()[name].to_str().unwrap().parse().unwrap()
+        };
+        // The hourly bucket holds a burst of ten, two of which are used, each refilled
+        // in 36 seconds
+        assert_eq!(header_value(&response, "x-ratelimit-limit"), 10);
+        assert_eq!(header_value(&response, "x-ratelimit-remaining"), 8);
+        let reset = header_value(&response, "x-ratelimit-reset");
+        assert!((71..=72).contains(&reset), "reset was {}", reset);
+
+        // Remaining counts down to zero on the last request the bucket allows
+        let request = || {
+            Request::builder()
+                .uri("/api/emails/user")
+                .body(Body::empty())
+                .unwrap()
+        };
+        for expected in (0..8).rev() {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(header_value(&response, "x-ratelimit-remaining"), expected);
+        }
+
+        // The next one is rejected, with a standard Retry-After header
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header_value(&response, "x-ratelimit-remaining"), 0);
+        let retry_after = header_value(&response, header::RETRY_AFTER.as_str());
+        assert!((1..=36).contains(&retry_after));
+    }
 }
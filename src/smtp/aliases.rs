@@ -0,0 +1,159 @@
+//! Mailbox aliases, resolved to their destination mailboxes before inbound email is stored
+
+use anyhow::Result;
+use std::collections::HashSet;
+use tracing::{debug, warn};
+
+use crate::storage::StorageBackend;
+
+/// Replace each aliased recipient with its destination mailboxes, following chains of
+/// aliases
+///
+/// Destinations keep the domain of the original recipient. A loop (A→B→A) is cut where it
+/// revisits a local part; if that leaves no destinations, the original recipient is used.
+pub async fn resolve_aliases(
+    storage: &dyn StorageBackend,
+    recipients: &[String],
+) -> Result<Vec<String>> {
+    let mut resolved = Vec::with_capacity(recipients.len());
+
+    for recipient in recipients {
+        let (local_part, domain) = match recipient.split_once('@') {
+            Some((local_part, domain)) => (local_part, Some(domain)),
+            None => (recipient.as_str(), None),
+        };
+
+        let mut visited = HashSet::new();
+        let mut local_parts = Vec::new();
+        expand(storage, local_part, &mut visited, &mut local_parts).await?;
+
+        if local_parts.is_empty() {
+            warn!(
+                "Alias loop for {} has no destination, delivering to it directly",
+                recipient
+            );
+            resolved.push(recipient.clone());
+            continue;
+        }
+
+        for destination in local_parts {
+            let address = match domain {
+                Some(domain) => format!("{}@{}", destination, domain),
+                None => destination,
+            };
+            if address != *recipient {
+                debug!("Resolved alias {} to {}", recipient, address);
+            }
+            resolved.push(address);
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Depth-first expansion of one local part into the mailboxes it delivers to
+async fn expand(
+    storage: &dyn StorageBackend,
+    local_part: &str,
+    visited: &mut HashSet<String>,
+    out: &mut Vec<String>,
+) -> Result<()> {
+    // Iterative to avoid boxing a recursive async fn
+    let mut stack = vec![local_part.to_string()];
+    while let Some(current) = stack.pop() {
+        if !visited.insert(current.clone()) {
+            debug!("Skipping already visited alias {}", current);
+            continue;
+        }
+
+        let destinations = storage.get_aliases_for(&current).await?;
+        if destinations.is_empty() {
+            out.push(current);
+        } else {
+            // Reversed so destinations are expanded in their stored order
+            stack.extend(destinations.into_iter().rev());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteBackend;
+
+    #[tokio::test]
+    async fn test_single_hop() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        storage
+            .create_alias("sales", &["alice".to_string()])
+            .await
+            .unwrap();
+
+        let resolved = resolve_aliases(
+            &storage,
+            &[
+                "sales@example.com".to_string(),
+                "bob@example.com".to_string(),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolved,
+            vec![
+                "alice@example.com".to_string(),
+                "bob@example.com".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_and_chains() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        storage
+            .create_alias("support", &["alice".to_string(), "team".to_string()])
+            .await
+            .unwrap();
+        storage
+            .create_alias("team", &["bob".to_string(), "carol".to_string()])
+            .await
+            .unwrap();
+
+        let mut resolved = resolve_aliases(&storage, &["support@example.com".to_string()])
+            .await
+            .unwrap();
+        resolved.sort();
+        assert_eq!(
+            resolved,
+            vec![
+                "alice@example.com".to_string(),
+                "bob@example.com".to_string(),
+                "carol@example.com".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_loop_detection() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        storage.create_alias("a", &["b".to_string()]).await.unwrap();
+        storage.create_alias("b", &["a".to_string()]).await.unwrap();
+        storage
+            .create_alias("c", &["a".to_string(), "dave".to_string()])
+            .await
+            .unwrap();
+
+        // A pure loop falls back to the original recipient
+        let resolved = resolve_aliases(&storage, &["a@example.com".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(resolved, vec!["a@example.com".to_string()]);
+
+        // A loop alongside a real mailbox delivers only to the real one
+        let resolved = resolve_aliases(&storage, &["c@example.com".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(resolved, vec!["dave@example.com".to_string()]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Per-email attachment limits, enforced before inbound email is stored
+
+use crate::config::{AttachmentLimitsConfig, AttachmentOverflowPolicy};
+use crate::storage::models::Email;
+
+/// Result of checking an email's attachments against the limits
+#[derive(Debug, PartialEq, Eq)]
+pub enum AttachmentLimitOutcome {
+    /// The email may be stored; `dropped` names the attachments whose content was removed
+    Accepted { dropped: Vec<String> },
+    /// The attachments exceed the limits and the message must be refused
+    Rejected,
+}
+
+/// Check an email's attachments against the count and total size limits
+///
+/// Attachments are kept in message order while they fit. With the truncate policy the
+/// rest keep their metadata but lose their content and are marked `dropped`, and the raw
+/// message is discarded since it would still carry them. With the reject policy the
+/// email is left unchanged.
+pub fn enforce_attachment_limits(
+    limits: &AttachmentLimitsConfig,
+    email: &mut Email,
+) -> AttachmentLimitOutcome {
+    let mut count = 0;
+    let mut bytes = 0;
+    let mut over = Vec::new();
+    for (index, attachment) in email.attachments.iter().enumerate() {
+        let fits = limits.max_count.is_none_or(|max| count < max)
+            && limits
+                .max_total_bytes
+                .is_none_or(|max| bytes + attachment.size <= max);
+        if fits {
+            count += 1;
+            bytes += attachment.size;
+        } else {
+            over.push(index);
+        }
+    }
+
+    if over.is_empty() {
+        return AttachmentLimitOutcome::Accepted {
+            dropped: Vec::new(),
+        };
+    }
+    if limits.policy == AttachmentOverflowPolicy::Reject {
+        return AttachmentLimitOutcome::Rejected;
+    }
+
+    let mut dropped = Vec::new();
+    for index in over {
+        let attachment = &mut email.attachments[index];
+        attachment.content = String::new();
+        attachment.dropped = true;
+        dropped.push(attachment.filename.clone());
+    }
+    email.raw = None;
+    AttachmentLimitOutcome::Accepted { dropped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smtp::parser::parse_email;
+
+    /// Multipart email with attachments of 10, 20, 30 and 5 bytes
+    fn email_with_attachments() -> Email {
+        let mut raw = String::from(
+            "From: sender@example.com\r\nTo: test@example.com\r\nSubject: Files\r\n\
+             MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+             --b\r\nContent-Type: text/plain\r\n\r\nSee attached\r\n",
+        );
+        for (name, size) in [("a.txt", 10), ("b.txt", 20), ("c.txt", 30), ("d.txt", 5)] {
+            raw.push_str(&format!(
+                "--b\r\nContent-Type: text/plain\r\n\
+                 Content-Disposition: attachment; filename=\"{}\"\r\n\r\n{}\r\n",
+                name,
+                "x".repeat(size)
+            ));
+        }
+        raw.push_str("--b--\r\n");
+        let email = parse_email(raw.as_bytes(), "test@example.com").unwrap();
+        assert_eq!(email.attachments.len(), 4);
+        email
+    }
+
+    fn limits(
+        max_count: Option<usize>,
+        max_total_bytes: Option<usize>,
+        policy: AttachmentOverflowPolicy,
+    ) -> AttachmentLimitsConfig {
+        AttachmentLimitsConfig {
+            max_count,
+            max_total_bytes,
+            policy,
+        }
+    }
+
+    #[test]
+    fn test_truncate_drops_attachments_over_the_limits() {
+        let mut email = email_with_attachments();
+        let outcome = enforce_attachment_limits(
+            &limits(Some(3), Some(40), AttachmentOverflowPolicy::Truncate),
+            &mut email,
+        );
+
+        // c.txt would take the total past 40 bytes; d.txt still fits after it
+        assert_eq!(
+            outcome,
+            AttachmentLimitOutcome::Accepted {
+                dropped: vec!["c.txt".to_string()]
+            }
+        );
+        let c = &email.attachments[2];
+        assert!(c.dropped);
+        assert_eq!(c.content, "");
+        assert_eq!(c.size, 30);
+        assert!(email
+            .attachments
+            .iter()
+            .filter(|a| a.filename != "c.txt")
+            .all(|a| !a.dropped && !a.content.is_empty()));
+        assert_eq!(email.raw, None);
+
+        let mut email = email_with_attachments();
+        let outcome = enforce_attachment_limits(
+            &limits(Some(2), None, AttachmentOverflowPolicy::Truncate),
+            &mut email,
+        );
+        assert_eq!(
+            outcome,
+            AttachmentLimitOutcome::Accepted {
+                dropped: vec!["c.txt".to_string(), "d.txt".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_reject_leaves_the_email_unchanged() {
+        let mut email = email_with_attachments();
+        let outcome = enforce_attachment_limits(
+            &limits(Some(3), None, AttachmentOverflowPolicy::Reject),
+            &mut email,
+        );
+        assert_eq!(outcome, AttachmentLimitOutcome::Rejected);
+        assert!(email.attachments.iter().all(|a| !a.dropped));
+        assert!(email.raw.is_some());
+
+        // Within both limits nothing happens under either policy
+        for policy in [
+            AttachmentOverflowPolicy::Reject,
+            AttachmentOverflowPolicy::Truncate,
+        ] {
+            let mut email = email_with_attachments();
+            let outcome = enforce_attachment_limits(&limits(Some(4), Some(65), policy), &mut email);
+            assert_eq!(
+                outcome,
+                AttachmentLimitOutcome::Accepted {
+                    dropped: Vec::new()
+                }
+            );
+            assert!(email.raw.is_some());
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Attachment scanning with ClamAV's clamd daemon over its INSTREAM protocol
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use crate::storage::models::Attachment;
+
+/// Largest chunk sent in one INSTREAM frame
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Time allowed for connecting to clamd and scanning one attachment
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `scan_result` of an attachment clamd found nothing in
+pub const SCAN_CLEAN: &str = "clean";
+
+/// Outcome of scanning one stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    /// Signature name reported by clamd
+    Infected(String),
+}
+
+impl ScanVerdict {
+    /// Value stored as the attachment's `scan_result`
+    pub fn as_scan_result(&self) -> String {
+        match self {
+            ScanVerdict::Clean => SCAN_CLEAN.to_string(),
+            ScanVerdict::Infected(signature) => format!("infected: {}", signature),
+        }
+    }
+}
+
+/// Client for a clamd daemon listening on TCP (`CLAMAV_ADDRESS`)
+pub struct ClamavScanner {
+    address: String,
+    reject_infected: bool,
+}
+
+impl ClamavScanner {
+    pub fn new(address: String, reject_infected: bool) -> Self {
+        Self {
+            address,
+            reject_infected,
+        }
+    }
+
+    /// Whether mail with an infected attachment should be refused
+    pub fn reject_infected(&self) -> bool {
+        self.reject_infected
+    }
+
+    /// Scan each attachment and record the verdict in its `scan_result`
+    ///
+    /// Scanning is best-effort: an attachment that could not be scanned, for example because
+    /// clamd is unreachable, is logged and left with no result. Returns whether any
+    /// attachment was found infected.
+    pub async fn scan_attachments(&self, attachments: &mut [Attachment]) -> bool {
+        let mut infected = false;
+        for attachment in attachments.iter_mut() {
+            let content = match STANDARD.decode(&attachment.content) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!(
+                        "⚠️ Could not decode attachment {} for scanning: {}",
+                        attachment.filename, e
+                    );
+                    continue;
+                }
+            };
+            match self.scan(&content).await {
+                Ok(verdict) => {
+                    debug!("Scanned attachment {}: {:?}", attachment.filename, verdict);
+                    infected |= matches!(verdict, ScanVerdict::Infected(_));
+                    attachment.scan_result = Some(verdict.as_scan_result());
+                }
+                Err(e) => {
+                    warn!(
+                        "⚠️ Virus scan of attachment {} failed, storing it unscanned: {:#}",
+                        attachment.filename, e
+                    );
+                }
+            }
+        }
+        infected
+    }
+
+    /// Send `data` to clamd and return its verdict
+    pub async fn scan(&self, data: &[u8]) -> Result<ScanVerdict> {
+        tokio::time::timeout(SCAN_TIMEOUT, async {
+            let mut stream = TcpStream::connect(&self.address)
+                .await
+                .with_context(|| format!("Failed to connect to clamd at {}", self.address))?;
+            instream(&mut stream, data).await
+        })
+        .await
+        .context("Timed out waiting for clamd")?
+    }
+}
+
+/// Run one INSTREAM command: the data goes out as length-prefixed chunks ended by a
+/// zero-length chunk, and clamd answers with a single null-terminated line
+async fn instream<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    data: &[u8],
+) -> Result<ScanVerdict> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        stream
+            .write_all(&(chunk.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reply = Vec::new();
+    stream
+        .read_to_end(&mut reply)
+        .await
+        .context("Failed to read clamd reply")?;
+    let reply = String::from_utf8_lossy(&reply);
+    parse_reply(reply.trim_end_matches(['\0', '\n']))
+}
+
+/// Interpret `stream: OK`, `stream: <signature> FOUND` or an error reply
+fn parse_reply(reply: &str) -> Result<ScanVerdict> {
+    let status = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if status == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = status.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(signature.to_string()))
+    } else {
+        bail!("clamd returned an error: {}", reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Test signature the mock daemon reports when a stream contains it
+    const MARKER: &[u8] = b"X5O!P%@AP-TEST-SIGNATURE";
+
+    /// clamd stand-in that checks the INSTREAM framing of one connection, reassembles the
+    /// stream and reports whether it contains `MARKER`
+    async fn mock_clamd() -> (String, tokio::task::JoinHandle<Vec<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 10];
+            stream.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+
+            let mut chunk_sizes = Vec::new();
+            let mut data = Vec::new();
+            loop {
+                let size = stream.read_u32().await.unwrap() as usize;
+                chunk_sizes.push(size);
+                if size == 0 {
+                    break;
+                }
+                let mut chunk = vec![0u8; size];
+                stream.read_exact(&mut chunk).await.unwrap();
+                data.extend_from_slice(&chunk);
+            }
+
+            let reply: &[u8] = if data.windows(MARKER.len()).any(|window| window == MARKER) {
+                b"stream: Test-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            stream.write_all(reply).await.unwrap();
+            chunk_sizes
+        });
+        (address, handle)
+    }
+
+    fn attachment(content: &[u8]) -> Attachment {
+        Attachment {
+            filename: "file.bin".to_string(),
+            content_type: "application/octet-stream".to_string(),
+            size: content.len(),
+            content: STANDARD.encode(content),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_instream_framing() {
+        let (address, clamd) = mock_clamd().await;
+        let scanner = ClamavScanner::new(address, false);
+
+        // Large enough to need two full chunks and a partial one
+        let mut data = vec![b'a'; CHUNK_SIZE * 2 + 10];
+        data.extend_from_slice(MARKER);
+        let verdict = scanner.scan(&data).await.unwrap();
+
+        assert_eq!(verdict, ScanVerdict::Infected("Test-Signature".to_string()));
+        assert_eq!(
+            clamd.await.unwrap(),
+            vec![CHUNK_SIZE, CHUNK_SIZE, 10 + MARKER.len(), 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_attachments_records_results() {
+        let (address, clamd) = mock_clamd().await;
+        let scanner = ClamavScanner::new(address, true);
+        let mut attachments = vec![attachment(b"harmless")];
+        assert!(!scanner.scan_attachments(&mut attachments).await);
+        assert_eq!(attachments[0].scan_result.as_deref(), Some(SCAN_CLEAN));
+        assert_eq!(clamd.await.unwrap(), vec![8, 0]);
+
+        let (address, _clamd) = mock_clamd().await;
+        let scanner = ClamavScanner::new(address, true);
+        let mut attachments = vec![attachment(MARKER)];
+        assert!(scanner.scan_attachments(&mut attachments).await);
+        assert_eq!(
+            attachments[0].scan_result.as_deref(),
+            Some("infected: Test-Signature")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_clamd_is_not_fatal() {
+        // Bind and drop a listener to get a port nothing is listening on
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let scanner = ClamavScanner::new(address, true);
+        let mut attachments = vec![attachment(MARKER)];
+        assert!(!scanner.scan_attachments(&mut attachments).await);
+        assert_eq!(attachments[0].scan_result, None);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("stream: OK").unwrap(), ScanVerdict::Clean);
+        assert_eq!(
+            parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND").unwrap(),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_reply("INSTREAM size limit exceeded. ERROR").is_err());
+    }
+}
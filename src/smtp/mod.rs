@@ -1,53 +1,160 @@
+pub mod aliases;
+pub mod attachment_limits;
+pub mod clamav;
+pub mod mx;
 pub mod parser;
+pub mod proxy_protocol;
+pub mod quota;
+pub mod routing;
+pub mod sender_rules;
+pub mod spf;
 
-use anyhow::Result;
-use mailin_embedded::{Handler, Server, SslConfig};
+use anyhow::{Context, Result};
+use mail_auth::DkimResult;
+use mailin::{Action, Handler, Session, SessionBuilder};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
-    Arc,
+    Arc, Mutex,
 };
-use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use uuid::Uuid;
 
+use crate::config::{configured_domain, AttachmentLimitsConfig, MailboxQuotaConfig};
+use crate::dkim::verify::{dkim_result_name, DkimVerifier};
+use crate::rate_limit::ip::IpRateLimiter;
 use crate::storage::{
     models::{Email, WebhookEvent},
     StorageBackend,
 };
-use crate::webhooks::WebhookTrigger;
-use parser::parse_email;
+use crate::webhooks::{DeletionNotifier, WebhookTrigger};
+use aliases::resolve_aliases;
+use attachment_limits::{enforce_attachment_limits, AttachmentLimitOutcome};
+use clamav::ClamavScanner;
+use mx::MxValidator;
+use parser::{parse_email, sanitize_email_html};
+use proxy_protocol::read_proxy_header;
+use quota::{enforce_quotas, QuotaOutcome};
+use routing::apply_routing_rules;
+use sender_rules::SenderRules;
+use spf::{SpfChecker, SpfResult};
+
+/// Idle time after which an SMTP session is dropped
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Background work the SMTP server waits for when shutting down
+#[derive(Default)]
+struct ServerTasks {
+    /// Accept loops, one per listening port
+    listeners: Mutex<JoinSet<()>>,
+    /// Storage and webhook tasks spawned for accepted emails
+    deliveries: Arc<Mutex<JoinSet<()>>>,
+}
 
 /// SMTP server that accepts all emails
 pub struct SmtpServer {
     storage: Arc<dyn StorageBackend>,
     email_sender: broadcast::Sender<Email>,
+    deletion_notifier: DeletionNotifier,
     domain_name: String,
+    /// Name announced in the greeting and EHLO reply
+    smtp_hostname: String,
+    additional_domains: Vec<String>,
     ssl_config: crate::config::SmtpSslConfig,
     reject_non_domain_emails: bool,
-    shutdown_flag: Arc<AtomicBool>,
+    catch_all_mailbox: Option<String>,
+    mailbox_quota: MailboxQuotaConfig,
+    attachment_limits: AttachmentLimitsConfig,
+    max_message_bytes: usize,
+    // Shared by every listener so the limit applies across ports
+    ip_limiter: Option<Arc<IpRateLimiter>>,
+    spf_checker: Option<Arc<SpfChecker>>,
+    dkim_verifier: Option<Arc<DkimVerifier>>,
+    clamav_scanner: Option<Arc<ClamavScanner>>,
+    mx_validator: Option<Arc<MxValidator>>,
+    sanitize_html: bool,
+    /// Expect a PROXY protocol header on every connection (`SMTP_PROXY_PROTOCOL`)
+    proxy_protocol: bool,
+    sender_rules: Arc<SenderRules>,
+    shutdown: Arc<watch::Sender<bool>>,
+    tasks: Arc<ServerTasks>,
 }
 
 impl SmtpServer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         storage: Arc<dyn StorageBackend>,
         email_sender: broadcast::Sender<Email>,
+        deletion_notifier: DeletionNotifier,
         domain_name: String,
+        smtp_hostname: String,
+        additional_domains: Vec<String>,
         ssl_config: crate::config::SmtpSslConfig,
         reject_non_domain_emails: bool,
+        catch_all_mailbox: Option<String>,
+        mailbox_quota: MailboxQuotaConfig,
+        attachment_limits: AttachmentLimitsConfig,
+        max_message_bytes: usize,
+        max_messages_per_ip_per_hour: Option<u32>,
+        spf_checker: Option<Arc<SpfChecker>>,
+        dkim_verifier: Option<Arc<DkimVerifier>>,
+        clamav_scanner: Option<Arc<ClamavScanner>>,
+        mx_validator: Option<Arc<MxValidator>>,
+        sanitize_html: bool,
+        proxy_protocol: bool,
+        sender_rules: Arc<SenderRules>,
     ) -> Self {
         Self {
             storage,
             email_sender,
+            deletion_notifier,
             domain_name,
+            smtp_hostname,
+            additional_domains,
             ssl_config,
             reject_non_domain_emails,
-            shutdown_flag: Arc::new(AtomicBool::new(false)),
+            catch_all_mailbox,
+            mailbox_quota,
+            attachment_limits,
+            max_message_bytes,
+            ip_limiter: max_messages_per_ip_per_hour
+                .map(|max| Arc::new(IpRateLimiter::per_hour(max))),
+            spf_checker,
+            dkim_verifier,
+            clamav_scanner,
+            mx_validator,
+            sanitize_html,
+            proxy_protocol,
+            sender_rules,
+            shutdown: Arc::new(watch::channel(false).0),
+            tasks: Arc::new(ServerTasks::default()),
         }
     }
 
-    /// Set the shutdown flag to signal all SMTP servers to stop
-    pub fn shutdown(&self) {
-        self.shutdown_flag.store(true, Ordering::SeqCst);
+    /// Stop all SMTP listeners and wait for accepted emails to finish storing
+    ///
+    /// Sessions still open are closed; a message is only lost if its client had not
+    /// yet received the reply to the end of its data.
+    pub async fn shutdown(&self) {
+        self.shutdown.send_replace(true);
         info!("🛑 SMTP server shutdown signal sent");
+
+        let mut listeners = std::mem::take(&mut *self.tasks.listeners.lock().unwrap());
+        while listeners.join_next().await.is_some() {}
+
+        let mut deliveries = std::mem::take(&mut *self.tasks.deliveries.lock().unwrap());
+        if !deliveries.is_empty() {
+            info!(
+                "⏳ Waiting for {} email delivery task(s) to finish",
+                deliveries.len()
+            );
+        }
+        while deliveries.join_next().await.is_some() {}
     }
 
     /// Start multiple SMTP servers on different ports
@@ -56,32 +163,65 @@ impl SmtpServer {
     /// - If SSL enabled, also starts SMTPS server on smtp_ssl_port
     pub async fn start_all(
         &self,
+        bind_address: IpAddr,
         smtp_port: u16,
         smtp_starttls_port: u16,
         smtp_ssl_port: u16,
     ) -> Result<()> {
         let storage = self.storage.clone();
         let email_sender = self.email_sender.clone();
+        let deletion_notifier = self.deletion_notifier.clone();
         let domain_name = self.domain_name.clone();
+        let smtp_hostname = self.smtp_hostname.clone();
+        let additional_domains = self.additional_domains.clone();
         let ssl_config = self.ssl_config.clone();
         let reject_non_domain_emails = self.reject_non_domain_emails;
-        let shutdown_flag = self.shutdown_flag.clone();
+        let catch_all_mailbox = self.catch_all_mailbox.clone();
+        let mailbox_quota = self.mailbox_quota.clone();
+        let attachment_limits = self.attachment_limits.clone();
+        let max_message_bytes = self.max_message_bytes;
+        let ip_limiter = self.ip_limiter.clone();
+        let spf_checker = self.spf_checker.clone();
+        let dkim_verifier = self.dkim_verifier.clone();
+        let clamav_scanner = self.clamav_scanner.clone();
+        let mx_validator = self.mx_validator.clone();
+        let sanitize_html = self.sanitize_html;
+        let proxy_protocol = self.proxy_protocol;
+        let sender_rules = self.sender_rules.clone();
+        let shutdown = self.shutdown.clone();
+        let tasks = self.tasks.clone();
 
         // Always start non-TLS SMTP server
         let non_tls_server = SmtpServer {
             storage: storage.clone(),
             email_sender: email_sender.clone(),
+            deletion_notifier: deletion_notifier.clone(),
             domain_name: domain_name.clone(),
+            smtp_hostname: smtp_hostname.clone(),
+            additional_domains: additional_domains.clone(),
             ssl_config: crate::config::SmtpSslConfig {
                 enabled: false,
                 cert_path: None,
                 key_path: None,
             },
             reject_non_domain_emails,
-            shutdown_flag: shutdown_flag.clone(),
+            catch_all_mailbox: catch_all_mailbox.clone(),
+            mailbox_quota: mailbox_quota.clone(),
+            attachment_limits: attachment_limits.clone(),
+            max_message_bytes,
+            ip_limiter: ip_limiter.clone(),
+            spf_checker: spf_checker.clone(),
+            dkim_verifier: dkim_verifier.clone(),
+            clamav_scanner: clamav_scanner.clone(),
+            mx_validator: mx_validator.clone(),
+            sanitize_html,
+            proxy_protocol,
+            sender_rules: sender_rules.clone(),
+            shutdown: shutdown.clone(),
+            tasks: tasks.clone(),
         };
         non_tls_server
-            .start_single(smtp_port, "non-TLS".to_string())
+            .start_single(bind_address, smtp_port, "non-TLS".to_string())
             .await?;
 
         // If SSL is enabled, start additional servers
@@ -90,26 +230,58 @@ impl SmtpServer {
             let starttls_server = SmtpServer {
                 storage: storage.clone(),
                 email_sender: email_sender.clone(),
+                deletion_notifier: deletion_notifier.clone(),
                 domain_name: domain_name.clone(),
+                smtp_hostname: smtp_hostname.clone(),
+                additional_domains: additional_domains.clone(),
                 ssl_config: ssl_config.clone(),
                 reject_non_domain_emails,
-                shutdown_flag: shutdown_flag.clone(),
+                catch_all_mailbox: catch_all_mailbox.clone(),
+                mailbox_quota: mailbox_quota.clone(),
+                attachment_limits: attachment_limits.clone(),
+                max_message_bytes,
+                ip_limiter: ip_limiter.clone(),
+                spf_checker: spf_checker.clone(),
+                dkim_verifier: dkim_verifier.clone(),
+                clamav_scanner: clamav_scanner.clone(),
+                mx_validator: mx_validator.clone(),
+                sanitize_html,
+                proxy_protocol,
+                sender_rules: sender_rules.clone(),
+                shutdown: shutdown.clone(),
+                tasks: tasks.clone(),
             };
             starttls_server
-                .start_single(smtp_starttls_port, "STARTTLS".to_string())
+                .start_single(bind_address, smtp_starttls_port, "STARTTLS".to_string())
                 .await?;
 
             // Start SMTPS server on port 465
             let smtps_server = SmtpServer {
                 storage,
                 email_sender,
+                deletion_notifier,
                 domain_name,
+                smtp_hostname,
+                additional_domains,
                 ssl_config,
                 reject_non_domain_emails,
-                shutdown_flag,
+                catch_all_mailbox,
+                mailbox_quota,
+                attachment_limits,
+                max_message_bytes,
+                ip_limiter,
+                spf_checker,
+                dkim_verifier,
+                clamav_scanner,
+                mx_validator,
+                sanitize_html,
+                proxy_protocol,
+                sender_rules,
+                shutdown,
+                tasks,
             };
             smtps_server
-                .start_single(smtp_ssl_port, "SMTPS".to_string())
+                .start_single(bind_address, smtp_ssl_port, "SMTPS".to_string())
                 .await?;
         }
 
@@ -120,100 +292,165 @@ impl SmtpServer {
     }
 
     /// Start a single SMTP server instance on the specified port
-    async fn start_single(&self, port: u16, server_type: String) -> Result<()> {
+    async fn start_single(
+        &self,
+        bind_address: IpAddr,
+        port: u16,
+        server_type: String,
+    ) -> Result<()> {
         debug!("Starting {} SMTP server on port {}...", server_type, port);
 
-        let addr = format!("0.0.0.0:{}", port);
-        let shutdown_flag = self.shutdown_flag.clone();
+        // Certificates are checked up front, but STARTTLS is not offered by the listeners yet
+        if let Err(e) = self.ssl_config.load_certificates() {
+            error!("Failed to load SSL certificates: {}", e);
+            return Err(e);
+        }
 
-        // Get the runtime handle to pass to both the blocking thread and handler
-        let runtime_handle = tokio::runtime::Handle::current();
-        let handler = SmtpHandler::new(
+        let addr = SocketAddr::new(bind_address, port);
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind {} SMTP server on {}", server_type, addr))?;
+        self.serve(listener, server_type);
+
+        Ok(())
+    }
+
+    /// Accept connections on `listener` in the background until shutdown
+    fn serve(&self, listener: TcpListener, server_type: String) {
+        let mut handler = SmtpHandler::new(
             self.storage.clone(),
             self.email_sender.clone(),
-            runtime_handle.clone(),
+            self.deletion_notifier.clone(),
+            tokio::runtime::Handle::current(),
             self.domain_name.clone(),
+            self.additional_domains.clone(),
             self.reject_non_domain_emails,
+            self.catch_all_mailbox.clone(),
+            self.mailbox_quota.clone(),
+            self.attachment_limits.clone(),
+            self.max_message_bytes,
+            self.ip_limiter.clone(),
+            self.spf_checker.clone(),
+            self.dkim_verifier.clone(),
+            self.clamav_scanner.clone(),
+            self.mx_validator.clone(),
+            self.sanitize_html,
+            self.sender_rules.clone(),
         );
+        handler.deliveries = self.tasks.deliveries.clone();
 
-        // Determine SSL configuration
-        let ssl_config = if self.ssl_config.enabled {
-            match self.ssl_config.load_certificates() {
-                Ok(Some((_certs, _key))) => {
-                    // mailin-embedded expects SslConfig::SelfSigned with cert/key data
-                    // We'll need to configure this properly
-                    SslConfig::None // Placeholder - mailin-embedded has limited SSL support
-                }
-                Ok(None) => SslConfig::None,
-                Err(e) => {
-                    error!("Failed to load SSL certificates: {}", e);
-                    return Err(e);
-                }
-            }
-        } else {
-            SslConfig::None
-        };
-
-        let domain_name = self.domain_name.clone();
-
-        // Run the server in a blocking manner with shutdown support
-        let server_handle = tokio::task::spawn_blocking(move || {
-            // Enter the runtime context so tokio::spawn works
-            let _guard = runtime_handle.enter();
+        self.tasks
+            .listeners
+            .lock()
+            .unwrap()
+            .spawn(accept_connections(
+                listener,
+                handler,
+                SessionBuilder::new(self.smtp_hostname.clone()),
+                self.proxy_protocol,
+                self.shutdown.subscribe(),
+                server_type,
+            ));
+    }
+}
 
-            let mut server = Server::new(handler);
+/// Accept SMTP connections until shutdown, then close the sessions still open
+async fn accept_connections(
+    listener: TcpListener,
+    handler: SmtpHandler,
+    session_builder: SessionBuilder,
+    proxy_protocol: bool,
+    mut shutdown: watch::Receiver<bool>,
+    server_type: String,
+) {
+    let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(0);
+    let mut sessions = JoinSet::new();
 
-            if let Err(e) = server
-                .with_name(&domain_name)
-                .with_ssl(ssl_config)
-                .and_then(|s| s.with_addr(&addr))
-            {
-                error!(
-                    "Failed to configure {} SMTP server on port {}: {}",
+    loop {
+        tokio::select! {
+            _ = shutdown.wait_for(|stop| *stop) => break,
+            accepted = listener.accept() => match accepted {
+                Ok((mut stream, peer)) => {
+                    debug!("New SMTP connection from {}", peer);
+                    let session_builder = session_builder.clone();
+                    let handler = handler.clone();
+                    sessions.spawn(async move {
+                        // Behind a load balancer the client's address comes from the header
+                        let client_ip = if proxy_protocol {
+                            let header = tokio::time::timeout(
+                                SESSION_TIMEOUT,
+                                read_proxy_header(&mut stream),
+                            )
+                            .await
+                            .context("Timed out waiting for the PROXY header")
+                            .and_then(|header| header);
+                            match header {
+                                Ok(Some(client)) => {
+                                    debug!("SMTP connection from {} is proxied for {}", peer, client);
+                                    client.ip()
+                                }
+                                Ok(None) => peer.ip(),
+                                Err(e) => {
+                                    warn!("⚠️ Dropping SMTP connection from {}: {:#}", peer, e);
+                                    return;
+                                }
+                            }
+                        } else {
+                            peer.ip()
+                        };
+                        let session = session_builder.build(client_ip, handler);
+                        if let Err(e) = run_session(stream, session).await {
+                            debug!("SMTP session from {} ended: {:#}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => error!(
+                    "{} SMTP server on port {} failed to accept a connection: {}",
                     server_type, port, e
-                );
-                return;
-            }
-
-            // Start a background task to monitor shutdown signal and abort the server
-            let shutdown_flag_clone = shutdown_flag.clone();
-            let server_type_clone = server_type.clone();
-            let port_clone = port;
+                ),
+            },
+            // Reap finished sessions
+            Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+        }
+    }
 
-            tokio::spawn(async move {
-                loop {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    if shutdown_flag_clone.load(Ordering::SeqCst) {
-                        info!(
-                            "🛑 Shutdown signal received for {} SMTP server on port {}",
-                            server_type_clone, port_clone
-                        );
-                        break;
-                    }
-                }
-            });
+    // Stop listening before cutting off open sessions. Emails already accepted are
+    // stored by delivery tasks that outlive their session.
+    drop(listener);
+    sessions.abort_all();
+    while sessions.join_next().await.is_some() {}
+    info!(
+        "✅ {} SMTP server on port {} stopped gracefully",
+        server_type, port
+    );
+}
 
-            // Note: mailin-embedded doesn't have built-in graceful shutdown
-            // The server will continue running until the process exits
-            // In a production environment, you might want to implement a custom
-            // shutdown mechanism or use a different SMTP library
-            if let Err(e) = server.serve() {
-                if shutdown_flag.load(Ordering::SeqCst) {
-                    info!(
-                        "✅ {} SMTP server on port {} stopped gracefully",
-                        server_type, port
-                    );
-                } else {
-                    error!("{} SMTP server error on port {}: {}", server_type, port, e);
-                }
-            }
-        });
+/// Exchange SMTP commands and replies with one client until either side closes
+async fn run_session(stream: TcpStream, mut session: Session<SmtpHandler>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    writer.write_all(&session.greeting().buffer()?).await?;
 
-        // Store the server handle for potential future use
-        // For now, we'll let it run in the background
-        drop(server_handle);
+    let mut line = Vec::with_capacity(80);
+    loop {
+        line.clear();
+        let read = tokio::time::timeout(SESSION_TIMEOUT, reader.read_until(b'\n', &mut line))
+            .await
+            .context("Session timed out")??;
+        if read == 0 {
+            return Ok(());
+        }
 
-        Ok(())
+        let response = session.process(&line);
+        match response.action {
+            Action::NoReply => {}
+            Action::Reply => writer.write_all(&response.buffer()?).await?,
+            // STARTTLS is never advertised, so an upgrade request also ends the session
+            Action::Close | Action::UpgradeTls => {
+                writer.write_all(&response.buffer()?).await?;
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -222,100 +459,288 @@ impl SmtpServer {
 struct SmtpHandler {
     storage: Arc<dyn StorageBackend>,
     email_sender: broadcast::Sender<Email>,
+    // Announces emails evicted by the mailbox quota
+    deletion_notifier: DeletionNotifier,
     runtime_handle: tokio::runtime::Handle,
     domain_name: String,
+    additional_domains: Vec<String>,
     reject_non_domain_emails: bool,
+    catch_all_mailbox: Option<String>,
+    mailbox_quota: MailboxQuotaConfig,
+    attachment_limits: AttachmentLimitsConfig,
+    max_message_bytes: usize,
+    ip_limiter: Option<Arc<IpRateLimiter>>,
+    spf_checker: Option<Arc<SpfChecker>>,
+    dkim_verifier: Option<Arc<DkimVerifier>>,
+    clamav_scanner: Option<Arc<ClamavScanner>>,
+    // Accepts other domains whose MX points here (`SMTP_ACCEPT_POLICY=mx`)
+    mx_validator: Option<Arc<MxValidator>>,
+    // Scrub HTML bodies before storage (`SANITIZE_HTML`)
+    sanitize_html: bool,
+    sender_rules: Arc<SenderRules>,
     // Store email data during the session
-    from: Arc<std::sync::Mutex<String>>,
-    to: Arc<std::sync::Mutex<Vec<String>>>,
-    data: Arc<std::sync::Mutex<Vec<u8>>>,
+    peer_ip: Arc<Mutex<Option<IpAddr>>>,
+    spf_result: Arc<Mutex<Option<SpfResult>>>,
+    from: Arc<Mutex<String>>,
+    to: Arc<Mutex<Vec<String>>>,
+    data: Arc<Mutex<Vec<u8>>>,
+    // Set once the message exceeds `max_message_bytes`; further data is discarded
+    oversized: Arc<AtomicBool>,
+    // Storage tasks for accepted emails, awaited on shutdown
+    deliveries: Arc<Mutex<JoinSet<()>>>,
 }
 
 impl SmtpHandler {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         storage: Arc<dyn StorageBackend>,
         email_sender: broadcast::Sender<Email>,
+        deletion_notifier: DeletionNotifier,
         runtime_handle: tokio::runtime::Handle,
         domain_name: String,
+        additional_domains: Vec<String>,
         reject_non_domain_emails: bool,
+        catch_all_mailbox: Option<String>,
+        mailbox_quota: MailboxQuotaConfig,
+        attachment_limits: AttachmentLimitsConfig,
+        max_message_bytes: usize,
+        ip_limiter: Option<Arc<IpRateLimiter>>,
+        spf_checker: Option<Arc<SpfChecker>>,
+        dkim_verifier: Option<Arc<DkimVerifier>>,
+        clamav_scanner: Option<Arc<ClamavScanner>>,
+        mx_validator: Option<Arc<MxValidator>>,
+        sanitize_html: bool,
+        sender_rules: Arc<SenderRules>,
     ) -> Self {
         Self {
             storage,
             email_sender,
+            deletion_notifier,
             runtime_handle,
             domain_name,
+            additional_domains,
             reject_non_domain_emails,
-            from: Arc::new(std::sync::Mutex::new(String::new())),
-            to: Arc::new(std::sync::Mutex::new(Vec::new())),
-            data: Arc::new(std::sync::Mutex::new(Vec::new())),
+            catch_all_mailbox,
+            mailbox_quota,
+            attachment_limits,
+            max_message_bytes,
+            ip_limiter,
+            spf_checker,
+            dkim_verifier,
+            clamav_scanner,
+            mx_validator,
+            sanitize_html,
+            sender_rules,
+            peer_ip: Arc::new(Mutex::new(None)),
+            spf_result: Arc::new(Mutex::new(None)),
+            from: Arc::new(Mutex::new(String::new())),
+            to: Arc::new(Mutex::new(Vec::new())),
+            data: Arc::new(Mutex::new(Vec::new())),
+            oversized: Arc::new(AtomicBool::new(false)),
+            deliveries: Arc::new(Mutex::new(JoinSet::new())),
         }
     }
 }
 
 impl Handler for SmtpHandler {
+    fn mail(&mut self, ip: IpAddr, domain: &str, from: &str) -> mailin::Response {
+        *self.peer_ip.lock().unwrap() = Some(ip);
+
+        let spf_result = self.spf_checker.as_ref().map(|checker| {
+            tokio::task::block_in_place(|| {
+                self.runtime_handle
+                    .block_on(checker.check(ip, from, domain))
+            })
+        });
+        *self.spf_result.lock().unwrap() = spf_result;
+
+        if let Some(result) = spf_result {
+            info!("SPF {} for {} from {}", result.as_str(), from, ip);
+            let reject = self
+                .spf_checker
+                .as_ref()
+                .is_some_and(|checker| checker.reject_fail());
+            if result == SpfResult::Fail && reject {
+                return mailin::Response::custom(
+                    550,
+                    format!("5.7.23 SPF check failed for {} from {}", from, ip),
+                );
+            }
+        }
+
+        mailin::response::OK
+    }
+
     fn data_start(
         &mut self,
         _domain: &str,
         from: &str,
         _is8bit: bool,
         to: &[String],
-    ) -> mailin_embedded::Response {
+    ) -> mailin::Response {
         info!("Receiving email from {} to {:?}", from, to);
 
+        if let Some(rule) = self.sender_rules.blocking_rule(from) {
+            info!(
+                "Rejecting email from {} - blocked by sender rule {} ({})",
+                from, rule.id, rule.pattern
+            );
+            return mailin::response::NO_MAILBOX;
+        }
+
         // Check domain validation if enabled
         if self.reject_non_domain_emails {
             for recipient in to {
                 if let Some(at_pos) = recipient.find('@') {
                     let domain = &recipient[at_pos + 1..];
-                    if domain != self.domain_name {
+                    if configured_domain(domain, &self.domain_name, &self.additional_domains)
+                        .is_none()
+                    {
+                        if let Some(validator) = &self.mx_validator {
+                            let accepted = tokio::task::block_in_place(|| {
+                                self.runtime_handle.block_on(validator.accepts(domain))
+                            });
+                            match accepted {
+                                Ok(true) => continue,
+                                Ok(false) => {}
+                                Err(e) => {
+                                    error!("Failed to check MX records of {}: {:#}", domain, e);
+                                    return mailin::response::INTERNAL_ERROR;
+                                }
+                            }
+                        }
+                        if self.catch_all_mailbox.is_some() {
+                            // Redirected to the catch-all mailbox in data_end
+                            continue;
+                        }
                         info!(
-                            "Rejecting email to {} - domain {} does not match configured domain {}",
-                            recipient, domain, self.domain_name
+                            "Rejecting email to {} - domain {} is not a configured domain",
+                            recipient, domain
                         );
-                        return mailin_embedded::response::NO_MAILBOX;
+                        return mailin::response::NO_MAILBOX;
                     }
                 } else {
                     // Invalid email format, reject
                     info!("Rejecting email to {} - invalid email format", recipient);
-                    return mailin_embedded::response::INTERNAL_ERROR;
+                    return mailin::response::INTERNAL_ERROR;
                 }
             }
         }
 
+        // Count only transactions that would otherwise be accepted
+        if let (Some(limiter), Some(ip)) = (&self.ip_limiter, *self.peer_ip.lock().unwrap()) {
+            if limiter.acquire(ip, Instant::now()).is_err() {
+                info!("Rejecting email from {} - per-IP message limit reached", ip);
+                return mailin::response::INTERNAL_ERROR;
+            }
+        }
+
         // Store from and to
         *self.from.lock().unwrap() = from.to_string();
         *self.to.lock().unwrap() = to.to_vec();
         self.data.lock().unwrap().clear();
+        self.oversized.store(false, Ordering::SeqCst);
 
-        mailin_embedded::response::OK
+        mailin::response::OK
     }
 
     fn data(&mut self, buf: &[u8]) -> std::io::Result<()> {
-        // Accumulate data
-        self.data.lock().unwrap().extend_from_slice(buf);
+        if self.oversized.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // Accumulate data, dropping the message once it grows past the size limit
+        let mut data = self.data.lock().unwrap();
+        if data.len() + buf.len() > self.max_message_bytes {
+            info!(
+                "Message exceeds the {} byte size limit, discarding",
+                self.max_message_bytes
+            );
+            self.oversized.store(true, Ordering::SeqCst);
+            data.clear();
+            data.shrink_to_fit();
+            return Ok(());
+        }
+        data.extend_from_slice(buf);
         Ok(())
     }
 
-    fn data_end(&mut self) -> mailin_embedded::Response {
+    fn data_end(&mut self) -> mailin::Response {
+        // Tag everything done for this message, through storage and webhook dispatch, with
+        // one ID so its log lines can be correlated
+        let span = info_span!("smtp_message", request_id = %Uuid::new_v4());
+        let _entered = span.enter();
+
+        // mailin-embedded has no hook for advertising SIZE in EHLO, so oversized
+        // messages are refused here, after the client has sent them
+        if self.oversized.swap(false, Ordering::SeqCst) {
+            return mailin::Response::custom(
+                552,
+                "5.3.4 Message size exceeds fixed maximum message size".to_string(),
+            );
+        }
+
         let from = self.from.lock().unwrap().clone();
         let to = self.to.lock().unwrap().clone();
         let data = self.data.lock().unwrap().clone();
 
-        let recipient = to
+        let resolved = tokio::task::block_in_place(|| {
+            self.runtime_handle.block_on(resolve_aliases(
+                self.storage.as_ref(),
+                &unique_recipients(&to),
+            ))
+        });
+        let mut recipients = match resolved {
+            Ok(resolved) => unique_recipients(&resolved),
+            Err(e) => {
+                error!("Failed to resolve aliases: {}", e);
+                return mailin::response::INTERNAL_ERROR;
+            }
+        };
+        if let Some(catch_all) = &self.catch_all_mailbox {
+            let routed = tokio::task::block_in_place(|| {
+                self.runtime_handle.block_on(route_to_catch_all(
+                    self.storage.as_ref(),
+                    &recipients,
+                    &self.domain_name,
+                    &self.additional_domains,
+                    self.reject_non_domain_emails,
+                    self.mx_validator.as_deref(),
+                    catch_all,
+                ))
+            });
+            recipients = match routed {
+                Ok(routed) => routed,
+                Err(e) => {
+                    error!("Failed to check catch-all routing: {}", e);
+                    return mailin::response::INTERNAL_ERROR;
+                }
+            };
+        }
+        let fallback_recipient = recipients
             .first()
             .map(|s| s.as_str())
             .unwrap_or("unknown@localhost");
 
         info!(
-            "Email received completely from {} to {} ({} bytes)",
+            "Email received completely from {} to {:?} ({} bytes)",
             from,
-            recipient,
+            recipients,
             data.len()
         );
 
-        // Parse the email
-        let email = match parse_email(&data, recipient) {
-            Ok(email) => {
+        // Parse the email once and deliver a copy to every envelope recipient
+        let mut parsed = match parse_email(&data, fallback_recipient) {
+            Ok(mut email) => {
+                email.spf_result = self
+                    .spf_result
+                    .lock()
+                    .unwrap()
+                    .map(|result| result.as_str().to_string());
+                // The raw message keeps the original HTML
+                if self.sanitize_html {
+                    sanitize_email_html(&mut email);
+                }
                 info!(
                     "Successfully parsed email: id={}, subject={}",
                     email.id, email.subject
@@ -324,44 +749,1134 @@ impl Handler for SmtpHandler {
             }
             Err(e) => {
                 error!("Failed to parse email: {}", e);
-                return mailin_embedded::response::INTERNAL_ERROR;
+                return mailin::response::INTERNAL_ERROR;
             }
         };
 
-        // Store the email using the tokio runtime handle
-        let storage = self.storage.clone();
-        let email_clone = email.clone();
+        // Subject routing rules apply to the mailboxes aliases and the catch-all resolved to
+        let routed = tokio::task::block_in_place(|| {
+            self.runtime_handle.block_on(apply_routing_rules(
+                self.storage.as_ref(),
+                &recipients,
+                &parsed.subject,
+            ))
+        });
+        recipients = match routed {
+            Ok(routed) => routed,
+            Err(e) => {
+                error!("Failed to apply routing rules: {}", e);
+                return mailin::response::INTERNAL_ERROR;
+            }
+        };
+
+        // Best-effort unless failures are configured to be refused
+        if let Some(verifier) = &self.dkim_verifier {
+            let result = tokio::task::block_in_place(|| {
+                self.runtime_handle.block_on(verifier.verify(&data))
+            });
+            let name = dkim_result_name(&result);
+            info!("DKIM {} for email {} from {}", name, parsed.id, from);
+            if verifier.reject_fail() && matches!(result, DkimResult::Fail(_)) {
+                return mailin::Response::custom(
+                    550,
+                    "5.7.20 DKIM signature verification failed".to_string(),
+                );
+            }
+            parsed.dkim_result = Some(name.to_string());
+        }
+
+        if self.attachment_limits.is_enabled() {
+            match enforce_attachment_limits(&self.attachment_limits, &mut parsed) {
+                AttachmentLimitOutcome::Accepted { dropped } => {
+                    if !dropped.is_empty() {
+                        info!(
+                            "📎 Dropped {} attachment(s) over the limits from email {}: {}",
+                            dropped.len(),
+                            parsed.id,
+                            dropped.join(", ")
+                        );
+                    }
+                }
+                AttachmentLimitOutcome::Rejected => {
+                    info!(
+                        "Rejecting email from {} - its attachments exceed the limits",
+                        from
+                    );
+                    return mailin::Response::custom(
+                        552,
+                        "5.3.4 Message attachments exceed the allowed count or size".to_string(),
+                    );
+                }
+            }
+        }
+
+        // Best-effort as well: attachments clamd can't be asked about are stored unscanned
+        if let Some(scanner) = &self.clamav_scanner {
+            if !parsed.attachments.is_empty() {
+                let infected = tokio::task::block_in_place(|| {
+                    self.runtime_handle
+                        .block_on(scanner.scan_attachments(&mut parsed.attachments))
+                });
+                if infected {
+                    info!(
+                        "🦠 Email {} from {} has an infected attachment",
+                        parsed.id, from
+                    );
+                    if scanner.reject_infected() {
+                        return mailin::Response::custom(
+                            554,
+                            "5.7.1 Message contains a virus".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        let emails: Vec<Email> = if recipients.is_empty() {
+            vec![parsed]
+        } else {
+            recipients
+                .into_iter()
+                .map(|recipient| parsed.for_recipient(recipient))
+                .collect()
+        };
+
+        // Skip recipients that already have this message, e.g. from a relay retrying, before
+        // quotas evict anything for it. Copies racing each other are caught by the store.
+        let deduplicated = tokio::task::block_in_place(|| {
+            self.runtime_handle
+                .block_on(drop_duplicates(self.storage.as_ref(), emails))
+        });
+        let emails = match deduplicated {
+            Ok(emails) if emails.is_empty() => {
+                info!("Message was already delivered to every recipient, skipping");
+                return mailin::response::OK;
+            }
+            Ok(emails) => emails,
+            Err(e) => {
+                error!("Failed to check for duplicate messages: {}", e);
+                return mailin::response::INTERNAL_ERROR;
+            }
+        };
 
-        // Use the stored runtime handle to spawn the storage task
+        // Enforce mailbox quotas before accepting; this must finish before we reply
+        let evicted = if self.mailbox_quota.is_enabled() {
+            let outcome = tokio::task::block_in_place(|| {
+                self.runtime_handle.block_on(enforce_quotas(
+                    self.storage.as_ref(),
+                    &self.mailbox_quota,
+                    &emails,
+                ))
+            });
+            match outcome {
+                Ok(QuotaOutcome::Accepted { evicted }) => evicted,
+                Ok(QuotaOutcome::Rejected { address }) => {
+                    info!("Rejecting email - mailbox {} is over quota", address);
+                    return mailin::response::INTERNAL_ERROR;
+                }
+                Err(e) => {
+                    error!("Failed to check mailbox quota: {}", e);
+                    return mailin::response::INTERNAL_ERROR;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        // Store the emails using the tokio runtime handle
+        let storage = self.storage.clone();
         let webhook_trigger = WebhookTrigger::new(self.storage.clone());
-        let email_for_webhook = email_clone.clone();
-        let to_address = email_clone.to.clone();
+        let email_sender = self.email_sender.clone();
+        let deletion_notifier = self.deletion_notifier.clone();
 
-        self.runtime_handle.spawn(async move {
-            if let Err(e) = storage.store_email(email_clone.clone()).await {
-                error!("Failed to store email: {}", e);
-            } else {
-                debug!("Successfully stored email {}", email_clone.id);
+        let mut deliveries = self.deliveries.lock().unwrap();
+        // Drop finished deliveries so the set only holds work still in flight
+        while deliveries.try_join_next().is_some() {}
+        let delivery = async move {
+            // Announce emails evicted to make room
+            for email in evicted {
+                deletion_notifier
+                    .handle_email_deletion(&email.id, &email.to)
+                    .await;
+            }
+
+            for email in emails {
+                match storage.store_email(email.clone()).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        info!(
+                            "Skipping duplicate message {} for {}",
+                            email.message_id.as_deref().unwrap_or_default(),
+                            email.to
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Failed to store email for {}: {}", email.to, e);
+                        continue;
+                    }
+                }
+                debug!("Successfully stored email {} for {}", email.id, email.to);
+
+                // Broadcast the stored email to WebSocket listeners
+                let _ = email_sender.send(email.clone());
 
                 // Trigger webhooks for email arrival
                 // Extract mailbox name without domain for webhook lookup
-                let mailbox_name = to_address.split('@').next().unwrap_or(&to_address);
+                let mailbox_name = email.to.split('@').next().unwrap_or(&email.to);
                 if let Err(e) = webhook_trigger
-                    .trigger_webhooks(
-                        mailbox_name,
-                        WebhookEvent::Arrival,
-                        Some(&email_for_webhook),
-                    )
+                    .trigger_webhooks(mailbox_name, WebhookEvent::Arrival, Some(&email))
                     .await
                 {
                     error!("Failed to trigger webhooks: {}", e);
                 }
             }
-        });
+        };
+        deliveries.spawn_on(delivery.instrument(span.clone()), &self.runtime_handle);
+        drop(deliveries);
+
+        mailin::response::OK
+    }
+}
+
+/// Apply catch-all routing to the envelope recipients
+///
+/// Recipients outside the configured domains (when those would otherwise be rejected)
+/// are delivered only to the catch-all, unless `mx_validator` accepts their domain. If any recipient's local part is neither
+/// owned by a user nor claimed, a single copy is also delivered to the catch-all.
+async fn route_to_catch_all(
+    storage: &dyn StorageBackend,
+    recipients: &[String],
+    domain_name: &str,
+    additional_domains: &[String],
+    reject_non_domain_emails: bool,
+    mx_validator: Option<&MxValidator>,
+    catch_all: &str,
+) -> Result<Vec<String>> {
+    let mut routed = Vec::with_capacity(recipients.len() + 1);
+    let mut needs_catch_all = false;
+
+    for recipient in recipients {
+        let (local_part, domain) = recipient.split_once('@').unwrap_or((recipient, ""));
+        let accepted_domain = configured_domain(domain, domain_name, additional_domains).is_some()
+            || match mx_validator {
+                Some(validator) => validator.accepts(domain).await?,
+                None => false,
+            };
+        if reject_non_domain_emails && !accepted_domain {
+            debug!(
+                "Redirecting email to {} to catch-all {}",
+                recipient, catch_all
+            );
+            needs_catch_all = true;
+            continue;
+        }
+
+        routed.push(recipient.clone());
+        if recipient.eq_ignore_ascii_case(catch_all) {
+            continue;
+        }
+        let known = storage.get_mailbox_owner(local_part).await?.is_some()
+            || storage.get_mailbox(local_part).await?.is_some();
+        if !known {
+            debug!(
+                "No mailbox for {}, copying to catch-all {}",
+                recipient, catch_all
+            );
+            needs_catch_all = true;
+        }
+    }
+
+    if needs_catch_all {
+        routed.push(catch_all.to_string());
+    }
+    Ok(unique_recipients(&routed))
+}
+
+/// Drop the copies of a message whose recipient already has an email with its `Message-ID`
+///
+/// Messages without a `Message-ID` are always kept.
+async fn drop_duplicates(storage: &dyn StorageBackend, emails: Vec<Email>) -> Result<Vec<Email>> {
+    let mut kept = Vec::with_capacity(emails.len());
+    for email in emails {
+        if let Some(message_id) = &email.message_id {
+            if storage
+                .email_exists_by_message_id(&email.to, message_id)
+                .await?
+            {
+                info!("Skipping duplicate message {} for {}", message_id, email.to);
+                continue;
+            }
+        }
+        kept.push(email);
+    }
+    Ok(kept)
+}
+
+/// Envelope recipients with duplicates (compared case-insensitively) removed, in order
+fn unique_recipients(to: &[String]) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    to.iter()
+        .filter(|recipient| seen.insert(recipient.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{models::RoutingRule, sqlite::SqliteBackend};
+
+    #[test]
+    fn test_unique_recipients() {
+        let to = vec![
+            "a@example.com".to_string(),
+            "B@example.com".to_string(),
+            "A@example.com".to_string(),
+        ];
+        assert_eq!(
+            unique_recipients(&to),
+            vec!["a@example.com".to_string(), "B@example.com".to_string()]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_delivers_to_every_recipient() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, mut email_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            true,
+            None,
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+
+        let recipients = vec![
+            "alice@example.com".to_string(),
+            "bob@example.com".to_string(),
+            "carol@example.com".to_string(),
+        ];
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(!response.is_error);
+        handler
+            .data(b"From: sender@other.com\r\nTo: alice@example.com\r\nSubject: Team update\r\n\r\nHello all")
+            .unwrap();
+        let response = handler.data_end();
+        assert!(!response.is_error);
+
+        // Each recipient is broadcast once
+        let mut broadcast_to = Vec::new();
+        for _ in 0..recipients.len() {
+            broadcast_to.push(email_receiver.recv().await.unwrap().to);
+        }
+        assert_eq!(broadcast_to, recipients);
+
+        // Storage happens in a background task
+        for recipient in &recipients {
+            let mut emails = Vec::new();
+            for _ in 0..50 {
+                emails = storage.get_emails_for_address(recipient).await.unwrap();
+                if !emails.is_empty() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            assert_eq!(emails.len(), 1, "{} did not receive the email", recipient);
+            assert_eq!(emails[0].subject, "Team update");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rejects_delivery_over_quota() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .store_email(Email::new(
+                "full@example.com".to_string(),
+                "sender@other.com".to_string(),
+                "Existing".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            ))
+            .await
+            .unwrap();
+
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            false,
+            None,
+            MailboxQuotaConfig {
+                max_emails: Some(1),
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+
+        let recipients = vec!["full@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler
+            .data(
+                b"From: sender@other.com\r\nTo: full@example.com\r\nSubject: One more\r\n\r\nHello",
+            )
+            .unwrap();
+        let response = handler.data_end();
+        assert!(response.is_error);
+        assert_eq!(
+            storage
+                .count_emails_for_address("full@example.com")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rejects_oversized_message() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            false,
+            None,
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            64,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+
+        let recipients = vec!["big@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler
+            .data(b"From: sender@other.com\r\nTo: big@example.com\r\nSubject: Big\r\n\r\n")
+            .unwrap();
+        handler.data(&[b'x'; 100]).unwrap();
+        assert!(handler.data.lock().unwrap().is_empty());
+
+        let response = handler.data_end();
+        assert!(response.is_error);
+
+        // The next message on the connection starts fresh
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: Small\r\n\r\nHi").unwrap();
+        let response = handler.data_end();
+        assert!(!response.is_error);
+    }
+
+    fn deletion_notifier(storage: &Arc<dyn StorageBackend>) -> DeletionNotifier {
+        let (deletion_sender, _) = broadcast::channel(10);
+        DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_sender)
+    }
+
+    fn catch_all_handler(
+        storage: Arc<dyn StorageBackend>,
+        catch_all: Option<&str>,
+    ) -> (SmtpHandler, broadcast::Receiver<Email>) {
+        let (email_sender, email_receiver) = broadcast::channel(10);
+        let handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            true,
+            catch_all.map(str::to_string),
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+        (handler, email_receiver)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_catch_all_receives_random_address() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (mut handler, mut email_receiver) =
+            catch_all_handler(storage.clone(), Some("catchall@example.com"));
+
+        let recipients = vec!["random-x7q2@elsewhere.org".to_string()];
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(!response.is_error);
+        handler
+            .data(b"From: sender@other.com\r\nSubject: Stray\r\n\r\nHello")
+            .unwrap();
+        let response = handler.data_end();
+        assert!(!response.is_error);
+
+        // Only the catch-all copy is delivered
+        let delivered = email_receiver.recv().await.unwrap();
+        assert_eq!(delivered.to, "catchall@example.com");
+        assert!(email_receiver.try_recv().is_err());
+
+        let mut emails = Vec::new();
+        for _ in 0..50 {
+            emails = storage
+                .get_emails_for_address("catchall@example.com")
+                .await
+                .unwrap();
+            if !emails.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Stray");
+        assert!(storage
+            .get_emails_for_address("random-x7q2@elsewhere.org")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_random_address_dropped_without_catch_all() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (mut handler, _email_receiver) = catch_all_handler(storage.clone(), None);
+
+        let recipients = vec!["random-x7q2@elsewhere.org".to_string()];
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(response.is_error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_attachment_limits_reject_policy() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (mut handler, _email_receiver) = catch_all_handler(storage.clone(), None);
+        handler.attachment_limits = AttachmentLimitsConfig {
+            max_count: Some(1),
+            max_total_bytes: None,
+            policy: crate::config::AttachmentOverflowPolicy::Reject,
+        };
+
+        let recipients = vec!["test@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        let mut message = String::from(
+            "From: sender@other.com\r\nTo: test@example.com\r\nSubject: Files\r\n\
+             MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+             --b\r\nContent-Type: text/plain\r\n\r\nSee attached\r\n",
+        );
+        for name in ["a.txt", "b.txt"] {
+            message.push_str(&format!(
+                "--b\r\nContent-Type: text/plain\r\n\
+                 Content-Disposition: attachment; filename=\"{}\"\r\n\r\ncontent\r\n",
+                name
+            ));
+        }
+        message.push_str("--b--\r\n");
+        handler.data(message.as_bytes()).unwrap();
+        let response = handler.data_end();
+        assert!(response.is_error);
+        assert_eq!(response.code, 552);
+        assert!(storage
+            .get_emails_for_address("test@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sender_rules_block_and_allow() {
+        use crate::storage::models::{SenderRule, SenderRuleAction};
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .create_sender_rule(SenderRule::new(
+                "spam.example".to_string(),
+                SenderRuleAction::Block,
+            ))
+            .await
+            .unwrap();
+        storage
+            .create_sender_rule(SenderRule::new(
+                "friend@spam.example".to_string(),
+                SenderRuleAction::Allow,
+            ))
+            .await
+            .unwrap();
+        let sender_rules = Arc::new(SenderRules::default());
+        sender_rules.refresh(storage.as_ref()).await.unwrap();
+
+        let (mut handler, mut email_receiver) = catch_all_handler(storage.clone(), None);
+        handler.sender_rules = sender_rules;
+        let recipients = vec!["test@example.com".to_string()];
+
+        let response = handler.data_start("client", "bulk@mail.spam.example", false, &recipients);
+        assert!(response.is_error);
+        assert_eq!(response.code, 550);
+
+        let response = handler.data_start("client", "friend@spam.example", false, &recipients);
+        assert!(!response.is_error);
+        handler
+            .data(b"From: friend@spam.example\r\nSubject: Hello\r\n\r\nHi")
+            .unwrap();
+        let response = handler.data_end();
+        assert!(!response.is_error);
+
+        let delivered = email_receiver.recv().await.unwrap();
+        assert_eq!(delivered.from, "friend@spam.example");
+        assert!(email_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_accepts_additional_domains() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, mut email_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec!["second.org".to_string()],
+            true,
+            None,
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+
+        let recipients = vec![
+            "user@example.com".to_string(),
+            "user@Second.org".to_string(),
+        ];
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(!response.is_error);
+        handler.data(b"Subject: Both\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+        assert_eq!(email_receiver.recv().await.unwrap().to, "user@example.com");
+        assert_eq!(email_receiver.recv().await.unwrap().to, "user@Second.org");
+
+        // A domain that is not configured is still rejected
+        let recipients = vec![
+            "user@second.org".to_string(),
+            "user@unlisted.net".to_string(),
+        ];
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(response.is_error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_catch_all_copies_unknown_local_parts() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .set_mailbox_password("claimed", "hash".to_string())
+            .await
+            .unwrap();
+        let (mut handler, mut email_receiver) =
+            catch_all_handler(storage.clone(), Some("catchall@example.com"));
+
+        // A claimed mailbox gets no catch-all copy
+        let recipients = vec!["claimed@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: Known\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+        assert_eq!(
+            email_receiver.recv().await.unwrap().to,
+            "claimed@example.com"
+        );
+        assert!(email_receiver.try_recv().is_err());
+
+        // An unknown local part is kept and also copied to the catch-all
+        let recipients = vec!["nobody@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: Unknown\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+        assert_eq!(
+            email_receiver.recv().await.unwrap().to,
+            "nobody@example.com"
+        );
+        assert_eq!(
+            email_receiver.recv().await.unwrap().to,
+            "catchall@example.com"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_delivers_aliases_to_destinations() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .create_alias("sales", &["alice".to_string(), "bob".to_string()])
+            .await
+            .unwrap();
+        let (mut handler, mut email_receiver) = catch_all_handler(storage.clone(), None);
+
+        let recipients = vec![
+            "sales@example.com".to_string(),
+            "bob@example.com".to_string(),
+        ];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: Quote\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+
+        // bob is reached twice but receives a single copy
+        assert_eq!(email_receiver.recv().await.unwrap().to, "alice@example.com");
+        assert_eq!(email_receiver.recv().await.unwrap().to, "bob@example.com");
+        assert!(email_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_routing_rules_copy_matching_subjects() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        storage
+            .create_alias("ops", &["alice".to_string()])
+            .await
+            .unwrap();
+        storage
+            .create_routing_rule(RoutingRule::new(
+                "alice".to_string(),
+                "[alert]*".to_string(),
+                "triage".to_string(),
+            ))
+            .await
+            .unwrap();
+        let (mut handler, mut email_receiver) = catch_all_handler(storage.clone(), None);
+
+        // Rules apply to the alias destination
+        let recipients = vec!["ops@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler
+            .data(b"Subject: [ALERT] Disk full\r\n\r\nHi")
+            .unwrap();
+        assert!(!handler.data_end().is_error);
+        assert_eq!(email_receiver.recv().await.unwrap().to, "alice@example.com");
+        assert_eq!(
+            email_receiver.recv().await.unwrap().to,
+            "triage@example.com"
+        );
+
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: Lunch?\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+        assert_eq!(email_receiver.recv().await.unwrap().to, "alice@example.com");
+        assert!(email_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sanitizes_html_body_but_keeps_raw() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (mut handler, mut email_receiver) = catch_all_handler(storage.clone(), None);
+        handler.sanitize_html = true;
+        let raw = "From: sender@other.com\r\nTo: user@example.com\r\nSubject: Scripted\r\n\
+                   Content-Type: text/html\r\n\r\n<p onclick=\"steal()\">Hello</p>\
+                   <script>alert('xss')</script><img src=\"https://tracker.example/pixel.gif\">";
+
+        handler.data_start(
+            "client",
+            "sender@other.com",
+            false,
+            &["user@example.com".to_string()],
+        );
+        handler.data(raw.as_bytes()).unwrap();
+        assert!(!handler.data_end().is_error);
+        let id = email_receiver.recv().await.unwrap().id;
+
+        let mut stored = None;
+        for _ in 0..50 {
+            stored = storage.get_email_by_id(&id).await.unwrap();
+            if stored.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let stored = stored.expect("email was not stored");
+        let html = stored.body_html.unwrap();
+        assert!(html.contains("<p>Hello</p>"));
+        for removed in ["<script", "alert", "onclick", "tracker.example"] {
+            assert!(!html.contains(removed), "{} survived sanitizing", removed);
+        }
+        assert_eq!(stored.body, html);
+        assert!(stored
+            .raw
+            .unwrap()
+            .contains("<script>alert('xss')</script>"));
+    }
+
+    /// Wait for the background deliveries spawned by `data_end`
+    async fn finish_deliveries(handler: &SmtpHandler) {
+        let mut deliveries = std::mem::take(&mut *handler.deliveries.lock().unwrap());
+        while deliveries.join_next().await.is_some() {}
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_skips_duplicate_message_id() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (mut handler, mut email_receiver) = catch_all_handler(storage.clone(), None);
+        let raw = b"From: sender@other.com\r\nTo: user@example.com\r\nMessage-ID: <dup-1@other.com>\r\nSubject: Once\r\n\r\nHi";
+
+        let recipients = vec!["user@example.com".to_string()];
+        for _ in 0..2 {
+            handler.data_start("client", "sender@other.com", false, &recipients);
+            handler.data(raw).unwrap();
+            assert!(!handler.data_end().is_error);
+        }
+        finish_deliveries(&handler).await;
+
+        // Only the first delivery is broadcast and stored
+        assert_eq!(email_receiver.recv().await.unwrap().to, "user@example.com");
+        assert!(email_receiver.try_recv().is_err());
+        let emails = storage
+            .get_emails_for_address("user@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].message_id.as_deref(), Some("dup-1@other.com"));
+
+        // The same message to a new recipient is still delivered to them
+        let recipients = vec![
+            "user@example.com".to_string(),
+            "other@example.com".to_string(),
+        ];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(raw).unwrap();
+        assert!(!handler.data_end().is_error);
+        finish_deliveries(&handler).await;
+        assert_eq!(email_receiver.recv().await.unwrap().to, "other@example.com");
+        assert!(email_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_quota_eviction_is_announced() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let old = Email::new(
+            "user@example.com".to_string(),
+            "sender@other.com".to_string(),
+            "Old".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        storage.store_email(old.clone()).await.unwrap();
+
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        let (deletion_sender, mut deletion_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            DeletionNotifier::new(WebhookTrigger::new(storage.clone()), deletion_sender),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            false,
+            None,
+            MailboxQuotaConfig {
+                max_emails: Some(1),
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Evict,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+
+        let recipients = vec!["user@example.com".to_string()];
+        handler.data_start("client", "sender@other.com", false, &recipients);
+        handler.data(b"Subject: New\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+        finish_deliveries(&handler).await;
+
+        // WebSocket/SSE clients drop the evicted email like any other deletion
+        assert_eq!(
+            deletion_receiver.try_recv().unwrap(),
+            (old.id.clone(), "user@example.com".to_string())
+        );
+        let emails = storage
+            .get_emails_for_address("user@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "New");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rejects_messages_over_per_ip_limit() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        let mut handler = SmtpHandler::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            tokio::runtime::Handle::current(),
+            "example.com".to_string(),
+            vec![],
+            false,
+            None,
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            Some(Arc::new(IpRateLimiter::per_hour(1))),
+            None,
+            None,
+            None,
+            None,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+        let ip: IpAddr = "192.0.2.10".parse().unwrap();
+        let recipients = vec!["user@example.com".to_string()];
+
+        handler.mail(ip, "client", "sender@other.com");
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(!response.is_error);
+        handler.data(b"Subject: First\r\n\r\nHi").unwrap();
+        assert!(!handler.data_end().is_error);
+
+        handler.mail(ip, "client", "sender@other.com");
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(response.is_error);
+
+        // Another IP is unaffected
+        handler.mail("192.0.2.11".parse().unwrap(), "client", "sender@other.com");
+        let response = handler.data_start("client", "sender@other.com", false, &recipients);
+        assert!(!response.is_error);
+    }
+
+    /// Read one (possibly multi-line) SMTP reply and return its last line
+    async fn smtp_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> String {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return line.trim_end().to_string();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown_waits_for_accepted_email() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        let server = SmtpServer::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            "example.com".to_string(),
+            "mx.example.com".to_string(),
+            vec![],
+            crate::config::SmtpSslConfig {
+                enabled: false,
+                cert_path: None,
+                key_path: None,
+            },
+            false,
+            None,
+            MailboxQuotaConfig {
+                max_emails: None,
+                max_bytes: None,
+                policy: crate::config::QuotaPolicy::Reject,
+            },
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            Arc::new(SenderRules::default()),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        server.serve(listener, "test".to_string());
+
+        let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut reader = BufReader::new(reader);
+        let greeting = smtp_reply(&mut reader).await;
+        assert!(greeting.starts_with("220 mx.example.com"), "{}", greeting);
+        for (command, code) in [
+            ("EHLO client.test", "250"),
+            ("MAIL FROM:<sender@other.com>", "250"),
+            ("RCPT TO:<user@example.com>", "250"),
+            ("DATA", "354"),
+            ("Subject: Before shutdown\r\n\r\nHello\r\n.", "250"),
+        ] {
+            writer
+                .write_all(format!("{}\r\n", command).as_bytes())
+                .await
+                .unwrap();
+            let reply = smtp_reply(&mut reader).await;
+            assert!(reply.starts_with(code), "{} got {}", command, reply);
+        }
+
+        // The session is still open; shutdown closes it and waits for storage
+        server.shutdown().await;
+        let emails = storage
+            .get_emails_for_address("user@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Before shutdown");
+
+        assert!(TcpStream::connect(addr).await.is_err());
+        let mut rest = String::new();
+        assert_eq!(reader.read_line(&mut rest).await.unwrap(), 0);
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_proxy_protocol_client_address_is_rate_limited() {
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new("sqlite::memory:").await.unwrap());
+        let (email_sender, _email_receiver) = broadcast::channel(10);
+        // One message per client per hour, with every connection coming from the balancer
+        let server = SmtpServer::new(
+            storage.clone(),
+            email_sender,
+            deletion_notifier(&storage),
+            "example.com".to_string(),
+            "mx.example.com".to_string(),
+            vec![],
+            crate::config::SmtpSslConfig {
+                enabled: false,
+                cert_path: None,
+                key_path: None,
+            },
+            false,
+            None,
+            MailboxQuotaConfig::default(),
+            AttachmentLimitsConfig::default(),
+            crate::config::DEFAULT_SMTP_MAX_MESSAGE_BYTES,
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            false,
+            true,
+            Arc::new(SenderRules::default()),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        server.serve(listener, "test".to_string());
+
+        // The DATA reply for one message sent through the balancer on behalf of `client`
+        let send = |client: &'static str| async move {
+            let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+            let mut reader = BufReader::new(reader);
+            let header = format!("PROXY TCP4 {} 127.0.0.1 40000 25\r\n", client);
+            writer.write_all(header.as_bytes()).await.unwrap();
+            assert!(smtp_reply(&mut reader).await.starts_with("220"));
+            for command in [
+                "EHLO client.test",
+                "MAIL FROM:<sender@other.com>",
+                "RCPT TO:<user@example.com>",
+            ] {
+                writer
+                    .write_all(format!("{}\r\n", command).as_bytes())
+                    .await
+                    .unwrap();
+                assert!(smtp_reply(&mut reader).await.starts_with("250"));
+            }
+            writer.write_all(b"DATA\r\n").await.unwrap();
+            smtp_reply(&mut reader).await
+        };
+
+        assert!(send("192.0.2.1").await.starts_with("354"));
+        assert!(send("192.0.2.2").await.starts_with("354"));
+        assert!(!send("192.0.2.1").await.starts_with("354"));
 
-        // Broadcast the email to WebSocket listeners
-        let _ = self.email_sender.send(email);
+        // Connections without the header are dropped before the greeting, possibly with a
+        // reset since the command sent is never read
+        let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        writer.write_all(b"EHLO client.test\r\n").await.unwrap();
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        assert!(matches!(reader.read_line(&mut line).await, Ok(0) | Err(_)));
 
-        mailin_embedded::response::OK
+        server.shutdown().await;
     }
 }
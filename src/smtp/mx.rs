@@ -0,0 +1,169 @@
+//! Recipient domain checks against MX records (`SMTP_ACCEPT_POLICY=mx`)
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use moka::future::Cache;
+use std::time::Duration;
+use tracing::debug;
+
+/// How long a domain's verdict is reused before its MX records are looked up again
+const MX_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Domains whose verdict is kept at most
+const MX_CACHE_CAPACITY: u64 = 10_000;
+
+/// Source of a domain's MX hosts, so the matching can be tested without DNS
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    /// Exchange host names of the domain's MX records, empty when it has none
+    async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>>;
+}
+
+#[async_trait]
+impl MxResolver for TokioAsyncResolver {
+    async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>> {
+        match self.mx_lookup(format!("{}.", domain)).await {
+            Ok(mx) => Ok(mx
+                .iter()
+                .map(|record| record.exchange().to_string())
+                .collect()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(Vec::new()),
+            Err(e) => Err(e).with_context(|| format!("MX lookup for {} failed", domain)),
+        }
+    }
+}
+
+/// Whether any of `mx_hosts` is `hostname`, ignoring case and the trailing root dot
+pub fn mx_points_at(mx_hosts: &[String], hostname: &str) -> bool {
+    let hostname = hostname.trim_end_matches('.');
+    mx_hosts
+        .iter()
+        .any(|host| host.trim_end_matches('.').eq_ignore_ascii_case(hostname))
+}
+
+/// Accepts recipient domains whose MX records point at this server's `SMTP_HOSTNAME`
+pub struct MxValidator {
+    resolver: Box<dyn MxResolver>,
+    hostname: String,
+    /// Verdicts per lowercased domain; failed lookups are not cached
+    verdicts: Cache<String, bool>,
+}
+
+impl MxValidator {
+    /// Create a validator using the system's DNS configuration
+    pub fn new(hostname: String) -> Result<Self> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to create DNS resolver")?;
+        Ok(Self::with_resolver(Box::new(resolver), hostname))
+    }
+
+    fn with_resolver(resolver: Box<dyn MxResolver>, hostname: String) -> Self {
+        let verdicts = Cache::builder()
+            .max_capacity(MX_CACHE_CAPACITY)
+            .time_to_live(MX_CACHE_TTL)
+            .build();
+        Self {
+            resolver,
+            hostname,
+            verdicts,
+        }
+    }
+
+    /// Whether mail for `domain` should be accepted because its MX points here
+    ///
+    /// An error means the lookup failed and the recipient should be deferred rather than
+    /// refused.
+    pub async fn accepts(&self, domain: &str) -> Result<bool> {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        if let Some(accepted) = self.verdicts.get(&domain).await {
+            return Ok(accepted);
+        }
+        let hosts = self.resolver.mx_hosts(&domain).await?;
+        let accepted = mx_points_at(&hosts, &self.hostname);
+        debug!(
+            "MX records of {} ({}) {} {}",
+            domain,
+            hosts.join(", "),
+            if accepted { "include" } else { "don't include" },
+            self.hostname
+        );
+        self.verdicts.insert(domain, accepted).await;
+        Ok(accepted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Resolver answering from a fixed table and counting its lookups
+    struct FakeResolver {
+        records: HashMap<&'static str, Vec<&'static str>>,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl MxResolver for FakeResolver {
+        async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            if domain == "broken.example" {
+                bail!("SERVFAIL");
+            }
+            Ok(self
+                .records
+                .get(domain)
+                .map(|hosts| hosts.iter().map(|host| host.to_string()).collect())
+                .unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn test_mx_points_at() {
+        let hosts = vec![
+            "mx1.provider.example.".to_string(),
+            "MAIL.Example.com.".to_string(),
+        ];
+        assert!(mx_points_at(&hosts, "mail.example.com"));
+        assert!(mx_points_at(&hosts, "mail.example.com."));
+        assert!(!mx_points_at(&hosts, "example.com"));
+        assert!(!mx_points_at(&hosts, "mail.example.co"));
+        assert!(!mx_points_at(&[], "mail.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_validator_accepts_domains_pointing_here() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let resolver = FakeResolver {
+            records: HashMap::from([
+                (
+                    "hosted.example",
+                    vec!["backup.example.net.", "mail.example.com."],
+                ),
+                ("elsewhere.example", vec!["mx.elsewhere.example."]),
+            ]),
+            lookups: lookups.clone(),
+        };
+        let validator = MxValidator::with_resolver(Box::new(resolver), "mail.example.com".into());
+
+        assert!(validator.accepts("hosted.example").await.unwrap());
+        assert!(!validator.accepts("elsewhere.example").await.unwrap());
+        assert!(!validator.accepts("no-mx.example").await.unwrap());
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+
+        // Verdicts are cached per domain, regardless of case
+        assert!(validator.accepts("Hosted.Example").await.unwrap());
+        assert!(!validator.accepts("elsewhere.example").await.unwrap());
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+
+        // Failures are reported and looked up again next time
+        assert!(validator.accepts("broken.example").await.is_err());
+        assert!(validator.accepts("broken.example").await.is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 5);
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Result};
 use mail_parser::{MessageParser, MimeHeaders};
+use std::borrow::Cow;
+use std::sync::LazyLock;
 
 use crate::storage::models::{Attachment, Email};
 
@@ -18,25 +20,60 @@ pub fn parse_email(raw_email: &[u8], fallback_recipient: &str) -> Result<Email>
         .map(|s| s.to_string())
         .unwrap_or_else(|| fallback_recipient.to_string());
 
-    // Extract from address
-    let from = message
-        .from()
-        .and_then(|addrs| addrs.first())
+    // Extract from address and display name
+    let sender = message.from().and_then(|addrs| addrs.first());
+    let from = sender
         .and_then(|addr| addr.address())
         .unwrap_or("unknown@unknown.com")
         .to_string();
-
-    // Extract subject
-    let subject = message.subject().unwrap_or("(No Subject)").to_string();
-
-    // Extract body (prefer HTML, fallback to text)
-    let body = if let Some(html) = message.body_html(0) {
-        html.to_string()
-    } else if let Some(text) = message.body_text(0) {
-        text.to_string()
-    } else {
-        "(No body)".to_string()
-    };
+    let from_name = sender
+        .and_then(|addr| addr.name())
+        .map(|name| decode_encoded_words(name).trim().to_string())
+        .filter(|name| !name.is_empty());
+
+    // Extract carbon-copy and reply-to addresses
+    let cc = message
+        .cc()
+        .map(|addrs| {
+            addrs
+                .iter()
+                .filter_map(|addr| addr.address())
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let reply_to = message
+        .reply_to()
+        .and_then(|addrs| addrs.first())
+        .and_then(|addr| addr.address())
+        .map(|s| s.to_string());
+
+    // mail_parser strips the angle brackets
+    let message_id = message.message_id().map(|id| id.to_string());
+    let in_reply_to = message
+        .in_reply_to()
+        .as_text_list()
+        .and_then(|ids| ids.first().map(|id| id.to_string()));
+    let references = message
+        .references()
+        .as_text_list()
+        .map(|ids| ids.iter().map(|id| id.to_string()).collect())
+        .unwrap_or_default();
+
+    // Extract subject. mail_parser decodes RFC 2047 encoded-words, but leaves
+    // them as-is when they are malformed (e.g. unescaped spaces in a Q word)
+    let subject = message
+        .subject()
+        .map(decode_encoded_words)
+        .unwrap_or_else(|| "(No Subject)".to_string());
+
+    // Extract both body alternatives; the display body prefers HTML, falling back to text
+    let body_text = message.body_text(0).map(|text| text.to_string());
+    let body_html = message.body_html(0).map(|html| html.to_string());
+    let body = body_html
+        .clone()
+        .or_else(|| body_text.clone())
+        .unwrap_or_else(|| "(No body)".to_string());
 
     // Extract attachments
     let mut attachments = Vec::new();
@@ -61,20 +98,164 @@ pub fn parse_email(raw_email: &[u8], fallback_recipient: &str) -> Result<Email>
             content_type,
             size: body.len(),
             content,
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: Some(Attachment::hash_content(body)),
         });
     }
 
     // Store raw email
     let raw = String::from_utf8_lossy(raw_email).to_string();
 
-    Ok(Email::new(
-        recipient,
-        from,
-        subject,
-        body,
-        Some(raw),
-        attachments,
-    ))
+    let mut email = Email::new(recipient, from, subject, body, Some(raw), attachments);
+    email.body_text = body_text;
+    email.body_html = body_html;
+    email.update_snippet();
+    email.from_name = from_name;
+    email.cc = cc;
+    email.reply_to = reply_to;
+    email.message_id = message_id;
+    email.in_reply_to = in_reply_to;
+    email.references = references;
+
+    Ok(email)
+}
+
+/// Attributes that make a browser fetch a resource when the HTML is displayed
+const RESOURCE_ATTRIBUTES: &[&str] = &["src", "srcset", "background", "poster"];
+
+/// HTML cleaner used for `SANITIZE_HTML`
+///
+/// Besides ammonia's defaults (no scripts, styles or event handlers), resources may only
+/// be embedded inline through `cid:` and `data:` URLs, and links may not be `data:` URLs.
+static HTML_SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_url_schemes(["cid", "data"])
+        .attribute_filter(|_element, attribute, value| {
+            let inline = ["cid:", "data:"]
+                .iter()
+                .any(|scheme| value.trim_start().to_ascii_lowercase().starts_with(scheme));
+            let blocked = if attribute == "href" {
+                inline
+            } else {
+                RESOURCE_ATTRIBUTES.contains(&attribute) && !inline
+            };
+            (!blocked).then_some(Cow::Borrowed(value))
+        });
+    builder
+});
+
+/// Strip scripts, event handlers and remotely loaded resources from an HTML body
+pub fn sanitize_html(html: &str) -> String {
+    HTML_SANITIZER.clean(html).to_string()
+}
+
+/// Replace an email's HTML body, and the display body taken from it, with a sanitized copy
+///
+/// The raw message is left untouched.
+pub fn sanitize_email_html(email: &mut Email) {
+    let Some(html) = &email.body_html else {
+        return;
+    };
+    let sanitized = sanitize_html(html);
+    if email.body == *html {
+        email.body = sanitized.clone();
+    }
+    email.body_html = Some(sanitized);
+}
+
+/// Decode any RFC 2047 encoded-words (`=?charset?B|Q?text?=`) left in a header value
+///
+/// Text outside encoded-words is kept unchanged, and whitespace between two
+/// adjacent encoded-words is dropped as the RFC requires.
+pub fn decode_encoded_words(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    let mut pending_space = "";
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_encoded_word(candidate) {
+            Some((decoded, consumed)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    output.push_str(pending_space);
+                    output.push_str(before);
+                }
+                output.push_str(&decoded);
+                pending_space = "";
+                after_word = true;
+                rest = &candidate[consumed..];
+            }
+            None => {
+                output.push_str(pending_space);
+                output.push_str(before);
+                output.push_str("=?");
+                pending_space = "";
+                after_word = false;
+                rest = &candidate[2..];
+            }
+        }
+
+        // Hold back whitespace until we know whether another encoded-word follows
+        if after_word {
+            let trimmed = rest.trim_start();
+            pending_space = &rest[..rest.len() - trimmed.len()];
+            rest = trimmed;
+        }
+    }
+
+    output.push_str(pending_space);
+    output.push_str(rest);
+    output
+}
+
+/// Decode a single encoded-word at the start of `input`, returning the text
+/// and the number of bytes consumed
+fn decode_encoded_word(input: &str) -> Option<(String, usize)> {
+    let inner = input.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    let consumed = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+
+    let bytes = match encoding {
+        "B" | "b" => {
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, text.trim()).ok()?
+        }
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+
+    // Drop any RFC 2231 language suffix (e.g. "utf-8*en")
+    let charset = charset.split('*').next().unwrap_or(charset);
+    let decoded = match mail_parser::decoders::charsets::map::charset_decoder(charset.as_bytes()) {
+        Some(decoder) => decoder(&bytes),
+        None => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+
+    Some((decoded, consumed))
+}
+
+/// Decode the "Q" encoding: quoted-printable with `_` standing in for a space
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [iter.next()?, iter.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    Some(bytes)
 }
 
 #[cfg(test)]
@@ -102,6 +283,21 @@ mod tests {
         b"To: recipient@example.com\r\nSubject: No From Header\r\n\r\nThis email has no from header.".to_vec()
     }
 
+    #[test]
+    fn test_sanitize_html() {
+        let html = r#"<a href="https://example.com" onmouseover="x()">link</a><style>p{}</style><img src="cid:logo@example.com"><img src="http://tracker.example/p.gif" alt="pixel"><a href="data:text/html,boom">data</a>"#;
+        let sanitized = sanitize_html(html);
+
+        assert!(sanitized.contains(r#"href="https://example.com""#));
+        assert!(!sanitized.contains("onmouseover"));
+        assert!(!sanitized.contains("<style"));
+        // Inline images stay, remote ones lose their source
+        assert!(sanitized.contains(r#"src="cid:logo@example.com""#));
+        assert!(!sanitized.contains("tracker.example"));
+        assert!(sanitized.contains(r#"alt="pixel""#));
+        assert!(!sanitized.contains("data:text/html"));
+    }
+
     #[test]
     fn test_parse_simple_email() {
         let raw_email = create_simple_email();
@@ -162,6 +358,16 @@ mod tests {
         assert!(email.body.contains("<h1>Hello World</h1>"));
     }
 
+    #[test]
+    fn test_parse_multipart_alternative_email() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: Alternatives\r\nMIME-Version: 1.0\r\nContent-Type: multipart/alternative; boundary=\"alt\"\r\n\r\n--alt\r\nContent-Type: text/plain\r\n\r\nPlain version\r\n--alt\r\nContent-Type: text/html\r\n\r\n<p>HTML version</p>\r\n--alt--".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+
+        assert!(email.body_text.unwrap().contains("Plain version"));
+        assert!(email.body_html.unwrap().contains("<p>HTML version</p>"));
+        assert!(email.body.contains("<p>HTML version</p>"));
+    }
+
     #[test]
     fn test_parse_email_with_attachment() {
         let raw_email = create_email_with_attachment();
@@ -226,6 +432,52 @@ mod tests {
         assert_eq!(email.subject, "Complex Headers");
     }
 
+    #[test]
+    fn test_parse_email_with_cc_and_reply_to() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nCc: \"Copy One\" <copy1@example.com>, copy2@example.com\r\nReply-To: replies@example.com\r\nSubject: Copies\r\n\r\nBody".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+
+        assert_eq!(email.cc, vec!["copy1@example.com", "copy2@example.com"]);
+        assert_eq!(email.reply_to, Some("replies@example.com".to_string()));
+
+        // Both are optional
+        let email = parse_email(&create_simple_email(), "recipient@example.com").unwrap();
+        assert!(email.cc.is_empty());
+        assert_eq!(email.reply_to, None);
+    }
+
+    #[test]
+    fn test_parse_email_message_id() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nMessage-ID: <abc.123@mail.example.com>\r\nSubject: Tracked\r\n\r\nBody".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+        assert_eq!(
+            email.message_id,
+            Some("abc.123@mail.example.com".to_string())
+        );
+
+        let email = parse_email(&create_simple_email(), "recipient@example.com").unwrap();
+        assert_eq!(email.message_id, None);
+    }
+
+    #[test]
+    fn test_parse_email_thread_headers() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nMessage-ID: <third@example.com>\r\nIn-Reply-To: <second@example.com>\r\nReferences: <first@example.com>\r\n <second@example.com>\r\nSubject: Re: Re: Hello\r\n\r\nBody".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+        assert_eq!(email.in_reply_to, Some("second@example.com".to_string()));
+        assert_eq!(
+            email.references,
+            vec![
+                "first@example.com".to_string(),
+                "second@example.com".to_string()
+            ]
+        );
+        assert_eq!(email.thread_root(), "first@example.com");
+
+        let email = parse_email(&create_simple_email(), "recipient@example.com").unwrap();
+        assert_eq!(email.in_reply_to, None);
+        assert!(email.references.is_empty());
+    }
+
     #[test]
     fn test_parse_email_with_unicode_content() {
         let raw_email = "From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: Unicode Test\r\n\r\nHello 世界! This email contains Unicode characters.".as_bytes().to_vec();
@@ -237,6 +489,46 @@ mod tests {
         assert!(email.body.contains("Hello 世界!"));
     }
 
+    #[test]
+    fn test_parse_email_with_japanese_encoded_subject() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: =?UTF-8?B?44GT44KT44Gr44Gh44Gv5LiW55WM?=\r\n\r\nBody".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+
+        assert_eq!(email.subject, "こんにちは世界");
+    }
+
+    #[test]
+    fn test_parse_email_with_latin1_quoted_printable_subject() {
+        let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: =?ISO-8859-1?Q?Caf=E9_cr=E8me?= =?ISO-8859-1?Q?_br=FBl=E9e?=\r\n\r\nBody".to_vec();
+        let email = parse_email(&raw_email, "recipient@example.com").unwrap();
+
+        assert_eq!(email.subject, "Café crème brûlée");
+    }
+
+    #[test]
+    fn test_decode_encoded_words() {
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?44GT44KT44Gr44Gh44Gv?="),
+            "こんにちは"
+        );
+        assert_eq!(
+            decode_encoded_words("=?iso-8859-1?q?Caf=E9?= au lait"),
+            "Café au lait"
+        );
+        // Whitespace between adjacent encoded-words is dropped
+        assert_eq!(
+            decode_encoded_words("=?utf-8?q?Hello?= =?utf-8?q?_World?="),
+            "Hello World"
+        );
+        // Malformed words and plain text are left untouched
+        assert_eq!(decode_encoded_words("Re: =?bogus"), "Re: =?bogus");
+        assert_eq!(
+            decode_encoded_words("=?utf-8?x?abc?= plain"),
+            "=?utf-8?x?abc?= plain"
+        );
+        assert_eq!(decode_encoded_words("Plain subject"), "Plain subject");
+    }
+
     #[test]
     fn test_parse_email_with_base64_attachment() {
         let raw_email = b"From: sender@example.com\r\nTo: recipient@example.com\r\nSubject: Base64 Attachment\r\nMIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"boundary123\"\r\n\r\n--boundary123\r\nContent-Type: text/plain\r\n\r\nThis is the email body.\r\n\r\n--boundary123\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"test.txt\"\r\nContent-Transfer-Encoding: base64\r\n\r\nVGVzdCBhdHRhY2htZW50IGNvbnRlbnQ=\r\n\r\n--boundary123--".to_vec();
@@ -253,4 +545,35 @@ mod tests {
         // The content should be base64 encoded
         assert!(attachment.content.len() > 0);
     }
+
+    #[test]
+    fn test_same_attachment_in_two_emails_has_the_same_hash() {
+        let email = |to: &str, encoding: &str, content: &str| {
+            let raw = format!(
+                "From: sender@example.com\r\nTo: {}\r\nSubject: Hashed\r\nMIME-Version: 1.0\r\n\
+                 Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+                 --b\r\nContent-Type: text/plain\r\n\r\nBody\r\n\
+                 --b\r\nContent-Type: text/plain\r\n\
+                 Content-Disposition: attachment; filename=\"notes.txt\"\r\n\
+                 Content-Transfer-Encoding: {}\r\n\r\n{}\r\n--b--",
+                to, encoding, content
+            );
+            parse_email(raw.as_bytes(), to).unwrap()
+        };
+
+        // The hash is of the decoded content, so the transfer encoding doesn't matter
+        let first = email("one@example.com", "7bit", "Test attachment content");
+        let second = email(
+            "two@example.com",
+            "base64",
+            "VGVzdCBhdHRhY2htZW50IGNvbnRlbnQ=",
+        );
+        let hash = first.attachments[0].content_hash.clone().unwrap();
+        assert_eq!(second.attachments[0].content_hash, Some(hash.clone()));
+        assert_eq!(hash, Attachment::hash_content(b"Test attachment content"));
+        assert_eq!(hash.len(), 64);
+
+        let other = email("one@example.com", "7bit", "Other content");
+        assert_ne!(other.attachments[0].content_hash, Some(hash));
+    }
 }
@@ -0,0 +1,167 @@
+//! PROXY protocol v1 and v2 headers (`SMTP_PROXY_PROTOCOL`), sent by TCP load balancers
+//! ahead of the SMTP conversation to pass on the real client address
+
+use anyhow::{bail, Context, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature every v2 header starts with
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest v1 header, including the CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Read the PROXY header at the start of a connection, consuming exactly its bytes
+///
+/// Returns the client's address, or `None` when the header carries no address (v1
+/// `UNKNOWN`, v2 `LOCAL` or an unsupported address family), in which case the connection's
+/// own peer should be used. A connection without a valid header is an error.
+pub async fn read_proxy_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<SocketAddr>> {
+    let mut start = [0u8; 12];
+    reader
+        .read_exact(&mut start)
+        .await
+        .context("Connection closed before the PROXY header")?;
+
+    if start == V2_SIGNATURE {
+        let mut fixed = [0u8; 4];
+        reader.read_exact(&mut fixed).await?;
+        let len = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut addresses = vec![0u8; len];
+        reader.read_exact(&mut addresses).await?;
+        return parse_v2(fixed[0], fixed[1], &addresses);
+    }
+
+    if !start.starts_with(b"PROXY ") {
+        bail!("Connection did not start with a PROXY header");
+    }
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            bail!("PROXY header is longer than {} bytes", V1_MAX_LEN);
+        }
+        line.push(reader.read_u8().await?);
+    }
+    parse_v1(std::str::from_utf8(&line).context("PROXY header is not ASCII")?)
+}
+
+/// Parse a v1 header line such as `PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\n`
+pub fn parse_v1(line: &str) -> Result<Option<SocketAddr>> {
+    let Some(fields) = line
+        .strip_suffix("\r\n")
+        .and_then(|line| line.strip_prefix("PROXY "))
+    else {
+        bail!("Malformed PROXY header");
+    };
+    let fields: Vec<&str> = fields.split(' ').collect();
+    match fields.as_slice() {
+        ["UNKNOWN", ..] => Ok(None),
+        [protocol @ ("TCP4" | "TCP6"), source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source
+                .parse()
+                .with_context(|| format!("Invalid PROXY source address '{}'", source))?;
+            if ip.is_ipv4() != (*protocol == "TCP4") {
+                bail!("PROXY source address {} does not match {}", ip, protocol);
+            }
+            let port: u16 = source_port
+                .parse()
+                .with_context(|| format!("Invalid PROXY source port '{}'", source_port))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => bail!("Malformed PROXY header"),
+    }
+}
+
+/// Parse the rest of a v2 header from its version/command and family bytes and its
+/// address block
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        bail!(
+            "Unsupported PROXY protocol version {}",
+            version_command >> 4
+        );
+    }
+    match version_command & 0x0f {
+        // LOCAL: a health check from the balancer itself
+        0 => return Ok(None),
+        1 => {}
+        command => bail!("Unknown PROXY command {}", command),
+    }
+
+    // Only TCP over IPv4 (0x11) and IPv6 (0x21) carry an address to use
+    match family {
+        0x11 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        0x21 if addresses.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        0x11 | 0x21 => bail!("PROXY header address block is too short"),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1_header() {
+        assert_eq!(
+            parse_v1("PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1("PROXY TCP6 2001:db8::1 2001:db8::2 4000 25\r\n").unwrap(),
+            Some("[2001:db8::1]:4000".parse().unwrap())
+        );
+        assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+
+        for line in [
+            "PROXY TCP4 192.0.2.1 198.51.100.1 56324 25",
+            "PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n",
+            "PROXY TCP6 192.0.2.1 198.51.100.1 56324 25\r\n",
+            "PROXY TCP4 not-an-ip 198.51.100.1 56324 25\r\n",
+            "PROXY UDP4 192.0.2.1 198.51.100.1 56324 25\r\n",
+            "EHLO client.example\r\n",
+        ] {
+            assert!(parse_v1(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_header_leaves_the_smtp_stream() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\nEHLO client\r\n";
+        let client = read_proxy_header(&mut stream).await.unwrap();
+        assert_eq!(client, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"EHLO client\r\n");
+
+        let mut v2 = V2_SIGNATURE.to_vec();
+        v2.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        v2.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 1, 0x1f, 0x90, 0, 25]);
+        v2.extend_from_slice(b"EHLO client\r\n");
+        let mut stream = v2.as_slice();
+        let client = read_proxy_header(&mut stream).await.unwrap();
+        assert_eq!(client, Some("203.0.113.7:8080".parse().unwrap()));
+        assert_eq!(stream, b"EHLO client\r\n");
+
+        // LOCAL connections keep the balancer's own address
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(
+            read_proxy_header(&mut local.as_slice()).await.unwrap(),
+            None
+        );
+
+        let mut plain: &[u8] = b"EHLO client.example\r\n";
+        assert!(read_proxy_header(&mut plain).await.is_err());
+        let mut endless = b"PROXY ".repeat(40);
+        endless.extend_from_slice(b"\r\n");
+        assert!(read_proxy_header(&mut endless.as_slice()).await.is_err());
+    }
+}
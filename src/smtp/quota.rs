@@ -0,0 +1,170 @@
+//! Per-mailbox storage quotas, enforced before inbound email is stored
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::config::{MailboxQuotaConfig, QuotaPolicy};
+use crate::storage::{models::Email, StorageBackend};
+
+/// Result of checking a delivery against mailbox quotas
+#[derive(Debug)]
+pub enum QuotaOutcome {
+    /// The emails may be stored; `evicted` lists any older emails moved to the trash to
+    /// make room, for the caller to announce through the `DeletionNotifier`
+    Accepted { evicted: Vec<Email> },
+    /// A mailbox is full (or the message can never fit) and the delivery must be refused
+    Rejected { address: String },
+}
+
+/// Check each email against its mailbox quota, evicting the oldest emails first when
+/// the policy allows it
+///
+/// With the reject policy nothing is modified, so a rejection refuses the whole delivery.
+pub async fn enforce_quotas(
+    storage: &dyn StorageBackend,
+    quota: &MailboxQuotaConfig,
+    emails: &[Email],
+) -> Result<QuotaOutcome> {
+    let mut evicted = Vec::new();
+
+    for email in emails {
+        let size = email.stored_size();
+
+        // A message larger than the whole byte quota can never be stored
+        if quota.max_bytes.is_some_and(|max| size > max) {
+            return Ok(QuotaOutcome::Rejected {
+                address: email.to.clone(),
+            });
+        }
+
+        let mut count = storage.count_emails_for_address(&email.to).await?;
+        let mut bytes = storage.total_bytes_for_address(&email.to).await?;
+        let fits = |count: i64, bytes: i64| {
+            quota.max_emails.is_none_or(|max| count < max)
+                && quota.max_bytes.is_none_or(|max| bytes + size <= max)
+        };
+
+        if fits(count, bytes) {
+            continue;
+        }
+
+        if quota.policy == QuotaPolicy::Reject {
+            return Ok(QuotaOutcome::Rejected {
+                address: email.to.clone(),
+            });
+        }
+
+        // Emails come back newest first, so evict from the end
+        let mut existing = storage.get_emails_for_address(&email.to).await?;
+        while !fits(count, bytes) {
+            let Some(oldest) = existing.pop() else {
+                break;
+            };
+            storage.delete_email(&oldest.id).await?;
+            info!(
+                "🗑️ Evicted email {} from {} to stay within quota",
+                oldest.id, oldest.to
+            );
+            count -= 1;
+            bytes -= oldest.stored_size();
+            evicted.push(oldest);
+        }
+    }
+
+    Ok(QuotaOutcome::Accepted { evicted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteBackend;
+
+    fn email(to: &str, subject: &str, body: &str) -> Email {
+        Email::new(
+            to.to_string(),
+            "sender@example.com".to_string(),
+            subject.to_string(),
+            body.to_string(),
+            None,
+            vec![],
+        )
+    }
+
+    async fn backend_with(emails: &[Email]) -> SqliteBackend {
+        let backend = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        for email in emails {
+            backend.store_email(email.clone()).await.unwrap();
+            // Keep timestamps distinct so "oldest" is well defined
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        backend
+    }
+
+    #[tokio::test]
+    async fn test_reject_policy() {
+        let first = email("a@example.com", "First", "one");
+        let second = email("a@example.com", "Second", "two");
+        let backend = backend_with(&[first.clone(), second.clone()]).await;
+        let quota = MailboxQuotaConfig {
+            max_emails: Some(2),
+            max_bytes: None,
+            policy: QuotaPolicy::Reject,
+        };
+
+        let outcome = enforce_quotas(&backend, &quota, &[email("a@example.com", "Third", "3")])
+            .await
+            .unwrap();
+        assert!(
+            matches!(outcome, QuotaOutcome::Rejected { ref address } if address == "a@example.com")
+        );
+        assert_eq!(
+            backend
+                .count_emails_for_address("a@example.com")
+                .await
+                .unwrap(),
+            2
+        );
+
+        // Other mailboxes are unaffected
+        let outcome = enforce_quotas(&backend, &quota, &[email("b@example.com", "Hi", "hi")])
+            .await
+            .unwrap();
+        assert!(matches!(outcome, QuotaOutcome::Accepted { ref evicted } if evicted.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_evict_policy() {
+        let first = email("a@example.com", "First", "aaaa");
+        let second = email("a@example.com", "Second", "bbbb");
+        let backend = backend_with(&[first.clone(), second.clone()]).await;
+        let quota = MailboxQuotaConfig {
+            max_emails: None,
+            max_bytes: Some(10),
+            policy: QuotaPolicy::Evict,
+        };
+
+        // 8 bytes stored, 4 more would exceed 10, so the oldest email makes room
+        let outcome = enforce_quotas(&backend, &quota, &[email("a@example.com", "Third", "cccc")])
+            .await
+            .unwrap();
+        match outcome {
+            QuotaOutcome::Accepted { evicted } => {
+                assert_eq!(evicted.len(), 1);
+                assert_eq!(evicted[0].id, first.id);
+            }
+            other => panic!("Expected acceptance, got {:?}", other),
+        }
+        assert!(backend.get_email_by_id(&first.id).await.unwrap().is_none());
+        assert!(backend.get_email_by_id(&second.id).await.unwrap().is_some());
+
+        // A message bigger than the whole quota is refused even when evicting
+        let outcome = enforce_quotas(
+            &backend,
+            &quota,
+            &[email("a@example.com", "Huge", "far too large")],
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, QuotaOutcome::Rejected { .. }));
+    }
+}
@@ -0,0 +1,125 @@
+//! Subject-based routing rules, copying inbound email into side mailboxes after aliases
+//! have been resolved
+
+use anyhow::Result;
+use std::collections::HashSet;
+use tracing::debug;
+
+use crate::storage::StorageBackend;
+
+/// Add the target mailboxes of every routing rule whose pattern matches `subject`
+///
+/// Targets keep the domain of the recipient whose rule routed to them and have their own
+/// rules applied in turn. Each address is routed at most once, so rules pointing back at
+/// each other (A→B→A) end instead of re-routing forever.
+pub async fn apply_routing_rules(
+    storage: &dyn StorageBackend,
+    recipients: &[String],
+    subject: &str,
+) -> Result<Vec<String>> {
+    let mut routed = recipients.to_vec();
+    let mut processed: HashSet<String> = recipients.iter().cloned().collect();
+
+    let mut next = 0;
+    while next < routed.len() {
+        let recipient = routed[next].clone();
+        next += 1;
+
+        let (local_part, domain) = match recipient.split_once('@') {
+            Some((local_part, domain)) => (local_part, Some(domain)),
+            None => (recipient.as_str(), None),
+        };
+
+        for rule in storage.get_routing_rules(local_part).await? {
+            if !rule.matches(subject) {
+                continue;
+            }
+            let target = match domain {
+                Some(domain) => format!("{}@{}", rule.target_mailbox, domain),
+                None => rule.target_mailbox.clone(),
+            };
+            if processed.insert(target.clone()) {
+                debug!(
+                    "Routing rule {} copies \"{}\" from {} to {}",
+                    rule.id, subject, recipient, target
+                );
+                routed.push(target);
+            }
+        }
+    }
+
+    Ok(routed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{models::RoutingRule, sqlite::SqliteBackend};
+
+    async fn add_rule(storage: &SqliteBackend, mailbox: &str, pattern: &str, target: &str) {
+        storage
+            .create_routing_rule(RoutingRule::new(
+                mailbox.to_string(),
+                pattern.to_string(),
+                target.to_string(),
+            ))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_matching_rule_adds_target() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        add_rule(&storage, "alice", "[alert]*", "triage").await;
+
+        let routed = apply_routing_rules(
+            &storage,
+            &["alice@example.com".to_string()],
+            "[ALERT] Disk full",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            routed,
+            vec![
+                "alice@example.com".to_string(),
+                "triage@example.com".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_matching_rule_is_noop() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        add_rule(&storage, "alice", "[alert]*", "triage").await;
+
+        let recipients = vec![
+            "alice@example.com".to_string(),
+            "bob@example.com".to_string(),
+        ];
+        let routed = apply_routing_rules(&storage, &recipients, "Lunch?")
+            .await
+            .unwrap();
+        assert_eq!(routed, recipients);
+    }
+
+    #[tokio::test]
+    async fn test_rule_loops_route_once() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        add_rule(&storage, "a", "invoice", "b").await;
+        add_rule(&storage, "b", "invoice", "a").await;
+        add_rule(&storage, "b", "invoice", "c").await;
+
+        let routed = apply_routing_rules(&storage, &["a@example.com".to_string()], "Invoice 7")
+            .await
+            .unwrap();
+        assert_eq!(
+            routed,
+            vec![
+                "a@example.com".to_string(),
+                "b@example.com".to_string(),
+                "c@example.com".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Sender block and allow rules, checked against the envelope sender before mail is accepted
+
+use anyhow::Result;
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::storage::{
+    models::{SenderRule, SenderRuleAction},
+    StorageBackend,
+};
+
+/// How often the rules are reloaded from storage
+pub const SENDER_RULES_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sender rules held in memory so SMTP transactions don't query storage
+///
+/// Rules created through the API take effect at the next [`SenderRules::refresh`].
+#[derive(Default)]
+pub struct SenderRules {
+    rules: RwLock<Vec<SenderRule>>,
+}
+
+impl SenderRules {
+    /// Replace the rules with those currently in storage
+    pub async fn refresh(&self, storage: &dyn StorageBackend) -> Result<()> {
+        let rules = storage.list_sender_rules().await?;
+        debug!("Loaded {} sender rule(s)", rules.len());
+        *self.rules.write().unwrap() = rules;
+        Ok(())
+    }
+
+    /// The block rule refusing mail from `sender`, unless an allow rule also matches it
+    pub fn blocking_rule(&self, sender: &str) -> Option<SenderRule> {
+        let rules = self.rules.read().unwrap();
+        let matching = || rules.iter().filter(|rule| rule.matches(sender));
+        if matching().any(|rule| rule.action == SenderRuleAction::Allow) {
+            return None;
+        }
+        matching()
+            .find(|rule| rule.action == SenderRuleAction::Block)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteBackend;
+
+    #[tokio::test]
+    async fn test_allow_rules_override_block_rules() {
+        let storage = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        let block = SenderRule::new("spam.example".to_string(), SenderRuleAction::Block);
+        storage.create_sender_rule(block.clone()).await.unwrap();
+        storage
+            .create_sender_rule(SenderRule::new(
+                "receipts@spam.example".to_string(),
+                SenderRuleAction::Allow,
+            ))
+            .await
+            .unwrap();
+
+        let rules = SenderRules::default();
+        // Nothing is blocked until the rules are loaded
+        assert!(rules.blocking_rule("bulk@spam.example").is_none());
+        rules.refresh(&storage).await.unwrap();
+
+        assert_eq!(
+            rules.blocking_rule("bulk@spam.example").map(|rule| rule.id),
+            Some(block.id.clone())
+        );
+        assert!(rules.blocking_rule("receipts@spam.example").is_none());
+        assert!(rules.blocking_rule("friend@good.example").is_none());
+
+        assert!(storage.delete_sender_rule(&block.id).await.unwrap());
+        assert!(!storage.delete_sender_rule(&block.id).await.unwrap());
+        rules.refresh(&storage).await.unwrap();
+        assert!(rules.blocking_rule("bulk@spam.example").is_none());
+    }
+}
@@ -0,0 +1,701 @@
+//! Sender Policy Framework (RFC 7208) checks for inbound mail
+
+use anyhow::{bail, Context, Result};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::pin::Pin;
+use tracing::debug;
+
+/// Limit on mechanisms and modifiers that cause DNS lookups (RFC 7208 section 4.6.4)
+const MAX_DNS_LOOKUPS: u32 = 10;
+
+/// Outcome of an SPF check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpfResult {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+    None,
+    TempError,
+    PermError,
+}
+
+impl SpfResult {
+    /// Name used in `Received-SPF` headers, and stored on the email
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpfResult::Pass => "pass",
+            SpfResult::Fail => "fail",
+            SpfResult::SoftFail => "softfail",
+            SpfResult::Neutral => "neutral",
+            SpfResult::None => "none",
+            SpfResult::TempError => "temperror",
+            SpfResult::PermError => "permerror",
+        }
+    }
+}
+
+/// Result a matching mechanism produces, written as `+`, `-`, `~` or `?`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Qualifier {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+}
+
+impl Qualifier {
+    fn result(self) -> SpfResult {
+        match self {
+            Qualifier::Pass => SpfResult::Pass,
+            Qualifier::Fail => SpfResult::Fail,
+            Qualifier::SoftFail => SpfResult::SoftFail,
+            Qualifier::Neutral => SpfResult::Neutral,
+        }
+    }
+}
+
+/// A mechanism; `None` domains mean the domain being checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mechanism {
+    All,
+    Include(String),
+    A {
+        domain: Option<String>,
+        prefix4: u8,
+        prefix6: u8,
+    },
+    Mx {
+        domain: Option<String>,
+        prefix4: u8,
+        prefix6: u8,
+    },
+    Ptr(Option<String>),
+    Ip4(Ipv4Addr, u8),
+    Ip6(Ipv6Addr, u8),
+    Exists(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub qualifier: Qualifier,
+    pub mechanism: Mechanism,
+}
+
+/// A parsed `v=spf1` record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpfRecord {
+    pub directives: Vec<Directive>,
+    pub redirect: Option<String>,
+}
+
+/// Whether a TXT record is an SPF record, i.e. starts with `v=spf1`
+fn is_spf_record(txt: &str) -> bool {
+    let mut terms = txt.split_ascii_whitespace();
+    terms
+        .next()
+        .is_some_and(|version| version.eq_ignore_ascii_case("v=spf1"))
+}
+
+/// Parse a `v=spf1` TXT record
+///
+/// Unknown modifiers are ignored as the RFC requires; unknown mechanisms are an error.
+pub fn parse_spf_record(txt: &str) -> Result<SpfRecord> {
+    if !is_spf_record(txt) {
+        bail!("Not an SPF record: {}", txt);
+    }
+
+    let mut directives = Vec::new();
+    let mut redirect = None;
+
+    for term in txt.split_ascii_whitespace().skip(1) {
+        // Modifiers are name=value; '=' can't appear in a mechanism name
+        if let Some((name, value)) = term.split_once('=') {
+            if !name.contains(':') && !name.contains('/') {
+                if name.eq_ignore_ascii_case("redirect") {
+                    if redirect.is_some() {
+                        bail!("Duplicate redirect modifier");
+                    }
+                    redirect = Some(domain_spec(value)?);
+                }
+                continue;
+            }
+        }
+
+        let (qualifier, term) = match term.as_bytes()[0] {
+            b'+' => (Qualifier::Pass, &term[1..]),
+            b'-' => (Qualifier::Fail, &term[1..]),
+            b'~' => (Qualifier::SoftFail, &term[1..]),
+            b'?' => (Qualifier::Neutral, &term[1..]),
+            _ => (Qualifier::Pass, term),
+        };
+        directives.push(Directive {
+            qualifier,
+            mechanism: parse_mechanism(term)?,
+        });
+    }
+
+    Ok(SpfRecord {
+        directives,
+        redirect,
+    })
+}
+
+fn parse_mechanism(term: &str) -> Result<Mechanism> {
+    let name_end = term.find([':', '/']).unwrap_or(term.len());
+    let (name, rest) = term.split_at(name_end);
+
+    match name.to_ascii_lowercase().as_str() {
+        "all" if rest.is_empty() => Ok(Mechanism::All),
+        "include" => Ok(Mechanism::Include(required_domain(rest)?)),
+        "exists" => Ok(Mechanism::Exists(required_domain(rest)?)),
+        "ptr" => Ok(Mechanism::Ptr(optional_domain(rest)?)),
+        "a" | "mx" => {
+            let (domain, cidr) = match rest.find('/') {
+                Some(slash) => rest.split_at(slash),
+                None => (rest, ""),
+            };
+            let domain = optional_domain(domain)?;
+            let (prefix4, prefix6) = parse_dual_cidr(cidr)?;
+            if name.eq_ignore_ascii_case("a") {
+                Ok(Mechanism::A {
+                    domain,
+                    prefix4,
+                    prefix6,
+                })
+            } else {
+                Ok(Mechanism::Mx {
+                    domain,
+                    prefix4,
+                    prefix6,
+                })
+            }
+        }
+        "ip4" => {
+            let value = rest.strip_prefix(':').context("ip4 requires an address")?;
+            let (addr, prefix) = split_cidr(value, 32)?;
+            let addr = addr
+                .parse()
+                .with_context(|| format!("Invalid ip4 address: {}", addr))?;
+            Ok(Mechanism::Ip4(addr, prefix))
+        }
+        "ip6" => {
+            let value = rest.strip_prefix(':').context("ip6 requires an address")?;
+            let (addr, prefix) = split_cidr(value, 128)?;
+            let addr = addr
+                .parse()
+                .with_context(|| format!("Invalid ip6 address: {}", addr))?;
+            Ok(Mechanism::Ip6(addr, prefix))
+        }
+        _ => bail!("Unknown SPF mechanism: {}", term),
+    }
+}
+
+fn domain_spec(value: &str) -> Result<String> {
+    if value.is_empty() {
+        bail!("Empty domain-spec");
+    }
+    Ok(value.to_string())
+}
+
+fn required_domain(rest: &str) -> Result<String> {
+    let value = rest
+        .strip_prefix(':')
+        .context("Mechanism requires a domain")?;
+    domain_spec(value)
+}
+
+fn optional_domain(rest: &str) -> Result<Option<String>> {
+    match rest.strip_prefix(':') {
+        Some(value) => domain_spec(value).map(Some),
+        None if rest.is_empty() => Ok(None),
+        None => bail!("Invalid mechanism argument: {}", rest),
+    }
+}
+
+/// `addr/prefix`, with the prefix defaulting to `max`
+fn split_cidr(value: &str, max: u8) -> Result<(&str, u8)> {
+    match value.split_once('/') {
+        Some((addr, prefix)) => Ok((addr, parse_prefix(prefix, max)?)),
+        None => Ok((value, max)),
+    }
+}
+
+/// `/24`, `//64` or `/24//64` after an `a` or `mx` mechanism
+fn parse_dual_cidr(cidr: &str) -> Result<(u8, u8)> {
+    if cidr.is_empty() {
+        return Ok((32, 128));
+    }
+    let cidr = cidr.strip_prefix('/').context("Invalid CIDR length")?;
+    match cidr.split_once("//") {
+        Some(("", v6)) => Ok((32, parse_prefix(v6, 128)?)),
+        Some((v4, v6)) => Ok((parse_prefix(v4, 32)?, parse_prefix(v6, 128)?)),
+        None => match cidr.strip_prefix('/') {
+            Some(v6) => Ok((32, parse_prefix(v6, 128)?)),
+            None => Ok((parse_prefix(cidr, 32)?, 128)),
+        },
+    }
+}
+
+fn parse_prefix(prefix: &str, max: u8) -> Result<u8> {
+    let prefix: u8 = prefix
+        .parse()
+        .with_context(|| format!("Invalid CIDR length: {}", prefix))?;
+    if prefix > max {
+        bail!("CIDR length {} is longer than {}", prefix, max);
+    }
+    Ok(prefix)
+}
+
+/// Whether `ip` is inside the network of `addr` with the family's prefix length
+fn in_network(ip: IpAddr, addr: IpAddr, prefix4: u8, prefix6: u8) -> bool {
+    match (ip, addr) {
+        (IpAddr::V4(ip), IpAddr::V4(addr)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix4 as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(addr) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(addr)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix6 as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(addr) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Expand the common macros (`%{s}`, `%{l}`, `%{o}`, `%{d}`, `%{i}`, `%{h}`) in a
+/// domain-spec; transformers and delimiters are not supported
+fn expand_macros(spec: &str, ctx: &CheckContext<'_>, domain: &str) -> Option<String> {
+    if !spec.contains('%') {
+        return Some(spec.to_string());
+    }
+
+    let mut out = String::with_capacity(spec.len());
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '%' => out.push('%'),
+            '_' => out.push(' '),
+            '-' => out.push_str("%20"),
+            '{' => {
+                let letter = chars.next()?.to_ascii_lowercase();
+                if chars.next()? != '}' {
+                    return None;
+                }
+                match letter {
+                    's' => out.push_str(ctx.sender),
+                    'l' => out.push_str(ctx.sender.split('@').next().unwrap_or("postmaster")),
+                    'o' => out.push_str(ctx.sender.rsplit('@').next().unwrap_or(domain)),
+                    'd' => out.push_str(domain),
+                    'h' => out.push_str(ctx.helo),
+                    'i' => match ctx.ip {
+                        IpAddr::V4(ip) => out.push_str(&ip.to_string()),
+                        IpAddr::V6(ip) => {
+                            let nibbles: Vec<String> = ip
+                                .octets()
+                                .iter()
+                                .flat_map(|b| [b >> 4, b & 0xf])
+                                .map(|n| format!("{:x}", n))
+                                .collect();
+                            out.push_str(&nibbles.join("."));
+                        }
+                    },
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+fn is_missing(e: &ResolveError) -> bool {
+    matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+/// Connection details an SPF check is evaluated against
+struct CheckContext<'a> {
+    ip: IpAddr,
+    sender: &'a str,
+    helo: &'a str,
+    lookups: u32,
+}
+
+impl CheckContext<'_> {
+    /// Count a DNS-querying term, failing once the limit is passed
+    fn count_lookup(&mut self) -> Result<(), SpfResult> {
+        self.lookups += 1;
+        if self.lookups > MAX_DNS_LOOKUPS {
+            debug!("SPF check exceeded {} DNS lookups", MAX_DNS_LOOKUPS);
+            return Err(SpfResult::PermError);
+        }
+        Ok(())
+    }
+}
+
+type CheckFuture<'a> = Pin<Box<dyn Future<Output = SpfResult> + Send + 'a>>;
+
+/// Evaluates SPF for inbound mail using an async DNS resolver
+pub struct SpfChecker {
+    resolver: TokioAsyncResolver,
+    reject_fail: bool,
+}
+
+impl SpfChecker {
+    /// Create a checker using the system's DNS configuration
+    pub fn new(reject_fail: bool) -> Result<Self> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to create DNS resolver")?;
+        Ok(Self {
+            resolver,
+            reject_fail,
+        })
+    }
+
+    /// Whether mail with a `fail` result should be refused
+    pub fn reject_fail(&self) -> bool {
+        self.reject_fail
+    }
+
+    /// Check whether `ip` may send mail for `sender`, falling back to the HELO
+    /// domain when the reverse-path is empty (bounces)
+    pub async fn check(&self, ip: IpAddr, sender: &str, helo: &str) -> SpfResult {
+        let domain = match sender.rsplit_once('@') {
+            Some((_, domain)) if !domain.is_empty() => domain,
+            _ => helo,
+        };
+        if domain.is_empty() {
+            return SpfResult::None;
+        }
+
+        let mut ctx = CheckContext {
+            ip,
+            sender,
+            helo,
+            lookups: 0,
+        };
+        let result = self.check_host(domain.to_string(), &mut ctx).await;
+        debug!("SPF result for {} from {}: {}", domain, ip, result.as_str());
+        result
+    }
+
+    /// The `check_host()` function of RFC 7208, boxed because `include` and
+    /// `redirect` recurse
+    fn check_host<'a>(&'a self, domain: String, ctx: &'a mut CheckContext<'_>) -> CheckFuture<'a> {
+        Box::pin(async move {
+            let record = match self.lookup_record(&domain).await {
+                Ok(Some(record)) => record,
+                Ok(None) => return SpfResult::None,
+                Err(result) => return result,
+            };
+            self.evaluate(&record, &domain, ctx).await
+        })
+    }
+
+    async fn lookup_record(&self, domain: &str) -> Result<Option<SpfRecord>, SpfResult> {
+        let txt = match self.resolver.txt_lookup(format!("{}.", domain)).await {
+            Ok(txt) => txt,
+            Err(e) if is_missing(&e) => return Ok(None),
+            Err(e) => {
+                debug!("SPF TXT lookup for {} failed: {}", domain, e);
+                return Err(SpfResult::TempError);
+            }
+        };
+
+        // Long records are split into several strings that are joined without spaces
+        let mut records = txt
+            .iter()
+            .map(|record| {
+                record
+                    .txt_data()
+                    .iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect::<String>()
+            })
+            .filter(|txt| is_spf_record(txt));
+
+        let Some(record) = records.next() else {
+            return Ok(None);
+        };
+        if records.next().is_some() {
+            debug!("{} publishes more than one SPF record", domain);
+            return Err(SpfResult::PermError);
+        }
+        parse_spf_record(&record).map(Some).map_err(|e| {
+            debug!("Invalid SPF record for {}: {}", domain, e);
+            SpfResult::PermError
+        })
+    }
+
+    async fn evaluate(
+        &self,
+        record: &SpfRecord,
+        domain: &str,
+        ctx: &mut CheckContext<'_>,
+    ) -> SpfResult {
+        for directive in &record.directives {
+            match self.matches(&directive.mechanism, domain, ctx).await {
+                Ok(true) => return directive.qualifier.result(),
+                Ok(false) => {}
+                Err(result) => return result,
+            }
+        }
+
+        if let Some(redirect) = &record.redirect {
+            if let Err(result) = ctx.count_lookup() {
+                return result;
+            }
+            let Some(target) = expand_macros(redirect, ctx, domain) else {
+                return SpfResult::PermError;
+            };
+            return match self.check_host(target, ctx).await {
+                SpfResult::None => SpfResult::PermError,
+                result => result,
+            };
+        }
+
+        SpfResult::Neutral
+    }
+
+    /// Whether a mechanism matches; `Err` ends the check with that result
+    async fn matches(
+        &self,
+        mechanism: &Mechanism,
+        domain: &str,
+        ctx: &mut CheckContext<'_>,
+    ) -> Result<bool, SpfResult> {
+        let target = |spec: &Option<String>, ctx: &CheckContext<'_>| match spec {
+            Some(spec) => expand_macros(spec, ctx, domain).ok_or(SpfResult::PermError),
+            None => Ok(domain.to_string()),
+        };
+
+        match mechanism {
+            Mechanism::All => Ok(true),
+            Mechanism::Ip4(addr, prefix) => Ok(in_network(ctx.ip, IpAddr::V4(*addr), *prefix, 0)),
+            Mechanism::Ip6(addr, prefix) => Ok(in_network(ctx.ip, IpAddr::V6(*addr), 0, *prefix)),
+            Mechanism::Include(spec) => {
+                ctx.count_lookup()?;
+                let target = target(&Some(spec.clone()), ctx)?;
+                match self.check_host(target, ctx).await {
+                    SpfResult::Pass => Ok(true),
+                    SpfResult::Fail | SpfResult::SoftFail | SpfResult::Neutral => Ok(false),
+                    SpfResult::TempError => Err(SpfResult::TempError),
+                    SpfResult::PermError | SpfResult::None => Err(SpfResult::PermError),
+                }
+            }
+            Mechanism::A {
+                domain: spec,
+                prefix4,
+                prefix6,
+            } => {
+                ctx.count_lookup()?;
+                let target = target(spec, ctx)?;
+                let addrs = self.addresses(&target, ctx.ip).await?;
+                Ok(addrs
+                    .into_iter()
+                    .any(|addr| in_network(ctx.ip, addr, *prefix4, *prefix6)))
+            }
+            Mechanism::Mx {
+                domain: spec,
+                prefix4,
+                prefix6,
+            } => {
+                ctx.count_lookup()?;
+                let target = target(spec, ctx)?;
+                let exchanges = match self.resolver.mx_lookup(format!("{}.", target)).await {
+                    Ok(mx) => mx
+                        .iter()
+                        .map(|mx| mx.exchange().to_string())
+                        .collect::<Vec<_>>(),
+                    Err(e) if is_missing(&e) => Vec::new(),
+                    Err(_) => return Err(SpfResult::TempError),
+                };
+                if exchanges.len() > MAX_DNS_LOOKUPS as usize {
+                    return Err(SpfResult::PermError);
+                }
+                for exchange in exchanges {
+                    let addrs = self.addresses(&exchange, ctx.ip).await?;
+                    if addrs
+                        .into_iter()
+                        .any(|addr| in_network(ctx.ip, addr, *prefix4, *prefix6))
+                    {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Mechanism::Exists(spec) => {
+                ctx.count_lookup()?;
+                let target = target(&Some(spec.clone()), ctx)?;
+                match self.resolver.ipv4_lookup(format!("{}.", target)).await {
+                    Ok(lookup) => Ok(lookup.iter().next().is_some()),
+                    Err(e) if is_missing(&e) => Ok(false),
+                    Err(_) => Err(SpfResult::TempError),
+                }
+            }
+            Mechanism::Ptr(_) => {
+                // Deprecated by the RFC and slow to evaluate; counted but never matched
+                ctx.count_lookup()?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Addresses of `host` in the same family as `ip`
+    async fn addresses(&self, host: &str, ip: IpAddr) -> Result<Vec<IpAddr>, SpfResult> {
+        let host = if host.ends_with('.') {
+            host.to_string()
+        } else {
+            format!("{}.", host)
+        };
+        let result = match ip {
+            IpAddr::V4(_) => self
+                .resolver
+                .ipv4_lookup(host)
+                .await
+                .map(|lookup| lookup.iter().map(|a| IpAddr::V4(a.0)).collect()),
+            IpAddr::V6(_) => self
+                .resolver
+                .ipv6_lookup(host)
+                .await
+                .map(|lookup| lookup.iter().map(|a| IpAddr::V6(a.0)).collect()),
+        };
+        match result {
+            Ok(addrs) => Ok(addrs),
+            Err(e) if is_missing(&e) => Ok(Vec::new()),
+            Err(_) => Err(SpfResult::TempError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple_record() {
+        let record = parse_spf_record("v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 -all").unwrap();
+        assert_eq!(
+            record.directives,
+            vec![
+                Directive {
+                    qualifier: Qualifier::Pass,
+                    mechanism: Mechanism::Ip4("192.0.2.0".parse().unwrap(), 24),
+                },
+                Directive {
+                    qualifier: Qualifier::Pass,
+                    mechanism: Mechanism::Ip6("2001:db8::".parse().unwrap(), 32),
+                },
+                Directive {
+                    qualifier: Qualifier::Fail,
+                    mechanism: Mechanism::All,
+                },
+            ]
+        );
+        assert_eq!(record.redirect, None);
+    }
+
+    #[test]
+    fn test_parse_qualifiers_and_domains() {
+        let record = parse_spf_record(
+            "v=spf1 +a mx:mail.example.com/24 ~include:_spf.example.net ?exists:%{i}.bl.example.org ?all",
+        )
+        .unwrap();
+        let mechanisms: Vec<_> = record
+            .directives
+            .iter()
+            .map(|d| (d.qualifier, d.mechanism.clone()))
+            .collect();
+        assert_eq!(
+            mechanisms,
+            vec![
+                (
+                    Qualifier::Pass,
+                    Mechanism::A {
+                        domain: None,
+                        prefix4: 32,
+                        prefix6: 128
+                    }
+                ),
+                (
+                    Qualifier::Pass,
+                    Mechanism::Mx {
+                        domain: Some("mail.example.com".to_string()),
+                        prefix4: 24,
+                        prefix6: 128
+                    }
+                ),
+                (
+                    Qualifier::SoftFail,
+                    Mechanism::Include("_spf.example.net".to_string())
+                ),
+                (
+                    Qualifier::Neutral,
+                    Mechanism::Exists("%{i}.bl.example.org".to_string())
+                ),
+                (Qualifier::Neutral, Mechanism::All),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_dual_cidr_and_modifiers() {
+        let record = parse_spf_record(
+            "V=SPF1 a//64 mx/24//48 exp=explain.example.com redirect=_spf.example.com",
+        )
+        .unwrap();
+        assert_eq!(
+            record.directives[0].mechanism,
+            Mechanism::A {
+                domain: None,
+                prefix4: 32,
+                prefix6: 64
+            }
+        );
+        assert_eq!(
+            record.directives[1].mechanism,
+            Mechanism::Mx {
+                domain: None,
+                prefix4: 24,
+                prefix6: 48
+            }
+        );
+        // Unknown modifiers such as exp= are ignored
+        assert_eq!(record.directives.len(), 2);
+        assert_eq!(record.redirect.as_deref(), Some("_spf.example.com"));
+    }
+
+    #[test]
+    fn test_parse_invalid_records() {
+        assert!(parse_spf_record("v=spf10 -all").is_err());
+        assert!(parse_spf_record("v=DKIM1; k=rsa").is_err());
+        assert!(parse_spf_record("v=spf1 foo:bar -all").is_err());
+        assert!(parse_spf_record("v=spf1 ip4:300.0.0.1").is_err());
+        assert!(parse_spf_record("v=spf1 ip4:192.0.2.1/33").is_err());
+        assert!(parse_spf_record("v=spf1 include").is_err());
+        assert!(parse_spf_record("v=spf1 redirect=a.example redirect=b.example").is_err());
+
+        // A record with no terms is valid and evaluates to neutral
+        let record = parse_spf_record("v=spf1").unwrap();
+        assert!(record.directives.is_empty());
+    }
+
+    #[test]
+    fn test_in_network() {
+        let ip: IpAddr = "192.0.2.77".parse().unwrap();
+        assert!(in_network(ip, "192.0.2.0".parse().unwrap(), 24, 128));
+        assert!(!in_network(ip, "192.0.3.0".parse().unwrap(), 24, 128));
+        assert!(in_network(ip, "10.0.0.0".parse().unwrap(), 0, 128));
+        assert!(!in_network(ip, "2001:db8::".parse().unwrap(), 32, 0));
+
+        let ip6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(in_network(ip6, "2001:db8::".parse().unwrap(), 32, 32));
+        assert!(!in_network(ip6, "2001:db9::".parse().unwrap(), 32, 32));
+    }
+}
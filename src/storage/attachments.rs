@@ -0,0 +1,164 @@
+//! Where attachment content lives (`ATTACHMENT_STORE`)
+//!
+//! Inline attachments keep their base64 content in the emails table. Filesystem
+//! attachments are written decoded to `ATTACHMENT_DIR/<email_id>/<index>`, and the row
+//! keeps only their metadata and that relative path.
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+use super::models::Email;
+
+/// Store that attachment content is kept in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AttachmentStore {
+    /// Base64 content in the email row
+    #[default]
+    Inline,
+    /// Decoded files under this directory
+    Filesystem(PathBuf),
+}
+
+impl AttachmentStore {
+    /// Move the content of an email's attachments out of the email, when they are kept on
+    /// the filesystem
+    ///
+    /// Each written attachment is left with empty content and its `path` set. Content that
+    /// is not valid base64 stays inline.
+    pub async fn offload(&self, email: &mut Email) -> Result<()> {
+        let AttachmentStore::Filesystem(dir) = self else {
+            return Ok(());
+        };
+        for (index, attachment) in email.attachments.iter_mut().enumerate() {
+            if attachment.path.is_some() {
+                continue;
+            }
+            let content = match STANDARD.decode(&attachment.content) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!(
+                        "⚠️ Keeping attachment {} of email {} inline, its content is not valid base64: {}",
+                        index, email.id, e
+                    );
+                    continue;
+                }
+            };
+            let relative = format!("{}/{}", email.id, index);
+            let path = dir.join(&relative);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            tokio::fs::write(&path, &content)
+                .await
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            debug!("Wrote attachment to {}", path.display());
+
+            attachment.content = String::new();
+            attachment.path = Some(relative);
+        }
+        Ok(())
+    }
+
+    /// Content of the attachment file at `relative`, an attachment's `path`
+    pub async fn read(&self, relative: &str) -> Result<Vec<u8>> {
+        let AttachmentStore::Filesystem(dir) = self else {
+            bail!(
+                "Attachment {} is stored on the filesystem but ATTACHMENT_STORE is inline",
+                relative
+            );
+        };
+        let path = dir.join(relative);
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    /// Delete the attachment files of permanently deleted emails
+    ///
+    /// Failures are only logged, since the emails themselves are already gone.
+    pub async fn remove(&self, email_ids: &[String]) {
+        let AttachmentStore::Filesystem(dir) = self else {
+            return;
+        };
+        for id in email_ids {
+            let path = dir.join(id);
+            match tokio::fs::remove_dir_all(&path).await {
+                Ok(()) => debug!("Removed attachments in {}", path.display()),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => warn!(
+                    "⚠️ Failed to remove attachments in {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::models::Attachment;
+
+    fn email_with_attachment(content: &[u8]) -> Email {
+        Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Subject".to_string(),
+            "Body".to_string(),
+            None,
+            vec![Attachment {
+                filename: "file.bin".to_string(),
+                content_type: "application/octet-stream".to_string(),
+                size: content.len(),
+                content: STANDARD.encode(content),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
+            }],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_inline_store_keeps_content() {
+        let store = AttachmentStore::Inline;
+        let mut email = email_with_attachment(b"inline bytes");
+        store.offload(&mut email).await.unwrap();
+        assert_eq!(email.attachments[0].path, None);
+        assert_eq!(
+            email.attachments[0].content,
+            STANDARD.encode(b"inline bytes")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filesystem_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::Filesystem(dir.path().to_path_buf());
+        let mut email = email_with_attachment(b"\x00binary\xff");
+        store.offload(&mut email).await.unwrap();
+
+        let attachment = &email.attachments[0];
+        assert_eq!(attachment.content, "");
+        assert_eq!(attachment.path, Some(format!("{}/0", email.id)));
+        assert_eq!(attachment.size, 8);
+        let file = dir.path().join(&email.id).join("0");
+        assert_eq!(std::fs::read(&file).unwrap(), b"\x00binary\xff");
+        let relative = attachment.path.as_deref().unwrap();
+        assert_eq!(store.read(relative).await.unwrap(), b"\x00binary\xff");
+
+        // A stored attachment cannot be read once the store is switched back to inline
+        assert!(AttachmentStore::Inline.read(relative).await.is_err());
+
+        store.remove(&[email.id.clone()]).await;
+        assert!(!dir.path().join(&email.id).exists());
+        // Removing again is not an error
+        store.remove(&[email.id]).await;
+    }
+}
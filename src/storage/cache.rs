@@ -0,0 +1,625 @@
+//! Read-through cache in front of a storage backend (`STORAGE_CACHE_ENABLED`)
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use moka::future::Cache;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::fts::{SearchQuery, SearchResult};
+use super::models::{
+    Attachment, AuditEntry, Email, EmailFilter, ForwardingRule, Mailbox, MailboxSummary,
+    MailboxUidState, PendingWebhookDelivery, RefreshToken, RoutingRule, SenderRule, SenderSummary,
+    SentEmail, StorageStats, User, Webhook, WebhookDelivery, WebhookEvent,
+};
+use super::StorageBackend;
+use crate::rate_limit::{RateLimit, RateLimitRequest};
+
+/// How long a cached read is served before the backend is asked again
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Mailbox listings and single emails each kept in the cache at most
+const CACHE_CAPACITY: u64 = 1_000;
+
+/// Caches `get_emails_for_address` and `get_email_by_id` results of the wrapped backend
+///
+/// Changes made through this backend invalidate the affected entries straight away.
+/// Changes made elsewhere, such as by another instance sharing the database, show up once
+/// the entries expire after [`CACHE_TTL`]. Everything else goes straight to the backend.
+pub struct CachingBackend<B> {
+    inner: B,
+    /// Emails per recipient address
+    mailboxes: Cache<String, Arc<Vec<Email>>>,
+    /// Emails by ID
+    emails: Cache<String, Email>,
+}
+
+impl<B: StorageBackend> CachingBackend<B> {
+    pub fn new(inner: B) -> Self {
+        let mailboxes = Cache::builder()
+            .max_capacity(CACHE_CAPACITY)
+            .time_to_live(CACHE_TTL)
+            .build();
+        let emails = Cache::builder()
+            .max_capacity(CACHE_CAPACITY)
+            .time_to_live(CACHE_TTL)
+            .build();
+        Self {
+            inner,
+            mailboxes,
+            emails,
+        }
+    }
+
+    /// Forget an email changed by ID along with its mailbox's listing
+    ///
+    /// When the email is not cached its address is unknown, so every listing is dropped.
+    async fn invalidate_email(&self, id: &str) {
+        match self.emails.remove(id).await {
+            Some(email) => self.mailboxes.invalidate(&email.to).await,
+            None => self.mailboxes.invalidate_all(),
+        }
+    }
+
+    /// Forget emails removed in bulk, given as (id, to_address)
+    async fn invalidate_deleted(&self, deleted: &[(String, String)]) {
+        for (id, address) in deleted {
+            self.emails.invalidate(id).await;
+            self.mailboxes.invalidate(address).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<B: StorageBackend> StorageBackend for CachingBackend<B> {
+    async fn store_email(&self, email: Email) -> Result<bool> {
+        let address = email.to.clone();
+        let inserted = self.inner.store_email(email).await?;
+        if inserted {
+            self.mailboxes.invalidate(&address).await;
+        }
+        Ok(inserted)
+    }
+
+    fn stream_all_emails(&self, since: Option<DateTime<Utc>>) -> BoxStream<'static, Result<Email>> {
+        self.inner.stream_all_emails(since)
+    }
+
+    async fn get_emails_for_address(&self, address: &str) -> Result<Vec<Email>> {
+        if let Some(emails) = self.mailboxes.get(address).await {
+            return Ok(emails.as_ref().clone());
+        }
+        let emails = self.inner.get_emails_for_address(address).await?;
+        self.mailboxes
+            .insert(address.to_string(), Arc::new(emails.clone()))
+            .await;
+        Ok(emails)
+    }
+
+    async fn query_emails(&self, address: &str, filter: &EmailFilter) -> Result<(Vec<Email>, i64)> {
+        self.inner.query_emails(address, filter).await
+    }
+
+    async fn sender_summary(
+        &self,
+        address: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<SenderSummary>> {
+        self.inner.sender_summary(address, limit).await
+    }
+
+    async fn count_emails_for_address(&self, address: &str) -> Result<i64> {
+        self.inner.count_emails_for_address(address).await
+    }
+
+    async fn total_bytes_for_address(&self, address: &str) -> Result<i64> {
+        self.inner.total_bytes_for_address(address).await
+    }
+
+    async fn list_mailboxes(&self, limit: i64, offset: i64) -> Result<Vec<MailboxSummary>> {
+        self.inner.list_mailboxes(limit, offset).await
+    }
+
+    async fn storage_stats(&self) -> Result<StorageStats> {
+        self.inner.storage_stats().await
+    }
+
+    async fn get_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        if let Some(email) = self.emails.get(id).await {
+            return Ok(Some(email));
+        }
+        let email = self.inner.get_email_by_id(id).await?;
+        if let Some(email) = &email {
+            self.emails.insert(id.to_string(), email.clone()).await;
+        }
+        Ok(email)
+    }
+
+    async fn email_exists_by_message_id(&self, to: &str, message_id: &str) -> Result<bool> {
+        self.inner.email_exists_by_message_id(to, message_id).await
+    }
+
+    async fn get_thread(&self, thread_id: &str) -> Result<Vec<Email>> {
+        self.inner.get_thread(thread_id).await
+    }
+
+    async fn mark_email_read(&self, id: &str, read: bool) -> Result<()> {
+        self.inner.mark_email_read(id, read).await?;
+        self.invalidate_email(id).await;
+        Ok(())
+    }
+
+    async fn set_email_flags(&self, id: &str, flags: &[String]) -> Result<()> {
+        self.inner.set_email_flags(id, flags).await?;
+        self.invalidate_email(id).await;
+        Ok(())
+    }
+
+    async fn delete_email(&self, id: &str) -> Result<()> {
+        self.inner.delete_email(id).await?;
+        self.invalidate_email(id).await;
+        Ok(())
+    }
+
+    async fn delete_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let email = self.inner.delete_email_by_id(id).await?;
+        self.invalidate_email(id).await;
+        Ok(email)
+    }
+
+    async fn delete_old_emails_with_details(&self, hours: i64) -> Result<Vec<(String, String)>> {
+        let deleted = self.inner.delete_old_emails_with_details(hours).await?;
+        self.invalidate_deleted(&deleted).await;
+        Ok(deleted)
+    }
+
+    async fn delete_expired_emails(
+        &self,
+        default_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let deleted = self.inner.delete_expired_emails(default_hours).await?;
+        self.invalidate_deleted(&deleted).await;
+        Ok(deleted)
+    }
+
+    async fn delete_emails_for_address(
+        &self,
+        address: &str,
+        older_than_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let deleted = self
+            .inner
+            .delete_emails_for_address(address, older_than_hours)
+            .await?;
+        self.invalidate_deleted(&deleted).await;
+        Ok(deleted)
+    }
+
+    async fn read_attachment_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.inner.read_attachment_file(path).await
+    }
+
+    async fn get_attachments_by_hash(&self, hash: &str) -> Result<Vec<(String, Attachment)>> {
+        self.inner.get_attachments_by_hash(hash).await
+    }
+
+    async fn get_deleted_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        self.inner.get_deleted_email_by_id(id).await
+    }
+
+    async fn restore_email(&self, id: &str) -> Result<bool> {
+        let restored = self.inner.restore_email(id).await?;
+        if restored {
+            self.invalidate_email(id).await;
+        }
+        Ok(restored)
+    }
+
+    async fn purge_deleted_emails(&self, hours: i64) -> Result<u64> {
+        self.inner.purge_deleted_emails(hours).await
+    }
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<()> {
+        self.inner.create_webhook(webhook).await
+    }
+
+    async fn create_webhooks(&self, webhooks: Vec<Webhook>) -> Result<()> {
+        self.inner.create_webhooks(webhooks).await
+    }
+
+    async fn get_webhooks_for_mailbox(&self, address: &str) -> Result<Vec<Webhook>> {
+        self.inner.get_webhooks_for_mailbox(address).await
+    }
+
+    async fn get_webhook_by_id(&self, id: &str) -> Result<Option<Webhook>> {
+        self.inner.get_webhook_by_id(id).await
+    }
+
+    async fn update_webhook(&self, webhook: Webhook) -> Result<()> {
+        self.inner.update_webhook(webhook).await
+    }
+
+    async fn delete_webhook(&self, id: &str) -> Result<()> {
+        self.inner.delete_webhook(id).await
+    }
+
+    async fn get_active_webhooks_for_event(
+        &self,
+        address: &str,
+        event: WebhookEvent,
+    ) -> Result<Vec<Webhook>> {
+        self.inner
+            .get_active_webhooks_for_event(address, event)
+            .await
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()> {
+        self.inner.record_webhook_delivery(delivery).await
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        self.inner.get_webhook_deliveries(webhook_id, limit).await
+    }
+
+    async fn enqueue_webhook_delivery(&self, delivery: PendingWebhookDelivery) -> Result<()> {
+        self.inner.enqueue_webhook_delivery(delivery).await
+    }
+
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>> {
+        self.inner.get_due_webhook_deliveries(now, limit).await
+    }
+
+    async fn reschedule_webhook_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.inner
+            .reschedule_webhook_delivery(id, attempts, next_attempt_at)
+            .await
+    }
+
+    async fn delete_pending_webhook_delivery(&self, id: &str) -> Result<()> {
+        self.inner.delete_pending_webhook_delivery(id).await
+    }
+
+    async fn get_mailbox(&self, address: &str) -> Result<Option<Mailbox>> {
+        self.inner.get_mailbox(address).await
+    }
+
+    async fn set_mailbox_password(&self, address: &str, password_hash: String) -> Result<()> {
+        self.inner
+            .set_mailbox_password(address, password_hash)
+            .await
+    }
+
+    async fn is_mailbox_locked(&self, address: &str) -> Result<bool> {
+        self.inner.is_mailbox_locked(address).await
+    }
+
+    async fn clear_mailbox_password(&self, address: &str) -> Result<()> {
+        self.inner.clear_mailbox_password(address).await
+    }
+
+    async fn verify_mailbox_password(&self, address: &str, password: &str) -> Result<bool> {
+        self.inner.verify_mailbox_password(address, password).await
+    }
+
+    async fn claim_mailbox_owner(&self, local_part: &str, user_id: &str) -> Result<bool> {
+        self.inner.claim_mailbox_owner(local_part, user_id).await
+    }
+
+    async fn get_mailbox_owner(&self, local_part: &str) -> Result<Option<String>> {
+        self.inner.get_mailbox_owner(local_part).await
+    }
+
+    async fn create_alias(&self, source: &str, destinations: &[String]) -> Result<()> {
+        self.inner.create_alias(source, destinations).await
+    }
+
+    async fn get_aliases_for(&self, source: &str) -> Result<Vec<String>> {
+        self.inner.get_aliases_for(source).await
+    }
+
+    async fn create_forwarding_rule(&self, rule: ForwardingRule) -> Result<()> {
+        self.inner.create_forwarding_rule(rule).await
+    }
+
+    async fn get_forwarding_rules(&self, mailbox: &str) -> Result<Vec<ForwardingRule>> {
+        self.inner.get_forwarding_rules(mailbox).await
+    }
+
+    async fn create_routing_rule(&self, rule: RoutingRule) -> Result<()> {
+        self.inner.create_routing_rule(rule).await
+    }
+
+    async fn get_routing_rules(&self, mailbox: &str) -> Result<Vec<RoutingRule>> {
+        self.inner.get_routing_rules(mailbox).await
+    }
+
+    async fn create_sender_rule(&self, rule: SenderRule) -> Result<()> {
+        self.inner.create_sender_rule(rule).await
+    }
+
+    async fn list_sender_rules(&self) -> Result<Vec<SenderRule>> {
+        self.inner.list_sender_rules().await
+    }
+
+    async fn delete_sender_rule(&self, id: &str) -> Result<bool> {
+        self.inner.delete_sender_rule(id).await
+    }
+
+    async fn audit(&self, entry: AuditEntry) -> Result<()> {
+        self.inner.audit(entry).await
+    }
+
+    async fn get_audit_log(&self, limit: i64) -> Result<Vec<AuditEntry>> {
+        self.inner.get_audit_log(limit).await
+    }
+
+    async fn set_mailbox_retention(
+        &self,
+        address: &str,
+        retention_hours: Option<i64>,
+    ) -> Result<()> {
+        self.inner
+            .set_mailbox_retention(address, retention_hours)
+            .await
+    }
+
+    async fn get_mailbox_retention(&self, address: &str) -> Result<Option<i64>> {
+        self.inner.get_mailbox_retention(address).await
+    }
+
+    async fn get_mailbox_retentions(&self) -> Result<Vec<(String, i64)>> {
+        self.inner.get_mailbox_retentions().await
+    }
+
+    async fn get_mailbox_uid_state(&self, address: &str) -> Result<MailboxUidState> {
+        self.inner.get_mailbox_uid_state(address).await
+    }
+
+    async fn create_user(&self, user: User) -> Result<()> {
+        self.inner.create_user(user).await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        self.inner.get_user_by_email(email).await
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        self.inner.get_user_by_id(id).await
+    }
+
+    async fn has_users(&self) -> Result<bool> {
+        self.inner.has_users().await
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        self.inner.list_users().await
+    }
+
+    async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        self.inner
+            .update_user_password(user_id, password_hash)
+            .await
+    }
+
+    async fn store_refresh_token(&self, token: RefreshToken) -> Result<()> {
+        self.inner.store_refresh_token(token).await
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
+        self.inner.get_refresh_token(token_hash).await
+    }
+
+    async fn delete_refresh_token(&self, token_hash: &str) -> Result<bool> {
+        self.inner.delete_refresh_token(token_hash).await
+    }
+
+    async fn delete_refresh_tokens_for_user(&self, user_id: &str) -> Result<u64> {
+        self.inner.delete_refresh_tokens_for_user(user_id).await
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        self.inner.revoke_token(jti, expires_at).await
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        self.inner.is_token_revoked(jti).await
+    }
+
+    async fn prune_revoked_tokens(&self, before: DateTime<Utc>) -> Result<u64> {
+        self.inner.prune_revoked_tokens(before).await
+    }
+
+    async fn record_failed_login(&self, email: &str, attempted_at: DateTime<Utc>) -> Result<()> {
+        self.inner.record_failed_login(email, attempted_at).await
+    }
+
+    async fn get_failed_logins_since(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        self.inner.get_failed_logins_since(email, since).await
+    }
+
+    async fn clear_failed_logins(&self, email: &str) -> Result<()> {
+        self.inner.clear_failed_logins(email).await
+    }
+
+    async fn create_rate_limit(&self, rate_limit: RateLimit) -> Result<()> {
+        self.inner.create_rate_limit(rate_limit).await
+    }
+
+    async fn get_rate_limit(&self, address: &str) -> Result<Option<RateLimit>> {
+        self.inner.get_rate_limit(address).await
+    }
+
+    async fn update_rate_limit(&self, rate_limit: RateLimit) -> Result<()> {
+        self.inner.update_rate_limit(rate_limit).await
+    }
+
+    async fn delete_rate_limit(&self, address: &str) -> Result<()> {
+        self.inner.delete_rate_limit(address).await
+    }
+
+    async fn record_rate_limit_request(&self, request: RateLimitRequest) -> Result<()> {
+        self.inner.record_rate_limit_request(request).await
+    }
+
+    async fn count_requests_since(&self, address: &str, since: DateTime<Utc>) -> Result<u32> {
+        self.inner.count_requests_since(address, since).await
+    }
+
+    async fn cleanup_old_rate_limit_requests(&self, before: DateTime<Utc>) -> Result<u64> {
+        self.inner.cleanup_old_rate_limit_requests(before).await
+    }
+
+    async fn search_emails(&self, query: SearchQuery) -> Result<Vec<SearchResult>> {
+        self.inner.search_emails(query).await
+    }
+
+    async fn search_emails_for_address(&self, address: &str, query: &str) -> Result<Vec<Email>> {
+        self.inner.search_emails_for_address(address, query).await
+    }
+
+    async fn store_sent_email(&self, email: SentEmail) -> Result<()> {
+        self.inner.store_sent_email(email).await
+    }
+
+    async fn get_sent_emails(&self, from_address: &str) -> Result<Vec<SentEmail>> {
+        self.inner.get_sent_emails(from_address).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteBackend;
+
+    async fn caching_backend() -> CachingBackend<SqliteBackend> {
+        CachingBackend::new(SqliteBackend::new("sqlite::memory:").await.unwrap())
+    }
+
+    fn email(subject: &str) -> Email {
+        Email::new(
+            "cached@test.local".to_string(),
+            "sender@example.com".to_string(),
+            subject.to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_second_read_is_served_from_cache() {
+        let storage = caching_backend().await;
+        let first = email("First");
+        storage.store_email(first.clone()).await.unwrap();
+
+        assert_eq!(
+            storage
+                .get_emails_for_address("cached@test.local")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(storage.get_email_by_id(&first.id).await.unwrap().is_some());
+
+        // Changes that bypass the cache are not seen until the entries expire
+        storage.inner.store_email(email("Unseen")).await.unwrap();
+        storage
+            .inner
+            .mark_email_read(&first.id, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .get_emails_for_address("cached@test.local")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            !storage
+                .get_email_by_id(&first.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .read
+        );
+    }
+
+    #[tokio::test]
+    async fn test_writes_invalidate_cached_reads() {
+        let storage = caching_backend().await;
+        let first = email("First");
+        storage.store_email(first.clone()).await.unwrap();
+        storage
+            .get_emails_for_address("cached@test.local")
+            .await
+            .unwrap();
+        storage.get_email_by_id(&first.id).await.unwrap();
+
+        storage.store_email(email("Second")).await.unwrap();
+        assert_eq!(
+            storage
+                .get_emails_for_address("cached@test.local")
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        storage.mark_email_read(&first.id, true).await.unwrap();
+        assert!(
+            storage
+                .get_email_by_id(&first.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .read
+        );
+
+        storage.delete_email_by_id(&first.id).await.unwrap();
+        assert!(storage.get_email_by_id(&first.id).await.unwrap().is_none());
+        assert_eq!(
+            storage
+                .get_emails_for_address("cached@test.local")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        storage
+            .delete_emails_for_address("cached@test.local", None)
+            .await
+            .unwrap();
+        assert!(storage
+            .get_emails_for_address("cached@test.local")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -52,3 +52,35 @@ impl SearchQuery {
         self
     }
 }
+
+/// Convert plain user input into an FTS5 query that matches every term as a prefix.
+///
+/// Each whitespace-separated term is quoted (so FTS5 operators and punctuation are
+/// treated literally) and the terms are implicitly ANDed together.
+pub fn to_fts5_prefix_query(input: &str) -> String {
+    input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_fts5_prefix_query() {
+        assert_eq!(to_fts5_prefix_query("invoice"), "\"invoice\"*");
+        assert_eq!(
+            to_fts5_prefix_query("  monthly   invoice "),
+            "\"monthly\"* \"invoice\"*"
+        );
+        assert_eq!(
+            to_fts5_prefix_query("say \"hi\""),
+            "\"say\"* \"\"\"hi\"\"\"*"
+        );
+        assert_eq!(to_fts5_prefix_query("NOT OR"), "\"NOT\"* \"OR\"*");
+        assert_eq!(to_fts5_prefix_query("   "), "");
+    }
+}
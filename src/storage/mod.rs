@@ -1,37 +1,172 @@
+pub mod attachments;
+pub mod cache;
 pub mod fts;
 pub mod models;
+pub mod postgres;
 pub mod sqlite;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use fts::{SearchQuery, SearchResult};
-use models::{Email, Mailbox, SentEmail, User, Webhook, WebhookEvent};
+use futures::stream::{self, BoxStream, StreamExt};
+use models::{
+    Attachment, AuditEntry, Email, EmailFilter, ForwardingRule, Mailbox, MailboxSummary,
+    MailboxUidState, PendingWebhookDelivery, RefreshToken, RoutingRule, SenderRule, SenderSummary,
+    SentEmail, StorageStats, User, Webhook, WebhookDelivery, WebhookEvent,
+};
 
 use crate::rate_limit::{RateLimit, RateLimitRequest};
 
+/// Emails held between a backend's cursor and a slow reader of
+/// [`StorageBackend::stream_all_emails`]
+const EMAIL_STREAM_BUFFER: usize = 64;
+
+/// Stream the emails a background task reads from a cursor and sends into the channel
+///
+/// The bounded channel lets the stream outlive the borrow of the backend's pool while only
+/// a few rows are in memory at once.
+fn email_channel_stream(
+    rx: tokio::sync::mpsc::Receiver<Result<Email>>,
+) -> BoxStream<'static, Result<Email>> {
+    stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|email| (email, rx))
+    })
+    .boxed()
+}
+
 /// Trait defining the storage backend interface
 /// This allows swapping storage implementations (SQLite, PostgreSQL, Redis, etc.)
 #[async_trait]
 pub trait StorageBackend: Send + Sync {
     /// Store a new email
-    async fn store_email(&self, email: Email) -> Result<()>;
+    ///
+    /// Returns false without storing anything when the mailbox already holds an email with
+    /// the same Message-ID, so concurrent deliveries of one message can't both be stored.
+    async fn store_email(&self, email: Email) -> Result<bool>;
 
     /// Get all emails for a specific address
+    ///
+    /// Like every other email query here, this skips emails in the trash.
     async fn get_emails_for_address(&self, address: &str) -> Result<Vec<Email>>;
 
+    /// Stream every email outside the trash, oldest first, optionally only those received
+    /// at or after `since`
+    ///
+    /// Rows are read from a database cursor as the stream is polled rather than loaded up
+    /// front, so whole-instance exports stay within a fixed amount of memory.
+    fn stream_all_emails(&self, since: Option<DateTime<Utc>>) -> BoxStream<'static, Result<Email>>;
+
+    /// Get a page of an address's emails matching `filter`, newest first, along with the
+    /// total number of matches
+    async fn query_emails(&self, address: &str, filter: &EmailFilter) -> Result<(Vec<Email>, i64)>;
+
+    /// Count an address's emails per sender, most frequent first, keeping the top `limit`
+    /// senders when set
+    async fn sender_summary(&self, address: &str, limit: Option<i64>)
+        -> Result<Vec<SenderSummary>>;
+
+    /// Count all emails for a specific address
+    async fn count_emails_for_address(&self, address: &str) -> Result<i64>;
+
+    /// Total stored size in bytes of all emails for an address (see `Email::stored_size`)
+    async fn total_bytes_for_address(&self, address: &str) -> Result<i64>;
+
+    /// Page of the distinct recipient addresses with email, most recently active first
+    async fn list_mailboxes(&self, limit: i64, offset: i64) -> Result<Vec<MailboxSummary>>;
+
+    /// Totals across all mailboxes
+    async fn storage_stats(&self) -> Result<StorageStats>;
+
     /// Get a specific email by its ID
     async fn get_email_by_id(&self, id: &str) -> Result<Option<Email>>;
 
-    /// Delete a specific email by its ID
+    /// Whether an email with this `Message-ID` was already stored for a recipient
+    ///
+    /// Emails in the trash count, so a redelivery doesn't bring back a deleted email.
+    async fn email_exists_by_message_id(&self, to: &str, message_id: &str) -> Result<bool>;
+
+    /// Get the emails in a conversation, oldest first (excluding the trash)
+    async fn get_thread(&self, thread_id: &str) -> Result<Vec<Email>>;
+
+    /// Mark an email as read or unread
+    async fn mark_email_read(&self, id: &str, read: bool) -> Result<()>;
+
+    /// Replace an email's IMAP flags (excluding `\Seen`, see `mark_email_read`)
+    async fn set_email_flags(&self, id: &str, flags: &[String]) -> Result<()>;
+
+    /// Move an email to the trash by setting its `deleted_at` time
     async fn delete_email(&self, id: &str) -> Result<()>;
 
-    /// Delete old emails and return details of deleted emails
+    /// Delete an email by ID and return it, so callers can announce the deletion
+    ///
+    /// Returns `None` if no email with that ID exists.
+    async fn delete_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let Some(email) = self.get_email_by_id(id).await? else {
+            return Ok(None);
+        };
+        self.delete_email(id).await?;
+        Ok(Some(email))
+    }
+
+    /// Move old emails to the trash and return details of the moved emails
+    ///
+    /// Mailboxes with their own retention (see `set_mailbox_retention`) are skipped.
     async fn delete_old_emails_with_details(&self, hours: i64) -> Result<Vec<(String, String)>>;
 
+    /// Move emails past their mailbox's retention to the trash, using `default_hours` for
+    /// mailboxes without their own, and return the (id, to_address) of each deleted email
+    ///
+    /// With no `default_hours` only mailboxes that set a retention are cleaned.
+    async fn delete_expired_emails(
+        &self,
+        default_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let mut deleted = match default_hours {
+            Some(hours) => self.delete_old_emails_with_details(hours).await?,
+            None => Vec::new(),
+        };
+        for (address, hours) in self.get_mailbox_retentions().await? {
+            deleted.extend(
+                self.delete_emails_for_address(&address, Some(hours))
+                    .await?,
+            );
+        }
+        Ok(deleted)
+    }
+
+    /// Move all of an address's emails to the trash, or only those older than
+    /// `older_than_hours`, and return the (id, to_address) of each deleted email
+    async fn delete_emails_for_address(
+        &self,
+        address: &str,
+        older_than_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>>;
+
+    /// Read the content of an attachment kept on the filesystem, given its `path`
+    async fn read_attachment_file(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Attachments whose `content_hash` is `hash`, across all emails not in the trash,
+    /// as (email_id, attachment) oldest email first
+    async fn get_attachments_by_hash(&self, hash: &str) -> Result<Vec<(String, Attachment)>>;
+
+    /// Get an email from the trash by its ID
+    async fn get_deleted_email_by_id(&self, id: &str) -> Result<Option<Email>>;
+
+    /// Take an email back out of the trash
+    ///
+    /// Returns `false` if no email with that ID is in the trash.
+    async fn restore_email(&self, id: &str) -> Result<bool>;
+
+    /// Permanently delete emails that have been in the trash for more than `hours`
+    async fn purge_deleted_emails(&self, hours: i64) -> Result<u64>;
+
     /// Create a new webhook
     async fn create_webhook(&self, webhook: Webhook) -> Result<()>;
 
+    /// Create several webhooks in one transaction: either all of them are stored or none
+    async fn create_webhooks(&self, webhooks: Vec<Webhook>) -> Result<()>;
+
     /// Get all webhooks for a specific mailbox
     async fn get_webhooks_for_mailbox(&self, address: &str) -> Result<Vec<Webhook>>;
 
@@ -51,10 +186,41 @@ pub trait StorageBackend: Send + Sync {
         event: WebhookEvent,
     ) -> Result<Vec<Webhook>>;
 
+    /// Record a webhook delivery attempt
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()>;
+
+    /// Get the most recent delivery attempts for a webhook, newest first
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>>;
+
+    /// Queue a failed webhook delivery to be retried later
+    async fn enqueue_webhook_delivery(&self, delivery: PendingWebhookDelivery) -> Result<()>;
+
+    /// Queued webhook deliveries due at `now`, soonest first
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>>;
+
+    /// Record another failed attempt of a queued delivery and when it is next due
+    async fn reschedule_webhook_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Remove a queued delivery once it succeeded or was given up on
+    async fn delete_pending_webhook_delivery(&self, id: &str) -> Result<()>;
+
     /// Get mailbox by address
     async fn get_mailbox(&self, address: &str) -> Result<Option<Mailbox>>;
 
-    /// Create or update a mailbox with password hash
+    /// Create or update a mailbox with password hash, replacing any existing password
     async fn set_mailbox_password(&self, address: &str, password_hash: String) -> Result<()>;
 
     /// Verify if a mailbox exists and is locked (has a password)
@@ -66,6 +232,64 @@ pub trait StorageBackend: Send + Sync {
     /// Verify a mailbox password
     async fn verify_mailbox_password(&self, address: &str, password: &str) -> Result<bool>;
 
+    /// Record a user as the owner of a mailbox (by local part) if it has no owner yet,
+    /// returning whether the claim succeeded
+    async fn claim_mailbox_owner(&self, local_part: &str, user_id: &str) -> Result<bool>;
+
+    /// Get the user ID that owns a mailbox (by local part), if any
+    async fn get_mailbox_owner(&self, local_part: &str) -> Result<Option<String>>;
+
+    /// Deliver mail for a mailbox (by local part) into each destination local part as well as
+    /// any destinations it already has
+    async fn create_alias(&self, source: &str, destinations: &[String]) -> Result<()>;
+
+    /// Get the destination local parts a mailbox (by local part) is aliased to
+    async fn get_aliases_for(&self, source: &str) -> Result<Vec<String>>;
+
+    /// Store a rule forwarding a mailbox's email to an outside address
+    async fn create_forwarding_rule(&self, rule: ForwardingRule) -> Result<()>;
+
+    /// Get the forwarding rules for a mailbox (by local part), oldest first
+    async fn get_forwarding_rules(&self, mailbox: &str) -> Result<Vec<ForwardingRule>>;
+
+    /// Store a rule copying a mailbox's email with a matching subject into another mailbox
+    async fn create_routing_rule(&self, rule: RoutingRule) -> Result<()>;
+
+    /// Get the routing rules for a mailbox (by local part), oldest first
+    async fn get_routing_rules(&self, mailbox: &str) -> Result<Vec<RoutingRule>>;
+
+    /// Store a rule blocking or allowing inbound mail by sender
+    async fn create_sender_rule(&self, rule: SenderRule) -> Result<()>;
+
+    /// Get every sender rule, oldest first
+    async fn list_sender_rules(&self) -> Result<Vec<SenderRule>>;
+
+    /// Delete a sender rule, returning whether it existed
+    async fn delete_sender_rule(&self, id: &str) -> Result<bool>;
+
+    /// Record an administrative change in the audit log
+    async fn audit(&self, entry: AuditEntry) -> Result<()>;
+
+    /// Get the most recent audit log entries, newest first
+    async fn get_audit_log(&self, limit: i64) -> Result<Vec<AuditEntry>>;
+
+    /// Keep a mailbox's (by full address) emails for `retention_hours` instead of the global
+    /// retention; `None` returns it to the global retention
+    async fn set_mailbox_retention(
+        &self,
+        address: &str,
+        retention_hours: Option<i64>,
+    ) -> Result<()>;
+
+    /// Get the retention a mailbox (by full address) overrides the global one with, if any
+    async fn get_mailbox_retention(&self, address: &str) -> Result<Option<i64>>;
+
+    /// Get every mailbox retention override as (address, retention_hours)
+    async fn get_mailbox_retentions(&self) -> Result<Vec<(String, i64)>>;
+
+    /// Get a mailbox's IMAP UIDVALIDITY and next UID, initializing them on first use
+    async fn get_mailbox_uid_state(&self, address: &str) -> Result<MailboxUidState>;
+
     // User authentication methods
 
     /// Create a new user
@@ -80,6 +304,46 @@ pub trait StorageBackend: Send + Sync {
     /// Check if any users exist (for determining if registration should be open)
     async fn has_users(&self) -> Result<bool>;
 
+    /// Get all users, oldest first
+    async fn list_users(&self) -> Result<Vec<User>>;
+
+    /// Replace a user's password hash, returning whether the user exists
+    async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool>;
+
+    /// Store a newly issued refresh token
+    async fn store_refresh_token(&self, token: RefreshToken) -> Result<()>;
+
+    /// Look up a refresh token by its hash
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>>;
+
+    /// Revoke a refresh token, returning whether it existed
+    async fn delete_refresh_token(&self, token_hash: &str) -> Result<bool>;
+
+    /// Revoke all of a user's refresh tokens, returning how many there were
+    async fn delete_refresh_tokens_for_user(&self, user_id: &str) -> Result<u64>;
+
+    /// Revoke an access token by its `jti` claim until the token expires
+    async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<()>;
+
+    /// Whether an access token's `jti` has been revoked
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool>;
+
+    /// Forget revoked tokens that expired before a given time, returning how many
+    async fn prune_revoked_tokens(&self, before: DateTime<Utc>) -> Result<u64>;
+
+    /// Record a failed login attempt for an email address
+    async fn record_failed_login(&self, email: &str, attempted_at: DateTime<Utc>) -> Result<()>;
+
+    /// Get failed login attempts for an email address since a given time, oldest first
+    async fn get_failed_logins_since(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>>;
+
+    /// Forget all failed login attempts for an email address
+    async fn clear_failed_logins(&self, email: &str) -> Result<()>;
+
     // Rate limiting methods
 
     /// Create a new rate limit
@@ -100,19 +364,16 @@ pub trait StorageBackend: Send + Sync {
     /// Count requests since a given timestamp for a mailbox
     async fn count_requests_since(&self, address: &str, since: DateTime<Utc>) -> Result<u32>;
 
-    /// Get the oldest request timestamp since a given time (for calculating retry-after)
-    async fn get_oldest_request_since(
-        &self,
-        address: &str,
-        since: DateTime<Utc>,
-    ) -> Result<Option<DateTime<Utc>>>;
-
     /// Clean up old rate limit requests (optional, for maintenance)
     async fn cleanup_old_rate_limit_requests(&self, before: DateTime<Utc>) -> Result<u64>;
 
     /// Search emails using FTS5 full-text search
     async fn search_emails(&self, query: SearchQuery) -> Result<Vec<SearchResult>>;
 
+    /// Search one address's emails for plain-text terms in subject, body and addresses,
+    /// newest first
+    async fn search_emails_for_address(&self, address: &str, query: &str) -> Result<Vec<Email>>;
+
     // Sent email methods
 
     /// Store a sent email
@@ -120,4 +381,7 @@ pub trait StorageBackend: Send + Sync {
 
     /// Get sent emails for a given from address
     async fn get_sent_emails(&self, from_address: &str) -> Result<Vec<SentEmail>>;
+
+    /// Check that the database is reachable
+    async fn ping(&self) -> Result<()>;
 }
@@ -1,9 +1,13 @@
 use chrono::{DateTime, Utc};
+use mail_parser::decoders::html::html_to_text;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// Email attachment
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub struct Attachment {
     /// Filename of the attachment
     pub filename: String,
@@ -16,10 +20,75 @@ pub struct Attachment {
 
     /// Base64-encoded content of the attachment
     pub content: String,
+
+    /// Virus scan verdict (`clean` or `infected: <signature>`), absent when not scanned
+    #[serde(default)]
+    pub scan_result: Option<String>,
+
+    /// File under `ATTACHMENT_DIR` holding the content, relative to it, when the attachment
+    /// is kept on the filesystem; `content` is then empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// Whether the content was dropped for exceeding the attachment limits; the metadata
+    /// is kept and `content` is empty
+    #[serde(default)]
+    pub dropped: bool,
+
+    /// Hex SHA-256 of the decoded content, the same for the same file in any email; absent
+    /// for attachments stored before hashes were recorded
+    #[serde(default)]
+    pub content_hash: Option<String>,
+}
+
+impl Attachment {
+    /// Hex SHA-256 of decoded attachment content, as stored in `content_hash`
+    pub fn hash_content(content: &[u8]) -> String {
+        Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Longest email preview, in characters
+pub const SNIPPET_CHARS: usize = 140;
+
+/// Preview of an email body on one line
+///
+/// HTML bodies have their tags dropped, whitespace is collapsed, and text longer than
+/// [`SNIPPET_CHARS`] is cut at the last word that fits and ends with `…`.
+pub fn make_snippet(body: &str, content_is_html: bool) -> String {
+    let text = if content_is_html {
+        html_to_text(body)
+    } else {
+        body.to_string()
+    };
+
+    let mut snippet = String::new();
+    let mut chars = 0;
+    for word in text.split_whitespace() {
+        let word_chars = word.chars().count();
+        let separator = usize::from(chars > 0);
+        if chars + separator + word_chars > SNIPPET_CHARS {
+            if chars == 0 {
+                // A single word longer than the whole preview
+                snippet.extend(word.chars().take(SNIPPET_CHARS));
+            }
+            snippet.push('…');
+            break;
+        }
+        if separator == 1 {
+            snippet.push(' ');
+        }
+        snippet.push_str(word);
+        chars += separator + word_chars;
+    }
+    snippet
 }
 
 /// Email model representing a stored email
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Email {
     /// Unique identifier for the email
     pub id: String,
@@ -30,12 +99,24 @@ pub struct Email {
     /// Sender email address
     pub from: String,
 
+    /// Sender display name from the `From` header, if it had one
+    #[serde(default)]
+    pub from_name: Option<String>,
+
     /// Email subject
     pub subject: String,
 
-    /// Email body (can be text or HTML)
+    /// Email body for display (HTML when available, otherwise text)
     pub body: String,
 
+    /// Plain-text body part, if the message has one
+    #[serde(default)]
+    pub body_text: Option<String>,
+
+    /// HTML body part, if the message has one
+    #[serde(default)]
+    pub body_html: Option<String>,
+
     /// Timestamp when email was received
     pub timestamp: DateTime<Utc>,
 
@@ -46,6 +127,64 @@ pub struct Email {
     /// Attachments
     #[serde(default)]
     pub attachments: Vec<Attachment>,
+
+    /// Whether the email has been read
+    #[serde(default)]
+    pub read: bool,
+
+    /// IMAP flags other than `\Seen` (e.g. `\Flagged`), which is tracked by `read`
+    #[serde(default)]
+    pub flags: Vec<String>,
+
+    /// Carbon-copy recipient addresses
+    #[serde(default)]
+    pub cc: Vec<String>,
+
+    /// Reply-To address, if the sender set one
+    #[serde(default)]
+    pub reply_to: Option<String>,
+
+    /// IMAP UID within the recipient's mailbox, assigned when the email is stored
+    #[serde(default)]
+    pub uid: Option<u32>,
+
+    /// IMAP mod-sequence (RFC 7162), raised within the recipient's mailbox whenever the
+    /// email is stored or its read state or flags change
+    #[serde(default)]
+    pub modseq: Option<u64>,
+
+    /// `Message-ID` header without angle brackets, used to drop duplicate deliveries
+    #[serde(default)]
+    pub message_id: Option<String>,
+
+    /// SPF check of the sender when the email was received (`pass`, `fail`, ...)
+    #[serde(default)]
+    pub spf_result: Option<String>,
+
+    /// DKIM verification of the message's signatures when it was received (`pass`, `fail`, ...)
+    #[serde(default)]
+    pub dkim_result: Option<String>,
+
+    /// `In-Reply-To` Message-ID without angle brackets
+    #[serde(default)]
+    pub in_reply_to: Option<String>,
+
+    /// `References` Message-IDs without angle brackets, oldest first
+    #[serde(default)]
+    pub references: Vec<String>,
+
+    /// Message-ID of the conversation's first message, assigned when the email is stored
+    #[serde(default)]
+    pub thread_id: Option<String>,
+
+    /// Size in bytes of the message as received: the raw message, or the body when the
+    /// raw message wasn't kept
+    #[serde(default)]
+    pub size_bytes: i64,
+
+    /// Start of the plain text of the body, for previews; see [`make_snippet`]
+    #[serde(default)]
+    pub snippet: String,
 }
 
 impl Email {
@@ -58,15 +197,68 @@ impl Email {
         raw: Option<String>,
         attachments: Vec<Attachment>,
     ) -> Self {
+        let size_bytes = raw.as_deref().unwrap_or(&body).len() as i64;
+        let snippet = make_snippet(&body, false);
         Self {
             id: Uuid::new_v4().to_string(),
             to,
             from,
+            from_name: None,
             subject,
             body,
+            body_text: None,
+            body_html: None,
             timestamp: Utc::now(),
             raw,
             attachments,
+            read: false,
+            flags: Vec::new(),
+            cc: Vec::new(),
+            reply_to: None,
+            uid: None,
+            modseq: None,
+            message_id: None,
+            spf_result: None,
+            dkim_result: None,
+            in_reply_to: None,
+            references: Vec::new(),
+            thread_id: None,
+            size_bytes,
+            snippet,
+        }
+    }
+
+    /// Recompute `snippet` from the plain-text part, or the display body when there is none
+    pub fn update_snippet(&mut self) {
+        self.snippet = match &self.body_text {
+            Some(text) => make_snippet(text, false),
+            None => make_snippet(&self.body, self.body_html.is_some()),
+        };
+    }
+
+    /// Size in bytes counted against mailbox quotas: the raw message, or the body if
+    /// the raw message wasn't kept
+    pub fn stored_size(&self) -> i64 {
+        self.raw.as_deref().unwrap_or(&self.body).len() as i64
+    }
+
+    /// Root Message-ID of the reference chain, falling back to this email's own
+    /// Message-ID (or id) when it doesn't reply to anything
+    pub fn thread_root(&self) -> String {
+        self.references
+            .first()
+            .or(self.in_reply_to.as_ref())
+            .or(self.message_id.as_ref())
+            .unwrap_or(&self.id)
+            .clone()
+    }
+
+    /// Copy this email for delivery to another recipient, with a fresh ID
+    pub fn for_recipient(&self, to: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            to,
+            ..self.clone()
         }
     }
 }
@@ -83,6 +275,10 @@ mod tests {
             content_type: "text/plain".to_string(),
             size: 100,
             content: "dGVzdCBjb250ZW50".to_string(), // base64 encoded "test content"
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
         };
 
         assert_eq!(attachment.filename, "test.txt");
@@ -98,6 +294,10 @@ mod tests {
             content_type: "text/plain".to_string(),
             size: 100,
             content: "dGVzdCBjb250ZW50".to_string(),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
         }];
 
         let email = Email::new(
@@ -116,6 +316,9 @@ mod tests {
         assert_eq!(email.raw, Some("raw email content".to_string()));
         assert_eq!(email.attachments, attachments);
 
+        assert!(!email.read);
+        assert!(email.flags.is_empty());
+
         // Check that ID is generated (UUID format)
         assert!(!email.id.is_empty());
         assert!(email.id.len() > 10);
@@ -149,12 +352,20 @@ mod tests {
                 content_type: "text/plain".to_string(),
                 size: 50,
                 content: "Y29udGVudDE=".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
             },
             Attachment {
                 filename: "file2.pdf".to_string(),
                 content_type: "application/pdf".to_string(),
                 size: 200,
                 content: "cGRmIGNvbnRlbnQ=".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
             },
         ];
 
@@ -205,6 +416,10 @@ mod tests {
             content_type: "text/plain".to_string(),
             size: 100,
             content: "dGVzdCBjb250ZW50".to_string(),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
         };
 
         // Test JSON serialization
@@ -278,6 +493,171 @@ mod tests {
         assert_eq!(deserialized.to, sent.to);
         assert_eq!(deserialized.subject, sent.subject);
     }
+
+    #[test]
+    fn test_webhook_matches_email() {
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "Alerts@Monitoring.example".to_string(),
+            "Disk usage warning".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        assert!(webhook.matches_email(Some(&email)));
+        assert!(webhook.matches_email(None));
+
+        webhook.from_filter = Some("alerts@monitoring".to_string());
+        assert!(webhook.matches_email(Some(&email)));
+        assert!(!webhook.matches_email(None));
+
+        webhook.subject_filter = Some("WARNING".to_string());
+        assert!(webhook.matches_email(Some(&email)));
+
+        webhook.subject_filter = Some("error".to_string());
+        assert!(!webhook.matches_email(Some(&email)));
+
+        webhook.subject_filter = None;
+        webhook.from_filter = Some("billing".to_string());
+        assert!(!webhook.matches_email(Some(&email)));
+    }
+    #[test]
+    fn test_sender_rule_matching() {
+        let rule = |pattern: &str| SenderRule::new(pattern.to_string(), SenderRuleAction::Block);
+
+        // Domains cover their subdomains but not lookalikes
+        for pattern in ["spam.example", "@Spam.Example"] {
+            assert!(rule(pattern).matches("bulk@spam.example"));
+            assert!(rule(pattern).matches("bulk@mail.spam.example"));
+            assert!(!rule(pattern).matches("bulk@notspam.example"));
+        }
+
+        assert!(rule("Promo@shop.example").matches("promo@SHOP.example"));
+        assert!(!rule("promo@shop.example").matches("news@shop.example"));
+
+        assert!(rule("*@*.spam.example").matches("x@a.spam.example"));
+        assert!(!rule("*@*.spam.example").matches("x@spam.example"));
+        assert!(rule("news-?@*").matches("news-1@anything.example"));
+        assert!(!rule("news-?@*").matches("news-10@anything.example"));
+        assert!(rule("*").matches(""));
+    }
+
+    #[test]
+    fn test_routing_rule_matching() {
+        let rule = |pattern: &str| {
+            RoutingRule::new(
+                "inbox".to_string(),
+                pattern.to_string(),
+                "triage".to_string(),
+            )
+        };
+
+        // Plain text matches anywhere in the subject
+        assert!(rule("Invoice").matches("Your invoice #42"));
+        assert!(!rule("invoice").matches("Your receipt"));
+
+        // Globs cover the whole subject
+        assert!(rule("[alert]*").matches("[ALERT] Disk full"));
+        assert!(!rule("[alert]*").matches("Re: [alert] Disk full"));
+        assert!(rule("ticket #???").matches("Ticket #123"));
+    }
+
+    #[test]
+    fn test_make_snippet_strips_html() {
+        let html = "<html><head><title>Ignored</title></head><body>\
+                    <p>Hello&nbsp;<b>there</b>,</p>\n\n<p>  see   you &amp; soon</p></body></html>";
+        assert_eq!(make_snippet(html, true), "Hello there, see you & soon");
+
+        // Plain text keeps anything that looks like markup
+        assert_eq!(
+            make_snippet("Use <b>bold</b>\r\n\tsparingly", false),
+            "Use <b>bold</b> sparingly"
+        );
+    }
+
+    #[test]
+    fn test_make_snippet_truncates_at_word_boundary() {
+        let body = "word ".repeat(40);
+        let snippet = make_snippet(&body, false);
+        // 28 five-character words plus separators fill 139 characters; the next won't fit
+        assert_eq!(snippet, format!("{}…", ["word"; 28].join(" ")));
+        assert!(snippet.chars().count() <= SNIPPET_CHARS + 1);
+
+        let short = make_snippet("Short body", false);
+        assert_eq!(short, "Short body");
+
+        // A single overlong word is cut mid-word
+        let long = make_snippet(&"x".repeat(200), false);
+        assert_eq!(long, format!("{}…", "x".repeat(SNIPPET_CHARS)));
+
+        let mut email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Subject".to_string(),
+            "<p>Display</p>".to_string(),
+            None,
+            vec![],
+        );
+        email.body_html = Some("<p>Display</p>".to_string());
+        email.update_snippet();
+        assert_eq!(email.snippet, "Display");
+        email.body_text = Some("Plain part".to_string());
+        email.update_snippet();
+        assert_eq!(email.snippet, "Plain part");
+    }
+}
+
+/// Criteria for listing a page of one address's emails
+///
+/// `from` and `subject_contains` are case-insensitive substring matches; `since` and
+/// `until` bound the received time inclusively.
+#[derive(Debug, Clone, Default)]
+pub struct EmailFilter {
+    pub from: Option<String>,
+    pub subject_contains: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Page size
+    pub limit: i64,
+    /// Number of matching emails to skip
+    pub offset: i64,
+}
+
+/// How many of a mailbox's emails came from one sender
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SenderSummary {
+    /// Sender address, as stored on the emails
+    pub from: String,
+    pub count: i64,
+    /// When the newest email from this sender was received
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A recipient address that has received email, with its message count
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct MailboxSummary {
+    pub address: String,
+    pub count: i64,
+    /// When the mailbox's newest email was received
+    pub latest: DateTime<Utc>,
+}
+
+/// Totals across every mailbox, for operators; emails in the trash are not counted
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct StorageStats {
+    pub total_emails: i64,
+    /// Distinct recipient addresses with at least one email
+    pub total_mailboxes: i64,
+    pub total_attachments: i64,
+    /// Stored size of all emails (see `Email::stored_size`)
+    pub total_bytes: i64,
+    pub oldest_email: Option<DateTime<Utc>>,
+    pub newest_email: Option<DateTime<Utc>>,
 }
 
 /// Sent email model representing an outbound email
@@ -316,10 +696,12 @@ impl SentEmail {
 }
 
 /// Webhook event types
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum WebhookEvent {
     Arrival,
     Deletion,
+    /// An email with at least one attachment arrived (sent alongside `Arrival`)
+    AttachmentReceived,
 }
 
 impl WebhookEvent {
@@ -327,6 +709,7 @@ impl WebhookEvent {
         match self {
             WebhookEvent::Arrival => "arrival",
             WebhookEvent::Deletion => "deletion",
+            WebhookEvent::AttachmentReceived => "attachment_received",
         }
     }
 
@@ -334,13 +717,45 @@ impl WebhookEvent {
         match s {
             "arrival" => Some(WebhookEvent::Arrival),
             "deletion" => Some(WebhookEvent::Deletion),
+            "attachment_received" => Some(WebhookEvent::AttachmentReceived),
+            _ => None,
+        }
+    }
+}
+
+/// Shape of the payloads a webhook sends
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum WebhookFormat {
+    /// The JSON event payload, or the webhook's payload template
+    #[default]
+    Json,
+    /// Slack incoming webhook message: `{"text": ...}`
+    Slack,
+    /// Discord webhook message: `{"content": ...}`
+    Discord,
+}
+
+impl WebhookFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookFormat::Json => "json",
+            WebhookFormat::Slack => "slack",
+            WebhookFormat::Discord => "discord",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "json" => Some(WebhookFormat::Json),
+            "slack" => Some(WebhookFormat::Slack),
+            "discord" => Some(WebhookFormat::Discord),
             _ => None,
         }
     }
 }
 
 /// Webhook configuration model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Webhook {
     /// Unique identifier for the webhook
     pub id: String,
@@ -359,6 +774,69 @@ pub struct Webhook {
 
     /// Whether the webhook is enabled
     pub enabled: bool,
+
+    /// Shared secret used to sign deliveries (never returned by the API)
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+
+    /// Number of times a failed delivery is retried after the first attempt
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry; each later retry doubles it
+    #[serde(default = "default_webhook_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+
+    /// Upper bound on the delay between retries
+    #[serde(default = "default_webhook_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+
+    /// Only fire for emails whose sender contains this text (case-insensitive)
+    #[serde(default)]
+    pub from_filter: Option<String>,
+
+    /// Only fire for emails whose subject contains this text (case-insensitive)
+    #[serde(default)]
+    pub subject_filter: Option<String>,
+
+    /// Whether payloads include each attachment's base64 content, not just its metadata
+    #[serde(default)]
+    pub include_attachment_content: bool,
+
+    /// Template rendered into the payload instead of the default one (see
+    /// [`crate::webhooks::render_payload_template`])
+    #[serde(default)]
+    pub payload_template: Option<String>,
+
+    /// Payload shape; the payload template only applies to `Json`
+    #[serde(default)]
+    pub format: WebhookFormat,
+
+    /// Extra headers sent with every delivery; a `User-Agent` here replaces the default
+    /// [`crate::webhooks::WEBHOOK_USER_AGENT`]
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// Default number of webhook retries (three attempts in total)
+pub const DEFAULT_WEBHOOK_MAX_RETRIES: u32 = 2;
+
+/// Default delay before the first webhook retry
+pub const DEFAULT_WEBHOOK_INITIAL_BACKOFF_SECS: u64 = 1;
+
+/// Default ceiling on the delay between webhook retries
+pub const DEFAULT_WEBHOOK_MAX_BACKOFF_SECS: u64 = 60;
+
+fn default_webhook_max_retries() -> u32 {
+    DEFAULT_WEBHOOK_MAX_RETRIES
+}
+
+fn default_webhook_initial_backoff_secs() -> u64 {
+    DEFAULT_WEBHOOK_INITIAL_BACKOFF_SECS
+}
+
+fn default_webhook_max_backoff_secs() -> u64 {
+    DEFAULT_WEBHOOK_MAX_BACKOFF_SECS
 }
 
 impl Webhook {
@@ -371,6 +849,370 @@ impl Webhook {
             events,
             created_at: Utc::now(),
             enabled: true,
+            secret: None,
+            max_retries: DEFAULT_WEBHOOK_MAX_RETRIES,
+            initial_backoff_secs: DEFAULT_WEBHOOK_INITIAL_BACKOFF_SECS,
+            max_backoff_secs: DEFAULT_WEBHOOK_MAX_BACKOFF_SECS,
+            from_filter: None,
+            subject_filter: None,
+            include_attachment_content: false,
+            payload_template: None,
+            format: WebhookFormat::Json,
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Whether the webhook's sender and subject filters accept an email
+    ///
+    /// Events without an email (deletions) only match webhooks with no filters.
+    pub fn matches_email(&self, email: Option<&Email>) -> bool {
+        let Some(email) = email else {
+            return self.from_filter.is_none() && self.subject_filter.is_none();
+        };
+
+        let contains = |haystack: &str, filter: &Option<String>| {
+            filter
+                .as_ref()
+                .is_none_or(|f| haystack.to_lowercase().contains(&f.to_lowercase()))
+        };
+        contains(&email.from, &self.from_filter) && contains(&email.subject, &self.subject_filter)
+    }
+}
+
+/// Maximum number of characters of a response body kept in a delivery record
+pub const MAX_DELIVERY_RESPONSE_LEN: usize = 1024;
+
+/// A single webhook delivery attempt
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDelivery {
+    /// Unique identifier for the delivery attempt
+    pub id: String,
+
+    /// Webhook the attempt was made for
+    pub webhook_id: String,
+
+    /// When the attempt was made
+    pub timestamp: DateTime<Utc>,
+
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+
+    /// HTTP status returned by the receiver (None if the request failed)
+    pub status_code: Option<u16>,
+
+    /// Whether the attempt succeeded
+    pub success: bool,
+
+    /// Truncated response body, or the error message if the request failed
+    pub response_body: Option<String>,
+}
+
+impl WebhookDelivery {
+    /// Create a delivery record, truncating the response body
+    pub fn new(
+        webhook_id: String,
+        attempt: u32,
+        status_code: Option<u16>,
+        success: bool,
+        response_body: Option<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            webhook_id,
+            timestamp: Utc::now(),
+            attempt,
+            status_code,
+            success,
+            response_body: response_body
+                .map(|body| body.chars().take(MAX_DELIVERY_RESPONSE_LEN).collect()),
+        }
+    }
+}
+
+/// A webhook delivery that failed every immediate attempt, kept to be retried later
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingWebhookDelivery {
+    /// Unique identifier for the queued delivery
+    pub id: String,
+
+    /// Webhook the payload is for
+    pub webhook_id: String,
+
+    /// Payload exactly as built for the original delivery
+    pub payload: serde_json::Value,
+
+    /// Attempts made so far, including the immediate ones
+    pub attempts: u32,
+
+    /// When the delivery was queued
+    pub created_at: DateTime<Utc>,
+
+    /// When the delivery is next due
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+impl PendingWebhookDelivery {
+    /// Queue a payload after `attempts` failed attempts
+    pub fn new(
+        webhook_id: String,
+        payload: serde_json::Value,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            webhook_id,
+            payload,
+            attempts,
+            created_at: Utc::now(),
+            next_attempt_at,
+        }
+    }
+}
+
+/// A rule relaying a mailbox's received email to an outside address through an
+/// upstream SMTP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardingRule {
+    /// Unique identifier for the rule
+    pub id: String,
+
+    /// Mailbox (local part) whose email is forwarded
+    pub mailbox: String,
+
+    /// Address the email is relayed to
+    pub destination: String,
+
+    /// Upstream SMTP server used to relay the email
+    pub relay_host: String,
+    pub relay_port: u16,
+    pub relay_username: Option<String>,
+    #[serde(skip_serializing)]
+    pub relay_password: Option<String>,
+
+    /// Whether the relay connection must be upgraded with STARTTLS
+    pub relay_starttls: bool,
+
+    /// When the rule was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl ForwardingRule {
+    /// Create a rule relaying through `relay_host:relay_port` with STARTTLS and no
+    /// credentials
+    pub fn new(mailbox: String, destination: String, relay_host: String, relay_port: u16) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            mailbox,
+            destination,
+            relay_host,
+            relay_port,
+            relay_username: None,
+            relay_password: None,
+            relay_starttls: true,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A rule delivering a copy of a mailbox's email whose subject matches a pattern into
+/// another mailbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// Unique identifier for the rule
+    pub id: String,
+
+    /// Mailbox (local part) whose email is checked
+    pub mailbox: String,
+
+    /// Subject the rule applies to: a glob with `*` and `?` matched against the whole
+    /// subject, or text the subject contains. Matching ignores case.
+    pub subject_pattern: String,
+
+    /// Mailbox (local part) that also receives matching email
+    pub target_mailbox: String,
+
+    /// When the rule was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl RoutingRule {
+    /// Create a rule with generated UUID
+    pub fn new(mailbox: String, subject_pattern: String, target_mailbox: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            mailbox,
+            subject_pattern,
+            target_mailbox,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether the rule's pattern covers an email subject
+    pub fn matches(&self, subject: &str) -> bool {
+        let pattern = self.subject_pattern.trim().to_lowercase();
+        let subject = subject.trim().to_lowercase();
+
+        if pattern.contains(['*', '?']) {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let subject: Vec<char> = subject.chars().collect();
+            return glob_matches(&pattern, &subject);
+        }
+        subject.contains(&pattern)
+    }
+}
+
+/// What happens to mail from senders matching a [`SenderRule`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SenderRuleAction {
+    /// Refuse the mail at SMTP time
+    Block,
+    /// Accept the mail even when a block rule also matches
+    Allow,
+}
+
+impl SenderRuleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SenderRuleAction::Block => "block",
+            SenderRuleAction::Allow => "allow",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "block" => Some(SenderRuleAction::Block),
+            "allow" => Some(SenderRuleAction::Allow),
+            _ => None,
+        }
+    }
+}
+
+/// A rule blocking or allowing inbound mail by its envelope sender (MAIL FROM)
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SenderRule {
+    /// Unique identifier for the rule
+    pub id: String,
+
+    /// Sender the rule applies to: a glob with `*` and `?` (`*@spam.example`), a domain
+    /// that also covers its subdomains (`spam.example` or `@spam.example`), or an exact
+    /// address. Matching ignores case.
+    pub pattern: String,
+
+    pub action: SenderRuleAction,
+
+    /// When the rule was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl SenderRule {
+    /// Create a rule with generated UUID
+    pub fn new(pattern: String, action: SenderRuleAction) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            pattern,
+            action,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether the rule's pattern covers a sender address
+    pub fn matches(&self, sender: &str) -> bool {
+        let pattern = self.pattern.trim().to_lowercase();
+        let sender = sender.trim().to_lowercase();
+
+        if pattern.contains(['*', '?']) {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let sender: Vec<char> = sender.chars().collect();
+            return glob_matches(&pattern, &sender);
+        }
+        match pattern.strip_prefix('@') {
+            Some(domain) => domain_matches(domain, &sender),
+            None if !pattern.contains('@') => domain_matches(&pattern, &sender),
+            None => pattern == sender,
+        }
+    }
+}
+
+/// Whether the sender's domain is `domain` or one of its subdomains
+fn domain_matches(domain: &str, sender: &str) -> bool {
+    let Some((_, sender_domain)) = sender.rsplit_once('@') else {
+        return false;
+    };
+    sender_domain == domain
+        || sender_domain
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Match `text` against a glob where `*` stands for any run of characters and `?` for one
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` seen and the text position it is matched up to
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// An administrative change recorded in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    /// Unique identifier for the entry
+    pub id: String,
+
+    /// User who made the change; `None` when auth is disabled or the request was
+    /// authorized with a mailbox password
+    pub actor: Option<String>,
+
+    /// What was done, e.g. `webhook.create` or `rate_limit.delete`
+    pub action: String,
+
+    /// What it was done to: a webhook or sender rule ID, or a mailbox address
+    pub target: String,
+
+    /// Action-specific details, such as the new settings
+    #[schema(value_type = Object)]
+    pub detail: serde_json::Value,
+
+    /// When the change was made
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// Create an entry with generated UUID, timestamped now
+    pub fn new(
+        actor: Option<String>,
+        action: &str,
+        target: String,
+        detail: serde_json::Value,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            actor,
+            action: action.to_string(),
+            target,
+            detail,
+            created_at: Utc::now(),
         }
     }
 }
@@ -415,6 +1257,19 @@ impl Mailbox {
     }
 }
 
+/// Persisted IMAP UID state for a mailbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxUidState {
+    /// UIDVALIDITY value, fixed for as long as UIDs in the mailbox are never reset
+    pub uid_validity: u32,
+
+    /// UID the next email stored in the mailbox will receive
+    pub uid_next: u32,
+
+    /// Highest mod-sequence given to an email in the mailbox (`HIGHESTMODSEQ`)
+    pub highest_modseq: u64,
+}
+
 /// User model for authentication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -430,16 +1285,37 @@ pub struct User {
 
     /// When the user was created
     pub created_at: DateTime<Utc>,
+
+    /// Whether the user may use the admin routes
+    #[serde(default)]
+    pub is_admin: bool,
 }
 
 impl User {
-    /// Create a new user with generated UUID
+    /// Create a new, non-admin user with generated UUID
     pub fn new(email: String, password_hash: String) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             email,
             password_hash,
             created_at: Utc::now(),
+            is_admin: false,
         }
     }
 }
+
+/// A long-lived refresh token, stored only as a hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshToken {
+    /// SHA-256 hex digest of the token handed to the client
+    pub token_hash: String,
+
+    /// User the token was issued to
+    pub user_id: String,
+
+    /// When the token stops being accepted
+    pub expires_at: DateTime<Utc>,
+
+    /// When the token was issued
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,3619 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions};
+use tracing::{debug, error, info, warn};
+
+use super::{
+    attachments::AttachmentStore,
+    email_channel_stream,
+    fts::{SearchQuery, SearchResult},
+    models::{
+        Attachment, AuditEntry, Email, EmailFilter, ForwardingRule, Mailbox, MailboxSummary,
+        MailboxUidState, PendingWebhookDelivery, RefreshToken, RoutingRule, SenderRule,
+        SenderRuleAction, SenderSummary, SentEmail, StorageStats, User, Webhook, WebhookDelivery,
+        WebhookEvent, WebhookFormat,
+    },
+    StorageBackend, EMAIL_STREAM_BUFFER,
+};
+
+/// PostgreSQL implementation of StorageBackend
+pub struct PostgresBackend {
+    pool: PgPool,
+    attachment_store: AttachmentStore,
+}
+
+/// A row of the emails table, as selected by the email queries
+#[derive(sqlx::FromRow)]
+struct EmailRow {
+    id: String,
+    to_address: String,
+    from_address: String,
+    subject: String,
+    body: String,
+    timestamp: DateTime<Utc>,
+    raw: Option<String>,
+    attachments: Option<String>,
+    read: bool,
+    flags: Option<String>,
+    cc: Option<String>,
+    reply_to: Option<String>,
+    body_text: Option<String>,
+    body_html: Option<String>,
+    uid: Option<i64>,
+    message_id: Option<String>,
+    spf_result: Option<String>,
+    dkim_result: Option<String>,
+    in_reply_to: Option<String>,
+    email_references: Option<String>,
+    thread_id: Option<String>,
+    size_bytes: Option<i64>,
+    snippet: Option<String>,
+    from_name: Option<String>,
+    modseq: Option<i64>,
+}
+
+/// Convert an emails table row into an Email
+fn email_from_row(row: EmailRow) -> Email {
+    // Deserialize attachments from JSON
+    let attachments = row
+        .attachments
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let flags = row
+        .flags
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let cc = row
+        .cc
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let references = row
+        .email_references
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+
+    let mut email = Email {
+        id: row.id,
+        to: row.to_address,
+        from: row.from_address,
+        from_name: row.from_name,
+        subject: row.subject,
+        body: row.body,
+        body_text: row.body_text,
+        body_html: row.body_html,
+        timestamp: row.timestamp,
+        raw: row.raw,
+        attachments,
+        read: row.read,
+        flags,
+        cc,
+        reply_to: row.reply_to,
+        uid: row.uid.map(|uid| uid as u32),
+        modseq: row.modseq.map(|modseq| modseq as u64),
+        message_id: row.message_id,
+        spf_result: row.spf_result,
+        dkim_result: row.dkim_result,
+        in_reply_to: row.in_reply_to,
+        references,
+        thread_id: row.thread_id,
+        size_bytes: row.size_bytes.unwrap_or_default(),
+        snippet: row.snippet.clone().unwrap_or_default(),
+    };
+    // Emails stored before previews were kept get one computed when read
+    if row.snippet.is_none() {
+        email.update_snippet();
+    }
+    email
+}
+
+/// A row of the forwarding_rules table
+#[derive(sqlx::FromRow)]
+struct ForwardingRuleRow {
+    id: String,
+    mailbox: String,
+    destination: String,
+    relay_host: String,
+    relay_port: i32,
+    relay_username: Option<String>,
+    relay_password: Option<String>,
+    relay_starttls: bool,
+    created_at: DateTime<Utc>,
+}
+
+fn forwarding_rule_from_row(row: ForwardingRuleRow) -> ForwardingRule {
+    ForwardingRule {
+        id: row.id,
+        mailbox: row.mailbox,
+        destination: row.destination,
+        relay_host: row.relay_host,
+        relay_port: row.relay_port as u16,
+        relay_username: row.relay_username,
+        relay_password: row.relay_password,
+        relay_starttls: row.relay_starttls,
+        created_at: row.created_at,
+    }
+}
+
+/// A row of the routing_rules table
+#[derive(sqlx::FromRow)]
+struct RoutingRuleRow {
+    id: String,
+    mailbox: String,
+    subject_pattern: String,
+    target_mailbox: String,
+    created_at: DateTime<Utc>,
+}
+
+fn routing_rule_from_row(row: RoutingRuleRow) -> RoutingRule {
+    RoutingRule {
+        id: row.id,
+        mailbox: row.mailbox,
+        subject_pattern: row.subject_pattern,
+        target_mailbox: row.target_mailbox,
+        created_at: row.created_at,
+    }
+}
+
+/// Row shape returned by `SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers`
+type WebhookRow = (
+    String,
+    String,
+    String,
+    String,
+    DateTime<Utc>,
+    bool,
+    Option<String>,
+    i64,
+    i64,
+    i64,
+    Option<String>,
+    Option<String>,
+    bool,
+    Option<String>,
+    String,
+    String,
+);
+
+/// Convert a webhooks table row into a Webhook
+fn webhook_from_row(row: WebhookRow) -> Webhook {
+    let (
+        id,
+        mailbox_address,
+        webhook_url,
+        events_json,
+        created_at,
+        enabled,
+        secret,
+        max_retries,
+        initial_backoff_secs,
+        max_backoff_secs,
+        from_filter,
+        subject_filter,
+        include_attachment_content,
+        payload_template,
+        format,
+        headers_json,
+    ) = row;
+
+    Webhook {
+        id,
+        mailbox_address,
+        webhook_url,
+        events: serde_json::from_str(&events_json).unwrap_or_default(),
+        created_at,
+        enabled,
+        secret,
+        max_retries: max_retries as u32,
+        initial_backoff_secs: initial_backoff_secs as u64,
+        max_backoff_secs: max_backoff_secs as u64,
+        from_filter,
+        subject_filter,
+        include_attachment_content,
+        payload_template,
+        format: WebhookFormat::from_str(&format).unwrap_or_default(),
+        headers: serde_json::from_str(&headers_json).unwrap_or_default(),
+    }
+}
+
+/// Columns of a `users` row: id, username, password hash, created_at, is_admin
+type UserRow = (String, String, String, DateTime<Utc>, bool);
+
+/// Convert a users table row into a User
+fn user_from_row((id, email, password_hash, created_at, is_admin): UserRow) -> User {
+    User {
+        id,
+        email,
+        password_hash,
+        created_at,
+        is_admin,
+    }
+}
+
+/// Create a mailbox's UID state unless it already exists
+async fn ensure_uid_state(conn: &mut PgConnection, address: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO mailbox_uids (address, uid_validity, uid_next)
+        VALUES ($1, $2, 1)
+        ON CONFLICT (address) DO NOTHING
+        "#,
+    )
+    .bind(address)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Reserve the next UID in a mailbox
+async fn allocate_uid(conn: &mut PgConnection, address: &str) -> Result<i64> {
+    ensure_uid_state(&mut *conn, address).await?;
+
+    let (uid,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE mailbox_uids SET uid_next = uid_next + 1 WHERE address = $1 RETURNING uid_next - 1",
+    )
+    .bind(address)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(uid)
+}
+
+/// Raise a mailbox's highest mod-sequence, returning the new value for the changed email
+async fn allocate_modseq(conn: &mut PgConnection, address: &str) -> Result<i64> {
+    ensure_uid_state(&mut *conn, address).await?;
+
+    let (modseq,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE mailbox_uids SET highest_modseq = highest_modseq + 1 WHERE address = $1 RETURNING highest_modseq",
+    )
+    .bind(address)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(modseq)
+}
+
+/// Give an email the next mod-sequence of its mailbox after changing it
+async fn bump_modseq(conn: &mut PgConnection, id: &str) -> Result<()> {
+    let address = sqlx::query_as::<_, (String,)>("SELECT to_address FROM emails WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some((address,)) = address {
+        let modseq = allocate_modseq(&mut *conn, &address).await?;
+        sqlx::query("UPDATE emails SET modseq = $1 WHERE id = $2")
+            .bind(modseq)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Insert a webhook row
+async fn insert_webhook(conn: &mut PgConnection, webhook: &Webhook) -> Result<()> {
+    // Serialize events to JSON
+    let events_json = serde_json::to_string(&webhook.events)?;
+    let headers_json = serde_json::to_string(&webhook.headers)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO webhooks (id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+        "#,
+    )
+    .bind(&webhook.id)
+    .bind(&webhook.mailbox_address)
+    .bind(&webhook.webhook_url)
+    .bind(&events_json)
+    .bind(webhook.created_at)
+    .bind(webhook.enabled)
+    .bind(&webhook.secret)
+    .bind(webhook.max_retries as i64)
+    .bind(webhook.initial_backoff_secs as i64)
+    .bind(webhook.max_backoff_secs as i64)
+    .bind(&webhook.from_filter)
+    .bind(&webhook.subject_filter)
+    .bind(webhook.include_attachment_content)
+    .bind(&webhook.payload_template)
+    .bind(webhook.format.as_str())
+    .bind(&headers_json)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Thread an email into the conversation of the message it replies to, when that message
+/// was already stored for the same recipient; otherwise use the root of its references
+async fn find_thread_id(conn: &mut PgConnection, email: &Email) -> Result<String> {
+    if let Some(parent) = email.in_reply_to.as_ref().or(email.references.last()) {
+        let thread_id = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT thread_id FROM emails WHERE to_address = $1 AND message_id = $2",
+        )
+        .bind(&email.to)
+        .bind(parent)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+        if let Some(thread_id) = thread_id {
+            return Ok(thread_id);
+        }
+    }
+    Ok(email.thread_root())
+}
+
+/// Assign UIDs, oldest first, to emails stored before UIDs were tracked
+async fn backfill_uids(pool: &PgPool) -> Result<()> {
+    let rows = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, to_address FROM emails WHERE uid IS NULL ORDER BY timestamp ASC, id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    for (id, address) in &rows {
+        let uid = allocate_uid(&mut tx, address).await?;
+        sqlx::query("UPDATE emails SET uid = $1 WHERE id = $2")
+            .bind(uid)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    info!("Assigned IMAP UIDs to {} existing emails", rows.len());
+    Ok(())
+}
+
+impl PostgresBackend {
+    /// Create a new PostgreSQL backend with the given database URL and the default pool size
+    #[cfg(test)]
+    pub async fn new(database_url: &str) -> Result<Self> {
+        Self::with_max_connections(
+            database_url,
+            crate::config::DEFAULT_DATABASE_MAX_CONNECTIONS,
+        )
+        .await
+    }
+
+    /// Create a new PostgreSQL backend whose pool holds at most `max_connections` connections
+    pub async fn with_max_connections(database_url: &str, max_connections: u32) -> Result<Self> {
+        info!(
+            "Connecting to PostgreSQL database (max {} connections)",
+            max_connections
+        );
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(database_url)
+            .await?;
+
+        // Run migrations
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS emails (
+                id TEXT PRIMARY KEY,
+                to_address TEXT NOT NULL,
+                from_address TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                body TEXT NOT NULL,
+                timestamp TIMESTAMPTZ NOT NULL,
+                raw TEXT,
+                attachments TEXT,
+                read BOOLEAN NOT NULL DEFAULT FALSE,
+                flags TEXT,
+                cc TEXT,
+                reply_to TEXT,
+                body_text TEXT,
+                body_html TEXT,
+                uid BIGINT,
+                deleted_at TIMESTAMPTZ,
+                message_id TEXT,
+                spf_result TEXT,
+                dkim_result TEXT,
+                in_reply_to TEXT,
+                email_references TEXT,
+                thread_id TEXT,
+                size_bytes BIGINT,
+                snippet TEXT,
+                from_name TEXT,
+                modseq BIGINT NOT NULL DEFAULT 1,
+                search_vector TSVECTOR GENERATED ALWAYS AS (
+                    to_tsvector(
+                        'english',
+                        to_address || ' ' || from_address || ' ' || subject || ' ' || body
+                    )
+                ) STORED
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Add columns introduced after the initial schema to existing databases
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS read BOOLEAN NOT NULL DEFAULT FALSE
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS flags TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS cc TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS reply_to TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS body_text TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS body_html TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS uid BIGINT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS message_id TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS spf_result TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS dkim_result TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS in_reply_to TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS email_references TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS thread_id TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS size_bytes BIGINT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS snippet TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS from_name TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE emails ADD COLUMN IF NOT EXISTS modseq BIGINT NOT NULL DEFAULT 1
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create mailbox_uids table holding each mailbox's IMAP UID state
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailbox_uids (
+                address TEXT PRIMARY KEY,
+                uid_validity BIGINT NOT NULL,
+                uid_next BIGINT NOT NULL,
+                highest_modseq BIGINT NOT NULL DEFAULT 1
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE mailbox_uids ADD COLUMN IF NOT EXISTS highest_modseq BIGINT NOT NULL DEFAULT 1
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // UIDs are unique within a mailbox
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_emails_uid ON emails(to_address, uid)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // A message is stored at most once per recipient
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_emails_message_id ON emails(to_address, message_id)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Conversations are looked up by their root Message-ID
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_emails_thread_id ON emails(thread_id)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Emails stored before threading start their own conversation
+        sqlx::query(
+            r#"
+            UPDATE emails SET thread_id = COALESCE(message_id, id) WHERE thread_id IS NULL
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Emails stored before sizes were recorded are measured from what was kept
+        sqlx::query(
+            r#"
+            UPDATE emails SET size_bytes = OCTET_LENGTH(COALESCE(raw, body)) WHERE size_bytes IS NULL
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        backfill_uids(&pool).await?;
+
+        // Create index on to_address for faster queries
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_to_address ON emails(to_address)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create index on timestamp for cleanup queries
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_timestamp ON emails(timestamp)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create GIN index for full-text search
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_emails_search ON emails USING GIN(search_vector)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create webhooks table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhooks (
+                id TEXT PRIMARY KEY,
+                mailbox_address TEXT NOT NULL,
+                webhook_url TEXT NOT NULL,
+                events TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                secret TEXT,
+                max_retries BIGINT NOT NULL DEFAULT 2,
+                initial_backoff_secs BIGINT NOT NULL DEFAULT 1,
+                max_backoff_secs BIGINT NOT NULL DEFAULT 60,
+                from_filter TEXT,
+                subject_filter TEXT,
+                include_attachment_content BOOLEAN NOT NULL DEFAULT FALSE,
+                payload_template TEXT,
+                format TEXT NOT NULL DEFAULT 'json',
+                headers TEXT NOT NULL DEFAULT '{}'
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS secret TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS max_retries BIGINT NOT NULL DEFAULT 2
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS initial_backoff_secs BIGINT NOT NULL DEFAULT 1
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS max_backoff_secs BIGINT NOT NULL DEFAULT 60
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS from_filter TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS subject_filter TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS include_attachment_content BOOLEAN NOT NULL DEFAULT FALSE
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS payload_template TEXT
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS format TEXT NOT NULL DEFAULT 'json'
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS headers TEXT NOT NULL DEFAULT '{}'
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create index on mailbox_address for faster webhook queries
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_webhooks_mailbox ON webhooks(mailbox_address)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create webhook_deliveries table for delivery history
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL,
+                timestamp TIMESTAMPTZ NOT NULL,
+                attempt INTEGER NOT NULL,
+                status_code INTEGER,
+                success BOOLEAN NOT NULL,
+                response_body TEXT
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries(webhook_id, timestamp)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create pending_webhook_deliveries table for deliveries retried later
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS pending_webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                next_attempt_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_pending_webhook_deliveries_due ON pending_webhook_deliveries(next_attempt_at)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create mailboxes table for password protection
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailboxes (
+                address TEXT PRIMARY KEY,
+                password_hash TEXT,
+                created_at TIMESTAMPTZ NOT NULL,
+                is_locked BOOLEAN NOT NULL DEFAULT FALSE
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create mailbox_owners table mapping mailbox local parts to users
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailbox_owners (
+                local_part TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create aliases table mapping a mailbox local part to destination local parts
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS aliases (
+                source TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (source, destination)
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create forwarding_rules table relaying a mailbox's email to an outside address
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS forwarding_rules (
+                id TEXT PRIMARY KEY,
+                mailbox TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                relay_host TEXT NOT NULL,
+                relay_port INTEGER NOT NULL,
+                relay_username TEXT,
+                relay_password TEXT,
+                relay_starttls BOOLEAN NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_forwarding_rules_mailbox ON forwarding_rules(mailbox)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create routing_rules table copying a mailbox's email by subject into another mailbox
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS routing_rules (
+                id TEXT PRIMARY KEY,
+                mailbox TEXT NOT NULL,
+                subject_pattern TEXT NOT NULL,
+                target_mailbox TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_routing_rules_mailbox ON routing_rules(mailbox)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create sender_rules table blocking or allowing inbound mail by sender
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sender_rules (
+                id TEXT PRIMARY KEY,
+                pattern TEXT NOT NULL,
+                action TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create audit_log table recording administrative changes
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id TEXT PRIMARY KEY,
+                actor TEXT,
+                action TEXT NOT NULL,
+                target TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create mailbox_retention table overriding the global retention per address
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailbox_retention (
+                address TEXT PRIMARY KEY,
+                retention_hours BIGINT NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create users table for authentication
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS users (
+                id TEXT PRIMARY KEY,
+                username TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE users ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Databases from before admin roles existed: the oldest user becomes the admin
+        sqlx::query(
+            r#"
+            UPDATE users SET is_admin = TRUE
+            WHERE id = (SELECT id FROM users ORDER BY created_at ASC LIMIT 1)
+              AND NOT EXISTS (SELECT 1 FROM users WHERE is_admin)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create refresh_tokens table (tokens are stored hashed)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS refresh_tokens (
+                token_hash TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create revoked_tokens table (access tokens revoked by logging out, by jti)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS revoked_tokens (
+                jti TEXT PRIMARY KEY,
+                expires_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create failed_logins table for login throttling
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS failed_logins (
+                email TEXT NOT NULL,
+                attempted_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_failed_logins_email ON failed_logins(email, attempted_at)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create rate_limits table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rate_limits (
+                mailbox_address TEXT PRIMARY KEY,
+                requests_per_hour BIGINT NOT NULL,
+                requests_per_day BIGINT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create rate_limit_requests table for tracking individual requests
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rate_limit_requests (
+                id BIGSERIAL PRIMARY KEY,
+                mailbox_address TEXT NOT NULL,
+                timestamp TIMESTAMPTZ NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create compound index for rate limit lookups
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_mailbox_timestamp ON rate_limit_requests(mailbox_address, timestamp)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create index on timestamp for efficient cleanup
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_timestamp ON rate_limit_requests(timestamp)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create sent_emails table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sent_emails (
+                id TEXT PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                to_address TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                body_text TEXT NOT NULL,
+                body_html TEXT,
+                timestamp TIMESTAMPTZ NOT NULL,
+                message_id TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_sent_emails_from ON sent_emails(from_address)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        info!("PostgreSQL database initialized successfully");
+
+        Ok(Self {
+            pool,
+            attachment_store: AttachmentStore::default(),
+        })
+    }
+
+    /// Keep attachment content in `store` instead of inline in the email rows
+    pub fn with_attachment_store(mut self, store: AttachmentStore) -> Self {
+        self.attachment_store = store;
+        self
+    }
+}
+
+#[async_trait]
+impl StorageBackend for PostgresBackend {
+    async fn store_email(&self, mut email: Email) -> Result<bool> {
+        self.attachment_store.offload(&mut email).await?;
+        email.update_snippet();
+
+        // Serialize attachments to JSON
+        let attachments_json = serde_json::to_string(&email.attachments)?;
+        let flags_json = serde_json::to_string(&email.flags)?;
+        let cc_json = serde_json::to_string(&email.cc)?;
+        let references_json = serde_json::to_string(&email.references)?;
+
+        let mut tx = self.pool.begin().await?;
+        let uid = allocate_uid(&mut tx, &email.to).await?;
+        let modseq = allocate_modseq(&mut tx, &email.to).await?;
+        let thread_id = match &email.thread_id {
+            Some(thread_id) => thread_id.clone(),
+            None => find_thread_id(&mut tx, &email).await?,
+        };
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO emails (id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25)
+            ON CONFLICT (to_address, message_id) DO NOTHING
+            "#,
+        )
+        .bind(&email.id)
+        .bind(&email.to)
+        .bind(&email.from)
+        .bind(&email.subject)
+        .bind(&email.body)
+        .bind(email.timestamp)
+        .bind(&email.raw)
+        .bind(&attachments_json)
+        .bind(email.read)
+        .bind(&flags_json)
+        .bind(&cc_json)
+        .bind(&email.reply_to)
+        .bind(&email.body_text)
+        .bind(&email.body_html)
+        .bind(uid)
+        .bind(&email.message_id)
+        .bind(&email.spf_result)
+        .bind(&email.dkim_result)
+        .bind(&email.in_reply_to)
+        .bind(&references_json)
+        .bind(&thread_id)
+        .bind(email.size_bytes)
+        .bind(&email.snippet)
+        .bind(&email.from_name)
+        .bind(modseq)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !inserted {
+            // Dropping the transaction rolls back the UID and modseq allocated above
+            drop(tx);
+            self.attachment_store.remove(&[email.id.clone()]).await;
+            debug!(
+                "Skipped duplicate email {} for address {} (Message-ID {})",
+                email.id,
+                email.to,
+                email.message_id.as_deref().unwrap_or_default()
+            );
+            return Ok(false);
+        }
+        tx.commit().await?;
+
+        info!(
+            "Stored email {} for address {} (UID {}) with {} attachments",
+            email.id,
+            email.to,
+            uid,
+            email.attachments.len()
+        );
+        Ok(true)
+    }
+
+    async fn get_emails_for_address(&self, address: &str) -> Result<Vec<Email>> {
+        let rows = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE to_address = $1 AND deleted_at IS NULL
+            ORDER BY timestamp DESC
+            "#,
+        )
+        .bind(address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(email_from_row).collect())
+    }
+
+    fn stream_all_emails(&self, since: Option<DateTime<Utc>>) -> BoxStream<'static, Result<Email>> {
+        let pool = self.pool.clone();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(EMAIL_STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut rows = sqlx::query_as::<_, EmailRow>(
+                r#"
+                SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+                FROM emails
+                WHERE deleted_at IS NULL AND ($1::TIMESTAMPTZ IS NULL OR timestamp >= $1)
+                ORDER BY timestamp, id
+                "#,
+            )
+            .bind(since)
+            .fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                let email = row.map(email_from_row).map_err(anyhow::Error::from);
+                // Stop reading once the reader has gone away or the cursor failed
+                if tx.send(email).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        email_channel_stream(rx)
+    }
+
+    async fn query_emails(&self, address: &str, filter: &EmailFilter) -> Result<(Vec<Email>, i64)> {
+        let rows = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE to_address = $1 AND deleted_at IS NULL
+            AND ($2::TEXT IS NULL OR POSITION(LOWER($2) IN LOWER(from_address)) > 0)
+            AND ($3::TEXT IS NULL OR POSITION(LOWER($3) IN LOWER(subject)) > 0)
+            AND ($4::TIMESTAMPTZ IS NULL OR timestamp >= $4)
+            AND ($5::TIMESTAMPTZ IS NULL OR timestamp <= $5)
+            ORDER BY timestamp DESC
+            LIMIT $6 OFFSET $7
+            "#,
+        )
+        .bind(address)
+        .bind(&filter.from)
+        .bind(&filter.subject_contains)
+        .bind(filter.since)
+        .bind(filter.until)
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM emails
+            WHERE to_address = $1 AND deleted_at IS NULL
+            AND ($2::TEXT IS NULL OR POSITION(LOWER($2) IN LOWER(from_address)) > 0)
+            AND ($3::TEXT IS NULL OR POSITION(LOWER($3) IN LOWER(subject)) > 0)
+            AND ($4::TIMESTAMPTZ IS NULL OR timestamp >= $4)
+            AND ($5::TIMESTAMPTZ IS NULL OR timestamp <= $5)
+            "#,
+        )
+        .bind(address)
+        .bind(&filter.from)
+        .bind(&filter.subject_contains)
+        .bind(filter.since)
+        .bind(filter.until)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((rows.into_iter().map(email_from_row).collect(), total.0))
+    }
+
+    async fn sender_summary(
+        &self,
+        address: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<SenderSummary>> {
+        let rows = sqlx::query_as::<_, (String, i64, DateTime<Utc>)>(
+            r#"
+            SELECT from_address, COUNT(*) AS count, MAX(timestamp) AS last_seen
+            FROM emails
+            WHERE to_address = $1 AND deleted_at IS NULL
+            GROUP BY from_address
+            ORDER BY count DESC, last_seen DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(address)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(from, count, last_seen)| SenderSummary {
+                from,
+                count,
+                last_seen,
+            })
+            .collect())
+    }
+
+    async fn count_emails_for_address(&self, address: &str) -> Result<i64> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM emails WHERE to_address = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(address)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0)
+    }
+
+    async fn total_bytes_for_address(&self, address: &str) -> Result<i64> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COALESCE(SUM(OCTET_LENGTH(COALESCE(raw, body))), 0)::BIGINT
+            FROM emails WHERE to_address = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(address)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0)
+    }
+
+    async fn list_mailboxes(&self, limit: i64, offset: i64) -> Result<Vec<MailboxSummary>> {
+        let rows = sqlx::query_as::<_, (String, i64, DateTime<Utc>)>(
+            r#"
+            SELECT to_address, COUNT(*) AS count, MAX(timestamp) AS latest
+            FROM emails
+            WHERE deleted_at IS NULL
+            GROUP BY to_address
+            ORDER BY latest DESC, to_address
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(address, count, latest)| MailboxSummary {
+                address,
+                count,
+                latest,
+            })
+            .collect())
+    }
+
+    async fn storage_stats(&self) -> Result<StorageStats> {
+        type StatsRow = (
+            i64,
+            i64,
+            i64,
+            i64,
+            Option<DateTime<Utc>>,
+            Option<DateTime<Utc>>,
+        );
+        let (total_emails, total_mailboxes, total_attachments, total_bytes, oldest, newest) =
+            sqlx::query_as::<_, StatsRow>(
+                r#"
+                SELECT COUNT(*),
+                       COUNT(DISTINCT to_address),
+                       COALESCE(SUM(json_array_length(attachments::json)), 0)::BIGINT,
+                       COALESCE(SUM(OCTET_LENGTH(COALESCE(raw, body))), 0)::BIGINT,
+                       MIN(timestamp),
+                       MAX(timestamp)
+                FROM emails WHERE deleted_at IS NULL
+                "#,
+            )
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(StorageStats {
+            total_emails,
+            total_mailboxes,
+            total_attachments,
+            total_bytes,
+            oldest_email: oldest,
+            newest_email: newest,
+        })
+    }
+
+    async fn get_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let row = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(email_from_row))
+    }
+
+    async fn email_exists_by_message_id(&self, to: &str, message_id: &str) -> Result<bool> {
+        let row = sqlx::query_as::<_, (bool,)>(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM emails WHERE to_address = $1 AND message_id = $2)
+            "#,
+        )
+        .bind(to)
+        .bind(message_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0)
+    }
+
+    async fn get_thread(&self, thread_id: &str) -> Result<Vec<Email>> {
+        let rows = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE thread_id = $1 AND deleted_at IS NULL
+            ORDER BY timestamp ASC, id ASC
+            "#,
+        )
+        .bind(thread_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(email_from_row).collect())
+    }
+
+    async fn mark_email_read(&self, id: &str, read: bool) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE emails SET read = $1 WHERE id = $2")
+            .bind(read)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        bump_modseq(&mut tx, id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn set_email_flags(&self, id: &str, flags: &[String]) -> Result<()> {
+        let flags_json = serde_json::to_string(flags)?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE emails SET flags = $1 WHERE id = $2")
+            .bind(&flags_json)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        bump_modseq(&mut tx, id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_email(&self, id: &str) -> Result<()> {
+        sqlx::query("UPDATE emails SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_old_emails_with_details(&self, hours: i64) -> Result<Vec<(String, String)>> {
+        let cutoff = Utc::now() - Duration::hours(hours);
+
+        // Move to the trash and return the IDs and addresses in a single statement
+        let deleted_emails = sqlx::query_as::<_, (String, String)>(
+            r#"
+            UPDATE emails
+            SET deleted_at = NOW()
+            WHERE timestamp < $1 AND deleted_at IS NULL
+                AND to_address NOT IN (SELECT address FROM mailbox_retention)
+            RETURNING id, to_address
+            "#,
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if !deleted_emails.is_empty() {
+            warn!(
+                "Moved {} old emails to the trash (older than {} hours)",
+                deleted_emails.len(),
+                hours
+            );
+        }
+
+        Ok(deleted_emails)
+    }
+
+    async fn delete_emails_for_address(
+        &self,
+        address: &str,
+        older_than_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let cutoff = older_than_hours.map(|hours| Utc::now() - Duration::hours(hours));
+
+        let deleted_emails = sqlx::query_as::<_, (String, String)>(
+            r#"
+            UPDATE emails
+            SET deleted_at = NOW()
+            WHERE to_address = $1 AND deleted_at IS NULL
+                AND ($2::TIMESTAMPTZ IS NULL OR timestamp < $2)
+            RETURNING id, to_address
+            "#,
+        )
+        .bind(address)
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deleted_emails)
+    }
+
+    async fn read_attachment_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.attachment_store.read(path).await
+    }
+
+    async fn get_attachments_by_hash(&self, hash: &str) -> Result<Vec<(String, Attachment)>> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            r#"
+            SELECT e.id, a.value::TEXT
+            FROM emails e, jsonb_array_elements(e.attachments::jsonb) a
+            WHERE e.deleted_at IS NULL AND a.value ->> 'content_hash' = $1
+            ORDER BY e.timestamp ASC, e.id ASC
+            "#,
+        )
+        .bind(hash)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, attachment)| Ok((id, serde_json::from_str(&attachment)?)))
+            .collect()
+    }
+
+    async fn get_deleted_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let row = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(email_from_row))
+    }
+
+    async fn restore_email(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE emails SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_emails(&self, hours: i64) -> Result<u64> {
+        let cutoff = Utc::now() - Duration::hours(hours);
+
+        let purged_ids: Vec<String> =
+            sqlx::query_scalar("DELETE FROM emails WHERE deleted_at < $1 RETURNING id")
+                .bind(cutoff)
+                .fetch_all(&self.pool)
+                .await?;
+        self.attachment_store.remove(&purged_ids).await;
+
+        let purged = purged_ids.len() as u64;
+        if purged > 0 {
+            warn!(
+                "Permanently deleted {} emails from the trash (deleted over {} hours ago)",
+                purged, hours
+            );
+        }
+
+        Ok(purged)
+    }
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        insert_webhook(&mut conn, &webhook).await?;
+
+        info!(
+            "Created webhook {} for mailbox {} with {} events",
+            webhook.id,
+            webhook.mailbox_address,
+            webhook.events.len()
+        );
+        Ok(())
+    }
+
+    async fn create_webhooks(&self, webhooks: Vec<Webhook>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for webhook in &webhooks {
+            insert_webhook(&mut tx, webhook).await?;
+        }
+        tx.commit().await?;
+
+        info!("Created {} webhooks in one batch", webhooks.len());
+        Ok(())
+    }
+
+    async fn get_webhooks_for_mailbox(&self, address: &str) -> Result<Vec<Webhook>> {
+        let rows = sqlx::query_as::<_, WebhookRow>(
+            r#"
+            SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers
+            FROM webhooks
+            WHERE mailbox_address = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let webhooks = rows.into_iter().map(webhook_from_row).collect();
+
+        Ok(webhooks)
+    }
+
+    async fn get_webhook_by_id(&self, id: &str) -> Result<Option<Webhook>> {
+        let row = sqlx::query_as::<_, WebhookRow>(
+            r#"
+            SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers
+            FROM webhooks
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(webhook_from_row))
+    }
+
+    async fn update_webhook(&self, webhook: Webhook) -> Result<()> {
+        // Serialize events to JSON
+        let events_json = serde_json::to_string(&webhook.events)?;
+        let headers_json = serde_json::to_string(&webhook.headers)?;
+
+        sqlx::query(
+            r#"
+            UPDATE webhooks
+            SET mailbox_address = $1, webhook_url = $2, events = $3, enabled = $4, secret = $5,
+                max_retries = $6, initial_backoff_secs = $7, max_backoff_secs = $8,
+                from_filter = $9, subject_filter = $10, include_attachment_content = $11,
+                payload_template = $12, format = $13, headers = $14
+            WHERE id = $15
+            "#,
+        )
+        .bind(&webhook.mailbox_address)
+        .bind(&webhook.webhook_url)
+        .bind(&events_json)
+        .bind(webhook.enabled)
+        .bind(&webhook.secret)
+        .bind(webhook.max_retries as i64)
+        .bind(webhook.initial_backoff_secs as i64)
+        .bind(webhook.max_backoff_secs as i64)
+        .bind(&webhook.from_filter)
+        .bind(&webhook.subject_filter)
+        .bind(webhook.include_attachment_content)
+        .bind(&webhook.payload_template)
+        .bind(webhook.format.as_str())
+        .bind(&headers_json)
+        .bind(&webhook.id)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Updated webhook {}", webhook.id);
+        Ok(())
+    }
+
+    async fn delete_webhook(&self, id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM webhooks
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM pending_webhook_deliveries WHERE webhook_id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        info!("Deleted webhook {}", id);
+        Ok(())
+    }
+
+    async fn get_active_webhooks_for_event(
+        &self,
+        address: &str,
+        event: WebhookEvent,
+    ) -> Result<Vec<Webhook>> {
+        let webhooks = self
+            .get_webhooks_for_mailbox(address)
+            .await?
+            .into_iter()
+            .filter(|webhook| webhook.enabled && webhook.events.contains(&event))
+            .collect();
+
+        Ok(webhooks)
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (id, webhook_id, timestamp, attempt, status_code, success, response_body)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(delivery.timestamp)
+        .bind(delivery.attempt as i32)
+        .bind(delivery.status_code.map(|code| code as i32))
+        .bind(delivery.success)
+        .bind(&delivery.response_body)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<
+            _,
+            (
+                String,
+                String,
+                DateTime<Utc>,
+                i32,
+                Option<i32>,
+                bool,
+                Option<String>,
+            ),
+        >(
+            r#"
+            SELECT id, webhook_id, timestamp, attempt, status_code, success, response_body
+            FROM webhook_deliveries
+            WHERE webhook_id = $1
+            ORDER BY timestamp DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let deliveries = rows
+            .into_iter()
+            .map(
+                |(id, webhook_id, timestamp, attempt, status_code, success, response_body)| {
+                    WebhookDelivery {
+                        id,
+                        webhook_id,
+                        timestamp,
+                        attempt: attempt as u32,
+                        status_code: status_code.map(|code| code as u16),
+                        success,
+                        response_body,
+                    }
+                },
+            )
+            .collect();
+
+        Ok(deliveries)
+    }
+
+    async fn enqueue_webhook_delivery(&self, delivery: PendingWebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO pending_webhook_deliveries (id, webhook_id, payload, attempts, created_at, next_attempt_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(serde_json::to_string(&delivery.payload)?)
+        .bind(delivery.attempts as i32)
+        .bind(delivery.created_at)
+        .bind(delivery.next_attempt_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>> {
+        let rows =
+            sqlx::query_as::<_, (String, String, String, i32, DateTime<Utc>, DateTime<Utc>)>(
+                r#"
+                SELECT id, webhook_id, payload, attempts, created_at, next_attempt_at
+                FROM pending_webhook_deliveries
+                WHERE next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $2
+                "#,
+            )
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(
+                |(id, webhook_id, payload, attempts, created_at, next_attempt_at)| {
+                    Ok(PendingWebhookDelivery {
+                        id,
+                        webhook_id,
+                        payload: serde_json::from_str(&payload)?,
+                        attempts: attempts as u32,
+                        created_at,
+                        next_attempt_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn reschedule_webhook_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE pending_webhook_deliveries
+            SET attempts = $1, next_attempt_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(attempts as i32)
+        .bind(next_attempt_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_pending_webhook_delivery(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM pending_webhook_deliveries WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_mailbox(&self, address: &str) -> Result<Option<Mailbox>> {
+        let row = sqlx::query_as::<_, (String, Option<String>, DateTime<Utc>, bool)>(
+            r#"
+            SELECT address, password_hash, created_at, is_locked
+            FROM mailboxes
+            WHERE address = $1
+            "#,
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(
+            row.map(|(address, password_hash, created_at, is_locked)| Mailbox {
+                address,
+                password_hash,
+                created_at,
+                is_locked,
+            }),
+        )
+    }
+
+    async fn set_mailbox_password(&self, address: &str, password_hash: String) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO mailboxes (address, password_hash, created_at, is_locked)
+            VALUES ($1, $2, $3, TRUE)
+            ON CONFLICT (address) DO UPDATE
+            SET password_hash = excluded.password_hash, is_locked = TRUE
+            "#,
+        )
+        .bind(address)
+        .bind(&password_hash)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        info!("Set password for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn is_mailbox_locked(&self, address: &str) -> Result<bool> {
+        let mailbox = self.get_mailbox(address).await?;
+        Ok(mailbox.map(|m| m.is_locked).unwrap_or(false))
+    }
+
+    async fn clear_mailbox_password(&self, address: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mailboxes
+            SET password_hash = NULL, is_locked = FALSE
+            WHERE address = $1
+            "#,
+        )
+        .bind(address)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Cleared password for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn verify_mailbox_password(&self, address: &str, password: &str) -> Result<bool> {
+        let mailbox = self.get_mailbox(address).await?;
+
+        match mailbox.and_then(|m| m.password_hash) {
+            Some(hash) => match bcrypt::verify(password, &hash) {
+                Ok(valid) => Ok(valid),
+                Err(e) => {
+                    error!("Password verification error for {}: {}", address, e);
+                    Ok(false)
+                }
+            },
+            // No password set - allow any password (unclaimed mailbox)
+            None => Ok(true),
+        }
+    }
+
+    async fn claim_mailbox_owner(&self, local_part: &str, user_id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO mailbox_owners (local_part, user_id, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (local_part) DO NOTHING
+            "#,
+        )
+        .bind(local_part)
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_mailbox_owner(&self, local_part: &str) -> Result<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT user_id FROM mailbox_owners WHERE local_part = $1",
+        )
+        .bind(local_part)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(user_id,)| user_id))
+    }
+
+    async fn create_alias(&self, source: &str, destinations: &[String]) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        for destination in destinations {
+            sqlx::query(
+                r#"
+                INSERT INTO aliases (source, destination, created_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (source, destination) DO NOTHING
+                "#,
+            )
+            .bind(source)
+            .bind(destination)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_aliases_for(&self, source: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT destination FROM aliases WHERE source = $1 ORDER BY created_at, destination",
+        )
+        .bind(source)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(destination,)| destination).collect())
+    }
+
+    async fn create_forwarding_rule(&self, rule: ForwardingRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO forwarding_rules (id, mailbox, destination, relay_host, relay_port, relay_username, relay_password, relay_starttls, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.mailbox)
+        .bind(&rule.destination)
+        .bind(&rule.relay_host)
+        .bind(rule.relay_port as i32)
+        .bind(&rule.relay_username)
+        .bind(&rule.relay_password)
+        .bind(rule.relay_starttls)
+        .bind(rule.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_forwarding_rules(&self, mailbox: &str) -> Result<Vec<ForwardingRule>> {
+        let rows = sqlx::query_as::<_, ForwardingRuleRow>(
+            r#"
+            SELECT id, mailbox, destination, relay_host, relay_port, relay_username, relay_password, relay_starttls, created_at
+            FROM forwarding_rules
+            WHERE mailbox = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(mailbox)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(forwarding_rule_from_row).collect())
+    }
+
+    async fn create_routing_rule(&self, rule: RoutingRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO routing_rules (id, mailbox, subject_pattern, target_mailbox, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.mailbox)
+        .bind(&rule.subject_pattern)
+        .bind(&rule.target_mailbox)
+        .bind(rule.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_routing_rules(&self, mailbox: &str) -> Result<Vec<RoutingRule>> {
+        let rows = sqlx::query_as::<_, RoutingRuleRow>(
+            r#"
+            SELECT id, mailbox, subject_pattern, target_mailbox, created_at
+            FROM routing_rules
+            WHERE mailbox = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(mailbox)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(routing_rule_from_row).collect())
+    }
+
+    async fn create_sender_rule(&self, rule: SenderRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sender_rules (id, pattern, action, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.pattern)
+        .bind(rule.action.as_str())
+        .bind(rule.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_sender_rules(&self) -> Result<Vec<SenderRule>> {
+        let rows = sqlx::query_as::<_, (String, String, String, DateTime<Utc>)>(
+            r#"
+            SELECT id, pattern, action, created_at
+            FROM sender_rules
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, pattern, action, created_at)| {
+                let Some(action) = SenderRuleAction::from_str(&action) else {
+                    warn!("Ignoring sender rule {} with unknown action {}", id, action);
+                    return None;
+                };
+                Some(SenderRule {
+                    id,
+                    pattern,
+                    action,
+                    created_at,
+                })
+            })
+            .collect())
+    }
+
+    async fn delete_sender_rule(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM sender_rules WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn audit(&self, entry: AuditEntry) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (id, actor, action, target, detail, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(&entry.id)
+        .bind(&entry.actor)
+        .bind(&entry.action)
+        .bind(&entry.target)
+        .bind(entry.detail.to_string())
+        .bind(entry.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_audit_log(&self, limit: i64) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query_as::<
+            _,
+            (
+                String,
+                Option<String>,
+                String,
+                String,
+                String,
+                DateTime<Utc>,
+            ),
+        >(
+            r#"
+                SELECT id, actor, action, target, detail, created_at
+                FROM audit_log
+                ORDER BY created_at DESC
+                LIMIT $1
+                "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, actor, action, target, detail, created_at)| AuditEntry {
+                    id,
+                    actor,
+                    action,
+                    target,
+                    detail: serde_json::from_str(&detail).unwrap_or_default(),
+                    created_at,
+                },
+            )
+            .collect())
+    }
+
+    async fn set_mailbox_retention(
+        &self,
+        address: &str,
+        retention_hours: Option<i64>,
+    ) -> Result<()> {
+        match retention_hours {
+            Some(hours) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO mailbox_retention (address, retention_hours, updated_at)
+                    VALUES ($1, $2, NOW())
+                    ON CONFLICT (address) DO UPDATE
+                    SET retention_hours = EXCLUDED.retention_hours, updated_at = EXCLUDED.updated_at
+                    "#,
+                )
+                .bind(address)
+                .bind(hours)
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM mailbox_retention WHERE address = $1")
+                    .bind(address)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_mailbox_retention(&self, address: &str) -> Result<Option<i64>> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            "SELECT retention_hours FROM mailbox_retention WHERE address = $1",
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(hours,)| hours))
+    }
+
+    async fn get_mailbox_retentions(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT address, retention_hours FROM mailbox_retention ORDER BY address",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn get_mailbox_uid_state(&self, address: &str) -> Result<MailboxUidState> {
+        let mut conn = self.pool.acquire().await?;
+        ensure_uid_state(&mut conn, address).await?;
+
+        let (uid_validity, uid_next, highest_modseq) = sqlx::query_as::<_, (i64, i64, i64)>(
+            "SELECT uid_validity, uid_next, highest_modseq FROM mailbox_uids WHERE address = $1",
+        )
+        .bind(address)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(MailboxUidState {
+            uid_validity: uid_validity as u32,
+            uid_next: uid_next as u32,
+            highest_modseq: highest_modseq as u64,
+        })
+    }
+
+    async fn create_user(&self, user: User) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, password_hash, created_at, is_admin)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(&user.id)
+        .bind(&user.email)
+        .bind(&user.password_hash)
+        .bind(user.created_at)
+        .bind(user.is_admin)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Created user {}", user.email);
+        Ok(())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            WHERE username = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+
+    async fn has_users(&self) -> Result<bool> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM users
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0 > 0)
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(user_from_row).collect())
+    }
+
+    async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(password_hash)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn store_refresh_token(&self, token: RefreshToken) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (token_hash, user_id, expires_at, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(&token.token_hash)
+        .bind(&token.user_id)
+        .bind(token.expires_at)
+        .bind(token.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
+        let row = sqlx::query_as::<_, (String, String, DateTime<Utc>, DateTime<Utc>)>(
+            r#"
+            SELECT token_hash, user_id, expires_at, created_at
+            FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(
+            |(token_hash, user_id, expires_at, created_at)| RefreshToken {
+                token_hash,
+                user_id,
+                expires_at,
+                created_at,
+            },
+        ))
+    }
+
+    async fn delete_refresh_token(&self, token_hash: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_refresh_tokens_for_user(&self, user_id: &str) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING")
+            .bind(jti)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        let row = sqlx::query_as::<_, (String,)>("SELECT jti FROM revoked_tokens WHERE jti = $1")
+            .bind(jti)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn prune_revoked_tokens(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn record_failed_login(&self, email: &str, attempted_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("INSERT INTO failed_logins (email, attempted_at) VALUES ($1, $2)")
+            .bind(email)
+            .bind(attempted_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_failed_logins_since(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        let rows = sqlx::query_as::<_, (DateTime<Utc>,)>(
+            r#"
+            SELECT attempted_at FROM failed_logins
+            WHERE email = $1 AND attempted_at >= $2
+            ORDER BY attempted_at ASC
+            "#,
+        )
+        .bind(email)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(attempted_at,)| attempted_at)
+            .collect())
+    }
+
+    async fn clear_failed_logins(&self, email: &str) -> Result<()> {
+        sqlx::query("DELETE FROM failed_logins WHERE email = $1")
+            .bind(email)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Rate limiting implementation
+
+    async fn create_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limits (mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(&rate_limit.mailbox_address)
+        .bind(rate_limit.requests_per_hour as i64)
+        .bind(rate_limit.requests_per_day as i64)
+        .bind(rate_limit.created_at)
+        .bind(rate_limit.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        info!(
+            "Created rate limit for mailbox {}",
+            rate_limit.mailbox_address
+        );
+        Ok(())
+    }
+
+    async fn get_rate_limit(&self, address: &str) -> Result<Option<crate::rate_limit::RateLimit>> {
+        let row = sqlx::query_as::<_, (String, i64, i64, DateTime<Utc>, DateTime<Utc>)>(
+            r#"
+            SELECT mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at
+            FROM rate_limits
+            WHERE mailbox_address = $1
+            "#,
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(
+            |(mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)| {
+                crate::rate_limit::RateLimit {
+                    mailbox_address,
+                    requests_per_hour: requests_per_hour as u32,
+                    requests_per_day: requests_per_day as u32,
+                    created_at,
+                    updated_at,
+                }
+            },
+        ))
+    }
+
+    async fn update_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE rate_limits
+            SET requests_per_hour = $1, requests_per_day = $2, updated_at = $3
+            WHERE mailbox_address = $4
+            "#,
+        )
+        .bind(rate_limit.requests_per_hour as i64)
+        .bind(rate_limit.requests_per_day as i64)
+        .bind(Utc::now())
+        .bind(&rate_limit.mailbox_address)
+        .execute(&self.pool)
+        .await?;
+
+        info!(
+            "Updated rate limit for mailbox {}",
+            rate_limit.mailbox_address
+        );
+        Ok(())
+    }
+
+    async fn delete_rate_limit(&self, address: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM rate_limits WHERE mailbox_address = $1
+            "#,
+        )
+        .bind(address)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Deleted rate limit for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn record_rate_limit_request(
+        &self,
+        request: crate::rate_limit::RateLimitRequest,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limit_requests (mailbox_address, timestamp)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(&request.mailbox_address)
+        .bind(request.timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn count_requests_since(&self, address: &str, since: DateTime<Utc>) -> Result<u32> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM rate_limit_requests
+            WHERE mailbox_address = $1 AND timestamp >= $2
+            "#,
+        )
+        .bind(address)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0 as u32)
+    }
+
+    async fn cleanup_old_rate_limit_requests(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM rate_limit_requests WHERE timestamp < $1
+            "#,
+        )
+        .bind(before)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn search_emails(&self, search: SearchQuery) -> Result<Vec<SearchResult>> {
+        let limit = search.limit.unwrap_or(50);
+
+        // Uses the generated search_vector column; websearch syntax supports quotes, OR and -term
+        let rows =
+            sqlx::query_as::<_, (String, String, String, String, DateTime<Utc>, String, f64)>(
+                r#"
+            SELECT
+                e.id,
+                e.to_address,
+                e.from_address,
+                e.subject,
+                e.timestamp,
+                ts_headline(
+                    'english',
+                    e.body,
+                    websearch_to_tsquery('english', $1),
+                    'StartSel=<mark>, StopSel=</mark>, MaxFragments=1, MaxWords=64'
+                ) AS snippet,
+                ts_rank(e.search_vector, websearch_to_tsquery('english', $1))::FLOAT8 AS rank
+            FROM emails e
+            WHERE e.search_vector @@ websearch_to_tsquery('english', $1)
+            AND ($2::TEXT IS NULL OR e.to_address = $2)
+            AND e.deleted_at IS NULL
+            ORDER BY rank DESC
+            LIMIT $3
+            "#,
+            )
+            .bind(&search.query)
+            .bind(&search.mailbox)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let results: Vec<SearchResult> = rows
+            .into_iter()
+            .map(
+                |(id, to, from, subject, timestamp, snippet, rank)| SearchResult {
+                    id,
+                    to,
+                    from,
+                    subject,
+                    snippet,
+                    timestamp: timestamp.to_rfc3339(),
+                    rank,
+                },
+            )
+            .collect();
+
+        info!(
+            "Search query '{}' returned {} results",
+            search.query,
+            results.len()
+        );
+
+        Ok(results)
+    }
+
+    async fn search_emails_for_address(&self, address: &str, query: &str) -> Result<Vec<Email>> {
+        if query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE search_vector @@ plainto_tsquery('english', $1)
+            AND to_address = $2
+            AND deleted_at IS NULL
+            ORDER BY timestamp DESC
+            "#,
+        )
+        .bind(query)
+        .bind(address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(email_from_row).collect())
+    }
+
+    async fn store_sent_email(&self, email: SentEmail) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sent_emails (id, from_address, to_address, subject, body_text, body_html, timestamp, message_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(&email.id)
+        .bind(&email.from)
+        .bind(&email.to)
+        .bind(&email.subject)
+        .bind(&email.body_text)
+        .bind(&email.body_html)
+        .bind(email.timestamp)
+        .bind(&email.message_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_sent_emails(&self, from_address: &str) -> Result<Vec<SentEmail>> {
+        let rows = sqlx::query_as::<
+            _,
+            (
+                String,
+                String,
+                String,
+                String,
+                String,
+                Option<String>,
+                DateTime<Utc>,
+                String,
+            ),
+        >(
+            r#"
+            SELECT id, from_address, to_address, subject, body_text, body_html, timestamp, message_id
+            FROM sent_emails
+            WHERE from_address = $1
+            ORDER BY timestamp DESC
+            "#,
+        )
+        .bind(from_address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let emails = rows
+            .into_iter()
+            .map(
+                |(id, from, to, subject, body_text, body_html, timestamp, message_id)| SentEmail {
+                    id,
+                    from,
+                    to,
+                    subject,
+                    body_text,
+                    body_html,
+                    timestamp,
+                    message_id,
+                },
+            )
+            .collect();
+
+        Ok(emails)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::{RateLimit, RateLimitRequest};
+    use crate::storage::models::Attachment;
+
+    /// Connect to the database in TEST_POSTGRES_URL, or skip the test if it is not set
+    async fn create_test_backend() -> Option<PostgresBackend> {
+        let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+        Some(PostgresBackend::new(&url).await.unwrap())
+    }
+
+    /// Unique address so tests can share a database without interfering
+    fn unique_address() -> String {
+        format!("{}@example.com", uuid::Uuid::new_v4())
+    }
+
+    #[tokio::test]
+    async fn test_postgres_email_roundtrip() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+        let address = unique_address();
+
+        let mut email = Email::new(
+            address.clone(),
+            "sender@example.com".to_string(),
+            "Postgres Subject".to_string(),
+            "Postgres body".to_string(),
+            Some("raw".to_string()),
+            vec![Attachment {
+                filename: "a.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                size: 5,
+                content: "aGVsbG8=".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
+            }],
+        );
+        email.cc = vec!["copy@example.com".to_string()];
+        email.reply_to = Some("replies@example.com".to_string());
+        email.body_text = Some("Postgres body".to_string());
+        email.message_id = Some("roundtrip@example.com".to_string());
+        email.spf_result = Some("softfail".to_string());
+        email.dkim_result = Some("pass".to_string());
+        backend.store_email(email.clone()).await.unwrap();
+        assert!(backend
+            .email_exists_by_message_id(&address, "roundtrip@example.com")
+            .await
+            .unwrap());
+        assert!(!backend
+            .email_exists_by_message_id(&address, "other@example.com")
+            .await
+            .unwrap());
+
+        let emails = backend.get_emails_for_address(&address).await.unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Postgres Subject");
+        assert_eq!(emails[0].uid, Some(1));
+        backend.ping().await.unwrap();
+        assert_eq!(emails[0].cc, email.cc);
+        assert_eq!(emails[0].reply_to, email.reply_to);
+        assert_eq!(emails[0].body_text, email.body_text);
+        assert_eq!(emails[0].body_html, None);
+        assert_eq!(emails[0].message_id, email.message_id);
+        assert_eq!(emails[0].spf_result, email.spf_result);
+        assert_eq!(emails[0].dkim_result, email.dkim_result);
+        assert_eq!(
+            emails[0].thread_id.as_deref(),
+            Some("roundtrip@example.com")
+        );
+        let thread = backend.get_thread("roundtrip@example.com").await.unwrap();
+        assert_eq!(thread.len(), 1);
+        assert_eq!(thread[0].id, email.id);
+        assert_eq!(emails[0].attachments.len(), 1);
+        assert_eq!(
+            emails[0].timestamp.timestamp_micros(),
+            email.timestamp.timestamp_micros()
+        );
+
+        assert_eq!(backend.count_emails_for_address(&address).await.unwrap(), 1);
+        assert_eq!(
+            backend.total_bytes_for_address(&address).await.unwrap(),
+            email.stored_size()
+        );
+        let filter = EmailFilter {
+            limit: 10,
+            offset: 1,
+            ..Default::default()
+        };
+        let (page, total) = backend.query_emails(&address, &filter).await.unwrap();
+        assert!(page.is_empty());
+        assert_eq!(total, 1);
+        let filter = EmailFilter {
+            from: Some("SENDER".to_string()),
+            since: Some(email.timestamp - chrono::Duration::minutes(1)),
+            until: Some(email.timestamp + chrono::Duration::minutes(1)),
+            limit: 10,
+            ..Default::default()
+        };
+        let (matches, total) = backend.query_emails(&address, &filter).await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(total, 1);
+        let filter = EmailFilter {
+            subject_contains: Some("missing".to_string()),
+            ..filter
+        };
+        let (matches, total) = backend.query_emails(&address, &filter).await.unwrap();
+        assert!(matches.is_empty());
+        assert_eq!(total, 0);
+
+        // Other tests share the database, so only lower bounds hold
+        let stats = backend.storage_stats().await.unwrap();
+        assert!(stats.total_emails >= 1);
+        assert!(stats.total_mailboxes >= 1);
+        assert!(stats.total_attachments >= 1);
+        assert!(stats.total_bytes >= email.stored_size());
+        assert!(stats.oldest_email.unwrap() <= email.timestamp);
+
+        let senders = backend.sender_summary(&address, None).await.unwrap();
+        assert_eq!(senders.len(), 1);
+        assert_eq!(senders[0].from, email.from);
+        assert_eq!(senders[0].count, 1);
+        assert_eq!(
+            senders[0].last_seen.timestamp_micros(),
+            email.timestamp.timestamp_micros()
+        );
+        assert!(backend
+            .sender_summary(&address, Some(0))
+            .await
+            .unwrap()
+            .is_empty());
+
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.raw, Some("raw".to_string()));
+        assert!(!fetched.read);
+
+        backend.mark_email_read(&email.id, true).await.unwrap();
+        assert!(
+            backend
+                .get_email_by_id(&email.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .read
+        );
+
+        backend
+            .set_email_flags(&email.id, &["\\Flagged".to_string()])
+            .await
+            .unwrap();
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.flags, vec!["\\Flagged".to_string()]);
+
+        let results = backend
+            .search_emails(SearchQuery::new("postgres".to_string()).with_mailbox(address.clone()))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, email.id);
+
+        let matches = backend
+            .search_emails_for_address(&address, "postgres body")
+            .await
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(backend
+            .search_emails_for_address(&address, "missing")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The email is too recent for an age-filtered bulk delete
+        assert!(backend
+            .delete_emails_for_address(&address, Some(24))
+            .await
+            .unwrap()
+            .is_empty());
+        backend.delete_email(&email.id).await.unwrap();
+        assert!(backend.get_email_by_id(&email.id).await.unwrap().is_none());
+        assert!(backend
+            .get_emails_for_address(&address)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(backend
+            .search_emails_for_address(&address, "postgres body")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Deleted emails can be restored until they are purged from the trash
+        assert!(backend
+            .get_deleted_email_by_id(&email.id)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(backend.restore_email(&email.id).await.unwrap());
+        assert_eq!(
+            backend
+                .get_emails_for_address(&address)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(!backend.restore_email(&email.id).await.unwrap());
+        backend.delete_email(&email.id).await.unwrap();
+        backend.purge_deleted_emails(24).await.unwrap();
+        assert!(backend.restore_email(&email.id).await.unwrap());
+        backend.delete_email(&email.id).await.unwrap();
+        sqlx::query("UPDATE emails SET deleted_at = NOW() - INTERVAL '25 hours' WHERE id = $1")
+            .bind(&email.id)
+            .execute(&backend.pool)
+            .await
+            .unwrap();
+        assert!(backend.purge_deleted_emails(24).await.unwrap() >= 1);
+        assert!(backend
+            .get_deleted_email_by_id(&email.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // UIDs keep counting after a delete
+        let next = Email::new(
+            address.clone(),
+            "sender@example.com".to_string(),
+            "Next".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(next.clone()).await.unwrap();
+        let fetched = backend.get_email_by_id(&next.id).await.unwrap().unwrap();
+        assert_eq!(fetched.uid, Some(2));
+        let state = backend.get_mailbox_uid_state(&address).await.unwrap();
+        assert_eq!(state.uid_next, 3);
+        assert!(state.uid_validity > 0);
+
+        // A mailbox's own retention is applied even without a global one
+        backend
+            .set_mailbox_retention(&address, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            backend.get_mailbox_retention(&address).await.unwrap(),
+            Some(1)
+        );
+        assert!(backend
+            .get_mailbox_retentions()
+            .await
+            .unwrap()
+            .contains(&(address.clone(), 1)));
+        let mut expired = Email::new(
+            address.clone(),
+            "sender@example.com".to_string(),
+            "Expired".to_string(),
+            "Expired body".to_string(),
+            None,
+            vec![],
+        );
+        expired.timestamp = Utc::now() - Duration::hours(2);
+        backend.store_email(expired.clone()).await.unwrap();
+        let deleted = backend.delete_expired_emails(None).await.unwrap();
+        assert!(deleted.contains(&(expired.id.clone(), address.clone())));
+        assert!(!deleted.iter().any(|(id, _)| *id == next.id));
+        backend.set_mailbox_retention(&address, None).await.unwrap();
+        assert_eq!(backend.get_mailbox_retention(&address).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_get_attachments_by_hash() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+        // Unique per run, since the database is shared between runs
+        let content = unique_address();
+        let hash = Attachment::hash_content(content.as_bytes());
+        let mut ids = Vec::new();
+        for to in [unique_address(), unique_address()] {
+            let email = Email::new(
+                to,
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![Attachment {
+                    filename: "shared.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: content.len(),
+                    content: String::new(),
+                    scan_result: None,
+                    path: None,
+                    dropped: false,
+                    content_hash: Some(hash.clone()),
+                }],
+            );
+            ids.push(email.id.clone());
+            backend.store_email(email).await.unwrap();
+        }
+        backend.delete_email(&ids[1]).await.unwrap();
+
+        let found = backend.get_attachments_by_hash(&hash).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, ids[0]);
+        assert_eq!(found[0].1.content_hash, Some(hash));
+    }
+
+    #[tokio::test]
+    async fn test_postgres_create_webhooks_is_all_or_nothing() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+        let (first_mailbox, second_mailbox) = (unique_address(), unique_address());
+        let webhook = |mailbox: &str| {
+            Webhook::new(
+                mailbox.to_string(),
+                "http://localhost/hook".to_string(),
+                vec![WebhookEvent::Arrival],
+            )
+        };
+
+        // The repeated ID fails the second insert, which undoes the first
+        let first = webhook(&first_mailbox);
+        let mut clash = webhook(&second_mailbox);
+        clash.id = first.id.clone();
+        assert!(backend.create_webhooks(vec![first, clash]).await.is_err());
+        assert!(backend
+            .get_webhooks_for_mailbox(&first_mailbox)
+            .await
+            .unwrap()
+            .is_empty());
+
+        backend
+            .create_webhooks(vec![webhook(&first_mailbox), webhook(&second_mailbox)])
+            .await
+            .unwrap();
+        for mailbox in [&first_mailbox, &second_mailbox] {
+            assert_eq!(
+                backend
+                    .get_webhooks_for_mailbox(mailbox)
+                    .await
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_postgres_webhooks_and_mailboxes() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+        let address = unique_address();
+
+        let mut webhook = Webhook::new(
+            address.clone(),
+            "http://localhost/hook".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.secret = Some("shh".to_string());
+        webhook.max_retries = 5;
+        webhook.max_backoff_secs = 120;
+        webhook.subject_filter = Some("invoice".to_string());
+        webhook.include_attachment_content = true;
+        webhook.payload_template = Some("{\"text\": \"{{ email.subject }}\"}".to_string());
+        webhook.format = WebhookFormat::Discord;
+        webhook
+            .headers
+            .insert("Authorization".to_string(), "Bearer abc".to_string());
+        backend.create_webhook(webhook.clone()).await.unwrap();
+        let stored = backend
+            .get_webhook_by_id(&webhook.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.secret, Some("shh".to_string()));
+        assert_eq!(stored.max_retries, 5);
+        assert_eq!(stored.initial_backoff_secs, 1);
+        assert_eq!(stored.max_backoff_secs, 120);
+        assert_eq!(stored.from_filter, None);
+        assert_eq!(stored.subject_filter, Some("invoice".to_string()));
+        assert!(stored.include_attachment_content);
+        assert_eq!(stored.payload_template, webhook.payload_template);
+        assert_eq!(stored.format, WebhookFormat::Discord);
+        assert_eq!(stored.headers, webhook.headers);
+
+        backend
+            .record_webhook_delivery(WebhookDelivery::new(
+                webhook.id.clone(),
+                1,
+                Some(502),
+                false,
+                Some("Bad Gateway".to_string()),
+            ))
+            .await
+            .unwrap();
+        let deliveries = backend
+            .get_webhook_deliveries(&webhook.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].status_code, Some(502));
+
+        let due_at = Utc::now();
+        let pending = PendingWebhookDelivery::new(
+            webhook.id.clone(),
+            serde_json::json!({ "event": "arrival" }),
+            1,
+            due_at,
+        );
+        backend
+            .enqueue_webhook_delivery(pending.clone())
+            .await
+            .unwrap();
+        let queued_for_webhook = |deliveries: Vec<PendingWebhookDelivery>| {
+            deliveries
+                .into_iter()
+                .filter(|d| d.webhook_id == webhook.id)
+                .collect::<Vec<_>>()
+        };
+        let due = queued_for_webhook(
+            backend
+                .get_due_webhook_deliveries(due_at, 100)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].payload, pending.payload);
+        let next = due_at + Duration::minutes(5);
+        backend
+            .reschedule_webhook_delivery(&pending.id, 2, next)
+            .await
+            .unwrap();
+        assert!(queued_for_webhook(
+            backend
+                .get_due_webhook_deliveries(due_at, 100)
+                .await
+                .unwrap()
+        )
+        .is_empty());
+        let due = queued_for_webhook(backend.get_due_webhook_deliveries(next, 100).await.unwrap());
+        assert_eq!(due[0].attempts, 2);
+        backend
+            .delete_pending_webhook_delivery(&pending.id)
+            .await
+            .unwrap();
+        assert!(
+            queued_for_webhook(backend.get_due_webhook_deliveries(next, 100).await.unwrap())
+                .is_empty()
+        );
+
+        let active = backend
+            .get_active_webhooks_for_event(&address, WebhookEvent::Arrival)
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 1);
+        let none = backend
+            .get_active_webhooks_for_event(&address, WebhookEvent::Deletion)
+            .await
+            .unwrap();
+        assert!(none.is_empty());
+
+        let attachments_webhook = Webhook::new(
+            address.clone(),
+            "https://example.com/attachments".to_string(),
+            vec![WebhookEvent::AttachmentReceived],
+        );
+        backend
+            .create_webhook(attachments_webhook.clone())
+            .await
+            .unwrap();
+        let active = backend
+            .get_active_webhooks_for_event(&address, WebhookEvent::AttachmentReceived)
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, attachments_webhook.id);
+        backend
+            .delete_webhook(&attachments_webhook.id)
+            .await
+            .unwrap();
+
+        backend.delete_webhook(&webhook.id).await.unwrap();
+        assert!(backend
+            .get_webhook_by_id(&webhook.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        let hash = bcrypt::hash("secret", 4).unwrap();
+        backend.set_mailbox_password(&address, hash).await.unwrap();
+        assert!(backend.is_mailbox_locked(&address).await.unwrap());
+        assert!(backend
+            .verify_mailbox_password(&address, "secret")
+            .await
+            .unwrap());
+        backend.clear_mailbox_password(&address).await.unwrap();
+        assert!(!backend.is_mailbox_locked(&address).await.unwrap());
+
+        assert!(backend.get_mailbox_owner(&address).await.unwrap().is_none());
+        assert!(backend
+            .claim_mailbox_owner(&address, "user-1")
+            .await
+            .unwrap());
+        assert!(!backend
+            .claim_mailbox_owner(&address, "user-2")
+            .await
+            .unwrap());
+        assert_eq!(
+            backend.get_mailbox_owner(&address).await.unwrap(),
+            Some("user-1".to_string())
+        );
+
+        let alias = format!("{}-alias", address);
+        backend
+            .create_alias(&alias, &[address.clone(), "other".to_string()])
+            .await
+            .unwrap();
+        // Creating the same alias again is a no-op
+        backend
+            .create_alias(&alias, std::slice::from_ref(&address))
+            .await
+            .unwrap();
+        let mut destinations = backend.get_aliases_for(&alias).await.unwrap();
+        destinations.sort();
+        assert_eq!(destinations, vec![address.clone(), "other".to_string()]);
+        assert!(backend.get_aliases_for(&address).await.unwrap().is_empty());
+
+        let mut rule = ForwardingRule::new(
+            address.clone(),
+            "me@real.example".to_string(),
+            "smtp.real.example".to_string(),
+            2525,
+        );
+        rule.relay_password = Some("relay-pass".to_string());
+        backend.create_forwarding_rule(rule.clone()).await.unwrap();
+        let rules = backend.get_forwarding_rules(&address).await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, rule.id);
+        assert_eq!(rules[0].relay_port, 2525);
+        assert_eq!(rules[0].relay_password, rule.relay_password);
+        assert!(rules[0].relay_starttls);
+
+        let routing = RoutingRule::new(
+            address.clone(),
+            "[alert]*".to_string(),
+            "triage".to_string(),
+        );
+        backend.create_routing_rule(routing.clone()).await.unwrap();
+        let rules = backend.get_routing_rules(&address).await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, routing.id);
+        assert_eq!(rules[0].subject_pattern, "[alert]*");
+        assert_eq!(rules[0].target_mailbox, "triage");
+    }
+
+    #[tokio::test]
+    async fn test_postgres_users_and_rate_limits() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+        let address = unique_address();
+
+        let user = User::new(address.clone(), "hash".to_string());
+        backend.create_user(user.clone()).await.unwrap();
+        assert!(backend.has_users().await.unwrap());
+        let fetched = backend.get_user_by_email(&address).await.unwrap().unwrap();
+        assert_eq!(fetched.id, user.id);
+        assert!(!fetched.is_admin);
+        assert!(backend
+            .list_users()
+            .await
+            .unwrap()
+            .iter()
+            .any(|listed| listed.id == user.id));
+
+        let token = RefreshToken {
+            token_hash: format!("hash-{}", address),
+            user_id: user.id.clone(),
+            expires_at: Utc::now() + Duration::days(30),
+            created_at: Utc::now(),
+        };
+        backend.store_refresh_token(token.clone()).await.unwrap();
+        let fetched = backend
+            .get_refresh_token(&token.token_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.user_id, user.id);
+        assert!(backend
+            .delete_refresh_token(&token.token_hash)
+            .await
+            .unwrap());
+        assert!(backend
+            .get_refresh_token(&token.token_hash)
+            .await
+            .unwrap()
+            .is_none());
+
+        backend.store_refresh_token(token.clone()).await.unwrap();
+        assert_eq!(
+            backend
+                .delete_refresh_tokens_for_user(&user.id)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(backend
+            .get_refresh_token(&token.token_hash)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(backend
+            .update_user_password(&user.id, "new-hash")
+            .await
+            .unwrap());
+        let fetched = backend.get_user_by_id(&user.id).await.unwrap().unwrap();
+        assert_eq!(fetched.password_hash, "new-hash");
+        assert!(!backend
+            .update_user_password("missing-user", "new-hash")
+            .await
+            .unwrap());
+
+        let since = Utc::now() - Duration::minutes(5);
+        backend
+            .record_failed_login(&address, Utc::now() - Duration::minutes(10))
+            .await
+            .unwrap();
+        backend
+            .record_failed_login(&address, Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(
+            backend
+                .get_failed_logins_since(&address, since)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        backend.clear_failed_logins(&address).await.unwrap();
+        assert!(backend
+            .get_failed_logins_since(&address, since)
+            .await
+            .unwrap()
+            .is_empty());
+
+        backend
+            .create_rate_limit(RateLimit::with_limits(address.clone(), 10, 20))
+            .await
+            .unwrap();
+        let limit = backend.get_rate_limit(&address).await.unwrap().unwrap();
+        assert_eq!(limit.requests_per_hour, 10);
+        assert_eq!(limit.requests_per_day, 20);
+
+        let since = Utc::now() - Duration::minutes(1);
+        backend
+            .record_rate_limit_request(RateLimitRequest::new(address.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            backend.count_requests_since(&address, since).await.unwrap(),
+            1
+        );
+
+        backend.delete_rate_limit(&address).await.unwrap();
+        assert!(backend.get_rate_limit(&address).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_postgres_revoked_tokens() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+
+        let now = Utc::now();
+        let expired = uuid::Uuid::new_v4().to_string();
+        let current = uuid::Uuid::new_v4().to_string();
+        backend
+            .revoke_token(&expired, now - Duration::minutes(1))
+            .await
+            .unwrap();
+        backend
+            .revoke_token(&current, now + Duration::hours(1))
+            .await
+            .unwrap();
+        backend
+            .revoke_token(&current, now + Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(backend.is_token_revoked(&expired).await.unwrap());
+
+        assert!(backend.prune_revoked_tokens(now).await.unwrap() >= 1);
+        assert!(!backend.is_token_revoked(&expired).await.unwrap());
+        assert!(backend.is_token_revoked(&current).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_postgres_audit_log() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+
+        let target = uuid::Uuid::new_v4().to_string();
+        let entry = AuditEntry::new(
+            Some("admin-id".to_string()),
+            "sender_rule.create",
+            target.clone(),
+            serde_json::json!({"pattern": "*@spam.example", "action": "block"}),
+        );
+        backend.audit(entry.clone()).await.unwrap();
+
+        let stored = backend
+            .get_audit_log(500)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|e| e.target == target)
+            .unwrap();
+        assert_eq!(stored.id, entry.id);
+        assert_eq!(stored.actor.as_deref(), Some("admin-id"));
+        assert_eq!(stored.action, "sender_rule.create");
+        assert_eq!(stored.detail, entry.detail);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_stream_all_emails() {
+        use futures::TryStreamExt;
+
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+
+        // Far in the future so other tests' emails fall before `since`
+        let since = Utc::now() + Duration::days(3650);
+        let address = format!("{}@example.com", uuid::Uuid::new_v4());
+        let mut ids = Vec::new();
+        for days in [2, 1] {
+            let mut email = Email::new(
+                address.clone(),
+                "sender@example.com".to_string(),
+                "Backup".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = since + Duration::days(days);
+            ids.push(email.id.clone());
+            backend.store_email(email).await.unwrap();
+        }
+
+        let exported: Vec<String> = backend
+            .stream_all_emails(Some(since))
+            .try_collect::<Vec<Email>>()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|email| email.to == address)
+            .map(|email| email.id)
+            .collect();
+        assert_eq!(exported, vec![ids[1].clone(), ids[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_sender_rules() {
+        let Some(backend) = create_test_backend().await else {
+            return;
+        };
+
+        let rule = SenderRule::new(
+            format!("*@{}.example", uuid::Uuid::new_v4()),
+            SenderRuleAction::Block,
+        );
+        backend.create_sender_rule(rule.clone()).await.unwrap();
+        let stored = backend
+            .list_sender_rules()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|r| r.id == rule.id)
+            .unwrap();
+        assert_eq!(stored.pattern, rule.pattern);
+        assert_eq!(stored.action, SenderRuleAction::Block);
+
+        assert!(backend.delete_sender_rule(&rule.id).await.unwrap());
+        assert!(!backend.delete_sender_rule(&rule.id).await.unwrap());
+        assert!(!backend
+            .list_sender_rules()
+            .await
+            .unwrap()
+            .iter()
+            .any(|r| r.id == rule.id));
+    }
+}
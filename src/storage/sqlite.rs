@@ -1,25 +1,449 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use futures::stream::{BoxStream, StreamExt};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
 use std::str::FromStr;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use super::{
-    fts::{SearchQuery, SearchResult},
-    models::{Email, Mailbox, SentEmail, User, Webhook, WebhookEvent},
-    StorageBackend,
+    attachments::AttachmentStore,
+    email_channel_stream,
+    fts::{to_fts5_prefix_query, SearchQuery, SearchResult},
+    models::{
+        Attachment, AuditEntry, Email, EmailFilter, ForwardingRule, Mailbox, MailboxSummary,
+        MailboxUidState, PendingWebhookDelivery, RefreshToken, RoutingRule, SenderRule,
+        SenderRuleAction, SenderSummary, SentEmail, StorageStats, User, Webhook, WebhookDelivery,
+        WebhookEvent, WebhookFormat,
+    },
+    StorageBackend, EMAIL_STREAM_BUFFER,
 };
 
 /// SQLite implementation of StorageBackend
 pub struct SqliteBackend {
     pool: SqlitePool,
+    attachment_store: AttachmentStore,
+}
+
+/// A row of the emails table, as selected by the email queries
+#[derive(sqlx::FromRow)]
+struct EmailRow {
+    id: String,
+    to_address: String,
+    from_address: String,
+    subject: String,
+    body: String,
+    timestamp: String,
+    raw: Option<String>,
+    attachments: Option<String>,
+    read: bool,
+    flags: Option<String>,
+    cc: Option<String>,
+    reply_to: Option<String>,
+    body_text: Option<String>,
+    body_html: Option<String>,
+    uid: Option<i64>,
+    message_id: Option<String>,
+    spf_result: Option<String>,
+    dkim_result: Option<String>,
+    in_reply_to: Option<String>,
+    email_references: Option<String>,
+    thread_id: Option<String>,
+    size_bytes: Option<i64>,
+    snippet: Option<String>,
+    from_name: Option<String>,
+    modseq: Option<i64>,
+}
+
+/// Convert an emails table row into an Email
+fn email_from_row(row: EmailRow) -> Email {
+    let timestamp = DateTime::parse_from_rfc3339(&row.timestamp)
+        .unwrap_or_else(|_| Utc::now().into())
+        .with_timezone(&Utc);
+
+    // Deserialize attachments from JSON
+    let attachments = row
+        .attachments
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let flags = row
+        .flags
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let cc = row
+        .cc
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let references = row
+        .email_references
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+
+    let mut email = Email {
+        id: row.id,
+        to: row.to_address,
+        from: row.from_address,
+        from_name: row.from_name,
+        subject: row.subject,
+        body: row.body,
+        body_text: row.body_text,
+        body_html: row.body_html,
+        timestamp,
+        raw: row.raw,
+        attachments,
+        read: row.read,
+        flags,
+        cc,
+        reply_to: row.reply_to,
+        uid: row.uid.map(|uid| uid as u32),
+        modseq: row.modseq.map(|modseq| modseq as u64),
+        message_id: row.message_id,
+        spf_result: row.spf_result,
+        dkim_result: row.dkim_result,
+        in_reply_to: row.in_reply_to,
+        references,
+        thread_id: row.thread_id,
+        size_bytes: row.size_bytes.unwrap_or_default(),
+        snippet: row.snippet.clone().unwrap_or_default(),
+    };
+    // Emails stored before previews were kept get one computed when read
+    if row.snippet.is_none() {
+        email.update_snippet();
+    }
+    email
+}
+
+/// A row of the forwarding_rules table
+#[derive(sqlx::FromRow)]
+struct ForwardingRuleRow {
+    id: String,
+    mailbox: String,
+    destination: String,
+    relay_host: String,
+    relay_port: i32,
+    relay_username: Option<String>,
+    relay_password: Option<String>,
+    relay_starttls: bool,
+    created_at: String,
+}
+
+fn forwarding_rule_from_row(row: ForwardingRuleRow) -> ForwardingRule {
+    ForwardingRule {
+        id: row.id,
+        mailbox: row.mailbox,
+        destination: row.destination,
+        relay_host: row.relay_host,
+        relay_port: row.relay_port as u16,
+        relay_username: row.relay_username,
+        relay_password: row.relay_password,
+        relay_starttls: row.relay_starttls,
+        created_at: DateTime::parse_from_rfc3339(&row.created_at)
+            .unwrap_or_else(|_| Utc::now().into())
+            .with_timezone(&Utc),
+    }
+}
+
+/// A row of the routing_rules table
+#[derive(sqlx::FromRow)]
+struct RoutingRuleRow {
+    id: String,
+    mailbox: String,
+    subject_pattern: String,
+    target_mailbox: String,
+    created_at: String,
+}
+
+fn routing_rule_from_row(row: RoutingRuleRow) -> RoutingRule {
+    RoutingRule {
+        id: row.id,
+        mailbox: row.mailbox,
+        subject_pattern: row.subject_pattern,
+        target_mailbox: row.target_mailbox,
+        created_at: DateTime::parse_from_rfc3339(&row.created_at)
+            .unwrap_or_else(|_| Utc::now().into())
+            .with_timezone(&Utc),
+    }
+}
+
+/// Row shape returned by `SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers`
+type WebhookRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    bool,
+    Option<String>,
+    i64,
+    i64,
+    i64,
+    Option<String>,
+    Option<String>,
+    bool,
+    Option<String>,
+    String,
+    String,
+);
+
+/// Convert a webhooks table row into a Webhook
+fn webhook_from_row(row: WebhookRow) -> Webhook {
+    let (
+        id,
+        mailbox_address,
+        webhook_url,
+        events_json,
+        created_at,
+        enabled,
+        secret,
+        max_retries,
+        initial_backoff_secs,
+        max_backoff_secs,
+        from_filter,
+        subject_filter,
+        include_attachment_content,
+        payload_template,
+        format,
+        headers_json,
+    ) = row;
+
+    let created_at = DateTime::parse_from_rfc3339(&created_at)
+        .unwrap_or_else(|_| Utc::now().into())
+        .with_timezone(&Utc);
+
+    // Deserialize events from JSON
+    let events = serde_json::from_str(&events_json).unwrap_or_default();
+
+    Webhook {
+        id,
+        mailbox_address,
+        webhook_url,
+        events,
+        created_at,
+        enabled,
+        secret,
+        max_retries: max_retries as u32,
+        initial_backoff_secs: initial_backoff_secs as u64,
+        max_backoff_secs: max_backoff_secs as u64,
+        from_filter,
+        subject_filter,
+        include_attachment_content,
+        payload_template,
+        format: WebhookFormat::from_str(&format).unwrap_or_default(),
+        headers: serde_json::from_str(&headers_json).unwrap_or_default(),
+    }
+}
+
+/// Columns of a `users` row: id, username, password hash, created_at, is_admin
+type UserRow = (String, String, String, String, bool);
+
+/// Convert a users table row into a User
+fn user_from_row((id, email, password_hash, created_at, is_admin): UserRow) -> User {
+    let created_at = DateTime::parse_from_rfc3339(&created_at)
+        .unwrap_or_else(|_| Utc::now().into())
+        .with_timezone(&Utc);
+
+    User {
+        id,
+        email,
+        password_hash,
+        created_at,
+        is_admin,
+    }
+}
+
+/// Add a column to an existing table unless it is already present
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let existing = sqlx::query_as::<_, (String,)>(
+        r#"
+        SELECT name FROM pragma_table_info(?) WHERE name = ?
+        "#,
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+
+    if existing.is_none() {
+        info!("Adding column {}.{}", table, column);
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Create a mailbox's UID state unless it already exists
+async fn ensure_uid_state(conn: &mut SqliteConnection, address: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO mailbox_uids (address, uid_validity, uid_next)
+        VALUES (?, ?, 1)
+        ON CONFLICT (address) DO NOTHING
+        "#,
+    )
+    .bind(address)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Reserve the next UID in a mailbox
+async fn allocate_uid(conn: &mut SqliteConnection, address: &str) -> Result<i64> {
+    ensure_uid_state(&mut *conn, address).await?;
+
+    let (uid,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE mailbox_uids SET uid_next = uid_next + 1 WHERE address = ? RETURNING uid_next - 1",
+    )
+    .bind(address)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(uid)
+}
+
+/// Raise a mailbox's highest mod-sequence, returning the new value for the changed email
+async fn allocate_modseq(conn: &mut SqliteConnection, address: &str) -> Result<i64> {
+    ensure_uid_state(&mut *conn, address).await?;
+
+    let (modseq,) = sqlx::query_as::<_, (i64,)>(
+        "UPDATE mailbox_uids SET highest_modseq = highest_modseq + 1 WHERE address = ? RETURNING highest_modseq",
+    )
+    .bind(address)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(modseq)
+}
+
+/// Give an email the next mod-sequence of its mailbox after changing it
+async fn bump_modseq(conn: &mut SqliteConnection, id: &str) -> Result<()> {
+    let address = sqlx::query_as::<_, (String,)>("SELECT to_address FROM emails WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some((address,)) = address {
+        let modseq = allocate_modseq(&mut *conn, &address).await?;
+        sqlx::query("UPDATE emails SET modseq = ? WHERE id = ?")
+            .bind(modseq)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Insert a webhook row
+async fn insert_webhook(conn: &mut SqliteConnection, webhook: &Webhook) -> Result<()> {
+    // Serialize events to JSON
+    let events_json = serde_json::to_string(&webhook.events)?;
+    let headers_json = serde_json::to_string(&webhook.headers)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO webhooks (id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&webhook.id)
+    .bind(&webhook.mailbox_address)
+    .bind(&webhook.webhook_url)
+    .bind(&events_json)
+    .bind(webhook.created_at.to_rfc3339())
+    .bind(webhook.enabled)
+    .bind(&webhook.secret)
+    .bind(webhook.max_retries as i64)
+    .bind(webhook.initial_backoff_secs as i64)
+    .bind(webhook.max_backoff_secs as i64)
+    .bind(&webhook.from_filter)
+    .bind(&webhook.subject_filter)
+    .bind(webhook.include_attachment_content)
+    .bind(&webhook.payload_template)
+    .bind(webhook.format.as_str())
+    .bind(&headers_json)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Thread an email into the conversation of the message it replies to, when that message
+/// was already stored for the same recipient; otherwise use the root of its references
+async fn find_thread_id(conn: &mut SqliteConnection, email: &Email) -> Result<String> {
+    if let Some(parent) = email.in_reply_to.as_ref().or(email.references.last()) {
+        let thread_id = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT thread_id FROM emails WHERE to_address = ? AND message_id = ?",
+        )
+        .bind(&email.to)
+        .bind(parent)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+        if let Some(thread_id) = thread_id {
+            return Ok(thread_id);
+        }
+    }
+    Ok(email.thread_root())
+}
+
+/// Assign UIDs, oldest first, to emails stored before UIDs were tracked
+async fn backfill_uids(pool: &SqlitePool) -> Result<()> {
+    let rows = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, to_address FROM emails WHERE uid IS NULL ORDER BY timestamp ASC, id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    for (id, address) in &rows {
+        let uid = allocate_uid(&mut tx, address).await?;
+        sqlx::query("UPDATE emails SET uid = ? WHERE id = ?")
+            .bind(uid)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    info!("Assigned IMAP UIDs to {} existing emails", rows.len());
+    Ok(())
 }
 
 impl SqliteBackend {
-    /// Create a new SQLite backend with the given database URL
+    /// Create a new SQLite backend with the given database URL and the default pool size
+    #[cfg(test)]
     pub async fn new(database_url: &str) -> Result<Self> {
-        info!("Connecting to SQLite database: {}", database_url);
+        Self::with_max_connections(
+            database_url,
+            crate::config::DEFAULT_DATABASE_MAX_CONNECTIONS,
+        )
+        .await
+    }
+
+    /// Create a new SQLite backend whose pool holds at most `max_connections` connections
+    ///
+    /// Connections use WAL journaling and wait on a locked database rather than failing,
+    /// so readers and a writer can work at the same time.
+    pub async fn with_max_connections(database_url: &str, max_connections: u32) -> Result<Self> {
+        info!(
+            "Connecting to SQLite database: {} (max {} connections)",
+            database_url, max_connections
+        );
 
         // Parse connection options and enable create_if_missing
         let connect_options = SqliteConnectOptions::from_str(database_url)?
@@ -28,7 +452,7 @@ impl SqliteBackend {
             .busy_timeout(std::time::Duration::from_secs(30));
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(5)
+            .max_connections(max_connections)
             .connect_with(connect_options)
             .await?;
 
@@ -43,12 +467,118 @@ impl SqliteBackend {
                 body TEXT NOT NULL,
                 timestamp TEXT NOT NULL,
                 raw TEXT,
-                attachments TEXT
+                attachments TEXT,
+                read INTEGER NOT NULL DEFAULT 0,
+                flags TEXT,
+                cc TEXT,
+                reply_to TEXT,
+                body_text TEXT,
+                body_html TEXT,
+                uid INTEGER,
+                deleted_at TEXT,
+                message_id TEXT,
+                spf_result TEXT,
+                dkim_result TEXT,
+                in_reply_to TEXT,
+                email_references TEXT,
+                thread_id TEXT,
+                size_bytes INTEGER,
+                snippet TEXT,
+                from_name TEXT,
+                modseq INTEGER NOT NULL DEFAULT 1
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Add columns introduced after the initial schema to existing databases
+        add_column_if_missing(&pool, "emails", "read", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "emails", "flags", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "cc", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "reply_to", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "body_text", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "body_html", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "uid", "INTEGER").await?;
+        add_column_if_missing(&pool, "emails", "deleted_at", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "message_id", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "spf_result", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "dkim_result", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "in_reply_to", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "email_references", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "thread_id", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "size_bytes", "INTEGER").await?;
+        add_column_if_missing(&pool, "emails", "snippet", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "from_name", "TEXT").await?;
+        add_column_if_missing(&pool, "emails", "modseq", "INTEGER NOT NULL DEFAULT 1").await?;
+
+        // Create mailbox_uids table holding each mailbox's IMAP UID state
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailbox_uids (
+                address TEXT PRIMARY KEY,
+                uid_validity INTEGER NOT NULL,
+                uid_next INTEGER NOT NULL,
+                highest_modseq INTEGER NOT NULL DEFAULT 1
             )
             "#,
         )
         .execute(&pool)
         .await?;
+        add_column_if_missing(
+            &pool,
+            "mailbox_uids",
+            "highest_modseq",
+            "INTEGER NOT NULL DEFAULT 1",
+        )
+        .await?;
+
+        // UIDs are unique within a mailbox
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_emails_uid ON emails(to_address, uid)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // A message is stored at most once per recipient
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_emails_message_id ON emails(to_address, message_id)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Conversations are looked up by their root Message-ID
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_emails_thread_id ON emails(thread_id)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Emails stored before threading start their own conversation
+        sqlx::query(
+            r#"
+            UPDATE emails SET thread_id = COALESCE(message_id, id) WHERE thread_id IS NULL
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Emails stored before sizes were recorded are measured from what was kept
+        sqlx::query(
+            r#"
+            UPDATE emails SET size_bytes = LENGTH(CAST(COALESCE(raw, body) AS BLOB)) WHERE size_bytes IS NULL
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        backfill_uids(&pool).await?;
 
         // Create index on to_address for faster queries
         sqlx::query(
@@ -77,13 +607,58 @@ impl SqliteBackend {
                 webhook_url TEXT NOT NULL,
                 events TEXT NOT NULL,
                 created_at TEXT NOT NULL,
-                enabled BOOLEAN DEFAULT 1
+                enabled BOOLEAN DEFAULT 1,
+                secret TEXT,
+                max_retries INTEGER NOT NULL DEFAULT 2,
+                initial_backoff_secs INTEGER NOT NULL DEFAULT 1,
+                max_backoff_secs INTEGER NOT NULL DEFAULT 60,
+                from_filter TEXT,
+                subject_filter TEXT,
+                include_attachment_content BOOLEAN NOT NULL DEFAULT 0,
+                payload_template TEXT,
+                format TEXT NOT NULL DEFAULT 'json',
+                headers TEXT NOT NULL DEFAULT '{}'
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
+        add_column_if_missing(&pool, "webhooks", "secret", "TEXT").await?;
+        add_column_if_missing(
+            &pool,
+            "webhooks",
+            "max_retries",
+            "INTEGER NOT NULL DEFAULT 2",
+        )
+        .await?;
+        add_column_if_missing(
+            &pool,
+            "webhooks",
+            "initial_backoff_secs",
+            "INTEGER NOT NULL DEFAULT 1",
+        )
+        .await?;
+        add_column_if_missing(
+            &pool,
+            "webhooks",
+            "max_backoff_secs",
+            "INTEGER NOT NULL DEFAULT 60",
+        )
+        .await?;
+        add_column_if_missing(&pool, "webhooks", "from_filter", "TEXT").await?;
+        add_column_if_missing(&pool, "webhooks", "subject_filter", "TEXT").await?;
+        add_column_if_missing(
+            &pool,
+            "webhooks",
+            "include_attachment_content",
+            "BOOLEAN NOT NULL DEFAULT 0",
+        )
+        .await?;
+        add_column_if_missing(&pool, "webhooks", "payload_template", "TEXT").await?;
+        add_column_if_missing(&pool, "webhooks", "format", "TEXT NOT NULL DEFAULT 'json'").await?;
+        add_column_if_missing(&pool, "webhooks", "headers", "TEXT NOT NULL DEFAULT '{}'").await?;
+
         // Create index on mailbox_address for faster webhook queries
         sqlx::query(
             r#"
@@ -93,110 +668,132 @@ impl SqliteBackend {
         .execute(&pool)
         .await?;
 
-        // Create mailboxes table for password protection
+        // Create webhook_deliveries table for delivery history
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS mailboxes (
-                address TEXT PRIMARY KEY,
-                password_hash TEXT,
-                created_at TEXT NOT NULL,
-                is_locked BOOLEAN DEFAULT 0
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                attempt INTEGER NOT NULL,
+                status_code INTEGER,
+                success BOOLEAN NOT NULL,
+                response_body TEXT
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create users table for authentication
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS users (
+            CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries(webhook_id, timestamp)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        // Create pending_webhook_deliveries table for deliveries retried later
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS pending_webhook_deliveries (
                 id TEXT PRIMARY KEY,
-                username TEXT NOT NULL UNIQUE,
-                password_hash TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                webhook_id TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                next_attempt_at TEXT NOT NULL
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create index on username for faster user lookups
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_users_username ON users(username)
+            CREATE INDEX IF NOT EXISTS idx_pending_webhook_deliveries_due ON pending_webhook_deliveries(next_attempt_at)
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create rate_limits table
+        // Create mailboxes table for password protection
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS rate_limits (
-                mailbox_address TEXT PRIMARY KEY,
-                requests_per_hour INTEGER NOT NULL,
-                requests_per_day INTEGER NOT NULL,
+            CREATE TABLE IF NOT EXISTS mailboxes (
+                address TEXT PRIMARY KEY,
+                password_hash TEXT,
                 created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
+                is_locked BOOLEAN DEFAULT 0
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create rate_limit_requests table for tracking individual requests
+        // Create mailbox_owners table mapping mailbox local parts to users
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS rate_limit_requests (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                mailbox_address TEXT NOT NULL,
-                timestamp TEXT NOT NULL
+            CREATE TABLE IF NOT EXISTS mailbox_owners (
+                local_part TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                created_at TEXT NOT NULL
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create index on mailbox_address for faster rate limit queries
+        // Create aliases table mapping a mailbox local part to destination local parts
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_mailbox ON rate_limit_requests(mailbox_address)
+            CREATE TABLE IF NOT EXISTS aliases (
+                source TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (source, destination)
+            )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create index on timestamp for efficient cleanup
+        // Create forwarding_rules table relaying a mailbox's email to an outside address
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_timestamp ON rate_limit_requests(timestamp)
+            CREATE TABLE IF NOT EXISTS forwarding_rules (
+                id TEXT PRIMARY KEY,
+                mailbox TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                relay_host TEXT NOT NULL,
+                relay_port INTEGER NOT NULL,
+                relay_username TEXT,
+                relay_password TEXT,
+                relay_starttls BOOLEAN NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create compound index for rate limit lookups
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_mailbox_timestamp ON rate_limit_requests(mailbox_address, timestamp)
+            CREATE INDEX IF NOT EXISTS idx_forwarding_rules_mailbox ON forwarding_rules(mailbox)
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create sent_emails table
+        // Create routing_rules table copying a mailbox's email by subject into another mailbox
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS sent_emails (
+            CREATE TABLE IF NOT EXISTS routing_rules (
                 id TEXT PRIMARY KEY,
-                from_address TEXT NOT NULL,
-                to_address TEXT NOT NULL,
-                subject TEXT NOT NULL,
-                body_text TEXT NOT NULL,
-                body_html TEXT,
-                timestamp TEXT NOT NULL,
-                message_id TEXT NOT NULL
+                mailbox TEXT NOT NULL,
+                subject_pattern TEXT NOT NULL,
+                target_mailbox TEXT NOT NULL,
+                created_at TEXT NOT NULL
             )
             "#,
         )
@@ -205,36 +802,37 @@ impl SqliteBackend {
 
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_sent_emails_from ON sent_emails(from_address)
+            CREATE INDEX IF NOT EXISTS idx_routing_rules_mailbox ON routing_rules(mailbox)
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create FTS5 virtual table for full-text search
+        // Create sender_rules table blocking or allowing inbound mail by sender
         sqlx::query(
             r#"
-            CREATE VIRTUAL TABLE IF NOT EXISTS emails_fts USING fts5(
-                id UNINDEXED,
-                to_address,
-                from_address,
-                subject,
-                body,
-                content='emails',
-                content_rowid='rowid'
+            CREATE TABLE IF NOT EXISTS sender_rules (
+                id TEXT PRIMARY KEY,
+                pattern TEXT NOT NULL,
+                action TEXT NOT NULL,
+                created_at TEXT NOT NULL
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        // Create triggers to keep FTS table in sync with emails table
+        // Create audit_log table recording administrative changes
         sqlx::query(
             r#"
-            CREATE TRIGGER IF NOT EXISTS emails_ai AFTER INSERT ON emails BEGIN
-                INSERT INTO emails_fts(rowid, id, to_address, from_address, subject, body)
-                VALUES (new.rowid, new.id, new.to_address, new.from_address, new.subject, new.body);
-            END
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id TEXT PRIMARY KEY,
+                actor TEXT,
+                action TEXT NOT NULL,
+                target TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#,
         )
         .execute(&pool)
@@ -242,950 +840,2772 @@ impl SqliteBackend {
 
         sqlx::query(
             r#"
-            CREATE TRIGGER IF NOT EXISTS emails_ad AFTER DELETE ON emails BEGIN
-                INSERT INTO emails_fts(emails_fts, rowid, id, to_address, from_address, subject, body)
-                VALUES('delete', old.rowid, old.id, old.to_address, old.from_address, old.subject, old.body);
-            END
+            CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)
             "#,
         )
         .execute(&pool)
         .await?;
 
+        // Create mailbox_retention table overriding the global retention per address
         sqlx::query(
             r#"
-            CREATE TRIGGER IF NOT EXISTS emails_au AFTER UPDATE ON emails BEGIN
-                INSERT INTO emails_fts(emails_fts, rowid, id, to_address, from_address, subject, body)
-                VALUES('delete', old.rowid, old.id, old.to_address, old.from_address, old.subject, old.body);
-                INSERT INTO emails_fts(rowid, id, to_address, from_address, subject, body)
-                VALUES (new.rowid, new.id, new.to_address, new.from_address, new.subject, new.body);
-            END
+            CREATE TABLE IF NOT EXISTS mailbox_retention (
+                address TEXT PRIMARY KEY,
+                retention_hours BIGINT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
             "#,
         )
         .execute(&pool)
         .await?;
 
-        info!("SQLite database initialized successfully");
-
-        Ok(Self { pool })
-    }
-}
+        // Create users table for authentication
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS users (
+                id TEXT PRIMARY KEY,
+                username TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-#[async_trait]
-impl StorageBackend for SqliteBackend {
-    async fn store_email(&self, email: Email) -> Result<()> {
-        // Serialize attachments to JSON
-        let attachments_json = serde_json::to_string(&email.attachments)?;
+        add_column_if_missing(&pool, "users", "is_admin", "INTEGER NOT NULL DEFAULT 0").await?;
 
+        // Databases from before admin roles existed: the oldest user becomes the admin
         sqlx::query(
             r#"
-            INSERT INTO emails (id, to_address, from_address, subject, body, timestamp, raw, attachments)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            UPDATE users SET is_admin = 1
+            WHERE id = (SELECT id FROM users ORDER BY created_at ASC LIMIT 1)
+              AND NOT EXISTS (SELECT 1 FROM users WHERE is_admin = 1)
             "#,
         )
-        .bind(&email.id)
-        .bind(&email.to)
-        .bind(&email.from)
-        .bind(&email.subject)
-        .bind(&email.body)
-        .bind(email.timestamp.to_rfc3339())
-        .bind(&email.raw)
-        .bind(&attachments_json)
-        .execute(&self.pool)
+        .execute(&pool)
         .await?;
 
-        info!(
-            "Stored email {} for address {} with {} attachments",
-            email.id,
-            email.to,
-            email.attachments.len()
-        );
-        Ok(())
-    }
-
-    async fn get_emails_for_address(&self, address: &str) -> Result<Vec<Email>> {
-        let rows = sqlx::query_as::<
-            _,
-            (
-                String,
-                String,
-                String,
-                String,
-                String,
-                String,
-                Option<String>,
-                Option<String>,
-            ),
-        >(
+        // Create index on username for faster user lookups
+        sqlx::query(
             r#"
-            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments
-            FROM emails
-            WHERE to_address = ?
-            ORDER BY timestamp DESC
+            CREATE INDEX IF NOT EXISTS idx_users_username ON users(username)
             "#,
         )
-        .bind(address)
-        .fetch_all(&self.pool)
+        .execute(&pool)
         .await?;
 
-        let emails = rows
-            .into_iter()
-            .map(
-                |(id, to, from, subject, body, timestamp, raw, attachments_json)| {
-                    let timestamp = DateTime::parse_from_rfc3339(&timestamp)
-                        .unwrap_or_else(|_| Utc::now().into())
-                        .with_timezone(&Utc);
-
-                    // Deserialize attachments from JSON
-                    let attachments = attachments_json
-                        .and_then(|json| serde_json::from_str(&json).ok())
-                        .unwrap_or_default();
-
-                    Email {
-                        id,
-                        to,
-                        from,
-                        subject,
-                        body,
-                        timestamp,
-                        raw,
-                        attachments,
-                    }
-                },
-            )
-            .collect();
-
-        Ok(emails)
-    }
-
-    async fn get_email_by_id(&self, id: &str) -> Result<Option<Email>> {
-        let row = sqlx::query_as::<
-            _,
-            (
-                String,
-                String,
-                String,
-                String,
-                String,
-                String,
-                Option<String>,
-                Option<String>,
-            ),
-        >(
+        // Create refresh_tokens table (tokens are stored hashed)
+        sqlx::query(
             r#"
-            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments
-            FROM emails
-            WHERE id = ?
+            CREATE TABLE IF NOT EXISTS refresh_tokens (
+                token_hash TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .execute(&pool)
         .await?;
 
-        Ok(row.map(
-            |(id, to, from, subject, body, timestamp, raw, attachments_json)| {
-                let timestamp = DateTime::parse_from_rfc3339(&timestamp)
-                    .unwrap_or_else(|_| Utc::now().into())
-                    .with_timezone(&Utc);
-
-                // Deserialize attachments from JSON
-                let attachments = attachments_json
-                    .and_then(|json| serde_json::from_str(&json).ok())
-                    .unwrap_or_default();
-
-                Email {
-                    id,
-                    to,
-                    from,
-                    subject,
-                    body,
-                    timestamp,
-                    raw,
-                    attachments,
-                }
-            },
-        ))
-    }
-
-    async fn delete_email(&self, id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM emails WHERE id = ?")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn delete_old_emails_with_details(&self, hours: i64) -> Result<Vec<(String, String)>> {
-        let cutoff = Utc::now() - Duration::hours(hours);
-        let cutoff_str = cutoff.to_rfc3339();
-
-        // First, get the IDs and addresses of emails to be deleted
-        let rows = sqlx::query_as::<_, (String, String)>(
+        // Create revoked_tokens table (access tokens revoked by logging out, by jti)
+        sqlx::query(
             r#"
-            SELECT id, to_address
-            FROM emails
-            WHERE timestamp < ?
+            CREATE TABLE IF NOT EXISTS revoked_tokens (
+                jti TEXT PRIMARY KEY,
+                expires_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(&cutoff_str)
-        .fetch_all(&self.pool)
+        .execute(&pool)
         .await?;
 
-        let deleted_emails = rows.clone();
-
-        // Then delete them
-        let result = sqlx::query(
+        // Create failed_logins table for login throttling
+        sqlx::query(
             r#"
-            DELETE FROM emails
-            WHERE timestamp < ?
+            CREATE TABLE IF NOT EXISTS failed_logins (
+                email TEXT NOT NULL,
+                attempted_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(cutoff_str)
-        .execute(&self.pool)
+        .execute(&pool)
         .await?;
 
-        let deleted = result.rows_affected() as usize;
-        if deleted > 0 {
-            warn!(
-                "Deleted {} old emails (older than {} hours)",
-                deleted, hours
-            );
-        }
-
-        Ok(deleted_emails)
-    }
-
-    async fn create_webhook(&self, webhook: Webhook) -> Result<()> {
-        // Serialize events to JSON
-        let events_json = serde_json::to_string(&webhook.events)?;
-
         sqlx::query(
             r#"
-            INSERT INTO webhooks (id, mailbox_address, webhook_url, events, created_at, enabled)
-            VALUES (?, ?, ?, ?, ?, ?)
+            CREATE INDEX IF NOT EXISTS idx_failed_logins_email ON failed_logins(email, attempted_at)
             "#,
         )
-        .bind(&webhook.id)
-        .bind(&webhook.mailbox_address)
-        .bind(&webhook.webhook_url)
-        .bind(&events_json)
-        .bind(webhook.created_at.to_rfc3339())
-        .bind(webhook.enabled)
-        .execute(&self.pool)
+        .execute(&pool)
         .await?;
 
-        info!(
-            "Created webhook {} for mailbox {} with {} events",
-            webhook.id,
-            webhook.mailbox_address,
-            webhook.events.len()
-        );
-        Ok(())
-    }
-
-    async fn get_webhooks_for_mailbox(&self, address: &str) -> Result<Vec<Webhook>> {
-        let rows = sqlx::query_as::<_, (String, String, String, String, String, bool)>(
+        // Create rate_limits table
+        sqlx::query(
             r#"
-            SELECT id, mailbox_address, webhook_url, events, created_at, enabled
-            FROM webhooks
-            WHERE mailbox_address = ?
-            ORDER BY created_at DESC
+            CREATE TABLE IF NOT EXISTS rate_limits (
+                mailbox_address TEXT PRIMARY KEY,
+                requests_per_hour INTEGER NOT NULL,
+                requests_per_day INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(address)
-        .fetch_all(&self.pool)
+        .execute(&pool)
         .await?;
 
-        let webhooks = rows
-            .into_iter()
-            .map(
-                |(id, mailbox_address, webhook_url, events_json, created_at, enabled)| {
-                    let created_at = DateTime::parse_from_rfc3339(&created_at)
-                        .unwrap_or_else(|_| Utc::now().into())
-                        .with_timezone(&Utc);
-
-                    // Deserialize events from JSON
-                    let events = serde_json::from_str(&events_json).unwrap_or_default();
-
-                    Webhook {
-                        id,
-                        mailbox_address,
-                        webhook_url,
-                        events,
-                        created_at,
-                        enabled,
-                    }
-                },
+        // Create rate_limit_requests table for tracking individual requests
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rate_limit_requests (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                mailbox_address TEXT NOT NULL,
+                timestamp TEXT NOT NULL
             )
-            .collect();
-
-        Ok(webhooks)
-    }
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-    async fn get_webhook_by_id(&self, id: &str) -> Result<Option<Webhook>> {
-        let row = sqlx::query_as::<_, (String, String, String, String, String, bool)>(
+        // Create index on mailbox_address for faster rate limit queries
+        sqlx::query(
             r#"
-            SELECT id, mailbox_address, webhook_url, events, created_at, enabled
-            FROM webhooks
-            WHERE id = ?
+            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_mailbox ON rate_limit_requests(mailbox_address)
             "#,
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .execute(&pool)
         .await?;
 
-        Ok(row.map(
-            |(id, mailbox_address, webhook_url, events_json, created_at, enabled)| {
-                let created_at = DateTime::parse_from_rfc3339(&created_at)
-                    .unwrap_or_else(|_| Utc::now().into())
-                    .with_timezone(&Utc);
-
-                // Deserialize events from JSON
-                let events = serde_json::from_str(&events_json).unwrap_or_default();
-
-                Webhook {
-                    id,
-                    mailbox_address,
-                    webhook_url,
-                    events,
-                    created_at,
-                    enabled,
-                }
-            },
-        ))
-    }
-
-    async fn update_webhook(&self, webhook: Webhook) -> Result<()> {
-        // Serialize events to JSON
-        let events_json = serde_json::to_string(&webhook.events)?;
-
+        // Create index on timestamp for efficient cleanup
         sqlx::query(
             r#"
-            UPDATE webhooks
-            SET mailbox_address = ?, webhook_url = ?, events = ?, enabled = ?
-            WHERE id = ?
+            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_timestamp ON rate_limit_requests(timestamp)
             "#,
         )
-        .bind(&webhook.mailbox_address)
-        .bind(&webhook.webhook_url)
-        .bind(&events_json)
-        .bind(webhook.enabled)
-        .bind(&webhook.id)
-        .execute(&self.pool)
+        .execute(&pool)
         .await?;
 
-        info!("Updated webhook {}", webhook.id);
-        Ok(())
-    }
-
-    async fn delete_webhook(&self, id: &str) -> Result<()> {
+        // Create compound index for rate limit lookups
         sqlx::query(
             r#"
-            DELETE FROM webhooks
-            WHERE id = ?
+            CREATE INDEX IF NOT EXISTS idx_rate_limit_requests_mailbox_timestamp ON rate_limit_requests(mailbox_address, timestamp)
             "#,
         )
-        .bind(id)
-        .execute(&self.pool)
+        .execute(&pool)
         .await?;
 
-        info!("Deleted webhook {}", id);
-        Ok(())
-    }
-
-    async fn get_active_webhooks_for_event(
-        &self,
-        address: &str,
-        event: WebhookEvent,
-    ) -> Result<Vec<Webhook>> {
-        let rows = sqlx::query_as::<_, (String, String, String, String, String, bool)>(
+        // Create sent_emails table
+        sqlx::query(
             r#"
-            SELECT id, mailbox_address, webhook_url, events, created_at, enabled
-            FROM webhooks
-            WHERE mailbox_address = ? AND enabled = 1
+            CREATE TABLE IF NOT EXISTS sent_emails (
+                id TEXT PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                to_address TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                body_text TEXT NOT NULL,
+                body_html TEXT,
+                timestamp TEXT NOT NULL,
+                message_id TEXT NOT NULL
+            )
             "#,
         )
-        .bind(address)
-        .fetch_all(&self.pool)
+        .execute(&pool)
         .await?;
 
-        let webhooks = rows
-            .into_iter()
-            .map(
-                |(id, mailbox_address, webhook_url, events_json, created_at, enabled)| {
-                    let created_at = DateTime::parse_from_rfc3339(&created_at)
-                        .unwrap_or_else(|_| Utc::now().into())
-                        .with_timezone(&Utc);
-
-                    // Deserialize events from JSON
-                    let events = serde_json::from_str(&events_json).unwrap_or_default();
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_sent_emails_from ON sent_emails(from_address)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-                    Webhook {
-                        id,
-                        mailbox_address,
-                        webhook_url,
-                        events,
-                        created_at,
-                        enabled,
-                    }
-                },
+        // Create FTS5 virtual table for full-text search
+        sqlx::query(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS emails_fts USING fts5(
+                id UNINDEXED,
+                to_address,
+                from_address,
+                subject,
+                body,
+                content='emails',
+                content_rowid='rowid'
             )
-            .filter(|webhook| webhook.events.contains(&event))
-            .collect();
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-        Ok(webhooks)
-    }
+        // Create triggers to keep FTS table in sync with emails table
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS emails_ai AFTER INSERT ON emails BEGIN
+                INSERT INTO emails_fts(rowid, id, to_address, from_address, subject, body)
+                VALUES (new.rowid, new.id, new.to_address, new.from_address, new.subject, new.body);
+            END
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-    async fn get_mailbox(&self, address: &str) -> Result<Option<Mailbox>> {
-        let row = sqlx::query_as::<_, (String, Option<String>, String, bool)>(
+        sqlx::query(
             r#"
-            SELECT address, password_hash, created_at, is_locked
-            FROM mailboxes
-            WHERE address = ?
+            CREATE TRIGGER IF NOT EXISTS emails_ad AFTER DELETE ON emails BEGIN
+                INSERT INTO emails_fts(emails_fts, rowid, id, to_address, from_address, subject, body)
+                VALUES('delete', old.rowid, old.id, old.to_address, old.from_address, old.subject, old.body);
+            END
             "#,
         )
-        .bind(address)
-        .fetch_optional(&self.pool)
+        .execute(&pool)
         .await?;
 
-        Ok(row.map(|(address, password_hash, created_at, is_locked)| {
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc);
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS emails_au AFTER UPDATE ON emails BEGIN
+                INSERT INTO emails_fts(emails_fts, rowid, id, to_address, from_address, subject, body)
+                VALUES('delete', old.rowid, old.id, old.to_address, old.from_address, old.subject, old.body);
+                INSERT INTO emails_fts(rowid, id, to_address, from_address, subject, body)
+                VALUES (new.rowid, new.id, new.to_address, new.from_address, new.subject, new.body);
+            END
+            "#,
+        )
+        .execute(&pool)
+        .await?;
 
-            Mailbox {
-                address,
-                password_hash,
-                created_at,
-                is_locked,
-            }
-        }))
-    }
+        info!("SQLite database initialized successfully");
 
-    async fn set_mailbox_password(&self, address: &str, password_hash: String) -> Result<()> {
-        // Check if mailbox exists
-        let existing = self.get_mailbox(address).await?;
+        Ok(Self {
+            pool,
+            attachment_store: AttachmentStore::default(),
+        })
+    }
 
-        if let Some(mailbox) = existing {
-            // Mailbox already exists and is locked - don't allow changing password
-            if mailbox.is_locked {
-                return Err(anyhow::anyhow!("Mailbox is already locked with a password"));
-            }
-            // Update existing unlocked mailbox
-            sqlx::query(
-                r#"
-                UPDATE mailboxes
-                SET password_hash = ?, is_locked = 1
-                WHERE address = ?
-                "#,
-            )
-            .bind(&password_hash)
-            .bind(address)
-            .execute(&self.pool)
-            .await?;
-        } else {
-            // Create new mailbox
-            sqlx::query(
-                r#"
-                INSERT INTO mailboxes (address, password_hash, created_at, is_locked)
-                VALUES (?, ?, ?, 1)
-                "#,
-            )
-            .bind(address)
-            .bind(&password_hash)
-            .bind(Utc::now().to_rfc3339())
-            .execute(&self.pool)
-            .await?;
-        }
+    /// Keep attachment content in `store` instead of inline in the email rows
+    pub fn with_attachment_store(mut self, store: AttachmentStore) -> Self {
+        self.attachment_store = store;
+        self
+    }
 
-        info!("Set password for mailbox {}", address);
-        Ok(())
+    /// Close the connection pool, so later queries fail
+    #[cfg(test)]
+    pub async fn close(&self) {
+        self.pool.close().await;
     }
+}
 
-    async fn is_mailbox_locked(&self, address: &str) -> Result<bool> {
-        let mailbox = self.get_mailbox(address).await?;
-        Ok(mailbox.map(|m| m.is_locked).unwrap_or(false))
+#[async_trait]
+impl StorageBackend for SqliteBackend {
+    async fn store_email(&self, mut email: Email) -> Result<bool> {
+        self.attachment_store.offload(&mut email).await?;
+        email.update_snippet();
+
+        // Serialize attachments to JSON
+        let attachments_json = serde_json::to_string(&email.attachments)?;
+        let flags_json = serde_json::to_string(&email.flags)?;
+        let cc_json = serde_json::to_string(&email.cc)?;
+        let references_json = serde_json::to_string(&email.references)?;
+
+        let mut tx = self.pool.begin().await?;
+        let uid = allocate_uid(&mut tx, &email.to).await?;
+        let modseq = allocate_modseq(&mut tx, &email.to).await?;
+        let thread_id = match &email.thread_id {
+            Some(thread_id) => thread_id.clone(),
+            None => find_thread_id(&mut tx, &email).await?,
+        };
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO emails (id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (to_address, message_id) DO NOTHING
+            "#,
+        )
+        .bind(&email.id)
+        .bind(&email.to)
+        .bind(&email.from)
+        .bind(&email.subject)
+        .bind(&email.body)
+        .bind(email.timestamp.to_rfc3339())
+        .bind(&email.raw)
+        .bind(&attachments_json)
+        .bind(email.read)
+        .bind(&flags_json)
+        .bind(&cc_json)
+        .bind(&email.reply_to)
+        .bind(&email.body_text)
+        .bind(&email.body_html)
+        .bind(uid)
+        .bind(&email.message_id)
+        .bind(&email.spf_result)
+        .bind(&email.dkim_result)
+        .bind(&email.in_reply_to)
+        .bind(&references_json)
+        .bind(&thread_id)
+        .bind(email.size_bytes)
+        .bind(&email.snippet)
+        .bind(&email.from_name)
+        .bind(modseq)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !inserted {
+            // Dropping the transaction rolls back the UID and modseq allocated above
+            drop(tx);
+            self.attachment_store.remove(&[email.id.clone()]).await;
+            debug!(
+                "Skipped duplicate email {} for address {} (Message-ID {})",
+                email.id,
+                email.to,
+                email.message_id.as_deref().unwrap_or_default()
+            );
+            return Ok(false);
+        }
+        tx.commit().await?;
+
+        info!(
+            "Stored email {} for address {} (UID {}) with {} attachments",
+            email.id,
+            email.to,
+            uid,
+            email.attachments.len()
+        );
+        Ok(true)
     }
 
-    async fn clear_mailbox_password(&self, address: &str) -> Result<()> {
-        sqlx::query(
+    async fn get_emails_for_address(&self, address: &str) -> Result<Vec<Email>> {
+        let rows = sqlx::query_as::<_, EmailRow>(
             r#"
-            UPDATE mailboxes
-            SET password_hash = NULL, is_locked = 0
-            WHERE address = ?
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE to_address = ? AND deleted_at IS NULL
+            ORDER BY timestamp DESC
             "#,
         )
         .bind(address)
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
         .await?;
 
-        info!("Cleared password for mailbox {}", address);
-        Ok(())
+        Ok(rows.into_iter().map(email_from_row).collect())
     }
 
-    async fn verify_mailbox_password(&self, address: &str, password: &str) -> Result<bool> {
-        let mailbox = self.get_mailbox(address).await?;
-
-        match mailbox {
-            Some(m) => {
-                if let Some(hash) = m.password_hash {
-                    // Verify password against stored hash
-                    match bcrypt::verify(password, &hash) {
-                        Ok(valid) => Ok(valid),
-                        Err(e) => {
-                            error!("Password verification error for {}: {}", address, e);
-                            Ok(false)
-                        }
-                    }
-                } else {
-                    // No password set - allow any password (unclaimed mailbox)
-                    info!("IMAP login allowed for unclaimed mailbox: {}", address);
-                    Ok(true)
+    fn stream_all_emails(&self, since: Option<DateTime<Utc>>) -> BoxStream<'static, Result<Email>> {
+        let pool = self.pool.clone();
+        let since = since.map(|t| t.to_rfc3339());
+        let (tx, rx) = tokio::sync::mpsc::channel(EMAIL_STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut rows = sqlx::query_as::<_, EmailRow>(
+                r#"
+                SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+                FROM emails
+                WHERE deleted_at IS NULL AND (? IS NULL OR timestamp >= ?)
+                ORDER BY timestamp, id
+                "#,
+            )
+            .bind(since.clone())
+            .bind(since)
+            .fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                let email = row.map(email_from_row).map_err(anyhow::Error::from);
+                // Stop reading once the reader has gone away or the cursor failed
+                if tx.send(email).await.is_err() || failed {
+                    break;
                 }
             }
-            None => {
-                // Mailbox doesn't exist - allow any password (like web UI)
-                info!("IMAP login allowed for non-existent mailbox: {}", address);
-                Ok(true)
-            }
-        }
+        });
+
+        email_channel_stream(rx)
     }
 
-    async fn create_user(&self, user: User) -> Result<()> {
-        sqlx::query(
+    async fn query_emails(&self, address: &str, filter: &EmailFilter) -> Result<(Vec<Email>, i64)> {
+        let since = filter.since.map(|t| t.to_rfc3339());
+        let until = filter.until.map(|t| t.to_rfc3339());
+
+        let rows = sqlx::query_as::<_, EmailRow>(
             r#"
-            INSERT INTO users (id, username, password_hash, created_at)
-            VALUES (?, ?, ?, ?)
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE to_address = ? AND deleted_at IS NULL
+            AND (? IS NULL OR INSTR(LOWER(from_address), LOWER(?)) > 0)
+            AND (? IS NULL OR INSTR(LOWER(subject), LOWER(?)) > 0)
+            AND (? IS NULL OR timestamp >= ?)
+            AND (? IS NULL OR timestamp <= ?)
+            ORDER BY timestamp DESC
+            LIMIT ? OFFSET ?
             "#,
         )
-        .bind(&user.id)
-        .bind(&user.email)
-        .bind(&user.password_hash)
-        .bind(user.created_at.to_rfc3339())
-        .execute(&self.pool)
+        .bind(address)
+        .bind(&filter.from)
+        .bind(&filter.from)
+        .bind(&filter.subject_contains)
+        .bind(&filter.subject_contains)
+        .bind(&since)
+        .bind(&since)
+        .bind(&until)
+        .bind(&until)
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all(&self.pool)
         .await?;
 
-        info!("Created user {}", user.email);
-        Ok(())
-    }
-
-    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, (String, String, String, String)>(
+        let total = sqlx::query_as::<_, (i64,)>(
             r#"
-            SELECT id, username, password_hash, created_at
-            FROM users
-            WHERE username = ?
+            SELECT COUNT(*) FROM emails
+            WHERE to_address = ? AND deleted_at IS NULL
+            AND (? IS NULL OR INSTR(LOWER(from_address), LOWER(?)) > 0)
+            AND (? IS NULL OR INSTR(LOWER(subject), LOWER(?)) > 0)
+            AND (? IS NULL OR timestamp >= ?)
+            AND (? IS NULL OR timestamp <= ?)
             "#,
         )
-        .bind(email)
-        .fetch_optional(&self.pool)
+        .bind(address)
+        .bind(&filter.from)
+        .bind(&filter.from)
+        .bind(&filter.subject_contains)
+        .bind(&filter.subject_contains)
+        .bind(&since)
+        .bind(&since)
+        .bind(&until)
+        .bind(&until)
+        .fetch_one(&self.pool)
         .await?;
 
-        Ok(row.map(|(id, email, password_hash, created_at)| {
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc);
-
-            User {
-                id,
-                email,
-                password_hash,
-                created_at,
-            }
-        }))
+        Ok((rows.into_iter().map(email_from_row).collect(), total.0))
     }
 
-    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, (String, String, String, String)>(
+    async fn sender_summary(
+        &self,
+        address: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<SenderSummary>> {
+        let rows = sqlx::query_as::<_, (String, i64, String)>(
             r#"
-            SELECT id, username, password_hash, created_at
-            FROM users
-            WHERE id = ?
+            SELECT from_address, COUNT(*) AS count, MAX(timestamp) AS last_seen
+            FROM emails
+            WHERE to_address = ? AND deleted_at IS NULL
+            GROUP BY from_address
+            ORDER BY count DESC, last_seen DESC
+            LIMIT ?
             "#,
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(address)
+        // A negative limit means no limit in SQLite
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(row.map(|(id, email, password_hash, created_at)| {
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc);
-
-            User {
-                id,
-                email,
-                password_hash,
-                created_at,
-            }
-        }))
+        Ok(rows
+            .into_iter()
+            .map(|(from, count, last_seen)| SenderSummary {
+                from,
+                count,
+                last_seen: DateTime::parse_from_rfc3339(&last_seen)
+                    .unwrap_or_else(|_| Utc::now().into())
+                    .with_timezone(&Utc),
+            })
+            .collect())
     }
 
-    async fn has_users(&self) -> Result<bool> {
+    async fn count_emails_for_address(&self, address: &str) -> Result<i64> {
         let row = sqlx::query_as::<_, (i64,)>(
             r#"
-            SELECT COUNT(*) FROM users
+            SELECT COUNT(*) FROM emails WHERE to_address = ? AND deleted_at IS NULL
             "#,
         )
+        .bind(address)
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(row.0 > 0)
+        Ok(row.0)
     }
 
-    // Rate limiting implementation
-
-    async fn create_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
-        sqlx::query(
+    async fn total_bytes_for_address(&self, address: &str) -> Result<i64> {
+        let row = sqlx::query_as::<_, (i64,)>(
             r#"
-            INSERT INTO rate_limits (mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?)
+            SELECT COALESCE(SUM(LENGTH(CAST(COALESCE(raw, body) AS BLOB))), 0)
+            FROM emails WHERE to_address = ? AND deleted_at IS NULL
             "#,
         )
-        .bind(&rate_limit.mailbox_address)
-        .bind(rate_limit.requests_per_hour)
-        .bind(rate_limit.requests_per_day)
-        .bind(rate_limit.created_at.to_rfc3339())
-        .bind(rate_limit.updated_at.to_rfc3339())
-        .execute(&self.pool)
+        .bind(address)
+        .fetch_one(&self.pool)
         .await?;
 
-        info!(
-            "Created rate limit for mailbox {}",
-            rate_limit.mailbox_address
-        );
-        Ok(())
+        Ok(row.0)
     }
 
-    async fn get_rate_limit(&self, address: &str) -> Result<Option<crate::rate_limit::RateLimit>> {
-        let row = sqlx::query_as::<_, (String, u32, u32, String, String)>(
+    async fn list_mailboxes(&self, limit: i64, offset: i64) -> Result<Vec<MailboxSummary>> {
+        let rows = sqlx::query_as::<_, (String, i64, String)>(
             r#"
-            SELECT mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at
-            FROM rate_limits
-            WHERE mailbox_address = ?
+            SELECT to_address, COUNT(*) AS count, MAX(timestamp) AS latest
+            FROM emails
+            WHERE deleted_at IS NULL
+            GROUP BY to_address
+            ORDER BY latest DESC, to_address
+            LIMIT ? OFFSET ?
             "#,
         )
-        .bind(address)
-        .fetch_optional(&self.pool)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(row.map(
-            |(mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)| {
-                let created_at = DateTime::parse_from_rfc3339(&created_at)
+        Ok(rows
+            .into_iter()
+            .map(|(address, count, latest)| MailboxSummary {
+                address,
+                count,
+                latest: DateTime::parse_from_rfc3339(&latest)
                     .unwrap_or_else(|_| Utc::now().into())
-                    .with_timezone(&Utc);
-                let updated_at = DateTime::parse_from_rfc3339(&updated_at)
-                    .unwrap_or_else(|_| Utc::now().into())
-                    .with_timezone(&Utc);
+                    .with_timezone(&Utc),
+            })
+            .collect())
+    }
 
-                crate::rate_limit::RateLimit {
-                    mailbox_address,
-                    requests_per_hour,
-                    requests_per_day,
-                    created_at,
-                    updated_at,
-                }
-            },
-        ))
+    async fn storage_stats(&self) -> Result<StorageStats> {
+        let (total_emails, total_mailboxes, total_attachments, total_bytes, oldest, newest) =
+            sqlx::query_as::<_, (i64, i64, i64, i64, Option<String>, Option<String>)>(
+                r#"
+                SELECT COUNT(*),
+                       COUNT(DISTINCT to_address),
+                       COALESCE(SUM(json_array_length(attachments)), 0),
+                       COALESCE(SUM(LENGTH(CAST(COALESCE(raw, body) AS BLOB))), 0),
+                       MIN(timestamp),
+                       MAX(timestamp)
+                FROM emails WHERE deleted_at IS NULL
+                "#,
+            )
+            .fetch_one(&self.pool)
+            .await?;
+
+        let parse = |timestamp: Option<String>| {
+            timestamp
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| t.with_timezone(&Utc))
+        };
+        Ok(StorageStats {
+            total_emails,
+            total_mailboxes,
+            total_attachments,
+            total_bytes,
+            oldest_email: parse(oldest),
+            newest_email: parse(newest),
+        })
     }
 
-    async fn update_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
-        sqlx::query(
+    async fn get_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let row = sqlx::query_as::<_, EmailRow>(
             r#"
-            UPDATE rate_limits
-            SET requests_per_hour = ?, requests_per_day = ?, updated_at = ?
-            WHERE mailbox_address = ?
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE id = ? AND deleted_at IS NULL
             "#,
         )
-        .bind(rate_limit.requests_per_hour)
-        .bind(rate_limit.requests_per_day)
-        .bind(Utc::now().to_rfc3339())
-        .bind(&rate_limit.mailbox_address)
-        .execute(&self.pool)
+        .bind(id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        info!(
-            "Updated rate limit for mailbox {}",
-            rate_limit.mailbox_address
-        );
-        Ok(())
+        Ok(row.map(email_from_row))
     }
 
-    async fn delete_rate_limit(&self, address: &str) -> Result<()> {
-        sqlx::query(
+    async fn email_exists_by_message_id(&self, to: &str, message_id: &str) -> Result<bool> {
+        let row = sqlx::query_as::<_, (i64,)>(
             r#"
-            DELETE FROM rate_limits WHERE mailbox_address = ?
+            SELECT COUNT(*) FROM emails WHERE to_address = ? AND message_id = ?
             "#,
         )
-        .bind(address)
-        .execute(&self.pool)
+        .bind(to)
+        .bind(message_id)
+        .fetch_one(&self.pool)
         .await?;
 
-        info!("Deleted rate limit for mailbox {}", address);
-        Ok(())
+        Ok(row.0 > 0)
     }
 
-    async fn record_rate_limit_request(
-        &self,
-        request: crate::rate_limit::RateLimitRequest,
-    ) -> Result<()> {
-        sqlx::query(
+    async fn get_thread(&self, thread_id: &str) -> Result<Vec<Email>> {
+        let rows = sqlx::query_as::<_, EmailRow>(
             r#"
-            INSERT INTO rate_limit_requests (mailbox_address, timestamp)
-            VALUES (?, ?)
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE thread_id = ? AND deleted_at IS NULL
+            ORDER BY timestamp ASC, id ASC
             "#,
         )
-        .bind(&request.mailbox_address)
-        .bind(request.timestamp.to_rfc3339())
-        .execute(&self.pool)
+        .bind(thread_id)
+        .fetch_all(&self.pool)
         .await?;
 
+        Ok(rows.into_iter().map(email_from_row).collect())
+    }
+
+    async fn mark_email_read(&self, id: &str, read: bool) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE emails SET read = ? WHERE id = ?")
+            .bind(read)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        bump_modseq(&mut tx, id).await?;
+        tx.commit().await?;
+
         Ok(())
     }
 
-    async fn count_requests_since(&self, address: &str, since: DateTime<Utc>) -> Result<u32> {
-        let row = sqlx::query_as::<_, (i64,)>(
+    async fn set_email_flags(&self, id: &str, flags: &[String]) -> Result<()> {
+        let flags_json = serde_json::to_string(flags)?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE emails SET flags = ? WHERE id = ?")
+            .bind(&flags_json)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        bump_modseq(&mut tx, id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_email(&self, id: &str) -> Result<()> {
+        sqlx::query("UPDATE emails SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_old_emails_with_details(&self, hours: i64) -> Result<Vec<(String, String)>> {
+        let cutoff = Utc::now() - Duration::hours(hours);
+
+        // Move to the trash and return the IDs and addresses in a single statement
+        let deleted_emails = sqlx::query_as::<_, (String, String)>(
             r#"
-            SELECT COUNT(*) FROM rate_limit_requests
-            WHERE mailbox_address = ? AND timestamp >= ?
+            UPDATE emails
+            SET deleted_at = ?
+            WHERE timestamp < ? AND deleted_at IS NULL
+                AND to_address NOT IN (SELECT address FROM mailbox_retention)
+            RETURNING id, to_address
             "#,
         )
-        .bind(address)
-        .bind(since.to_rfc3339())
-        .fetch_one(&self.pool)
+        .bind(Utc::now().to_rfc3339())
+        .bind(cutoff.to_rfc3339())
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(row.0 as u32)
+        if !deleted_emails.is_empty() {
+            warn!(
+                "Moved {} old emails to the trash (older than {} hours)",
+                deleted_emails.len(),
+                hours
+            );
+        }
+
+        Ok(deleted_emails)
     }
 
-    async fn get_oldest_request_since(
+    async fn delete_emails_for_address(
         &self,
         address: &str,
-        since: DateTime<Utc>,
-    ) -> Result<Option<DateTime<Utc>>> {
-        let row = sqlx::query_as::<_, (String,)>(
+        older_than_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let cutoff =
+            older_than_hours.map(|hours| (Utc::now() - Duration::hours(hours)).to_rfc3339());
+
+        let deleted_emails = sqlx::query_as::<_, (String, String)>(
             r#"
-            SELECT timestamp FROM rate_limit_requests
-            WHERE mailbox_address = ? AND timestamp >= ?
-            ORDER BY timestamp ASC
-            LIMIT 1
+            UPDATE emails
+            SET deleted_at = ?
+            WHERE to_address = ? AND deleted_at IS NULL AND (? IS NULL OR timestamp < ?)
+            RETURNING id, to_address
             "#,
         )
+        .bind(Utc::now().to_rfc3339())
         .bind(address)
-        .bind(since.to_rfc3339())
-        .fetch_optional(&self.pool)
+        .bind(&cutoff)
+        .bind(&cutoff)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(row.map(|(timestamp,)| {
-            DateTime::parse_from_rfc3339(&timestamp)
-                .unwrap_or_else(|_| Utc::now().into())
-                .with_timezone(&Utc)
-        }))
+        Ok(deleted_emails)
     }
 
-    async fn cleanup_old_rate_limit_requests(&self, before: DateTime<Utc>) -> Result<u64> {
-        let result = sqlx::query(
+    async fn read_attachment_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.attachment_store.read(path).await
+    }
+
+    async fn get_attachments_by_hash(&self, hash: &str) -> Result<Vec<(String, Attachment)>> {
+        let rows = sqlx::query_as::<_, (String, String)>(
             r#"
-            DELETE FROM rate_limit_requests WHERE timestamp < ?
+            SELECT e.id, a.value
+            FROM emails e, json_each(e.attachments) a
+            WHERE e.deleted_at IS NULL AND json_extract(a.value, '$.content_hash') = ?
+            ORDER BY e.timestamp ASC, e.id ASC
             "#,
         )
-        .bind(before.to_rfc3339())
-        .execute(&self.pool)
+        .bind(hash)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(result.rows_affected())
+        rows.into_iter()
+            .map(|(id, attachment)| Ok((id, serde_json::from_str(&attachment)?)))
+            .collect()
     }
 
-    async fn search_emails(&self, search: SearchQuery) -> Result<Vec<SearchResult>> {
-        let limit = search.limit.unwrap_or(50);
-
-        // Build the search query
-        let sql = if search.mailbox.is_some() {
-            r#"
-                SELECT 
-                    e.id,
-                    e.to_address,
-                    e.from_address,
-                    e.subject,
-                    e.timestamp,
-                    snippet(emails_fts, 3, '<mark>', '</mark>', '...', 64) as snippet,
-                    rank
-                FROM emails_fts
-                JOIN emails e ON emails_fts.rowid = e.rowid
-                WHERE emails_fts MATCH ?
-                AND e.to_address = ?
-                ORDER BY rank
-                LIMIT ?
-                "#
-            .to_string()
-        } else {
+    async fn get_deleted_email_by_id(&self, id: &str) -> Result<Option<Email>> {
+        let row = sqlx::query_as::<_, EmailRow>(
             r#"
-                SELECT 
-                    e.id,
-                    e.to_address,
-                    e.from_address,
-                    e.subject,
-                    e.timestamp,
-                    snippet(emails_fts, 3, '<mark>', '</mark>', '...', 64) as snippet,
-                    rank
-                FROM emails_fts
-                JOIN emails e ON emails_fts.rowid = e.rowid
-                WHERE emails_fts MATCH ?
-                ORDER BY rank
-                LIMIT ?
-                "#
-            .to_string()
-        };
+            SELECT id, to_address, from_address, subject, body, timestamp, raw, attachments, read, flags, cc, reply_to, body_text, body_html, uid, message_id, spf_result, dkim_result, in_reply_to, email_references, thread_id, size_bytes, snippet, from_name, modseq
+            FROM emails
+            WHERE id = ? AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
 
-        let rows = if let Some(mailbox) = &search.mailbox {
-            sqlx::query_as::<_, (String, String, String, String, String, String, f64)>(&sql)
-                .bind(&search.query)
-                .bind(mailbox)
-                .bind(limit)
-                .fetch_all(&self.pool)
-                .await?
-        } else {
-            sqlx::query_as::<_, (String, String, String, String, String, String, f64)>(&sql)
-                .bind(&search.query)
-                .bind(limit)
+        Ok(row.map(email_from_row))
+    }
+
+    async fn restore_email(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE emails SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_deleted_emails(&self, hours: i64) -> Result<u64> {
+        let cutoff = Utc::now() - Duration::hours(hours);
+
+        let purged_ids: Vec<String> =
+            sqlx::query_scalar("DELETE FROM emails WHERE deleted_at < ? RETURNING id")
+                .bind(cutoff.to_rfc3339())
                 .fetch_all(&self.pool)
-                .await?
-        };
+                .await?;
+        self.attachment_store.remove(&purged_ids).await;
 
-        let results: Vec<SearchResult> = rows
-            .into_iter()
-            .map(
-                |(id, to, from, subject, timestamp, snippet, rank)| SearchResult {
-                    id,
-                    to,
-                    from,
-                    subject,
-                    snippet,
-                    timestamp,
-                    rank,
-                },
-            )
-            .collect();
+        let purged = purged_ids.len() as u64;
+        if purged > 0 {
+            warn!(
+                "Permanently deleted {} emails from the trash (deleted over {} hours ago)",
+                purged, hours
+            );
+        }
+
+        Ok(purged)
+    }
+
+    async fn create_webhook(&self, webhook: Webhook) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        insert_webhook(&mut conn, &webhook).await?;
 
         info!(
-            "Search query '{}' returned {} results",
-            search.query,
-            results.len()
+            "Created webhook {} for mailbox {} with {} events",
+            webhook.id,
+            webhook.mailbox_address,
+            webhook.events.len()
         );
+        Ok(())
+    }
 
-        Ok(results)
+    async fn create_webhooks(&self, webhooks: Vec<Webhook>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for webhook in &webhooks {
+            insert_webhook(&mut tx, webhook).await?;
+        }
+        tx.commit().await?;
+
+        info!("Created {} webhooks in one batch", webhooks.len());
+        Ok(())
     }
 
-    async fn store_sent_email(&self, email: SentEmail) -> Result<()> {
-        sqlx::query(
+    async fn get_webhooks_for_mailbox(&self, address: &str) -> Result<Vec<Webhook>> {
+        let rows = sqlx::query_as::<_, WebhookRow>(
             r#"
-            INSERT INTO sent_emails (id, from_address, to_address, subject, body_text, body_html, timestamp, message_id)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers
+            FROM webhooks
+            WHERE mailbox_address = ?
+            ORDER BY created_at DESC
             "#,
         )
-        .bind(&email.id)
-        .bind(&email.from)
-        .bind(&email.to)
-        .bind(&email.subject)
-        .bind(&email.body_text)
-        .bind(&email.body_html)
-        .bind(email.timestamp.to_rfc3339())
-        .bind(&email.message_id)
-        .execute(&self.pool)
+        .bind(address)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(())
+        let webhooks = rows.into_iter().map(webhook_from_row).collect();
+
+        Ok(webhooks)
     }
 
-    async fn get_sent_emails(&self, from_address: &str) -> Result<Vec<SentEmail>> {
-        let rows = sqlx::query_as::<_, (String, String, String, String, String, Option<String>, String, String)>(
+    async fn get_webhook_by_id(&self, id: &str) -> Result<Option<Webhook>> {
+        let row = sqlx::query_as::<_, WebhookRow>(
             r#"
-            SELECT id, from_address, to_address, subject, body_text, body_html, timestamp, message_id
-            FROM sent_emails
-            WHERE from_address = ?
-            ORDER BY timestamp DESC
+            SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers
+            FROM webhooks
+            WHERE id = ?
             "#,
         )
-        .bind(from_address)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(webhook_from_row))
+    }
+
+    async fn update_webhook(&self, webhook: Webhook) -> Result<()> {
+        // Serialize events to JSON
+        let events_json = serde_json::to_string(&webhook.events)?;
+        let headers_json = serde_json::to_string(&webhook.headers)?;
+
+        sqlx::query(
+            r#"
+            UPDATE webhooks
+            SET mailbox_address = ?, webhook_url = ?, events = ?, enabled = ?, secret = ?,
+                max_retries = ?, initial_backoff_secs = ?, max_backoff_secs = ?,
+                from_filter = ?, subject_filter = ?, include_attachment_content = ?,
+                payload_template = ?, format = ?, headers = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&webhook.mailbox_address)
+        .bind(&webhook.webhook_url)
+        .bind(&events_json)
+        .bind(webhook.enabled)
+        .bind(&webhook.secret)
+        .bind(webhook.max_retries as i64)
+        .bind(webhook.initial_backoff_secs as i64)
+        .bind(webhook.max_backoff_secs as i64)
+        .bind(&webhook.from_filter)
+        .bind(&webhook.subject_filter)
+        .bind(webhook.include_attachment_content)
+        .bind(&webhook.payload_template)
+        .bind(webhook.format.as_str())
+        .bind(&headers_json)
+        .bind(&webhook.id)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Updated webhook {}", webhook.id);
+        Ok(())
+    }
+
+    async fn delete_webhook(&self, id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM webhooks
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM pending_webhook_deliveries WHERE webhook_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        info!("Deleted webhook {}", id);
+        Ok(())
+    }
+
+    async fn get_active_webhooks_for_event(
+        &self,
+        address: &str,
+        event: WebhookEvent,
+    ) -> Result<Vec<Webhook>> {
+        let rows = sqlx::query_as::<_, WebhookRow>(
+            r#"
+            SELECT id, mailbox_address, webhook_url, events, created_at, enabled, secret, max_retries, initial_backoff_secs, max_backoff_secs, from_filter, subject_filter, include_attachment_content, payload_template, format, headers
+            FROM webhooks
+            WHERE mailbox_address = ? AND enabled = 1
+            "#,
+        )
+        .bind(address)
         .fetch_all(&self.pool)
         .await?;
 
-        let emails = rows
+        let webhooks = rows
+            .into_iter()
+            .map(webhook_from_row)
+            .filter(|webhook| webhook.events.contains(&event))
+            .collect();
+
+        Ok(webhooks)
+    }
+
+    async fn record_webhook_delivery(&self, delivery: WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (id, webhook_id, timestamp, attempt, status_code, success, response_body)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(delivery.timestamp.to_rfc3339())
+        .bind(delivery.attempt)
+        .bind(delivery.status_code)
+        .bind(delivery.success)
+        .bind(&delivery.response_body)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<
+            _,
+            (
+                String,
+                String,
+                String,
+                u32,
+                Option<u16>,
+                bool,
+                Option<String>,
+            ),
+        >(
+            r#"
+            SELECT id, webhook_id, timestamp, attempt, status_code, success, response_body
+            FROM webhook_deliveries
+            WHERE webhook_id = ?
+            ORDER BY timestamp DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let deliveries = rows
             .into_iter()
             .map(
-                |(id, from, to, subject, body_text, body_html, timestamp, message_id)| SentEmail {
-                    id,
-                    from,
-                    to,
-                    subject,
-                    body_text,
-                    body_html,
-                    timestamp: timestamp
-                        .parse::<DateTime<Utc>>()
-                        .unwrap_or_else(|_| Utc::now()),
-                    message_id,
+                |(id, webhook_id, timestamp, attempt, status_code, success, response_body)| {
+                    WebhookDelivery {
+                        id,
+                        webhook_id,
+                        timestamp: DateTime::parse_from_rfc3339(&timestamp)
+                            .unwrap_or_else(|_| Utc::now().into())
+                            .with_timezone(&Utc),
+                        attempt,
+                        status_code,
+                        success,
+                        response_body,
+                    }
                 },
             )
             .collect();
 
-        Ok(emails)
+        Ok(deliveries)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::storage::models::{Attachment, Email};
-    use chrono::{Duration, Utc};
+    async fn enqueue_webhook_delivery(&self, delivery: PendingWebhookDelivery) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO pending_webhook_deliveries (id, webhook_id, payload, attempts, created_at, next_attempt_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(serde_json::to_string(&delivery.payload)?)
+        .bind(delivery.attempts)
+        .bind(delivery.created_at.to_rfc3339())
+        .bind(delivery.next_attempt_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
 
-    async fn create_test_backend() -> SqliteBackend {
-        // Use in-memory database for tests
-        let database_url = "sqlite::memory:";
-        SqliteBackend::new(database_url).await.unwrap()
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_sqlite_backend_creation() {
-        let _backend = create_test_backend().await;
-        // If we get here without panicking, the backend was created successfully
-        assert!(true);
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingWebhookDelivery>> {
+        let rows = sqlx::query_as::<_, (String, String, String, u32, String, String)>(
+            r#"
+            SELECT id, webhook_id, payload, attempts, created_at, next_attempt_at
+            FROM pending_webhook_deliveries
+            WHERE next_attempt_at <= ?
+            ORDER BY next_attempt_at
+            LIMIT ?
+            "#,
+        )
+        .bind(now.to_rfc3339())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let parse_time = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .unwrap_or_else(|_| Utc::now().into())
+                .with_timezone(&Utc)
+        };
+        rows.into_iter()
+            .map(
+                |(id, webhook_id, payload, attempts, created_at, next_attempt_at)| {
+                    Ok(PendingWebhookDelivery {
+                        id,
+                        webhook_id,
+                        payload: serde_json::from_str(&payload)?,
+                        attempts,
+                        created_at: parse_time(&created_at),
+                        next_attempt_at: parse_time(&next_attempt_at),
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn reschedule_webhook_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE pending_webhook_deliveries
+            SET attempts = ?, next_attempt_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(attempts)
+        .bind(next_attempt_at.to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_pending_webhook_delivery(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM pending_webhook_deliveries WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_mailbox(&self, address: &str) -> Result<Option<Mailbox>> {
+        let row = sqlx::query_as::<_, (String, Option<String>, String, bool)>(
+            r#"
+            SELECT address, password_hash, created_at, is_locked
+            FROM mailboxes
+            WHERE address = ?
+            "#,
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(address, password_hash, created_at, is_locked)| {
+            let created_at = DateTime::parse_from_rfc3339(&created_at)
+                .unwrap_or_else(|_| Utc::now().into())
+                .with_timezone(&Utc);
+
+            Mailbox {
+                address,
+                password_hash,
+                created_at,
+                is_locked,
+            }
+        }))
+    }
+
+    async fn set_mailbox_password(&self, address: &str, password_hash: String) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO mailboxes (address, password_hash, created_at, is_locked)
+            VALUES (?, ?, ?, 1)
+            ON CONFLICT (address) DO UPDATE
+            SET password_hash = excluded.password_hash, is_locked = 1
+            "#,
+        )
+        .bind(address)
+        .bind(&password_hash)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        info!("Set password for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn is_mailbox_locked(&self, address: &str) -> Result<bool> {
+        let mailbox = self.get_mailbox(address).await?;
+        Ok(mailbox.map(|m| m.is_locked).unwrap_or(false))
+    }
+
+    async fn clear_mailbox_password(&self, address: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE mailboxes
+            SET password_hash = NULL, is_locked = 0
+            WHERE address = ?
+            "#,
+        )
+        .bind(address)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Cleared password for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn verify_mailbox_password(&self, address: &str, password: &str) -> Result<bool> {
+        let mailbox = self.get_mailbox(address).await?;
+
+        match mailbox {
+            Some(m) => {
+                if let Some(hash) = m.password_hash {
+                    // Verify password against stored hash
+                    match bcrypt::verify(password, &hash) {
+                        Ok(valid) => Ok(valid),
+                        Err(e) => {
+                            error!("Password verification error for {}: {}", address, e);
+                            Ok(false)
+                        }
+                    }
+                } else {
+                    // No password set - allow any password (unclaimed mailbox)
+                    info!("IMAP login allowed for unclaimed mailbox: {}", address);
+                    Ok(true)
+                }
+            }
+            None => {
+                // Mailbox doesn't exist - allow any password (like web UI)
+                info!("IMAP login allowed for non-existent mailbox: {}", address);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn claim_mailbox_owner(&self, local_part: &str, user_id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO mailbox_owners (local_part, user_id, created_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT (local_part) DO NOTHING
+            "#,
+        )
+        .bind(local_part)
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_mailbox_owner(&self, local_part: &str) -> Result<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT user_id FROM mailbox_owners WHERE local_part = ?",
+        )
+        .bind(local_part)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(user_id,)| user_id))
+    }
+
+    async fn create_alias(&self, source: &str, destinations: &[String]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        for destination in destinations {
+            sqlx::query(
+                r#"
+                INSERT INTO aliases (source, destination, created_at)
+                VALUES (?, ?, ?)
+                ON CONFLICT (source, destination) DO NOTHING
+                "#,
+            )
+            .bind(source)
+            .bind(destination)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_aliases_for(&self, source: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT destination FROM aliases WHERE source = ? ORDER BY created_at, destination",
+        )
+        .bind(source)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(destination,)| destination).collect())
+    }
+
+    async fn create_forwarding_rule(&self, rule: ForwardingRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO forwarding_rules (id, mailbox, destination, relay_host, relay_port, relay_username, relay_password, relay_starttls, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.mailbox)
+        .bind(&rule.destination)
+        .bind(&rule.relay_host)
+        .bind(rule.relay_port as i32)
+        .bind(&rule.relay_username)
+        .bind(&rule.relay_password)
+        .bind(rule.relay_starttls)
+        .bind(rule.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_forwarding_rules(&self, mailbox: &str) -> Result<Vec<ForwardingRule>> {
+        let rows = sqlx::query_as::<_, ForwardingRuleRow>(
+            r#"
+            SELECT id, mailbox, destination, relay_host, relay_port, relay_username, relay_password, relay_starttls, created_at
+            FROM forwarding_rules
+            WHERE mailbox = ?
+            ORDER BY created_at
+            "#,
+        )
+        .bind(mailbox)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(forwarding_rule_from_row).collect())
+    }
+
+    async fn create_routing_rule(&self, rule: RoutingRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO routing_rules (id, mailbox, subject_pattern, target_mailbox, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.mailbox)
+        .bind(&rule.subject_pattern)
+        .bind(&rule.target_mailbox)
+        .bind(rule.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_routing_rules(&self, mailbox: &str) -> Result<Vec<RoutingRule>> {
+        let rows = sqlx::query_as::<_, RoutingRuleRow>(
+            r#"
+            SELECT id, mailbox, subject_pattern, target_mailbox, created_at
+            FROM routing_rules
+            WHERE mailbox = ?
+            ORDER BY created_at
+            "#,
+        )
+        .bind(mailbox)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(routing_rule_from_row).collect())
+    }
+
+    async fn create_sender_rule(&self, rule: SenderRule) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sender_rules (id, pattern, action, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(&rule.id)
+        .bind(&rule.pattern)
+        .bind(rule.action.as_str())
+        .bind(rule.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_sender_rules(&self) -> Result<Vec<SenderRule>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String)>(
+            r#"
+            SELECT id, pattern, action, created_at
+            FROM sender_rules
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, pattern, action, created_at)| {
+                let Some(action) = SenderRuleAction::from_str(&action) else {
+                    warn!("Ignoring sender rule {} with unknown action {}", id, action);
+                    return None;
+                };
+                Some(SenderRule {
+                    id,
+                    pattern,
+                    action,
+                    created_at: DateTime::parse_from_rfc3339(&created_at)
+                        .unwrap_or_else(|_| Utc::now().into())
+                        .with_timezone(&Utc),
+                })
+            })
+            .collect())
+    }
+
+    async fn delete_sender_rule(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM sender_rules WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn audit(&self, entry: AuditEntry) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (id, actor, action, target, detail, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&entry.id)
+        .bind(&entry.actor)
+        .bind(&entry.action)
+        .bind(&entry.target)
+        .bind(entry.detail.to_string())
+        .bind(entry.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_audit_log(&self, limit: i64) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query_as::<_, (String, Option<String>, String, String, String, String)>(
+            r#"
+            SELECT id, actor, action, target, detail, created_at
+            FROM audit_log
+            ORDER BY created_at DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, actor, action, target, detail, created_at)| AuditEntry {
+                    id,
+                    actor,
+                    action,
+                    target,
+                    detail: serde_json::from_str(&detail).unwrap_or_default(),
+                    created_at: DateTime::parse_from_rfc3339(&created_at)
+                        .unwrap_or_else(|_| Utc::now().into())
+                        .with_timezone(&Utc),
+                },
+            )
+            .collect())
+    }
+
+    async fn set_mailbox_retention(
+        &self,
+        address: &str,
+        retention_hours: Option<i64>,
+    ) -> Result<()> {
+        match retention_hours {
+            Some(hours) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO mailbox_retention (address, retention_hours, updated_at)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT (address) DO UPDATE
+                    SET retention_hours = excluded.retention_hours, updated_at = excluded.updated_at
+                    "#,
+                )
+                .bind(address)
+                .bind(hours)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM mailbox_retention WHERE address = ?")
+                    .bind(address)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_mailbox_retention(&self, address: &str) -> Result<Option<i64>> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            "SELECT retention_hours FROM mailbox_retention WHERE address = ?",
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(hours,)| hours))
+    }
+
+    async fn get_mailbox_retentions(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT address, retention_hours FROM mailbox_retention ORDER BY address",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn get_mailbox_uid_state(&self, address: &str) -> Result<MailboxUidState> {
+        let mut conn = self.pool.acquire().await?;
+        ensure_uid_state(&mut conn, address).await?;
+
+        let (uid_validity, uid_next, highest_modseq) = sqlx::query_as::<_, (i64, i64, i64)>(
+            "SELECT uid_validity, uid_next, highest_modseq FROM mailbox_uids WHERE address = ?",
+        )
+        .bind(address)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(MailboxUidState {
+            uid_validity: uid_validity as u32,
+            uid_next: uid_next as u32,
+            highest_modseq: highest_modseq as u64,
+        })
+    }
+
+    async fn create_user(&self, user: User) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, password_hash, created_at, is_admin)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&user.id)
+        .bind(&user.email)
+        .bind(&user.password_hash)
+        .bind(user.created_at.to_rfc3339())
+        .bind(user.is_admin)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Created user {}", user.email);
+        Ok(())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            WHERE username = ?
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(user_from_row))
+    }
+
+    async fn has_users(&self) -> Result<bool> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM users
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0 > 0)
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query_as::<_, UserRow>(
+            r#"
+            SELECT id, username, password_hash, created_at, is_admin
+            FROM users
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(user_from_row).collect())
+    }
+
+    async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(password_hash)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn store_refresh_token(&self, token: RefreshToken) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (token_hash, user_id, expires_at, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(&token.token_hash)
+        .bind(&token.user_id)
+        .bind(token.expires_at.to_rfc3339())
+        .bind(token.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>> {
+        let row = sqlx::query_as::<_, (String, String, String, String)>(
+            r#"
+            SELECT token_hash, user_id, expires_at, created_at
+            FROM refresh_tokens
+            WHERE token_hash = ?
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(token_hash, user_id, expires_at, created_at)| {
+            let parse = |s: &str| {
+                DateTime::parse_from_rfc3339(s)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now())
+            };
+
+            RefreshToken {
+                token_hash,
+                user_id,
+                expires_at: parse(&expires_at),
+                created_at: parse(&created_at),
+            }
+        }))
+    }
+
+    async fn delete_refresh_token(&self, token_hash: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = ?")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_refresh_tokens_for_user(&self, user_id: &str) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_token(&self, jti: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO revoked_tokens (jti, expires_at) VALUES (?, ?)")
+            .bind(jti)
+            .bind(expires_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        let row = sqlx::query_as::<_, (String,)>("SELECT jti FROM revoked_tokens WHERE jti = ?")
+            .bind(jti)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn prune_revoked_tokens(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < ?")
+            .bind(before.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn record_failed_login(&self, email: &str, attempted_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("INSERT INTO failed_logins (email, attempted_at) VALUES (?, ?)")
+            .bind(email)
+            .bind(attempted_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_failed_logins_since(
+        &self,
+        email: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            r#"
+            SELECT attempted_at FROM failed_logins
+            WHERE email = ? AND attempted_at >= ?
+            ORDER BY attempted_at ASC
+            "#,
+        )
+        .bind(email)
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(attempted_at,)| {
+                DateTime::parse_from_rfc3339(&attempted_at)
+                    .ok()
+                    .map(|dt| dt.with_timezone(&Utc))
+            })
+            .collect())
+    }
+
+    async fn clear_failed_logins(&self, email: &str) -> Result<()> {
+        sqlx::query("DELETE FROM failed_logins WHERE email = ?")
+            .bind(email)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Rate limiting implementation
+
+    async fn create_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limits (mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&rate_limit.mailbox_address)
+        .bind(rate_limit.requests_per_hour)
+        .bind(rate_limit.requests_per_day)
+        .bind(rate_limit.created_at.to_rfc3339())
+        .bind(rate_limit.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        info!(
+            "Created rate limit for mailbox {}",
+            rate_limit.mailbox_address
+        );
+        Ok(())
+    }
+
+    async fn get_rate_limit(&self, address: &str) -> Result<Option<crate::rate_limit::RateLimit>> {
+        let row = sqlx::query_as::<_, (String, u32, u32, String, String)>(
+            r#"
+            SELECT mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at
+            FROM rate_limits
+            WHERE mailbox_address = ?
+            "#,
+        )
+        .bind(address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(
+            |(mailbox_address, requests_per_hour, requests_per_day, created_at, updated_at)| {
+                let created_at = DateTime::parse_from_rfc3339(&created_at)
+                    .unwrap_or_else(|_| Utc::now().into())
+                    .with_timezone(&Utc);
+                let updated_at = DateTime::parse_from_rfc3339(&updated_at)
+                    .unwrap_or_else(|_| Utc::now().into())
+                    .with_timezone(&Utc);
+
+                crate::rate_limit::RateLimit {
+                    mailbox_address,
+                    requests_per_hour,
+                    requests_per_day,
+                    created_at,
+                    updated_at,
+                }
+            },
+        ))
+    }
+
+    async fn update_rate_limit(&self, rate_limit: crate::rate_limit::RateLimit) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE rate_limits
+            SET requests_per_hour = ?, requests_per_day = ?, updated_at = ?
+            WHERE mailbox_address = ?
+            "#,
+        )
+        .bind(rate_limit.requests_per_hour)
+        .bind(rate_limit.requests_per_day)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&rate_limit.mailbox_address)
+        .execute(&self.pool)
+        .await?;
+
+        info!(
+            "Updated rate limit for mailbox {}",
+            rate_limit.mailbox_address
+        );
+        Ok(())
+    }
+
+    async fn delete_rate_limit(&self, address: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM rate_limits WHERE mailbox_address = ?
+            "#,
+        )
+        .bind(address)
+        .execute(&self.pool)
+        .await?;
+
+        info!("Deleted rate limit for mailbox {}", address);
+        Ok(())
+    }
+
+    async fn record_rate_limit_request(
+        &self,
+        request: crate::rate_limit::RateLimitRequest,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limit_requests (mailbox_address, timestamp)
+            VALUES (?, ?)
+            "#,
+        )
+        .bind(&request.mailbox_address)
+        .bind(request.timestamp.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn count_requests_since(&self, address: &str, since: DateTime<Utc>) -> Result<u32> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            r#"
+            SELECT COUNT(*) FROM rate_limit_requests
+            WHERE mailbox_address = ? AND timestamp >= ?
+            "#,
+        )
+        .bind(address)
+        .bind(since.to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.0 as u32)
+    }
+
+    async fn cleanup_old_rate_limit_requests(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM rate_limit_requests WHERE timestamp < ?
+            "#,
+        )
+        .bind(before.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn search_emails(&self, search: SearchQuery) -> Result<Vec<SearchResult>> {
+        let limit = search.limit.unwrap_or(50);
+
+        // Build the search query
+        let sql = if search.mailbox.is_some() {
+            r#"
+                SELECT 
+                    e.id,
+                    e.to_address,
+                    e.from_address,
+                    e.subject,
+                    e.timestamp,
+                    snippet(emails_fts, 3, '<mark>', '</mark>', '...', 64) as snippet,
+                    rank
+                FROM emails_fts
+                JOIN emails e ON emails_fts.rowid = e.rowid
+                WHERE emails_fts MATCH ?
+                AND e.to_address = ?
+                AND e.deleted_at IS NULL
+                ORDER BY rank
+                LIMIT ?
+                "#
+            .to_string()
+        } else {
+            r#"
+                SELECT 
+                    e.id,
+                    e.to_address,
+                    e.from_address,
+                    e.subject,
+                    e.timestamp,
+                    snippet(emails_fts, 3, '<mark>', '</mark>', '...', 64) as snippet,
+                    rank
+                FROM emails_fts
+                JOIN emails e ON emails_fts.rowid = e.rowid
+                WHERE emails_fts MATCH ?
+                AND e.deleted_at IS NULL
+                ORDER BY rank
+                LIMIT ?
+                "#
+            .to_string()
+        };
+
+        let rows = if let Some(mailbox) = &search.mailbox {
+            sqlx::query_as::<_, (String, String, String, String, String, String, f64)>(&sql)
+                .bind(&search.query)
+                .bind(mailbox)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?
+        } else {
+            sqlx::query_as::<_, (String, String, String, String, String, String, f64)>(&sql)
+                .bind(&search.query)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?
+        };
+
+        let results: Vec<SearchResult> = rows
+            .into_iter()
+            .map(
+                |(id, to, from, subject, timestamp, snippet, rank)| SearchResult {
+                    id,
+                    to,
+                    from,
+                    subject,
+                    snippet,
+                    timestamp,
+                    rank,
+                },
+            )
+            .collect();
+
+        info!(
+            "Search query '{}' returned {} results",
+            search.query,
+            results.len()
+        );
+
+        Ok(results)
+    }
+
+    async fn search_emails_for_address(&self, address: &str, query: &str) -> Result<Vec<Email>> {
+        let fts_query = to_fts5_prefix_query(query);
+        if fts_query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as::<_, EmailRow>(
+            r#"
+            SELECT e.id, e.to_address, e.from_address, e.subject, e.body, e.timestamp, e.raw, e.attachments, e.read, e.flags, e.cc, e.reply_to, e.body_text, e.body_html, e.uid, e.message_id, e.spf_result, e.dkim_result, e.in_reply_to, e.email_references, e.thread_id, e.size_bytes, e.snippet, e.from_name, e.modseq
+            FROM emails_fts
+            JOIN emails e ON emails_fts.rowid = e.rowid
+            WHERE emails_fts MATCH ?
+            AND e.to_address = ?
+            AND e.deleted_at IS NULL
+            ORDER BY e.timestamp DESC
+            "#,
+        )
+        .bind(&fts_query)
+        .bind(address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(email_from_row).collect())
+    }
+
+    async fn store_sent_email(&self, email: SentEmail) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sent_emails (id, from_address, to_address, subject, body_text, body_html, timestamp, message_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&email.id)
+        .bind(&email.from)
+        .bind(&email.to)
+        .bind(&email.subject)
+        .bind(&email.body_text)
+        .bind(&email.body_html)
+        .bind(email.timestamp.to_rfc3339())
+        .bind(&email.message_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_sent_emails(&self, from_address: &str) -> Result<Vec<SentEmail>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, Option<String>, String, String)>(
+            r#"
+            SELECT id, from_address, to_address, subject, body_text, body_html, timestamp, message_id
+            FROM sent_emails
+            WHERE from_address = ?
+            ORDER BY timestamp DESC
+            "#,
+        )
+        .bind(from_address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let emails = rows
+            .into_iter()
+            .map(
+                |(id, from, to, subject, body_text, body_html, timestamp, message_id)| SentEmail {
+                    id,
+                    from,
+                    to,
+                    subject,
+                    body_text,
+                    body_html,
+                    timestamp: timestamp
+                        .parse::<DateTime<Utc>>()
+                        .unwrap_or_else(|_| Utc::now()),
+                    message_id,
+                },
+            )
+            .collect();
+
+        Ok(emails)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::models::{Attachment, Email, EmailFilter, ForwardingRule};
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    async fn create_test_backend() -> SqliteBackend {
+        // Use in-memory database for tests
+        let database_url = "sqlite::memory:";
+        SqliteBackend::new(database_url).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backend_creation() {
+        let _backend = create_test_backend().await;
+        // If we get here without panicking, the backend was created successfully
+        assert!(true);
+    }
+
+    #[tokio::test]
+    async fn test_store_and_retrieve_email() {
+        let backend = create_test_backend().await;
+
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Test Subject".to_string(),
+            "Test body content".to_string(),
+            Some("raw email content".to_string()),
+            vec![],
+        );
+
+        // Store the email
+        backend.store_email(email.clone()).await.unwrap();
+
+        // Retrieve by address
+        let emails = backend
+            .get_emails_for_address("test@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].id, email.id);
+        assert_eq!(emails[0].to, email.to);
+        assert_eq!(emails[0].from, email.from);
+        assert_eq!(emails[0].subject, email.subject);
+        assert_eq!(emails[0].body, email.body);
+        assert_eq!(emails[0].raw, email.raw);
+
+        // Retrieve by ID
+        let retrieved_email = backend.get_email_by_id(&email.id).await.unwrap();
+        assert!(retrieved_email.is_some());
+        let retrieved_email = retrieved_email.unwrap();
+        assert_eq!(retrieved_email.id, email.id);
+        assert_eq!(retrieved_email.to, email.to);
+    }
+
+    #[tokio::test]
+    async fn test_size_bytes_matches_raw_message() {
+        const FIXTURE: &[u8] = include_bytes!("../dkim/testdata/signed.eml");
+        let backend = create_test_backend().await;
+
+        let email = crate::smtp::parser::parse_email(FIXTURE, "test@example.com").unwrap();
+        backend.store_email(email.clone()).await.unwrap();
+
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.size_bytes, FIXTURE.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_snippet_is_stored_and_computed_for_old_rows() {
+        let backend = create_test_backend().await;
+
+        let mut email = Email::new(
+            "preview@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Preview".to_string(),
+            "<p>Hello <i>there</i></p>".to_string(),
+            None,
+            vec![],
+        );
+        email.body_html = Some(email.body.clone());
+        backend.store_email(email.clone()).await.unwrap();
+
+        let emails = backend
+            .get_emails_for_address("preview@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails[0].snippet, "Hello there");
+
+        // Rows stored before snippets were kept have no snippet column value
+        sqlx::query("UPDATE emails SET snippet = NULL WHERE id = ?")
+            .bind(&email.id)
+            .execute(&backend.pool)
+            .await
+            .unwrap();
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.snippet, "Hello there");
+    }
+
+    #[tokio::test]
+    async fn test_store_email_with_cc_and_reply_to() {
+        let backend = create_test_backend().await;
+
+        let mut email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Copies".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email.cc = vec![
+            "copy1@example.com".to_string(),
+            "copy2@example.com".to_string(),
+        ];
+        email.reply_to = Some("replies@example.com".to_string());
+        backend.store_email(email.clone()).await.unwrap();
+
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.cc, email.cc);
+        assert_eq!(fetched.reply_to, email.reply_to);
+    }
+
+    #[tokio::test]
+    async fn test_storage_stats() {
+        let backend = create_test_backend().await;
+        let stats = backend.storage_stats().await.unwrap();
+        assert_eq!(stats.total_emails, 0);
+        assert_eq!(stats.total_bytes, 0);
+        assert_eq!(stats.oldest_email, None);
+
+        let attachment = Attachment {
+            filename: "a.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            size: 4,
+            content: "dGVzdA==".to_string(),
+            scan_result: None,
+            path: None,
+            dropped: false,
+            content_hash: None,
+        };
+        let now = Utc::now();
+        let mut emails = Vec::new();
+        for (to, hours_ago, attachments) in [
+            (
+                "one@example.com",
+                3,
+                vec![attachment.clone(), attachment.clone()],
+            ),
+            ("one@example.com", 2, vec![]),
+            ("two@example.com", 1, vec![attachment]),
+        ] {
+            let mut email = Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                attachments,
+            );
+            email.timestamp = now - Duration::hours(hours_ago);
+            backend.store_email(email.clone()).await.unwrap();
+            emails.push(email);
+        }
+        // Trashed emails are not counted
+        let trashed = Email::new(
+            "three@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Subject".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(trashed.clone()).await.unwrap();
+        backend.delete_email(&trashed.id).await.unwrap();
+
+        let stats = backend.storage_stats().await.unwrap();
+        assert_eq!(stats.total_emails, 3);
+        assert_eq!(stats.total_mailboxes, 2);
+        assert_eq!(stats.total_attachments, 3);
+        assert_eq!(
+            stats.total_bytes,
+            emails.iter().map(Email::stored_size).sum::<i64>()
+        );
+        assert_eq!(
+            stats.oldest_email.unwrap().timestamp(),
+            emails[0].timestamp.timestamp()
+        );
+        assert_eq!(
+            stats.newest_email.unwrap().timestamp(),
+            emails[2].timestamp.timestamp()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sender_summary() {
+        let backend = create_test_backend().await;
+        let now = Utc::now();
+        let store = |from: &str, to: &str, hours_ago: i64| {
+            let mut email = Email::new(
+                to.to_string(),
+                from.to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = now - Duration::hours(hours_ago);
+            email
+        };
+
+        for hours_ago in [5, 3, 1] {
+            backend
+                .store_email(store("alice@example.com", "box@example.com", hours_ago))
+                .await
+                .unwrap();
+        }
+        for hours_ago in [4, 2] {
+            backend
+                .store_email(store("bob@example.com", "box@example.com", hours_ago))
+                .await
+                .unwrap();
+        }
+        // Trashed emails and other mailboxes don't count
+        let trashed = store("bob@example.com", "box@example.com", 0);
+        let trashed_id = trashed.id.clone();
+        backend.store_email(trashed).await.unwrap();
+        backend.delete_email(&trashed_id).await.unwrap();
+        backend
+            .store_email(store("bob@example.com", "other@example.com", 0))
+            .await
+            .unwrap();
+
+        let senders = backend
+            .sender_summary("box@example.com", None)
+            .await
+            .unwrap();
+        assert_eq!(senders.len(), 2);
+        assert_eq!(senders[0].from, "alice@example.com");
+        assert_eq!(senders[0].count, 3);
+        assert_eq!(
+            senders[0].last_seen.timestamp(),
+            (now - Duration::hours(1)).timestamp()
+        );
+        assert_eq!(senders[1].from, "bob@example.com");
+        assert_eq!(senders[1].count, 2);
+        assert_eq!(
+            senders[1].last_seen.timestamp(),
+            (now - Duration::hours(2)).timestamp()
+        );
+
+        let top = backend
+            .sender_summary("box@example.com", Some(1))
+            .await
+            .unwrap();
+        assert_eq!(top, senders[..1]);
+    }
+
+    #[tokio::test]
+    async fn test_query_emails() {
+        let backend = create_test_backend().await;
+
+        let now = Utc::now();
+        for (from, subject, hours_old) in [
+            ("alice@example.com", "Report", 10),
+            ("bob@example.com", "Weekly report", 5),
+            ("alice@example.com", "Lunch", 1),
+        ] {
+            let mut email = Email::new(
+                "test@example.com".to_string(),
+                from.to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = now - Duration::hours(hours_old);
+            backend.store_email(email).await.unwrap();
+        }
+
+        let query = |filter: EmailFilter| {
+            let backend = &backend;
+            async move {
+                let (emails, total) = backend
+                    .query_emails("test@example.com", &filter)
+                    .await
+                    .unwrap();
+                let subjects: Vec<String> = emails.into_iter().map(|e| e.subject).collect();
+                (subjects, total)
+            }
+        };
+        let page = EmailFilter {
+            limit: 10,
+            ..Default::default()
+        };
+
+        let (subjects, total) = query(page.clone()).await;
+        assert_eq!(subjects, vec!["Lunch", "Weekly report", "Report"]);
+        assert_eq!(total, 3);
+
+        let (subjects, _) = query(EmailFilter {
+            from: Some("ALICE".to_string()),
+            ..page.clone()
+        })
+        .await;
+        assert_eq!(subjects, vec!["Lunch", "Report"]);
+
+        let (subjects, _) = query(EmailFilter {
+            subject_contains: Some("report".to_string()),
+            ..page.clone()
+        })
+        .await;
+        assert_eq!(subjects, vec!["Weekly report", "Report"]);
+
+        let (subjects, _) = query(EmailFilter {
+            since: Some(now - Duration::hours(6)),
+            until: Some(now - Duration::hours(2)),
+            ..page.clone()
+        })
+        .await;
+        assert_eq!(subjects, vec!["Weekly report"]);
+
+        let (subjects, total) = query(EmailFilter {
+            from: Some("alice".to_string()),
+            subject_contains: Some("report".to_string()),
+            limit: 1,
+            offset: 1,
+            ..page.clone()
+        })
+        .await;
+        assert!(subjects.is_empty());
+        assert_eq!(total, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_emails_for_address() {
+        let backend = create_test_backend().await;
+
+        let new_email = |to: &str| {
+            Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                "Hello".to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            )
+        };
+        let mut old = new_email("test@example.com");
+        old.timestamp = Utc::now() - Duration::hours(48);
+        let recent = new_email("test@example.com");
+        let other = new_email("other@example.com");
+        for email in [&old, &recent, &other] {
+            backend.store_email(email.clone()).await.unwrap();
+        }
+
+        let deleted = backend
+            .delete_emails_for_address("test@example.com", Some(24))
+            .await
+            .unwrap();
+        assert_eq!(
+            deleted,
+            vec![(old.id.clone(), "test@example.com".to_string())]
+        );
+
+        let deleted = backend
+            .delete_emails_for_address("test@example.com", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            deleted,
+            vec![(recent.id.clone(), "test@example.com".to_string())]
+        );
+        assert!(backend
+            .get_emails_for_address("test@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(backend.get_email_by_id(&other.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reply_chain_shares_thread() {
+        let backend = create_test_backend().await;
+
+        let new_email = |subject: &str, message_id: &str| {
+            let mut email = Email::new(
+                "test@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.message_id = Some(message_id.to_string());
+            email
+        };
+
+        let mut first = new_email("Plans", "first@example.com");
+        first.timestamp = Utc::now() - Duration::minutes(2);
+        let mut second = new_email("Re: Plans", "second@example.com");
+        second.timestamp = Utc::now() - Duration::minutes(1);
+        second.in_reply_to = Some("first@example.com".to_string());
+        second.references = vec!["first@example.com".to_string()];
+        // Some clients only send In-Reply-To; the parent's thread is looked up
+        let mut third = new_email("Re: Re: Plans", "third@example.com");
+        third.in_reply_to = Some("second@example.com".to_string());
+        let unrelated = new_email("Other", "other@example.com");
+
+        for email in [&first, &second, &third, &unrelated] {
+            backend.store_email(email.clone()).await.unwrap();
+        }
+
+        let thread = backend.get_thread("first@example.com").await.unwrap();
+        let ids: Vec<&str> = thread.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec![&first.id, &second.id, &third.id]);
+        assert!(thread
+            .iter()
+            .all(|e| e.thread_id.as_deref() == Some("first@example.com")));
+        assert_eq!(thread[1].in_reply_to, second.in_reply_to);
+        assert_eq!(thread[1].references, second.references);
+
+        let fetched = backend
+            .get_email_by_id(&unrelated.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.thread_id.as_deref(), Some("other@example.com"));
+
+        // Trashed emails drop out of the thread
+        backend.delete_email(&second.id).await.unwrap();
+        assert_eq!(
+            backend.get_thread("first@example.com").await.unwrap().len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_email_with_authentication_results() {
+        let backend = create_test_backend().await;
+
+        let mut email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Authenticated".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email.spf_result = Some("pass".to_string());
+        email.dkim_result = Some("fail".to_string());
+        backend.store_email(email.clone()).await.unwrap();
+
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.spf_result.as_deref(), Some("pass"));
+        assert_eq!(fetched.dkim_result.as_deref(), Some("fail"));
+    }
+
+    #[tokio::test]
+    async fn test_store_email_skips_duplicate_message_id() {
+        let backend = create_test_backend().await;
+
+        let mut email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Once".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        email.message_id = Some("once@example.com".to_string());
+        assert!(backend.store_email(email.clone()).await.unwrap());
+        let uid_next = backend
+            .get_mailbox_uid_state("test@example.com")
+            .await
+            .unwrap()
+            .uid_next;
+
+        // A second copy is not stored and doesn't use up a UID
+        let mut copy = email.clone();
+        copy.id = uuid::Uuid::new_v4().to_string();
+        assert!(!backend.store_email(copy.clone()).await.unwrap());
+        assert!(backend.get_email_by_id(&copy.id).await.unwrap().is_none());
+        assert_eq!(
+            backend
+                .get_mailbox_uid_state("test@example.com")
+                .await
+                .unwrap()
+                .uid_next,
+            uid_next
+        );
+
+        // Emails without a Message-ID never clash
+        let mut anonymous = email.clone();
+        anonymous.message_id = None;
+        for _ in 0..2 {
+            anonymous.id = uuid::Uuid::new_v4().to_string();
+            assert!(backend.store_email(anonymous.clone()).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_email_with_text_and_html_bodies() {
+        let backend = create_test_backend().await;
+
+        let mut email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Alternatives".to_string(),
+            "<p>Hello</p>".to_string(),
+            None,
+            vec![],
+        );
+        email.body_text = Some("Hello".to_string());
+        email.body_html = Some("<p>Hello</p>".to_string());
+        backend.store_email(email.clone()).await.unwrap();
+
+        let fetched = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fetched.body_text, email.body_text);
+        assert_eq!(fetched.body_html, email.body_html);
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_read() {
+        let backend = create_test_backend().await;
+
+        let email = Email::new(
+            "reader@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Unread".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(email.clone()).await.unwrap();
+
+        let stored = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert!(!stored.read);
+
+        backend.mark_email_read(&email.id, true).await.unwrap();
+        let stored = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert!(stored.read);
+
+        backend.mark_email_read(&email.id, false).await.unwrap();
+        let emails = backend
+            .get_emails_for_address("reader@example.com")
+            .await
+            .unwrap();
+        assert!(!emails[0].read);
+    }
+
+    #[tokio::test]
+    async fn test_set_email_flags() {
+        let backend = create_test_backend().await;
+
+        let email = Email::new(
+            "flags@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Flag me".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(email.clone()).await.unwrap();
+
+        backend
+            .set_email_flags(&email.id, &["\\Flagged".to_string()])
+            .await
+            .unwrap();
+        let stored = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(stored.flags, vec!["\\Flagged".to_string()]);
+        assert!(!stored.read);
+
+        backend.set_email_flags(&email.id, &[]).await.unwrap();
+        let stored = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert!(stored.flags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        use crate::storage::models::User;
+
+        let backend = create_test_backend().await;
+        assert!(backend.list_users().await.unwrap().is_empty());
+
+        let mut admin = User::new("admin@example.com".to_string(), "hash".to_string());
+        admin.is_admin = true;
+        admin.created_at = Utc::now() - Duration::hours(1);
+        let user = User::new("user@example.com".to_string(), "hash".to_string());
+        backend.create_user(user).await.unwrap();
+        backend.create_user(admin).await.unwrap();
+
+        let users = backend.list_users().await.unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].email, "admin@example.com");
+        assert!(users[0].is_admin);
+        assert_eq!(users[1].email, "user@example.com");
+        assert!(!users[1].is_admin);
+    }
+
+    #[tokio::test]
+    async fn test_update_user_password_and_revoke_refresh_tokens() {
+        use crate::storage::models::{RefreshToken, User};
+
+        let backend = create_test_backend().await;
+        let user = User::new("user@example.com".to_string(), "old-hash".to_string());
+        backend.create_user(user.clone()).await.unwrap();
+
+        assert!(backend
+            .update_user_password(&user.id, "new-hash")
+            .await
+            .unwrap());
+        let fetched = backend.get_user_by_id(&user.id).await.unwrap().unwrap();
+        assert_eq!(fetched.password_hash, "new-hash");
+        assert!(!backend
+            .update_user_password("missing-user", "new-hash")
+            .await
+            .unwrap());
+
+        for (token_hash, user_id) in [
+            ("a", &user.id),
+            ("b", &user.id),
+            ("c", &"other".to_string()),
+        ] {
+            backend
+                .store_refresh_token(RefreshToken {
+                    token_hash: token_hash.to_string(),
+                    user_id: user_id.clone(),
+                    expires_at: Utc::now() + Duration::days(30),
+                    created_at: Utc::now(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            backend
+                .delete_refresh_tokens_for_user(&user.id)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(backend.get_refresh_token("a").await.unwrap().is_none());
+        assert!(backend.get_refresh_token("c").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_revoked_tokens_are_pruned_after_expiry() {
+        let backend = create_test_backend().await;
+        let now = Utc::now();
+        backend
+            .revoke_token("expired", now - Duration::minutes(1))
+            .await
+            .unwrap();
+        backend
+            .revoke_token("current", now + Duration::hours(1))
+            .await
+            .unwrap();
+        // Revoking twice is not an error
+        backend
+            .revoke_token("current", now + Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(backend.is_token_revoked("expired").await.unwrap());
+        assert!(!backend.is_token_revoked("unknown").await.unwrap());
+
+        assert_eq!(backend.prune_revoked_tokens(now).await.unwrap(), 1);
+        assert!(!backend.is_token_revoked("expired").await.unwrap());
+        assert!(backend.is_token_revoked("current").await.unwrap());
     }
 
     #[tokio::test]
-    async fn test_store_and_retrieve_email() {
-        let backend = create_test_backend().await;
-
-        let email = Email::new(
-            "test@example.com".to_string(),
-            "sender@example.com".to_string(),
-            "Test Subject".to_string(),
-            "Test body content".to_string(),
-            Some("raw email content".to_string()),
-            vec![],
+    async fn test_concurrent_writes_with_custom_pool_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let database_url = format!("sqlite:{}", temp_dir.path().join("busy.db").display());
+        let backend = Arc::new(
+            SqliteBackend::with_max_connections(&database_url, 12)
+                .await
+                .unwrap(),
         );
 
-        // Store the email
-        backend.store_email(email.clone()).await.unwrap();
+        let writers: Vec<_> = (0..48)
+            .map(|i| {
+                let backend = backend.clone();
+                tokio::spawn(async move {
+                    let email = Email::new(
+                        format!("busy{}@example.com", i % 4),
+                        "sender@example.com".to_string(),
+                        format!("Message {}", i),
+                        "Body".to_string(),
+                        None,
+                        vec![],
+                    );
+                    backend.store_email(email).await
+                })
+            })
+            .collect();
+        for writer in writers {
+            // Each write waits for the lock instead of failing with "database is locked"
+            writer.await.unwrap().unwrap();
+        }
 
-        // Retrieve by address
-        let emails = backend
-            .get_emails_for_address("test@example.com")
+        for i in 0..4 {
+            let emails = backend
+                .get_emails_for_address(&format!("busy{}@example.com", i))
+                .await
+                .unwrap();
+            assert_eq!(emails.len(), 12);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrates_emails_table_without_read_column() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let database_url = format!("sqlite:{}", temp_dir.path().join("old.db").display());
+
+        // Create a database with the original emails schema
+        {
+            let options = SqliteConnectOptions::from_str(&database_url)
+                .unwrap()
+                .create_if_missing(true);
+            let pool = SqlitePoolOptions::new()
+                .connect_with(options)
+                .await
+                .unwrap();
+            sqlx::query(
+                r#"
+                CREATE TABLE emails (
+                    id TEXT PRIMARY KEY,
+                    to_address TEXT NOT NULL,
+                    from_address TEXT NOT NULL,
+                    subject TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    timestamp TEXT NOT NULL,
+                    raw TEXT,
+                    attachments TEXT
+                )
+                "#,
+            )
+            .execute(&pool)
             .await
             .unwrap();
-        assert_eq!(emails.len(), 1);
-        assert_eq!(emails[0].id, email.id);
-        assert_eq!(emails[0].to, email.to);
-        assert_eq!(emails[0].from, email.from);
-        assert_eq!(emails[0].subject, email.subject);
-        assert_eq!(emails[0].body, email.body);
-        assert_eq!(emails[0].raw, email.raw);
+            sqlx::query(
+                "INSERT INTO emails VALUES ('old', 'a@example.com', 'b@example.com', 's', 'b', ?, NULL, NULL)",
+            )
+            .bind(Utc::now().to_rfc3339())
+            .execute(&pool)
+            .await
+            .unwrap();
+            pool.close().await;
+        }
 
-        // Retrieve by ID
-        let retrieved_email = backend.get_email_by_id(&email.id).await.unwrap();
-        assert!(retrieved_email.is_some());
-        let retrieved_email = retrieved_email.unwrap();
-        assert_eq!(retrieved_email.id, email.id);
-        assert_eq!(retrieved_email.to, email.to);
+        let backend = SqliteBackend::new(&database_url).await.unwrap();
+        let email = backend.get_email_by_id("old").await.unwrap().unwrap();
+        assert!(!email.read);
+        assert!(email.flags.is_empty());
+        assert!(email.cc.is_empty());
+        assert_eq!(email.reply_to, None);
+        assert_eq!(email.body_text, None);
+        assert_eq!(email.body_html, None);
+        assert_eq!(email.uid, Some(1));
+        let state = backend
+            .get_mailbox_uid_state("a@example.com")
+            .await
+            .unwrap();
+        assert_eq!(state.uid_next, 2);
     }
 
     #[tokio::test]
@@ -1198,12 +3618,20 @@ mod tests {
                 content_type: "text/plain".to_string(),
                 size: 100,
                 content: "dGVzdCBjb250ZW50".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
             },
             Attachment {
                 filename: "test.pdf".to_string(),
                 content_type: "application/pdf".to_string(),
                 size: 200,
                 content: "cGRmIGNvbnRlbnQ=".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
             },
         ];
 
@@ -1290,6 +3718,370 @@ mod tests {
         assert!(ids.contains(&email2.id));
     }
 
+    #[tokio::test]
+    async fn test_delete_and_restore_email() {
+        let backend = create_test_backend().await;
+        let email = Email::new(
+            "trash@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Oops".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(email.clone()).await.unwrap();
+
+        backend.delete_email(&email.id).await.unwrap();
+        assert!(backend
+            .get_emails_for_address("trash@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(backend.get_email_by_id(&email.id).await.unwrap().is_none());
+        assert_eq!(
+            backend
+                .count_emails_for_address("trash@example.com")
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(backend
+            .get_deleted_email_by_id(&email.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        assert!(backend.restore_email(&email.id).await.unwrap());
+        let emails = backend
+            .get_emails_for_address("trash@example.com")
+            .await
+            .unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].id, email.id);
+        assert!(backend
+            .get_deleted_email_by_id(&email.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Restoring an email that isn't in the trash does nothing
+        assert!(!backend.restore_email(&email.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_filesystem_attachments_are_stored_and_purged() {
+        use crate::storage::attachments::AttachmentStore;
+
+        let dir = tempfile::tempdir().unwrap();
+        let backend = create_test_backend()
+            .await
+            .with_attachment_store(AttachmentStore::Filesystem(dir.path().to_path_buf()));
+        let email = Email::new(
+            "files@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "With file".to_string(),
+            "Body".to_string(),
+            None,
+            vec![Attachment {
+                filename: "a.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                size: 5,
+                content: "aGVsbG8=".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
+            }],
+        );
+        backend.store_email(email.clone()).await.unwrap();
+
+        // Only the metadata and path are kept in the row
+        let stored = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        let attachment = &stored.attachments[0];
+        assert_eq!(attachment.content, "");
+        assert_eq!(attachment.size, 5);
+        let path = attachment.path.clone().unwrap();
+        assert_eq!(backend.read_attachment_file(&path).await.unwrap(), b"hello");
+
+        backend.delete_email(&email.id).await.unwrap();
+        sqlx::query("UPDATE emails SET deleted_at = ? WHERE id = ?")
+            .bind((Utc::now() - Duration::hours(25)).to_rfc3339())
+            .bind(&email.id)
+            .execute(&backend.pool)
+            .await
+            .unwrap();
+        assert_eq!(backend.purge_deleted_emails(24).await.unwrap(), 1);
+        assert!(!dir.path().join(&email.id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_emails() {
+        let backend = create_test_backend().await;
+        let recent = Email::new(
+            "trash@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Recently deleted".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        let expired = Email::new(
+            "trash@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Deleted long ago".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(recent.clone()).await.unwrap();
+        backend.store_email(expired.clone()).await.unwrap();
+        backend.delete_email(&recent.id).await.unwrap();
+        backend.delete_email(&expired.id).await.unwrap();
+
+        // Backdate one deletion past the trash window
+        sqlx::query("UPDATE emails SET deleted_at = ? WHERE id = ?")
+            .bind((Utc::now() - Duration::hours(25)).to_rfc3339())
+            .bind(&expired.id)
+            .execute(&backend.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(backend.purge_deleted_emails(24).await.unwrap(), 1);
+        assert!(backend
+            .get_deleted_email_by_id(&expired.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!backend.restore_email(&expired.id).await.unwrap());
+
+        // The recently deleted email can still be restored
+        assert!(backend.restore_email(&recent.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_uids_stable_after_delete() {
+        let backend = create_test_backend().await;
+
+        let mut ids = Vec::new();
+        for subject in ["One", "Two", "Three"] {
+            let email = Email::new(
+                "uids@example.com".to_string(),
+                "sender@example.com".to_string(),
+                subject.to_string(),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            ids.push(email.id.clone());
+            backend.store_email(email).await.unwrap();
+        }
+        let state = backend
+            .get_mailbox_uid_state("uids@example.com")
+            .await
+            .unwrap();
+        assert_eq!(state.uid_next, 4);
+
+        backend.delete_email(&ids[1]).await.unwrap();
+
+        let first = backend.get_email_by_id(&ids[0]).await.unwrap().unwrap();
+        let third = backend.get_email_by_id(&ids[2]).await.unwrap().unwrap();
+        assert_eq!(first.uid, Some(1));
+        assert_eq!(third.uid, Some(3));
+
+        // Deleted UIDs are never reused and UIDVALIDITY doesn't change
+        let email = Email::new(
+            "uids@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Four".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(email.clone()).await.unwrap();
+        let fourth = backend.get_email_by_id(&email.id).await.unwrap().unwrap();
+        assert_eq!(fourth.uid, Some(4));
+        let after = backend
+            .get_mailbox_uid_state("uids@example.com")
+            .await
+            .unwrap();
+        assert_eq!(after.uid_next, 5);
+        assert_eq!(after.uid_validity, state.uid_validity);
+
+        // Each mailbox numbers its own UIDs
+        let other = Email::new(
+            "other@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Other".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(other.clone()).await.unwrap();
+        let other = backend.get_email_by_id(&other.id).await.unwrap().unwrap();
+        assert_eq!(other.uid, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_query_emails_paginated() {
+        let backend = create_test_backend().await;
+
+        // Store 5 emails with distinct timestamps, oldest first
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            let mut email = Email::new(
+                "page@example.com".to_string(),
+                "sender@example.com".to_string(),
+                format!("Subject {}", i),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = Utc::now() - Duration::minutes(10 - i);
+            ids.push(email.id.clone());
+            backend.store_email(email).await.unwrap();
+        }
+
+        assert_eq!(
+            backend
+                .count_emails_for_address("page@example.com")
+                .await
+                .unwrap(),
+            5
+        );
+
+        // First page holds the two newest emails
+        let filter = EmailFilter {
+            limit: 2,
+            offset: 0,
+            ..Default::default()
+        };
+        let (page, total) = backend
+            .query_emails("page@example.com", &filter)
+            .await
+            .unwrap();
+        assert_eq!(total, 5);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].id, ids[4]);
+        assert_eq!(page[1].id, ids[3]);
+
+        // Last page is partial
+        let filter = EmailFilter {
+            limit: 2,
+            offset: 4,
+            ..Default::default()
+        };
+        let (page, total) = backend
+            .query_emails("page@example.com", &filter)
+            .await
+            .unwrap();
+        assert_eq!(total, 5);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, ids[0]);
+    }
+
+    #[tokio::test]
+    async fn test_total_bytes_for_address() {
+        let backend = create_test_backend().await;
+        assert_eq!(
+            backend
+                .total_bytes_for_address("size@example.com")
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Raw messages count when present, otherwise the body
+        let with_raw = Email::new(
+            "size@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Raw".to_string(),
+            "body".to_string(),
+            Some("Subject: Raw\r\n\r\nbödy".to_string()),
+            vec![],
+        );
+        let without_raw = Email::new(
+            "size@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "No raw".to_string(),
+            "twelve bytes".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(with_raw.clone()).await.unwrap();
+        backend.store_email(without_raw.clone()).await.unwrap();
+
+        assert_eq!(
+            backend
+                .total_bytes_for_address("size@example.com")
+                .await
+                .unwrap(),
+            with_raw.stored_size() + 12
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_emails_for_address() {
+        let backend = create_test_backend().await;
+
+        let invoice = Email::new(
+            "search@example.com".to_string(),
+            "billing@example.com".to_string(),
+            "Monthly Invoice".to_string(),
+            "Your invoice is attached".to_string(),
+            None,
+            vec![],
+        );
+        let meeting = Email::new(
+            "search@example.com".to_string(),
+            "boss@example.com".to_string(),
+            "Meeting".to_string(),
+            "Agenda for tomorrow".to_string(),
+            None,
+            vec![],
+        );
+        let other_mailbox = Email::new(
+            "other@example.com".to_string(),
+            "billing@example.com".to_string(),
+            "Invoice".to_string(),
+            "Not yours".to_string(),
+            None,
+            vec![],
+        );
+        backend.store_email(invoice.clone()).await.unwrap();
+        backend.store_email(meeting.clone()).await.unwrap();
+        backend.store_email(other_mailbox).await.unwrap();
+
+        let results = backend
+            .search_emails_for_address("search@example.com", "invoice")
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, invoice.id);
+
+        // Prefix match on body text
+        let results = backend
+            .search_emails_for_address("search@example.com", "agen")
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, meeting.id);
+
+        // FTS5 operators in user input are treated literally
+        let results = backend
+            .search_emails_for_address("search@example.com", "\"unbalanced")
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+
+        // Deleted emails drop out of the index
+        backend.delete_email(&invoice.id).await.unwrap();
+        let results = backend
+            .search_emails_for_address("search@example.com", "invoice")
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
     #[tokio::test]
     async fn test_delete_old_emails() {
         let backend = create_test_backend().await;
@@ -1363,6 +4155,88 @@ mod tests {
         assert_eq!(deleted_details[0].1, old_email.to);
     }
 
+    #[tokio::test]
+    async fn test_delete_expired_emails_per_mailbox_retention() {
+        let backend = create_test_backend().await;
+        let email_aged = |to: &str, hours: i64| {
+            let mut email = Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                format!("{} hours old", hours),
+                "Body".to_string(),
+                None,
+                vec![],
+            );
+            email.timestamp = Utc::now() - Duration::hours(hours);
+            email
+        };
+
+        // "keep" holds mail for three days, "other" follows the global 24 hours
+        backend
+            .set_mailbox_retention("keep@example.com", Some(72))
+            .await
+            .unwrap();
+        assert_eq!(
+            backend
+                .get_mailbox_retention("keep@example.com")
+                .await
+                .unwrap(),
+            Some(72)
+        );
+        assert_eq!(
+            backend
+                .get_mailbox_retention("other@example.com")
+                .await
+                .unwrap(),
+            None
+        );
+        let kept = email_aged("keep@example.com", 30);
+        let expired = email_aged("keep@example.com", 80);
+        let other = email_aged("other@example.com", 30);
+        for email in [&kept, &expired, &other] {
+            backend.store_email(email.clone()).await.unwrap();
+        }
+
+        let mut deleted = backend.delete_expired_emails(Some(24)).await.unwrap();
+        deleted.sort();
+        let mut expected = vec![
+            (expired.id.clone(), expired.to.clone()),
+            (other.id.clone(), other.to.clone()),
+        ];
+        expected.sort();
+        assert_eq!(deleted, expected);
+        let remaining = backend
+            .get_emails_for_address("keep@example.com")
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, kept.id);
+
+        // Without a global retention only the override applies
+        let old = email_aged("other@example.com", 1000);
+        backend.store_email(old.clone()).await.unwrap();
+        assert!(backend
+            .delete_expired_emails(None)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Clearing the override puts the mailbox back on the global schedule
+        backend
+            .set_mailbox_retention("keep@example.com", None)
+            .await
+            .unwrap();
+        assert!(backend.get_mailbox_retentions().await.unwrap().is_empty());
+        let mut deleted = backend.delete_expired_emails(Some(24)).await.unwrap();
+        deleted.sort();
+        let mut expected = vec![
+            (kept.id.clone(), kept.to.clone()),
+            (old.id.clone(), old.to.clone()),
+        ];
+        expected.sort();
+        assert_eq!(deleted, expected);
+    }
+
     #[tokio::test]
     async fn test_delete_old_emails_no_old_emails() {
         let backend = create_test_backend().await;
@@ -1407,6 +4281,154 @@ mod tests {
         assert!(emails.is_empty()); // Should not panic, just return empty
     }
 
+    #[tokio::test]
+    async fn test_get_attachments_by_hash() {
+        let backend = create_test_backend().await;
+        let hash = Attachment::hash_content(b"shared");
+        let email = |to: &str, content_hash: Option<String>| {
+            Email::new(
+                to.to_string(),
+                "sender@example.com".to_string(),
+                "Subject".to_string(),
+                "Body".to_string(),
+                None,
+                vec![Attachment {
+                    filename: "shared.txt".to_string(),
+                    content_type: "text/plain".to_string(),
+                    size: 6,
+                    content: "c2hhcmVk".to_string(),
+                    scan_result: None,
+                    path: None,
+                    dropped: false,
+                    content_hash,
+                }],
+            )
+        };
+        let first = email("one@example.com", Some(hash.clone()));
+        let second = email("two@example.com", Some(hash.clone()));
+        let trashed = email("three@example.com", Some(hash.clone()));
+        for email in [&first, &second, &trashed] {
+            backend.store_email(email.clone()).await.unwrap();
+        }
+        // Attachments stored before hashing and emails in the trash are left out
+        backend
+            .store_email(email("four@example.com", None))
+            .await
+            .unwrap();
+        backend.delete_email(&trashed.id).await.unwrap();
+
+        let found = backend.get_attachments_by_hash(&hash).await.unwrap();
+        let mut ids: Vec<&str> = found.iter().map(|(id, _)| id.as_str()).collect();
+        ids.sort();
+        let mut expected = vec![first.id.as_str(), second.id.as_str()];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert!(found
+            .iter()
+            .all(|(_, a)| a.filename == "shared.txt" && a.content_hash == Some(hash.clone())));
+        assert!(backend
+            .get_attachments_by_hash("missing")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_webhooks_is_all_or_nothing() {
+        use crate::storage::models::{Webhook, WebhookEvent};
+
+        let backend = create_test_backend().await;
+        let webhook = |mailbox: &str| {
+            Webhook::new(
+                mailbox.to_string(),
+                "http://localhost/hook".to_string(),
+                vec![WebhookEvent::Arrival],
+            )
+        };
+
+        // The repeated ID fails the second insert, which undoes the first
+        let first = webhook("first");
+        let mut clash = webhook("second");
+        clash.id = first.id.clone();
+        assert!(backend.create_webhooks(vec![first, clash]).await.is_err());
+        assert!(backend
+            .get_webhooks_for_mailbox("first")
+            .await
+            .unwrap()
+            .is_empty());
+
+        backend
+            .create_webhooks(vec![webhook("first"), webhook("second")])
+            .await
+            .unwrap();
+        for mailbox in ["first", "second"] {
+            assert_eq!(
+                backend
+                    .get_webhooks_for_mailbox(mailbox)
+                    .await
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_rules() {
+        let backend = create_test_backend().await;
+        assert!(backend
+            .get_forwarding_rules("temp")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let mut rule = ForwardingRule::new(
+            "temp".to_string(),
+            "me@real.example".to_string(),
+            "smtp.real.example".to_string(),
+            2525,
+        );
+        rule.relay_username = Some("relay-user".to_string());
+        rule.relay_password = Some("relay-pass".to_string());
+        rule.relay_starttls = false;
+        backend.create_forwarding_rule(rule.clone()).await.unwrap();
+
+        let rules = backend.get_forwarding_rules("temp").await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, rule.id);
+        assert_eq!(rules[0].destination, "me@real.example");
+        assert_eq!(rules[0].relay_host, "smtp.real.example");
+        assert_eq!(rules[0].relay_port, 2525);
+        assert_eq!(rules[0].relay_username, rule.relay_username);
+        assert_eq!(rules[0].relay_password, rule.relay_password);
+        assert!(!rules[0].relay_starttls);
+        assert!(backend
+            .get_forwarding_rules("other")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_aliases() {
+        let backend = SqliteBackend::new("sqlite::memory:").await.unwrap();
+        assert!(backend.get_aliases_for("sales").await.unwrap().is_empty());
+
+        backend
+            .create_alias("sales", &["alice".to_string(), "bob".to_string()])
+            .await
+            .unwrap();
+        backend
+            .create_alias("sales", &["bob".to_string()])
+            .await
+            .unwrap();
+
+        let mut destinations = backend.get_aliases_for("sales").await.unwrap();
+        destinations.sort();
+        assert_eq!(destinations, vec!["alice".to_string(), "bob".to_string()]);
+        assert!(backend.get_aliases_for("alice").await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_store_and_retrieve_sent_email() {
         let backend = SqliteBackend::new("sqlite::memory:").await.unwrap();
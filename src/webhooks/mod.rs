@@ -1,21 +1,404 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use minijinja::{value::ValueKind, Environment, Output, State, UndefinedBehavior};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Client;
 use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
 use std::time::Duration;
+use tokio::sync::{broadcast, Semaphore};
 use tokio::time::sleep;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, warn, Instrument};
 
+use crate::config::DEFAULT_WEBHOOK_MAX_CONCURRENT;
 use crate::storage::{
-    models::{Email, Webhook, WebhookEvent},
+    models::{
+        Email, PendingWebhookDelivery, Webhook, WebhookDelivery, WebhookEvent, WebhookFormat,
+    },
     StorageBackend,
 };
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+/// Header carrying the HMAC-SHA256 signature of the request body
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// `User-Agent` sent with deliveries unless the webhook sets its own
+pub const WEBHOOK_USER_AGENT: &str = concat!("dynip-email/", env!("CARGO_PKG_VERSION"));
+
+/// Headers a webhook may not set, since the server or the HTTP client controls them
+const RESERVED_HEADERS: [&str; 6] = [
+    "connection",
+    "content-length",
+    "content-type",
+    "host",
+    "transfer-encoding",
+    "x-webhook-signature",
+];
+
+/// Sign a webhook body with the webhook's secret
+///
+/// Returns `sha256=<hex>` where `<hex>` is the lowercase hex HMAC-SHA256 of the exact
+/// request body bytes, keyed with the secret.
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// Describe an email's attachments for a webhook payload, with their base64 content only
+/// when requested
+fn attachments_payload(email: &Email, include_content: bool) -> Vec<Value> {
+    email
+        .attachments
+        .iter()
+        .map(|attachment| {
+            let mut entry = json!({
+                "filename": attachment.filename,
+                "content_type": attachment.content_type,
+                "size": attachment.size,
+            });
+            if include_content {
+                entry["content"] = json!(attachment.content);
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Longest email body excerpt in a chat message, in characters
+const SNIPPET_CHARS: usize = 200;
+
+/// Start of an email's text on one line, for chat messages
+///
+/// Uses the plain-text part when there is one and otherwise the body with its HTML tags
+/// dropped.
+fn body_snippet(email: &Email) -> String {
+    let text = match (&email.body_text, &email.body_html) {
+        (Some(text), _) => text.clone(),
+        (None, Some(_)) => {
+            let mut text = String::with_capacity(email.body.len());
+            let mut in_tag = false;
+            for c in email.body.chars() {
+                match c {
+                    '<' => in_tag = true,
+                    '>' if in_tag => {
+                        in_tag = false;
+                        text.push(' ');
+                    }
+                    c if !in_tag => text.push(c),
+                    _ => {}
+                }
+            }
+            text
+        }
+        (None, None) => email.body.clone(),
+    };
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > SNIPPET_CHARS {
+        let cut: String = text.chars().take(SNIPPET_CHARS).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        text
+    }
+}
+
+/// Message for Slack and Discord webhooks summarizing an event: what happened in which
+/// mailbox, then the sender, subject and start of the body
+fn chat_payload(
+    format: WebhookFormat,
+    event: &WebhookEvent,
+    email: Option<&Email>,
+    mailbox: &str,
+) -> Value {
+    let bold = |text: &str| match format {
+        WebhookFormat::Slack => format!("*{}*", text),
+        _ => format!("**{}**", text),
+    };
+    let headline = match event {
+        WebhookEvent::Arrival => "📨 New email for",
+        WebhookEvent::AttachmentReceived => "📎 New email with attachments for",
+        WebhookEvent::Deletion => "🗑️ Email deleted from",
+    };
+
+    let mut text = format!("{} {}", headline, bold(mailbox));
+    if let Some(email) = email {
+        text.push_str(&format!("\n{} {}", bold("From:"), email.from));
+        text.push_str(&format!("\n{} {}", bold("Subject:"), email.subject));
+        let snippet = body_snippet(email);
+        if !snippet.is_empty() {
+            text.push_str(&format!("\n> {}", snippet));
+        }
+    }
+
+    match format {
+        WebhookFormat::Discord => json!({ "content": text }),
+        _ => json!({ "text": text }),
+    }
+}
+
+/// Payload sent when a webhook has no template: the event, the mailbox and the email
+fn default_payload(event: &WebhookEvent, email: Option<&Email>, webhook: &Webhook) -> Value {
+    let mut payload = json!({
+        "event": event.as_str(),
+        "mailbox": webhook.mailbox_address,
+        "webhook_id": webhook.id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+
+    if let Some(email) = email {
+        payload["email"] = json!({
+            "id": email.id,
+            "to": email.to,
+            "from": email.from,
+            "subject": email.subject,
+            "body": email.body,
+            "snippet": email.snippet,
+            "timestamp": email.timestamp.to_rfc3339(),
+            "dkim_result": email.dkim_result,
+            "attachments": attachments_payload(email, webhook.include_attachment_content)
+        });
+    }
+
+    payload
+}
+
+/// Render a payload template with the fields of the default payload as its variables
+///
+/// Templates see `event`, `mailbox`, `webhook_id`, `timestamp` and, when there is an email,
+/// `email.subject`, `email.from` and the other email fields. Strings are written JSON-escaped
+/// so they can be placed inside quoted JSON strings, lists and objects are written as JSON,
+/// and missing values render as nothing. The rendered text must be valid JSON.
+pub fn render_payload_template(template: &str, default_payload: &Value) -> Result<Value> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Chainable);
+    env.set_formatter(json_formatter);
+
+    let rendered = env
+        .render_str(template, default_payload)
+        .context("Failed to render payload template")?;
+    serde_json::from_str(&rendered).context("Payload template did not render valid JSON")
+}
+
+/// Example email used to check templates and to preview payloads without a real one
+///
+/// Its sender and subject contain quotes and a line break, so templates that don't escape
+/// them show up as broken JSON.
+pub fn sample_email(to: &str) -> Email {
+    let mut email = Email::new(
+        to.to_string(),
+        "Sender \"Quoted\" <sender@example.com>".to_string(),
+        "Example \"subject\"\nsecond line".to_string(),
+        "Example body".to_string(),
+        None,
+        vec![],
+    );
+    email.dkim_result = Some("pass".to_string());
+    email
+}
+
+/// Check that a payload template renders valid JSON for an example arriving email
+pub fn validate_payload_template(template: &str) -> Result<()> {
+    let email = sample_email("mailbox@example.com");
+    let webhook = Webhook::new(
+        "mailbox".to_string(),
+        "https://example.com/webhook".to_string(),
+        vec![WebhookEvent::Arrival],
+    );
+
+    let payload = default_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+    render_payload_template(template, &payload).map(|_| ())
+}
+
+/// Check that custom webhook headers are valid and don't override reserved ones
+pub fn validate_webhook_headers(headers: &HashMap<String, String>) -> Result<()> {
+    for (name, value) in headers {
+        let header = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name '{}'", name))?;
+        if RESERVED_HEADERS.contains(&header.as_str()) {
+            bail!(
+                "Header '{}' is set by the server and cannot be overridden",
+                name
+            );
+        }
+        HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header '{}'", name))?;
+    }
+    Ok(())
+}
+
+fn json_formatter(
+    out: &mut Output,
+    state: &State,
+    value: &minijinja::Value,
+) -> Result<(), minijinja::Error> {
+    if value.is_undefined() || value.is_none() {
+        return Ok(());
+    }
+    let json = match value.as_str() {
+        Some(s) => {
+            let quoted = Value::from(s).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        }
+        None if matches!(value.kind(), ValueKind::Seq | ValueKind::Map) => {
+            serde_json::to_string(value).map_err(|e| {
+                minijinja::Error::new(minijinja::ErrorKind::BadSerialization, e.to_string())
+            })?
+        }
+        None => return minijinja::escape_formatter(out, state, value),
+    };
+    out.write_str(&json)?;
+    Ok(())
+}
+
+/// Delay before retry number `retry` (1-based): the webhook's initial backoff doubled for
+/// each earlier retry, capped at its maximum backoff
+fn retry_delay(webhook: &Webhook, retry: u32) -> Duration {
+    let factor = 1_u64.checked_shl(retry - 1).unwrap_or(u64::MAX);
+    let secs = webhook
+        .initial_backoff_secs
+        .saturating_mul(factor)
+        .min(webhook.max_backoff_secs);
+    Duration::from_secs(secs)
+}
+
+/// How often queued deliveries are checked for ones that are due
+pub const PENDING_DELIVERY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Queued deliveries retried per poll
+const PENDING_DELIVERY_BATCH: i64 = 100;
+
+/// How long a delivery stays queued before it is given up on
+pub const PENDING_DELIVERY_MAX_AGE: chrono::Duration = chrono::Duration::hours(24);
+
+/// Longest wait between attempts of a queued delivery
+const PENDING_RETRY_MAX_DELAY: Duration = Duration::from_secs(3600);
+
+/// Delay before retrying a queued delivery that has failed `attempts` times: a minute,
+/// doubled for each earlier attempt, capped at an hour
+fn pending_retry_delay(attempts: u32) -> chrono::Duration {
+    let factor = 1_u32
+        .checked_shl(attempts.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    let delay = Duration::from_secs(60)
+        .saturating_mul(factor)
+        .min(PENDING_RETRY_MAX_DELAY);
+    chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::hours(1))
+}
+
+/// Announces deleted emails to WebSocket/SSE clients and to deletion webhooks
+///
+/// API deletes and retention cleanup both go through [`DeletionNotifier::handle_email_deletion`],
+/// so clients and webhooks see the same thing whatever removed the email.
+#[derive(Clone)]
+pub struct DeletionNotifier {
+    webhook_trigger: WebhookTrigger,
+    deletion_sender: broadcast::Sender<(String, String)>,
+}
+
+impl DeletionNotifier {
+    pub fn new(
+        webhook_trigger: WebhookTrigger,
+        deletion_sender: broadcast::Sender<(String, String)>,
+    ) -> Self {
+        Self {
+            webhook_trigger,
+            deletion_sender,
+        }
+    }
+
+    /// Announce that the email `email_id` was deleted from the mailbox `address` (the full
+    /// address the email was delivered to)
+    ///
+    /// Deletion webhooks get the email itself while it is still in the trash.
+    pub async fn handle_email_deletion(&self, email_id: &str, address: &str) {
+        info!(
+            "📤 Broadcasting deletion notification for email {} to address {}",
+            email_id, address
+        );
+        // No subscribers is fine
+        let _ = self
+            .deletion_sender
+            .send((email_id.to_string(), address.to_string()));
+
+        let email = match self
+            .webhook_trigger
+            .storage
+            .get_deleted_email_by_id(email_id)
+            .await
+        {
+            Ok(email) => email,
+            Err(e) => {
+                warn!("⚠️ Failed to load deleted email {}: {}", email_id, e);
+                None
+            }
+        };
+
+        // Webhooks are registered by mailbox name, without the domain
+        let mailbox_name = address.split('@').next().unwrap_or(address);
+        if let Err(e) = self
+            .webhook_trigger
+            .trigger_webhooks(mailbox_name, WebhookEvent::Deletion, email.as_ref())
+            .await
+        {
+            error!("Failed to trigger deletion webhooks: {}", e);
+        }
+    }
+
+    /// Move emails past their retention to the trash, announce each deletion, and return
+    /// the (id, to_address) of each deleted email
+    ///
+    /// `default_hours` applies to mailboxes without a retention of their own, as in
+    /// [`StorageBackend::delete_expired_emails`].
+    pub async fn delete_expired_emails(
+        &self,
+        default_hours: Option<i64>,
+    ) -> Result<Vec<(String, String)>> {
+        let deleted = self
+            .webhook_trigger
+            .storage
+            .delete_expired_emails(default_hours)
+            .await?;
+        for (email_id, address) in &deleted {
+            self.handle_email_deletion(email_id, address).await;
+        }
+        Ok(deleted)
+    }
+}
+
+/// Slots for webhook requests in flight, shared by every `WebhookTrigger`
+static DELIVERY_PERMITS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+/// Limit how many webhook requests are in flight at once across the process
+///
+/// Must be called before the first webhook is triggered; later calls have no effect.
+pub fn set_max_concurrent_deliveries(max: usize) {
+    if DELIVERY_PERMITS.set(Arc::new(Semaphore::new(max))).is_err() {
+        warn!("⚠️ Webhook concurrency limit already set, ignoring {}", max);
+    }
+}
+
+fn delivery_permits() -> Arc<Semaphore> {
+    DELIVERY_PERMITS
+        .get_or_init(|| Arc::new(Semaphore::new(DEFAULT_WEBHOOK_MAX_CONCURRENT)))
+        .clone()
+}
 
 /// Webhook trigger system for sending HTTP POST requests
 #[derive(Clone)]
 pub struct WebhookTrigger {
     client: Client,
     storage: Arc<dyn StorageBackend>,
+    permits: Arc<Semaphore>,
 }
 
 impl WebhookTrigger {
@@ -26,20 +409,53 @@ impl WebhookTrigger {
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { client, storage }
+        Self {
+            client,
+            storage,
+            permits: delivery_permits(),
+        }
     }
 
     /// Trigger webhooks for a specific event and mailbox
+    ///
+    /// An arriving email with attachments also fires `AttachmentReceived` webhooks.
     pub async fn trigger_webhooks(
         &self,
         address: &str,
         event: WebhookEvent,
         email: Option<&Email>,
     ) -> Result<()> {
-        let webhooks = self
+        let has_attachments = email.is_some_and(|email| !email.attachments.is_empty());
+        let attachment_event = event == WebhookEvent::Arrival && has_attachments;
+
+        self.trigger_event(address, event, email).await?;
+        if attachment_event {
+            self.trigger_event(address, WebhookEvent::AttachmentReceived, email)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Deliver one event to the mailbox's subscribed webhooks whose filters match
+    async fn trigger_event(
+        &self,
+        address: &str,
+        event: WebhookEvent,
+        email: Option<&Email>,
+    ) -> Result<()> {
+        let webhooks: Vec<Webhook> = self
             .storage
             .get_active_webhooks_for_event(address, event.clone())
-            .await?;
+            .await?
+            .into_iter()
+            .filter(|webhook| {
+                let matches = webhook.matches_email(email);
+                if !matches {
+                    debug!("🔍 Webhook {} filters don't match, skipping", webhook.id);
+                }
+                matches
+            })
+            .collect();
 
         if webhooks.is_empty() {
             debug!(
@@ -69,18 +485,31 @@ impl WebhookTrigger {
 
         for webhook in webhooks {
             let client = self.client.clone();
+            let storage = self.storage.clone();
+            let permits = self.permits.clone();
             let payload = self.create_webhook_payload(&event, email, &webhook);
             let webhook_url = self.normalize_webhook_url(&webhook.webhook_url)?;
-            let webhook_id = webhook.id.clone();
 
             info!(
                 "🚀 Spawning webhook task for {} -> {}",
-                webhook_id, webhook_url
+                webhook.id, webhook_url
             );
 
-            let handle = tokio::spawn(async move {
-                Self::send_webhook_with_retry(client, &webhook_url, payload, &webhook_id).await
-            });
+            // Deliveries stay in the caller's span, e.g. the SMTP message's request ID
+            let handle = tokio::spawn(
+                async move {
+                    Self::send_webhook_with_retry(
+                        client,
+                        storage,
+                        &permits,
+                        &webhook_url,
+                        payload,
+                        &webhook,
+                    )
+                    .await
+                }
+                .in_current_span(),
+            );
 
             handles.push(handle);
         }
@@ -96,32 +525,39 @@ impl WebhookTrigger {
     }
 
     /// Create webhook payload based on event type
-    fn create_webhook_payload(
+    ///
+    /// Slack and Discord webhooks get a chat message summarizing the email (see
+    /// [`WebhookFormat`]). A JSON webhook with a payload template gets the rendered template
+    /// instead of the default payload; if rendering fails the default payload is sent.
+    ///
+    /// The payload is serialized to JSON once before sending; when the webhook has a secret,
+    /// those exact bytes are signed and the signature is sent in the `X-Webhook-Signature`
+    /// header (see [`sign_payload`]).
+    pub fn create_webhook_payload(
         &self,
         event: &WebhookEvent,
         email: Option<&Email>,
         webhook: &Webhook,
     ) -> Value {
-        let mut payload = json!({
-            "event": event.as_str(),
-            "mailbox": webhook.mailbox_address,
-            "webhook_id": webhook.id,
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-        });
-
-        if let Some(email) = email {
-            payload["email"] = json!({
-                "id": email.id,
-                "to": email.to,
-                "from": email.from,
-                "subject": email.subject,
-                "body": email.body,
-                "timestamp": email.timestamp.to_rfc3339(),
-                "attachments": email.attachments.len()
-            });
+        if webhook.format != WebhookFormat::Json {
+            return chat_payload(webhook.format, event, email, &webhook.mailbox_address);
         }
 
-        payload
+        let payload = default_payload(event, email, webhook);
+        let Some(template) = &webhook.payload_template else {
+            return payload;
+        };
+
+        match render_payload_template(template, &payload) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                warn!(
+                    "⚠️ Payload template of webhook {} failed, sending the default payload: {:#}",
+                    webhook.id, e
+                );
+                payload
+            }
+        }
     }
 
     /// Normalize webhook URL by adding http:// if no scheme is provided
@@ -134,15 +570,179 @@ impl WebhookTrigger {
         }
     }
 
-    /// Send webhook with retry logic
+    /// Build a POST request with a JSON body and the webhook's headers, signed when a
+    /// secret is set
+    fn build_request(
+        client: &Client,
+        url: &str,
+        payload: &Value,
+        webhook: &Webhook,
+    ) -> Result<reqwest::RequestBuilder> {
+        let body = serde_json::to_vec(payload)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::USER_AGENT,
+            HeaderValue::from_static(WEBHOOK_USER_AGENT),
+        );
+        for (name, value) in &webhook.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", name))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header '{}'", name))?;
+            headers.insert(name, value);
+        }
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        if let Some(secret) = &webhook.secret {
+            headers.insert(
+                SIGNATURE_HEADER,
+                HeaderValue::from_str(&sign_payload(secret, &body))?,
+            );
+        }
+
+        Ok(client
+            .post(url)
+            .headers(headers)
+            .timeout(Duration::from_secs(10))
+            .body(body))
+    }
+
+    /// Record a delivery attempt, logging (not propagating) storage failures
+    async fn record_delivery(
+        storage: &Arc<dyn StorageBackend>,
+        webhook_id: &str,
+        attempt: u32,
+        status_code: Option<u16>,
+        success: bool,
+        response_body: Option<String>,
+    ) {
+        let delivery = WebhookDelivery::new(
+            webhook_id.to_string(),
+            attempt,
+            status_code,
+            success,
+            response_body,
+        );
+        if let Err(e) = storage.record_webhook_delivery(delivery).await {
+            warn!(
+                "⚠️ Failed to record delivery for webhook {}: {}",
+                webhook_id, e
+            );
+        }
+    }
+
+    /// Make one delivery attempt and record it, returning whether it succeeded
+    async fn attempt_delivery(
+        client: &Client,
+        storage: &Arc<dyn StorageBackend>,
+        url: &str,
+        payload: &Value,
+        webhook: &Webhook,
+        attempt: u32,
+    ) -> Result<bool> {
+        let webhook_id = webhook.id.as_str();
+        match Self::build_request(client, url, payload, webhook)?
+            .send()
+            .await
+        {
+            Ok(response) => {
+                let status = response.status();
+                let headers = response.headers();
+
+                info!(
+                    "📡 Webhook {} received response: {} {}",
+                    webhook_id,
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or("Unknown")
+                );
+                debug!("📋 Response headers: {:?}", headers);
+
+                if status.is_success() {
+                    info!(
+                        "✅ Webhook {} sent successfully to {} (status: {})",
+                        webhook_id, url, status
+                    );
+                    let body_text = response.text().await.ok();
+                    Self::record_delivery(
+                        storage,
+                        webhook_id,
+                        attempt,
+                        Some(status.as_u16()),
+                        true,
+                        body_text,
+                    )
+                    .await;
+                    Ok(true)
+                } else {
+                    // Try to read response body for more details
+                    let body_text = response
+                        .text()
+                        .await
+                        .unwrap_or_else(|_| "Failed to read response body".to_string());
+                    Self::record_delivery(
+                        storage,
+                        webhook_id,
+                        attempt,
+                        Some(status.as_u16()),
+                        false,
+                        Some(body_text.clone()),
+                    )
+                    .await;
+                    warn!(
+                        "❌ Webhook {} failed with status {}: {}",
+                        webhook_id, status, body_text
+                    );
+                    Ok(false)
+                }
+            }
+            Err(e) => {
+                let error_details = if e.is_timeout() {
+                    format!("Timeout error: {}", e)
+                } else if e.is_connect() {
+                    format!("Connection error: {} - Check if the webhook URL is reachable and the server is running", e)
+                } else if e.is_request() {
+                    format!("Request error: {} - Check the webhook URL format", e)
+                } else {
+                    format!("HTTP client error: {}", e)
+                };
+
+                warn!(
+                    "❌ Webhook {} attempt {} failed: {}",
+                    webhook_id, attempt, error_details
+                );
+                Self::record_delivery(
+                    storage,
+                    webhook_id,
+                    attempt,
+                    None,
+                    false,
+                    Some(error_details),
+                )
+                .await;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Send webhook with the webhook's retry policy, recording every attempt
+    ///
+    /// Each attempt waits for a slot in `permits` and releases it before backing off.
+    /// Returns whether an attempt succeeded; failed deliveries are logged, not returned as
+    /// errors. A delivery that fails every attempt is queued to be retried later (see
+    /// [`WebhookTrigger::retry_pending_deliveries`]).
     async fn send_webhook_with_retry(
         client: Client,
+        storage: Arc<dyn StorageBackend>,
+        permits: &Semaphore,
         url: &str,
         payload: Value,
-        webhook_id: &str,
-    ) -> Result<()> {
-        let max_retries = 3;
-        let mut last_error = None;
+        webhook: &Webhook,
+    ) -> Result<bool> {
+        let webhook_id = webhook.id.as_str();
+        let max_attempts = webhook.max_retries.saturating_add(1);
 
         info!("🚀 Sending webhook {} to URL: {}", webhook_id, url);
         debug!(
@@ -151,95 +751,169 @@ impl WebhookTrigger {
                 .unwrap_or_else(|_| "Failed to serialize".to_string())
         );
 
-        for attempt in 1..=max_retries {
+        for attempt in 1..=max_attempts {
+            let permit = permits
+                .acquire()
+                .await
+                .context("Webhook delivery limiter closed")?;
             info!(
                 "🔄 Webhook {} attempt {}/{}",
-                webhook_id, attempt, max_retries
+                webhook_id, attempt, max_attempts
             );
 
-            match client
-                .post(url)
-                .json(&payload)
-                .timeout(Duration::from_secs(10))
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    let status = response.status();
-                    let headers = response.headers();
+            if Self::attempt_delivery(&client, &storage, url, &payload, webhook, attempt).await? {
+                return Ok(true);
+            }
+            drop(permit);
 
-                    info!(
-                        "📡 Webhook {} received response: {} {}",
-                        webhook_id,
-                        status.as_u16(),
-                        status.canonical_reason().unwrap_or("Unknown")
-                    );
-                    debug!("📋 Response headers: {:?}", headers);
-
-                    if status.is_success() {
-                        info!(
-                            "✅ Webhook {} sent successfully to {} (status: {})",
-                            webhook_id, url, status
-                        );
-                        return Ok(());
-                    } else {
-                        // Try to read response body for more details
-                        let body_text = response
-                            .text()
-                            .await
-                            .unwrap_or_else(|_| "Failed to read response body".to_string());
-                        warn!(
-                            "❌ Webhook {} failed with status {}: {}",
-                            webhook_id, status, body_text
-                        );
-                        last_error = Some(format!("HTTP {}: {}", status, body_text));
-                    }
-                }
-                Err(e) => {
-                    let error_details = if e.is_timeout() {
-                        format!("Timeout error: {}", e)
-                    } else if e.is_connect() {
-                        format!("Connection error: {} - Check if the webhook URL is reachable and the server is running", e)
-                    } else if e.is_request() {
-                        format!("Request error: {} - Check the webhook URL format", e)
-                    } else {
-                        format!("HTTP client error: {}", e)
-                    };
+            if attempt < max_attempts {
+                let delay = retry_delay(webhook, attempt);
+                info!("⏳ Retrying webhook {} in {:?}", webhook_id, delay);
+                sleep(delay).await;
+            }
+        }
+
+        let next_attempt_at = Utc::now() + pending_retry_delay(max_attempts);
+        error!(
+            "💥 Webhook {} failed after {} attempts, retrying from the queue at {}",
+            webhook_id, max_attempts, next_attempt_at
+        );
+        let pending =
+            PendingWebhookDelivery::new(webhook.id.clone(), payload, max_attempts, next_attempt_at);
+        if let Err(e) = storage.enqueue_webhook_delivery(pending).await {
+            error!("❌ Failed to queue webhook {} for retry: {}", webhook_id, e);
+        }
+
+        Ok(false) // Don't propagate webhook failures
+    }
 
+    /// Make one more attempt at each queued delivery due at `now`, returning how many
+    /// succeeded
+    ///
+    /// Succeeded deliveries leave the queue, failed ones are rescheduled with a longer
+    /// delay, and deliveries queued longer than [`PENDING_DELIVERY_MAX_AGE`] or whose webhook
+    /// was deleted are dropped. Deliveries of a disabled webhook wait until it is enabled
+    /// again or they grow too old.
+    pub async fn retry_pending_deliveries(&self, now: DateTime<Utc>) -> Result<usize> {
+        let due = self
+            .storage
+            .get_due_webhook_deliveries(now, PENDING_DELIVERY_BATCH)
+            .await?;
+        let mut delivered = 0;
+
+        for pending in due {
+            let webhook = self.storage.get_webhook_by_id(&pending.webhook_id).await?;
+            let expired = now - pending.created_at > PENDING_DELIVERY_MAX_AGE;
+            let webhook = match webhook {
+                Some(webhook) if !expired => webhook,
+                Some(_) => {
                     warn!(
-                        "❌ Webhook {} attempt {} failed: {}",
-                        webhook_id, attempt, error_details
+                        "🗑️ Giving up on webhook {} delivery queued at {} after {} attempts",
+                        pending.webhook_id, pending.created_at, pending.attempts
+                    );
+                    self.storage
+                        .delete_pending_webhook_delivery(&pending.id)
+                        .await?;
+                    continue;
+                }
+                None => {
+                    debug!(
+                        "Dropping queued delivery {} of deleted webhook {}",
+                        pending.id, pending.webhook_id
                     );
-                    last_error = Some(error_details);
+                    self.storage
+                        .delete_pending_webhook_delivery(&pending.id)
+                        .await?;
+                    continue;
                 }
+            };
+            if !webhook.enabled {
+                continue;
             }
 
-            if attempt < max_retries {
-                let delay = Duration::from_secs(2_u64.pow(attempt - 1));
-                info!("⏳ Retrying webhook {} in {:?}", webhook_id, delay);
-                sleep(delay).await;
+            let url = self.normalize_webhook_url(&webhook.webhook_url)?;
+            let attempt = pending.attempts.saturating_add(1);
+            info!(
+                "🔄 Retrying queued webhook {} delivery (attempt {})",
+                webhook.id, attempt
+            );
+            let permit = self
+                .permits
+                .acquire()
+                .await
+                .context("Webhook delivery limiter closed")?;
+            let success = Self::attempt_delivery(
+                &self.client,
+                &self.storage,
+                &url,
+                &pending.payload,
+                &webhook,
+                attempt,
+            )
+            .await?;
+            drop(permit);
+
+            if success {
+                self.storage
+                    .delete_pending_webhook_delivery(&pending.id)
+                    .await?;
+                delivered += 1;
+            } else {
+                let next_attempt_at = now + pending_retry_delay(attempt);
+                info!(
+                    "⏳ Retrying queued webhook {} delivery at {}",
+                    webhook.id, next_attempt_at
+                );
+                self.storage
+                    .reschedule_webhook_delivery(&pending.id, attempt, next_attempt_at)
+                    .await?;
             }
         }
 
-        error!(
-            "💥 Webhook {} failed after {} attempts. Last error: {}",
-            webhook_id,
-            max_retries,
-            last_error.unwrap_or_else(|| "Unknown error".to_string())
-        );
+        Ok(delivered)
+    }
+
+    /// Deliver the arrival payload for `email` to one webhook again, for example after its
+    /// endpoint was down when the email arrived
+    ///
+    /// The webhook's retry policy applies, but not its filters or enabled flag. Returns
+    /// whether the delivery succeeded.
+    pub async fn replay_arrival(&self, webhook: &Webhook, email: &Email) -> Result<bool> {
+        let payload = self.create_webhook_payload(&WebhookEvent::Arrival, Some(email), webhook);
+        let url = self.normalize_webhook_url(&webhook.webhook_url)?;
 
-        Ok(()) // Don't propagate webhook failures
+        info!(
+            "🔁 Replaying arrival of email {} to webhook {}",
+            email.id, webhook.id
+        );
+        Self::send_webhook_with_retry(
+            self.client.clone(),
+            self.storage.clone(),
+            &self.permits,
+            &url,
+            payload,
+            webhook,
+        )
+        .await
     }
 
     /// Test a webhook by sending a test payload
     pub async fn test_webhook(&self, webhook: &Webhook) -> Result<bool> {
-        let test_payload = json!({
-            "event": "test",
-            "mailbox": webhook.mailbox_address,
-            "webhook_id": webhook.id,
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-            "message": "This is a test webhook payload"
-        });
+        let test_payload = match webhook.format {
+            WebhookFormat::Json => json!({
+                "event": "test",
+                "mailbox": webhook.mailbox_address,
+                "webhook_id": webhook.id,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "message": "This is a test webhook payload"
+            }),
+            WebhookFormat::Slack => json!({
+                "text": format!("🧪 Test message for *{}*", webhook.mailbox_address)
+            }),
+            WebhookFormat::Discord => json!({
+                "content": format!("🧪 Test message for **{}**", webhook.mailbox_address)
+            }),
+        };
 
         // Normalize URL - add http:// if no scheme is provided
         let url = self.normalize_webhook_url(&webhook.webhook_url)?;
@@ -251,11 +925,7 @@ impl WebhookTrigger {
                 .unwrap_or_else(|_| "Failed to serialize".to_string())
         );
 
-        match self
-            .client
-            .post(&url)
-            .json(&test_payload)
-            .timeout(Duration::from_secs(10))
+        match Self::build_request(&self.client, &url, &test_payload, webhook)?
             .send()
             .await
         {
@@ -273,12 +943,31 @@ impl WebhookTrigger {
                         "✅ Test webhook {} succeeded (status: {})",
                         webhook.id, status
                     );
+                    let body_text = response.text().await.ok();
+                    Self::record_delivery(
+                        &self.storage,
+                        &webhook.id,
+                        1,
+                        Some(status.as_u16()),
+                        true,
+                        body_text,
+                    )
+                    .await;
                     Ok(true)
                 } else {
                     let body_text = response
                         .text()
                         .await
                         .unwrap_or_else(|_| "Failed to read response body".to_string());
+                    Self::record_delivery(
+                        &self.storage,
+                        &webhook.id,
+                        1,
+                        Some(status.as_u16()),
+                        false,
+                        Some(body_text.clone()),
+                    )
+                    .await;
                     warn!(
                         "❌ Test webhook {} failed with status {}: {}",
                         webhook.id, status, body_text
@@ -298,6 +987,15 @@ impl WebhookTrigger {
                 };
 
                 error!("💥 Test webhook {} failed: {}", webhook.id, error_details);
+                Self::record_delivery(
+                    &self.storage,
+                    &webhook.id,
+                    1,
+                    None,
+                    false,
+                    Some(error_details),
+                )
+                .await;
                 Ok(false)
             }
         }
@@ -335,6 +1033,7 @@ mod tests {
         let trigger = WebhookTrigger {
             client: Client::new(),
             storage,
+            permits: delivery_permits(),
         };
 
         let payload =
@@ -345,39 +1044,49 @@ mod tests {
         assert_eq!(payload["webhook_id"], webhook.id);
         assert!(payload["email"].is_object());
         assert_eq!(payload["email"]["id"], email.id);
+        assert_eq!(payload["email"]["snippet"], "Test body");
+        assert!(payload["email"]["dkim_result"].is_null());
     }
 
     #[test]
-    fn test_webhook_event_serialization() {
-        assert_eq!(WebhookEvent::Arrival.as_str(), "arrival");
-        assert_eq!(WebhookEvent::Deletion.as_str(), "deletion");
-
-        assert_eq!(
-            WebhookEvent::from_str("arrival"),
-            Some(WebhookEvent::Arrival)
-        );
+    fn test_sign_payload() {
         assert_eq!(
-            WebhookEvent::from_str("deletion"),
-            Some(WebhookEvent::Deletion)
+            sign_payload("It's a Secret to Everybody", b"Hello, World!"),
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
         );
-        assert_eq!(WebhookEvent::from_str("invalid"), None);
     }
 
     #[tokio::test]
-    async fn test_webhook_http_delivery_success() {
-        use mockito::Server;
+    async fn test_webhook_delivery_is_signed_with_secret() {
+        use mockito::{Matcher, Server};
 
         let mut server = Server::new_async().await;
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.secret = Some("top-secret".to_string());
+
+        // The test payload embeds a timestamp, so check the header against the received body
         let _mock = server
             .mock("POST", "/webhook")
+            .match_header(
+                SIGNATURE_HEADER,
+                Matcher::Regex("^sha256=[0-9a-f]{64}$".into()),
+            )
+            .match_request(|request| {
+                let body = request.body().unwrap();
+                let signature = request.header(SIGNATURE_HEADER)[0]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                signature == sign_payload("top-secret", body)
+            })
             .with_status(200)
-            .with_header("content-type", "application/json")
             .create_async()
             .await;
 
-        let webhook_url = format!("{}/webhook", server.url());
-        let webhook = Webhook::new("test".to_string(), webhook_url, vec![WebhookEvent::Arrival]);
-
         let storage = Arc::new(
             crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
                 .await
@@ -385,41 +1094,678 @@ mod tests {
         );
         let trigger = WebhookTrigger::new(storage);
 
-        let result = trigger.test_webhook(&webhook).await;
-        assert!(result.is_ok());
-        assert!(result.unwrap());
-
+        assert!(trigger.test_webhook(&webhook).await.unwrap());
         _mock.assert_async().await;
     }
 
     #[tokio::test]
-    async fn test_webhook_http_delivery_failure() {
+    async fn test_webhook_delivery_sends_custom_headers() {
         use mockito::Server;
 
         let mut server = Server::new_async().await;
-        let _mock = server
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook
+            .headers
+            .insert("X-Route-To".to_string(), "mail-team".to_string());
+
+        // Without a custom User-Agent the default one is sent
+        let default_agent = server
             .mock("POST", "/webhook")
-            .with_status(500)
+            .match_header("x-route-to", "mail-team")
+            .match_header("user-agent", WEBHOOK_USER_AGENT)
+            .match_header("content-type", "application/json")
+            .with_status(200)
+            .expect(2)
             .create_async()
             .await;
 
-        let webhook_url = format!("{}/webhook", server.url());
-        let webhook = Webhook::new("test".to_string(), webhook_url, vec![WebhookEvent::Arrival]);
-
-        let storage = Arc::new(
+        let storage: Arc<dyn StorageBackend> = Arc::new(
             crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
                 .await
                 .unwrap(),
         );
-        let trigger = WebhookTrigger::new(storage);
-
-        let result = trigger.test_webhook(&webhook).await;
-        assert!(result.is_ok());
-        assert!(!result.unwrap());
+        let trigger = WebhookTrigger::new(storage.clone());
+        assert!(trigger.test_webhook(&webhook).await.unwrap());
+        assert!(WebhookTrigger::send_webhook_with_retry(
+            Client::new(),
+            storage.clone(),
+            &Semaphore::new(1),
+            &webhook.webhook_url,
+            json!({"event": "arrival"}),
+            &webhook,
+        )
+        .await
+        .unwrap());
+        default_agent.assert_async().await;
+
+        // A custom User-Agent replaces the default rather than being added next to it
+        webhook
+            .headers
+            .insert("User-Agent".to_string(), "receiver-router/1.0".to_string());
+        let custom_agent = server
+            .mock("POST", "/webhook")
+            .match_header("x-route-to", "mail-team")
+            .match_header("user-agent", "receiver-router/1.0")
+            .match_request(|request| request.header("user-agent").len() == 1)
+            .with_status(200)
+            .create_async()
+            .await;
+        assert!(trigger.test_webhook(&webhook).await.unwrap());
+        custom_agent.assert_async().await;
+    }
+
+    #[test]
+    fn test_validate_webhook_headers() {
+        let headers = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        assert!(validate_webhook_headers(&headers(&[])).is_ok());
+        assert!(validate_webhook_headers(&headers(&[
+            ("Authorization", "Bearer abc"),
+            ("User-Agent", "receiver-router/1.0"),
+        ]))
+        .is_ok());
+
+        for reserved in ["Content-Length", "content-type", "HOST", SIGNATURE_HEADER] {
+            assert!(
+                validate_webhook_headers(&headers(&[(reserved, "x")])).is_err(),
+                "{}",
+                reserved
+            );
+        }
+        assert!(validate_webhook_headers(&headers(&[("Bad Header", "x")])).is_err());
+        assert!(validate_webhook_headers(&headers(&[("X-Ok", "line\nbreak")])).is_err());
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_up_to_ceiling() {
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.initial_backoff_secs = 2;
+        webhook.max_backoff_secs = 10;
+
+        assert_eq!(retry_delay(&webhook, 1), Duration::from_secs(2));
+        assert_eq!(retry_delay(&webhook, 2), Duration::from_secs(4));
+        assert_eq!(retry_delay(&webhook, 3), Duration::from_secs(8));
+        assert_eq!(retry_delay(&webhook, 4), Duration::from_secs(10));
+        assert_eq!(retry_delay(&webhook, 100), Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_with_zero_retries_makes_one_attempt() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/webhook")
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.max_retries = 0;
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        WebhookTrigger::send_webhook_with_retry(
+            Client::new(),
+            storage.clone(),
+            &Semaphore::new(1),
+            &webhook.webhook_url,
+            json!({ "event": "arrival" }),
+            &webhook,
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+        let deliveries = storage
+            .get_webhook_deliveries(&webhook.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_up_to_max_retries() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/webhook")
+            .with_status(500)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.max_retries = 2;
+        webhook.initial_backoff_secs = 0;
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        WebhookTrigger::send_webhook_with_retry(
+            Client::new(),
+            storage,
+            &Semaphore::new(1),
+            &webhook.webhook_url,
+            json!({ "event": "arrival" }),
+            &webhook,
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried_from_queue_after_restart() {
+        use crate::storage::sqlite::SqliteBackend;
+        use mockito::{Matcher, Server};
+
+        let dir = tempfile::tempdir().unwrap();
+        let database_url = format!("sqlite:{}", dir.path().join("queue.db").display());
+
+        let mut server = Server::new_async().await;
+        let down = server
+            .mock("POST", "/webhook")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut webhook = Webhook::new(
+            "queue".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        webhook.max_retries = 0;
+        let email = Email::new(
+            "queue@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Queued".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new(&database_url).await.unwrap());
+        storage.create_webhook(webhook.clone()).await.unwrap();
+        let trigger = WebhookTrigger::new(storage.clone());
+        trigger
+            .trigger_webhooks("queue", WebhookEvent::Arrival, Some(&email))
+            .await
+            .unwrap();
+        down.assert_async().await;
+
+        let later = Utc::now() + chrono::Duration::minutes(2);
+        let queued = storage.get_due_webhook_deliveries(later, 10).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].webhook_id, webhook.id);
+        assert_eq!(queued[0].attempts, 1);
+        assert_eq!(queued[0].payload["email"]["id"], email.id);
+        // Not due yet
+        assert_eq!(
+            trigger.retry_pending_deliveries(Utc::now()).await.unwrap(),
+            0
+        );
+        drop(trigger);
+        drop(storage);
+
+        // The endpoint recovers while the server restarts
+        down.remove_async().await;
+        let up = server
+            .mock("POST", "/webhook")
+            .match_body(Matcher::PartialJson(json!({ "email": { "id": email.id } })))
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let storage: Arc<dyn StorageBackend> =
+            Arc::new(SqliteBackend::new(&database_url).await.unwrap());
+        let trigger = WebhookTrigger::new(storage.clone());
+        assert_eq!(trigger.retry_pending_deliveries(later).await.unwrap(), 1);
+        up.assert_async().await;
+
+        assert!(storage
+            .get_due_webhook_deliveries(later, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        let deliveries = storage
+            .get_webhook_deliveries(&webhook.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert!(deliveries.iter().any(|d| d.attempt == 2 && d.success));
+    }
+
+    #[tokio::test]
+    async fn test_queued_delivery_is_rescheduled_then_dropped() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let _down = server
+            .mock("POST", "/webhook")
+            .with_status(500)
+            .create_async()
+            .await;
+        let webhook = Webhook::new(
+            "queue".to_string(),
+            format!("{}/webhook", server.url()),
+            vec![WebhookEvent::Arrival],
+        );
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        storage.create_webhook(webhook.clone()).await.unwrap();
+        let now = Utc::now();
+        storage
+            .enqueue_webhook_delivery(PendingWebhookDelivery::new(
+                webhook.id.clone(),
+                json!({ "event": "arrival" }),
+                3,
+                now,
+            ))
+            .await
+            .unwrap();
+        let trigger = WebhookTrigger::new(storage.clone());
+
+        // A failed retry backs off further
+        assert_eq!(trigger.retry_pending_deliveries(now).await.unwrap(), 0);
+        assert!(storage
+            .get_due_webhook_deliveries(now + chrono::Duration::minutes(7), 10)
+            .await
+            .unwrap()
+            .is_empty());
+        let queued = storage
+            .get_due_webhook_deliveries(now + chrono::Duration::minutes(8), 10)
+            .await
+            .unwrap();
+        assert_eq!(queued[0].attempts, 4);
+
+        // Past the maximum age it is given up on without another attempt
+        let expired = now + PENDING_DELIVERY_MAX_AGE + chrono::Duration::minutes(1);
+        assert_eq!(trigger.retry_pending_deliveries(expired).await.unwrap(), 0);
+        assert!(storage
+            .get_due_webhook_deliveries(expired, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        let deliveries = storage
+            .get_webhook_deliveries(&webhook.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+    }
+
+    #[test]
+    fn test_pending_retry_delay_backs_off_to_an_hour() {
+        assert_eq!(pending_retry_delay(1), chrono::Duration::minutes(1));
+        assert_eq!(pending_retry_delay(3), chrono::Duration::minutes(4));
+        assert_eq!(pending_retry_delay(7), chrono::Duration::hours(1));
+        assert_eq!(pending_retry_delay(100), chrono::Duration::hours(1));
+    }
+
+    #[tokio::test]
+    async fn test_trigger_webhooks_applies_filters() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let matching = server
+            .mock("POST", "/matching")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+        let filtered = server
+            .mock("POST", "/filtered")
+            .with_status(200)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let mut webhook = Webhook::new(
+            "alerts".to_string(),
+            format!("{}/matching", server.url()),
+            vec![WebhookEvent::Arrival, WebhookEvent::Deletion],
+        );
+        webhook.from_filter = Some("@Example.com".to_string());
+        webhook.subject_filter = Some("invoice".to_string());
+        storage.create_webhook(webhook).await.unwrap();
+        let mut webhook = Webhook::new(
+            "alerts".to_string(),
+            format!("{}/filtered", server.url()),
+            vec![WebhookEvent::Arrival, WebhookEvent::Deletion],
+        );
+        webhook.subject_filter = Some("receipt".to_string());
+        storage.create_webhook(webhook).await.unwrap();
+
+        let email = Email::new(
+            "alerts@example.com".to_string(),
+            "billing@example.com".to_string(),
+            "Your INVOICE is ready".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        let trigger = WebhookTrigger::new(storage);
+        trigger
+            .trigger_webhooks("alerts", WebhookEvent::Arrival, Some(&email))
+            .await
+            .unwrap();
+        // Deletions carry no email, so filtered webhooks never fire for them
+        trigger
+            .trigger_webhooks("alerts", WebhookEvent::Deletion, None)
+            .await
+            .unwrap();
+
+        matching.assert_async().await;
+        filtered.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_deliveries_stay_within_limit() {
+        use axum::{routing::post, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const LIMIT: usize = 3;
+        const WEBHOOKS: usize = 20;
+
+        /// Requests the receiver is handling now, at most at once, and in total
+        #[derive(Default)]
+        struct Counts {
+            current: AtomicUsize,
+            peak: AtomicUsize,
+            total: AtomicUsize,
+        }
+        let counts = Arc::new(Counts::default());
+        let handler_counts = counts.clone();
+        let app = Router::new().route(
+            "/hook",
+            post(move || {
+                let counts = handler_counts.clone();
+                async move {
+                    let now = counts.current.fetch_add(1, Ordering::SeqCst) + 1;
+                    counts.peak.fetch_max(now, Ordering::SeqCst);
+                    sleep(Duration::from_millis(50)).await;
+                    counts.current.fetch_sub(1, Ordering::SeqCst);
+                    counts.total.fetch_add(1, Ordering::SeqCst);
+                    "ok"
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        for _ in 0..WEBHOOKS {
+            let webhook =
+                Webhook::new("busy".to_string(), url.clone(), vec![WebhookEvent::Arrival]);
+            storage.create_webhook(webhook).await.unwrap();
+        }
+
+        let trigger = WebhookTrigger {
+            permits: Arc::new(Semaphore::new(LIMIT)),
+            ..WebhookTrigger::new(storage)
+        };
+        trigger
+            .trigger_webhooks("busy", WebhookEvent::Arrival, None)
+            .await
+            .unwrap();
+
+        assert_eq!(counts.total.load(Ordering::SeqCst), WEBHOOKS);
+        let peak = counts.peak.load(Ordering::SeqCst);
+        assert!(peak <= LIMIT, "{} requests were in flight at once", peak);
+        assert!(peak > 1, "deliveries did not run concurrently");
+    }
+
+    #[tokio::test]
+    async fn test_attachment_received_fires_only_with_attachments() {
+        use crate::storage::models::Attachment;
+        use mockito::{Matcher, Server};
+
+        let mut server = Server::new_async().await;
+        let arrivals = server
+            .mock("POST", "/arrivals")
+            .match_body(Matcher::PartialJson(json!({ "event": "arrival" })))
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+        let attachments = server
+            .mock("POST", "/attachments")
+            .match_body(Matcher::PartialJson(
+                json!({ "event": "attachment_received" }),
+            ))
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        // An existing arrival-only webhook is unaffected by attachments
+        storage
+            .create_webhook(Webhook::new(
+                "docs".to_string(),
+                format!("{}/arrivals", server.url()),
+                vec![WebhookEvent::Arrival],
+            ))
+            .await
+            .unwrap();
+        storage
+            .create_webhook(Webhook::new(
+                "docs".to_string(),
+                format!("{}/attachments", server.url()),
+                vec![WebhookEvent::AttachmentReceived],
+            ))
+            .await
+            .unwrap();
+
+        let plain = Email::new(
+            "docs@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "No files".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+        let with_file = Email::new(
+            "docs@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Scan".to_string(),
+            "Body".to_string(),
+            None,
+            vec![Attachment {
+                filename: "scan.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                size: 4,
+                content: "JVBERg==".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
+            }],
+        );
+        let trigger = WebhookTrigger::new(storage);
+        for email in [&plain, &with_file] {
+            trigger
+                .trigger_webhooks("docs", WebhookEvent::Arrival, Some(email))
+                .await
+                .unwrap();
+        }
+        // Only arrivals carry attachments to announce
+        trigger
+            .trigger_webhooks("docs", WebhookEvent::Deletion, Some(&with_file))
+            .await
+            .unwrap();
+
+        arrivals.assert_async().await;
+        attachments.assert_async().await;
+    }
+
+    #[test]
+    fn test_webhook_event_serialization() {
+        assert_eq!(WebhookEvent::Arrival.as_str(), "arrival");
+        assert_eq!(WebhookEvent::Deletion.as_str(), "deletion");
+        assert_eq!(
+            WebhookEvent::AttachmentReceived.as_str(),
+            "attachment_received"
+        );
+
+        assert_eq!(
+            WebhookEvent::from_str("arrival"),
+            Some(WebhookEvent::Arrival)
+        );
+        assert_eq!(
+            WebhookEvent::from_str("deletion"),
+            Some(WebhookEvent::Deletion)
+        );
+        assert_eq!(
+            WebhookEvent::from_str("attachment_received"),
+            Some(WebhookEvent::AttachmentReceived)
+        );
+        assert_eq!(WebhookEvent::from_str("invalid"), None);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_http_delivery_success() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/webhook")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .create_async()
+            .await;
+
+        let webhook_url = format!("{}/webhook", server.url());
+        let webhook = Webhook::new("test".to_string(), webhook_url, vec![WebhookEvent::Arrival]);
+
+        let storage = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage);
+
+        let result = trigger.test_webhook(&webhook).await;
+        assert!(result.is_ok());
+        assert!(result.unwrap());
+
+        _mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_webhook_http_delivery_failure() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/webhook")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let webhook_url = format!("{}/webhook", server.url());
+        let webhook = Webhook::new("test".to_string(), webhook_url, vec![WebhookEvent::Arrival]);
+
+        let storage = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage);
+
+        let result = trigger.test_webhook(&webhook).await;
+        assert!(result.is_ok());
+        assert!(!result.unwrap());
 
         _mock.assert_async().await;
     }
 
+    #[tokio::test]
+    async fn test_webhook_delivery_attempts_are_recorded() {
+        use mockito::Server;
+
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/webhook")
+            .with_status(503)
+            .with_body("x".repeat(5000))
+            .create_async()
+            .await;
+
+        let webhook_url = format!("{}/webhook", server.url());
+        let webhook = Webhook::new("test".to_string(), webhook_url, vec![WebhookEvent::Arrival]);
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage.clone());
+
+        assert!(!trigger.test_webhook(&webhook).await.unwrap());
+
+        let deliveries = storage
+            .get_webhook_deliveries(&webhook.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].attempt, 1);
+        assert_eq!(deliveries[0].status_code, Some(503));
+        assert!(!deliveries[0].success);
+        assert_eq!(
+            deliveries[0].response_body.as_ref().unwrap().len(),
+            crate::storage::models::MAX_DELIVERY_RESPONSE_LEN
+        );
+    }
+
     #[tokio::test]
     async fn test_webhook_http_delivery_timeout() {
         use mockito::Server;
@@ -503,6 +1849,205 @@ mod tests {
         assert!(payload["email"].is_object());
         assert_eq!(payload["email"]["id"], email.id);
         assert_eq!(payload["email"]["subject"], "Test Subject");
+        assert_eq!(payload["email"]["attachments"], json!([]));
         assert!(payload["timestamp"].is_string());
     }
+
+    #[tokio::test]
+    async fn test_webhook_payload_attachments() {
+        use crate::storage::models::Attachment;
+
+        let mut webhook = Webhook::new(
+            "test".to_string(),
+            "http://localhost:3009".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        let email = Email::new(
+            "test@example.com".to_string(),
+            "sender@example.com".to_string(),
+            "Report".to_string(),
+            "See attached".to_string(),
+            None,
+            vec![Attachment {
+                filename: "report.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                size: 12,
+                content: "cmVwb3J0IGJ5dGVz".to_string(),
+                scan_result: None,
+                path: None,
+                dropped: false,
+                content_hash: None,
+            }],
+        );
+
+        let storage = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage);
+
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        assert_eq!(
+            payload["email"]["attachments"],
+            json!([{
+                "filename": "report.pdf",
+                "content_type": "application/pdf",
+                "size": 12
+            }])
+        );
+
+        webhook.include_attachment_content = true;
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        assert_eq!(
+            payload["email"]["attachments"],
+            json!([{
+                "filename": "report.pdf",
+                "content_type": "application/pdf",
+                "size": 12,
+                "content": "cmVwb3J0IGJ5dGVz"
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_payload_template() {
+        let mut webhook = Webhook::new(
+            "team".to_string(),
+            "https://hooks.slack.example/services/T000".to_string(),
+            vec![WebhookEvent::Arrival, WebhookEvent::Deletion],
+        );
+        webhook.payload_template = Some(
+            r#"{
+                "text": "New mail for {{ mailbox }} from {{ email.from }}",
+                "blocks": [{"type": "section", "text": {"type": "mrkdwn", "text": "*{{ email.subject }}*"}}],
+                "event": "{{ event }}",
+                "attachments": {{ email.attachments }}
+            }"#
+            .to_string(),
+        );
+        let email = Email::new(
+            "team@example.com".to_string(),
+            "Alice <alice@example.com>".to_string(),
+            "Quarterly \"numbers\"\nattached".to_string(),
+            "Body".to_string(),
+            None,
+            vec![],
+        );
+
+        let storage = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage);
+
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        assert_eq!(
+            payload,
+            json!({
+                "text": "New mail for team from Alice <alice@example.com>",
+                "blocks": [{
+                    "type": "section",
+                    "text": {"type": "mrkdwn", "text": "*Quarterly \"numbers\"\nattached*"}
+                }],
+                "event": "arrival",
+                "attachments": []
+            })
+        );
+
+        // Deletions have no email, so its fields render empty
+        webhook.payload_template =
+            Some(r#"{"text": "{{ event }}: {{ email.subject }}"}"#.to_string());
+        let payload = trigger.create_webhook_payload(&WebhookEvent::Deletion, None, &webhook);
+        assert_eq!(payload, json!({"text": "deletion: "}));
+
+        // A template that stops rendering valid JSON falls back to the default payload
+        webhook.payload_template = Some("{{ email.subject }}".to_string());
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        assert_eq!(payload["event"], "arrival");
+        assert_eq!(payload["email"]["id"], email.id);
+    }
+
+    #[tokio::test]
+    async fn test_slack_and_discord_payloads() {
+        let mut email = Email::new(
+            "team@example.com".to_string(),
+            "alice@example.com".to_string(),
+            "Standup notes".to_string(),
+            "<p>Shipped the <b>release</b></p>".to_string(),
+            None,
+            vec![],
+        );
+        email.body_html = Some(email.body.clone());
+        let mut webhook = Webhook::new(
+            "team".to_string(),
+            "https://hooks.example/T000".to_string(),
+            vec![WebhookEvent::Arrival],
+        );
+        // Chat formats take precedence over a payload template
+        webhook.payload_template = Some(r#"{"custom": true}"#.to_string());
+
+        let storage = Arc::new(
+            crate::storage::sqlite::SqliteBackend::new("sqlite::memory:")
+                .await
+                .unwrap(),
+        );
+        let trigger = WebhookTrigger::new(storage);
+
+        webhook.format = WebhookFormat::Slack;
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        let keys: Vec<&String> = payload.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["text"]);
+        assert_eq!(
+            payload["text"],
+            "📨 New email for *team*\n*From:* alice@example.com\n*Subject:* Standup notes\n> Shipped the release"
+        );
+
+        webhook.format = WebhookFormat::Discord;
+        let payload =
+            trigger.create_webhook_payload(&WebhookEvent::Arrival, Some(&email), &webhook);
+        let keys: Vec<&String> = payload.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["content"]);
+        let content = payload["content"].as_str().unwrap();
+        assert!(content.starts_with("📨 New email for **team**"));
+        assert!(content.contains("**Subject:** Standup notes"));
+
+        let payload = trigger.create_webhook_payload(&WebhookEvent::Deletion, None, &webhook);
+        assert_eq!(
+            payload,
+            json!({ "content": "🗑️ Email deleted from **team**" })
+        );
+    }
+
+    #[test]
+    fn test_body_snippet_is_truncated() {
+        let email = Email::new(
+            "team@example.com".to_string(),
+            "alice@example.com".to_string(),
+            "Long".to_string(),
+            "word ".repeat(100),
+            None,
+            vec![],
+        );
+        let snippet = body_snippet(&email);
+        assert!(snippet.ends_with('…'));
+        assert_eq!(snippet.chars().count(), SNIPPET_CHARS);
+    }
+
+    #[test]
+    fn test_validate_payload_template() {
+        assert!(validate_payload_template(r#"{"text": "{{ email.subject }}"}"#).is_ok());
+        assert!(
+            validate_payload_template(r#"{"count": {{ email.attachments | length }}}"#).is_ok()
+        );
+        assert!(validate_payload_template(r#"{"text": "{{ email.subject"}"#).is_err());
+        assert!(validate_payload_template(r#"{"text": {{ email.subject }}}"#).is_err());
+        assert!(validate_payload_template("plain {{ mailbox }}").is_err());
+    }
 }
@@ -394,6 +394,10 @@ async function handleRegister() {
 
 // Logout
 function logout() {
+    if (authToken) {
+        // Revoke the token server-side too; the local session is cleared either way
+        fetch('/api/auth/logout', { method: 'POST', headers: getAuthHeaders() }).catch(() => {});
+    }
     authToken = null;
     currentUser = null;
     localStorage.removeItem('auth_token');
@@ -920,9 +924,22 @@ function connectWebSocket(address) {
                 return;
             }
             
+            // Updates were missed; replace the list with what the mailbox now holds
+            if (data.type === 'Resync') {
+                console.log('Resyncing after missing', data.missed, 'update(s)');
+                emails = data.emails;
+                displayEmails(emails);
+                updateEmailCount(emails.length);
+                return;
+            }
+            
             // New email received
             if (data.type === 'Email') {
                 const email = data;
+                // Already listed by a resync
+                if (emails.some(existing => existing.id === email.id)) {
+                    return;
+                }
                 console.log('New email received:', email);
                 email.isNew = true;
                 emails.unshift(email);